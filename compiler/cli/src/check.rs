@@ -1,12 +1,29 @@
 use crate::{
     database::Database,
+    debug,
     utils::{module_for_path, packages_path},
     Exit, ProgramResult,
 };
-use candy_frontend::{ast_to_hir::AstToHir, hir::CollectErrors};
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    error::{CompilerError, LimitedErrors, DEFAULT_MAX_REPORTED_ERRORS},
+    hir::CollectErrors,
+    hir_to_mir::ExecutionTarget,
+    lints::{Lint, LintLevel, LintLevelResolver},
+    mir_optimize::OptimizeMir,
+    module::Module,
+    TracingConfig,
+};
 use clap::{arg, Parser, ValueHint};
-use std::path::PathBuf;
-use tracing::warn;
+use diffy::create_patch;
+use itertools::Itertools;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+use tracing::{error, warn};
 
 /// Check a Candy program for obvious errors.
 ///
@@ -18,24 +35,133 @@ pub struct Options {
     /// current working directory will be checked.
     #[arg(value_hint = ValueHint::FilePath)]
     path: Option<PathBuf>,
+
+    /// Report the given lint as an error. Use `warnings` to report all lints
+    /// that would be warnings as errors.
+    ///
+    /// This overrides the `lints` configured in the package's
+    /// `_package.candy`.
+    #[arg(long, value_name = "LINT")]
+    deny: Vec<LintSelector>,
+
+    /// Don't report the given lint. Use `warnings` to allow all lints.
+    #[arg(long, value_name = "LINT")]
+    allow: Vec<LintSelector>,
+
+    /// Compile the program a second time in a separate process and fail if
+    /// any intermediate representation differs between both compilations.
+    ///
+    /// Caches and distributed builds rely on the compiler producing the same
+    /// output for the same input. The separate process uses different hash
+    /// seeds and memory addresses, so order-dependent output shows up as well.
+    #[arg(long)]
+    verify_deterministic: bool,
+
+    /// Print the intermediate representations as JSON instead of checking the
+    /// program. `--verify-deterministic` uses this for the second compilation.
+    #[arg(long, hide = true)]
+    print_irs: bool,
+
+    /// The maximum number of errors to report. Repetitions of an error right
+    /// after it are reported together with it.
+    #[arg(long, default_value_t = DEFAULT_MAX_REPORTED_ERRORS)]
+    max_errors: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum LintSelector {
+    Warnings,
+    Lint(Lint),
+}
+impl FromStr for LintSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "warnings" {
+            return Ok(Self::Warnings);
+        }
+        Lint::from_str(s)
+            .map(Self::Lint)
+            .map_err(|_| format!("`{s}` is not a lint."))
+    }
 }
 
 pub fn check(options: Options) -> ProgramResult {
-    let packages_path = packages_path();
-    let db = Database::new_with_file_system_module_provider(packages_path);
-    let module = module_for_path(options.path)?;
+    let db = Database::new_with_file_system_module_provider(packages_path());
+    let module = module_for_path(options.path.clone())?;
+
+    if options.print_irs {
+        let irs = debug::irs(&db, &module, &ExecutionTarget::Module(module.clone()));
+        println!("{}", serde_json::to_string(&irs).unwrap());
+        return Ok(());
+    }
+    if options.verify_deterministic {
+        verify_deterministic(&db, &module, options.path.as_deref())?;
+    }
 
     // TODO: Once my other PR is merged, update this to get the MIR instead.
     // This will return a tuple containing the MIR and errors, even from
     // imported modules.
 
-    let (hir, _) = db.hir(module).unwrap();
+    let (hir, _) = db.hir(module.clone()).unwrap();
     let mut errors = vec![];
     hir.collect_errors(&mut errors);
-    let has_errors = !errors.is_empty();
+    let mut has_errors = !errors.is_empty();
 
-    for error in errors {
-        warn!("{}", error.to_string_with_location(&db));
+    let errors = LimitedErrors::new(
+        errors.into_iter().sorted_by_key(|it| it.span.start),
+        options.max_errors,
+        CompilerError::is_repeated_by,
+    );
+    for group in errors.groups {
+        let repetitions = match group.repetitions.len() {
+            0 => String::new(),
+            1 => " (repeated once right after this)".to_string(),
+            n => format!(" (repeated {n} times right after this)"),
+        };
+        warn!("{}{repetitions}", group.first.to_string_with_location(&db));
+    }
+    if errors.num_omitted > 0 {
+        warn!(
+            "{} more errors are not shown. Use `--max-errors` to show more.",
+            errors.num_omitted,
+        );
+    }
+
+    // Warnings are found while optimizing the MIR. They only make the check
+    // fail if their lint is denied.
+    let mut lint_levels = LintLevelResolver::for_package(&db, module.package.clone());
+    for selector in options.allow {
+        match selector {
+            LintSelector::Warnings => lint_levels.set_warnings_level(LintLevel::Allow),
+            LintSelector::Lint(lint) => lint_levels.set_level(lint, LintLevel::Allow),
+        }
+    }
+    for selector in options.deny {
+        match selector {
+            LintSelector::Warnings => lint_levels.set_warnings_level(LintLevel::Deny),
+            LintSelector::Lint(lint) => lint_levels.set_level(lint, LintLevel::Deny),
+        }
+    }
+    if let Ok((_, _, mir_errors)) = db.optimized_mir(
+        ExecutionTarget::Module(module.clone()),
+        TracingConfig::off(),
+    ) {
+        for (warning, level) in mir_errors
+            .iter()
+            .filter(|it| it.module == module)
+            .filter_map(|it| Some((it, lint_levels.level_of(&it.payload)?)))
+            .sorted_by_key(|(it, _)| it.span.start)
+        {
+            match level {
+                LintLevel::Allow => {}
+                LintLevel::Warn => warn!("Warning: {}", warning.to_string_with_location(&db)),
+                LintLevel::Deny => {
+                    error!("{}", warning.to_string_with_location(&db));
+                    has_errors = true;
+                }
+            }
+        }
     }
 
     if has_errors {
@@ -44,3 +170,42 @@ pub fn check(options: Options) -> ProgramResult {
         Ok(())
     }
 }
+
+fn verify_deterministic(db: &Database, module: &Module, path: Option<&Path>) -> ProgramResult {
+    let first = debug::irs(db, module, &ExecutionTarget::Module(module.clone()));
+    let second = irs_of_separate_process(path)?;
+
+    let mut is_deterministic = true;
+    for ((ir_name, first), (_, second)) in first.iter().zip_eq(&second) {
+        if first != second {
+            is_deterministic = false;
+            error!(
+                "The {ir_name} of {module} differs between two compilations:\n{}",
+                create_patch(first, second),
+            );
+        }
+    }
+    if is_deterministic {
+        Ok(())
+    } else {
+        Err(Exit::CompilationNotDeterministic)
+    }
+}
+/// Compiles the module in a new `candy` process and returns its IRs.
+fn irs_of_separate_process(path: Option<&Path>) -> Result<Vec<(String, String)>, Exit> {
+    let mut command = Command::new(env::current_exe().unwrap());
+    command.args(["check", "--quiet", "--print-irs"]);
+    command.args(path);
+    let output = match command.stderr(Stdio::inherit()).output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            error!("The second compilation failed with {}.", output.status);
+            return Err(Exit::CompilationNotDeterministic);
+        }
+        Err(error) => {
+            error!("Couldn't start the second compilation: {error}");
+            return Err(Exit::IoError);
+        }
+    };
+    Ok(serde_json::from_slice(&output.stdout).unwrap())
+}
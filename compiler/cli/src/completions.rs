@@ -0,0 +1,503 @@
+use crate::utils::packages_path;
+use clap::{Arg, Command, Parser, ValueEnum, ValueHint};
+use itertools::Itertools;
+use std::{env::current_dir, iter};
+
+/// Prints the files that module arguments complete to.
+const LIST_MODULES: &str = "candy completions --list-modules";
+
+/// Print a script that lets your shell complete Candy commands.
+///
+/// For example, add `source <(candy completions bash)` to your `.bashrc` or
+/// `candy completions fish | source` to your fish config. Arguments that take a
+/// Candy file complete to the modules of the package surrounding your current
+/// working directory.
+#[derive(Parser, Debug)]
+pub struct Options {
+    #[arg(value_enum, required_unless_present = "list_modules")]
+    shell: Option<Shell>,
+
+    /// Print the Candy files of the package surrounding the current working
+    /// directory, one per line. The completion scripts call this.
+    #[arg(long, hide = true)]
+    list_modules: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+enum Shell {
+    Bash,
+    /// Uses zsh's emulation of bash completions.
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+pub fn completions(options: &Options, command: Command) {
+    if options.list_modules {
+        list_modules();
+        return;
+    }
+
+    print!("{}", script(options.shell.unwrap(), command));
+}
+fn script(shell: Shell, mut command: Command) -> String {
+    // Building propagates global arguments like `--quiet` to the subcommands.
+    command.build();
+    let commands = CommandCompletions::collect(&command, &[]);
+    match shell {
+        Shell::Bash => bash(&commands),
+        Shell::Zsh => format!(
+            "autoload -U +X bashcompinit && bashcompinit\n\n{}",
+            bash(&commands),
+        ),
+        Shell::Fish => fish(&commands),
+        Shell::Powershell => powershell(&commands),
+    }
+}
+
+fn list_modules() {
+    let packages_path = packages_path();
+    let current_directory = current_dir().unwrap();
+    // Outside of packages, there's nothing to complete.
+    let Some(package) = packages_path.find_surrounding_package(&current_directory) else {
+        return;
+    };
+    for module in package.modules(&packages_path) {
+        let Some(path) = module.try_to_path(&packages_path) else {
+            continue;
+        };
+        let path = path.strip_prefix(&current_directory).unwrap_or(&path);
+        println!("{}", path.display());
+    }
+}
+
+/// What can follow a command.
+struct CommandCompletions {
+    /// For `candy` and each subcommand leading to this command, the name
+    /// followed by its aliases.
+    path: Vec<Vec<String>>,
+    subcommands: Vec<Candidate>,
+    flags: Vec<Flag>,
+    positional: Values,
+}
+struct Candidate {
+    name: String,
+    help: String,
+}
+struct Flag {
+    /// Without the leading dashes.
+    long: String,
+    help: String,
+    /// `None` if the flag doesn't take a value.
+    values: Option<Values>,
+}
+enum Values {
+    Nothing,
+    Modules,
+    Paths,
+    Directories,
+    OneOf(Vec<String>),
+}
+
+impl CommandCompletions {
+    fn collect(command: &Command, parent_path: &[Vec<String>]) -> Vec<Self> {
+        let mut path = parent_path.to_vec();
+        path.push(
+            iter::once(command.get_name())
+                .chain(command.get_all_aliases())
+                .map(ToString::to_string)
+                .collect(),
+        );
+
+        let subcommands = command
+            .get_subcommands()
+            .filter(|it| !it.is_hide_set())
+            .collect_vec();
+        let arguments = command
+            .get_arguments()
+            .filter(|it| !it.is_hide_set())
+            .collect_vec();
+        let flags = arguments
+            .iter()
+            .filter_map(|argument| {
+                Some(Flag {
+                    long: argument.get_long()?.to_string(),
+                    help: first_line(argument.get_help()),
+                    values: argument
+                        .get_action()
+                        .takes_values()
+                        .then(|| Values::of(argument)),
+                })
+            })
+            .collect();
+        let positional = arguments
+            .iter()
+            .find(|it| it.is_positional())
+            .map_or(Values::Nothing, |it| Values::of(it));
+
+        let mut commands = vec![Self {
+            path: path.clone(),
+            subcommands: subcommands
+                .iter()
+                .map(|it| Candidate {
+                    name: it.get_name().to_string(),
+                    help: first_line(it.get_about()),
+                })
+                .collect(),
+            flags,
+            positional,
+        }];
+        for subcommand in subcommands {
+            commands.extend(Self::collect(subcommand, &path));
+        }
+        commands
+    }
+
+    fn name(&self) -> String {
+        self.path.iter().map(|names| &names[0]).join(" ")
+    }
+}
+impl Values {
+    fn of(argument: &Arg) -> Self {
+        let possible_values = argument.get_possible_values();
+        if !possible_values.is_empty() {
+            return Self::OneOf(
+                possible_values
+                    .iter()
+                    .filter(|it| !it.is_hide_set())
+                    .map(|it| it.get_name().to_string())
+                    .collect(),
+            );
+        }
+        match argument.get_value_hint() {
+            ValueHint::FilePath if argument.is_positional() => Self::Modules,
+            ValueHint::AnyPath | ValueHint::FilePath => Self::Paths,
+            ValueHint::DirPath => Self::Directories,
+            _ => Self::Nothing,
+        }
+    }
+}
+fn first_line(help: Option<&impl ToString>) -> String {
+    help.map_or_else(String::new, |it| {
+        it.to_string()
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    })
+}
+
+fn bash(commands: &[CommandCompletions]) -> String {
+    let mut script = vec![
+        "_candy() {".to_string(),
+        r#"    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}""#
+            .to_string(),
+        r#"    local command="candy" word"#.to_string(),
+        r#"    for word in "${COMP_WORDS[@]:1:COMP_CWORD-1}"; do"#.to_string(),
+        r#"        case "$command,$word" in"#.to_string(),
+    ];
+    for command in commands.iter().filter(|it| it.path.len() > 1) {
+        let (names, parent) = command.path.split_last().unwrap();
+        let parent = parent.iter().map(|names| &names[0]).join(" ");
+        let patterns = names
+            .iter()
+            .map(|name| format!("'{parent},{name}'"))
+            .join(" | ");
+        script.push(format!(
+            "            {patterns}) command='{}' ;;",
+            command.name(),
+        ));
+    }
+    script.extend([
+        "        esac".to_string(),
+        "    done".to_string(),
+        String::new(),
+    ]);
+
+    script.push(r#"    case "$command,$prev" in"#.to_string());
+    for command in commands {
+        for flag in &command.flags {
+            let Some(values) = &flag.values else {
+                continue;
+            };
+            script.push(format!(
+                "        '{},--{}') {}; return ;;",
+                command.name(),
+                flag.long,
+                bash_completion(values),
+            ));
+        }
+    }
+    script.extend(["    esac".to_string(), String::new()]);
+
+    script.push(r#"    case "$command" in"#.to_string());
+    for command in commands {
+        let flags = command
+            .flags
+            .iter()
+            .map(|it| format!("--{}", it.long))
+            .join(" ");
+        let others = if command.subcommands.is_empty() {
+            bash_completion(&command.positional)
+        } else {
+            let subcommands = command.subcommands.iter().map(|it| &it.name).join(" ");
+            format!(r#"COMPREPLY=($(compgen -W '{subcommands}' -- "$cur"))"#)
+        };
+        script.extend([
+            format!("        '{}')", command.name()),
+            r#"            if [[ "$cur" == -* ]]; then"#.to_string(),
+            format!(r#"                COMPREPLY=($(compgen -W '{flags}' -- "$cur"))"#),
+            "            else".to_string(),
+            format!("                {others}"),
+            "            fi".to_string(),
+            "            ;;".to_string(),
+        ]);
+    }
+    script.extend([
+        "    esac".to_string(),
+        "}".to_string(),
+        "complete -F _candy candy".to_string(),
+    ]);
+    script.iter().map(|line| format!("{line}\n")).join("")
+}
+fn bash_completion(values: &Values) -> String {
+    match values {
+        Values::Nothing => "COMPREPLY=()".to_string(),
+        Values::Modules => {
+            format!(r#"COMPREPLY=($(compgen -W "$({LIST_MODULES} 2>/dev/null)" -- "$cur"))"#)
+        }
+        Values::Paths => r#"COMPREPLY=($(compgen -f -- "$cur"))"#.to_string(),
+        Values::Directories => r#"COMPREPLY=($(compgen -d -- "$cur"))"#.to_string(),
+        Values::OneOf(values) => {
+            format!(
+                r#"COMPREPLY=($(compgen -W '{}' -- "$cur"))"#,
+                values.join(" ")
+            )
+        }
+    }
+}
+
+fn fish(commands: &[CommandCompletions]) -> String {
+    // Files are only completed where we ask for them.
+    let mut lines = vec!["complete -c candy -f".to_string()];
+    for command in commands {
+        let mut conditions = command.path[1..]
+            .iter()
+            .map(|names| format!("__fish_seen_subcommand_from {}", names.join(" ")))
+            .collect_vec();
+        if command.path.len() == 1 {
+            conditions.push("__fish_use_subcommand".to_string());
+        } else if !command.subcommands.is_empty() {
+            let subcommands = command.subcommands.iter().map(|it| &it.name).join(" ");
+            conditions.push(format!("not __fish_seen_subcommand_from {subcommands}"));
+        }
+        let complete = format!(
+            "complete -c candy -n {}",
+            fish_quote(&conditions.join("; and "))
+        );
+
+        for subcommand in &command.subcommands {
+            lines.push(format!(
+                "{complete} -a {} -d {}",
+                subcommand.name,
+                fish_quote(&subcommand.help),
+            ));
+        }
+        for flag in &command.flags {
+            let values = flag
+                .values
+                .as_ref()
+                .map_or_else(String::new, |it| format!(" -r{}", fish_completion(it)));
+            lines.push(format!(
+                "{complete} -l {}{values} -d {}",
+                flag.long,
+                fish_quote(&flag.help),
+            ));
+        }
+        if command.subcommands.is_empty() && !matches!(command.positional, Values::Nothing) {
+            lines.push(format!(
+                "{complete}{}",
+                fish_completion(&command.positional)
+            ));
+        }
+    }
+    lines.iter().map(|line| format!("{line}\n")).join("")
+}
+fn fish_completion(values: &Values) -> String {
+    match values {
+        Values::Nothing => String::new(),
+        Values::Modules => format!(" -a '({LIST_MODULES})'"),
+        Values::Paths => " -F".to_string(),
+        Values::Directories => " -a '(__fish_complete_directories)'".to_string(),
+        Values::OneOf(values) => format!(" -a {}", fish_quote(&values.join(" "))),
+    }
+}
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn powershell(commands: &[CommandCompletions]) -> String {
+    let mut script = vec![
+        "Register-ArgumentCompleter -Native -CommandName candy -ScriptBlock {".to_string(),
+        "    param($wordToComplete, $commandAst, $cursorPosition)".to_string(),
+        String::new(),
+        "    $words = @($commandAst.CommandElements |".to_string(),
+        "        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } |".to_string(),
+        "        ForEach-Object { $_.ToString() })".to_string(),
+        "    $command = 'candy'".to_string(),
+        "    foreach ($word in $words | Select-Object -Skip 1) {".to_string(),
+        r#"        switch ("$command,$word") {"#.to_string(),
+    ];
+    for command in commands.iter().filter(|it| it.path.len() > 1) {
+        let (names, parent) = command.path.split_last().unwrap();
+        let parent = parent.iter().map(|names| &names[0]).join(" ");
+        for name in names {
+            script.push(format!(
+                "            '{parent},{name}' {{ $command = '{}' }}",
+                command.name(),
+            ));
+        }
+    }
+    script.extend([
+        "        }".to_string(),
+        "    }".to_string(),
+        "    $previous = $words[-1]".to_string(),
+        String::new(),
+        // Without candidates, PowerShell falls back to completing paths.
+        "    $candidates = $null".to_string(),
+        r#"    switch ("$command,$previous") {"#.to_string(),
+    ]);
+    for command in commands {
+        for flag in &command.flags {
+            let Some(values) = &flag.values else {
+                continue;
+            };
+            script.push(format!(
+                "        '{},--{}' {{ $candidates = {} }}",
+                command.name(),
+                flag.long,
+                powershell_completion(values),
+            ));
+        }
+    }
+    script.extend([
+        "        default {".to_string(),
+        "            if ($wordToComplete.StartsWith('-')) {".to_string(),
+        "                switch ($command) {".to_string(),
+    ]);
+    for command in commands {
+        script.push(format!(
+            "                    '{}' {{ $candidates = @({}) }}",
+            command.name(),
+            command
+                .flags
+                .iter()
+                .map(|it| format!("'--{}'", it.long))
+                .join(", "),
+        ));
+    }
+    script.extend([
+        "                }".to_string(),
+        "            } else {".to_string(),
+        "                switch ($command) {".to_string(),
+    ]);
+    for command in commands {
+        let candidates = if command.subcommands.is_empty() {
+            powershell_completion(&command.positional)
+        } else {
+            format!(
+                "@({})",
+                command
+                    .subcommands
+                    .iter()
+                    .map(|it| format!("'{}'", it.name))
+                    .join(", "),
+            )
+        };
+        script.push(format!(
+            "                    '{}' {{ $candidates = {candidates} }}",
+            command.name(),
+        ));
+    }
+    script.extend([
+        "                }".to_string(),
+        "            }".to_string(),
+        "        }".to_string(),
+        "    }".to_string(),
+        "    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {"
+            .to_string(),
+        "        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)"
+            .to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+    ]);
+    script.iter().map(|line| format!("{line}\n")).join("")
+}
+fn powershell_completion(values: &Values) -> String {
+    match values {
+        Values::Nothing => "@()".to_string(),
+        Values::Modules => format!("@({LIST_MODULES})"),
+        Values::Paths | Values::Directories => "$null".to_string(),
+        Values::OneOf(values) => format!(
+            "@({})",
+            values.iter().map(|it| format!("'{it}'")).join(", "),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{script, CommandCompletions, Shell};
+    use crate::CandyOptions;
+    use clap::{Command, CommandFactory, ValueEnum};
+
+    /// Checks that each script mentions every visible subcommand, alias, and
+    /// long flag of the CLI.
+    #[test]
+    fn scripts_cover_all_commands_and_flags() {
+        fn expected_words(command: &Command, words: &mut Vec<String>) {
+            for subcommand in command.get_subcommands().filter(|it| !it.is_hide_set()) {
+                words.push(subcommand.get_name().to_string());
+                words.extend(subcommand.get_all_aliases().map(ToString::to_string));
+                expected_words(subcommand, words);
+            }
+            for argument in command.get_arguments().filter(|it| !it.is_hide_set()) {
+                words.extend(argument.get_long().map(|it| format!("--{it}")));
+            }
+        }
+
+        let mut command = CandyOptions::command();
+        command.build();
+        let mut words = vec![];
+        expected_words(&command, &mut words);
+        assert!(words.contains(&"fmt".to_string()));
+
+        for shell in Shell::value_variants() {
+            let script = script(*shell, CandyOptions::command());
+            for word in &words {
+                // Fish lists flags without their dashes.
+                let word = if *shell == Shell::Fish {
+                    word.trim_start_matches('-')
+                } else {
+                    word
+                };
+                assert!(
+                    script.contains(word),
+                    "The {shell:?} script doesn't complete `{word}`.",
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hidden_flags_are_not_completed() {
+        let mut command = CandyOptions::command();
+        command.build();
+        let commands = CommandCompletions::collect(&command, &[]);
+        let completions = commands
+            .iter()
+            .find(|it| it.name() == "candy completions")
+            .unwrap();
+        assert!(completions.flags.iter().all(|it| it.long != "list-modules"));
+    }
+}
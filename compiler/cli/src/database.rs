@@ -19,6 +19,10 @@ use candy_frontend::{
     rcst_to_cst::RcstToCstStorage,
     string_to_rcst::StringToRcstStorage,
 };
+use candy_vm::{
+    eval::{eval_expression, ExecutionResult},
+    heap::Heap,
+};
 
 #[cfg_attr(
     feature = "inkwell",
@@ -76,6 +80,17 @@ impl Database {
             ),
         }
     }
+
+    /// Compiles and runs the expression, optionally in the context of a
+    /// module. See [`candy_vm::eval`] for details.
+    pub fn eval_expression(
+        &mut self,
+        heap: &mut Heap,
+        source: &str,
+        context: Option<&Module>,
+    ) -> ExecutionResult {
+        eval_expression(self, heap, source, context, None)
+    }
 }
 
 impl ModuleProviderOwner for Database {
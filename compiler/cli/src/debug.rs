@@ -8,6 +8,7 @@ use candy_backend_inkwell::LlvmIrDb;
 use candy_frontend::{
     ast_to_hir::AstToHir,
     cst_to_ast::CstToAst,
+    hir::HirDb,
     hir_to_mir::{ExecutionTarget, HirToMir},
     lir_optimize::OptimizeLir,
     mir_optimize::OptimizeMir,
@@ -20,7 +21,10 @@ use candy_frontend::{
     utils::DoHash,
     TracingConfig, TracingMode,
 };
-use candy_vm::{byte_code::RichIrForByteCode, heap::HeapData, lir_to_byte_code::compile_byte_code};
+use candy_vm::{
+    byte_code::RichIrForByteCode, disassembler::RichIrForDisassembly, heap::HeapData,
+    lir_to_byte_code::compile_byte_code,
+};
 use clap::{Parser, ValueEnum, ValueHint};
 use colored::{Color, Colorize};
 use diffy::{create_patch, PatchFormatter};
@@ -50,9 +54,15 @@ pub enum Options {
     /// Abstract Syntax Tree
     Ast(OnlyPath),
 
+    /// Abstract Syntax Tree after applying all desugarings
+    DesugaredAst(OnlyPath),
+
     /// High-Level Intermediate Representation
     Hir(OnlyPath),
 
+    /// Expressions that can panic and who is blamed if they do
+    Responsibility(OnlyPath),
+
     /// Mid-Level Intermediate Representation
     Mir(PathAndExecutionTargetAndTracing),
 
@@ -68,6 +78,10 @@ pub enum Options {
     /// VM Byte Code
     VmByteCode(PathAndExecutionTargetAndTracing),
 
+    /// Disassembled VM Byte Code with named constants, symbolic jump targets,
+    /// and interleaved source lines
+    Disassembly(PathAndExecutionTargetAndTracing),
+
     /// LLVM Intermediate Representation
     #[cfg(feature = "inkwell")]
     LlvmIr(PathAndExecutionTarget),
@@ -156,11 +170,32 @@ pub fn debug(options: Options) -> ProgramResult {
             let ast = db.ast(module.clone());
             ast.ok().map(|(ast, _)| RichIr::for_ast(&module, &ast))
         }
+        Options::DesugaredAst(options) => {
+            let module = module_for_path(options.path)?;
+            let ast = db.desugared_ast(module.clone());
+            ast.ok().map(|ast| RichIr::for_desugared_ast(&module, &ast))
+        }
         Options::Hir(options) => {
             let module = module_for_path(options.path)?;
             let hir = db.hir(module.clone());
             hir.ok().map(|(hir, _)| RichIr::for_hir(&module, &hir))
         }
+        Options::Responsibility(options) => {
+            let module = module_for_path(options.path)?;
+            db.hir(module.clone()).ok().map(|_| {
+                let responsibilities = db
+                    .all_hir_ids(module.clone())
+                    .into_iter()
+                    .unique()
+                    .sorted()
+                    .filter_map(|id| {
+                        let responsibility = db.responsibility(id.clone())?;
+                        responsibility.can_panic().then_some((id, responsibility))
+                    })
+                    .collect_vec();
+                RichIr::for_responsibilities(&module, &responsibilities)
+            })
+        }
         Options::Mir(options) => {
             let module = module_for_path(options.path.clone())?;
             let execution_target = options.execution_target.resolve(module.clone());
@@ -200,6 +235,18 @@ pub fn debug(options: Options) -> ProgramResult {
             let (vm_byte_code, _) = compile_byte_code(&db, execution_target, tracing.clone());
             Some(RichIr::for_byte_code(&module, &vm_byte_code, &tracing))
         }
+        Options::Disassembly(options) => {
+            let module = module_for_path(options.path.clone())?;
+            let execution_target = options.execution_target.resolve(module.clone());
+            let tracing = options.to_tracing_config();
+            let (vm_byte_code, _) = compile_byte_code(&db, execution_target, tracing.clone());
+            Some(RichIr::for_disassembly(
+                &module,
+                &vm_byte_code,
+                &tracing,
+                &db,
+            ))
+        }
         #[cfg(feature = "inkwell")]
         Options::LlvmIr(options) => {
             let module = module_for_path(options.path.clone())?;
@@ -325,7 +372,6 @@ impl Gold {
     }
 }
 impl GoldOptions {
-    const TRACING_CONFIG: TracingConfig = TracingConfig::off();
     fn visit_irs(
         &self,
         db: &Database,
@@ -363,61 +409,8 @@ impl GoldOptions {
                 visitor(path, ir_name, &ir_file, ir);
             };
 
-            let rcst = db.rcst(module.clone());
-            let rcst = RichIr::for_rcst(&module, &rcst).unwrap();
-            visit("RCST", rcst.text);
-
-            let cst = db.cst(module.clone());
-            let cst = RichIr::for_cst(&module, &cst).unwrap();
-            visit("CST", cst.text);
-
-            let (ast, _) = db.ast(module.clone()).unwrap();
-            let ast = RichIr::for_ast(&module, &ast);
-            visit("AST", ast.text);
-
-            let (hir, _) = db.hir(module.clone()).unwrap();
-            let hir = RichIr::for_hir(&module, &hir);
-            visit("HIR", hir.text);
-
-            let (mir, _) = db
-                .mir(execution_target.clone(), Self::TRACING_CONFIG.clone())
-                .unwrap();
-            let mir = RichIr::for_mir(&module, &mir, &Self::TRACING_CONFIG);
-            visit("MIR", mir.text);
-
-            let (optimized_mir, _, _) = db
-                .optimized_mir(execution_target.clone(), Self::TRACING_CONFIG.clone())
-                .unwrap();
-            let optimized_mir =
-                RichIr::for_optimized_mir(&module, &optimized_mir, &Self::TRACING_CONFIG);
-            visit("Optimized MIR", optimized_mir.text);
-
-            let (lir, _) = db
-                .lir(execution_target.clone(), Self::TRACING_CONFIG.clone())
-                .unwrap();
-            let lir = RichIr::for_lir(&module, &lir, &Self::TRACING_CONFIG);
-            visit("LIR", lir.text);
-
-            let (optimized_lir, _) = db
-                .optimized_lir(execution_target.clone(), Self::TRACING_CONFIG.clone())
-                .unwrap();
-            let optimized_lir =
-                RichIr::for_optimized_lir(&module, &optimized_lir, &Self::TRACING_CONFIG);
-            visit("Optimized LIR", optimized_lir.text);
-
-            let (vm_byte_code, _) =
-                compile_byte_code(db, execution_target.clone(), Self::TRACING_CONFIG.clone());
-            let vm_byte_code_rich_ir =
-                RichIr::for_byte_code(&module, &vm_byte_code, &Self::TRACING_CONFIG);
-            visit(
-                "VM Byte Code",
-                Self::format_byte_code(&vm_byte_code, &vm_byte_code_rich_ir),
-            );
-
-            #[cfg(feature = "inkwell")]
-            {
-                let llvm_ir = db.llvm_ir(execution_target).unwrap();
-                visit("LLVM IR", llvm_ir.text);
+            for (ir_name, ir) in irs(db, &module, &execution_target) {
+                visit(ir_name, ir);
             }
         }
         Ok(())
@@ -471,6 +464,70 @@ impl GoldOptions {
     }
 }
 
+/// All IRs of the module in the order in which they're created. Addresses in
+/// the byte code are replaced so that the IRs of different compilations can be
+/// compared.
+pub fn irs(
+    db: &Database,
+    module: &Module,
+    execution_target: &ExecutionTarget,
+) -> Vec<(&'static str, String)> {
+    const TRACING_CONFIG: TracingConfig = TracingConfig::off();
+    let mut irs = vec![];
+    let mut visit = |ir_name: &'static str, ir: String| irs.push((ir_name, ir));
+
+    let rcst = db.rcst(module.clone());
+    let rcst = RichIr::for_rcst(module, &rcst).unwrap();
+    visit("RCST", rcst.text);
+
+    let cst = db.cst(module.clone());
+    let cst = RichIr::for_cst(module, &cst).unwrap();
+    visit("CST", cst.text);
+
+    let (ast, _) = db.ast(module.clone()).unwrap();
+    let ast = RichIr::for_ast(module, &ast);
+    visit("AST", ast.text);
+
+    let (hir, _) = db.hir(module.clone()).unwrap();
+    let hir = RichIr::for_hir(module, &hir);
+    visit("HIR", hir.text);
+
+    let (mir, _) = db.mir(execution_target.clone(), TRACING_CONFIG).unwrap();
+    let mir = RichIr::for_mir(module, &mir, &TRACING_CONFIG);
+    visit("MIR", mir.text);
+
+    let (optimized_mir, _, _) = db
+        .optimized_mir(execution_target.clone(), TRACING_CONFIG)
+        .unwrap();
+    let optimized_mir = RichIr::for_optimized_mir(module, &optimized_mir, &TRACING_CONFIG);
+    visit("Optimized MIR", optimized_mir.text);
+
+    let (lir, _) = db.lir(execution_target.clone(), TRACING_CONFIG).unwrap();
+    let lir = RichIr::for_lir(module, &lir, &TRACING_CONFIG);
+    visit("LIR", lir.text);
+
+    let (optimized_lir, _) = db
+        .optimized_lir(execution_target.clone(), TRACING_CONFIG)
+        .unwrap();
+    let optimized_lir = RichIr::for_optimized_lir(module, &optimized_lir, &TRACING_CONFIG);
+    visit("Optimized LIR", optimized_lir.text);
+
+    let (vm_byte_code, _) = compile_byte_code(db, execution_target.clone(), TRACING_CONFIG);
+    let vm_byte_code_rich_ir = RichIr::for_byte_code(module, &vm_byte_code, &TRACING_CONFIG);
+    visit(
+        "VM Byte Code",
+        GoldOptions::format_byte_code(&vm_byte_code, &vm_byte_code_rich_ir),
+    );
+
+    #[cfg(feature = "inkwell")]
+    {
+        let llvm_ir = db.llvm_ir(execution_target.clone()).unwrap();
+        visit("LLVM IR", llvm_ir.text);
+    }
+
+    irs
+}
+
 lazy_static! {
     static ref ADDRESS_REGEX: Regex = {
         const ADDRESS: &str = "0x[0-9a-f]{1,16}";
@@ -0,0 +1,63 @@
+use crate::{
+    database::Database,
+    utils::{module_for_path, packages_path},
+    Exit, PanicDetails, ProgramResult,
+};
+use candy_frontend::position::{LineIndex, Offset, PositionEncoding};
+use candy_vm::{eval::ExecutionResult, heap::Heap};
+use clap::{Parser, ValueHint};
+use std::{ops::Range, path::PathBuf};
+use tracing::error;
+
+/// Evaluate a Candy expression and print its value.
+///
+/// The expression can't call handles, so it can't access the outside world.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// The expression to evaluate, e.g., `(use "Core").int.add 1 2`.
+    expression: String,
+
+    /// Evaluate the expression inside this module, so that it can access the
+    /// module's definitions.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    context: Option<PathBuf>,
+}
+
+pub fn eval(options: Options) -> ProgramResult {
+    let mut db = Database::new_with_file_system_module_provider(packages_path());
+    let context = options.context.map(module_for_path).transpose()?;
+
+    let line_index = LineIndex::new(&options.expression);
+    let to_positions = |span: Range<Offset>| {
+        line_index.offset_to_position(span.start, PositionEncoding::CodePoint)
+            ..line_index.offset_to_position(span.end, PositionEncoding::CodePoint)
+    };
+
+    let mut heap = Heap::default();
+    match db.eval_expression(&mut heap, &options.expression, context.as_ref()) {
+        ExecutionResult::Value(value) => {
+            println!("{value:?}");
+            Ok(())
+        }
+        ExecutionResult::CompilerErrors(errors) => {
+            for error in errors {
+                let Range { start, end } = to_positions(error.span);
+                error!("{start} – {end}: {}", error.payload);
+            }
+            Err(Exit::CodeContainsErrors)
+        }
+        ExecutionResult::Panicked {
+            panic,
+            snippet_span,
+        } => {
+            error!("The expression panicked: {}", panic.reason);
+            error!("{} is responsible.", panic.responsible);
+            Err(Exit::CodePanicked(Box::new(PanicDetails {
+                reason: panic.reason,
+                responsible: panic.responsible,
+                responsible_span: snippet_span.map(to_positions),
+            })))
+        }
+        ExecutionResult::DidNotFinish => unreachable!("The evaluation has no instruction budget."),
+    }
+}
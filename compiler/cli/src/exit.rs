@@ -0,0 +1,224 @@
+//! How the CLI reports the outcome of a command to tools wrapping it.
+//!
+//! Every [`Exit`] variant has its own exit code, so scripts can tell compile
+//! errors, panics, and IO problems apart. With `--json`, a final result object
+//! is printed as well:
+//!
+//! ```json
+//! {"status":"codePanicked","exitCode":4,"durationMicros":1234,"panic":{…}}
+//! ```
+
+use candy_frontend::{hir, position::Position};
+use serde_json::{json, Value};
+use std::{ops::Range, process::ExitCode, time::Duration};
+
+pub type ProgramResult = Result<(), Exit>;
+#[derive(Debug)]
+pub enum Exit {
+    CodePanicked(Box<PanicDetails>),
+    DirectoryNotFound,
+    #[cfg(feature = "inkwell")]
+    ExternalError,
+    FileNotFound,
+    FuzzingFoundFailingCases,
+    IoError,
+    NotInCandyPackage,
+    PathAlreadyExists,
+    RecordingError,
+    CodeContainsErrors,
+    #[cfg(feature = "inkwell")]
+    LlvmError(String),
+    GoldOutdated,
+    CompilationNotDeterministic,
+    SelfTestsFailed,
+    LspTransportError,
+}
+#[derive(Debug)]
+pub struct PanicDetails {
+    pub reason: String,
+    pub responsible: hir::Id,
+    pub responsible_span: Option<Range<Position>>,
+}
+
+impl Exit {
+    /// The process exit code for this outcome.
+    ///
+    /// Codes 1 and 2 are left out because Rust and clap use them for crashes
+    /// and invalid arguments.
+    #[must_use]
+    pub const fn code(&self) -> u8 {
+        match self {
+            Self::CodeContainsErrors => 3,
+            Self::CodePanicked(_) => 4,
+            Self::FuzzingFoundFailingCases => 5,
+            Self::GoldOutdated => 6,
+            Self::CompilationNotDeterministic => 7,
+            Self::SelfTestsFailed => 8,
+            Self::FileNotFound => 10,
+            Self::DirectoryNotFound => 11,
+            Self::NotInCandyPackage => 12,
+            Self::RecordingError => 13,
+            Self::PathAlreadyExists => 14,
+            Self::IoError => 15,
+            Self::LspTransportError => 20,
+            #[cfg(feature = "inkwell")]
+            Self::ExternalError => 30,
+            #[cfg(feature = "inkwell")]
+            Self::LlvmError(_) => 31,
+        }
+    }
+
+    #[must_use]
+    pub const fn status(&self) -> &'static str {
+        match self {
+            Self::CodePanicked(_) => "codePanicked",
+            Self::DirectoryNotFound => "directoryNotFound",
+            #[cfg(feature = "inkwell")]
+            Self::ExternalError => "externalError",
+            Self::FileNotFound => "fileNotFound",
+            Self::FuzzingFoundFailingCases => "fuzzingFoundFailingCases",
+            Self::IoError => "ioError",
+            Self::NotInCandyPackage => "notInCandyPackage",
+            Self::PathAlreadyExists => "pathAlreadyExists",
+            Self::RecordingError => "recordingError",
+            Self::CodeContainsErrors => "codeContainsErrors",
+            #[cfg(feature = "inkwell")]
+            Self::LlvmError(_) => "llvmError",
+            Self::GoldOutdated => "goldOutdated",
+            Self::CompilationNotDeterministic => "compilationNotDeterministic",
+            Self::SelfTestsFailed => "selfTestsFailed",
+            Self::LspTransportError => "lspTransportError",
+        }
+    }
+}
+
+#[must_use]
+pub fn exit_code(result: &ProgramResult) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(exit) => ExitCode::from(exit.code()),
+    }
+}
+
+#[must_use]
+pub fn format_json(result: &ProgramResult, duration: Duration) -> String {
+    let mut envelope = json!({
+        "status": result.as_ref().err().map_or("success", Exit::status),
+        "exitCode": result.as_ref().err().map_or(0, Exit::code),
+        // JSON values can't hold a `u128`.
+        "durationMicros": u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+    });
+    match result {
+        Err(Exit::CodePanicked(panic)) => {
+            envelope["panic"] = json!({
+                "reason": panic.reason,
+                "responsible": panic.responsible.to_string(),
+                "responsibleSpan": panic.responsible_span.as_ref().map(|span| json!({
+                    "start": position_to_json(span.start),
+                    "end": position_to_json(span.end),
+                })),
+            });
+        }
+        #[cfg(feature = "inkwell")]
+        Err(Exit::LlvmError(message)) => envelope["message"] = Value::from(message.as_str()),
+        _ => {}
+    }
+    envelope.to_string()
+}
+fn position_to_json(position: Position) -> Value {
+    json!({ "line": position.line, "character": position.character })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_json, Exit, PanicDetails};
+    use candy_frontend::{
+        hir,
+        module::{Module, ModuleKind, Package},
+        position::Position,
+    };
+    use itertools::Itertools;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    fn exits() -> Vec<Exit> {
+        vec![
+            Exit::CodePanicked(Box::new(PanicDetails {
+                reason: "Oops".to_string(),
+                responsible: hir::Id::new(
+                    Module {
+                        package: Package::Anonymous {
+                            url: "test".to_string(),
+                        },
+                        path: vec![],
+                        kind: ModuleKind::Code,
+                    },
+                    vec![],
+                ),
+                responsible_span: Some(
+                    Position {
+                        line: 1,
+                        character: 2,
+                    }..Position {
+                        line: 1,
+                        character: 6,
+                    },
+                ),
+            })),
+            Exit::DirectoryNotFound,
+            Exit::FileNotFound,
+            Exit::FuzzingFoundFailingCases,
+            Exit::IoError,
+            Exit::NotInCandyPackage,
+            Exit::PathAlreadyExists,
+            Exit::RecordingError,
+            Exit::CodeContainsErrors,
+            Exit::GoldOutdated,
+            Exit::CompilationNotDeterministic,
+            Exit::SelfTestsFailed,
+            Exit::LspTransportError,
+        ]
+    }
+
+    #[test]
+    fn exits_have_distinct_codes_and_statuses() {
+        let exits = exits();
+        assert!(exits.iter().map(Exit::code).all_unique());
+        assert!(exits.iter().map(Exit::status).all_unique());
+        // Success, crashes, and invalid arguments use codes 0 to 2.
+        assert!(exits.iter().all(|it| it.code() > 2));
+    }
+
+    #[test]
+    fn successes_are_formatted_as_json() {
+        let json: Value =
+            serde_json::from_str(&format_json(&Ok(()), Duration::from_micros(1234))).unwrap();
+        assert_eq!(
+            json,
+            json!({ "status": "success", "exitCode": 0, "durationMicros": 1234 }),
+        );
+    }
+
+    #[test]
+    fn panics_are_formatted_as_json() {
+        let result = Err(exits().remove(0));
+        let json: Value =
+            serde_json::from_str(&format_json(&result, Duration::from_micros(42))).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "status": "codePanicked",
+                "exitCode": 4,
+                "durationMicros": 42,
+                "panic": {
+                    "reason": "Oops",
+                    "responsible": "anonymous:test::",
+                    "responsibleSpan": {
+                        "start": { "line": 1, "character": 2 },
+                        "end": { "line": 1, "character": 6 },
+                    },
+                },
+            }),
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{Exit, ProgramResult};
+use candy_formatter::Formatter;
+use candy_frontend::{
+    cst::{Cst, CstKind},
+    error::CompilerErrorPayload,
+    position::{LineIndex, PositionEncoding},
+    rcst_to_cst::RcstsToCstsExt,
+    string_to_rcst::parse_rcst,
+};
+use clap::{Parser, ValueHint};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+use tracing::{error, info};
+
+/// Format Candy files.
+///
+/// This command formats the given files in place. If no files are provided,
+/// it reads code from stdin and writes the formatted code to stdout, so that
+/// editors without language server support can use it as a filter.
+///
+/// Files containing syntax errors are left unchanged and the errors are
+/// printed to stderr.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// The files to format in place.
+    #[arg(value_hint = ValueHint::FilePath, conflicts_with = "stdin_filename")]
+    paths: Vec<PathBuf>,
+
+    /// The path of the file whose code is passed via stdin. It's used in error
+    /// messages and for choosing line endings if the code itself doesn't
+    /// contain any. The file doesn't have to exist.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    stdin_filename: Option<PathBuf>,
+}
+impl Options {
+    #[must_use]
+    pub fn uses_stdio(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn format(options: Options) -> ProgramResult {
+    if options.uses_stdio() {
+        return format_stdin(options.stdin_filename.as_deref());
+    }
+
+    let mut has_errors = false;
+    for path in &options.paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                error!("{} doesn't exist.", path.display());
+                return Err(Exit::FileNotFound);
+            }
+            Err(error) => {
+                error!("Couldn't read {}: {error}", path.display());
+                return Err(Exit::IoError);
+            }
+        };
+        let Ok(formatted) = format_source(&source, &path.display().to_string(), None) else {
+            has_errors = true;
+            continue;
+        };
+        if formatted != source {
+            if let Err(error) = fs::write(path, formatted) {
+                error!("Couldn't write {}: {error}", path.display());
+                return Err(Exit::IoError);
+            }
+            info!("Formatted {}.", path.display());
+        }
+    }
+    if has_errors {
+        Err(Exit::CodeContainsErrors)
+    } else {
+        Ok(())
+    }
+}
+
+fn format_stdin(filename: Option<&Path>) -> ProgramResult {
+    let mut source = String::new();
+    if let Err(error) = io::stdin().read_to_string(&mut source) {
+        error!("Couldn't read from stdin: {error}");
+        return Err(Exit::IoError);
+    }
+
+    let name = filename.map_or_else(|| "<stdin>".to_string(), |it| it.display().to_string());
+    let existing_content = filename.and_then(|it| fs::read_to_string(it).ok());
+    let formatted = format_source(&source, &name, existing_content.as_deref())?;
+
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(formatted.as_bytes())
+        .and_then(|()| stdout.flush())
+        .map_err(|error| {
+            error!("Couldn't write to stdout: {error}");
+            Exit::IoError
+        })
+}
+
+/// Formats the code or reports its syntax errors. The formatted code uses the
+/// line endings of the `source` or, if it doesn't contain line breaks, the
+/// ones of the `fallback_for_line_endings`.
+fn format_source(
+    source: &str,
+    name: &str,
+    fallback_for_line_endings: Option<&str>,
+) -> Result<String, Exit> {
+    let csts = parse_rcst(source).to_csts();
+
+    let mut errors = vec![];
+    collect_errors(&csts, &mut errors);
+    if !errors.is_empty() {
+        let line_index = LineIndex::new(source);
+        for (cst, message) in errors {
+            let position = line_index
+                .offset_to_position(cst.display_span().start, PositionEncoding::CodePoint);
+            error!(
+                "{name}:{}:{}: {message}",
+                position.line + 1,
+                position.character + 1,
+            );
+        }
+        return Err(Exit::CodeContainsErrors);
+    }
+
+    let formatted = csts.format_to_string();
+    let uses_crlf = if source.contains('\n') {
+        source.contains("\r\n")
+    } else {
+        fallback_for_line_endings.is_some_and(|it| it.contains("\r\n"))
+    };
+    Ok(if uses_crlf {
+        formatted.replace("\r\n", "\n").replace('\n', "\r\n")
+    } else {
+        formatted
+    })
+}
+
+fn collect_errors<'a>(
+    csts: impl IntoIterator<Item = &'a Cst>,
+    errors: &mut Vec<(&'a Cst, String)>,
+) {
+    for cst in csts {
+        if let CstKind::Error { error, .. } = &cst.kind {
+            errors.push((cst, CompilerErrorPayload::Cst(*error).to_string()));
+        }
+        collect_errors(cst.kind.children(), errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, Options};
+    use crate::Exit;
+    use std::{env, fs, path::PathBuf, process};
+
+    /// A file in a fresh temporary directory.
+    fn temporary_file(name: &str, content: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("candy-format-{}-{name}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("main.candy");
+        fs::write(&path, content).unwrap();
+        path
+    }
+    fn format_paths(paths: Vec<PathBuf>) -> Result<(), Exit> {
+        format(Options {
+            paths,
+            stdin_filename: None,
+        })
+    }
+
+    #[test]
+    fn formats_files_in_place() {
+        let path = temporary_file("in-place", "foo   =   1\n");
+        assert!(format_paths(vec![path.clone()]).is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo = 1\n");
+    }
+
+    #[test]
+    fn leaves_files_with_syntax_errors_unchanged() {
+        let path = temporary_file("syntax-error", "foo   =   \"unclosed\n");
+        let result = format_paths(vec![path.clone()]);
+        assert!(matches!(result, Err(Exit::CodeContainsErrors)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo   =   \"unclosed\n");
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let path = temporary_file("missing", "").with_file_name("missing.candy");
+        let result = format_paths(vec![path]);
+        assert!(matches!(result, Err(Exit::FileNotFound)));
+    }
+
+    #[test]
+    fn reports_unreadable_files_instead_of_panicking() {
+        // Reading a directory as a file fails.
+        let directory = temporary_file("unreadable", "").with_file_name("directory.candy");
+        fs::create_dir_all(&directory).unwrap();
+        let result = format_paths(vec![directory]);
+        assert!(matches!(result, Err(Exit::IoError)));
+    }
+}
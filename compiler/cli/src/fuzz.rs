@@ -4,9 +4,11 @@ use crate::{
     utils::{module_for_path, packages_path},
     Exit, ProgramResult,
 };
+use candy_fuzzer::Corpus;
 use clap::{Parser, ValueHint};
-use std::path::PathBuf;
-use tracing::{error, info};
+use itertools::Itertools;
+use std::{io, path::PathBuf};
+use tracing::{error, info, warn};
 
 /// Fuzz a Candy module.
 ///
@@ -16,19 +18,51 @@ use tracing::{error, info};
 ///
 /// Fuzzable functions are functions written without curly braces.
 #[derive(Parser, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Options {
     /// The file or package to fuzz. If none is provided, the package of your
     /// current working directory will be fuzzed.
     #[arg(value_hint = ValueHint::FilePath)]
     path: Option<PathBuf>,
+
+    /// Write a Candy file reproducing each failing case next to the fuzzed
+    /// module so that it can be committed as a regression test.
+    ///
+    /// Each test comes with the `.stdout` and `.panic` files that
+    /// `candy selftest` uses to check that the case still panics.
+    #[arg(long)]
+    generate_tests: bool,
+
+    /// Overwrite regression tests that already exist.
+    #[arg(long, requires = "generate_tests")]
+    force: bool,
+
+    /// Don't start with the inputs stored in the package's fuzzing corpus and
+    /// don't store new interesting inputs in it.
+    ///
+    /// The corpus is shared with the language server.
+    #[arg(long)]
+    no_corpus: bool,
+
+    /// Only fuzz the functions that the module exports, i.e., its public API.
+    /// Internal helpers are still executed when called by exported functions.
+    #[arg(long)]
+    public_only: bool,
 }
 
 pub fn fuzz(options: Options) -> ProgramResult {
-    let db = Database::new_with_file_system_module_provider(packages_path());
+    let packages_path = packages_path();
+    let db = Database::new_with_file_system_module_provider(packages_path.clone());
     let module = module_for_path(options.path)?;
 
+    let corpus = if options.no_corpus {
+        None
+    } else {
+        Corpus::for_module(&module, &packages_path)
+    };
+
     debug!("Fuzzing `{module}`…");
-    let failing_cases = candy_fuzzer::fuzz(&db, module);
+    let failing_cases = candy_fuzzer::fuzz(&db, module, corpus.as_ref(), options.public_only);
 
     if failing_cases.is_empty() {
         info!("All found fuzzable functions seem fine.");
@@ -37,9 +71,45 @@ pub fn fuzz(options: Options) -> ProgramResult {
         error!("");
         error!("Finished fuzzing.");
         error!("These are the failing cases:");
-        for case in failing_cases {
+        for (index, group) in candy_fuzzer::group_failing_cases(failing_cases)
+            .iter()
+            .enumerate()
+        {
             error!("");
+            let case = &group.representative;
             case.dump(&db);
+            if !group.others.is_empty() {
+                error!(
+                    "The same panic occurred {} times in total, also when fuzzing {}.",
+                    group.occurrences(),
+                    group
+                        .others
+                        .iter()
+                        .map(|it| format!("`{}`", it.function().function_name()))
+                        .unique()
+                        .join(", "),
+                );
+            }
+
+            if !options.generate_tests {
+                continue;
+            }
+            let Some(test) = case.to_regression_test(index) else {
+                warn!("This case can't be reproduced in a separate file.");
+                continue;
+            };
+            match test.write(&packages_path, options.force) {
+                Ok(path) => info!("Wrote a regression test to {}.", path.display()),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    warn!(
+                        "Didn't write the regression test: {error} Pass `--force` to overwrite it."
+                    );
+                }
+                Err(error) => {
+                    warn!("Couldn't write the regression test: {error}");
+                    info!("This is the regression test:\n{}", test.source);
+                }
+            }
         }
         Err(Exit::FuzzingFoundFailingCases)
     }
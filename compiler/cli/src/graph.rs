@@ -0,0 +1,222 @@
+use crate::{
+    database::Database,
+    stats::lir_expression_count,
+    utils::{module_for_path, packages_path},
+    ProgramResult,
+};
+use candy_frontend::{
+    ast_to_hir::AstToHir, hir_to_mir::ExecutionTarget, lir_optimize::OptimizeLir, module::Module,
+    TracingConfig,
+};
+use clap::{Parser, ValueEnum, ValueHint};
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+use std::path::PathBuf;
+
+/// Print the module dependency graph of a Candy program.
+///
+/// The graph contains the given module and all modules it uses, directly or
+/// transitively. Each module is labeled with the number of LIR expressions it
+/// compiles to. Because imports are inlined, this includes the code of the
+/// modules it uses, so modules that pull in a lot of code stand out.
+///
+/// Render the output with Graphviz (`candy graph | dot -Tsvg > graph.svg`) or
+/// paste it into a Mermaid diagram.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// The file or package whose dependencies to show. If none is provided,
+    /// the package of your current working directory is used.
+    #[arg(value_hint = ValueHint::FilePath)]
+    path: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    format: GraphFormat,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+enum GraphFormat {
+    /// Graphviz's DOT language. Node sizes are proportional to the number of
+    /// LIR expressions.
+    Dot,
+    Mermaid,
+}
+
+pub fn graph(options: Options) -> ProgramResult {
+    let db = Database::new_with_file_system_module_provider(packages_path());
+    let module = module_for_path(options.path)?;
+
+    let graph = ModuleGraph::build(&db, module);
+    let output = match options.format {
+        GraphFormat::Dot => graph.to_dot(),
+        GraphFormat::Mermaid => graph.to_mermaid(),
+    };
+    println!("{output}");
+    Ok(())
+}
+
+struct ModuleGraph {
+    /// The entry module comes first, followed by the other modules in the
+    /// order in which they were discovered.
+    modules: Vec<Module>,
+    /// The number of LIR expressions of each module, or `None` if it couldn't
+    /// be compiled.
+    sizes: Vec<Option<usize>>,
+    /// Pairs of indices into `modules`: The first module uses the second one.
+    uses: Vec<(usize, usize)>,
+}
+impl ModuleGraph {
+    fn build(db: &Database, entry: Module) -> Self {
+        let mut indices = FxHashMap::default();
+        indices.insert(entry.clone(), 0);
+        let mut modules = vec![entry];
+        let mut uses = vec![];
+
+        let mut index = 0;
+        while index < modules.len() {
+            let module = modules[index].clone();
+            for (_, used_module) in db.used_modules(module).iter() {
+                let used_index = *indices.entry(used_module.clone()).or_insert_with(|| {
+                    modules.push(used_module.clone());
+                    modules.len() - 1
+                });
+                uses.push((index, used_index));
+            }
+            index += 1;
+        }
+
+        let sizes = modules
+            .iter()
+            .map(|module| {
+                db.optimized_lir(
+                    ExecutionTarget::Module(module.clone()),
+                    TracingConfig::off(),
+                )
+                .ok()
+                .map(|(lir, _)| lir_expression_count(&lir))
+            })
+            .collect();
+        Self {
+            modules,
+            sizes,
+            uses: uses.into_iter().unique().collect(),
+        }
+    }
+
+    fn label(&self, index: usize) -> String {
+        let size = self.sizes[index].map_or_else(
+            || "doesn't compile".to_string(),
+            |size| format!("{size} LIR expressions"),
+        );
+        format!("{}\n{size}", self.modules[index])
+    }
+
+    fn to_dot(&self) -> String {
+        let max_size = self
+            .sizes
+            .iter()
+            .flatten()
+            .max()
+            .copied()
+            .unwrap_or(1)
+            .max(1);
+        let mut lines = vec![
+            "digraph modules {".to_string(),
+            "  node [shape=box];".to_string(),
+        ];
+        for (index, size) in self.sizes.iter().enumerate() {
+            // The area of a node is proportional to its size.
+            #[allow(clippy::cast_precision_loss)]
+            let relative_size = size.unwrap_or_default() as f64 / max_size as f64;
+            lines.push(format!(
+                "  m{index} [label=\"{}\", width={:.2}];",
+                self.label(index).replace('"', "\\\"").replace('\n', "\\n"),
+                relative_size.sqrt().mul_add(3.0, 1.0),
+            ));
+        }
+        for (user, used) in &self.uses {
+            lines.push(format!("  m{user} -> m{used};"));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut lines = vec!["graph LR".to_string()];
+        for index in 0..self.modules.len() {
+            lines.push(format!(
+                "  m{index}[\"{}\"]",
+                // Mermaid doesn't support backslash escapes in labels.
+                self.label(index)
+                    .replace('"', "#quot;")
+                    .replace('\n', "<br/>"),
+            ));
+        }
+        for (user, used) in &self.uses {
+            lines.push(format!("  m{user} --> m{used}"));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleGraph;
+    use crate::{
+        database::Database,
+        utils::{module_for_path, packages_path},
+    };
+    use candy_frontend::module::{Module, ModuleKind, Package};
+    use std::path::PathBuf;
+
+    #[test]
+    fn used_modules_are_included() {
+        let packages_path = packages_path();
+        let db = Database::new_with_file_system_module_provider(packages_path.clone());
+        let path = PathBuf::from(packages_path.to_string()).join("SelfTests/helloWorld.candy");
+        let module = module_for_path(path).unwrap();
+
+        let graph = ModuleGraph::build(&db, module.clone());
+        assert_eq!(graph.modules.len(), 2);
+        assert_eq!(graph.modules[0], module);
+        assert_eq!(graph.modules[1].package, Package::builtins());
+        assert!(graph.sizes.iter().all(Option::is_some));
+        assert_eq!(graph.uses, [(0, 1)]);
+    }
+
+    fn graph() -> ModuleGraph {
+        let module = |name: &str| Module {
+            package: Package::User("/package".into()),
+            path: vec![name.to_string()],
+            kind: ModuleKind::Code,
+        };
+        ModuleGraph {
+            modules: vec![module("main"), module("helper")],
+            sizes: vec![Some(100), None],
+            uses: vec![(0, 1)],
+        }
+    }
+
+    #[test]
+    fn graphs_are_printed_as_dot() {
+        assert_eq!(
+            graph().to_dot(),
+            r#"digraph modules {
+  node [shape=box];
+  m0 [label="\"/package\":main\n100 LIR expressions", width=4.00];
+  m1 [label="\"/package\":helper\ndoesn't compile", width=1.00];
+  m0 -> m1;
+}"#,
+        );
+    }
+
+    #[test]
+    fn graphs_are_printed_as_mermaid() {
+        assert_eq!(
+            graph().to_mermaid(),
+            r##"graph LR
+  m0["#quot;/package#quot;:main<br/>100 LIR expressions"]
+  m1["#quot;/package#quot;:helper<br/>doesn't compile"]
+  m0 --> m1"##,
+        );
+    }
+}
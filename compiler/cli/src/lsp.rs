@@ -1,12 +1,128 @@
-use crate::{utils::packages_path, ProgramResult};
+use crate::{utils::packages_path, Exit, ProgramResult};
 use candy_language_server::server::Server;
-use tracing::info;
+use clap::Parser;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{error, info};
+
+/// Start a Language Server.
+///
+/// By default, the server communicates with a single client over stdin and
+/// stdout. With `--tcp` (or `--pipe` on Windows), it instead waits for clients
+/// to connect. Multiple clients can connect to the same server process, and
+/// each of them gets its own session with separate workspace state.
+///
+/// Sessions don't share anything but the process: Each one compiles and
+/// analyzes the modules it uses, including common packages like `Core`, on its
+/// own.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// Communicate with a single client over stdin and stdout. This is the
+    /// default.
+    #[arg(long, conflicts_with = "tcp")]
+    stdio: bool,
+
+    /// Listen for clients on this TCP port on localhost.
+    #[arg(long, value_name = "PORT")]
+    tcp: Option<u16>,
+
+    /// Listen for clients on this named pipe, e.g., `\\.\pipe\candy-lsp`.
+    #[cfg(windows)]
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["stdio", "tcp"])]
+    pipe: Option<String>,
+}
+impl Options {
+    #[must_use]
+    pub const fn uses_stdio(&self) -> bool {
+        #[cfg(windows)]
+        if self.pipe.is_some() {
+            return false;
+        }
+        self.stdio || self.tcp.is_none()
+    }
+}
+
+pub async fn lsp(options: Options) -> ProgramResult {
+    #[cfg(windows)]
+    if let Some(pipe) = options.pipe {
+        return serve_named_pipe(&pipe).await;
+    }
+
+    if let Some(port) = options.tcp {
+        return serve_tcp(port).await;
+    }
 
-pub async fn lsp() -> ProgramResult {
     info!("Starting language server…");
+    serve(tokio::io::stdin(), tokio::io::stdout()).await;
+    Ok(())
+}
+
+async fn serve_tcp(port: u16) -> ProgramResult {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+        .await
+        .map_err(|error| {
+            error!("Couldn't listen on port {port}: {error}");
+            Exit::LspTransportError
+        })?;
+    info!("Language server is listening on port {port}.");
+
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(it) => it,
+            Err(error) => {
+                error!("Couldn't accept a client: {error}");
+                continue;
+            }
+        };
+        info!("Client {address} connected.");
+        tokio::spawn(async move {
+            let (read, write) = tokio::io::split(stream);
+            serve(read, write).await;
+            info!("Client {address} disconnected.");
+        });
+    }
+}
+
+#[cfg(windows)]
+async fn serve_named_pipe(name: &str) -> ProgramResult {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let create_pipe = |is_first: bool| {
+        ServerOptions::new()
+            .first_pipe_instance(is_first)
+            .create(name)
+            .map_err(|error| {
+                error!("Couldn't create the named pipe {name}: {error}");
+                Exit::LspTransportError
+            })
+    };
+    let mut pipe = create_pipe(true)?;
+    info!("Language server is listening on named pipe {name}.");
+
+    loop {
+        if let Err(error) = pipe.connect().await {
+            error!("Couldn't accept a client: {error}");
+            continue;
+        }
+        info!("Client connected.");
+
+        // Create the next instance before handing off this one so that there's
+        // always an instance that clients can connect to.
+        let connected_pipe = std::mem::replace(&mut pipe, create_pipe(false)?);
+        tokio::spawn(async move {
+            let (read, write) = tokio::io::split(connected_pipe);
+            serve(read, write).await;
+            info!("Client disconnected.");
+        });
+    }
+}
+
+/// Runs a separate language server session for one client.
+///
+/// A `tower_lsp` service talks to exactly one client, so clients can't share a
+/// server and its database.
+async fn serve(read: impl AsyncRead + Unpin + Send, write: impl AsyncWrite + Send) {
     let (service, socket) = Server::create(packages_path());
-    tower_lsp::Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+    tower_lsp::Server::new(read, write, socket)
         .serve(service)
         .await;
-    Ok(())
 }
@@ -11,8 +11,8 @@
 )]
 
 use candy_vm::CAN_USE_STDOUT;
-use clap::Parser;
-use std::sync::atomic::Ordering;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::{process::ExitCode, sync::atomic::Ordering, time::Instant};
 use tracing::{debug, Level, Metadata};
 use tracing_subscriber::{
     filter,
@@ -21,67 +21,120 @@ use tracing_subscriber::{
 };
 
 mod check;
+mod completions;
 mod database;
 mod debug;
+mod eval;
+mod exit;
+mod format;
 mod fuzz;
+mod graph;
 #[cfg(feature = "inkwell")]
 mod inkwell;
 mod lsp;
+mod new;
 mod run;
+mod selftest;
+mod stats;
 mod utils;
+mod watch;
+
+pub use exit::{Exit, PanicDetails, ProgramResult};
 
 #[derive(Parser, Debug)]
 #[command(name = "candy", about = "The 🍭 Candy CLI.")]
-enum CandyOptions {
+struct CandyOptions {
+    /// Don't print any logs.
+    #[arg(long, global = true)]
+    quiet: bool,
+
+    /// After the command finished, print a JSON object describing its result:
+    /// the status, the exit code, the duration, and, if the code panicked, the
+    /// reason and the responsible code.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+#[derive(Subcommand, Debug)]
+enum Command {
     Run(run::Options),
 
     Check(check::Options),
 
+    Eval(eval::Options),
+
+    #[command(alias = "fmt")]
+    Format(format::Options),
+
     Fuzz(fuzz::Options),
 
+    Graph(graph::Options),
+
     #[command(subcommand)]
     Debug(debug::Options),
 
-    /// Start a Language Server.
-    Lsp,
+    Lsp(lsp::Options),
+
+    New(new::Options),
+
+    Selftest(selftest::Options),
+
+    Completions(completions::Options),
 
     #[cfg(feature = "inkwell")]
     Inkwell(inkwell::Options),
 }
 
 #[tokio::main]
-async fn main() -> ProgramResult {
+async fn main() -> ExitCode {
     let options = CandyOptions::parse();
 
-    let should_log_to_stdout = !matches!(options, CandyOptions::Lsp);
-    init_logger(should_log_to_stdout);
+    let should_log_to_stdout = match &options.command {
+        Command::Format(options) => !options.uses_stdio(),
+        // The graph is printed to stdout.
+        Command::Graph(_) => false,
+        Command::Lsp(options) => !options.uses_stdio(),
+        // The script is printed to stdout.
+        Command::Completions(_) => false,
+        _ => true,
+    };
+    if !options.quiet {
+        init_logger(should_log_to_stdout);
+    }
     CAN_USE_STDOUT.store(should_log_to_stdout, Ordering::Relaxed);
 
-    match options {
-        CandyOptions::Run(options) => run::run(options),
-        CandyOptions::Check(options) => check::check(options),
-        CandyOptions::Fuzz(options) => fuzz::fuzz(options),
-        CandyOptions::Debug(options) => debug::debug(options),
-        CandyOptions::Lsp => lsp::lsp().await,
+    let start = Instant::now();
+    let result = match options.command {
+        Command::Run(options) => run::run(options),
+        Command::Check(options) => check::check(options),
+        Command::Eval(options) => eval::eval(options),
+        Command::Format(options) => format::format(options),
+        Command::Fuzz(options) => fuzz::fuzz(options),
+        Command::Graph(options) => graph::graph(options),
+        Command::Debug(options) => debug::debug(options),
+        Command::Lsp(options) => lsp::lsp(options).await,
+        Command::New(options) => new::new(options),
+        Command::Selftest(options) => selftest::selftest(options),
+        Command::Completions(options) => {
+            completions::completions(&options, CandyOptions::command());
+            Ok(())
+        }
         #[cfg(feature = "inkwell")]
-        CandyOptions::Inkwell(options) => inkwell::compile(&options),
-    }
-}
+        Command::Inkwell(options) => inkwell::compile(&options),
+    };
 
-pub type ProgramResult = Result<(), Exit>;
-#[derive(Debug)]
-pub enum Exit {
-    CodePanicked,
-    DirectoryNotFound,
-    #[cfg(feature = "inkwell")]
-    ExternalError,
-    FileNotFound,
-    FuzzingFoundFailingCases,
-    NotInCandyPackage,
-    CodeContainsErrors,
-    #[cfg(feature = "inkwell")]
-    LlvmError(String),
-    GoldOutdated,
+    if options.json {
+        let json = exit::format_json(&result, start.elapsed());
+        if should_log_to_stdout {
+            println!("{json}");
+        } else {
+            // The language server uses stdout for communicating.
+            eprintln!("{json}");
+        }
+    }
+    exit::exit_code(&result)
 }
 
 fn init_logger(use_stdout: bool) {
@@ -141,3 +194,29 @@ fn level_for(module: &'static str, level: Level) -> impl Fn(&Metadata) -> bool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CandyOptions, Command};
+    use clap::Parser;
+
+    #[test]
+    fn global_flags_are_accepted_before_and_after_the_command() {
+        for arguments in [
+            ["candy", "--quiet", "--json", "check"],
+            ["candy", "check", "--quiet", "--json"],
+        ] {
+            let options = CandyOptions::try_parse_from(arguments).unwrap();
+            assert!(options.quiet);
+            assert!(options.json);
+            assert!(matches!(options.command, Command::Check(_)));
+        }
+    }
+
+    #[test]
+    fn global_flags_are_off_by_default() {
+        let options = CandyOptions::try_parse_from(["candy", "check"]).unwrap();
+        assert!(!options.quiet);
+        assert!(!options.json);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{utils::packages_path, Exit, ProgramResult};
+use candy_frontend::module::{Module, ModuleKind, Package, PackagesPath};
+use clap::{Parser, ValueHint};
+use serde_json::json;
+use std::{fs, io, path::PathBuf};
+use tracing::{error, info};
+
+/// Create a new Candy package.
+///
+/// This command creates a folder containing the package's manifest, a module
+/// with a hello-world `main` function, a module with tests, and settings for
+/// VS Code.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// The folder to create. It must not exist yet.
+    #[arg(value_hint = ValueHint::DirPath)]
+    path: PathBuf,
+
+    /// Create a package that's `use`d by other packages. Its root module exports
+    /// a function and `main.candy` shows how to use it.
+    #[arg(long, conflicts_with = "bin")]
+    lib: bool,
+
+    /// Create a package that's run. Its root module exports the `main` function.
+    /// This is the default.
+    #[arg(long)]
+    bin: bool,
+}
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn new(options: Options) -> ProgramResult {
+    if options.path.exists() {
+        error!("`{}` already exists.", options.path.to_string_lossy());
+        return Err(Exit::PathAlreadyExists);
+    }
+    let package = create_package(&options, &packages_path()).map_err(|error| {
+        error!(
+            "Couldn't create the package at `{}`: {error}",
+            options.path.to_string_lossy(),
+        );
+        Exit::IoError
+    })?;
+
+    info!("Created the package {package}.");
+    Ok(())
+}
+
+fn create_package(options: &Options, packages_path: &PackagesPath) -> io::Result<Package> {
+    fs::create_dir_all(&options.path)?;
+    let package = Package::User(fs::canonicalize(&options.path)?);
+
+    let module = |path: &[&str]| Module {
+        package: package.clone(),
+        path: path.iter().map(ToString::to_string).collect(),
+        kind: ModuleKind::Code,
+    };
+    // Clap ensures that at most one of the flags is set.
+    let (root, main, test) = match (options.lib, options.bin) {
+        (true, false) => (LIB_ROOT, LIB_MAIN, LIB_TEST),
+        (false, _) => (BIN_ROOT, BIN_MAIN, BIN_TEST),
+        (true, true) => unreachable!(),
+    };
+    for (module, content) in [
+        (package.manifest(), MANIFEST),
+        (module(&[]), root),
+        (module(&["main"]), main),
+        (module(&["test"]), test),
+    ] {
+        fs::write(file_of(&module, packages_path), content)?;
+    }
+
+    let vs_code_settings = package.to_path(packages_path).unwrap().join(".vscode");
+    fs::create_dir_all(&vs_code_settings)?;
+    let settings = json!({
+        "candy.packagesPath": packages_path.to_string(),
+        "editor.formatOnSave": true,
+    });
+    fs::write(
+        vs_code_settings.join("settings.json"),
+        format!("{settings:#}\n"),
+    )?;
+    Ok(package)
+}
+
+/// Code modules can either be a file `foo.candy` or a folder containing a file
+/// `_.candy`. Apart from the package's root module, we create files.
+fn file_of(module: &Module, packages_path: &PackagesPath) -> PathBuf {
+    let mut paths = module.to_possible_paths(packages_path).unwrap();
+    if module.path.is_empty() {
+        paths.swap_remove(0)
+    } else {
+        paths.pop().unwrap()
+    }
+}
+
+const MANIFEST: &str = "\
+# This file marks the root folder of the package. It can configure the levels of
+# lints:
+#
+# lints := [UnreachableMatchCase: Deny]
+";
+
+const BIN_ROOT: &str = "\
+[main] := use \".main\"
+";
+const BIN_MAIN: &str = "\
+greeting name := \"Hello, {name}!\"
+
+main := { environment ->
+  environment.stdout (greeting \"world\")
+}
+";
+const BIN_TEST: &str = "\
+[check, equals] = use \"Core\"
+[greeting] = use \"..main\"
+
+check (greeting \"world\" | equals \"Hello, world!\")
+";
+
+const LIB_ROOT: &str = "\
+greeting name := \"Hello, {name}!\"
+";
+const LIB_MAIN: &str = "\
+[greeting] = use \"..\"
+
+main := { environment ->
+  environment.stdout (greeting \"world\")
+}
+";
+const LIB_TEST: &str = "\
+[check, equals] = use \"Core\"
+[greeting] = use \"..\"
+
+check (greeting \"world\" | equals \"Hello, world!\")
+";
+
+#[cfg(test)]
+mod tests {
+    use super::{new, Options};
+    use crate::Exit;
+    use std::{env, fs, path::PathBuf, process};
+
+    fn temporary_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("candy-new-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+    fn new_package(path: PathBuf) -> Result<(), Exit> {
+        new(Options {
+            path,
+            lib: false,
+            bin: false,
+        })
+    }
+
+    #[test]
+    fn creates_a_package() {
+        let path = temporary_path("package");
+        assert!(new_package(path.clone()).is_ok());
+        for file in ["_package.candy", "_.candy", "main.candy", "test.candy"] {
+            assert!(path.join(file).is_file(), "`{file}` is missing.");
+        }
+        assert!(path.join(".vscode/settings.json").is_file());
+    }
+
+    #[test]
+    fn refuses_to_overwrite_existing_paths() {
+        let path = temporary_path("existing");
+        fs::create_dir_all(&path).unwrap();
+        let result = new_package(path.clone());
+        assert!(matches!(result, Err(Exit::PathAlreadyExists)));
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn reports_io_errors_instead_of_panicking() {
+        // A file can't contain a package.
+        let file = temporary_path("file");
+        fs::write(&file, "").unwrap();
+        let result = new_package(file.join("package"));
+        assert!(matches!(result, Err(Exit::IoError)));
+    }
+}
@@ -1,19 +1,28 @@
 use crate::{
     database::Database,
+    stats::{compile_byte_code_with_stats, format_duration},
     utils::{module_for_path, packages_path},
-    Exit, ProgramResult,
+    watch::{definitions, describe_changed_definitions, wait_for_changes, watched_modules},
+    Exit, PanicDetails, ProgramResult,
+};
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    hir_to_mir::ExecutionTarget,
+    module::{FileSystemModuleProvider, Module, MutableModuleProviderOwner, PackagesPath},
+    position::PositionConversionDb,
+    TracingConfig, TracingMode,
 };
-use candy_frontend::{hir_to_mir::ExecutionTarget, TracingConfig, TracingMode};
 use candy_vm::{
-    environment::DefaultEnvironment, heap::Heap, lir_to_byte_code::compile_byte_code,
-    tracer::stack_trace::StackTracer, Vm, VmFinished,
+    environment::DefaultEnvironment,
+    heap::Heap,
+    lir_to_byte_code::compile_byte_code,
+    recording::{Recording, RecordingEnvironment, ReplayEnvironment},
+    tracer::{cost::CostTracer, stack_trace::StackTracer},
+    Vm, VmFinished,
 };
 use clap::{Parser, ValueHint};
-use std::{
-    path::PathBuf,
-    time::{Duration, Instant},
-};
-use tracing::{debug, error};
+use std::{path::PathBuf, time::Instant};
+use tracing::{debug, error, info};
 
 /// Run a Candy program.
 ///
@@ -21,21 +30,97 @@ use tracing::{debug, error};
 /// your current working directory. The module should export a `main` function.
 /// This function is then called with an environment.
 #[derive(Parser, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Options {
     /// The file or package to run. If none is provided, the package of your
     /// current working directory will be run.
     #[arg(value_hint = ValueHint::FilePath)]
     path: Option<PathBuf>,
 
+    /// Print how long each compilation stage took and how big its output was.
+    /// The stages up to the HIR are listed for each module.
+    #[arg(long)]
+    stats: bool,
+
+    /// Like `--stats`, but print the statistics as JSON.
+    #[arg(long)]
+    stats_json: bool,
+
+    /// After running, print how many instructions were executed per call site
+    /// to stderr. Counting them slows down the execution.
+    #[arg(long)]
+    cost_report: bool,
+
+    /// Record all interactions with the outside world (stdin, random bytes,
+    /// HTTP requests, etc.) into this file.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    record: Option<PathBuf>,
+
+    /// Replay the interactions recorded with `--record` instead of talking to
+    /// the outside world. The recorded arguments are used as well.
+    #[arg(long, value_hint = ValueHint::FilePath, conflicts_with_all = ["record", "arguments"])]
+    replay: Option<PathBuf>,
+
+    /// Check the VM's data stack and heap before running each instruction.
+    /// This is slow, but turns memory corruption into immediate panics.
+    #[arg(long)]
+    vm_checks: bool,
+
+    /// After running, watch the program's modules and assets and run it again
+    /// whenever one of them changes. Before rerunning, the top-level
+    /// definitions that were added, changed, or removed are reported.
+    #[arg(long, conflicts_with_all = ["record", "replay"])]
+    watch: bool,
+
     #[arg(last(true))]
     arguments: Vec<String>,
 }
 
+/// Programs can run for a long time, so we compact the heap once more than half
+/// of the memory it used since the last compaction got freed.
+const HEAP_COMPACTION_THRESHOLD: f64 = 0.5;
+
+#[allow(clippy::needless_pass_by_value)]
 pub fn run(options: Options) -> ProgramResult {
     let packages_path = packages_path();
-    let db = Database::new_with_file_system_module_provider(packages_path.clone());
-    let module = module_for_path(options.path)?;
+    let mut db = Database::new_with_file_system_module_provider(packages_path.clone());
+    let module = module_for_path(options.path.clone())?;
+
+    if !options.watch {
+        return run_module(&db, &packages_path, module, &options, &mut vec![]);
+    }
+    loop {
+        // The result is already logged, and the program should be rerun even
+        // if it panicked.
+        let mut loaded_assets = vec![];
+        let _ = run_module(
+            &db,
+            &packages_path,
+            module.clone(),
+            &options,
+            &mut loaded_assets,
+        );
+        let modules = watched_modules(&db, module.clone(), &loaded_assets);
+        info!("Watching {} modules for changes.", modules.len());
+        for changed in wait_for_changes(&packages_path, &modules) {
+            let before = definitions(&db, &changed);
+            db.invalidate_module(&changed);
+            let changes = describe_changed_definitions(&before, &definitions(&db, &changed))
+                .map_or_else(String::new, |it| format!(": {it}"));
+            info!("{changed} changed{changes}.");
+        }
+    }
+}
 
+/// Runs the module's `main` function and adds the assets the program loaded at
+/// runtime to `loaded_assets`.
+fn run_module(
+    db: &Database,
+    packages_path: &PackagesPath,
+    module: Module,
+    options: &Options,
+    loaded_assets: &mut Vec<Module>,
+) -> ProgramResult {
     let tracing = TracingConfig {
         register_fuzzables: TracingMode::Off,
         calls: TracingMode::All,
@@ -45,7 +130,18 @@ pub fn run(options: Options) -> ProgramResult {
     debug!("Running {module}.");
 
     let compilation_start = Instant::now();
-    let byte_code = compile_byte_code(&db, ExecutionTarget::MainFunction(module), tracing).0;
+    let target = ExecutionTarget::MainFunction(module.clone());
+    let byte_code = if options.stats || options.stats_json {
+        let (byte_code, stats) = compile_byte_code_with_stats(db, target, tracing);
+        if options.stats_json {
+            println!("{}", stats.format_json());
+        } else {
+            println!("{}", stats.format_table());
+        }
+        byte_code
+    } else {
+        compile_byte_code(db, target, tracing).0
+    };
 
     let compilation_end = Instant::now();
     debug!(
@@ -55,16 +151,66 @@ pub fn run(options: Options) -> ProgramResult {
 
     debug!("Running program.");
     let mut heap = Heap::default();
-    let (environment_object, mut environment) =
-        DefaultEnvironment::new(&mut heap, &options.arguments);
-    let vm = Vm::for_main_function(
-        &byte_code,
-        &mut heap,
-        environment_object,
-        StackTracer::default(),
-    );
-    let VmFinished { result, tracer, .. } =
-        vm.run_forever_with_environment(&mut heap, &mut environment);
+    let cost_tracer = options.cost_report.then(CostTracer::default);
+    let VmFinished {
+        result,
+        tracer: (stack_tracer, cost_tracer),
+        ..
+    } = if let Some(path) = &options.replay {
+        let recording = Recording::load(path).map_err(|error| {
+            error!("Couldn't load the recording {}: {error}", path.display());
+            Exit::RecordingError
+        })?;
+        let (environment_object, mut environment) = ReplayEnvironment::new(&mut heap, recording);
+        let vm = Vm::for_main_function(
+            &byte_code,
+            &mut heap,
+            environment_object,
+            (StackTracer::default(), cost_tracer),
+        )
+        .with_automatic_heap_compaction(HEAP_COMPACTION_THRESHOLD);
+        let vm = if options.vm_checks {
+            vm.with_checks()
+        } else {
+            vm
+        };
+        vm.run_forever_with_environment(&mut heap, &mut environment)
+    } else {
+        let (environment_object, environment) =
+            DefaultEnvironment::new(&mut heap, &options.arguments);
+        let mut environment = environment.with_assets(
+            module,
+            FileSystemModuleProvider {
+                packages_path: packages_path.clone(),
+            },
+        );
+        let vm = Vm::for_main_function(
+            &byte_code,
+            &mut heap,
+            environment_object,
+            (StackTracer::default(), cost_tracer),
+        )
+        .with_automatic_heap_compaction(HEAP_COMPACTION_THRESHOLD);
+        let vm = if options.vm_checks {
+            vm.with_checks()
+        } else {
+            vm
+        };
+        if let Some(path) = &options.record {
+            let mut environment = RecordingEnvironment::new(environment_object, environment);
+            let finished = vm.run_forever_with_environment(&mut heap, &mut environment);
+            if let Err(error) = environment.into_recording().save(path) {
+                error!("Couldn't save the recording {}: {error}", path.display());
+                return Err(Exit::RecordingError);
+            }
+            debug!("Saved the recording to {}.", path.display());
+            finished
+        } else {
+            let finished = vm.run_forever_with_environment(&mut heap, &mut environment);
+            loaded_assets.extend(environment.loaded_assets());
+            finished
+        }
+    };
     let result = match result {
         Ok(return_value) => {
             debug!("The main function returned: {return_value:?}");
@@ -75,9 +221,16 @@ pub fn run(options: Options) -> ProgramResult {
             error!("{} is responsible.", panic.responsible);
             error!(
                 "This is the stack trace:\n{}",
-                tracer.format(&db, &packages_path),
+                stack_tracer.format(db, packages_path, &byte_code),
             );
-            Err(Exit::CodePanicked)
+            let responsible_span = db
+                .hir_id_to_origin_span(&panic.responsible)
+                .map(|span| db.range_to_positions(panic.responsible.module.clone(), span));
+            Err(Exit::CodePanicked(Box::new(PanicDetails {
+                reason: panic.reason,
+                responsible: panic.responsible,
+                responsible_span,
+            })))
         }
     };
     let execution_end = Instant::now();
@@ -86,14 +239,11 @@ pub fn run(options: Options) -> ProgramResult {
         format_duration(execution_end - compilation_end),
     );
 
+    if let Some(cost_tracer) = cost_tracer {
+        // The program's own output goes to stdout, so we keep the report apart.
+        eprintln!("{}", cost_tracer.format(db, packages_path));
+    }
+
     drop(byte_code); // Make sure the byte code is kept around until here.
     result
 }
-
-fn format_duration(duration: Duration) -> String {
-    if duration < Duration::from_millis(1) {
-        format!("{} µs", duration.as_micros())
-    } else {
-        format!("{} ms", duration.as_millis())
-    }
-}
@@ -0,0 +1,246 @@
+//! Runs example programs and compares their behavior to golden files.
+//!
+//! Each `.candy` file in the directory (except for files starting with `_`,
+//! such as `_package.candy`) is a test. Next to `example.candy`, these files
+//! describe how the program should behave:
+//!
+//! - `example.candy.stdin` (optional): The lines that `environment.stdin`
+//!   returns.
+//! - `example.candy.stdout`: What the program should write to
+//!   `environment.stdout`.
+//! - `example.candy.panic` (optional): The reason of the expected panic. If
+//!   this file doesn't exist, the `main` function should return normally.
+//!
+//! Programs run with checks enabled in the VM, a seeded random source, and a
+//! clock that is fixed at the Unix epoch, so that their output is
+//! deterministic.
+
+use crate::{database::Database, utils::packages_path, Exit, ProgramResult};
+use candy_frontend::{
+    hir_to_mir::ExecutionTarget,
+    module::{Module, ModuleFromPathError, ModuleKind, PackagesPath},
+    TracingConfig,
+};
+use candy_vm::{
+    capabilities::{FixedClock, SeededRandomSource},
+    environment::{CapturedOutput, DefaultEnvironment, Input, Output},
+    heap::Heap,
+    lir_to_byte_code::compile_byte_code,
+    tracer::DummyTracer,
+    Vm, VmFinished,
+};
+use clap::{Parser, ValueHint};
+use diffy::{create_patch, PatchFormatter};
+use itertools::Itertools;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tracing::{error, info};
+use walkdir::{DirEntry, WalkDir};
+
+/// Run the example programs of the compiler's self tests.
+///
+/// This command compiles and runs each program with scripted stdin and
+/// compares its stdout and panic to the expected ones stored next to it.
+#[derive(Parser, Debug)]
+pub struct Options {
+    /// The directory containing the programs. Defaults to the `SelfTests`
+    /// package.
+    #[arg(value_hint = ValueHint::DirPath)]
+    directory: Option<PathBuf>,
+
+    /// Overwrite the expected outputs with the actual ones instead of
+    /// comparing them.
+    #[arg(long)]
+    update: bool,
+}
+
+pub fn selftest(options: Options) -> ProgramResult {
+    let packages_path = packages_path();
+    let directory = options
+        .directory
+        .unwrap_or_else(|| packages_path.join("SelfTests"));
+    if !directory.is_dir() {
+        error!("{} is not a directory.", directory.display());
+        return Err(Exit::DirectoryNotFound);
+    }
+
+    let mut failed_tests = vec![];
+    for program in find_programs(&directory) {
+        let outcome = run_program(&packages_path, &program)?;
+        if options.update {
+            outcome.save(&program).unwrap();
+            continue;
+        }
+
+        let differences = match Outcome::load(&program) {
+            Ok(expected) => outcome.differences_to(&expected),
+            Err(error) => vec![format!("Couldn't load the expected outputs: {error}")],
+        };
+        if differences.is_empty() {
+            info!("✅ {}", program.display());
+        } else {
+            error!("❌ {}\n{}", program.display(), differences.join("\n"));
+            failed_tests.push(program);
+        }
+    }
+
+    if options.update {
+        info!("Updated the expected outputs.");
+        Ok(())
+    } else if failed_tests.is_empty() {
+        info!("All self tests passed.");
+        Ok(())
+    } else {
+        error!(
+            "These self tests failed:\n{}",
+            failed_tests
+                .iter()
+                .map(|it| format!("- {}", it.display()))
+                .join("\n"),
+        );
+        Err(Exit::SelfTestsFailed)
+    }
+}
+
+fn find_programs(directory: &Path) -> Vec<PathBuf> {
+    WalkDir::new(directory)
+        .into_iter()
+        .map(Result::unwrap)
+        .filter(|it| it.file_type().is_file())
+        .filter(|it| {
+            let name = it.file_name().to_string_lossy();
+            name.ends_with(".candy") && !name.starts_with('_')
+        })
+        .map(DirEntry::into_path)
+        .sorted()
+        .collect()
+}
+
+/// How a program behaved when it was run.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Outcome {
+    stdout: String,
+    panic_reason: Option<String>,
+}
+impl Outcome {
+    fn load(program: &Path) -> io::Result<Self> {
+        Ok(Self {
+            stdout: fs::read_to_string(companion_file(program, "stdout"))?,
+            panic_reason: read_optional(&companion_file(program, "panic"))?,
+        })
+    }
+    fn save(&self, program: &Path) -> io::Result<()> {
+        fs::write(companion_file(program, "stdout"), &self.stdout)?;
+        let panic_file = companion_file(program, "panic");
+        match &self.panic_reason {
+            Some(reason) => fs::write(panic_file, reason),
+            None if panic_file.exists() => fs::remove_file(panic_file),
+            None => Ok(()),
+        }
+    }
+
+    fn differences_to(&self, expected: &Self) -> Vec<String> {
+        let mut differences = vec![];
+        if self.stdout != expected.stdout {
+            let patch = create_patch(&expected.stdout, &self.stdout);
+            differences.push(format!(
+                "The stdout differs:\n{}",
+                PatchFormatter::new().fmt_patch(&patch),
+            ));
+        }
+        match (&expected.panic_reason, &self.panic_reason) {
+            (None, None) => {}
+            (None, Some(actual)) => {
+                differences.push(format!("The program panicked unexpectedly: {actual}"));
+            }
+            (Some(expected), None) => {
+                differences.push(format!("The program didn't panic, expected: {expected}"));
+            }
+            (Some(expected), Some(actual)) if expected != actual => {
+                differences.push(format!(
+                    "The program panicked with the wrong reason.\nExpected: {expected}\nActual: \
+                     {actual}",
+                ));
+            }
+            (Some(_), Some(_)) => {}
+        }
+        differences
+    }
+}
+
+fn run_program(packages_path: &PackagesPath, program: &Path) -> Result<Outcome, Exit> {
+    let module =
+        Module::from_path(packages_path, program, ModuleKind::Code).map_err(
+            |error| match error {
+                ModuleFromPathError::NotFound(_) => Exit::FileNotFound,
+                ModuleFromPathError::NotInPackage(_) => {
+                    error!("{} is not in a Candy package.", program.display());
+                    Exit::NotInCandyPackage
+                }
+            },
+        )?;
+    let stdin = read_optional(&companion_file(program, "stdin"))
+        .unwrap()
+        .unwrap_or_default();
+
+    // Each program gets a fresh database so that tests don't influence each
+    // other.
+    let db = Database::new_with_file_system_module_provider(packages_path.clone());
+    let (byte_code, _) = compile_byte_code(
+        &db,
+        ExecutionTarget::MainFunction(module),
+        TracingConfig::off(),
+    );
+
+    let mut heap = Heap::default();
+    let output = CapturedOutput::default();
+    let (environment_object, mut environment) = DefaultEnvironment::new_with_input_and_output(
+        &mut heap,
+        &[],
+        Input::Scripted(stdin),
+        Output::Captured(output.clone()),
+    );
+    let VmFinished { result, .. } =
+        Vm::for_main_function(&byte_code, &mut heap, environment_object, DummyTracer)
+            .with_checks()
+            .with_random_source(SeededRandomSource::new(0))
+            .with_clock(FixedClock::new(SystemTime::UNIX_EPOCH))
+            .run_forever_with_environment(&mut heap, &mut environment);
+
+    Ok(Outcome {
+        stdout: output.stdout(),
+        panic_reason: result.err().map(|panic| panic.reason),
+    })
+}
+
+/// E.g., `example.candy.stdout` for `example.candy`.
+fn companion_file(program: &Path, extension: &str) -> PathBuf {
+    let mut path = program.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{selftest, Options};
+
+    #[test]
+    fn self_tests_pass() {
+        let options = Options {
+            directory: None,
+            update: false,
+        };
+        assert!(selftest(options).is_ok(), "Some self tests failed.");
+    }
+}
@@ -0,0 +1,320 @@
+use crate::database::Database;
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    cst_to_ast::CstToAst,
+    hir_to_mir::{ExecutionTarget, HirToMir},
+    lir::Lir,
+    lir_optimize::OptimizeLir,
+    mir_optimize::OptimizeMir,
+    mir_to_lir::MirToLir,
+    module::{Module, ModuleDb},
+    rcst_to_cst::RcstToCst,
+    string_to_rcst::StringToRcst,
+    TracingConfig,
+};
+use candy_vm::{byte_code::ByteCode, lir_to_byte_code::compile_byte_code};
+use itertools::Itertools;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// Timings and output sizes of the individual compilation stages.
+///
+/// Salsa caches the result of every query, so by requesting the stages one
+/// after another, each measurement only contains the work of that stage.
+#[derive(Debug, Default)]
+pub struct CompilationStats {
+    /// The stages that run per module, for the entry module followed by the
+    /// modules it uses in the order in which they were discovered.
+    modules: Vec<ModuleStats>,
+    /// The stages that run on the whole program with all modules inlined.
+    program: Vec<StageStats>,
+}
+#[derive(Debug)]
+struct ModuleStats {
+    module: Module,
+    stages: Vec<StageStats>,
+}
+#[derive(Debug)]
+struct StageStats {
+    name: &'static str,
+    duration: Duration,
+    size: Option<usize>,
+    unit: &'static str,
+}
+
+impl StageStats {
+    fn measure<T>(
+        name: &'static str,
+        unit: &'static str,
+        run: impl FnOnce() -> T,
+        size: impl FnOnce(&T) -> Option<usize>,
+    ) -> (Self, T) {
+        let start = Instant::now();
+        let result = run();
+        let duration = start.elapsed();
+        let stats = Self {
+            name,
+            duration,
+            size: size(&result),
+            unit,
+        };
+        (stats, result)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "stage": self.name,
+            "durationMicros": micros(self.duration),
+            "size": self.size,
+            "unit": self.unit,
+        })
+    }
+}
+
+impl CompilationStats {
+    fn measure_module(&mut self, db: &Database, module: Module) {
+        let mut stages = vec![];
+        let mut measure = |name, unit, run: &dyn Fn() -> Option<usize>| {
+            stages.push(StageStats::measure(name, unit, run, |size| *size).0);
+        };
+        measure("Loading", "bytes", &|| {
+            db.get_module_content(module.clone()).map(|it| it.len())
+        });
+        measure("Parsing (RCST)", "top-level nodes", &|| {
+            db.rcst(module.clone()).ok().map(|it| it.len())
+        });
+        measure("CST", "top-level nodes", &|| {
+            db.cst(module.clone()).ok().map(|it| it.len())
+        });
+        measure("AST", "nodes", &|| {
+            db.ast(module.clone())
+                .ok()
+                .map(|(_, ast_to_cst_ids)| ast_to_cst_ids.len())
+        });
+        measure("HIR", "expressions", &|| {
+            db.hir(module.clone())
+                .ok()
+                .map(|(_, hir_to_ast_ids)| hir_to_ast_ids.len())
+        });
+        self.modules.push(ModuleStats { module, stages });
+    }
+    /// Measures a stage of the whole program whose output is only needed for
+    /// its size since Salsa caches it for the next stage.
+    fn measure_program<T>(
+        &mut self,
+        name: &'static str,
+        unit: &'static str,
+        run: impl FnOnce() -> T,
+        size: impl FnOnce(&T) -> Option<usize>,
+    ) {
+        self.program
+            .push(StageStats::measure(name, unit, run, size).0);
+    }
+
+    fn all_stages(&self) -> impl Iterator<Item = &StageStats> {
+        self.modules
+            .iter()
+            .flat_map(|it| &it.stages)
+            .chain(&self.program)
+    }
+    #[must_use]
+    pub fn total_duration(&self) -> Duration {
+        self.all_stages().map(|it| it.duration).sum()
+    }
+
+    #[must_use]
+    pub fn format_table(&self) -> String {
+        let name_width = self
+            .all_stages()
+            .map(|it| it.name.len() + 2)
+            .chain(self.modules.iter().map(|it| it.module.to_string().len()))
+            .max()
+            .unwrap_or_default()
+            .max("Whole program".len());
+        let format_stage = |stage: &StageStats| {
+            format!(
+                "  {:<width$}  {:>10}  {}",
+                stage.name,
+                format_duration(stage.duration),
+                stage.size.map_or_else(
+                    || "(failed)".to_string(),
+                    |size| format!("{size} {}", stage.unit),
+                ),
+                width = name_width - 2,
+            )
+        };
+
+        let mut lines = vec![format!(
+            "{:<name_width$}  {:>10}  Output size",
+            "Stage", "Time",
+        )];
+        for module in &self.modules {
+            lines.push(module.module.to_string());
+            lines.extend(module.stages.iter().map(format_stage));
+        }
+        lines.push("Whole program".to_string());
+        lines.extend(self.program.iter().map(format_stage));
+        lines.push(format!(
+            "{:<name_width$}  {:>10}",
+            "Total",
+            format_duration(self.total_duration()),
+        ));
+        lines.join("\n")
+    }
+
+    #[must_use]
+    pub fn format_json(&self) -> String {
+        let modules = self
+            .modules
+            .iter()
+            .map(|module| {
+                json!({
+                    "module": module.module.to_string(),
+                    "stages": module.stages.iter().map(StageStats::to_json).collect_vec(),
+                })
+            })
+            .collect_vec();
+        json!({
+            "modules": modules,
+            "stages": self.program.iter().map(StageStats::to_json).collect_vec(),
+            "totalDurationMicros": micros(self.total_duration()),
+        })
+        .to_string()
+    }
+}
+
+/// Compiles the target to byte code like [`compile_byte_code`], but runs all
+/// preceding stages individually to record their timings and output sizes.
+///
+/// The stages up to the HIR are measured for the target module and each module
+/// it uses. Later stages work on the whole program, where used modules are
+/// inlined.
+pub fn compile_byte_code_with_stats(
+    db: &Database,
+    target: ExecutionTarget,
+    tracing: TracingConfig,
+) -> (ByteCode, CompilationStats) {
+    let mut stats = CompilationStats::default();
+
+    let mut modules = vec![target.module().clone()];
+    let mut index = 0;
+    while index < modules.len() {
+        let module = modules[index].clone();
+        stats.measure_module(db, module.clone());
+        for (_, used_module) in db.used_modules(module).iter() {
+            if !modules.contains(used_module) {
+                modules.push(used_module.clone());
+            }
+        }
+        index += 1;
+    }
+
+    stats.measure_program(
+        "MIR",
+        "expressions",
+        || db.mir(target.clone(), tracing.clone()),
+        |mir| {
+            mir.as_ref()
+                .ok()
+                .map(|(mir, _)| mir.complexity().expressions)
+        },
+    );
+    stats.measure_program(
+        "Optimized MIR",
+        "expressions",
+        || db.optimized_mir(target.clone(), tracing.clone()),
+        |mir| {
+            mir.as_ref()
+                .ok()
+                .map(|(mir, _, _)| mir.complexity().expressions)
+        },
+    );
+    stats.measure_program(
+        "LIR",
+        "expressions",
+        || db.lir(target.clone(), tracing.clone()),
+        |lir| lir.as_ref().ok().map(|(lir, _)| lir_expression_count(lir)),
+    );
+    stats.measure_program(
+        "Optimized LIR",
+        "expressions",
+        || db.optimized_lir(target.clone(), tracing.clone()),
+        |lir| lir.as_ref().ok().map(|(lir, _)| lir_expression_count(lir)),
+    );
+    let (byte_code_stats, byte_code) = StageStats::measure(
+        "Byte code",
+        "instructions",
+        || compile_byte_code(db, target, tracing).0,
+        |byte_code| Some(byte_code.instructions.len()),
+    );
+    stats.program.push(byte_code_stats);
+
+    (byte_code, stats)
+}
+
+#[must_use]
+pub fn lir_expression_count(lir: &Lir) -> usize {
+    lir.bodies()
+        .ids_and_bodies()
+        .map(|(_, body)| body.expressions().len())
+        .sum()
+}
+
+/// JSON numbers don't support `u128`, but durations in microseconds fit into a
+/// `u64` for more than half a million years.
+fn micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap()
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_millis(1) {
+        format!("{} µs", duration.as_micros())
+    } else {
+        format!("{} ms", duration.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile_byte_code_with_stats;
+    use crate::{
+        database::Database,
+        utils::{module_for_path, packages_path},
+    };
+    use candy_frontend::{hir_to_mir::ExecutionTarget, module::Package, TracingConfig};
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    #[test]
+    fn stages_up_to_the_hir_are_measured_per_module() {
+        let packages_path = packages_path();
+        let db = Database::new_with_file_system_module_provider(packages_path.clone());
+        let path = PathBuf::from(packages_path.to_string()).join("SelfTests/helloWorld.candy");
+        let module = module_for_path(path).unwrap();
+
+        let (_, stats) = compile_byte_code_with_stats(
+            &db,
+            ExecutionTarget::MainFunction(module.clone()),
+            TracingConfig::off(),
+        );
+
+        let modules = stats
+            .modules
+            .iter()
+            .map(|it| &it.module)
+            .collect::<Vec<_>>();
+        assert_eq!(modules.len(), 2);
+        assert_eq!(modules[0], &module);
+        assert_eq!(modules[1].package, Package::builtins());
+        for module in &stats.modules {
+            assert_eq!(module.stages.len(), 5);
+            assert!(module.stages.iter().all(|it| it.size.is_some()));
+        }
+        assert_eq!(stats.program.len(), 5);
+
+        let json: Value = serde_json::from_str(&stats.format_json()).unwrap();
+        assert_eq!(json["modules"].as_array().unwrap().len(), 2);
+        assert_eq!(json["modules"][1]["stages"][0]["stage"], "Loading");
+        assert_eq!(json["stages"][4]["stage"], "Byte code");
+    }
+}
@@ -0,0 +1,160 @@
+use crate::database::Database;
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    cst::CstKind,
+    module::{Module, PackagesPath},
+    rcst_to_cst::RcstToCst,
+};
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+use std::{
+    fs, thread,
+    time::{Duration, SystemTime},
+};
+
+/// How often we check whether one of the watched files changed.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The `entry` module, the code modules it uses directly or transitively, the
+/// assets these use, and the assets the program loaded at runtime.
+pub fn watched_modules(db: &Database, entry: Module, loaded_assets: &[Module]) -> Vec<Module> {
+    let mut modules = vec![entry];
+    let mut index = 0;
+    while index < modules.len() {
+        let module = modules[index].clone();
+        let used_modules = db
+            .used_modules(module.clone())
+            .iter()
+            .map(|(_, used_module)| used_module.clone())
+            .collect_vec();
+        for used_module in used_modules
+            .into_iter()
+            .chain(db.used_assets(module).iter().cloned())
+        {
+            if !modules.contains(&used_module) {
+                modules.push(used_module);
+            }
+        }
+        index += 1;
+    }
+    for asset in loaded_assets {
+        if !modules.contains(asset) {
+            modules.push(asset.clone());
+        }
+    }
+    modules
+}
+
+/// Blocks until at least one of the `modules` is created, changed, or deleted
+/// and returns the ones that did.
+pub fn wait_for_changes(packages_path: &PackagesPath, modules: &[Module]) -> Vec<Module> {
+    let initial = modules
+        .iter()
+        .map(|module| last_modified(packages_path, module))
+        .collect_vec();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let changed = modules
+            .iter()
+            .zip_eq(&initial)
+            .filter(|(module, initial)| last_modified(packages_path, module) != **initial)
+            .map(|(module, _)| module.clone())
+            .collect_vec();
+        if !changed.is_empty() {
+            return changed;
+        }
+    }
+}
+/// The modification times of the files that could contain the module's code.
+fn last_modified(packages_path: &PackagesPath, module: &Module) -> Vec<Option<SystemTime>> {
+    module
+        .to_possible_paths(packages_path)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|path| fs::metadata(path).and_then(|it| it.modified()).ok())
+        .collect()
+}
+
+/// The top-level definitions of a code module, mapped to their code without
+/// whitespace. Comparing them before and after a change shows which
+/// definitions changed.
+pub fn definitions(db: &Database, module: &Module) -> FxHashMap<String, String> {
+    let Ok(csts) = db.cst(module.clone()) else {
+        return FxHashMap::default();
+    };
+    csts.iter()
+        .filter_map(|cst| {
+            let CstKind::Assignment { left, .. } = &cst.kind else {
+                return None;
+            };
+            let left = match &left.kind {
+                CstKind::TrailingWhitespace { child, .. } => child,
+                _ => left,
+            };
+            // Functions are named by the receiver of the call on the left.
+            let name = match &left.kind {
+                CstKind::Call { receiver, .. } => receiver,
+                _ => left,
+            };
+            let code = cst.to_string().split_whitespace().join(" ");
+            Some((name.to_string().trim().to_string(), code))
+        })
+        .collect()
+}
+/// Describes the definitions that were added, changed, or removed, or returns
+/// `None` if there are no such definitions.
+pub fn describe_changed_definitions(
+    before: &FxHashMap<String, String>,
+    after: &FxHashMap<String, String>,
+) -> Option<String> {
+    let names = |filter: &dyn Fn(&String) -> bool| {
+        let names = after
+            .keys()
+            .chain(before.keys())
+            .unique()
+            .filter(|it| filter(it))
+            .sorted()
+            .map(|it| format!("`{it}`"))
+            .join(", ");
+        (!names.is_empty()).then_some(names)
+    };
+    let changes = [
+        names(&|name| !before.contains_key(name)).map(|it| format!("added {it}")),
+        names(&|name| {
+            before.contains_key(name) && after.contains_key(name) && before[name] != after[name]
+        })
+        .map(|it| format!("changed {it}")),
+        names(&|name| !after.contains_key(name)).map(|it| format!("removed {it}")),
+    ];
+    let changes = changes.into_iter().flatten().join("; ");
+    (!changes.is_empty()).then_some(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{definitions, describe_changed_definitions};
+    use crate::database::Database;
+    use candy_frontend::module::{
+        InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+    };
+
+    #[test]
+    fn reports_added_changed_and_removed_definitions() {
+        let mut db = Database::new(Box::<InMemoryModuleProvider>::default());
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, b"foo = 1\nbar a := a\nbaz = 3\n".to_vec());
+        let before = definitions(&db, &module);
+        db.did_change_module(&module, b"foo   =   1\nbar a := Foo\nqux = 4\n".to_vec());
+        let after = definitions(&db, &module);
+
+        assert_eq!(
+            describe_changed_definitions(&before, &after).as_deref(),
+            Some("added `qux`; changed `bar`; removed `baz`"),
+        );
+        assert_eq!(describe_changed_definitions(&after, &after), None);
+    }
+}
@@ -118,8 +118,15 @@ impl NormalizeSpans for Ast {
                 expression.normalize_spans();
                 cases.normalize_spans();
             }
-            AstKind::MatchCase(MatchCase { pattern, body }) => {
+            AstKind::MatchCase(MatchCase {
+                pattern,
+                condition,
+                body,
+            }) => {
                 pattern.normalize_spans();
+                if let Some(condition) = condition {
+                    condition.normalize_spans();
+                }
                 body.normalize_spans();
             }
             AstKind::OrPattern(OrPattern(patterns)) => patterns.normalize_spans(),
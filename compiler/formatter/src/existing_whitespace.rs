@@ -2,7 +2,7 @@ use crate::{
     format::{format_cst, FormattingInfo},
     text_edits::TextEdits,
     width::{SinglelineWidth, Width},
-    Indentation,
+    FormatterOptions, Indentation,
 };
 use candy_frontend::{
     cst::{Cst, CstError, CstKind},
@@ -28,6 +28,13 @@ pub enum TrailingWithIndentationConfig {
         previous_width: Width,
         indentation: Indentation,
     },
+    /// Like [`Self::Trailing`], but for the whitespace before the closing
+    /// punctuation of a collection. Comments on their own lines are indented
+    /// like the items while the closing punctuation is dedented.
+    BeforeClosingPunctuation {
+        previous_width: Width,
+        item_indentation: Indentation,
+    },
 }
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum WhitespacePositionInBody {
@@ -304,10 +311,13 @@ impl<'a> ExistingWhitespace<'a> {
                     .filter(|(it, _)| it.kind.is_newline())
                     .count()
                     .clamp(1, 1 + MAX_CONSECUTIVE_EMPTY_LINES);
-                (indentation, trailing_newline_count)
+                (*indentation, trailing_newline_count)
             }
             TrailingWithIndentationConfig::Trailing { indentation, .. }
-            | TrailingWithIndentationConfig::Body { indentation, .. } => (indentation, 1),
+            | TrailingWithIndentationConfig::Body { indentation, .. } => (*indentation, 1),
+            TrailingWithIndentationConfig::BeforeClosingPunctuation {
+                item_indentation, ..
+            } => (item_indentation.with_dedent(), 1),
         };
         edits.change(
             trailing_range,
@@ -346,6 +356,10 @@ impl<'a> ExistingWhitespace<'a> {
                 TrailingWithIndentationConfig::Trailing {
                     previous_width,
                     indentation,
+                }
+                | TrailingWithIndentationConfig::BeforeClosingPunctuation {
+                    previous_width,
+                    item_indentation: indentation,
                 } => (*previous_width, *indentation, true, 1),
             };
 
@@ -422,6 +436,7 @@ impl<'a> ExistingWhitespace<'a> {
                         item,
                         &FormattingInfo {
                             indentation,
+                            options: FormatterOptions::default(),
                             trailing_comma_condition: None,
                             is_single_expression_in_assignment_body: false,
                         },
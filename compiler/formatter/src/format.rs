@@ -10,6 +10,7 @@ use crate::{
     formatted_cst::FormattedCst,
     text_edits::TextEdits,
     width::{Indentation, SinglelineWidth, StringWidth, Width},
+    CallArgumentsLayout, FormatterOptions, LambdaBodyLayout,
 };
 use candy_frontend::{
     cst::{Cst, CstError, CstKind, IntRadix, UnwrapWhitespaceAndComment},
@@ -22,15 +23,24 @@ use traversal::dft_post_rev;
 #[derive(Clone, Default)]
 pub struct FormattingInfo {
     pub indentation: Indentation,
+    pub options: FormatterOptions,
 
     // The fields below apply only for direct descendants.
     pub trailing_comma_condition: Option<TrailingCommaCondition>,
     pub is_single_expression_in_assignment_body: bool,
 }
 impl FormattingInfo {
+    pub fn new(options: FormatterOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
     pub const fn with_indent(&self) -> Self {
         Self {
             indentation: self.indentation.with_indent(),
+            options: self.options,
             trailing_comma_condition: None,
             is_single_expression_in_assignment_body: false,
         }
@@ -38,6 +48,7 @@ impl FormattingInfo {
     pub const fn with_dedent(&self) -> Self {
         Self {
             indentation: self.indentation.with_dedent(),
+            options: self.options,
             trailing_comma_condition: None,
             is_single_expression_in_assignment_body: false,
         }
@@ -45,6 +56,7 @@ impl FormattingInfo {
     pub const fn with_trailing_comma_condition(&self, condition: TrailingCommaCondition) -> Self {
         Self {
             indentation: self.indentation,
+            options: self.options,
             trailing_comma_condition: Some(condition),
             is_single_expression_in_assignment_body: false,
         }
@@ -52,6 +64,7 @@ impl FormattingInfo {
     pub const fn for_single_expression_in_assignment_body(&self) -> Self {
         Self {
             indentation: self.indentation.with_indent(),
+            options: self.options,
             trailing_comma_condition: None,
             is_single_expression_in_assignment_body: true,
         }
@@ -69,6 +82,7 @@ impl FormattingInfo {
             } else {
                 self.indentation
             },
+            options: self.options,
             trailing_comma_condition: None,
             is_single_expression_in_assignment_body: false,
         }
@@ -150,6 +164,14 @@ fn split_leading_whitespace(start_offset: Offset, csts: &[Cst]) -> (ExistingWhit
     let leading_whitespace = ExistingWhitespace::new(start_offset, leading_whitespace);
     (leading_whitespace, rest)
 }
+fn has_trailing_newline(cst: &Cst) -> bool {
+    match &cst.kind {
+        CstKind::TrailingWhitespace { whitespace, .. } => whitespace
+            .iter()
+            .any(|it| matches!(it.kind, CstKind::Newline(_))),
+        _ => false,
+    }
+}
 
 /// The non-trivial cases usually work in three steps, though these are often not clearly separated:
 ///
@@ -185,7 +207,7 @@ pub fn format_cst<'a>(
         | CstKind::ClosingBracket
         | CstKind::OpeningCurlyBrace
         | CstKind::ClosingCurlyBrace => SinglelineWidth::from(1).into(),
-        CstKind::Arrow => SinglelineWidth::from(2).into(),
+        CstKind::Arrow | CstKind::If => SinglelineWidth::from(2).into(),
         CstKind::SingleQuote | CstKind::DoubleQuote | CstKind::Percent | CstKind::Octothorpe => {
             SinglelineWidth::from(1).into()
         }
@@ -358,72 +380,8 @@ pub fn format_cst<'a>(
             }
             width
         }
-        CstKind::BinaryBar { left, bar, right } => {
-            // Left
-            let mut left =
-                format_receiver(edits, previous_width, left, info, ReceiverParent::BinaryBar);
-
-            // Bar
-            let width_for_right_side = Width::multiline(None, info.indentation.width());
-            let bar_width = format_cst(edits, width_for_right_side, bar, info)
-                .into_space_and_move_comments_to(edits, &mut left.whitespace);
-            let left_min_width = left.min_width(info.indentation);
-
-            // Right
-            let (right_width, whitespace) = {
-                let (right, right_parentheses) = ExistingParentheses::split_from(edits, right);
-                // Depending on the precedence of `right` and whether there's an opening parenthesis
-                // with a comment, we might be able to remove the parentheses. However, we won't insert
-                // any by ourselves.
-                let right_needs_parentheses = match right.precedence() {
-                    Some(PrecedenceCategory::High) => {
-                        right_parentheses.are_required_due_to_comments()
-                    }
-                    Some(PrecedenceCategory::Low) | None => right_parentheses.is_some(),
-                };
-                let (previous_width_for_right, info_for_right) = if right_needs_parentheses {
-                    (
-                        width_for_right_side
-                            + bar_width
-                            + SinglelineWidth::PARENTHESIS
-                            + SinglelineWidth::PARENTHESIS,
-                        info.with_indent(),
-                    )
-                } else {
-                    (width_for_right_side + bar_width, info.clone())
-                };
-                let right = format_cst(edits, previous_width_for_right, right, &info_for_right);
-                if right_needs_parentheses {
-                    assert!(right_parentheses.is_some());
-                    right_parentheses.into_some(
-                        edits,
-                        previous_width
-                            + left_min_width
-                            + SinglelineWidth::SPACE
-                            + bar_width
-                            + SinglelineWidth::SPACE,
-                        right,
-                        info,
-                    )
-                } else {
-                    right_parentheses.into_none(edits, right)
-                }
-                .split()
-            };
-
-            let left_width = if let Some(right_first_line_width) = right_width.first_line_width()
-                && (left_min_width
-                    + SinglelineWidth::SPACE
-                    + bar_width
-                    + right_first_line_width)
-                .fits(info.indentation)
-            {
-                left.into_trailing_with_space(edits)
-            } else {
-                left.into_trailing_with_indentation(edits, info.indentation)
-            };
-
-            return FormattedCst::new(left_width + bar_width + right_width, whitespace);
+        CstKind::BinaryBar { .. } | CstKind::StructAccess { .. } => {
+            return format_left_nested(edits, previous_width, cst, info);
         }
         CstKind::Parenthesized { .. } => {
             // Whenever parentheses are necessary, they are handled by the parent. Hence, we try to
@@ -448,6 +406,10 @@ pub fn format_cst<'a>(
             receiver,
             arguments,
         } => {
+            // Similar to a magic trailing comma, arguments that are already on
+            // separate lines stay that way.
+            let are_arguments_on_separate_lines = has_trailing_newline(receiver)
+                && arguments.iter().rev().skip(1).all(has_trailing_newline);
             let receiver =
                 format_receiver(edits, previous_width, receiver, info, ReceiverParent::Call);
             if arguments.is_empty() {
@@ -487,6 +449,21 @@ pub fn format_cst<'a>(
                     )
                 };
 
+            if !is_singleline
+                && info.options.call_arguments_layout == CallArgumentsLayout::Packed
+                && !are_arguments_on_separate_lines
+                && let Some(line_breaks) = pack_arguments(&arguments, argument_info.indentation)
+            {
+                return format_packed_arguments(
+                    edits,
+                    previous_width,
+                    receiver,
+                    arguments,
+                    &line_breaks,
+                    &argument_info,
+                );
+            }
+
             let width = receiver.into_trailing(edits, trailing);
 
             let last_argument = arguments.pop().unwrap();
@@ -636,57 +613,6 @@ pub fn format_cst<'a>(
                 whitespace,
             );
         }
-        CstKind::StructAccess { struct_, dot, key } => {
-            let (struct_, struct_parentheses) = ExistingParentheses::split_from(edits, struct_);
-            let struct_needs_parentheses = match struct_.precedence() {
-                Some(PrecedenceCategory::High) => struct_parentheses.are_required_due_to_comments(),
-                Some(PrecedenceCategory::Low) => true,
-                None => struct_parentheses.is_some(),
-            };
-            let (previous_width_for_struct, info_for_struct) = if struct_needs_parentheses {
-                let previous_width_for_struct = if struct_parentheses.are_required_due_to_comments()
-                {
-                    Width::multiline(None, info.indentation.with_indent().width())
-                } else {
-                    previous_width + SinglelineWidth::PARENTHESIS
-                };
-                (previous_width_for_struct, info.with_indent())
-            } else {
-                (previous_width, info.clone())
-            };
-            let struct_ = format_cst(edits, previous_width_for_struct, struct_, &info_for_struct);
-            let mut struct_ = if struct_needs_parentheses {
-                struct_parentheses.into_some(edits, previous_width, struct_, info)
-            } else {
-                struct_parentheses.into_none(edits, struct_)
-            };
-
-            let previous_width_for_dot =
-                Width::multiline(None, info.indentation.with_indent().width());
-            let dot_width = format_cst(edits, previous_width_for_dot, dot, &info.with_indent())
-                .into_empty_and_move_comments_to(edits, &mut struct_.whitespace);
-
-            let key = format_cst(
-                edits,
-                previous_width_for_dot + dot_width,
-                key,
-                &info.with_indent(),
-            );
-
-            let min_width =
-                struct_.min_width(info.indentation) + dot_width + key.min_width(info.indentation);
-            let struct_trailing = if min_width.fits(info.indentation) {
-                TrailingWhitespace::None
-            } else {
-                TrailingWhitespace::Indentation(info.indentation.with_indent())
-            };
-
-            let (key_width, whitespace) = key.split();
-            return FormattedCst::new(
-                struct_.into_trailing(edits, struct_trailing) + dot_width + key_width,
-                whitespace,
-            );
-        }
         CstKind::Match {
             expression,
             percent,
@@ -744,6 +670,7 @@ pub fn format_cst<'a>(
         }
         CstKind::MatchCase {
             pattern,
+            guard,
             arrow,
             body,
         } => {
@@ -751,9 +678,19 @@ pub fn format_cst<'a>(
 
             let previous_width_for_arrow =
                 Width::multiline(None, info.indentation.with_indent().width());
+            let guard = guard.as_deref().map(|(keyword, condition)| {
+                let keyword = format_cst(edits, previous_width_for_arrow, keyword, info);
+                let condition = format_cst(edits, previous_width_for_arrow, condition, info);
+                (keyword, condition)
+            });
             let mut arrow = format_cst(edits, previous_width_for_arrow, arrow, info);
-            let pattern_width =
-                pattern.into_space_and_move_comments_to(edits, &mut arrow.whitespace);
+            let pattern_width = if let Some((mut keyword, mut condition)) = guard {
+                pattern.into_space_and_move_comments_to(edits, &mut keyword.whitespace)
+                    + keyword.into_space_and_move_comments_to(edits, &mut condition.whitespace)
+                    + condition.into_space_and_move_comments_to(edits, &mut arrow.whitespace)
+            } else {
+                pattern.into_space_and_move_comments_to(edits, &mut arrow.whitespace)
+            };
 
             let (body_width, whitespace) = format_csts(
                 edits,
@@ -791,6 +728,12 @@ pub fn format_cst<'a>(
                 SinglelineWidth::PARENTHESIS.into(),
             );
 
+            let body_starts_on_new_line = has_trailing_newline(
+                parameters_and_arrow
+                    .as_ref()
+                    .map_or(opening_curly_brace, |(_, arrow)| arrow),
+            );
+
             let opening_curly_brace = format_cst(edits, previous_width, opening_curly_brace, &info);
 
             let previous_width_for_inner =
@@ -874,6 +817,12 @@ pub fn format_cst<'a>(
                 })
                 .unwrap_or_default();
             let body_min_width = body.min_width(info.indentation);
+            let expand_body = !body_min_width.is_empty()
+                && match info.options.lambda_body_layout {
+                    LambdaBodyLayout::CollapseIfFits => false,
+                    LambdaBodyLayout::AlwaysExpand => true,
+                    LambdaBodyLayout::Preserve => body_starts_on_new_line,
+                };
             let width_until_arrow = opening_curly_brace.min_width(info.indentation)
                 + SinglelineWidth::SPACE
                 + parameters_and_arrow_min_width;
@@ -888,14 +837,15 @@ pub fn format_cst<'a>(
                     + SinglelineWidth::SPACE
                     + closing_curly_brace_width
             };
-            let opening_curly_brace_trailing =
-                if previous_width.last_line_fits(info.indentation, width_for_first_line) {
-                    TrailingWhitespace::Space
-                } else if body_min_width.is_empty() {
-                    TrailingWhitespace::Indentation(info.indentation)
-                } else {
-                    TrailingWhitespace::Indentation(info.indentation.with_indent())
-                };
+            let opening_curly_brace_trailing = if expand_body && parameters_and_arrow.is_none() {
+                TrailingWhitespace::Indentation(info.indentation.with_indent())
+            } else if previous_width.last_line_fits(info.indentation, width_for_first_line) {
+                TrailingWhitespace::Space
+            } else if body_min_width.is_empty() {
+                TrailingWhitespace::Indentation(info.indentation)
+            } else {
+                TrailingWhitespace::Indentation(info.indentation.with_indent())
+            };
 
             // Body
             let space_if_parameters = if parameters_width_and_arrow.is_some() {
@@ -912,7 +862,8 @@ pub fn format_cst<'a>(
                 body_min_width + space_if_body_not_empty + closing_curly_brace_width;
             let body_trailing = if body.child_width().is_empty() {
                 TrailingWhitespace::None
-            } else if !arrow_has_comments
+            } else if !expand_body
+                && !arrow_has_comments
                 && previous_width.last_line_fits(
                     info.indentation,
                     width_until_arrow + space_if_parameters + width_from_body,
@@ -926,7 +877,8 @@ pub fn format_cst<'a>(
             // Parameters and arrow
             let parameters_and_arrow_width = parameters_width_and_arrow
                 .map(|(parameters_width, arrow)| {
-                    let arrow_trailing = if !arrow.whitespace.has_comments()
+                    let arrow_trailing = if !expand_body
+                        && !arrow.whitespace.has_comments()
                         && width_until_arrow
                             .last_line_fits(info.indentation, space_if_parameters + width_from_body)
                     {
@@ -1023,6 +975,245 @@ pub fn format_cst<'a>(
     FormattedCst::new(width, ExistingWhitespace::empty(cst.data.span.end))
 }
 
+/// Pipelines and chains of struct accesses are nested to the left, e.g., `foo | bar | baz` is
+/// `(foo | bar) | baz`. Recursing into the left sides would overflow the stack for long chains.
+/// Hence, we first walk down the left sides and then format the levels from the inside out.
+fn format_left_nested<'a>(
+    edits: &mut TextEdits,
+    previous_width: Width,
+    cst: &'a Cst,
+    info: &FormattingInfo,
+) -> FormattedCst<'a> {
+    enum Level<'a> {
+        TrailingWhitespace {
+            child: &'a Cst,
+            whitespace: &'a [Cst],
+        },
+        BinaryBar {
+            bar: &'a Cst,
+            right: &'a Cst,
+            previous_width: Width,
+            info: FormattingInfo,
+            left_parentheses: ExistingParentheses<'a>,
+            left_needs_parentheses: bool,
+        },
+        StructAccess {
+            dot: &'a Cst,
+            key: &'a Cst,
+            previous_width: Width,
+            info: FormattingInfo,
+            struct_parentheses: ExistingParentheses<'a>,
+            struct_needs_parentheses: bool,
+        },
+    }
+
+    let mut levels = vec![];
+    let mut current = cst;
+    let mut current_previous_width = previous_width;
+    let mut current_info = info.clone();
+    let mut formatted = loop {
+        match &current.kind {
+            CstKind::TrailingWhitespace { child, whitespace } => {
+                levels.push(Level::TrailingWhitespace { child, whitespace });
+                current = child;
+            }
+            CstKind::BinaryBar { left, bar, right } => {
+                let (left, left_parentheses) = ExistingParentheses::split_from(edits, left);
+                let left_needs_parentheses =
+                    receiver_needs_parentheses(left, &left_parentheses, ReceiverParent::BinaryBar);
+                levels.push(Level::BinaryBar {
+                    bar,
+                    right,
+                    previous_width: current_previous_width,
+                    info: current_info.clone(),
+                    left_parentheses,
+                    left_needs_parentheses,
+                });
+                if left_needs_parentheses {
+                    current_previous_width = current_previous_width
+                        + SinglelineWidth::PARENTHESIS
+                        + SinglelineWidth::PARENTHESIS;
+                }
+                current = left;
+            }
+            CstKind::StructAccess { struct_, dot, key } => {
+                let (struct_, struct_parentheses) = ExistingParentheses::split_from(edits, struct_);
+                let struct_needs_parentheses = match struct_.precedence() {
+                    Some(PrecedenceCategory::High) => {
+                        struct_parentheses.are_required_due_to_comments()
+                    }
+                    Some(PrecedenceCategory::Low) => true,
+                    None => struct_parentheses.is_some(),
+                };
+                let (previous_width_for_struct, info_for_struct) = if struct_needs_parentheses {
+                    let previous_width_for_struct =
+                        if struct_parentheses.are_required_due_to_comments() {
+                            Width::multiline(None, current_info.indentation.with_indent().width())
+                        } else {
+                            current_previous_width + SinglelineWidth::PARENTHESIS
+                        };
+                    (previous_width_for_struct, current_info.with_indent())
+                } else {
+                    (current_previous_width, current_info.clone())
+                };
+                levels.push(Level::StructAccess {
+                    dot,
+                    key,
+                    previous_width: current_previous_width,
+                    info: current_info,
+                    struct_parentheses,
+                    struct_needs_parentheses,
+                });
+                current_previous_width = previous_width_for_struct;
+                current_info = info_for_struct;
+                current = struct_;
+            }
+            _ => break format_cst(edits, current_previous_width, current, &current_info),
+        }
+    };
+
+    for level in levels.into_iter().rev() {
+        formatted = match level {
+            Level::TrailingWhitespace { child, whitespace } => {
+                let mut whitespace = ExistingWhitespace::new(child.data.span.end, whitespace);
+                let child_width = formatted.into_empty_and_move_comments_to(edits, &mut whitespace);
+                FormattedCst::new(child_width, whitespace)
+            }
+            Level::BinaryBar {
+                bar,
+                right,
+                previous_width,
+                info,
+                left_parentheses,
+                left_needs_parentheses,
+            } => {
+                let left = if left_needs_parentheses {
+                    left_parentheses.into_some(edits, previous_width, formatted, &info)
+                } else {
+                    left_parentheses.into_none(edits, formatted)
+                };
+                format_binary_bar_after_left(edits, previous_width, left, bar, right, &info)
+            }
+            Level::StructAccess {
+                dot,
+                key,
+                previous_width,
+                info,
+                struct_parentheses,
+                struct_needs_parentheses,
+            } => {
+                let struct_ = if struct_needs_parentheses {
+                    struct_parentheses.into_some(edits, previous_width, formatted, &info)
+                } else {
+                    struct_parentheses.into_none(edits, formatted)
+                };
+                format_struct_access_after_struct(edits, struct_, dot, key, &info)
+            }
+        };
+    }
+    formatted
+}
+fn format_binary_bar_after_left<'a>(
+    edits: &mut TextEdits,
+    previous_width: Width,
+    mut left: FormattedCst<'a>,
+    bar: &'a Cst,
+    right: &'a Cst,
+    info: &FormattingInfo,
+) -> FormattedCst<'a> {
+    // Bar
+    let width_for_right_side = Width::multiline(None, info.indentation.width());
+    let bar_width = format_cst(edits, width_for_right_side, bar, info)
+        .into_space_and_move_comments_to(edits, &mut left.whitespace);
+    let left_min_width = left.min_width(info.indentation);
+
+    // Right
+    let (right_width, whitespace) = {
+        let (right, right_parentheses) = ExistingParentheses::split_from(edits, right);
+        // Depending on the precedence of `right` and whether there's an opening parenthesis
+        // with a comment, we might be able to remove the parentheses. However, we won't insert
+        // any by ourselves.
+        let right_needs_parentheses = match right.precedence() {
+            Some(PrecedenceCategory::High) => right_parentheses.are_required_due_to_comments(),
+            Some(PrecedenceCategory::Low) | None => right_parentheses.is_some(),
+        };
+        let (previous_width_for_right, info_for_right) = if right_needs_parentheses {
+            (
+                width_for_right_side
+                    + bar_width
+                    + SinglelineWidth::PARENTHESIS
+                    + SinglelineWidth::PARENTHESIS,
+                info.with_indent(),
+            )
+        } else {
+            (width_for_right_side + bar_width, info.clone())
+        };
+        let right = format_cst(edits, previous_width_for_right, right, &info_for_right);
+        if right_needs_parentheses {
+            assert!(right_parentheses.is_some());
+            right_parentheses.into_some(
+                edits,
+                previous_width
+                    + left_min_width
+                    + SinglelineWidth::SPACE
+                    + bar_width
+                    + SinglelineWidth::SPACE,
+                right,
+                info,
+            )
+        } else {
+            right_parentheses.into_none(edits, right)
+        }
+        .split()
+    };
+
+    let left_width = if let Some(right_first_line_width) = right_width.first_line_width()
+        && (left_min_width
+            + SinglelineWidth::SPACE
+            + bar_width
+            + right_first_line_width)
+        .fits(info.indentation)
+    {
+        left.into_trailing_with_space(edits)
+    } else {
+        left.into_trailing_with_indentation(edits, info.indentation)
+    };
+
+    FormattedCst::new(left_width + bar_width + right_width, whitespace)
+}
+fn format_struct_access_after_struct<'a>(
+    edits: &mut TextEdits,
+    mut struct_: FormattedCst<'a>,
+    dot: &'a Cst,
+    key: &'a Cst,
+    info: &FormattingInfo,
+) -> FormattedCst<'a> {
+    let previous_width_for_dot = Width::multiline(None, info.indentation.with_indent().width());
+    let dot_width = format_cst(edits, previous_width_for_dot, dot, &info.with_indent())
+        .into_empty_and_move_comments_to(edits, &mut struct_.whitespace);
+
+    let key = format_cst(
+        edits,
+        previous_width_for_dot + dot_width,
+        key,
+        &info.with_indent(),
+    );
+
+    let min_width =
+        struct_.min_width(info.indentation) + dot_width + key.min_width(info.indentation);
+    let struct_trailing = if min_width.fits(info.indentation) {
+        TrailingWhitespace::None
+    } else {
+        TrailingWhitespace::Indentation(info.indentation.with_indent())
+    };
+
+    let (key_width, whitespace) = key.split();
+    FormattedCst::new(
+        struct_.into_trailing(edits, struct_trailing) + dot_width + key_width,
+        whitespace,
+    )
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 enum ReceiverParent {
     BinaryBar,
@@ -1036,14 +1227,8 @@ fn format_receiver<'a>(
     parent: ReceiverParent,
 ) -> FormattedCst<'a> {
     let (receiver, receiver_parentheses) = ExistingParentheses::split_from(edits, receiver);
-    let receiver_needs_parentheses = match receiver.precedence() {
-        Some(PrecedenceCategory::High) => receiver_parentheses.are_required_due_to_comments(),
-        Some(PrecedenceCategory::Low) => match parent {
-            ReceiverParent::BinaryBar => receiver_parentheses.are_required_due_to_comments(),
-            ReceiverParent::Call => true,
-        },
-        None => receiver_parentheses.is_some(),
-    };
+    let receiver_needs_parentheses =
+        receiver_needs_parentheses(receiver, &receiver_parentheses, parent);
     let previous_width_for_receiver = if receiver_needs_parentheses {
         previous_width + SinglelineWidth::PARENTHESIS + SinglelineWidth::PARENTHESIS
     } else {
@@ -1058,6 +1243,21 @@ fn format_receiver<'a>(
     }
 }
 
+fn receiver_needs_parentheses(
+    receiver: &Cst,
+    receiver_parentheses: &ExistingParentheses,
+    parent: ReceiverParent,
+) -> bool {
+    match receiver.precedence() {
+        Some(PrecedenceCategory::High) => receiver_parentheses.are_required_due_to_comments(),
+        Some(PrecedenceCategory::Low) => match parent {
+            ReceiverParent::BinaryBar => receiver_parentheses.are_required_due_to_comments(),
+            ReceiverParent::Call => true,
+        },
+        None => receiver_parentheses.is_some(),
+    }
+}
+
 struct Argument<'a> {
     argument: MaybeSandwichLikeArgument<'a>,
     precedence: Option<PrecedenceCategory>,
@@ -1165,6 +1365,66 @@ impl<'a> Argument<'a> {
         }
     }
 }
+
+/// For [`CallArgumentsLayout::Packed`], whether each argument starts on a new
+/// line. Returns `None` if the arguments can't be packed because of comments or
+/// a trailing sandwich-like argument.
+fn pack_arguments(arguments: &[Argument], indentation: Indentation) -> Option<Vec<bool>> {
+    let mut line_breaks = Vec::with_capacity(arguments.len());
+    let mut line_width = Width::default();
+    for argument in arguments {
+        if matches!(
+            argument.argument,
+            MaybeSandwichLikeArgument::SandwichLike(_)
+        ) {
+            return None;
+        }
+        let width = argument.min_singleline_width();
+        if !width.is_singleline() {
+            return None;
+        }
+
+        let width_with_space = line_width + SinglelineWidth::SPACE + width;
+        if line_breaks.is_empty() || !width_with_space.fits(indentation) {
+            line_breaks.push(true);
+            line_width = width;
+        } else {
+            line_breaks.push(false);
+            line_width = width_with_space;
+        }
+    }
+    Some(line_breaks)
+}
+fn format_packed_arguments<'a>(
+    edits: &mut TextEdits,
+    previous_width: Width,
+    receiver: FormattedCst<'a>,
+    mut arguments: Vec<Argument<'a>>,
+    line_breaks: &[bool],
+    info: &FormattingInfo,
+) -> FormattedCst<'a> {
+    let mut width = receiver.into_trailing_with_indentation(edits, info.indentation);
+
+    let last_argument = arguments.pop().unwrap();
+    let line_breaks = &line_breaks[1..];
+    for (argument, is_followed_by_line_break) in arguments.into_iter().zip_eq(line_breaks) {
+        // Arguments sharing a line with others might need parentheses.
+        let argument = argument.format(edits, previous_width + width, info, true);
+        width += if *is_followed_by_line_break {
+            argument.into_trailing_with_indentation(edits, info.indentation)
+        } else {
+            argument.into_trailing_with_space(edits)
+        };
+    }
+    let (last_argument_width, whitespace) = last_argument
+        .format(edits, previous_width + width, info, true)
+        .split();
+    FormattedCst::new(
+        (width + last_argument_width).without_first_line_width(),
+        whitespace,
+    )
+}
+
 enum MaybeSandwichLikeArgument<'a> {
     SandwichLike(&'a Cst),
     Other {
@@ -1220,6 +1480,7 @@ pub impl<D> CstExtension for Cst<D> {
             | CstKind::DoubleQuote
             | CstKind::Percent
             | CstKind::Octothorpe
+            | CstKind::If
             | CstKind::Whitespace(_)
             | CstKind::Newline(_)
             | CstKind::Comment { .. } => None,
@@ -1250,7 +1511,7 @@ pub impl<D> CstExtension for Cst<D> {
 
 #[cfg(test)]
 mod test {
-    use crate::Formatter;
+    use crate::{CallArgumentsLayout, Formatter, FormatterOptions, LambdaBodyLayout};
     use candy_frontend::{rcst_to_cst::RcstsToCstsExt, string_to_rcst::parse_rcst};
     use itertools::Itertools;
 
@@ -1261,6 +1522,17 @@ mod test {
     // expressions have the same formatted version, there's only a comment in front of the first
     // test case.
 
+    #[test]
+    fn long_chains_dont_overflow_the_stack() {
+        test(
+            &format!("bar{}", " | baz".repeat(100)),
+            &format!("bar{}{}\n", " | baz".repeat(16), "\n| baz".repeat(84)),
+        );
+        test(
+            &format!("bar{}", ".baz".repeat(100)),
+            &format!("bar{}{}\n", ".baz".repeat(24), "\n  .baz".repeat(76)),
+        );
+    }
     #[test]
     fn test_csts() {
         test(" ", "");
@@ -1589,6 +1861,13 @@ mod test {
             "foo { bar -> looooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooongExpression }",
             "foo { bar ->\n  looooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooongExpression\n}\n",
         );
+        // foo baz { bar ->
+        //   looooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooongExpression
+        // }
+        test(
+            "foo baz { bar -> looooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooongExpression }",
+            "foo baz { bar ->\n  looooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooongExpression\n}\n",
+        );
         // foo (
         //   looooooooooooooooooooooooongItem0,
         //   looooooooooooooooooooooooongItem1,
@@ -1694,6 +1973,57 @@ mod test {
         //   bar,
         // )
         test("(foo# abc\n  , bar,)", "(\n  foo, # abc\n  bar,\n)\n");
+        // (
+        //   foo,
+        //   # abc
+        // )
+        test("(\n  foo,\n  # abc\n)", "(\n  foo,\n  # abc\n)\n");
+        test("(foo,\n# abc\n)", "(\n  foo,\n  # abc\n)\n");
+        // (
+        //   foo, # abc
+        //   # def
+        // )
+        test("(foo, # abc\n  # def\n)", "(\n  foo, # abc\n  # def\n)\n");
+        // (
+        //   foo,
+        //   # abc
+        //   bar,
+        // )
+        test(
+            "(\n  foo,\n  # abc\n  bar,\n)",
+            "(\n  foo,\n  # abc\n  bar,\n)\n",
+        );
+        test("(foo,\n  # abc\n  bar)", "(\n  foo,\n  # abc\n  bar,\n)\n");
+        // (
+        //   (
+        //     foo,
+        //     # abc
+        //   ),
+        //   bar,
+        // )
+        test(
+            "(\n  (\n    foo,\n    # abc\n  ),\n  bar,\n)",
+            "(\n  (\n    foo,\n    # abc\n  ),\n  bar,\n)\n",
+        );
+        // (
+        //   (
+        //     foo, # abc
+        //   ), # def
+        // )
+        test(
+            "((foo, # abc\n), # def\n)",
+            "(\n  (\n    foo, # abc\n  ), # def\n)\n",
+        );
+        // (
+        //   (
+        //     foo, # abc
+        //   ),
+        //   # def
+        // )
+        test(
+            "((foo, # abc\n),\n# def\n)",
+            "(\n  (\n    foo, # abc\n  ),\n  # def\n)\n",
+        );
     }
     #[test]
     fn test_struct() {
@@ -1773,6 +2103,27 @@ mod test {
             "[foo: bar # abc\n  , baz]",
             "[\n  foo: bar, # abc\n  baz,\n]\n",
         );
+        // [
+        //   foo: bar,
+        //   # abc
+        // ]
+        test("[\n  foo: bar,\n  # abc\n]", "[\n  foo: bar,\n  # abc\n]\n");
+        test("[foo: bar\n# abc\n]", "[\n  foo: bar,\n  # abc\n]\n");
+        // [ # abc
+        // ]
+        test("[# abc\n]", "[ # abc\n]\n");
+        test("[ # abc\n  # def\n]", "[ # abc\n  # def\n]\n");
+        // [
+        //   foo:
+        //     [
+        //       bar,
+        //       # abc
+        //     ],
+        // ]
+        test(
+            "[foo: [bar,\n  # abc\n  ]]",
+            "[\n  foo:\n    [\n      bar,\n      # abc\n    ],\n]\n",
+        );
     }
     #[test]
     fn test_struct_access() {
@@ -1838,6 +2189,15 @@ mod test {
             "foo %\n  Bar # abc\n  -> Baz",
             "foo %\n  Bar -> # abc\n    Baz\n",
         );
+
+        // Guards
+        // foo %
+        //   Foo x if x | isPositive -> x
+        //   _ -> 0
+        test(
+            "foo %\n  Foo x  if  x | isPositive->x\n  _ -> 0",
+            "foo %\n  Foo x if x | isPositive -> x\n  _ -> 0\n",
+        );
     }
     #[test]
     fn test_function() {
@@ -2040,6 +2400,106 @@ mod test {
             "foo :=\n  # looooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooooongComment\n  Foo\n\n  Bar\n",
         );
     }
+    #[test]
+    fn test_lambda_body_layout() {
+        let always_expand = FormatterOptions {
+            lambda_body_layout: LambdaBodyLayout::AlwaysExpand,
+            ..FormatterOptions::default()
+        };
+        test_with_options("{ foo }", "{\n  foo\n}\n", always_expand);
+        test_with_options("{ foo -> bar }", "{ foo ->\n  bar\n}\n", always_expand);
+        test_with_options("{ foo ->\n  bar\n}", "{ foo ->\n  bar\n}\n", always_expand);
+        test_with_options("{ foo -> }", "{ foo -> }\n", always_expand);
+
+        let preserve = FormatterOptions {
+            lambda_body_layout: LambdaBodyLayout::Preserve,
+            ..FormatterOptions::default()
+        };
+        test_with_options("{ foo -> bar }", "{ foo -> bar }\n", preserve);
+        test_with_options("{ foo ->\n  bar\n}", "{ foo ->\n  bar\n}\n", preserve);
+        test_with_options("{ foo ->   bar\n}", "{ foo -> bar }\n", preserve);
+        test_with_options("{\n  foo\n}", "{\n  foo\n}\n", preserve);
+    }
+    #[test]
+    fn test_call_arguments_layout() {
+        let packed = FormatterOptions {
+            call_arguments_layout: CallArgumentsLayout::Packed,
+            ..FormatterOptions::default()
+        };
+        test_with_options("foo bar baz\n", "foo bar baz\n", packed);
+
+        let arguments = (0..26).map(|_| "argument1").join(" ");
+        test_with_options(
+            &format!("foo {arguments}"),
+            &format!(
+                "foo\n  {}\n  {}\n  {}\n",
+                (0..9).map(|_| "argument1").join(" "),
+                (0..9).map(|_| "argument1").join(" "),
+                (0..8).map(|_| "argument1").join(" "),
+            ),
+            packed,
+        );
+        // Arguments that are already on separate lines stay that way.
+        let arguments = (0..26).map(|_| "argument1").join("\n  ");
+        test_with_options(
+            &format!("foo\n  {arguments}"),
+            &format!("foo\n  {arguments}\n"),
+            packed,
+        );
+        test_with_options(
+            "foo\n  argument1\n  argument2\n",
+            "foo argument1 argument2\n",
+            packed,
+        );
+        // Nested calls sharing a line with other arguments keep their parentheses.
+        let arguments = (0..10).map(|_| "argument1").join(" ");
+        test_with_options(
+            &format!("foo {arguments} (bar baz) {arguments}"),
+            &format!(
+                "foo\n  {}\n  argument1 (bar baz) {}\n  {}\n",
+                (0..9).map(|_| "argument1").join(" "),
+                (0..7).map(|_| "argument1").join(" "),
+                (0..3).map(|_| "argument1").join(" "),
+            ),
+            packed,
+        );
+    }
+    #[test]
+    fn test_organize_uses() {
+        let organize_uses = FormatterOptions {
+            organize_uses: true,
+            ..FormatterOptions::default()
+        };
+        test_with_options("foo = 1\n", "foo = 1\n", organize_uses);
+        test_with_options(
+            "bar = use \"Bar\"\n\nfoo = 1\n",
+            "bar = use \"Bar\"\n\nfoo = 1\n",
+            organize_uses,
+        );
+        // bar = use "Bar"
+        // [baz] = use "Baz"
+        //
+        // foo = 1
+        test_with_options(
+            "foo = 1\n[baz] = use \"Baz\"\nbar = use \"Bar\"\n",
+            "bar = use \"Bar\"\n[baz] = use \"Baz\"\n\nfoo = 1\n",
+            organize_uses,
+        );
+        // # Module docs.
+        //
+        // bar = use "Bar"
+        // # Baz.
+        // baz = use "Baz"
+        //
+        // foo = 1
+        //
+        // qux = 2
+        test_with_options(
+            "# Module docs.\n\nfoo = 1\n\n# Baz.\nbaz = use \"Baz\"\nbar = use \"Bar\"\n\nqux = 2\n",
+            "# Module docs.\n\nbar = use \"Bar\"\n# Baz.\nbaz = use \"Baz\"\n\nfoo = 1\n\nqux = 2\n",
+            organize_uses,
+        );
+    }
 
     #[track_caller]
     fn test(source: &str, expected: &str) {
@@ -2049,4 +2509,16 @@ mod test {
         let formatted = csts.as_slice().format_to_string();
         assert_eq!(formatted, expected);
     }
+    #[track_caller]
+    fn test_with_options(source: &str, expected: &str, options: FormatterOptions) {
+        let csts = parse_rcst(source).to_csts();
+        let formatted = csts.as_slice().format_to_string_with_options(options);
+        assert_eq!(formatted, expected);
+
+        let reformatted = parse_rcst(&formatted)
+            .to_csts()
+            .as_slice()
+            .format_to_string_with_options(options);
+        assert_eq!(reformatted, formatted, "Formatting is not idempotent.");
+    }
 }
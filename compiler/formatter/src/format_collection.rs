@@ -1,5 +1,5 @@
 use crate::{
-    existing_whitespace::{ExistingWhitespace, TrailingWhitespace},
+    existing_whitespace::{ExistingWhitespace, TrailingWhitespace, TrailingWithIndentationConfig},
     format::{format_cst, CstExtension, FormattingInfo},
     formatted_cst::FormattedCst,
     text_edits::TextEdits,
@@ -8,6 +8,20 @@ use crate::{
 use candy_frontend::{cst::Cst, position::Offset};
 use itertools::Itertools;
 
+/// Formats a list or struct.
+///
+/// Comments are anchored as follows:
+///
+/// - A comment after the opening punctuation stays on its line. Comments on
+///   the following lines are indented like the items.
+/// - A comment between an item and its comma moves after the comma.
+/// - A comment after a comma stays on the line of its item. Comments on the
+///   following lines are indented like the items.
+/// - The same holds after the last item. The closing punctuation then goes on
+///   its own line, indented like the opening punctuation.
+///
+/// Any comment makes the collection span multiple lines, and every item gets a
+/// trailing comma.
 pub fn format_collection<'a>(
     edits: &mut TextEdits,
     previous_width: Width,
@@ -83,40 +97,51 @@ pub fn format_collection<'a>(
         })
         .collect_vec();
 
-    let (opening_punctuation_trailing, item_trailing, last_item_trailing) =
-        if min_width.is_singleline() {
-            (
-                TrailingWhitespace::None,
-                TrailingWhitespace::Space,
-                TrailingWhitespace::None,
-            )
+    let is_singleline = min_width.is_singleline();
+    let item_trailing = if is_singleline {
+        TrailingWhitespace::Space
+    } else {
+        TrailingWhitespace::Indentation(info.indentation.with_indent())
+    };
+    // The whitespace before the closing punctuation belongs to the last item
+    // or, in empty collections, to the opening punctuation.
+    let into_trailing_before_closing_punctuation = |edits: &mut TextEdits, cst: FormattedCst| {
+        if is_singleline {
+            cst.into_empty_trailing(edits)
         } else {
-            (
-                TrailingWhitespace::Indentation(info.indentation.with_indent()),
-                TrailingWhitespace::Indentation(info.indentation.with_indent()),
-                TrailingWhitespace::Indentation(info.indentation),
+            cst.into_trailing_with_indentation_detailed(
+                edits,
+                &TrailingWithIndentationConfig::BeforeClosingPunctuation {
+                    // TODO: Pass actual previous width
+                    previous_width: Width::default(),
+                    item_indentation: info.indentation.with_indent(),
+                },
             )
-        };
+        }
+    };
 
+    let opening_punctuation_width = if items.is_empty() {
+        into_trailing_before_closing_punctuation(edits, opening_punctuation)
+    } else if is_singleline {
+        opening_punctuation.into_empty_trailing(edits)
+    } else {
+        opening_punctuation.into_trailing(edits, item_trailing)
+    };
     let last_item_index = items.len().checked_sub(1);
+    let items_width = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            if last_item_index == Some(index) {
+                into_trailing_before_closing_punctuation(edits, item)
+            } else {
+                item.into_trailing(edits, item_trailing)
+            }
+        })
+        .sum::<Width>();
     let (closing_punctuation_width, whitespace) = closing_punctuation.split();
     FormattedCst::new(
-        opening_punctuation.into_trailing(edits, opening_punctuation_trailing)
-            + items
-                .into_iter()
-                .enumerate()
-                .map(|(index, item)| {
-                    item.into_trailing(
-                        edits,
-                        if last_item_index == Some(index) {
-                            last_item_trailing
-                        } else {
-                            item_trailing
-                        },
-                    )
-                })
-                .sum::<Width>()
-            + closing_punctuation_width,
+        opening_punctuation_width + items_width + closing_punctuation_width,
         whitespace,
     )
 }
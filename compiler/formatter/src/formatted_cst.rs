@@ -128,6 +128,13 @@ impl<'a> FormattedCst<'a> {
                 previous_width: *previous_width + self.child_width,
                 indentation: *indentation,
             },
+            TrailingWithIndentationConfig::BeforeClosingPunctuation {
+                previous_width,
+                item_indentation,
+            } => TrailingWithIndentationConfig::BeforeClosingPunctuation {
+                previous_width: *previous_width + self.child_width,
+                item_indentation: *item_indentation,
+            },
         };
         let whitespace_width = self
             .whitespace
@@ -12,11 +12,16 @@
     clippy::too_many_lines
 )]
 
-use candy_frontend::{cst::Cst, position::Offset};
+use candy_frontend::{
+    cst::Cst, position::Offset, rcst_to_cst::RcstsToCstsExt, string_to_rcst::parse_rcst,
+};
 use existing_whitespace::{TrailingWithIndentationConfig, WhitespacePositionInBody};
 use extension_trait::extension_trait;
 use format::{format_csts, FormattingInfo};
 use itertools::Itertools;
+pub use options::{CallArgumentsLayout, FormatterOptions, LambdaBodyLayout};
+use organize_uses::organize_uses;
+pub use suspicious_layout::{find_suspicious_layouts, SuspiciousLayout, SuspiciousLayoutKind};
 use text_edits::TextEdits;
 use width::{Indentation, Width};
 
@@ -25,6 +30,9 @@ mod existing_whitespace;
 mod format;
 mod format_collection;
 mod formatted_cst;
+mod options;
+mod organize_uses;
+mod suspicious_layout;
 mod text_edits;
 mod width;
 
@@ -33,18 +41,42 @@ pub impl<C: AsRef<[Cst]>> Formatter for C {
     fn format_to_string(&self) -> String {
         self.format_to_edits().apply()
     }
+    fn format_to_string_with_options(&self, options: FormatterOptions) -> String {
+        self.format_to_edits_with_options(options).apply()
+    }
     fn format_to_edits(&self) -> TextEdits {
+        self.format_to_edits_with_options(FormatterOptions::default())
+    }
+    fn format_to_edits_with_options(&self, options: FormatterOptions) -> TextEdits {
         let csts = self.as_ref();
         // TOOD: Is there an elegant way to avoid stringifying the whole CST?
         let source = csts.iter().join("");
+        let suspicious_layouts = find_suspicious_layouts(&source, csts);
         let mut edits = TextEdits::new(source);
+        edits.add_suspicious_layouts(suspicious_layouts);
+
+        if options.organize_uses
+            && let Some(organized) = organize_uses(csts)
+        {
+            // Moving code around doesn't map well to edits of the existing
+            // whitespace, so we replace the whole document.
+            let formatted = parse_rcst(&organized)
+                .to_csts()
+                .format_to_string_with_options(FormatterOptions {
+                    organize_uses: false,
+                    ..options
+                });
+            let source_length = edits.source().len();
+            edits.change(Offset(0)..Offset(source_length), formatted);
+            return edits;
+        }
 
         let formatted = format_csts(
             &mut edits,
             Width::default(),
             csts,
             Offset::default(),
-            &FormattingInfo::default(),
+            &FormattingInfo::new(options),
         );
         if formatted.child_width() == Width::default() && !formatted.whitespace.has_comments() {
             _ = formatted.into_empty_trailing(&mut edits);
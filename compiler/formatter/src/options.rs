@@ -0,0 +1,53 @@
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FormatterOptions {
+    pub lambda_body_layout: LambdaBodyLayout,
+    pub call_arguments_layout: CallArgumentsLayout,
+    /// Whether to move all top-level `use`s to the start of the module, sorted
+    /// by the used module.
+    pub organize_uses: bool,
+}
+
+/// When to put the body of a function containing a single expression on the
+/// same line as its curly braces (and parameters).
+///
+/// Bodies that don't fit into a single line are always expanded, and bodies
+/// with multiple expressions can't be collapsed at all.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LambdaBodyLayout {
+    /// `{ x -> x }`
+    #[default]
+    CollapseIfFits,
+    /// ```candy
+    /// { x ->
+    ///   x
+    /// }
+    /// ```
+    AlwaysExpand,
+    /// Keeps bodies expanded if they start on a new line and collapses them
+    /// otherwise.
+    Preserve,
+}
+
+/// How to lay out the arguments of a call that doesn't fit into a single line.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CallArgumentsLayout {
+    /// ```candy
+    /// foo
+    ///   firstArgument
+    ///   secondArgument
+    ///   thirdArgument
+    /// ```
+    #[default]
+    OnePerLine,
+    /// Fills each line with as many arguments as fit:
+    ///
+    /// ```candy
+    /// foo
+    ///   firstArgument secondArgument
+    ///   thirdArgument
+    /// ```
+    ///
+    /// Calls that already have each argument on its own line keep that layout,
+    /// so you can still choose it for individual calls.
+    Packed,
+}
@@ -0,0 +1,168 @@
+//! Moves all top-level `use`s of a module to its start:
+//!
+//! ```candy
+//! # Module documentation stays at the top if it's followed by a blank line.
+//!
+//! [bar] = use "Bar"
+//! # Comments directly above a `use` move together with it.
+//! foo = use "Foo"
+//!
+//! main := { environment -> … }
+//! ```
+//!
+//! `use`s are sorted by the used module and separated from the remaining code
+//! by a blank line. `use`s of modules that aren't known statically (e.g.,
+//! because of text interpolation) stay where they are.
+
+use candy_frontend::cst::{Cst, CstKind};
+use itertools::Itertools;
+use std::ops::RangeInclusive;
+
+struct Chunk {
+    /// The lines of the expression and of the comments directly above it.
+    lines: RangeInclusive<usize>,
+    used_module: Option<String>,
+}
+
+/// Returns the source with organized `use`s or `None` if nothing changed.
+///
+/// The result still needs to be formatted.
+#[must_use]
+pub fn organize_uses(csts: &[Cst]) -> Option<String> {
+    let source = csts.iter().join("");
+    let lines = source.split('\n').collect_vec();
+    let line_starts = source
+        .match_indices('\n')
+        .map(|(index, _)| index + 1)
+        .collect_vec();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset);
+
+    let mut chunks: Vec<Chunk> = vec![];
+    for cst in csts {
+        if cst.is_whitespace_or_comment() {
+            continue;
+        }
+        let expression = unwrap_trailing_whitespace(cst);
+        let span = &expression.data.span;
+        let end_line = line_of((*span.end).max(*span.start + 1) - 1);
+        let mut start_line = line_of(*span.start);
+
+        let previous_end_line = chunks.last().map(|it| *it.lines.end());
+        if previous_end_line.is_some_and(|it| it >= start_line) {
+            // Multiple expressions in a single line can't be moved separately.
+            return None;
+        }
+        while start_line > previous_end_line.map_or(0, |it| it + 1)
+            && lines[start_line - 1].trim_start().starts_with('#')
+        {
+            start_line -= 1;
+        }
+
+        chunks.push(Chunk {
+            lines: start_line..=end_line,
+            used_module: used_module(expression),
+        });
+    }
+    let first_line = *chunks.first()?.lines.start();
+    let mut uses = chunks
+        .into_iter()
+        .filter(|it| it.used_module.is_some())
+        .collect_vec();
+    if uses.is_empty() {
+        return None;
+    }
+    uses.sort_by(|a, b| a.used_module.cmp(&b.used_module));
+
+    let mut result: Vec<&str> = vec![];
+    let header = &lines[..first_line];
+    let trailing_blank_lines = header.iter().rev().take_while(|it| is_blank(it)).count();
+    let header = &header[..header.len() - trailing_blank_lines];
+    if !header.is_empty() {
+        result.extend(header);
+        result.push("");
+    }
+    for use_ in &uses {
+        result.extend(&lines[use_.lines.clone()]);
+    }
+
+    let mut rest: Vec<&str> = vec![];
+    let mut removed_lines = uses.iter().map(|it| it.lines.clone()).collect_vec();
+    removed_lines.sort_by_key(|it| *it.start());
+    let mut removed_lines = removed_lines.into_iter().peekable();
+    let mut line = first_line;
+    while line < lines.len() {
+        if let Some(removed) = removed_lines.next_if(|it| *it.start() == line) {
+            line = removed.end() + 1;
+            // Don't leave two blank lines where the `use` was.
+            if rest.last().map_or(true, |it| is_blank(it))
+                && lines.get(line).is_some_and(|it| is_blank(it))
+            {
+                line += 1;
+            }
+            continue;
+        }
+        rest.push(lines[line]);
+        line += 1;
+    }
+    let rest = rest.into_iter().skip_while(|it| is_blank(it)).collect_vec();
+    if !rest.is_empty() {
+        result.push("");
+        result.extend(rest);
+    }
+
+    let result = result.join("\n");
+    if result == source {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+fn used_module(expression: &Cst) -> Option<String> {
+    let call = match &expression.kind {
+        CstKind::Assignment { body, .. } => {
+            let (body,) = body
+                .iter()
+                .filter(|it| !it.is_whitespace_or_comment())
+                .collect_tuple()?;
+            unwrap_trailing_whitespace(body)
+        }
+        _ => expression,
+    };
+    let CstKind::Call {
+        receiver,
+        arguments,
+    } = &call.kind
+    else {
+        return None;
+    };
+    let CstKind::Identifier(receiver) = &unwrap_trailing_whitespace(receiver).kind else {
+        return None;
+    };
+    if receiver != "use" {
+        return None;
+    }
+
+    let (argument,) = arguments.iter().collect_tuple()?;
+    let CstKind::Text { parts, .. } = &unwrap_trailing_whitespace(argument).kind else {
+        return None;
+    };
+    parts
+        .iter()
+        .map(|part| match &part.kind {
+            CstKind::TextPart(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn unwrap_trailing_whitespace(mut cst: &Cst) -> &Cst {
+    while let CstKind::TrailingWhitespace { child, .. } = &cst.kind {
+        cst = child;
+    }
+    cst
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
@@ -0,0 +1,155 @@
+//! Finds code whose indentation suggests a different structure than the one
+//! the parser sees:
+//!
+//! ```candy
+//! foo
+//!   bar
+//!   # A comment.
+//!     baz
+//! ```
+//!
+//! This looks like `foo (bar baz)`, but the comment ends the arguments of
+//! `bar`, so it's actually `foo bar baz`. The formatter fixes the indentation,
+//! but that silently changes what the code looks like it means, so we report
+//! these cases alongside the edits.
+
+use candy_frontend::{
+    cst::{Cst, CstKind},
+    position::Offset,
+};
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+use traversal::dft_pre;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SuspiciousLayout {
+    pub span: Range<Offset>,
+    pub kind: SuspiciousLayoutKind,
+}
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SuspiciousLayoutKind {
+    /// An argument is indented further than the previous argument, so it
+    /// looks like an argument of the previous argument.
+    ArgumentLooksNested,
+    /// An expression in a body is indented further than the previous
+    /// expression, so it looks like part of the previous expression.
+    ExpressionLooksNested,
+}
+impl Display for SuspiciousLayoutKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ArgumentLooksNested => write!(
+                f,
+                "This argument is indented further than the previous one, but it's passed to the same function. Add parentheses if you meant to pass it to the previous argument.",
+            ),
+            Self::ExpressionLooksNested => write!(
+                f,
+                "This expression is indented further than the previous one, but it's a separate expression.",
+            ),
+        }
+    }
+}
+
+#[must_use]
+pub fn find_suspicious_layouts(source: &str, csts: &[Cst]) -> Vec<SuspiciousLayout> {
+    let mut finder = SuspiciousLayoutFinder {
+        source,
+        layouts: vec![],
+    };
+    finder.visit_body(csts);
+    for cst in csts {
+        for cst in dft_pre(cst, |it| it.children().into_iter()).map(|(_, it)| it) {
+            match &cst.kind {
+                CstKind::Call { arguments, .. } => finder.visit_arguments(arguments),
+                CstKind::MatchCase { body, .. }
+                | CstKind::Function { body, .. }
+                | CstKind::Assignment { body, .. } => finder.visit_body(body),
+                _ => {}
+            }
+        }
+    }
+    finder.layouts
+}
+
+struct SuspiciousLayoutFinder<'s> {
+    source: &'s str,
+    layouts: Vec<SuspiciousLayout>,
+}
+impl<'s> SuspiciousLayoutFinder<'s> {
+    fn visit_arguments(&mut self, arguments: &[Cst]) {
+        self.check_siblings(arguments.iter(), SuspiciousLayoutKind::ArgumentLooksNested);
+    }
+    fn visit_body(&mut self, body: &[Cst]) {
+        self.check_siblings(
+            body.iter().filter(|it| !it.is_whitespace_or_comment()),
+            SuspiciousLayoutKind::ExpressionLooksNested,
+        );
+    }
+
+    /// Siblings that each start on a new line should be indented equally.
+    fn check_siblings<'c>(
+        &mut self,
+        siblings: impl Iterator<Item = &'c Cst>,
+        kind: SuspiciousLayoutKind,
+    ) {
+        let mut previous_indentation = None;
+        for sibling in siblings {
+            let indentation = self.indentation_if_starting_line(sibling.data.span.start);
+            if let (Some(previous), Some(current)) = (previous_indentation, indentation)
+                && current > previous
+            {
+                self.layouts.push(SuspiciousLayout {
+                    span: sibling.display_span(),
+                    kind,
+                });
+            }
+            previous_indentation = indentation;
+        }
+    }
+
+    /// Returns the indentation of the line if only whitespace precedes the
+    /// offset in it.
+    fn indentation_if_starting_line(&self, offset: Offset) -> Option<usize> {
+        let before = &self.source[..*offset];
+        let line_start = before.rfind('\n').map_or(0, |it| it + 1);
+        let indentation = &before[line_start..];
+        indentation
+            .chars()
+            .all(|it| it == ' ')
+            .then_some(indentation.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_suspicious_layouts, SuspiciousLayoutKind};
+    use candy_frontend::{rcst_to_cst::RcstsToCstsExt, string_to_rcst::parse_rcst};
+    use itertools::Itertools;
+
+    #[test]
+    fn test_suspicious_layouts() {
+        test("foo\n  bar\n  baz", &[]);
+        test("foo bar\n  baz", &[]);
+        // `baz` is an argument of `bar`.
+        test("foo\n  bar\n    baz", &[]);
+        test(
+            "foo\n  bar\n  # abc\n    baz",
+            &[("baz", SuspiciousLayoutKind::ArgumentLooksNested)],
+        );
+        test(
+            "foo = { a ->\n  bar\n  # abc\n    baz\n}",
+            &[("baz", SuspiciousLayoutKind::ExpressionLooksNested)],
+        );
+    }
+
+    fn test(source: &str, expected: &[(&str, SuspiciousLayoutKind)]) {
+        let csts = parse_rcst(source).to_csts();
+        let layouts = find_suspicious_layouts(source, &csts)
+            .into_iter()
+            .map(|it| (&source[*it.span.start..*it.span.end], it.kind))
+            .collect_vec();
+        assert_eq!(layouts, expected);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::SuspiciousLayout;
 use candy_frontend::position::Offset;
 use std::{borrow::Cow, ops::Range};
 
@@ -26,12 +27,17 @@ pub struct TextEdits {
 
     /// The edits are sorted by their start position.
     edits: Vec<TextEdit>,
+
+    /// Places in the original source whose indentation doesn't match their
+    /// structure.
+    suspicious_layouts: Vec<SuspiciousLayout>,
 }
 impl TextEdits {
     pub fn new(source: String) -> Self {
         Self {
             source,
             edits: vec![],
+            suspicious_layouts: vec![],
         }
     }
 
@@ -117,6 +123,13 @@ impl TextEdits {
         }
     }
 
+    pub fn suspicious_layouts(&self) -> &[SuspiciousLayout] {
+        &self.suspicious_layouts
+    }
+    pub fn add_suspicious_layouts(&mut self, layouts: impl IntoIterator<Item = SuspiciousLayout>) {
+        self.suspicious_layouts.extend(layouts);
+    }
+
     pub fn finish(self) -> Vec<TextEdit> {
         self.edits
     }
@@ -1,6 +1,7 @@
 use super::{cst, cst_to_ast::CstToAst, error::CompilerError};
 use crate::{
     module::Module,
+    position::Offset,
     rich_ir::{RichIrBuilder, ToRichIr, TokenType},
 };
 use derive_more::{Deref, From};
@@ -131,6 +132,8 @@ pub struct Match {
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct MatchCase {
     pub pattern: Box<Ast>,
+    /// The guard's condition, i.e., the expression after `if`.
+    pub condition: Option<Box<Ast>>,
     pub body: Vec<Ast>,
 }
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -147,9 +150,9 @@ pub struct AstString {
 pub enum AstError {
     ExpectedNameOrPatternInAssignment,
     ExpectedParameter,
-    FunctionMissesClosingCurlyBrace,
+    FunctionMissesClosingCurlyBrace(RecoveryHint),
     ListItemMissesComma,
-    ListMissesClosingParenthesis,
+    ListMissesClosingParenthesis(RecoveryHint),
     ListWithNonListItem,
     OrPatternIsMissingIdentifiers {
         identifier: String,
@@ -157,19 +160,45 @@ pub enum AstError {
         all_captures: Vec<cst::Id>,
     },
     ParenthesizedInPattern,
-    ParenthesizedMissesClosingParenthesis,
+    ParenthesizedMissesClosingParenthesis(RecoveryHint),
     PatternContainsInvalidExpression,
     PatternLiteralPartContainsInvalidExpression,
     PipeInPattern,
     StructKeyMissesColon,
-    StructMissesClosingBrace,
+    StructMissesClosingBrace(RecoveryHint),
     StructShorthandWithNotIdentifier,
     StructValueMissesComma,
     StructWithNonStructField,
-    TextInterpolationMissesClosingCurlyBraces,
-    TextMissesClosingQuote,
+    TextInterpolationMissesClosingCurlyBraces(RecoveryHint),
+    TextMissesClosingQuote(RecoveryHint),
     UnexpectedPunctuation,
 }
+impl AstError {
+    #[must_use]
+    pub const fn recovery_hint(&self) -> Option<&RecoveryHint> {
+        match self {
+            Self::FunctionMissesClosingCurlyBrace(hint)
+            | Self::ListMissesClosingParenthesis(hint)
+            | Self::ParenthesizedMissesClosingParenthesis(hint)
+            | Self::StructMissesClosingBrace(hint)
+            | Self::TextInterpolationMissesClosingCurlyBraces(hint)
+            | Self::TextMissesClosingQuote(hint) => Some(hint),
+            _ => None,
+        }
+    }
+}
+
+/// When the parser doesn't find a closing token, it inserts an error node
+/// instead and continues parsing. This records which token is missing and where
+/// it should go, so that tools can offer to insert it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RecoveryHint {
+    pub expected_token: String,
+    /// Directly after the code preceding the missing token. The error node is
+    /// usually placed after the whitespace and comments following that code,
+    /// which is not where a user would put the token.
+    pub insertion_offset: Offset,
+}
 
 pub trait FindAst {
     fn find(&self, id: &Id) -> Option<&Ast>;
@@ -248,7 +277,10 @@ impl FindAst for Match {
 }
 impl FindAst for MatchCase {
     fn find(&self, id: &Id) -> Option<&Ast> {
-        self.pattern.find(id).or_else(|| self.body.find(id))
+        self.pattern
+            .find(id)
+            .or_else(|| self.condition.as_ref().and_then(|it| it.find(id)))
+            .or_else(|| self.body.find(id))
     }
 }
 impl FindAst for OrPattern {
@@ -352,8 +384,15 @@ impl CollectErrors for Ast {
                 expression.collect_errors(errors);
                 cases.collect_errors(errors);
             }
-            AstKind::MatchCase(MatchCase { pattern, body }) => {
+            AstKind::MatchCase(MatchCase {
+                pattern,
+                condition,
+                body,
+            }) => {
                 pattern.collect_errors(errors);
+                if let Some(condition) = condition {
+                    condition.collect_errors(errors);
+                }
                 body.collect_errors(errors);
             }
             AstKind::OrPattern(OrPattern(patterns)) => {
@@ -541,6 +580,10 @@ impl ToRichIr for Match {
 impl ToRichIr for MatchCase {
     fn build_rich_ir(&self, builder: &mut RichIrBuilder) {
         self.pattern.build_rich_ir(builder);
+        if let Some(condition) = &self.condition {
+            builder.push(" if ", None, EnumSet::empty());
+            condition.build_rich_ir(builder);
+        }
         builder.push(" -> ", None, EnumSet::empty());
         builder.push_foldable(|builder| builder.push_children_multiline(&self.body));
     }
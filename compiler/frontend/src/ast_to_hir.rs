@@ -6,19 +6,22 @@ use crate::{
     builtin_functions::{self, BuiltinFunction},
     cst::{self, CstDb},
     cst_to_ast::CstToAst,
+    deprecation::deprecated_exports,
     error::{CompilerError, CompilerErrorPayload},
     hir::{
         self, Body, Expression, Function, FunctionKind, HirError, IdKey, Pattern,
         PatternIdentifierId,
     },
     id::IdGenerator,
-    module::{Module, Package},
+    language_version::{LanguageFeature, LanguageVersion},
+    module::{Module, ModuleKind, Package, UsePath},
     position::Offset,
     string_to_rcst::ModuleError,
     utils::AdjustCasingOfFirstLetter,
 };
-use itertools::Itertools;
-use rustc_hash::FxHashMap;
+use derive_more::Deref;
+use itertools::{Either, Itertools};
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::{collections::hash_map::Entry, mem, ops::Range, sync::Arc};
 
 #[salsa::query_group(AstToHirStorage)]
@@ -32,6 +35,17 @@ pub trait AstToHir: CstDb + CstToAst {
     #[salsa::transparent]
     fn hir_id_to_display_span(&self, id: &hir::Id) -> Option<Range<Offset>>;
 
+    /// Like [`AstToHir::hir_to_ast_id`], but synthesized IDs resolve to the
+    /// user syntax they were generated for (see [`SyntheticSpan`]). IDs that
+    /// aren't part of the HIR at all, such as the ones the MIR creates for
+    /// desugared needs and pattern matching, resolve via their parent.
+    #[salsa::transparent]
+    fn hir_to_origin_ast_id(&self, id: &hir::Id) -> Option<ast::Id>;
+    #[salsa::transparent]
+    fn hir_id_to_origin_span(&self, id: &hir::Id) -> Option<Range<Offset>>;
+    #[salsa::transparent]
+    fn hir_id_to_origin_display_span(&self, id: &hir::Id) -> Option<Range<Offset>>;
+
     #[salsa::transparent]
     fn ast_to_hir_ids(&self, id: &ast::Id) -> Vec<hir::Id>;
     #[salsa::transparent]
@@ -44,9 +58,40 @@ pub trait AstToHir: CstDb + CstToAst {
     fn cst_to_last_hir_id(&self, module: Module, id: cst::Id) -> Option<hir::Id>;
 
     fn hir(&self, module: Module) -> HirResult;
+
+    /// The statically known `use` calls in the module, together with the code
+    /// modules they refer to.
+    fn used_modules(&self, module: Module) -> Arc<Vec<(hir::Id, Module)>>;
+    /// The asset modules that the module uses statically, e.g., to rerun a
+    /// program when one of them changes.
+    fn used_assets(&self, module: Module) -> Arc<Vec<Module>>;
+
+    /// See [`crate::deprecation`].
+    fn deprecated_exports(&self, module: Module) -> Arc<FxHashMap<String, String>>;
+
+    /// The functions that the module exports, i.e., the ones that other modules
+    /// can call.
+    fn exported_functions(&self, module: Module) -> Arc<FxHashSet<hir::Id>>;
 }
 
-pub type HirResult = Result<(Arc<Body>, Arc<FxHashMap<hir::Id, ast::Id>>), ModuleError>;
+pub type HirResult = Result<(Arc<Body>, Arc<HirToAstIds>), ModuleError>;
+
+/// Maps HIR IDs to the AST IDs they were lowered from.
+///
+/// Synthesized expressions, e.g., the calls that text interpolations and
+/// struct accesses desugar to, don't have an AST ID of their own. Instead,
+/// they get a [`SyntheticSpan`].
+#[derive(Clone, Debug, Default, Deref, Eq, PartialEq)]
+pub struct HirToAstIds {
+    #[deref]
+    ids: FxHashMap<hir::Id, ast::Id>,
+    synthetic_spans: FxHashMap<hir::Id, SyntheticSpan>,
+}
+/// The user syntax that caused a synthesized HIR expression to be generated.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SyntheticSpan {
+    pub origin: ast::Id,
+}
 
 fn hir_to_ast_id(db: &dyn AstToHir, id: &hir::Id) -> Option<ast::Id> {
     let (_, hir_to_ast_id_mapping) = db.hir(id.module.clone()).ok()?;
@@ -63,6 +108,26 @@ fn hir_id_to_display_span(db: &dyn AstToHir, id: &hir::Id) -> Option<Range<Offse
     Some(db.find_cst(id.module.clone(), cst_id).display_span())
 }
 
+fn hir_to_origin_ast_id(db: &dyn AstToHir, id: &hir::Id) -> Option<ast::Id> {
+    let (_, hir_to_ast_ids) = db.hir(id.module.clone()).ok()?;
+    let mut id = id.clone();
+    loop {
+        if let Some(ast_id) = hir_to_ast_ids.ids.get(&id) {
+            return Some(ast_id.clone());
+        }
+        if let Some(SyntheticSpan { origin }) = hir_to_ast_ids.synthetic_spans.get(&id) {
+            return Some(origin.clone());
+        }
+        id = id.parent()?;
+    }
+}
+fn hir_id_to_origin_span(db: &dyn AstToHir, id: &hir::Id) -> Option<Range<Offset>> {
+    db.ast_id_to_span(&db.hir_to_origin_ast_id(id)?)
+}
+fn hir_id_to_origin_display_span(db: &dyn AstToHir, id: &hir::Id) -> Option<Range<Offset>> {
+    db.ast_id_to_display_span(&db.hir_to_origin_ast_id(id)?)
+}
+
 fn ast_to_hir_ids(db: &dyn AstToHir, id: &ast::Id) -> Vec<hir::Id> {
     if let Ok((_, hir_to_ast_id_mapping)) = db.hir(id.module.clone()) {
         hir_to_ast_id_mapping
@@ -87,28 +152,121 @@ fn cst_to_last_hir_id(db: &dyn AstToHir, module: Module, id: cst::Id) -> Option<
 }
 
 fn hir(db: &dyn AstToHir, module: Module) -> HirResult {
-    db.ast(module.clone()).map(|(ast, _)| {
+    db.desugared_ast(module.clone()).map(|ast| {
         let (body, id_mapping) = compile_top_level(db, module, &ast);
         (Arc::new(body), Arc::new(id_mapping))
     })
 }
 
-fn compile_top_level(
-    db: &dyn AstToHir,
-    module: Module,
-    ast: &[Ast],
-) -> (Body, FxHashMap<hir::Id, ast::Id>) {
+#[allow(clippy::needless_pass_by_value)]
+fn used_modules(db: &dyn AstToHir, module: Module) -> Arc<Vec<(hir::Id, Module)>> {
+    let Ok((hir, _)) = db.hir(module) else {
+        return Arc::default();
+    };
+    let mut used_modules = vec![];
+    collect_used_modules(&hir, &hir, ModuleKind::Code, &mut used_modules);
+    Arc::new(used_modules)
+}
+#[allow(clippy::needless_pass_by_value)]
+fn used_assets(db: &dyn AstToHir, module: Module) -> Arc<Vec<Module>> {
+    let Ok((hir, _)) = db.hir(module) else {
+        return Arc::default();
+    };
+    let mut used_assets = vec![];
+    collect_used_modules(&hir, &hir, ModuleKind::Asset, &mut used_assets);
+    Arc::new(
+        used_assets
+            .into_iter()
+            .map(|(_, asset)| asset)
+            .unique()
+            .collect(),
+    )
+}
+fn collect_used_modules(
+    root: &Body,
+    body: &Body,
+    kind: ModuleKind,
+    used_modules: &mut Vec<(hir::Id, Module)>,
+) {
+    for (id, expression) in &body.expressions {
+        match expression {
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                if let [path] = arguments.as_slice()
+                    && let Some(Expression::Function(Function {
+                        kind: FunctionKind::Use,
+                        ..
+                    })) = find_through_references(root, function)
+                    && let Some(Expression::Text(path)) = find_through_references(root, path)
+                    && let Ok(path) = UsePath::parse(path)
+                    && let Ok(used_module) = path.resolve_relative_to(id.module.clone())
+                    && used_module.kind == kind
+                {
+                    used_modules.push((id.clone(), used_module));
+                }
+            }
+            Expression::Match { cases, .. } => {
+                for case_body in cases.iter().flat_map(hir::MatchCase::bodies) {
+                    collect_used_modules(root, case_body, kind, used_modules);
+                }
+            }
+            Expression::Function(function) => {
+                collect_used_modules(root, &function.body, kind, used_modules);
+            }
+            _ => {}
+        }
+    }
+}
+fn find_through_references<'a>(root: &'a Body, id: &hir::Id) -> Option<&'a Expression> {
+    let mut expression = root.find(id)?;
+    while let Expression::Reference(target) = expression {
+        expression = root.find(target)?;
+    }
+    Some(expression)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn exported_functions(db: &dyn AstToHir, module: Module) -> Arc<FxHashSet<hir::Id>> {
+    let Ok((hir, _)) = db.hir(module) else {
+        return Arc::default();
+    };
+    // The exports struct is always the last expression.
+    let Some((_, Expression::Struct(exports))) = hir.expressions.iter().last() else {
+        return Arc::default();
+    };
+    let functions = exports
+        .values()
+        .filter_map(|value| {
+            let mut id = value;
+            loop {
+                match hir.find(id)? {
+                    Expression::Reference(target) => id = target,
+                    Expression::Function(_) => return Some(id.clone()),
+                    _ => return None,
+                }
+            }
+        })
+        .collect();
+    Arc::new(functions)
+}
+
+fn compile_top_level(db: &dyn AstToHir, module: Module, ast: &[Ast]) -> (Body, HirToAstIds) {
     let is_builtins_package = module.package == Package::builtins();
     let mut context = Context {
         module: module.clone(),
         id_mapping: FxHashMap::default(),
+        synthetic_spans: FxHashMap::default(),
+        origin: None,
         db,
         public_identifiers: FxHashMap::default(),
         body: Body::default(),
-        id_prefix: hir::Id::new(module, vec![]),
+        id_prefix: hir::Id::new(module.clone(), vec![]),
         identifiers: im::HashMap::new(),
         is_top_level: true,
         use_id: None,
+        language_version: db.language_version(module),
     };
 
     if is_builtins_package {
@@ -118,17 +276,24 @@ fn compile_top_level(
     context.compile(ast);
     context.generate_exports_struct();
 
-    let id_mapping = context
+    let ids = context
         .id_mapping
         .into_iter()
         .filter_map(|(key, value)| value.map(|value| (key, value)))
         .collect();
-    (context.body, id_mapping)
+    let hir_to_ast_ids = HirToAstIds {
+        ids,
+        synthetic_spans: context.synthetic_spans,
+    };
+    (context.body, hir_to_ast_ids)
 }
 
 struct Context<'a> {
     module: Module,
     id_mapping: FxHashMap<hir::Id, Option<ast::Id>>,
+    synthetic_spans: FxHashMap<hir::Id, SyntheticSpan>,
+    /// The innermost AST node that is currently being compiled.
+    origin: Option<ast::Id>,
     db: &'a dyn AstToHir,
     public_identifiers: FxHashMap<String, hir::Id>,
     body: Body,
@@ -136,6 +301,7 @@ struct Context<'a> {
     identifiers: im::HashMap<String, hir::Id>,
     is_top_level: bool,
     use_id: Option<hir::Id>,
+    language_version: LanguageVersion,
 }
 
 impl Context<'_> {
@@ -189,6 +355,12 @@ impl Context<'_> {
     }
 
     fn compile_single(&mut self, ast: &Ast) -> hir::Id {
+        let outer_origin = self.origin.replace(ast.id.clone());
+        let id = self.lower_single(ast);
+        self.origin = outer_origin;
+        id
+    }
+    fn lower_single(&mut self, ast: &Ast) -> hir::Id {
         match &ast.kind {
             AstKind::Int(Int(int)) => self.push(ast.id.clone(), Expression::Int(int.clone()), None),
             AstKind::Text(text) => self.lower_text(Some(ast.id.clone()), text),
@@ -361,21 +533,42 @@ impl Context<'_> {
                 let cases = cases
                     .iter()
                     .map(|case| match &case.kind {
-                        AstKind::MatchCase(MatchCase { box pattern, body }) => {
+                        AstKind::MatchCase(MatchCase {
+                            box pattern,
+                            condition,
+                            body,
+                        }) => {
                             let (pattern, pattern_identifiers) = self.lower_pattern(pattern);
 
+                            let condition = condition.as_ref().map(|condition| {
+                                let reset_state = self.start_scope();
+                                self.push_pattern_identifier_references(&pattern_identifiers);
+                                let feature = LanguageFeature::MatchCaseGuards;
+                                if self.language_version.supports(feature) {
+                                    self.compile_single(condition);
+                                } else {
+                                    self.push_error(
+                                        condition.id.clone(),
+                                        self.db.ast_id_to_display_span(&condition.id).unwrap(),
+                                        HirError::RequiresNewerLanguageVersion {
+                                            feature,
+                                            version: self.language_version,
+                                        },
+                                    );
+                                }
+                                self.end_scope(reset_state)
+                            });
+
                             let reset_state = self.start_scope();
-                            for (name, (ast_id, identifier_id)) in pattern_identifiers {
-                                self.push(
-                                    ast_id,
-                                    Expression::PatternIdentifierReference(identifier_id),
-                                    name.clone(),
-                                );
-                            }
+                            self.push_pattern_identifier_references(&pattern_identifiers);
                             self.compile(body.as_ref());
                             let body = self.end_scope(reset_state);
 
-                            (pattern, body)
+                            hir::MatchCase {
+                                pattern,
+                                condition,
+                                body,
+                            }
                         }
                         AstKind::Error { errors } => {
                             let pattern = Pattern::Error {
@@ -386,7 +579,11 @@ impl Context<'_> {
                             self.compile(&[]);
                             let body = self.end_scope(reset_state);
 
-                            (pattern, body)
+                            hir::MatchCase {
+                                pattern,
+                                condition: None,
+                                body,
+                            }
                         }
                         _ => unreachable!("Expected match case in match cases, got {case:?}."),
                     })
@@ -415,6 +612,27 @@ impl Context<'_> {
     }
 
     fn lower_text(&mut self, id: Option<ast::Id>, text: &Text) -> hir::Id {
+        // Adjacent literal parts (e.g., the lines of a multiline text) are
+        // folded into a single text. Texts without interpolations therefore
+        // become a single text expression that doesn't need any
+        // concatenation at runtime.
+        let mut parts: Vec<Either<String, &Ast>> = vec![];
+        for part in &text.0 {
+            if let AstKind::TextPart(TextPart(string)) = &part.kind {
+                if let Some(Either::Left(previous)) = parts.last_mut() {
+                    previous.push_str(&string.value);
+                } else {
+                    parts.push(Either::Left(string.value.clone()));
+                }
+            } else {
+                parts.push(Either::Right(part));
+            }
+        }
+        if parts.len() <= 1 && parts.first().map_or(true, Either::is_left) {
+            let value = parts.pop().map(Either::unwrap_left).unwrap_or_default();
+            return self.push(id, Expression::Text(value), None);
+        }
+
         let text_concatenate_function = self.push(
             None,
             Expression::Builtin(BuiltinFunction::TextConcatenate),
@@ -430,14 +648,16 @@ impl Context<'_> {
             None,
         );
 
-        let compiled_parts = text
-            .0
-            .iter()
+        let compiled_parts = parts
+            .into_iter()
             .map(|part| {
+                let part = match part {
+                    Either::Left(string) => {
+                        return self.push(None, Expression::Text(string), None);
+                    }
+                    Either::Right(part) => part,
+                };
                 let hir = self.compile_single(part);
-                if part.kind.is_text_part() {
-                    return hir;
-                }
 
                 // Convert the part to text if it is not already a text.
                 let type_of = self.push(
@@ -517,7 +737,7 @@ impl Context<'_> {
                     None,
                 )
             })
-            .unwrap_or_else(|| self.push(id, Expression::Text(String::new()), None))
+            .unwrap()
     }
 
     fn compile_function(
@@ -704,12 +924,26 @@ impl Context<'_> {
             .collect_vec()
     }
 
+    fn push_pattern_identifier_references(&mut self, pattern_identifiers: &PatternIdentifierIds) {
+        for (name, (ast_id, identifier_id)) in pattern_identifiers
+            .iter()
+            .sorted_by_key(|(_, (_, identifier_id))| identifier_id.0)
+        {
+            self.push(
+                ast_id.clone(),
+                Expression::PatternIdentifierReference(*identifier_id),
+                name.clone(),
+            );
+        }
+    }
+
     fn lower_pattern(&mut self, ast: &Ast) -> (Pattern, PatternIdentifierIds) {
         let mut context = PatternContext {
             db: self.db,
             module: self.module.clone(),
             identifier_id_generator: IdGenerator::default(),
             identifier_ids: FxHashMap::default(),
+            language_version: self.language_version,
         };
         let pattern = context.compile_pattern(ast);
         (pattern, context.identifier_ids)
@@ -779,7 +1013,12 @@ impl Context<'_> {
             );
             let id = self.id_prefix.child(last_part);
             if let Entry::Vacant(entry) = self.id_mapping.entry(id.clone()) {
-                entry.insert(ast_id.into());
+                let ast_id = entry.insert(ast_id.into());
+                if ast_id.is_none()
+                    && let Some(origin) = self.origin.clone()
+                {
+                    self.synthetic_spans.insert(id.clone(), SyntheticSpan { origin });
+                }
                 return id;
             }
         }
@@ -847,7 +1086,7 @@ impl Context<'_> {
         // ]
 
         let mut exports = FxHashMap::default();
-        for (name, id) in self.public_identifiers.clone() {
+        for (name, id) in self.public_identifiers.clone().into_iter().sorted() {
             exports.insert(
                 self.push(
                     None,
@@ -870,6 +1109,7 @@ struct PatternContext<'a> {
     module: Module,
     identifier_id_generator: IdGenerator<PatternIdentifierId>,
     identifier_ids: PatternIdentifierIds,
+    language_version: LanguageVersion,
 }
 impl<'a> PatternContext<'a> {
     fn compile_pattern(&mut self, ast: &Ast) -> Pattern {
@@ -884,6 +1124,20 @@ impl<'a> PatternContext<'a> {
                     .join(""),
             ),
             AstKind::TextPart(_) => unreachable!("TextPart should not occur in AST patterns."),
+            AstKind::Identifier(Identifier(name)) if name.value == "_" => {
+                let feature = LanguageFeature::WildcardPatterns;
+                if self.language_version.supports(feature) {
+                    Pattern::Wildcard
+                } else {
+                    self.error(
+                        ast,
+                        HirError::RequiresNewerLanguageVersion {
+                            feature,
+                            version: self.language_version,
+                        },
+                    )
+                }
+            }
             AstKind::Identifier(Identifier(name)) => {
                 let (_, pattern_id) = self
                     .identifier_ids
@@ -919,7 +1173,7 @@ impl<'a> PatternContext<'a> {
                                     "Expected identifier in struct shorthand, got {value:?}."
                                 ),
                             },
-                            |key| self.compile_pattern(key),
+                            |key| self.compile_struct_key_pattern(key),
                         );
                         (key, self.compile_pattern(value))
                     })
@@ -965,6 +1219,23 @@ impl<'a> PatternContext<'a> {
         }
     }
 
+    /// Struct keys are compared by value, so they can't contain a wildcard or
+    /// capture identifiers (e.g., `[Foo _: value]`).
+    fn compile_struct_key_pattern(&mut self, ast: &Ast) -> Pattern {
+        fn find_identifier(ast: &Ast) -> Option<&Ast> {
+            match &ast.kind {
+                AstKind::Identifier(_) => Some(ast),
+                AstKind::Call(call) => call.arguments.iter().find_map(find_identifier),
+                _ => None,
+            }
+        }
+
+        if let Some(identifier) = find_identifier(ast) {
+            return self.error(identifier, HirError::PatternStructKeyIsNotConstant);
+        }
+        self.compile_pattern(ast)
+    }
+
     fn error(&self, ast: &Ast, error: HirError) -> Pattern {
         Pattern::Error {
             errors: vec![CompilerError {
@@ -975,3 +1246,178 @@ impl<'a> PatternContext<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hir::{CollectErrors, MatchCase},
+        test_database::Database,
+    };
+    use num_bigint::BigUint;
+
+    fn compile(source: &str) -> (Arc<Body>, Vec<CompilerError>) {
+        let (db, module) = Database::with_main_module(source);
+        let (body, _) = db.hir(module).unwrap();
+        let mut errors = vec![];
+        body.collect_errors(&mut errors);
+        (body, errors)
+    }
+    fn match_cases(body: &Body) -> &[MatchCase] {
+        body.expressions
+            .values()
+            .find_map(|expression| match expression {
+                Expression::Match { cases, .. } => Some(cases.as_slice()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn guards_can_use_captured_identifiers() {
+        let (body, errors) = compile("foo = Foo 1\nbar = foo %\n  Foo x if x -> x\n  _ -> 0\n");
+        assert_eq!(errors, vec![]);
+
+        let [guarded, wildcard] = match_cases(&body) else {
+            panic!("Expected two cases.");
+        };
+        let Pattern::Tag {
+            value: Some(box Pattern::NewIdentifier(x)),
+            ..
+        } = guarded.pattern
+        else {
+            panic!("Unexpected pattern: {:?}", guarded.pattern);
+        };
+        let condition = guarded.condition.as_ref().unwrap();
+        let (first_id, first_expression) = condition.expressions.iter().next().unwrap();
+        assert_eq!(first_expression, &Expression::PatternIdentifierReference(x));
+        assert_eq!(condition.identifiers[first_id], "x");
+        assert!(!guarded.is_catch_all());
+
+        assert_eq!(wildcard.pattern, Pattern::Wildcard);
+        assert_eq!(wildcard.condition, None);
+        assert!(wildcard.is_catch_all());
+    }
+
+    #[test]
+    fn wildcards_in_lists_and_tags_capture_nothing() {
+        let (body, errors) = compile("foo = (1, Foo 2)\nbar = foo %\n  (_, Foo _) -> 1\n");
+        assert_eq!(errors, vec![]);
+
+        let [case] = match_cases(&body) else {
+            panic!("Expected one case.");
+        };
+        assert_eq!(
+            case.pattern,
+            Pattern::List(vec![
+                Pattern::Wildcard,
+                Pattern::Tag {
+                    symbol: "Foo".to_string(),
+                    value: Some(Box::new(Pattern::Wildcard)),
+                },
+            ]),
+        );
+        assert_eq!(case.pattern.captured_identifiers(), vec![]);
+    }
+
+    #[test]
+    fn struct_keys_in_patterns_must_be_constant() {
+        for (source, identifier) in [
+            ("foo = [Foo: 1]\nbar = foo %\n  [Foo _: x] -> x\n", "_"),
+            ("foo = [Foo: 1]\nbar = foo %\n  [Foo y: x] -> x\n", "y"),
+        ] {
+            let (_, errors) = compile(source);
+            let [error] = errors.as_slice() else {
+                panic!("Expected one error for `{source}`, got {errors:?}.");
+            };
+            assert_eq!(
+                error.payload,
+                CompilerErrorPayload::Hir(HirError::PatternStructKeyIsNotConstant),
+            );
+            assert_eq!(&source[*error.span.start..*error.span.end], identifier);
+        }
+    }
+
+    #[test]
+    fn int_literals_are_lowered_to_their_values() {
+        let (body, errors) = compile("a = 31\nb = 0031\nc = 0x1f\nd = 0X1F\ne = 0b11111\n");
+        assert_eq!(errors, vec![]);
+
+        let ints = body
+            .expressions
+            .values()
+            .filter_map(|expression| match expression {
+                Expression::Int(int) => Some(int.clone()),
+                _ => None,
+            })
+            .collect_vec();
+        assert_eq!(ints, vec![BigUint::from(31u8); 5]);
+    }
+
+    #[test]
+    fn adjacent_literal_text_parts_are_folded() {
+        let (body, errors) = compile("foo = \"\n  Hello,\n  world!\n  \"\n");
+        assert_eq!(errors, vec![]);
+
+        let texts = body
+            .expressions
+            .values()
+            .filter_map(|expression| match expression {
+                Expression::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect_vec();
+        assert_eq!(texts, vec!["Hello,\nworld!\n"]);
+        assert!(!body
+            .expressions
+            .values()
+            .any(|it| matches!(it, Expression::Builtin(BuiltinFunction::TextConcatenate))));
+    }
+
+    #[test]
+    fn synthesized_expressions_resolve_to_their_origin() {
+        let source = "foo = [Bar: 1]\nx = foo.bar\n";
+        let (db, module) = Database::with_main_module(source);
+        let (body, _) = db.hir(module).unwrap();
+        let origin_text = |id: &hir::Id| {
+            let span = db.hir_id_to_origin_span(id).unwrap();
+            &source[*span.start..*span.end]
+        };
+
+        let struct_get = body
+            .expressions
+            .iter()
+            .find_map(|(id, expression)| {
+                matches!(expression, Expression::Builtin(BuiltinFunction::StructGet)).then_some(id)
+            })
+            .unwrap();
+        assert_eq!(db.hir_to_ast_id(struct_get), None);
+        assert_eq!(origin_text(struct_get), "foo.bar");
+
+        // IDs that the MIR creates below HIR expressions resolve via their
+        // parent.
+        let one = body
+            .expressions
+            .iter()
+            .find_map(|(id, expression)| matches!(expression, Expression::Int(_)).then_some(id))
+            .unwrap();
+        assert_eq!(db.hir_to_ast_id(&one.child("mir")), None);
+        assert_eq!(origin_text(&one.child("mir")), "1");
+    }
+
+    #[test]
+    fn only_exported_functions_are_public() {
+        let (db, module) = Database::with_main_module(
+            "foo a := a\nbar a = a\nalsoFoo := foo\nbaz := 1\nqux := { a -> bar a }\n",
+        );
+        let exported = db
+            .exported_functions(module)
+            .iter()
+            .map(hir::Id::function_name)
+            .collect_vec();
+        // `qux` is an anonymous function.
+        assert_eq!(exported.len(), 2);
+        assert!(exported.contains(&"foo".to_string()));
+        assert!(!exported.contains(&"bar".to_string()));
+    }
+}
@@ -1,9 +1,14 @@
 use crate::{
+    comment::doc_comment_lines,
+    hir::{self, HirDb, IdKey},
     impl_display_via_richir,
+    module::Module,
     rich_ir::{RichIrBuilder, ToRichIr, TokenModifier, TokenType},
 };
 use enumset::EnumSet;
+use itertools::Itertools;
 use lazy_static::lazy_static;
+use std::sync::Arc;
 use strum::IntoEnumIterator;
 use strum_macros::{AsRefStr, EnumIter};
 
@@ -22,8 +27,8 @@ use strum_macros::{AsRefStr, EnumIter};
 /// corresponding functions from the `Builtins` package, all preconditions are
 /// guaranteed to be true and built-ins can ignore the responsibility parameter.
 ///
-/// See the source code of the `Builtins` package for documentation on what
-/// these functions do.
+/// For documentation on what these functions do, see
+/// [`HirDb::builtin_function_documentation`].
 #[derive(AsRefStr, Clone, Copy, Debug, EnumIter, Eq, Hash, PartialEq)]
 #[strum(serialize_all = "snake_case")]
 pub enum BuiltinFunction {
@@ -45,10 +50,15 @@ pub enum BuiltinFunction {
     IntShiftLeft,
     IntShiftRight,
     IntSubtract,
+    IteratorFoldLeft,
+    IteratorNext,
     ListFilled,
+    ListFilter,
+    ListFoldLeft,
     ListGet,
     ListInsert,
     ListLength,
+    ListMap,
     ListRemoveAt,
     ListReplace,
     Print,
@@ -70,6 +80,7 @@ pub enum BuiltinFunction {
     TextTrimEnd,
     TextTrimStart,
     ToDebugText,
+    ToDebugTextWithLimits,
     TypeOf,
 }
 lazy_static! {
@@ -98,10 +109,15 @@ impl BuiltinFunction {
             Self::IntShiftLeft => true,
             Self::IntShiftRight => true,
             Self::IntSubtract => true,
+            Self::IteratorFoldLeft => false,
+            Self::IteratorNext => false,
             Self::ListFilled => true,
+            Self::ListFilter => false,
+            Self::ListFoldLeft => false,
             Self::ListGet => true,
             Self::ListInsert => true,
             Self::ListLength => true,
+            Self::ListMap => false,
             Self::ListRemoveAt => true,
             Self::ListReplace => true,
             Self::Print => false,
@@ -123,6 +139,7 @@ impl BuiltinFunction {
             Self::TextTrimEnd => true,
             Self::TextTrimStart => true,
             Self::ToDebugText => true,
+            Self::ToDebugTextWithLimits => true,
             Self::TypeOf => true,
         }
     }
@@ -148,10 +165,15 @@ impl BuiltinFunction {
             Self::IntShiftLeft => 2,
             Self::IntShiftRight => 2,
             Self::IntSubtract => 2,
+            Self::IteratorFoldLeft => 3,
+            Self::IteratorNext => 1,
             Self::ListFilled => 2,
+            Self::ListFilter => 2,
+            Self::ListFoldLeft => 3,
             Self::ListGet => 2,
             Self::ListInsert => 3,
             Self::ListLength => 1,
+            Self::ListMap => 2,
             Self::ListRemoveAt => 2,
             Self::ListReplace => 3,
             Self::Print => 1,
@@ -173,9 +195,375 @@ impl BuiltinFunction {
             Self::TextTrimEnd => 1,
             Self::TextTrimStart => 1,
             Self::ToDebugText => 1,
+            Self::ToDebugTextWithLimits => 4,
             Self::TypeOf => 1,
         }
     }
+
+    /// How Candy code refers to this builtin, e.g., `intAdd` for `✨.intAdd`.
+    #[must_use]
+    pub fn name(&self) -> String {
+        let name = format!("{self:?}");
+        let mut characters = name.chars();
+        let first = characters.next().unwrap();
+        first.to_lowercase().chain(characters).collect()
+    }
+
+    /// A short description of what this builtin does.
+    #[must_use]
+    pub const fn summary(&self) -> &'static str {
+        match self {
+            Self::Equals => "Returns whether both values are equal.",
+            Self::FunctionRun => {
+                "Calls the function without arguments and returns its return value."
+            }
+            Self::GetArgumentCount => "Returns the number of arguments the function requires.",
+            Self::IfElse => {
+                "Runs `then` if the condition is `True`, otherwise `else`, and returns the result."
+            }
+            Self::IntAdd => "Returns `a` + `b`.",
+            Self::IntBitLength => {
+                "Returns the number of bits necessary to represent the int, ignoring the sign."
+            }
+            Self::IntBitwiseAnd => "Returns the bitwise \"and\" of `a` and `b`.",
+            Self::IntBitwiseOr => "Returns the bitwise \"or\" of `a` and `b`.",
+            Self::IntBitwiseXor => "Returns the bitwise \"xor\" of `a` and `b`.",
+            Self::IntCompareTo => {
+                "Returns `Less`, `Equal`, or `Greater` depending on how `a` relates to `b`."
+            }
+            Self::IntDivideTruncating => "Returns `dividend` ÷ `divisor`, rounded towards zero.",
+            Self::IntModulo => "Returns `dividend` % `divisor`, which is never negative.",
+            Self::IntMultiply => "Returns `factorA` × `factorB`.",
+            Self::IntParse => {
+                "Parses a decimal int, returning `Ok` and the int or `Error NotAnInteger`."
+            }
+            Self::IntRemainder => {
+                "Returns the remainder of `dividend` ÷ `divisor` with the sign of the `dividend`."
+            }
+            Self::IntShiftLeft => "Returns `value` << `amount`.",
+            Self::IntShiftRight => "Returns `value` >> `amount`.",
+            Self::IntSubtract => "Returns `minuend` - `subtrahend`.",
+            Self::IteratorFoldLeft => {
+                "Combines the iterator's items from left to right, starting with `initialValue`."
+            }
+            Self::IteratorNext => {
+                "Returns `Ok [Item, Rest]` with the next item and remaining items, or `Error`."
+            }
+            Self::ListFilled => "Returns a list of `length` items, each of which is `item`.",
+            Self::ListFilter => "Returns a list of the items for which `tester` returns `True`.",
+            Self::ListFoldLeft => {
+                "Combines the list's items from left to right, starting with `initialValue`."
+            }
+            Self::ListGet => "Returns the item at the zero-based index.",
+            Self::ListInsert => "Returns a new list with the item inserted at the index.",
+            Self::ListLength => "Returns the number of items in the list.",
+            Self::ListMap => "Returns a list of the results of calling `mapper` with each item.",
+            Self::ListRemoveAt => "Returns a new list without the item at the index.",
+            Self::ListReplace => "Returns a new list with the item at the index replaced.",
+            Self::Print => "Prints the text and returns `Nothing`.",
+            Self::StructGet => "Returns the value that's saved in the struct for the key.",
+            Self::StructGetKeys => {
+                "Returns a list of all keys of the struct in an unspecified order."
+            }
+            Self::StructHasKey => "Returns whether the struct contains the key.",
+            Self::TagGetValue => "Returns the tag's value.",
+            Self::TagHasValue => "Returns whether the tag has a value.",
+            Self::TagWithoutValue => "Returns the tag without its value.",
+            Self::TextCharacters => {
+                "Returns a list of the text's characters (Unicode grapheme clusters)."
+            }
+            Self::TextConcatenate => "Returns a concatenation of both texts.",
+            Self::TextContains => "Returns whether the text contains the pattern literally.",
+            Self::TextEndsWith => "Returns whether the text ends with the suffix.",
+            Self::TextFromUtf8 => {
+                "Decodes UTF-8 bytes, returning `Ok` and the text or `Error NotUtf8`."
+            }
+            Self::TextGetRange => "Returns the characters of the text in the given range.",
+            Self::TextIsEmpty => "Returns whether the text is empty.",
+            Self::TextLength => {
+                "Returns the number of characters (Unicode grapheme clusters) in the text."
+            }
+            Self::TextStartsWith => "Returns whether the text starts with the prefix.",
+            Self::TextTrimEnd => "Returns the text without whitespace at the end.",
+            Self::TextTrimStart => "Returns the text without whitespace at the start.",
+            Self::ToDebugText => "Returns a textual representation of the value.",
+            Self::ToDebugTextWithLimits => "Like `toDebugText`, but shortens huge values.",
+            Self::TypeOf => {
+                "Returns the value's type: `Function`, `Int`, `List`, `Struct`, `Text`, or `Tag`."
+            }
+        }
+    }
+
+    /// The parameters in the order in which they are passed. These are the same
+    /// as the ones of the corresponding function in the `Builtins` package.
+    #[must_use]
+    pub const fn parameters(&self) -> &'static [BuiltinParameter] {
+        // A macro instead of a `const fn` so that the arrays get promoted to
+        // `'static` constants.
+        macro_rules! param {
+            ($name:literal, $description:literal $(,)?) => {
+                BuiltinParameter {
+                    name: $name,
+                    description: $description,
+                }
+            };
+        }
+
+        match self {
+            Self::Equals => &[param!("a", "Any value."), param!("b", "Any value.")],
+            Self::FunctionRun => &[param!("function", "A function without parameters.")],
+            Self::GetArgumentCount => &[param!("function", "Any function.")],
+            Self::IfElse => &[
+                param!("condition", "`True` or `False`."),
+                param!("then", "A function without parameters."),
+                param!("else", "A function without parameters."),
+            ],
+            Self::IntAdd => &[param!("a", "An int."), param!("b", "An int.")],
+            Self::IntBitLength => &[param!("value", "An int.")],
+            Self::IntBitwiseAnd => &[param!("a", "An int."), param!("b", "An int.")],
+            Self::IntBitwiseOr => &[param!("a", "An int."), param!("b", "An int.")],
+            Self::IntBitwiseXor => &[param!("a", "An int."), param!("b", "An int.")],
+            Self::IntCompareTo => &[param!("a", "An int."), param!("b", "An int.")],
+            Self::IntDivideTruncating => &[
+                param!("dividend", "An int."),
+                param!("divisor", "A non-zero int."),
+            ],
+            Self::IntModulo => &[
+                param!("dividend", "An int."),
+                param!("divisor", "A non-zero int."),
+            ],
+            Self::IntMultiply => &[param!("factorA", "An int."), param!("factorB", "An int.")],
+            Self::IntParse => &[param!("text", "The text to parse.")],
+            Self::IntRemainder => &[
+                param!("dividend", "An int."),
+                param!("divisor", "A non-zero int."),
+            ],
+            Self::IntShiftLeft => &[
+                param!("value", "An int."),
+                param!("amount", "A non-negative int."),
+            ],
+            Self::IntShiftRight => &[
+                param!("value", "An int."),
+                param!("amount", "A non-negative int."),
+            ],
+            Self::IntSubtract => &[
+                param!("minuend", "An int."),
+                param!("subtrahend", "An int."),
+            ],
+            Self::IteratorFoldLeft => &[
+                param!(
+                    "iterator",
+                    "A function without parameters returning `Ok [Item, Rest]` or `Error`.",
+                ),
+                param!("initialValue", "The value to combine with the first item."),
+                param!(
+                    "combine",
+                    "A function taking the value so far and the next item.",
+                ),
+            ],
+            Self::IteratorNext => &[param!(
+                "iterator",
+                "A function without parameters returning `Ok [Item, Rest]` or `Error`.",
+            )],
+            Self::ListFilled => &[
+                param!("length", "A non-negative int."),
+                param!("item", "Any value."),
+            ],
+            Self::ListFilter => &[
+                param!("list", "A list."),
+                param!(
+                    "tester",
+                    "A function taking an item and returning `True` or `False`.",
+                ),
+            ],
+            Self::ListFoldLeft => &[
+                param!("list", "A list."),
+                param!("initialValue", "The value to combine with the first item."),
+                param!(
+                    "combine",
+                    "A function taking the value so far and the next item.",
+                ),
+            ],
+            Self::ListGet => &[
+                param!("list", "A list."),
+                param!("index", "An int in the range of the list's indices."),
+            ],
+            Self::ListInsert => &[
+                param!("list", "A list."),
+                param!(
+                    "index",
+                    "An int between zero and the list's length, inclusive.",
+                ),
+                param!("item", "Any value."),
+            ],
+            Self::ListLength => &[param!("list", "A list.")],
+            Self::ListMap => &[
+                param!("list", "A list."),
+                param!("mapper", "A function taking an item."),
+            ],
+            Self::ListRemoveAt => &[
+                param!("list", "A list."),
+                param!("index", "An int in the range of the list's indices."),
+            ],
+            Self::ListReplace => &[
+                param!("list", "A list."),
+                param!("index", "An int in the range of the list's indices."),
+                param!("newItem", "Any value."),
+            ],
+            Self::Print => &[param!("message", "A text.")],
+            Self::StructGet => &[
+                param!("struct", "A struct."),
+                param!("key", "A key of the struct."),
+            ],
+            Self::StructGetKeys => &[param!("struct", "A struct.")],
+            Self::StructHasKey => &[param!("struct", "A struct."), param!("key", "Any value.")],
+            Self::TagGetValue => &[param!("tag", "A tag with a value.")],
+            Self::TagHasValue => &[param!("tag", "A tag.")],
+            Self::TagWithoutValue => &[param!("tag", "A tag.")],
+            Self::TextCharacters => &[param!("text", "A text.")],
+            Self::TextConcatenate => &[param!("a", "A text."), param!("b", "A text.")],
+            Self::TextContains => &[
+                param!("text", "A text."),
+                param!("pattern", "The text to look for."),
+            ],
+            Self::TextEndsWith => &[param!("text", "A text."), param!("suffix", "A text.")],
+            Self::TextFromUtf8 => &[param!(
+                "bytes",
+                "A list of ints between 0 and 255, inclusive.",
+            )],
+            Self::TextGetRange => &[
+                param!("text", "A text."),
+                param!(
+                    "startInclusive",
+                    "The index of the first character to include.",
+                ),
+                param!(
+                    "endExclusive",
+                    "The index after the last character to include.",
+                ),
+            ],
+            Self::TextIsEmpty => &[param!("text", "A text.")],
+            Self::TextLength => &[param!("text", "A text.")],
+            Self::TextStartsWith => &[param!("text", "A text."), param!("prefix", "A text.")],
+            Self::TextTrimEnd => &[param!("text", "A text.")],
+            Self::TextTrimStart => &[param!("text", "A text.")],
+            Self::ToDebugText => &[param!("value", "Any value.")],
+            Self::ToDebugTextWithLimits => &[
+                param!("value", "Any value."),
+                param!(
+                    "maxDepth",
+                    "How deep nested values are shown, or `Unlimited`.",
+                ),
+                param!(
+                    "maxItems",
+                    "How many items of lists and structs are shown, or `Unlimited`.",
+                ),
+                param!(
+                    "maxLength",
+                    "The maximum length of the result, or `Unlimited`.",
+                ),
+            ],
+            Self::TypeOf => &[param!("value", "Any value.")],
+        }
+    }
+
+    /// The indices of parameters that are used as an index or length. If they
+    /// are ints, they have to be non-negative and fit in a `usize`.
+    #[must_use]
+    pub const fn index_parameters(&self) -> &'static [usize] {
+        match self {
+            Self::ListFilled => &[0],
+            Self::ListGet | Self::ListInsert | Self::ListRemoveAt | Self::ListReplace => &[1],
+            Self::TextGetRange => &[1, 2],
+            Self::ToDebugTextWithLimits => &[1, 2, 3],
+            _ => &[],
+        }
+    }
+}
+
+/// A parameter of a builtin function (see [`BuiltinFunction::parameters`]).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BuiltinParameter {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// What tools like the language server show about a builtin function.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BuiltinFunctionDocumentation {
+    pub builtin_function: BuiltinFunction,
+    pub name: String,
+    pub parameters: &'static [BuiltinParameter],
+    pub summary: &'static str,
+    pub is_pure: bool,
+    /// The doc comment of the function with the same name in the `Builtins`
+    /// package. It contains details and examples.
+    ///
+    /// This is `None` if the package isn't available.
+    pub details: Option<Vec<String>>,
+}
+impl BuiltinFunctionDocumentation {
+    /// How the builtin is called, e.g., `✨.intAdd a b`.
+    #[must_use]
+    pub fn signature(&self) -> String {
+        let mut signature = format!("✨.{}", self.name);
+        for parameter in self.parameters {
+            signature.push(' ');
+            signature.push_str(parameter.name);
+        }
+        signature
+    }
+
+    #[must_use]
+    pub fn to_markdown(&self) -> String {
+        let mut sections = vec![
+            format!("```candy\n{}\n```", self.signature()),
+            self.summary.to_string(),
+        ];
+        if !self.parameters.is_empty() {
+            sections.push(
+                self.parameters
+                    .iter()
+                    .map(|it| format!("- `{}`: {}", it.name, it.description))
+                    .join("\n"),
+            );
+        }
+        sections.push(if self.is_pure {
+            "This builtin is pure.".to_string()
+        } else {
+            "This builtin is not pure: It may call functions passed to it or have side effects."
+                .to_string()
+        });
+        if let Some(details) = &self.details
+            && !details.is_empty()
+        {
+            sections.push(details.iter().join("\n"));
+        }
+        sections.join("\n\n")
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn builtin_function_documentation(
+    db: &dyn HirDb,
+    builtin_function: BuiltinFunction,
+) -> Arc<BuiltinFunctionDocumentation> {
+    let name = builtin_function.name();
+    let wrapper_id = hir::Id::new(
+        Module::from_package_name("Builtins".to_string()),
+        vec![IdKey::Named {
+            name: name.clone(),
+            disambiguator: 0,
+        }],
+    );
+    Arc::new(BuiltinFunctionDocumentation {
+        builtin_function,
+        name,
+        parameters: builtin_function.parameters(),
+        summary: builtin_function.summary(),
+        is_pure: builtin_function.is_pure(),
+        details: doc_comment_lines(db, &wrapper_id),
+    })
 }
 
 impl_display_via_richir!(BuiltinFunction);
@@ -189,3 +577,19 @@ impl ToRichIr for BuiltinFunction {
         builder.push_reference(*self, range);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::VALUES;
+
+    #[test]
+    fn parameters_match_num_parameters() {
+        for builtin_function in VALUES.iter() {
+            assert_eq!(
+                builtin_function.parameters().len(),
+                builtin_function.num_parameters(),
+                "{builtin_function:?} has the wrong number of documented parameters.",
+            );
+        }
+    }
+}
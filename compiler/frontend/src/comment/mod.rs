@@ -1,2 +1,75 @@
+use crate::{
+    ast_to_hir::AstToHir,
+    cst::{CstDb, CstKind},
+    hir,
+};
+use itertools::Itertools;
+
 pub mod rcst;
 pub mod string_to_rcst;
+
+/// Returns the lines of the doc comment of a module or an assignment without
+/// the leading `# `.
+///
+/// A module's doc comment is at the start of the file. An assignment's doc
+/// comment follows directly after the assignment sign:
+///
+/// ```candy
+/// foo a :=
+///   # Does something with `a`.
+///   a
+/// ```
+///
+/// Returns `None` if the ID doesn't belong to a module or an assignment.
+pub fn doc_comment_lines<Db: CstDb + AstToHir + ?Sized>(
+    db: &Db,
+    id: &hir::Id,
+) -> Option<Vec<String>> {
+    let comments_and_newlines = if id.is_root() {
+        db.cst(id.module.clone())
+            .ok()?
+            .iter()
+            .take_while(|it| {
+                matches!(
+                    it.kind,
+                    CstKind::Whitespace(_) | CstKind::Newline(_) | CstKind::Comment { .. }
+                )
+            })
+            .cloned()
+            .collect_vec()
+    } else {
+        let cst_id = db.hir_to_cst_id(id)?;
+        match db.find_cst(id.module.clone(), cst_id).kind {
+            CstKind::Assignment {
+                box assignment_sign,
+                body,
+                ..
+            } => {
+                let mut trivia = match assignment_sign.kind {
+                    CstKind::TrailingWhitespace { whitespace, .. } => whitespace,
+                    _ => vec![],
+                };
+                // In assignments without parameters, the comment is part of the
+                // body.
+                trivia.extend(body.into_iter().take_while(|it| {
+                    matches!(
+                        it.kind,
+                        CstKind::Whitespace(_) | CstKind::Newline(_) | CstKind::Comment { .. }
+                    )
+                }));
+                trivia
+            }
+            _ => return None,
+        }
+    };
+    let lines = comments_and_newlines
+        .iter()
+        .filter_map(|it| match &it.kind {
+            CstKind::Comment { comment, .. } => {
+                Some(comment.strip_prefix(' ').unwrap_or(comment).to_string())
+            }
+            _ => None,
+        })
+        .collect();
+    Some(lines)
+}
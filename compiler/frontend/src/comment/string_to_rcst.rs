@@ -1,6 +1,6 @@
-use super::rcst::Rcst;
+use super::{doc_comment_lines, rcst::Rcst};
 use crate::{
-    cst::{self, CstDb},
+    cst::CstDb,
     hir::{self, HirDb},
 };
 use itertools::Itertools;
@@ -11,46 +11,12 @@ pub trait CommentStringToRcst: CstDb + HirDb {
     fn comment_rcst(&self, id: hir::Id) -> Arc<Vec<Rcst>>;
 }
 
+#[allow(clippy::needless_pass_by_value)]
 fn comment_rcst(db: &dyn CommentStringToRcst, id: hir::Id) -> Arc<Vec<Rcst>> {
-    let comments_and_newlines = if id.is_root() {
-        db.cst(id.module)
-            .unwrap()
-            .iter()
-            .take_while(|it| {
-                matches!(
-                    it.kind,
-                    cst::CstKind::Whitespace(_)
-                        | cst::CstKind::Newline(_)
-                        | cst::CstKind::Comment { .. }
-                )
-            })
-            .cloned()
-            .collect_vec()
-    } else {
-        let cst_id = db.hir_to_cst_id(&id).unwrap();
-        match db.find_cst(id.module, cst_id).kind {
-            cst::CstKind::Assignment {
-                box assignment_sign,
-                ..
-            } => match assignment_sign.kind {
-                cst::CstKind::TrailingWhitespace { whitespace, .. } => whitespace,
-                _ => vec![],
-            },
-            _ => panic!(
-                "Tried to get the comment RCST for something other than a module or assignment."
-            ),
-        }
-    };
-    let comment_lines = comments_and_newlines
-        .iter()
-        .filter_map(|it| match &it.kind {
-            cst::CstKind::Comment { comment, .. } => {
-                Some(comment.strip_prefix(' ').unwrap_or(comment))
-            }
-            cst::CstKind::Newline(_) => None,
-            _ => unreachable!(),
-        })
-        .collect_vec();
+    let comment_lines = doc_comment_lines(db, &id).unwrap_or_else(|| {
+        panic!("Tried to get the comment RCST for something other than a module or assignment.")
+    });
+    let comment_lines = comment_lines.iter().map(String::as_str).collect_vec();
 
     let (remaining, rcsts) = parse::blocks(comment_lines, 0).unwrap();
     assert!(remaining.is_empty());
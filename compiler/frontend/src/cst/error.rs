@@ -6,9 +6,11 @@ pub enum CstError {
     IntContainsNonDigits,
     ListItemMissesValue,
     ListNotClosed,
+    MatchCaseGuardMissesCondition,
     MatchCaseMissesArrow,
     MatchCaseMissesBody,
     MatchMissesCases,
+    NestingTooDeep,
     OpeningParenthesisMissesExpression,
     OrPatternMissesRight,
     ParenthesisNotClosed,
@@ -31,6 +31,7 @@ impl<D> IsMultiline for CstKind<D> {
             Self::DoubleQuote => false,
             Self::Percent => false,
             Self::Octothorpe => false,
+            Self::If => false,
             Self::Whitespace(_) => false,
             Self::Newline(_) => true,
             Self::Comment { .. } => false,
@@ -107,9 +108,17 @@ impl<D> IsMultiline for CstKind<D> {
             } => expression.is_multiline() || percent.is_multiline() || cases.is_multiline(),
             Self::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
-            } => pattern.is_multiline() || arrow.is_multiline() || body.is_multiline(),
+            } => {
+                pattern.is_multiline()
+                    || guard.as_deref().map_or(false, |(keyword, condition)| {
+                        keyword.is_multiline() || condition.is_multiline()
+                    })
+                    || arrow.is_multiline()
+                    || body.is_multiline()
+            }
             Self::Function {
                 opening_curly_brace,
                 parameters_and_arrow,
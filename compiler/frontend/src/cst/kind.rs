@@ -22,6 +22,7 @@ pub enum CstKind<D = CstData> {
     DoubleQuote,        // "
     Percent,            // %
     Octothorpe,         // #
+    If,                 // if (only in match case guards)
     Whitespace(String), // contains only non-multiline whitespace
     Newline(String), // the associated `String` because some systems (such as Windows) have weird newlines
     Comment {
@@ -104,6 +105,8 @@ pub enum CstKind<D = CstData> {
     },
     MatchCase {
         pattern: Box<Cst<D>>,
+        /// The `if` keyword and the condition.
+        guard: Option<Box<(Cst<D>, Cst<D>)>>,
         arrow: Box<Cst<D>>,
         body: Vec<Cst<D>>,
     },
@@ -160,6 +163,7 @@ impl<D> CstKind<D> {
             | Self::DoubleQuote
             | Self::Percent
             | Self::Octothorpe
+            | Self::If
             | Self::Whitespace(_)
             | Self::Newline(_) => vec![],
             Self::Comment { octothorpe, .. } => vec![octothorpe],
@@ -289,10 +293,16 @@ impl<D> CstKind<D> {
             }
             Self::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
             } => {
-                let mut children = vec![pattern.as_ref(), arrow.as_ref()];
+                let mut children = vec![pattern.as_ref()];
+                if let Some(box (keyword, condition)) = guard {
+                    children.push(keyword);
+                    children.push(condition);
+                }
+                children.push(arrow);
                 children.extend(body);
                 children
             }
@@ -346,6 +356,7 @@ impl<D> Display for CstKind<D> {
             Self::DoubleQuote => '"'.fmt(f),
             Self::Percent => '%'.fmt(f),
             Self::Octothorpe => '#'.fmt(f),
+            Self::If => "if".fmt(f),
             Self::Whitespace(whitespace) => whitespace.fmt(f),
             Self::Newline(newline) => newline.fmt(f),
             Self::Comment {
@@ -505,10 +516,15 @@ impl<D> Display for CstKind<D> {
             }
             Self::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
             } => {
                 pattern.fmt(f)?;
+                if let Some(box (keyword, condition)) = guard {
+                    keyword.fmt(f)?;
+                    condition.fmt(f)?;
+                }
                 arrow.fmt(f)?;
                 for expression in body {
                     expression.fmt(f)?;
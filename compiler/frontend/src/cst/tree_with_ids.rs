@@ -1,5 +1,6 @@
 use super::{Cst, CstKind, Id};
 use crate::position::Offset;
+use std::iter;
 
 pub trait TreeWithIds {
     fn first_id(&self) -> Option<Id>;
@@ -35,6 +36,7 @@ impl TreeWithIds for Cst {
             | CstKind::DoubleQuote
             | CstKind::Percent
             | CstKind::Octothorpe
+            | CstKind::If
             | CstKind::Whitespace(_)
             | CstKind::Newline(_) => None,
             CstKind::Comment {
@@ -138,10 +140,16 @@ impl TreeWithIds for Cst {
                 .or_else(|| cases.find(id)),
             CstKind::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
             } => pattern
                 .find(id)
+                .or_else(|| {
+                    guard.as_deref().and_then(|(keyword, condition)| {
+                        keyword.find(id).or_else(|| condition.find(id))
+                    })
+                })
                 .or_else(|| arrow.find(id))
                 .or_else(|| body.find(id)),
             CstKind::Function {
@@ -176,7 +184,33 @@ impl TreeWithIds for Cst {
         Some(self.data.span.start)
     }
     fn find_by_offset(&self, offset: Offset) -> Option<&Cst> {
-        let (inner, is_end_inclusive) = match &self.kind {
+        // Deeply nested code would overflow the call stack if we searched
+        // recursively, so we keep track of the path to the current node
+        // ourselves. Each entry contains the children that we still have to
+        // search.
+        let mut stack = vec![(self, self.children_to_search(offset).into_iter())];
+        while let Some((_, children)) = stack.last_mut() {
+            if let Some(child) = children.next() {
+                stack.push((child, child.children_to_search(offset).into_iter()));
+                continue;
+            }
+
+            // None of the children contain the offset.
+            let (cst, _) = stack.pop().unwrap();
+            if cst.data.span.contains(&offset)
+                || (cst.is_end_inclusive() && cst.data.span.end == offset)
+            {
+                return Some(cst);
+            }
+        }
+        None
+    }
+}
+impl Cst {
+    /// The children that may contain the offset, in the order in which
+    /// [`TreeWithIds::find_by_offset`] searches them.
+    fn children_to_search(&self, offset: Offset) -> Vec<&Self> {
+        match &self.kind {
             CstKind::EqualsSign
             | CstKind::Comma
             | CstKind::Dot
@@ -194,40 +228,36 @@ impl TreeWithIds for Cst {
             | CstKind::DoubleQuote
             | CstKind::Percent
             | CstKind::Octothorpe
+            | CstKind::If
             | CstKind::Whitespace(_)
-            | CstKind::Newline(_) => (None, false),
+            | CstKind::Newline(_) => vec![],
             CstKind::Comment {
                 octothorpe,
                 comment: _,
-            } => (octothorpe.find_by_offset(offset), true),
+            } => vec![octothorpe.as_ref()],
             CstKind::TrailingWhitespace {
                 child,
                 whitespace: _,
-            } => (child.find_by_offset(offset), false),
+            } => vec![child.as_ref()],
             CstKind::Identifier(_)
             | CstKind::Symbol(_)
             | CstKind::Int {
                 radix_prefix: _,
                 value: _,
                 string: _,
-            } => (None, true),
+            } => vec![],
             CstKind::Text {
                 opening: _,
                 parts,
                 closing: _,
             } => {
-                let interpolation_index = parts
-                    .binary_search_by_key(&offset, |it| it.first_offset().unwrap())
-                    .or_else(|err| if err == 0 { Err(()) } else { Ok(err - 1) })
-                    .ok();
-
-                if let Some(part) = interpolation_index.map(|index| &parts[index])
-                    && part.kind.is_text_interpolation()
-                    && let Some(child) = part.find_by_offset(offset)
-                    && !child.kind.is_text_interpolation() {
-                    (Some(child), false)
-                } else {
-                    (None, false)
+                // Only interpolated expressions are found inside texts, not
+                // the interpolations themselves.
+                match child_at_offset(parts, offset).map(|part| &part.kind) {
+                    Some(CstKind::TextInterpolation { expression, .. }) => {
+                        vec![expression.as_ref()]
+                    }
+                    _ => vec![],
                 }
             }
             CstKind::OpeningText {
@@ -239,149 +269,133 @@ impl TreeWithIds for Cst {
                 closing_single_quotes: _,
             }
             | CstKind::TextNewline(_)
-            | CstKind::TextPart(_) => (None, false),
+            | CstKind::TextPart(_) => vec![],
             CstKind::TextInterpolation {
                 opening_curly_braces: _,
                 expression,
                 closing_curly_braces: _,
-            } => (expression.find_by_offset(offset), false),
-            CstKind::BinaryBar { left, bar, right } => (
-                left.find_by_offset(offset)
-                    .or_else(|| bar.find_by_offset(offset))
-                    .or_else(|| right.find_by_offset(offset)),
-                false,
-            ),
+            } => vec![expression.as_ref()],
+            CstKind::BinaryBar { left, bar, right } => {
+                vec![left.as_ref(), bar.as_ref(), right.as_ref()]
+            }
             CstKind::Parenthesized {
                 opening_parenthesis: _,
                 inner,
                 closing_parenthesis: _,
-            } => (inner.find_by_offset(offset), false),
+            } => vec![inner.as_ref()],
             CstKind::Call {
                 receiver,
                 arguments,
-            } => (
-                receiver
-                    .find_by_offset(offset)
-                    .or_else(|| arguments.find_by_offset(offset)),
-                false,
-            ),
+            } => iter::once(receiver.as_ref())
+                .chain(child_at_offset(arguments, offset))
+                .collect(),
             CstKind::List {
                 opening_parenthesis,
                 items,
                 closing_parenthesis,
-            } => (
-                opening_parenthesis
-                    .find_by_offset(offset)
-                    .or_else(|| items.find_by_offset(offset))
-                    .or_else(|| closing_parenthesis.find_by_offset(offset)),
-                false,
-            ),
-            CstKind::ListItem { value, comma } => (
-                value
-                    .find_by_offset(offset)
-                    .or_else(|| comma.find_by_offset(offset)),
-                false,
-            ),
+            } => iter::once(opening_parenthesis.as_ref())
+                .chain(child_at_offset(items, offset))
+                .chain([closing_parenthesis.as_ref()])
+                .collect(),
+            CstKind::ListItem { value, comma } => {
+                iter::once(value.as_ref()).chain(comma.as_deref()).collect()
+            }
             CstKind::Struct {
                 opening_bracket,
                 fields,
                 closing_bracket,
-            } => (
-                opening_bracket
-                    .find_by_offset(offset)
-                    .or_else(|| fields.find_by_offset(offset))
-                    .or_else(|| closing_bracket.find_by_offset(offset)),
-                false,
-            ),
+            } => iter::once(opening_bracket.as_ref())
+                .chain(child_at_offset(fields, offset))
+                .chain([closing_bracket.as_ref()])
+                .collect(),
             CstKind::StructField {
                 key_and_colon,
                 value,
                 comma,
-            } => (
-                key_and_colon
-                    .as_deref()
-                    .and_then(|(key, colon)| {
-                        key.find_by_offset(offset)
-                            .or_else(|| colon.find_by_offset(offset))
-                    })
-                    .or_else(|| value.find_by_offset(offset))
-                    .or_else(|| comma.find_by_offset(offset)),
-                false,
-            ),
-            CstKind::StructAccess { struct_, dot, key } => (
-                struct_
-                    .find_by_offset(offset)
-                    .or_else(|| dot.find_by_offset(offset))
-                    .or_else(|| key.find_by_offset(offset)),
-                false,
-            ),
+            } => key_and_colon
+                .as_deref()
+                .map(|(key, colon)| [key, colon])
+                .into_iter()
+                .flatten()
+                .chain([value.as_ref()])
+                .chain(comma.as_deref())
+                .collect(),
+            CstKind::StructAccess { struct_, dot, key } => {
+                vec![struct_.as_ref(), dot.as_ref(), key.as_ref()]
+            }
             CstKind::Match {
                 expression,
                 percent,
                 cases,
-            } => (
-                expression
-                    .find_by_offset(offset)
-                    .or_else(|| percent.find_by_offset(offset))
-                    .or_else(|| cases.find_by_offset(offset)),
-                false,
-            ),
+            } => [expression.as_ref(), percent.as_ref()]
+                .into_iter()
+                .chain(child_at_offset(cases, offset))
+                .collect(),
             CstKind::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
-            } => (
-                pattern
-                    .find_by_offset(offset)
-                    .or_else(|| arrow.find_by_offset(offset))
-                    .or_else(|| body.find_by_offset(offset)),
-                false,
-            ),
+            } => iter::once(pattern.as_ref())
+                .chain(
+                    guard
+                        .as_deref()
+                        .map(|(keyword, condition)| [keyword, condition])
+                        .into_iter()
+                        .flatten(),
+                )
+                .chain([arrow.as_ref()])
+                .chain(child_at_offset(body, offset))
+                .collect(),
             CstKind::Function {
                 opening_curly_brace,
                 parameters_and_arrow,
                 body,
                 closing_curly_brace,
-            } => (
-                opening_curly_brace
-                    .find_by_offset(offset)
-                    .or_else(|| {
-                        parameters_and_arrow
-                            .as_ref()
-                            .and_then(|(parameters, arrow)| {
-                                parameters
-                                    .find_by_offset(offset)
-                                    .or_else(|| arrow.find_by_offset(offset))
-                            })
-                    })
-                    .or_else(|| body.find_by_offset(offset))
-                    .or_else(|| closing_curly_brace.find_by_offset(offset)),
-                false,
-            ),
+            } => iter::once(opening_curly_brace.as_ref())
+                .chain(
+                    parameters_and_arrow
+                        .as_ref()
+                        .map(|(parameters, arrow)| {
+                            child_at_offset(parameters, offset)
+                                .into_iter()
+                                .chain([arrow.as_ref()])
+                        })
+                        .into_iter()
+                        .flatten(),
+                )
+                .chain(child_at_offset(body, offset))
+                .chain([closing_curly_brace.as_ref()])
+                .collect(),
             CstKind::Assignment {
                 left,
                 assignment_sign,
                 body,
-            } => (
-                left.find_by_offset(offset)
-                    .or_else(|| assignment_sign.find_by_offset(offset))
-                    .or_else(|| body.find_by_offset(offset)),
-                false,
-            ),
-            CstKind::Error { .. } => (None, false),
-        };
-
-        inner.or_else(|| {
-            if self.data.span.contains(&offset)
-                || (is_end_inclusive && self.data.span.end == offset)
-            {
-                Some(self)
-            } else {
-                None
-            }
-        })
+            } => [left.as_ref(), assignment_sign.as_ref()]
+                .into_iter()
+                .chain(child_at_offset(body, offset))
+                .collect(),
+            CstKind::Error { .. } => vec![],
+        }
+    }
+    const fn is_end_inclusive(&self) -> bool {
+        matches!(
+            self.kind,
+            CstKind::Comment { .. }
+                | CstKind::Identifier(_)
+                | CstKind::Symbol(_)
+                | CstKind::Int { .. },
+        )
     }
 }
+/// The child that starts last before or at the offset.
+fn child_at_offset<T: TreeWithIds>(children: &[T], offset: Offset) -> Option<&T> {
+    let child_index = children
+        .binary_search_by_key(&offset, |it| it.first_offset().unwrap())
+        .or_else(|err| if err == 0 { Err(()) } else { Ok(err - 1) })
+        .ok()?;
+    Some(&children[child_index])
+}
 impl<T: TreeWithIds> TreeWithIds for Option<T> {
     fn first_id(&self) -> Option<Id> {
         self.as_ref().and_then(TreeWithIds::first_id)
@@ -428,10 +442,6 @@ impl<T: TreeWithIds> TreeWithIds for [T] {
         self.iter().find_map(TreeWithIds::first_offset)
     }
     fn find_by_offset(&self, offset: Offset) -> Option<&Cst> {
-        let child_index = self
-            .binary_search_by_key(&offset, |it| it.first_offset().unwrap())
-            .or_else(|err| if err == 0 { Err(()) } else { Ok(err - 1) })
-            .ok()?;
-        self[child_index].find_by_offset(offset)
+        child_at_offset(self, offset)?.find_by_offset(offset)
     }
 }
@@ -25,6 +25,7 @@ impl<D: Clone> UnwrapWhitespaceAndComment for Cst<D> {
             | CstKind::DoubleQuote
             | CstKind::Percent
             | CstKind::Octothorpe
+            | CstKind::If
             | CstKind::Whitespace(_)
             | CstKind::Newline(_)
             | CstKind::Comment { .. }) => kind.clone(),
@@ -144,10 +145,17 @@ impl<D: Clone> UnwrapWhitespaceAndComment for Cst<D> {
             },
             CstKind::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
             } => CstKind::MatchCase {
                 pattern: pattern.unwrap_whitespace_and_comment(),
+                guard: guard.as_deref().map(|(keyword, condition)| {
+                    Box::new((
+                        keyword.unwrap_whitespace_and_comment(),
+                        condition.unwrap_whitespace_and_comment(),
+                    ))
+                }),
                 arrow: arrow.unwrap_whitespace_and_comment(),
                 body: body.unwrap_whitespace_and_comment(),
             },
@@ -4,12 +4,15 @@ use rustc_hash::{FxHashMap, FxHashSet};
 use crate::{
     ast::{
         self, Assignment, AssignmentBody, Ast, AstError, AstKind, AstString, Call, CollectErrors,
-        Function, Identifier, Int, List, Match, MatchCase, OrPattern, Struct, StructAccess, Symbol,
-        Text, TextPart,
+        Function, Identifier, Int, List, Match, MatchCase, OrPattern, RecoveryHint, Struct,
+        StructAccess, Symbol, Text, TextPart,
     },
     cst::{self, Cst, CstDb, CstKind, UnwrapWhitespaceAndComment},
+    desugar::{desugared_ast, DesugaredAstResult},
     error::{CompilerError, CompilerErrorPayload},
-    module::Module,
+    language_version::{language_version, LanguageVersion},
+    lints::{lint_levels, LintLevels},
+    module::{Module, Package},
     position::Offset,
     rcst_to_cst::RcstToCst,
     string_to_rcst::ModuleError,
@@ -30,6 +33,12 @@ pub trait CstToAst: CstDb + RcstToCst {
     fn cst_to_ast_ids(&self, module: Module, id: cst::Id) -> Vec<ast::Id>;
 
     fn ast(&self, module: Module) -> AstResult;
+    /// See [`crate::desugar`].
+    fn desugared_ast(&self, module: Module) -> DesugaredAstResult;
+    /// See [`crate::lints`].
+    fn lint_levels(&self, package: Package) -> LintLevels;
+    /// See [`crate::language_version`].
+    fn language_version(&self, module: Module) -> LanguageVersion;
 }
 
 pub type AstResult = Result<(Arc<Vec<Ast>>, Arc<FxHashMap<ast::Id, cst::Id>>), ModuleError>;
@@ -113,7 +122,8 @@ impl LoweringContext {
             | CstKind::SingleQuote
             | CstKind::DoubleQuote
             | CstKind::Percent
-            | CstKind::Octothorpe => self.create_error_ast(
+            | CstKind::Octothorpe
+            | CstKind::If => self.create_error_ast(
                 cst,
                 vec![self.create_error(cst, AstError::UnexpectedPunctuation)],
             ),
@@ -187,16 +197,20 @@ impl LoweringContext {
                             }
 
                             let ast = self.lower_cst(expression, LoweringType::Expression);
-                            if closing_curly_braces.len() == opening_single_quote_count + 1
-                                && closing_curly_braces
-                                    .iter()
-                                    .all(|closing_curly_brace| closing_curly_brace.kind.is_closing_curly_brace())
-                            {
+                            let existing_closing_curly_braces = closing_curly_braces
+                                .iter()
+                                .filter(|closing_curly_brace| closing_curly_brace.kind.is_closing_curly_brace())
+                                .collect_vec();
+                            let missing_count = opening_single_quote_count + 1 - existing_closing_curly_braces.len();
+                            if missing_count == 0 {
                                 Some(ast)
                             } else {
+                                let preceding = existing_closing_curly_braces.last().copied().unwrap_or(&**expression);
                                 errors.push(self.create_error(
                                     part,
-                                    AstError::TextInterpolationMissesClosingCurlyBraces,
+                                    AstError::TextInterpolationMissesClosingCurlyBraces(
+                                        recovery_hint("}".repeat(missing_count), preceding),
+                                    ),
                                 ));
                                 None
                             }
@@ -218,7 +232,12 @@ impl LoweringContext {
                         .all(|single_quote| single_quote.kind.is_single_quote())
                         && opening_single_quote_count == closing_single_quotes.len()
                 ) {
-                    errors.push(self.create_error(closing, AstError::TextMissesClosingQuote));
+                    let expected_token = format!("\"{}", "'".repeat(opening_single_quote_count));
+                    let preceding = parts.last().unwrap_or(&**opening);
+                    errors.push(self.create_error(
+                        closing,
+                        AstError::TextMissesClosingQuote(recovery_hint(expected_token, preceding)),
+                    ));
                 }
 
                 self.create_errors_or_ast(cst, errors, Text(lowered_parts))
@@ -346,16 +365,15 @@ impl LoweringContext {
                     "Parenthesized needs to start with opening parenthesis, but started with {opening_parenthesis}.",
                 );
 
-                let inner = self.lower_cst(inner, LoweringType::Expression);
-
                 let mut errors = vec![];
                 if !closing_parenthesis.kind.is_closing_parenthesis() {
                     errors.push(self.create_error(
                         closing_parenthesis,
-                        AstError::ParenthesizedMissesClosingParenthesis,
+                        AstError::ParenthesizedMissesClosingParenthesis(recovery_hint(")", inner)),
                     ));
                 }
 
+                let inner = self.lower_cst(inner, LoweringType::Expression);
                 self.create_errors_or_use_ast(cst, errors, inner)
             }
             CstKind::Call {
@@ -379,7 +397,9 @@ impl LoweringContext {
                                     closing_parenthesis,
                                     vec![self.create_error(
                                         cst,
-                                        AstError::ParenthesizedMissesClosingParenthesis,
+                                        AstError::ParenthesizedMissesClosingParenthesis(
+                                            recovery_hint(")", inner),
+                                        ),
                                     )],
                                 );
                             }
@@ -445,12 +465,11 @@ impl LoweringContext {
                 }
 
                 if !closing_parenthesis.kind.is_closing_parenthesis() {
-                    errors.push(
-                        self.create_error(
-                            closing_parenthesis,
-                            AstError::ListMissesClosingParenthesis,
-                        ),
-                    );
+                    let preceding = items.last().unwrap_or(&**opening_parenthesis);
+                    errors.push(self.create_error(
+                        closing_parenthesis,
+                        AstError::ListMissesClosingParenthesis(recovery_hint(")", preceding)),
+                    ));
                 }
 
                 self.create_errors_or_ast(cst, errors, List(ast_items))
@@ -535,9 +554,11 @@ impl LoweringContext {
                 }
 
                 if !closing_bracket.kind.is_closing_bracket() {
-                    errors.push(
-                        self.create_error(closing_bracket, AstError::StructMissesClosingBrace),
-                    );
+                    let preceding = fields.last().unwrap_or(&**opening_bracket);
+                    errors.push(self.create_error(
+                        closing_bracket,
+                        AstError::StructMissesClosingBrace(recovery_hint("]", preceding)),
+                    ));
                 }
 
                 self.create_errors_or_ast(
@@ -584,6 +605,7 @@ impl LoweringContext {
             }
             CstKind::MatchCase {
                 pattern,
+                guard,
                 arrow: _,
                 body,
             } => {
@@ -592,6 +614,13 @@ impl LoweringContext {
                 };
 
                 let pattern = self.lower_cst(pattern, LoweringType::Pattern);
+                let condition = guard.as_deref().map(|(keyword, condition)| {
+                    assert!(
+                        keyword.kind.is_if(),
+                        "Expected an `if` keyword before the guard's condition, but found {keyword}.",
+                    );
+                    Box::new(self.lower_cst(condition, LoweringType::Expression))
+                });
 
                 // TODO: handle error in arrow
 
@@ -601,6 +630,7 @@ impl LoweringContext {
                     cst.data.id,
                     MatchCase {
                         pattern: Box::new(pattern),
+                        condition,
                         body,
                     },
                 )
@@ -630,13 +660,17 @@ impl LoweringContext {
                     vec![]
                 };
 
-                let body = self.lower_csts(body);
+                let lowered_body = self.lower_csts(body);
 
                 let mut errors = vec![];
                 if !closing_curly_brace.kind.is_closing_curly_brace() {
+                    let preceding = body
+                        .last()
+                        .or_else(|| parameters_and_arrow.as_ref().map(|(_, arrow)| &**arrow))
+                        .unwrap_or(&**opening_curly_brace);
                     errors.push(self.create_error(
                         closing_curly_brace,
-                        AstError::FunctionMissesClosingCurlyBrace,
+                        AstError::FunctionMissesClosingCurlyBrace(recovery_hint("}", preceding)),
                     ));
                 }
 
@@ -645,7 +679,7 @@ impl LoweringContext {
                     errors,
                     Function {
                         parameters,
-                        body,
+                        body: lowered_body,
                         fuzzable: false,
                     },
                 )
@@ -836,3 +870,14 @@ impl LoweringContext {
         }
     }
 }
+
+/// Suggests inserting `expected_token` directly after `preceding`.
+///
+/// Whitespace and comments were already unwrapped from the CST, so the span of
+/// `preceding` doesn't include the whitespace following it.
+fn recovery_hint(expected_token: impl Into<String>, preceding: &Cst) -> RecoveryHint {
+    RecoveryHint {
+        expected_token: expected_token.into(),
+        insertion_offset: preceding.data.span.end,
+    }
+}
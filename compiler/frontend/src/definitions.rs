@@ -0,0 +1,147 @@
+//! Classifies the top-level definitions of a module so that tools can present
+//! them without guessing from the syntax of their bodies.
+//!
+//! Definitions are classified by what their value resolves to. For example, in
+//! `foo := bar`, `foo` is a function if `bar` is one.
+
+use crate::{
+    hir::{Body, Expression, Function, FunctionKind, HirDb, Id, IdKey},
+    module::Module,
+};
+use linked_hash_map::LinkedHashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DefinitionKind {
+    /// A value that's computed once when the module runs.
+    Constant,
+    Function {
+        arity: usize,
+    },
+    /// Makes a module or one of its definitions available under another
+    /// name, e.g., `foo := (use "..bar").foo`.
+    ReExport,
+}
+
+/// The kinds of the module's top-level definitions in the order in which they
+/// are defined. Keys are the IDs of the defined identifiers.
+pub type DefinitionKinds = Arc<LinkedHashMap<Id, DefinitionKind>>;
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn definition_kinds(db: &dyn HirDb, module: Module) -> DefinitionKinds {
+    let Ok((hir, _)) = db.hir(module) else {
+        return Arc::default();
+    };
+
+    let kinds = hir
+        .expressions
+        .iter()
+        .filter_map(|(id, expression)| {
+            let name = hir.identifiers.get(id)?;
+            let kind = match expression {
+                // Assignments to an identifier create several references
+                // with the name. The last one refers to the previous one.
+                Expression::Reference(target) if defines(&hir, target, name) => {
+                    kind_of(&hir, target)
+                }
+                Expression::PatternIdentifierReference(_) => DefinitionKind::Constant,
+                _ => return None,
+            };
+            Some((id.clone(), kind))
+        })
+        .collect();
+    Arc::new(kinds)
+}
+
+/// Whether the expression is an earlier definition of the name. Functions
+/// defined with parameters (`foo a := …`) get the name as their ID, but aren't
+/// registered as identifiers.
+fn defines(hir: &Body, id: &Id, name: &str) -> bool {
+    if let Some(identifier) = hir.identifiers.get(id) {
+        return identifier == name;
+    }
+    matches!(hir.find(id), Some(Expression::Function(_)))
+        && matches!(id.keys.last(), Some(IdKey::Named { name: key, .. }) if key == name)
+}
+fn kind_of(hir: &Body, id: &Id) -> DefinitionKind {
+    match resolve(hir, id) {
+        Some(Expression::Function(Function {
+            parameters, kind, ..
+        })) if *kind != FunctionKind::Use => DefinitionKind::Function {
+            arity: parameters.len(),
+        },
+        Some(Expression::Builtin(builtin_function)) => DefinitionKind::Function {
+            arity: builtin_function.num_parameters(),
+        },
+        // `use "…"` itself or a struct access on it
+        Some(Expression::Call {
+            function,
+            arguments,
+        }) if is_use(hir, function)
+            || matches!(
+                arguments.as_slice(),
+                [struct_, key] if is_use_call(hir, struct_)
+                    && matches!(resolve(hir, key), Some(Expression::Symbol(_))),
+            ) =>
+        {
+            DefinitionKind::ReExport
+        }
+        _ => DefinitionKind::Constant,
+    }
+}
+fn resolve<'a>(hir: &'a Body, id: &Id) -> Option<&'a Expression> {
+    let mut expression = hir.find(id)?;
+    while let Expression::Reference(target) = expression {
+        expression = hir.find(target)?;
+    }
+    Some(expression)
+}
+fn is_use_call(hir: &Body, id: &Id) -> bool {
+    matches!(
+        resolve(hir, id),
+        Some(Expression::Call { function, .. }) if is_use(hir, function),
+    )
+}
+fn is_use(hir: &Body, id: &Id) -> bool {
+    matches!(
+        resolve(hir, id),
+        Some(Expression::Function(Function {
+            kind: FunctionKind::Use,
+            ..
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DefinitionKind;
+    use crate::{ast_to_hir::AstToHir, hir::HirDb, test_database::Database};
+    use itertools::Itertools;
+
+    /// The names and kinds of the definitions in the source.
+    fn definition_kinds(source: &str) -> Vec<(String, DefinitionKind)> {
+        let (db, module) = Database::with_main_module(source);
+        let (hir, _) = db.hir(module.clone()).unwrap();
+        db.definition_kinds(module)
+            .iter()
+            .map(|(id, kind)| (hir.identifiers[id].clone(), *kind))
+            .collect_vec()
+    }
+
+    #[test]
+    fn definitions_are_classified_by_their_value() {
+        assert_eq!(
+            definition_kinds(
+                "foo a b := a\nbar := foo\nbaz = 1\nother := use \"..other\"\nqux := (use \"..other\").qux\n[x] = [X: 1]\n",
+            ),
+            [
+                ("foo".to_string(), DefinitionKind::Function { arity: 2 }),
+                ("bar".to_string(), DefinitionKind::Function { arity: 2 }),
+                ("baz".to_string(), DefinitionKind::Constant),
+                ("other".to_string(), DefinitionKind::ReExport),
+                ("qux".to_string(), DefinitionKind::ReExport),
+                ("x".to_string(), DefinitionKind::Constant),
+            ],
+        );
+    }
+}
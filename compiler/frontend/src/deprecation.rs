@@ -0,0 +1,271 @@
+//! Exports can be marked as deprecated by starting a line of their doc comment
+//! with `Deprecated:`. The rest of that line should tell users what to use
+//! instead:
+//!
+//! ```candy
+//! oldName :=
+//!   # Deprecated: Use `newName` instead.
+//!   newName
+//! ```
+//!
+//! Accessing a deprecated export of a statically known module (such as in
+//! `(use "Foo").oldName` or `foo = use "Foo"` followed by `foo.oldName`)
+//! results in a warning at the accessed key. Destructuring it (such as in
+//! `[oldName] = use "Foo"`) results in a warning at the identifier it's bound
+//! to.
+
+use crate::{
+    ast_to_hir::AstToHir,
+    builtin_functions::BuiltinFunction,
+    comment::doc_comment_lines,
+    error::CompilerError,
+    hir::{self, Body, Expression, Pattern},
+    mir::MirError,
+    module::Module,
+    utils::AdjustCasingOfFirstLetter,
+};
+use rustc_hash::FxHashMap;
+use std::sync::Arc;
+
+/// Maps the symbols of deprecated exports (e.g., `OldName`) to the rest of the
+/// `Deprecated:` line in their doc comment.
+pub fn deprecated_exports(db: &dyn AstToHir, module: Module) -> Arc<FxHashMap<String, String>> {
+    let Ok((hir, _)) = db.hir(module) else {
+        return Arc::default();
+    };
+    let Some((_, Expression::Struct(exports))) = hir.expressions.iter().last() else {
+        return Arc::default();
+    };
+
+    let mut deprecated_exports = FxHashMap::default();
+    for (key, value) in exports {
+        let Some(Expression::Symbol(symbol)) = hir.find(key) else {
+            continue;
+        };
+        // Exports point to the reference that is mapped to the assignment.
+        let Some(Expression::Reference(definition)) = hir.find(value) else {
+            continue;
+        };
+        let Some(hint) = doc_comment_lines(db, definition).and_then(|lines| {
+            lines.iter().find_map(|line| {
+                line.trim_start()
+                    .strip_prefix("Deprecated:")
+                    .map(|hint| hint.trim().to_string())
+            })
+        }) else {
+            continue;
+        };
+        deprecated_exports.insert(symbol.clone(), hint);
+    }
+    Arc::new(deprecated_exports)
+}
+
+pub fn deprecation_warnings<Db: AstToHir + ?Sized>(db: &Db, module: Module) -> Vec<CompilerError> {
+    let Ok((hir, _)) = db.hir(module.clone()) else {
+        return vec![];
+    };
+    let used_modules: FxHashMap<_, _> = db.used_modules(module).iter().cloned().collect();
+    if used_modules.is_empty() {
+        return vec![];
+    }
+
+    let mut warnings = vec![];
+    collect_deprecation_warnings(db, &hir, &hir, &used_modules, &mut warnings);
+    warnings
+}
+fn collect_deprecation_warnings<Db: AstToHir + ?Sized>(
+    db: &Db,
+    root: &Body,
+    body: &Body,
+    used_modules: &FxHashMap<hir::Id, Module>,
+    warnings: &mut Vec<CompilerError>,
+) {
+    for (id, expression) in &body.expressions {
+        match expression {
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                if let [struct_, key] = arguments.as_slice()
+                    && is_struct_get(root, function)
+                    && let Some(used_module) = used_modules.get(follow_references(root, struct_))
+                    && let Some(Expression::Symbol(symbol)) = root.find(key)
+                    && let Some(hint) = db.deprecated_exports(used_module.clone()).get(symbol)
+                    && let Some(span) = db.hir_id_to_span(key)
+                {
+                    warnings.push(CompilerError {
+                        module: key.module.clone(),
+                        span,
+                        payload: MirError::UsesDeprecatedExport {
+                            module: used_module.clone(),
+                            name: symbol.lowercase_first_letter(),
+                            hint: hint.clone(),
+                        }
+                        .into(),
+                    });
+                }
+            }
+            Expression::Destructure {
+                expression,
+                pattern: Pattern::Struct(fields),
+            } => {
+                let Some(used_module) = used_modules.get(follow_references(root, expression))
+                else {
+                    continue;
+                };
+                let deprecated_exports = db.deprecated_exports(used_module.clone());
+                for (key, value) in fields {
+                    let Pattern::Tag {
+                        symbol,
+                        value: None,
+                    } = key
+                    else {
+                        continue;
+                    };
+                    let Some(hint) = deprecated_exports.get(symbol) else {
+                        continue;
+                    };
+                    // Patterns don't have IDs of their own, so we point to the
+                    // first identifier that the value is bound to or, if there
+                    // is none, to the whole pattern.
+                    let identifier_reference = value.captured_identifiers().first().and_then(
+                        |identifier_id| {
+                            body.expressions.iter().find_map(|(id, expression)| {
+                                matches!(
+                                    expression,
+                                    Expression::PatternIdentifierReference(it) if it == identifier_id,
+                                )
+                                .then_some(id)
+                            })
+                        },
+                    );
+                    let Some(span) = db.hir_id_to_span(identifier_reference.unwrap_or(id)) else {
+                        continue;
+                    };
+                    warnings.push(CompilerError {
+                        module: id.module.clone(),
+                        span,
+                        payload: MirError::UsesDeprecatedExport {
+                            module: used_module.clone(),
+                            name: symbol.lowercase_first_letter(),
+                            hint: hint.clone(),
+                        }
+                        .into(),
+                    });
+                }
+            }
+            Expression::Match { cases, .. } => {
+                for case_body in cases.iter().flat_map(hir::MatchCase::bodies) {
+                    collect_deprecation_warnings(db, root, case_body, used_modules, warnings);
+                }
+            }
+            Expression::Function(function) => {
+                collect_deprecation_warnings(db, root, &function.body, used_modules, warnings);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Struct accesses call either the `structGet` builtin directly (inside the
+/// Builtins package) or `(use "Builtins").structGet`.
+fn is_struct_get(root: &Body, function: &hir::Id) -> bool {
+    match root.find(follow_references(root, function)) {
+        Some(Expression::Builtin(BuiltinFunction::StructGet)) => true,
+        Some(Expression::Call { function, .. }) => matches!(
+            root.find(follow_references(root, function)),
+            Some(Expression::Builtin(BuiltinFunction::StructGet)),
+        ),
+        _ => false,
+    }
+}
+fn follow_references<'a>(root: &'a Body, mut id: &'a hir::Id) -> &'a hir::Id {
+    while let Some(Expression::Reference(target)) = root.find(id) {
+        id = target;
+    }
+    id
+}
+
+#[cfg(test)]
+mod test {
+    use super::deprecation_warnings;
+    use crate::{
+        error::CompilerErrorPayload,
+        mir::MirError,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+    };
+
+    const FOO: &str = "\
+newName := 1
+oldName :=
+  # Does the same as `newName`.
+  #
+  # Deprecated: Use `newName` instead.
+  newName
+deprecatedIsNotTheFirstWord :=
+  # Deprecated exports are still available.
+  2
+";
+
+    fn module(name: &str) -> Module {
+        Module {
+            package: Package::User("/package".into()),
+            path: vec![name.to_string()],
+            kind: ModuleKind::Code,
+        }
+    }
+    /// The names of the deprecated exports that `main` uses and the code the
+    /// warnings point to.
+    fn warnings(main: &str) -> Vec<(String, String)> {
+        let mut db = Database::default();
+        db.did_open_module(&module("foo"), FOO.as_bytes().to_vec());
+        db.did_open_module(&module("main"), main.as_bytes().to_vec());
+        deprecation_warnings(&db, module("main"))
+            .into_iter()
+            .map(|warning| {
+                let CompilerErrorPayload::Mir(MirError::UsesDeprecatedExport {
+                    name, hint, ..
+                }) = warning.payload
+                else {
+                    panic!("Unexpected warning: {warning:?}");
+                };
+                assert_eq!(hint, "Use `newName` instead.");
+                (
+                    name,
+                    main[*warning.span.start..*warning.span.end].to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn struct_accesses_of_deprecated_exports_are_reported() {
+        assert_eq!(
+            warnings("foo = use \"..foo\"\nbar = foo.oldName\nbaz = foo.newName\n"),
+            [("oldName".to_string(), "oldName".to_string())],
+        );
+        assert_eq!(
+            warnings("bar = (use \"..foo\").oldName\n"),
+            [("oldName".to_string(), "oldName".to_string())],
+        );
+    }
+    #[test]
+    fn destructured_deprecated_exports_are_reported() {
+        assert_eq!(
+            warnings("[oldName, newName] = use \"..foo\"\n"),
+            [("oldName".to_string(), "oldName".to_string())],
+        );
+        assert_eq!(
+            warnings("[OldName: bar] = use \"..foo\"\n"),
+            [("oldName".to_string(), "bar".to_string())],
+        );
+    }
+    #[test]
+    fn only_lines_starting_with_the_marker_deprecate_exports() {
+        assert_eq!(
+            warnings("[deprecatedIsNotTheFirstWord] = use \"..foo\"\n"),
+            [],
+        );
+    }
+}
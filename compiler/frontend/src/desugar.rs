@@ -0,0 +1,240 @@
+//! Desugarings rewrite syntactic sugar in the AST into equivalent AST that is
+//! more explicit. HIR lowering works on the desugared AST, and you can view it
+//! using `candy debug desugared-ast` or in the language server's IR view.
+//!
+//! Some sugar is already removed while lowering the CST to the AST: Pipes such
+//! as `foo | bar baz` become calls that receive the piped value as their first
+//! argument.
+//!
+//! To add a desugaring, write a function that rewrites a single node and add it
+//! to [`DESUGARINGS`].
+
+use crate::{
+    ast::{
+        Assignment, AssignmentBody, Ast, AstKind, AstString, Call, Function, Identifier, List,
+        Match, MatchCase, OrPattern, Struct, StructAccess, Symbol, Text, TextPart,
+    },
+    cst_to_ast::CstToAst,
+    module::Module,
+    string_to_rcst::ModuleError,
+    utils::AdjustCasingOfFirstLetter,
+};
+use std::sync::Arc;
+
+pub type DesugaredAstResult = Result<Arc<Vec<Ast>>, ModuleError>;
+
+pub struct Desugaring {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Rewrites a single node whose children are already desugared.
+    desugar: fn(Ast) -> Ast,
+}
+
+/// All desugarings in the order in which they are applied to each node.
+pub const DESUGARINGS: &[Desugaring] = &[
+    Desugaring {
+        name: "structShorthands",
+        description: "`[foo]` becomes `[Foo: foo]`, both in expressions and in patterns.",
+        desugar: desugar_struct_shorthands,
+    },
+    Desugaring {
+        name: "textParts",
+        description: "Adjacent literal parts of a text (e.g., lines) become a single part.",
+        desugar: merge_text_parts,
+    },
+];
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn desugared_ast(db: &dyn CstToAst, module: Module) -> DesugaredAstResult {
+    let (asts, _) = db.ast(module)?;
+    Ok(Arc::new(desugar_all(asts.iter().cloned())))
+}
+
+fn desugar_all(asts: impl IntoIterator<Item = Ast>) -> Vec<Ast> {
+    asts.into_iter().map(desugar).collect()
+}
+fn desugar(ast: Ast) -> Ast {
+    let ast = desugar_children(ast);
+    DESUGARINGS
+        .iter()
+        .fold(ast, |ast, desugaring| (desugaring.desugar)(ast))
+}
+fn desugar_boxed(ast: Box<Ast>) -> Box<Ast> {
+    Box::new(desugar(*ast))
+}
+fn desugar_children(ast: Ast) -> Ast {
+    let kind = match ast.kind {
+        kind @ (AstKind::Int(_)
+        | AstKind::TextPart(_)
+        | AstKind::Identifier(_)
+        | AstKind::Symbol(_)
+        | AstKind::Error { .. }) => kind,
+        AstKind::Text(Text(parts)) => Text(desugar_all(parts)).into(),
+        AstKind::List(List(items)) => List(desugar_all(items)).into(),
+        AstKind::Struct(Struct { fields }) => Struct {
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| (key.map(desugar), desugar(value)))
+                .collect(),
+        }
+        .into(),
+        AstKind::StructAccess(StructAccess { struct_, key }) => StructAccess {
+            struct_: desugar_boxed(struct_),
+            key,
+        }
+        .into(),
+        AstKind::Function(function) => desugar_function(function).into(),
+        AstKind::Call(Call {
+            receiver,
+            arguments,
+            is_from_pipe,
+        }) => Call {
+            receiver: desugar_boxed(receiver),
+            arguments: desugar_all(arguments),
+            is_from_pipe,
+        }
+        .into(),
+        AstKind::Assignment(Assignment { is_public, body }) => Assignment {
+            is_public,
+            body: match body {
+                AssignmentBody::Function { name, function } => AssignmentBody::Function {
+                    name,
+                    function: desugar_function(function),
+                },
+                AssignmentBody::Body { pattern, body } => AssignmentBody::Body {
+                    pattern: desugar_boxed(pattern),
+                    body: desugar_all(body),
+                },
+            },
+        }
+        .into(),
+        AstKind::Match(Match { expression, cases }) => Match {
+            expression: desugar_boxed(expression),
+            cases: desugar_all(cases),
+        }
+        .into(),
+        AstKind::MatchCase(MatchCase {
+            pattern,
+            condition,
+            body,
+        }) => MatchCase {
+            pattern: desugar_boxed(pattern),
+            condition: condition.map(desugar_boxed),
+            body: desugar_all(body),
+        }
+        .into(),
+        AstKind::OrPattern(OrPattern(patterns)) => OrPattern(desugar_all(patterns)).into(),
+    };
+    Ast { id: ast.id, kind }
+}
+fn desugar_function(function: Function) -> Function {
+    Function {
+        parameters: desugar_all(function.parameters),
+        body: desugar_all(function.body),
+        fuzzable: function.fuzzable,
+    }
+}
+
+fn desugar_struct_shorthands(mut ast: Ast) -> Ast {
+    if let AstKind::Struct(Struct { fields }) = &mut ast.kind {
+        for (key, value) in fields {
+            if key.is_none()
+                && let AstKind::Identifier(Identifier(name)) = &value.kind
+            {
+                *key = Some(Ast {
+                    id: value.id.clone(),
+                    kind: Symbol(AstString {
+                        id: name.id.clone(),
+                        value: name.value.uppercase_first_letter(),
+                    })
+                    .into(),
+                });
+            }
+        }
+    }
+    ast
+}
+
+fn merge_text_parts(mut ast: Ast) -> Ast {
+    if let AstKind::Text(Text(parts)) = &mut ast.kind {
+        let mut merged_parts: Vec<Ast> = Vec::with_capacity(parts.len());
+        for part in parts.drain(..) {
+            if let AstKind::TextPart(TextPart(string)) = &part.kind
+                && let Some(Ast {
+                    kind: AstKind::TextPart(TextPart(previous)),
+                    ..
+                }) = merged_parts.last_mut()
+            {
+                previous.value.push_str(&string.value);
+            } else {
+                merged_parts.push(part);
+            }
+        }
+        *parts = merged_parts;
+    }
+    ast
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast;
+
+    fn node(local: usize, kind: impl Into<AstKind>) -> Ast {
+        Ast {
+            id: id(local),
+            kind: kind.into(),
+        }
+    }
+    fn string(local: usize, value: &str) -> AstString {
+        AstString {
+            id: id(local),
+            value: value.to_string(),
+        }
+    }
+    fn id(local: usize) -> ast::Id {
+        ast::Id::new(Module::from_package_name("Test".to_string()), local)
+    }
+
+    #[test]
+    fn test_struct_shorthands() {
+        let shorthand = node(
+            0,
+            Struct {
+                fields: vec![(None, node(1, Identifier(string(2, "foo"))))],
+            },
+        );
+        let explicit = node(
+            0,
+            Struct {
+                fields: vec![(
+                    Some(node(1, Symbol(string(2, "Foo")))),
+                    node(1, Identifier(string(2, "foo"))),
+                )],
+            },
+        );
+        assert_eq!(desugar(shorthand), explicit);
+    }
+
+    #[test]
+    fn test_text_parts() {
+        let text = node(
+            0,
+            Text(vec![
+                node(1, TextPart(string(2, "foo"))),
+                node(3, TextPart(string(4, "\n"))),
+                node(5, Identifier(string(6, "bar"))),
+                node(7, TextPart(string(8, "baz"))),
+            ]),
+        );
+        let merged = node(
+            0,
+            Text(vec![
+                node(1, TextPart(string(2, "foo\n"))),
+                node(5, Identifier(string(6, "bar"))),
+                node(7, TextPart(string(8, "baz"))),
+            ]),
+        );
+        assert_eq!(desugar(text), merged);
+    }
+}
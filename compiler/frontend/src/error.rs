@@ -1,7 +1,14 @@
 use enumset::EnumSet;
 
-use super::{ast::AstError, cst, cst::CstError, hir::HirError};
+use super::{
+    ast::{AstError, RecoveryHint},
+    cst,
+    cst::CstError,
+    hir::HirError,
+};
 use crate::{
+    language_version::LanguageVersion,
+    lints::Lint,
     mir::MirError,
     module::Module,
     position::{Offset, PositionConversionDb, RangeOfPosition},
@@ -39,6 +46,31 @@ impl CompilerError {
         let range = db.range_to_positions(self.module.clone(), self.span.clone());
         format!("{}:{}: {}", self.module, range.format(), self.payload)
     }
+
+    /// Whether `next` is the same error right after or overlapping this one,
+    /// as it often happens when the parser recovers from a syntax error.
+    #[must_use]
+    pub fn is_repeated_by(&self, next: &Self) -> bool {
+        let is_adjacent_or_overlapping = next.span.start <= self.span.end;
+        self.module == next.module && self.payload == next.payload && is_adjacent_or_overlapping
+    }
+}
+impl CompilerErrorPayload {
+    /// Warnings point out suspicious code that still compiles and runs as
+    /// written.
+    #[must_use]
+    pub const fn is_warning(&self) -> bool {
+        Lint::of(self).is_some()
+    }
+
+    /// For errors about a missing token, which token to insert where.
+    #[must_use]
+    pub const fn recovery_hint(&self) -> Option<&RecoveryHint> {
+        match self {
+            Self::Ast(error) => error.recovery_hint(),
+            _ => None,
+        }
+    }
 }
 impl Display for CompilerErrorPayload {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -61,8 +93,12 @@ impl Display for CompilerErrorPayload {
                 CstError::ListItemMissesValue => "This list item is missing a value.",
                 CstError::ListNotClosed => "The list is not closed.",
                 CstError::MatchMissesCases => "This match misses cases to match against.",
+                CstError::MatchCaseGuardMissesCondition => {
+                    "This match case guard misses a condition after the `if`."
+                }
                 CstError::MatchCaseMissesArrow => "This match case misses an arrow.",
                 CstError::MatchCaseMissesBody => "This match case misses a body to run.",
+                CstError::NestingTooDeep => "This code is nested too deeply.",
                 CstError::OpeningParenthesisMissesExpression => {
                     "Here's an opening parenthesis without an expression after it."
                 }
@@ -95,13 +131,13 @@ impl Display for CompilerErrorPayload {
                     "An assignment should have a name or pattern on the left side.".to_string()
                 }
                 AstError::ExpectedParameter => "A parameter should come here.".to_string(),
-                AstError::FunctionMissesClosingCurlyBrace => {
+                AstError::FunctionMissesClosingCurlyBrace(_) => {
                     "This function doesn't have a closing curly brace.".to_string()
                 }
                 AstError::ListItemMissesComma => {
                     "This list item should be followed by a comma.".to_string()
                 }
-                AstError::ListMissesClosingParenthesis => {
+                AstError::ListMissesClosingParenthesis(_) => {
                     "This list doesn't have a closing parenthesis.".to_string()
                 }
                 AstError::ListWithNonListItem => "This is not a list item.".to_string(),
@@ -118,7 +154,7 @@ impl Display for CompilerErrorPayload {
                 AstError::ParenthesizedInPattern => {
                     "Parentheses are not allowed in patterns.".to_string()
                 }
-                AstError::ParenthesizedMissesClosingParenthesis => {
+                AstError::ParenthesizedMissesClosingParenthesis(_) => {
                     "This expression is parenthesized, but the closing parenthesis is missing."
                         .to_string()
                 }
@@ -132,7 +168,7 @@ impl Display for CompilerErrorPayload {
                 AstError::StructKeyMissesColon => {
                     "This struct key should be followed by a colon.".to_string()
                 }
-                AstError::StructMissesClosingBrace => {
+                AstError::StructMissesClosingBrace(_) => {
                     "This struct doesn't have a closing bracket.".to_string()
                 }
                 AstError::StructShorthandWithNotIdentifier => {
@@ -144,10 +180,10 @@ impl Display for CompilerErrorPayload {
                 AstError::StructWithNonStructField => {
                     "Structs should only contain struct key.".to_string()
                 }
-                AstError::TextInterpolationMissesClosingCurlyBraces => {
+                AstError::TextInterpolationMissesClosingCurlyBraces(_) => {
                     "This text interpolation never ends.".to_string()
                 }
-                AstError::TextMissesClosingQuote => "This text never ends.".to_string(),
+                AstError::TextMissesClosingQuote(_) => "This text never ends.".to_string(),
                 AstError::UnexpectedPunctuation => "This punctuation was unexpected.".to_string(),
             },
             Self::Hir(error) => match error {
@@ -155,12 +191,18 @@ impl Display for CompilerErrorPayload {
                     format!("`needs` accepts one or two arguments, but was called with {num_args} arguments. Its parameters are the `condition` and an optional `message`.")
                 }
                 HirError::PatternContainsCall => "Calls in patterns are not allowed.".to_string(),
+                HirError::PatternStructKeyIsNotConstant => {
+                    "Struct keys in patterns can't contain `_` or capture identifiers.".to_string()
+                }
                 HirError::PublicAssignmentInNotTopLevel => {
                     "Public assignments (:=) can only be used in top-level code.".to_string()
                 }
                 HirError::PublicAssignmentWithSameName { name } => {
                     format!("There already exists a public assignment (:=) named `{name}`.")
                 }
+                HirError::RequiresNewerLanguageVersion { feature, version } => {
+                    format!("{feature} require language version {}, but this module uses version {version}. Declare a newer version with a leading comment like `# candy {}` or with `languageVersion` in the package's `_package.candy`.", feature.introduced_in(), LanguageVersion::LATEST)
+                }
                 HirError::UnknownReference { name } => format!("`{name}` is not in scope."),
             },
             Self::Mir(error) => match error {
@@ -182,6 +224,41 @@ impl Display for CompilerErrorPayload {
                         cycle.iter().join(" → "),
                     )
                 }
+                MirError::UseCycle { cycle } => format!(
+                    "This `use` is part of a cycle: {}",
+                    cycle.iter().join(" → "),
+                ),
+                MirError::NeedsConditionIsAlwaysTrue => {
+                    "This condition is always true, so the `needs` never fails.".to_string()
+                }
+                MirError::NeedsConditionIsAlwaysFalse => {
+                    "This condition is always false, so the `needs` always fails.".to_string()
+                }
+                MirError::UnreachableMatchCase => {
+                    "This case is never reached because an earlier case matches everything."
+                        .to_string()
+                }
+                MirError::IntArgumentOutOfRange {
+                    builtin_function,
+                    parameter,
+                } => format!(
+                    "This int can't be `{parameter}` of `✨.{}`, which must be between 0 and {}.",
+                    builtin_function.name(),
+                    usize::MAX,
+                ),
+                MirError::UsesDeprecatedExport { module, name, hint } => {
+                    if hint.is_empty() {
+                        format!("`{name}` from {module} is deprecated.")
+                    } else {
+                        format!("`{name}` from {module} is deprecated: {hint}")
+                    }
+                }
+                MirError::UnconventionalName {
+                    name,
+                    conventional_name,
+                } => format!(
+                    "`{name}` doesn't follow the naming convention, it should be called `{conventional_name}`.",
+                ),
             },
         };
         write!(f, "{message}")
@@ -227,3 +304,87 @@ impl ToRichIr for CompilerError {
         builder.push(format!(": {}", self.payload), None, EnumSet::empty());
     }
 }
+
+/// A single syntax error can cause lots of follow-up errors. Reporters
+/// therefore show repetitions of an error together with it and stop after this
+/// many errors by default.
+pub const DEFAULT_MAX_REPORTED_ERRORS: usize = 50;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErrorGroup<T> {
+    pub first: T,
+    pub repetitions: Vec<T>,
+}
+
+/// Errors grouped by repetitions and limited to a maximum number of groups.
+///
+/// This is generic so that the CLI can use it for [`CompilerError`]s and the
+/// language server for the diagnostics it reports.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LimitedErrors<T> {
+    pub groups: Vec<ErrorGroup<T>>,
+    /// The number of errors that are not part of any group because the limit
+    /// was reached.
+    pub num_omitted: usize,
+}
+impl<T> LimitedErrors<T> {
+    /// The errors should be sorted by position. `is_repetition` is called with
+    /// the last error of the current group and the next error.
+    #[must_use]
+    pub fn new(
+        errors: impl IntoIterator<Item = T>,
+        max_groups: usize,
+        is_repetition: impl Fn(&T, &T) -> bool,
+    ) -> Self {
+        let mut groups: Vec<ErrorGroup<T>> = vec![];
+        let mut num_omitted = 0;
+        for error in errors {
+            if let Some(group) = groups.last_mut() {
+                let last = group.repetitions.last().unwrap_or(&group.first);
+                if is_repetition(last, &error) {
+                    group.repetitions.push(error);
+                    continue;
+                }
+            }
+
+            if groups.len() < max_groups {
+                groups.push(ErrorGroup {
+                    first: error,
+                    repetitions: vec![],
+                });
+            } else {
+                num_omitted += 1;
+            }
+        }
+        Self {
+            groups,
+            num_omitted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_limited_errors() {
+        let errors = LimitedErrors::new([1, 2, 3, 5, 6, 8, 10, 11], 2, |last, next| {
+            *next == last + 1
+        });
+        assert_eq!(
+            errors.groups,
+            vec![
+                ErrorGroup {
+                    first: 1,
+                    repetitions: vec![2, 3],
+                },
+                ErrorGroup {
+                    first: 5,
+                    repetitions: vec![6],
+                },
+            ],
+        );
+        assert_eq!(errors.num_omitted, 3);
+    }
+}
@@ -51,12 +51,70 @@ impl Sub<usize> for MaxLength {
     }
 }
 
+/// Limits how much of a nested value is shown.
+///
+/// Values can't contain references to themselves, so these limits (and the
+/// [`MaxLength`]) are enough to keep the output of huge values readable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NestingLimits {
+    /// How many levels of tag values, list items, and struct entries are shown
+    /// before they are collapsed to `…`.
+    pub max_depth: Option<usize>,
+
+    /// How many items of a list or entries of a struct are shown.
+    pub max_items: Option<usize>,
+}
+impl NestingLimits {
+    pub const UNLIMITED: Self = Self {
+        max_depth: None,
+        max_items: None,
+    };
+
+    /// The limits used for values in panic messages and stack traces.
+    pub const FOR_MESSAGES: Self = Self {
+        max_depth: Some(4),
+        max_items: Some(16),
+    };
+    pub const MAX_LENGTH_FOR_MESSAGES: MaxLength = MaxLength::Limited(200);
+
+    const fn can_nest(self) -> bool {
+        !matches!(self.max_depth, Some(0))
+    }
+    fn nested(self) -> Self {
+        Self {
+            max_depth: self.max_depth.map(|it| it - 1),
+            max_items: self.max_items,
+        }
+    }
+    fn shown_items(self, len: usize) -> usize {
+        self.max_items.map_or(len, |max_items| len.min(max_items))
+    }
+}
+
 /// Formats the value, using the visitor to match across possible values.
 pub fn format_value<'a, T: 'a + Copy>(
     value: T,
     precedence: Precedence,
     max_length: MaxLength,
     visitor: &impl Fn(T) -> Option<FormatValue<'a, T>>,
+) -> Option<String> {
+    format_value_with_limits(
+        value,
+        precedence,
+        max_length,
+        NestingLimits::UNLIMITED,
+        visitor,
+    )
+}
+
+/// Like [`format_value`], but additionally collapses deeply nested values and
+/// long collections according to the `limits`.
+pub fn format_value_with_limits<'a, T: 'a + Copy>(
+    value: T,
+    precedence: Precedence,
+    max_length: MaxLength,
+    limits: NestingLimits,
+    visitor: &impl Fn(T) -> Option<FormatValue<'a, T>>,
 ) -> Option<String> {
     // For each case, the different alternatives of printing are listed.
     // Depending on the available space, the best is chosen.
@@ -104,11 +162,12 @@ pub fn format_value<'a, T: 'a + Copy>(
 
             if let Some(value) = value {
                 string.push(' ');
-                if symbol_fits {
-                    string.push_str(&format_value(
+                if symbol_fits && limits.can_nest() {
+                    string.push_str(&format_value_with_limits(
                         value,
                         Precedence::High,
                         max_length - (length_needed_for_structure - 2 + symbol.len()),
+                        limits.nested(),
                         visitor,
                     )?);
                 } else {
@@ -155,9 +214,27 @@ pub fn format_value<'a, T: 'a + Copy>(
             }
 
             let list_len = list.len();
+            let list_summary = || {
+                let summary = format!("(list of {} items)", list_len);
+                if max_length.fits(summary.len()) {
+                    summary
+                } else {
+                    "…".to_string()
+                }
+            };
+            if !limits.can_nest() || limits.shown_items(list_len) == 0 {
+                return Some(list_summary());
+            }
+
             if list_len == 1 {
                 let item = list[0];
-                let item = format_value(item, Precedence::Low, MaxLength::Unlimited, visitor)?;
+                let item = format_value_with_limits(
+                    item,
+                    Precedence::Low,
+                    MaxLength::Unlimited,
+                    limits.nested(),
+                    visitor,
+                )?;
                 return if max_length.fits(item.len() + 3) {
                     Some(format!("({item},)"))
                 } else {
@@ -167,14 +244,20 @@ pub fn format_value<'a, T: 'a + Copy>(
 
             let mut items = Vec::with_capacity(list_len);
             let mut total_item_length = 0;
-            for item in list {
+            for item in &list[..limits.shown_items(list_len)] {
                 // Would an additional item fit?
                 // surrounding parentheses, items, and for each item comma + space, new item
                 if !max_length.fits(2 + total_item_length + items.len() * 2 + 1) {
                     break;
                 }
 
-                let item = format_value(*item, Precedence::Low, MaxLength::Unlimited, visitor)?;
+                let item = format_value_with_limits(
+                    *item,
+                    Precedence::Low,
+                    MaxLength::Unlimited,
+                    limits.nested(),
+                    visitor,
+                )?;
                 total_item_length += item.len();
                 items.push(item);
             }
@@ -182,25 +265,26 @@ pub fn format_value<'a, T: 'a + Copy>(
                 return Some(format!("({})", items.into_iter().join(", ")));
             }
 
-            // Not all items fit. Try to remove the back ones, showing "+ X more" instead.
-            while let Some(popped) = items.pop() {
-                total_item_length -= popped.len();
-                let extra_text = format!("+ {} more", list_len - items.len());
-                if max_length.fits(total_item_length + items.len() * 2 + extra_text.len()) {
-                    return Some(format!(
-                        "({}, {})",
-                        items.into_iter().join(", "),
-                        extra_text,
-                    ));
+            // Not all items fit or are allowed to be shown. Try to remove the
+            // back ones, showing "+ X more" instead.
+            loop {
+                if !items.is_empty() && items.len() < list_len {
+                    let extra_text = format!("+ {} more", list_len - items.len());
+                    if max_length.fits(total_item_length + items.len() * 2 + extra_text.len()) {
+                        return Some(format!(
+                            "({}, {})",
+                            items.into_iter().join(", "),
+                            extra_text,
+                        ));
+                    }
                 }
+                let Some(popped) = items.pop() else {
+                    break;
+                };
+                total_item_length -= popped.len();
             }
 
-            let summary = format!("(list of {} items)", list_len);
-            if max_length.fits(summary.len()) {
-                summary
-            } else {
-                "…".to_string()
-            }
+            list_summary()
         }
         FormatValue::Struct(entries) => {
             // - all entries: `[Baz: 2, Foo: Bar]`
@@ -218,50 +302,76 @@ pub fn format_value<'a, T: 'a + Copy>(
             }
 
             let num_entries = entries.len();
+            let struct_summary = || {
+                let summary = format!("[struct with {} entries]", num_entries);
+                if max_length.fits(summary.len()) {
+                    summary
+                } else {
+                    "…".to_string()
+                }
+            };
+            if !limits.can_nest() || limits.shown_items(num_entries) == 0 {
+                return Some(struct_summary());
+            }
 
             let mut entries = entries
                 .iter()
                 .map(|(key, value)| {
-                    format_value(*key, Precedence::Low, MaxLength::Unlimited, visitor)
-                        .map(|key| (key, value))
+                    format_value_with_limits(
+                        *key,
+                        Precedence::Low,
+                        MaxLength::Unlimited,
+                        limits.nested(),
+                        visitor,
+                    )
+                    .map(|key| (key, value))
                 })
                 .collect::<Option<Vec<_>>>()?;
             entries.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+            entries.truncate(limits.shown_items(num_entries));
             let mut total_keys_length: usize = entries.iter().map(|(key, _)| key.len()).sum();
 
             // surrounding brackets, keys, and for each key colon + space + dots + comma + space
             if entries.len() < num_entries
                 || !max_length.fits(2 + total_keys_length + entries.len() * 5)
             {
-                // Not all keys fit. Try to remove the back ones, showing "+ X more" instead.
-                while let Some(popped) = entries.pop() {
-                    total_keys_length -= popped.0.len();
-                    let extra_text = format!("+ {} more", num_entries - entries.len());
-                    if max_length.fits(2 + total_keys_length + entries.len() * 5 + extra_text.len())
-                    {
-                        return Some(format!(
-                            "[{}, {}]",
-                            entries
-                                .into_iter()
-                                .map(|(key, _)| format!("{key}: …"))
-                                .join(", "),
-                            extra_text,
-                        ));
+                // Not all keys fit or are allowed to be shown. Try to remove the
+                // back ones, showing "+ X more" instead.
+                loop {
+                    if !entries.is_empty() && entries.len() < num_entries {
+                        let extra_text = format!("+ {} more", num_entries - entries.len());
+                        if max_length
+                            .fits(2 + total_keys_length + entries.len() * 5 + extra_text.len())
+                        {
+                            return Some(format!(
+                                "[{}, {}]",
+                                entries
+                                    .into_iter()
+                                    .map(|(key, _)| format!("{key}: …"))
+                                    .join(", "),
+                                extra_text,
+                            ));
+                        }
                     }
+                    let Some(popped) = entries.pop() else {
+                        break;
+                    };
+                    total_keys_length -= popped.0.len();
                 }
 
-                let summary = format!("[struct with {} entries]", num_entries);
-                return Some(if max_length.fits(summary.len()) {
-                    summary
-                } else {
-                    "…".to_string()
-                });
+                return Some(struct_summary());
             }
 
             let mut values = Vec::with_capacity(num_entries);
             let mut total_values_length = num_entries; // dots for every value
             for (_, value) in &entries {
-                let value = format_value(**value, Precedence::Low, MaxLength::Unlimited, visitor)?;
+                let value = format_value_with_limits(
+                    **value,
+                    Precedence::Low,
+                    MaxLength::Unlimited,
+                    limits.nested(),
+                    visitor,
+                )?;
                 total_values_length += value.len() - 1; // remove the dots, add the value
                 values.push(value);
 
@@ -319,3 +429,104 @@ pub fn format_value<'a, T: 'a + Copy>(
         .to_string(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{format_value_with_limits, FormatValue, MaxLength, NestingLimits, Precedence};
+    use num_bigint::BigInt;
+    use std::borrow::Cow;
+
+    /// Values are referenced by their index in an arena.
+    enum Value {
+        Int(i64),
+        Tag(&'static str, Option<usize>),
+        List(Vec<usize>),
+        Struct(Vec<(usize, usize)>),
+    }
+
+    fn format(arena: &[Value], value: usize, limits: NestingLimits) -> String {
+        format_value_with_limits(
+            value,
+            Precedence::Low,
+            MaxLength::Unlimited,
+            limits,
+            &|id| {
+                Some(match &arena[id] {
+                    Value::Int(int) => FormatValue::Int(Cow::Owned(BigInt::from(*int))),
+                    Value::Tag(symbol, value) => FormatValue::Tag {
+                        symbol,
+                        value: *value,
+                    },
+                    Value::List(items) => FormatValue::List(items),
+                    Value::Struct(entries) => FormatValue::Struct(Cow::Borrowed(entries)),
+                })
+            },
+        )
+        .unwrap()
+    }
+    const fn limits(max_depth: Option<usize>, max_items: Option<usize>) -> NestingLimits {
+        NestingLimits {
+            max_depth,
+            max_items,
+        }
+    }
+
+    #[test]
+    fn max_items_limits_lists_and_structs() {
+        let arena = vec![
+            Value::Int(1),
+            Value::Int(2),
+            Value::Int(3),
+            Value::List(vec![0, 1, 2]),
+            Value::Tag("Foo", None),
+            Value::Tag("Bar", None),
+            Value::Struct(vec![(4, 0), (5, 1)]),
+        ];
+        assert_eq!(format(&arena, 3, NestingLimits::UNLIMITED), "(1, 2, 3)");
+        assert_eq!(format(&arena, 3, limits(None, Some(2))), "(1, 2, + 1 more)");
+        assert_eq!(
+            format(&arena, 3, limits(None, Some(0))),
+            "(list of 3 items)"
+        );
+
+        assert_eq!(
+            format(&arena, 6, NestingLimits::UNLIMITED),
+            "[Bar: 2, Foo: 1]",
+        );
+        assert_eq!(
+            format(&arena, 6, limits(None, Some(1))),
+            "[Bar: …, + 1 more]"
+        );
+        assert_eq!(
+            format(&arena, 6, limits(None, Some(0))),
+            "[struct with 2 entries]",
+        );
+    }
+
+    #[test]
+    fn max_depth_collapses_nested_values() {
+        let arena = vec![
+            Value::Int(1),
+            Value::Tag("Foo", Some(0)),
+            Value::Tag("Foo", Some(1)),
+            Value::List(vec![2]),
+            Value::List(vec![3, 0]),
+        ];
+        assert_eq!(format(&arena, 2, NestingLimits::UNLIMITED), "Foo (Foo 1)");
+        assert_eq!(format(&arena, 2, limits(Some(1), None)), "Foo (Foo …)");
+        assert_eq!(format(&arena, 2, limits(Some(0), None)), "Foo …");
+
+        assert_eq!(
+            format(&arena, 4, NestingLimits::UNLIMITED),
+            "((Foo (Foo 1),), 1)",
+        );
+        assert_eq!(
+            format(&arena, 4, limits(Some(1), None)),
+            "((list of 1 items), 1)",
+        );
+        assert_eq!(
+            format(&arena, 4, limits(Some(0), None)),
+            "(list of 2 items)"
+        );
+    }
+}
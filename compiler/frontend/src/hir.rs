@@ -1,9 +1,14 @@
 use crate::{
     ast_to_hir::AstToHir,
-    builtin_functions::BuiltinFunction,
+    builtin_functions::{
+        builtin_function_documentation, BuiltinFunction, BuiltinFunctionDocumentation,
+    },
+    definitions::{definition_kinds, DefinitionKinds},
     error::CompilerError,
     impl_countable_id, impl_display_via_richir,
+    language_version::{LanguageFeature, LanguageVersion},
     module::{Module, ModuleKind, Package},
+    responsibility::{responsibility, Responsibility},
     rich_ir::{ReferenceKey, RichIrBuilder, ToRichIr, TokenType},
 };
 use derive_more::From;
@@ -24,6 +29,14 @@ pub trait HirDb: AstToHir {
     fn find_expression(&self, id: Id) -> Option<Expression>;
     fn containing_body_of(&self, id: Id) -> Arc<Body>;
     fn all_hir_ids(&self, module: Module) -> Vec<Id>;
+    /// See [`crate::responsibility`].
+    fn responsibility(&self, id: Id) -> Option<Responsibility>;
+    /// See [`crate::definitions`].
+    fn definition_kinds(&self, module: Module) -> DefinitionKinds;
+    fn builtin_function_documentation(
+        &self,
+        builtin_function: BuiltinFunction,
+    ) -> Arc<BuiltinFunctionDocumentation>;
 }
 #[allow(clippy::needless_pass_by_value)]
 fn find_expression(db: &dyn HirDb, id: Id) -> Option<Expression> {
@@ -44,11 +57,11 @@ fn containing_body_of(db: &dyn HirDb, id: Id) -> Arc<Body> {
         match db.find_expression(parent_id).unwrap() {
             Expression::Match { cases, .. } => {
                 let body = cases
-                    .into_iter()
-                    .map(|(_, body)| body)
+                    .iter()
+                    .flat_map(MatchCase::bodies)
                     .find(|body| body.expressions.contains_key(&id))
                     .unwrap();
-                Arc::new(body)
+                Arc::new(body.clone())
             }
             Expression::Function(function) => Arc::new(function.body),
             _ => panic!("Parent of an expression must be a function (or root scope)."),
@@ -90,7 +103,7 @@ impl Expression {
             Self::PatternIdentifierReference(_) => {}
             Self::Match { expression, cases } => {
                 ids.push(expression.clone());
-                for (_, body) in cases {
+                for body in cases.iter().flat_map(MatchCase::bodies) {
                     body.collect_all_ids(ids);
                 }
             }
@@ -315,10 +328,7 @@ pub enum Expression {
     PatternIdentifierReference(PatternIdentifierId),
     Match {
         expression: Id,
-        /// Each case consists of the pattern to match against, and the body
-        /// which starts with [PatternIdentifierReference]s for all identifiers
-        /// in the pattern.
-        cases: Vec<(Pattern, Body)>,
+        cases: Vec<MatchCase>,
     },
     Function(Function),
     Builtin(BuiltinFunction),
@@ -371,9 +381,36 @@ impl ToRichIr for PatternIdentifierId {
     }
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MatchCase {
+    pub pattern: Pattern,
+    /// The guard's condition. Like the body, it starts with
+    /// [Expression::PatternIdentifierReference]s for all identifiers in the
+    /// pattern and evaluates to the value of its last expression.
+    pub condition: Option<Body>,
+    /// Starts with [Expression::PatternIdentifierReference]s for all
+    /// identifiers in the pattern.
+    pub body: Body,
+}
+impl MatchCase {
+    /// Cases without a guard whose pattern is a wildcard or a single identifier
+    /// match every value.
+    #[must_use]
+    pub const fn is_catch_all(&self) -> bool {
+        self.condition.is_none()
+            && matches!(self.pattern, Pattern::NewIdentifier(_) | Pattern::Wildcard)
+    }
+
+    pub fn bodies(&self) -> impl Iterator<Item = &Body> {
+        self.condition.iter().chain([&self.body])
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Pattern {
     NewIdentifier(PatternIdentifierId),
+    /// `_`, which matches everything without capturing it.
+    Wildcard,
     Int(BigUint),
     Text(String),
     Tag {
@@ -381,7 +418,7 @@ pub enum Pattern {
         value: Option<Box<Pattern>>,
     },
     List(Vec<Pattern>),
-    // Keys may not contain `NewIdentifier`.
+    // Keys may not contain `NewIdentifier` or `Wildcard`.
     Struct(Vec<(Pattern, Pattern)>),
     Or(Vec<Pattern>),
     Error {
@@ -398,7 +435,7 @@ impl Pattern {
     pub fn contains_captured_identifiers(&self) -> bool {
         match self {
             Self::NewIdentifier(_) => true,
-            Self::Int(_) | Self::Text(_) => false,
+            Self::Wildcard | Self::Int(_) | Self::Text(_) => false,
             Self::Tag { value, .. } => value
                 .as_ref()
                 .map(|value| value.contains_captured_identifiers())
@@ -414,7 +451,7 @@ impl Pattern {
     pub fn captured_identifier_count(&self) -> usize {
         match self {
             Self::NewIdentifier(_) => 1,
-            Self::Int(_) | Self::Text(_) => 0,
+            Self::Wildcard | Self::Int(_) | Self::Text(_) => 0,
             Self::Tag { value, .. } => value
                 .as_ref()
                 .map(|value| value.captured_identifier_count())
@@ -445,7 +482,7 @@ impl Pattern {
     fn collect_captured_identifiers(&self, ids: &mut Vec<PatternIdentifierId>) {
         match self {
             Self::NewIdentifier(identifier_id) => ids.push(*identifier_id),
-            Self::Int(_) | Self::Text(_) => {}
+            Self::Wildcard | Self::Int(_) | Self::Text(_) => {}
             Self::Tag { value, .. } => {
                 if let Some(value) = value {
                     value.collect_captured_identifiers(ids);
@@ -526,11 +563,22 @@ impl Hash for Body {
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum HirError {
-    NeedsWithWrongNumberOfArguments { num_args: usize },
+    NeedsWithWrongNumberOfArguments {
+        num_args: usize,
+    },
     PatternContainsCall,
+    PatternStructKeyIsNotConstant,
     PublicAssignmentInNotTopLevel,
-    PublicAssignmentWithSameName { name: String },
-    UnknownReference { name: String },
+    PublicAssignmentWithSameName {
+        name: String,
+    },
+    RequiresNewerLanguageVersion {
+        feature: LanguageFeature,
+        version: LanguageVersion,
+    },
+    UnknownReference {
+        name: String,
+    },
 }
 
 impl Body {
@@ -569,7 +617,7 @@ impl ToRichIr for Expression {
             Self::Struct(fields) => {
                 builder.push("[", None, EnumSet::empty());
                 builder.push_children_custom(
-                    fields.iter().collect_vec(),
+                    fields.iter().sorted().collect_vec(),
                     |builder, (key, value)| {
                         key.build_rich_ir(builder);
                         builder.push(": ", None, EnumSet::empty());
@@ -594,9 +642,28 @@ impl ToRichIr for Expression {
             Self::Match { expression, cases } => {
                 expression.build_rich_ir(builder);
                 builder.push(" %", None, EnumSet::empty());
-                builder.push_children_custom_multiline(cases, |builder, (pattern, body)| {
-                    pattern.build_rich_ir(builder);
-                    builder.push(" ->", None, EnumSet::empty());
+                builder.push_children_custom_multiline(cases, |builder, case| {
+                    case.pattern.build_rich_ir(builder);
+                    if let Some(condition) = &case.condition {
+                        builder.push(" if", None, EnumSet::empty());
+                        builder.indent();
+                        builder.push_foldable(|builder| {
+                            builder.push_newline();
+                            condition.build_rich_ir(builder);
+                        });
+                        builder.dedent();
+                        builder.push_newline();
+                    }
+                    let body = &case.body;
+                    builder.push(
+                        if case.condition.is_some() {
+                            "->"
+                        } else {
+                            " ->"
+                        },
+                        None,
+                        EnumSet::empty(),
+                    );
                     builder.indent();
                     builder.push_foldable(|builder| {
                         if !body.expressions.is_empty() {
@@ -667,6 +734,9 @@ impl ToRichIr for Pattern {
                 builder.push(format!(r#""{text}""#), TokenType::Text, EnumSet::empty());
             }
             Self::NewIdentifier(reference) => reference.build_rich_ir(builder),
+            Self::Wildcard => {
+                builder.push("_", None, EnumSet::empty());
+            }
             Self::Tag { symbol, value } => {
                 builder.push(symbol, TokenType::Symbol, EnumSet::empty());
                 if let Some(value) = value {
@@ -771,7 +841,10 @@ impl Expression {
             Self::Destructure { .. } => None,
             Self::PatternIdentifierReference { .. } => None,
             // TODO: use binary search
-            Self::Match { cases, .. } => cases.iter().find_map(|(_, body)| body.find(id)),
+            Self::Match { cases, .. } => cases
+                .iter()
+                .flat_map(MatchCase::bodies)
+                .find_map(|body| body.find(id)),
             Self::Function(Function { body, .. }) => body.find(id),
             Self::Builtin(_) => None,
             Self::Call { .. } => None,
@@ -811,9 +884,11 @@ impl CollectErrors for Expression {
             | Self::Struct(_)
             | Self::PatternIdentifierReference { .. } => {}
             Self::Match { cases, .. } => {
-                for (pattern, body) in cases {
-                    pattern.collect_errors(errors);
-                    body.collect_errors(errors);
+                for case in cases {
+                    case.pattern.collect_errors(errors);
+                    for body in case.bodies() {
+                        body.collect_errors(errors);
+                    }
                 }
             }
             Self::Builtin(_) | Self::Call { .. } | Self::UseModule { .. } | Self::Needs { .. } => {}
@@ -830,7 +905,7 @@ impl CollectErrors for Expression {
 impl CollectErrors for Pattern {
     fn collect_errors(&self, errors: &mut Vec<CompilerError>) {
         match self {
-            Self::NewIdentifier(_) | Self::Int(_) | Self::Text(_) => {}
+            Self::NewIdentifier(_) | Self::Wildcard | Self::Int(_) | Self::Text(_) => {}
             Self::Tag { value, .. } => {
                 if let Some(value) = value {
                     value.collect_errors(errors);
@@ -3,11 +3,12 @@ use super::{
     cst::CstDb,
     error::CompilerError,
     hir,
-    mir::{BodyBuilder, Expression, Id, Mir},
+    mir::{BodyBuilder, Expression, Id, Mir, MirError},
     tracing::TracingConfig,
 };
 use crate::{
     builtin_functions::BuiltinFunction,
+    format::{MaxLength, NestingLimits},
     module::{Module, ModuleKind},
     position::PositionConversionDb,
     string_to_rcst::ModuleError,
@@ -59,6 +60,7 @@ fn mir(db: &dyn HirToMir, target: ExecutionTarget, tracing: TracingConfig) -> Mi
                 &tracing,
                 &mut errors,
             );
+            warn_about_unreachable_match_cases(db, &hir, &mut errors);
             (mir, errors)
         }
         ModuleKind::Asset => {
@@ -77,6 +79,44 @@ fn mir(db: &dyn HirToMir, target: ExecutionTarget, tracing: TracingConfig) -> Mi
     Ok((Arc::new(mir), Arc::new(errors)))
 }
 
+/// Cases after a catch-all case (e.g., `_ -> …`) can never be reached.
+fn warn_about_unreachable_match_cases(
+    db: &dyn HirToMir,
+    body: &hir::Body,
+    errors: &mut FxHashSet<CompilerError>,
+) {
+    for expression in body.expressions.values() {
+        match expression {
+            hir::Expression::Match { cases, .. } => {
+                let mut is_after_catch_all = false;
+                for case in cases {
+                    // The body starts with the identifiers of the pattern, so
+                    // this usually points into the pattern.
+                    if is_after_catch_all
+                        && let Some(first_id) = case.body.expressions.keys().next()
+                        && let Some(span) = db.hir_id_to_span(first_id)
+                    {
+                        errors.insert(CompilerError {
+                            module: first_id.module.clone(),
+                            span,
+                            payload: MirError::UnreachableMatchCase.into(),
+                        });
+                    }
+                    is_after_catch_all |= case.is_catch_all();
+
+                    for body in case.bodies() {
+                        warn_about_unreachable_match_cases(db, body, errors);
+                    }
+                }
+            }
+            hir::Expression::Function(function) => {
+                warn_about_unreachable_match_cases(db, &function.body, errors);
+            }
+            _ => {}
+        }
+    }
+}
+
 /// In the MIR, there's no longer the concept of needs. Instead, HIR IDs are
 /// first-class expressions and there's a `panic` expression that takes a HIR
 /// ID that's responsible.
@@ -323,8 +363,11 @@ impl<'a> LoweringContext<'a> {
                 body.push_list(items.iter().map(|item| self.mapping[item]).collect())
             }
             hir::Expression::Struct(fields) => {
+                // The order of fields in the map depends on the hashes of their
+                // IDs, which include the module's path.
                 let fields = fields
                     .iter()
+                    .sorted()
                     .map(|(key, value)| (self.mapping[key], self.mapping[value]))
                     .collect();
                 body.push_struct(fields)
@@ -516,7 +559,7 @@ impl<'a> LoweringContext<'a> {
         hir_id: hir::Id,
         body: &mut BodyBuilder,
         expression: Id,
-        cases: &[(hir::Pattern, hir::Body)],
+        cases: &[hir::MatchCase],
         responsible_for_needs: Id,
         responsible_for_match: Id,
     ) -> Id {
@@ -537,7 +580,7 @@ impl<'a> LoweringContext<'a> {
         hir_id: hir::Id,
         body: &mut BodyBuilder,
         expression: Id,
-        cases: &[(hir::Pattern, hir::Body)],
+        cases: &[hir::MatchCase],
         responsible_for_needs: Id,
         responsible_for_match: Id,
         mut no_match_reasons: Vec<Id>,
@@ -549,34 +592,67 @@ impl<'a> LoweringContext<'a> {
                 // TODO: concat reasons
                 body.push_panic(reason, responsible_for_match)
             }
-            [(case_pattern, case_body), rest @ ..] => {
+            [case, rest @ ..] => {
                 let pattern_result = PatternLoweringContext::compile_pattern(
                     body,
                     hir_id.clone(),
                     responsible_for_match,
                     expression,
-                    case_pattern,
+                    &case.pattern,
                 );
 
-                let is_match = body.push_is_match(pattern_result, responsible_for_match);
-
                 let case_id = hir_id.child(format!("case-{case_index}"));
                 let builtin_if_else = body.push_builtin(BuiltinFunction::IfElse);
+
+                let mut is_match = body.push_is_match(pattern_result, responsible_for_match);
+                if let Some(condition) = &case.condition {
+                    // The guard is only evaluated after the pattern matched,
+                    // so it can use the captured identifiers.
+                    let check_guard = body.push_function(case_id.child("checkGuard"), |body, _| {
+                        self.ongoing_destructuring = Some(OngoingDestructuring {
+                            result: pattern_result,
+                            is_trivial: false,
+                        });
+                        self.compile_expressions(
+                            body,
+                            responsible_for_needs,
+                            &condition.expressions,
+                        );
+                    });
+                    let pattern_did_not_match =
+                        body.push_function(case_id.child("patternDidNotMatch"), |body, _| {
+                            body.push_bool(false);
+                        });
+                    is_match = body.push_call(
+                        builtin_if_else,
+                        vec![is_match, check_guard, pattern_did_not_match],
+                        responsible_for_match,
+                    );
+                }
+
                 let then_function = body.push_function(case_id.child("matched"), |body, _| {
                     self.ongoing_destructuring = Some(OngoingDestructuring {
                         result: pattern_result,
                         is_trivial: false,
                     });
-                    self.compile_expressions(body, responsible_for_needs, &case_body.expressions);
+                    self.compile_expressions(body, responsible_for_needs, &case.body.expressions);
                 });
                 let else_function = body.push_function(case_id.child("didNotMatch"), |body, _| {
-                    let list_get_function = body.push_builtin(BuiltinFunction::ListGet);
-                    let one = body.push_int(1);
-                    let reason = body.push_call(
-                        list_get_function,
-                        vec![pattern_result, one],
-                        responsible_for_match,
-                    );
+                    let reason = if case.condition.is_some() {
+                        // The pattern may have matched, so the result doesn't
+                        // necessarily contain a reason.
+                        body.push_text(
+                            "The pattern didn't match or the guard was false.".to_string(),
+                        )
+                    } else {
+                        let list_get_function = body.push_builtin(BuiltinFunction::ListGet);
+                        let one = body.push_int(1);
+                        body.push_call(
+                            list_get_function,
+                            vec![pattern_result, one],
+                            responsible_for_match,
+                        )
+                    };
                     no_match_reasons.push(reason);
 
                     self.compile_match_rec(
@@ -630,6 +706,7 @@ impl PatternLoweringContext {
     fn compile(&self, body: &mut BodyBuilder, expression: Id, pattern: &hir::Pattern) -> Id {
         match pattern {
             hir::Pattern::NewIdentifier(_) => self.push_match(body, vec![expression]),
+            hir::Pattern::Wildcard => self.push_match(body, vec![]),
             hir::Pattern::Int(int) => {
                 let expected = body.push_int(int.clone());
                 self.compile_exact_value(body, expression, expected)
@@ -668,8 +745,7 @@ impl PatternLoweringContext {
                             } else {
                                 let builtin_tag_get_value = body.push_builtin(BuiltinFunction::TagGetValue);
                                 let actual_value = body.push_call(builtin_tag_get_value, vec![expression], self.responsible);
-                                let builtin_to_debug_text = body.push_builtin(BuiltinFunction::ToDebugText);
-                                let actual_value_text = body.push_call(builtin_to_debug_text, vec![actual_value], self.responsible);
+                                let actual_value_text = self.push_to_debug_text_for_message(body, actual_value);
                                 vec![
                                     body.push_text("Expected tag to not have a value, but it has one: `".to_string()),
                                     actual_value_text,
@@ -767,20 +843,10 @@ impl PatternLoweringContext {
                                         self.compile(body, value, value_pattern);
                                     },
                                     |body| {
-                                        let to_debug_text =
-                                            body.push_builtin(BuiltinFunction::ToDebugText);
-
-                                        let key_as_text = body.push_call(
-                                            to_debug_text,
-                                            vec![key],
-                                            self.responsible,
-                                        );
-
-                                        let struct_as_text = body.push_call(
-                                            to_debug_text,
-                                            vec![expression],
-                                            self.responsible,
-                                        );
+                                        let key_as_text =
+                                            self.push_to_debug_text_for_message(body, key);
+                                        let struct_as_text =
+                                            self.push_to_debug_text_for_message(body, expression);
 
                                         let reason_parts = vec![
                                             body.push_text(
@@ -929,10 +995,8 @@ impl PatternLoweringContext {
             equals,
             then_builder,
             |body| {
-                let to_debug_text = body.push_builtin(BuiltinFunction::ToDebugText);
-                let expected_as_text =
-                    body.push_call(to_debug_text, vec![expected], self.responsible);
-                let actual_as_text = body.push_call(to_debug_text, vec![actual], self.responsible);
+                let expected_as_text = self.push_to_debug_text_for_message(body, expected);
+                let actual_as_text = self.push_to_debug_text_for_message(body, actual);
                 let reason_parts = reason_factory(body, expected_as_text, actual_as_text);
                 let reason = self.push_text_concatenate(body, reason_parts);
                 self.push_no_match(body, reason);
@@ -946,8 +1010,8 @@ impl PatternLoweringContext {
         pattern: &hir::Pattern,
     ) -> Id {
         match pattern {
-            hir::Pattern::NewIdentifier(_) => {
-                panic!("New identifiers can't be used in this part of a pattern.")
+            hir::Pattern::NewIdentifier(_) | hir::Pattern::Wildcard => {
+                panic!("New identifiers and wildcards can't be used in this part of a pattern.")
             }
             hir::Pattern::Int(int) => body.push_int(int.clone()),
             hir::Pattern::Text(text) => body.push_text(text.clone()),
@@ -1026,6 +1090,27 @@ impl PatternLoweringContext {
             .unwrap()
     }
 
+    /// Formats a value for a panic message. Huge values are shortened so that
+    /// they don't flood the output.
+    fn push_to_debug_text_for_message(&self, body: &mut BodyBuilder, value: Id) -> Id {
+        let builtin_to_debug_text = body.push_builtin(BuiltinFunction::ToDebugTextWithLimits);
+        let mut push_limit = |limit: Option<usize>| match limit {
+            Some(limit) => body.push_int(limit),
+            None => body.push_tag("Unlimited".to_string(), None),
+        };
+        let max_depth = push_limit(NestingLimits::FOR_MESSAGES.max_depth);
+        let max_items = push_limit(NestingLimits::FOR_MESSAGES.max_items);
+        let max_length = push_limit(match NestingLimits::MAX_LENGTH_FOR_MESSAGES {
+            MaxLength::Unlimited => None,
+            MaxLength::Limited(max_length) => Some(max_length),
+        });
+        body.push_call(
+            builtin_to_debug_text,
+            vec![value, max_depth, max_items, max_length],
+            self.responsible,
+        )
+    }
+
     fn push_match(&self, body: &mut BodyBuilder, mut captured_identifiers: Vec<Id>) -> Id {
         captured_identifiers.insert(0, self.match_tag);
         body.push_list(captured_identifiers)
@@ -1073,3 +1158,45 @@ impl BodyBuilder {
         self.push_panic(reason, responsible)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{error::CompilerErrorPayload, test_database::Database};
+
+    /// The code that the unreachable-case warnings point to.
+    fn unreachable_cases(source: &str) -> Vec<String> {
+        let (db, module) = Database::with_main_module(source);
+        let (_, errors) = db
+            .mir(ExecutionTarget::Module(module), TracingConfig::off())
+            .unwrap();
+        errors
+            .iter()
+            .filter(|error| {
+                error.payload == CompilerErrorPayload::Mir(MirError::UnreachableMatchCase)
+            })
+            .map(|error| source[*error.span.start..*error.span.end].to_string())
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn cases_after_a_catch_all_are_unreachable() {
+        assert_eq!(
+            unreachable_cases("foo = 1 %\n  _ -> 1\n  bar -> 2\n  2 -> baz\n"),
+            vec!["bar", "baz"],
+        );
+        assert_eq!(
+            unreachable_cases("foo = 1 %\n  bar -> 1\n  _ -> 2\n"),
+            vec!["2"],
+        );
+    }
+
+    #[test]
+    fn guarded_cases_are_not_catch_alls() {
+        assert_eq!(
+            unreachable_cases("foo = 1 %\n  bar if False -> 1\n  _ -> 2\n"),
+            Vec::<String>::new(),
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Newer syntax is gated behind a language version, so packages written for an
+//! older version keep their meaning while the language evolves.
+//!
+//! A module can declare its version with a comment before any code:
+//!
+//! ```candy
+//! # candy 0.1
+//! ```
+//!
+//! Modules without such a comment use the version configured in the package's
+//! `_package.candy`:
+//!
+//! ```candy
+//! languageVersion := "0.1"
+//! ```
+//!
+//! Like the lints, the manifest isn't executed, so the version must be written
+//! as a literal text. Without any configuration, the latest version is used.
+
+use crate::{
+    ast::{Assignment, AssignmentBody, Ast, AstKind, Identifier, Text, TextPart},
+    cst::{Cst, CstKind},
+    cst_to_ast::CstToAst,
+    module::Module,
+};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use tracing::warn;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LanguageVersion {
+    V0_1,
+    /// Adds wildcard patterns and guards in match cases.
+    V0_2,
+}
+impl LanguageVersion {
+    pub const LATEST: Self = Self::V0_2;
+
+    #[must_use]
+    pub fn supports(self, feature: LanguageFeature) -> bool {
+        self >= feature.introduced_in()
+    }
+}
+impl FromStr for LanguageVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0.1" => Ok(Self::V0_1),
+            "0.2" => Ok(Self::V0_2),
+            _ => Err(()),
+        }
+    }
+}
+impl Display for LanguageVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::V0_1 => write!(f, "0.1"),
+            Self::V0_2 => write!(f, "0.2"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LanguageFeature {
+    /// `_` in patterns
+    WildcardPatterns,
+    /// `pattern if condition -> body`
+    MatchCaseGuards,
+}
+impl LanguageFeature {
+    #[must_use]
+    pub const fn introduced_in(self) -> LanguageVersion {
+        match self {
+            Self::WildcardPatterns | Self::MatchCaseGuards => LanguageVersion::V0_2,
+        }
+    }
+}
+impl Display for LanguageFeature {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::WildcardPatterns => write!(f, "Wildcard patterns"),
+            Self::MatchCaseGuards => write!(f, "Match case guards"),
+        }
+    }
+}
+
+/// The version declared by the module's leading comment, falling back to the
+/// package's manifest and then to the latest version.
+#[allow(clippy::needless_pass_by_value)]
+pub fn language_version(db: &dyn CstToAst, module: Module) -> LanguageVersion {
+    pragma_version(db, &module)
+        .or_else(|| manifest_version(db, &module))
+        .unwrap_or(LanguageVersion::LATEST)
+}
+
+fn pragma_version(db: &dyn CstToAst, module: &Module) -> Option<LanguageVersion> {
+    let cst = db.cst(module.clone()).ok()?;
+    let version = cst
+        .iter()
+        .take_while(|it| it.kind.is_whitespace_or_comment())
+        .find_map(pragma)?;
+    parse_version(version, || format!("The leading comment of {module}"))
+}
+fn pragma(cst: &Cst) -> Option<&str> {
+    match &cst.kind {
+        CstKind::Comment { comment, .. } => comment
+            .trim_start_matches('#')
+            .trim()
+            .strip_prefix("candy "),
+        CstKind::TrailingWhitespace { child, .. } => pragma(child),
+        _ => None,
+    }
+}
+
+fn manifest_version(db: &dyn CstToAst, module: &Module) -> Option<LanguageVersion> {
+    let manifest = module.package.manifest();
+    let (asts, _) = db.ast(manifest.clone()).ok()?;
+    let version = asts.iter().find_map(language_version_text)?;
+    parse_version(version, || format!("The `languageVersion` in {manifest}"))
+}
+fn language_version_text(ast: &Ast) -> Option<&str> {
+    let AstKind::Assignment(Assignment {
+        body: AssignmentBody::Body { pattern, body },
+        ..
+    }) = &ast.kind
+    else {
+        return None;
+    };
+    let AstKind::Identifier(Identifier(name)) = &pattern.kind else {
+        return None;
+    };
+    if name.value != "languageVersion" {
+        return None;
+    }
+    let AstKind::Text(Text(parts)) = &body.last()?.kind else {
+        return None;
+    };
+    match parts.as_slice() {
+        [part] => match &part.kind {
+            AstKind::TextPart(TextPart(text)) => Some(&text.value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_version(version: &str, source: impl FnOnce() -> String) -> Option<LanguageVersion> {
+    let parsed = LanguageVersion::from_str(version.trim()).ok();
+    if parsed.is_none() {
+        warn!(
+            "{} declares the unknown language version `{version}`.",
+            source()
+        );
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_supports() {
+        assert!(!LanguageVersion::V0_1.supports(LanguageFeature::WildcardPatterns));
+        assert!(LanguageVersion::V0_2.supports(LanguageFeature::MatchCaseGuards));
+        assert!(LanguageVersion::LATEST.supports(LanguageFeature::WildcardPatterns));
+    }
+
+    #[test]
+    fn test_parse() {
+        for version in [LanguageVersion::V0_1, LanguageVersion::V0_2] {
+            assert_eq!(LanguageVersion::from_str(&version.to_string()), Ok(version));
+        }
+        assert_eq!(LanguageVersion::from_str("0.3"), Err(()));
+    }
+}
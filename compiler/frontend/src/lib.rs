@@ -27,21 +27,32 @@ pub mod builtin_functions;
 pub mod comment;
 pub mod cst;
 pub mod cst_to_ast;
+pub mod definitions;
+pub mod deprecation;
+pub mod desugar;
 pub mod error;
 pub mod format;
 pub mod hir;
 pub mod hir_to_mir;
 pub mod id;
+pub mod language_version;
+pub mod lints;
 pub mod lir;
 pub mod lir_optimize;
 pub mod mir;
 pub mod mir_optimize;
 pub mod mir_to_lir;
 pub mod module;
+pub mod naming_conventions;
 pub mod position;
 pub mod rcst;
 pub mod rcst_to_cst;
+pub mod refactorings;
+pub mod responsibility;
 pub mod rich_ir;
 pub mod string_to_rcst;
+#[cfg(any(test, feature = "testing"))]
+pub mod test_database;
 pub mod tracing;
+pub mod trivia;
 pub mod utils;
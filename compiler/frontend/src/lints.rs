@@ -0,0 +1,206 @@
+//! Warnings are lints whose level can be configured per package in the
+//! package's `_package.candy`:
+//!
+//! ```candy
+//! lints := [
+//!   NeedsConditionIsAlwaysTrue: Allow,
+//!   UnreachableMatchCase: Deny,
+//! ]
+//! ```
+//!
+//! The manifest isn't executed, so the `lints` struct must be written literally
+//! with symbols as keys and values. Lints that aren't mentioned keep the level
+//! `Warn`. Tools can override levels on top of the manifest, e.g., for the
+//! CLI's `--deny warnings`.
+
+use crate::{
+    ast::{Assignment, AssignmentBody, Ast, AstKind, Identifier, Struct, Symbol},
+    cst_to_ast::CstToAst,
+    error::CompilerErrorPayload,
+    mir::MirError,
+    module::Package,
+};
+use rustc_hash::FxHashMap;
+use std::{str::FromStr, sync::Arc};
+use strum_macros::{AsRefStr, EnumString};
+use tracing::warn;
+
+#[derive(AsRefStr, Clone, Copy, Debug, EnumString, Eq, Hash, PartialEq)]
+pub enum Lint {
+    NeedsConditionIsAlwaysTrue,
+    NeedsConditionIsAlwaysFalse,
+    UnreachableMatchCase,
+    UsesDeprecatedExport,
+    UnconventionalName,
+}
+impl Lint {
+    #[must_use]
+    pub const fn of(payload: &CompilerErrorPayload) -> Option<Self> {
+        match payload {
+            CompilerErrorPayload::Mir(error) => match error {
+                MirError::NeedsConditionIsAlwaysTrue => Some(Self::NeedsConditionIsAlwaysTrue),
+                MirError::NeedsConditionIsAlwaysFalse => Some(Self::NeedsConditionIsAlwaysFalse),
+                MirError::UnreachableMatchCase => Some(Self::UnreachableMatchCase),
+                MirError::UsesDeprecatedExport { .. } => Some(Self::UsesDeprecatedExport),
+                MirError::UnconventionalName { .. } => Some(Self::UnconventionalName),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(AsRefStr, Clone, Copy, Debug, EnumString, Eq, Hash, PartialEq)]
+pub enum LintLevel {
+    /// The lint isn't reported.
+    Allow,
+    /// The lint is reported as a warning.
+    Warn,
+    /// The lint is reported as an error, so checks fail.
+    Deny,
+}
+
+pub type LintLevels = Arc<FxHashMap<Lint, LintLevel>>;
+
+/// The levels configured in the `lints` section of the package's manifest.
+#[allow(clippy::needless_pass_by_value)]
+pub fn lint_levels(db: &dyn CstToAst, package: Package) -> LintLevels {
+    let manifest = package.manifest();
+    let Ok((asts, _)) = db.ast(manifest.clone()) else {
+        return Arc::default();
+    };
+    let Some(fields) = asts.iter().find_map(lints_struct_fields) else {
+        return Arc::default();
+    };
+
+    let mut levels = FxHashMap::default();
+    for (key, value) in fields {
+        let (Some(key), value) = (key.as_ref().and_then(symbol), symbol(value)) else {
+            warn!("The lints in {manifest} must map symbols to symbols.");
+            continue;
+        };
+        match (Lint::from_str(key), value.map(LintLevel::from_str)) {
+            (Ok(lint), Some(Ok(level))) => {
+                levels.insert(lint, level);
+            }
+            (Err(_), _) => warn!("{manifest} configures the unknown lint `{key}`."),
+            (_, _) => {
+                warn!("The level of `{key}` in {manifest} must be `Allow`, `Warn`, or `Deny`.");
+            }
+        }
+    }
+    Arc::new(levels)
+}
+fn lints_struct_fields(ast: &Ast) -> Option<&Vec<(Option<Ast>, Ast)>> {
+    let AstKind::Assignment(Assignment {
+        body: AssignmentBody::Body { pattern, body },
+        ..
+    }) = &ast.kind
+    else {
+        return None;
+    };
+    let AstKind::Identifier(Identifier(name)) = &pattern.kind else {
+        return None;
+    };
+    if name.value != "lints" {
+        return None;
+    }
+    match &body.last()?.kind {
+        AstKind::Struct(Struct { fields }) => Some(fields),
+        _ => None,
+    }
+}
+fn symbol(ast: &Ast) -> Option<&str> {
+    match &ast.kind {
+        AstKind::Symbol(Symbol(symbol)) => Some(&symbol.value),
+        _ => None,
+    }
+}
+
+/// Decides how warnings are reported, based on the package's manifest and
+/// overrides.
+#[derive(Clone, Debug)]
+pub struct LintLevelResolver {
+    levels: FxHashMap<Lint, LintLevel>,
+    /// The level of lints that would be warnings otherwise.
+    warnings_level: LintLevel,
+}
+impl LintLevelResolver {
+    #[must_use]
+    pub fn for_package(db: &dyn CstToAst, package: Package) -> Self {
+        Self {
+            levels: (*db.lint_levels(package)).clone(),
+            warnings_level: LintLevel::Warn,
+        }
+    }
+
+    /// Overrides the level configured in the manifest.
+    pub fn set_level(&mut self, lint: Lint, level: LintLevel) {
+        self.levels.insert(lint, level);
+    }
+    /// Changes the level of all lints that would be warnings, e.g., to report
+    /// them as errors instead. Lints with other levels keep them.
+    pub fn set_warnings_level(&mut self, level: LintLevel) {
+        self.warnings_level = level;
+    }
+
+    /// Returns `None` for errors that are not lints.
+    #[must_use]
+    pub fn level_of(&self, payload: &CompilerErrorPayload) -> Option<LintLevel> {
+        let level = self
+            .levels
+            .get(&Lint::of(payload)?)
+            .copied()
+            .unwrap_or(LintLevel::Warn);
+        Some(if level == LintLevel::Warn {
+            self.warnings_level
+        } else {
+            level
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::module::Module;
+
+    #[test]
+    fn test_warnings_level() {
+        let mut resolver = LintLevelResolver {
+            levels: FxHashMap::from_iter([
+                (Lint::UnreachableMatchCase, LintLevel::Allow),
+                (Lint::UsesDeprecatedExport, LintLevel::Deny),
+            ]),
+            warnings_level: LintLevel::Warn,
+        };
+        let level_of = |resolver: &LintLevelResolver, error: MirError| {
+            resolver.level_of(&CompilerErrorPayload::Mir(error))
+        };
+
+        assert_eq!(
+            level_of(&resolver, MirError::NeedsConditionIsAlwaysTrue),
+            Some(LintLevel::Warn),
+        );
+        assert_eq!(
+            level_of(&resolver, MirError::UnreachableMatchCase),
+            Some(LintLevel::Allow),
+        );
+
+        resolver.set_warnings_level(LintLevel::Deny);
+        assert_eq!(
+            level_of(&resolver, MirError::NeedsConditionIsAlwaysTrue),
+            Some(LintLevel::Deny),
+        );
+        assert_eq!(
+            level_of(&resolver, MirError::UnreachableMatchCase),
+            Some(LintLevel::Allow),
+        );
+        let deprecated = MirError::UsesDeprecatedExport {
+            module: Module::from_package_name("Foo".to_string()),
+            name: "foo".to_string(),
+            hint: String::new(),
+        };
+        assert_eq!(level_of(&resolver, deprecated), Some(LintLevel::Deny));
+    }
+}
@@ -44,6 +44,10 @@ impl Bodies {
     pub fn get(&self, id: BodyId) -> &Body {
         &self.0[id.to_usize()]
     }
+    #[must_use]
+    pub fn get_mut(&mut self, id: BodyId) -> &mut Body {
+        &mut self.0[id.to_usize()]
+    }
     pub fn push(&mut self, body: Body) -> BodyId {
         let id = BodyId::from_usize(self.0.len());
         self.0.push(body);
@@ -144,6 +148,10 @@ impl Body {
         &self.original_hirs
     }
 
+    pub fn add_original_hirs(&mut self, original_hirs: impl IntoIterator<Item = hir::Id>) {
+        self.original_hirs.extend(original_hirs);
+    }
+
     #[must_use]
     pub const fn captured_count(&self) -> usize {
         self.captured_count
@@ -170,6 +178,10 @@ impl Body {
         &self.expressions
     }
     #[must_use]
+    pub fn expressions_mut(&mut self) -> &mut [Expression] {
+        &mut self.expressions
+    }
+    #[must_use]
     pub fn expression(&self, id: Id) -> Option<&Expression> {
         let expression_id_offset = self.expression_id_offset();
         if id.to_usize() < expression_id_offset {
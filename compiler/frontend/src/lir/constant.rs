@@ -148,7 +148,7 @@ impl Constant {
             Self::Struct(fields) => {
                 builder.push("[", None, EnumSet::empty());
                 builder.push_children_custom(
-                    fields.iter().collect_vec(),
+                    fields.iter().sorted().collect_vec(),
                     |builder, (key, value)| {
                         key.build_rich_ir_with_constants(builder, constants);
                         builder.push(": ", None, EnumSet::empty());
@@ -7,7 +7,7 @@ use derive_more::From;
 use enumset::EnumSet;
 use itertools::Itertools;
 
-#[derive(Clone, Debug, Eq, From, PartialEq)]
+#[derive(Clone, Debug, Eq, From, Hash, PartialEq)]
 pub enum Expression {
     CreateTag {
         symbol: String,
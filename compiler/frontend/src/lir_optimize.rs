@@ -1,8 +1,31 @@
+//! Optimizations on the LIR.
+//!
+//! Each body gets its reference counting adjustments combined, i.e., dups and
+//! drops of the same value cancel each other out.
+//!
+//! Afterwards, we deduplicate bodies: Desugaring generates many small functions
+//! that only differ in which values they capture. Common subtree elimination in
+//! the MIR can't merge those because they refer to different captured IDs. In
+//! the LIR, however, captured values, parameters, and locals are numbered by
+//! their position, so such functions compile to identical bodies. We only keep
+//! the first one of them and update all references:
+//!
+//! ```lir
+//! body_0 (responsible $1) =         |  body_0 (responsible $1) =
+//!   $2 = $0                         |    $2 = $0
+//! body_1 (responsible $1) =         |  body_1 $0 (+ responsible $1) =
+//!   $2 = $0                         |    $2 = { body_0 capturing $0 }
+//! body_2 $0 (+ responsible $1) =    |    $3 = { body_0 capturing $0 }
+//!   $2 = { body_0 capturing $0 }    |    ...
+//!   $3 = { body_1 capturing $0 }    |
+//!   ...                             |
+//! ```
+
 use crate::{
     hir_to_mir::ExecutionTarget,
-    lir::{Bodies, Body, Expression, Id, Lir},
+    lir::{Bodies, Body, BodyId, Constant, ConstantId, Constants, Expression, Id, Lir},
     mir_to_lir::{LirResult, MirToLir},
-    utils::{HashMapExtension, HashSetExtension},
+    utils::{DoHash, HashMapExtension, HashSetExtension},
     TracingConfig,
 };
 use itertools::Itertools;
@@ -22,16 +45,105 @@ fn optimized_lir(
 ) -> LirResult {
     let (lir, errors) = db.lir(target, tracing)?;
 
-    let mut bodies = Bodies::default();
+    let mut deduplication = BodyDeduplication::new(lir.constants());
+    let module_body_id = lir.bodies().ids_and_bodies().last().unwrap().0;
     for (id, body) in lir.bodies().ids_and_bodies() {
-        let new_id = bodies.push(body.optimize());
-        assert_eq!(id, new_id);
+        // The module body has to stay the last one.
+        deduplication.add(id, body.optimize(), id != module_body_id);
     }
+    let (constants, bodies) = deduplication.finish();
 
-    let optimized_lir = Lir::new(lir.constants().clone(), bodies);
+    let optimized_lir = Lir::new(constants, bodies);
     Ok((Arc::new(optimized_lir), errors))
 }
 
+/// Bodies are ordered so that a function's body comes before the bodies that
+/// create the function. Hence, when we add a body, all functions it refers to
+/// are already deduplicated.
+struct BodyDeduplication<'c> {
+    constants: &'c Constants,
+    bodies: Bodies,
+    /// Maps old body IDs to IDs in [bodies].
+    body_mapping: FxHashMap<BodyId, BodyId>,
+    /// Maps hashes of bodies' code to candidates for deduplication.
+    bodies_by_hash: FxHashMap<u64, Vec<BodyId>>,
+    /// For each new body, the first constant referring to it.
+    function_constants: FxHashMap<BodyId, ConstantId>,
+}
+impl<'c> BodyDeduplication<'c> {
+    fn new(constants: &'c Constants) -> Self {
+        Self {
+            constants,
+            bodies: Bodies::default(),
+            body_mapping: FxHashMap::default(),
+            bodies_by_hash: FxHashMap::default(),
+            function_constants: FxHashMap::default(),
+        }
+    }
+
+    fn add(&mut self, old_id: BodyId, mut body: Body, allow_deduplication: bool) {
+        for expression in body.expressions_mut() {
+            match expression {
+                Expression::CreateFunction { body_id, .. } => {
+                    *body_id = self.body_mapping[&*body_id];
+                }
+                Expression::Constant(constant_id) => {
+                    *constant_id = self.canonical_constant(*constant_id);
+                }
+                _ => {}
+            }
+        }
+
+        let hash = (
+            body.captured_count(),
+            body.parameter_count(),
+            body.expressions(),
+        )
+            .do_hash();
+        let candidates = self.bodies_by_hash.entry(hash).or_default();
+        if allow_deduplication
+            && let Some(canonical_id) = candidates
+                .iter()
+                .find(|it| self.bodies.get(**it).has_same_code_as(&body))
+        {
+            let canonical_id = *canonical_id;
+            self.bodies
+                .get_mut(canonical_id)
+                .add_original_hirs(body.original_hirs().iter().cloned());
+            self.body_mapping.force_insert(old_id, canonical_id);
+            return;
+        }
+
+        let new_id = self.bodies.push(body);
+        candidates.push(new_id);
+        self.body_mapping.force_insert(old_id, new_id);
+    }
+    /// Constants of deduplicated functions get deduplicated as well so that
+    /// bodies creating the same functions become identical.
+    fn canonical_constant(&mut self, id: ConstantId) -> ConstantId {
+        let Constant::Function(body_id) = self.constants.get(id) else {
+            return id;
+        };
+        *self
+            .function_constants
+            .entry(self.body_mapping[body_id])
+            .or_insert(id)
+    }
+
+    fn finish(self) -> (Constants, Bodies) {
+        let mut constants = Constants::default();
+        for (id, constant) in self.constants.ids_and_constants() {
+            let constant = match constant {
+                Constant::Function(body_id) => Constant::Function(self.body_mapping[body_id]),
+                constant => constant.clone(),
+            };
+            let new_id = constants.push(constant);
+            assert_eq!(id, new_id);
+        }
+        (constants, self.bodies)
+    }
+}
+
 impl Body {
     fn optimize(&self) -> Self {
         let mut new_body = Self::new(
@@ -99,6 +211,12 @@ impl Body {
 
         new_body
     }
+    fn has_same_code_as(&self, other: &Self) -> bool {
+        self.captured_count() == other.captured_count()
+            && self.parameter_count() == other.parameter_count()
+            && self.expressions() == other.expressions()
+    }
+
     fn maybe_dup(
         &mut self,
         to_dup: &mut FxHashMap<Id, usize>,
@@ -148,3 +266,79 @@ impl Body {
         (to_dup, to_drop)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::OptimizeLir;
+    use crate::{
+        hir_to_mir::ExecutionTarget,
+        lir::{BodyId, Expression, Lir},
+        mir_to_lir::MirToLir,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+
+    /// The unoptimized and optimized LIR of the module.
+    fn lirs(source: &str) -> (Lir, Lir) {
+        let mut db = Database::default();
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+        let target = ExecutionTarget::Module(module);
+        let (lir, _) = db.lir(target.clone(), TracingConfig::off()).unwrap();
+        let (optimized_lir, _) = db.optimized_lir(target, TracingConfig::off()).unwrap();
+        ((*lir).clone(), (*optimized_lir).clone())
+    }
+    /// The bodies of functions that capture values, in the order in which they
+    /// are created.
+    fn created_function_bodies(lir: &Lir) -> Vec<BodyId> {
+        lir.bodies()
+            .ids_and_bodies()
+            .flat_map(|(_, body)| body.expressions())
+            .filter_map(|expression| match expression {
+                Expression::CreateFunction { body_id, .. } => Some(*body_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn functions_that_only_capture_different_values_are_deduplicated() {
+        let (lir, optimized_lir) = lirs("f a b := [First: { a }, Second: { b }]\n");
+        let bodies = created_function_bodies(&lir);
+        assert_eq!(bodies.len(), 2);
+        assert_ne!(bodies[0], bodies[1]);
+
+        let optimized_bodies = created_function_bodies(&optimized_lir);
+        assert_eq!(optimized_bodies.len(), 2);
+        assert_eq!(optimized_bodies[0], optimized_bodies[1]);
+        assert_eq!(
+            optimized_lir.bodies().ids_and_bodies().count(),
+            lir.bodies().ids_and_bodies().count() - 1,
+        );
+
+        let deduplicated = optimized_lir.bodies().get(optimized_bodies[0]);
+        for body in bodies {
+            assert!(lir
+                .bodies()
+                .get(body)
+                .original_hirs()
+                .is_subset(deduplicated.original_hirs()));
+        }
+    }
+    #[test]
+    fn different_functions_are_kept() {
+        let (lir, optimized_lir) = lirs("f a b := [First: { a }, Second: { [a, b] }]\n");
+        let optimized_bodies = created_function_bodies(&optimized_lir);
+        assert_eq!(optimized_bodies.len(), 2);
+        assert_ne!(optimized_bodies[0], optimized_bodies[1]);
+        assert_eq!(
+            optimized_lir.bodies().ids_and_bodies().count(),
+            lir.bodies().ids_and_bodies().count(),
+        );
+    }
+}
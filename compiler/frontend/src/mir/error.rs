@@ -1,10 +1,48 @@
-use crate::module::Module;
+use crate::{builtin_functions::BuiltinFunction, module::Module};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum MirError {
-    UseWithInvalidPath { module: Module, path: String },
-    UseHasTooManyParentNavigations { module: Module, path: String },
-    ModuleNotFound { module: Module, path: String },
-    UseNotStaticallyResolvable { containing_module: Module },
-    ModuleHasCycle { cycle: Vec<String> },
+    UseWithInvalidPath {
+        module: Module,
+        path: String,
+    },
+    UseHasTooManyParentNavigations {
+        module: Module,
+        path: String,
+    },
+    ModuleNotFound {
+        module: Module,
+        path: String,
+    },
+    UseNotStaticallyResolvable {
+        containing_module: Module,
+    },
+    ModuleHasCycle {
+        cycle: Vec<String>,
+    },
+    /// The modules in the order in which they use each other, starting and
+    /// ending with the module that contains the offending `use`.
+    UseCycle {
+        cycle: Vec<Module>,
+    },
+    NeedsConditionIsAlwaysTrue,
+    NeedsConditionIsAlwaysFalse,
+    /// A match case after a case without a guard that matches everything.
+    UnreachableMatchCase,
+    /// An int that can't be an index or length is passed to a builtin that
+    /// uses it as one (see [`BuiltinFunction::index_parameters`]).
+    IntArgumentOutOfRange {
+        builtin_function: BuiltinFunction,
+        parameter: String,
+    },
+    UsesDeprecatedExport {
+        module: Module,
+        name: String,
+        hint: String,
+    },
+    /// See [`crate::naming_conventions`].
+    UnconventionalName {
+        name: String,
+        conventional_name: String,
+    },
 }
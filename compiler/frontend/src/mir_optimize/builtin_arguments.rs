@@ -0,0 +1,139 @@
+//! Reports constant ints that are passed to builtins as an index or length but
+//! don't fit in a `usize`:
+//!
+//! ```candy
+//! ✨.listFilled 100000000000000000000 Foo # This length is too large.
+//! ```
+//!
+//! The VM converts these ints to a `usize`, so it would crash instead of
+//! panicking with a helpful message. Like for constant `needs`, we only look
+//! at calls written in the module itself. Calls in inlined functions are
+//! usually guarded by a `needs` that fails first.
+
+use super::current_expression::{Context, CurrentExpression};
+use crate::{
+    error::CompilerError,
+    hir,
+    mir::{Expression, MirError},
+};
+use num_traits::ToPrimitive;
+
+pub fn check_index_arguments(context: &mut Context, expression: &CurrentExpression) {
+    if expression.id() >= context.first_generated_id {
+        return;
+    }
+    let Expression::Call {
+        function,
+        arguments,
+        responsible,
+    } = &**expression
+    else {
+        return;
+    };
+    let Expression::Builtin(builtin_function) = context.visible.get(*function) else {
+        return;
+    };
+    let Expression::HirId(call_id) = context.visible.get(*responsible) else {
+        return;
+    };
+
+    for &index in builtin_function.index_parameters() {
+        let Some(Expression::Int(value)) = arguments.get(index).map(|it| context.visible.get(*it))
+        else {
+            continue;
+        };
+        if value.to_usize().is_some() {
+            continue;
+        }
+
+        let Ok((hir, _)) = context.db.hir(call_id.module.clone()) else {
+            return;
+        };
+        let Some(hir::Expression::Call {
+            arguments: hir_arguments,
+            ..
+        }) = hir.find(call_id)
+        else {
+            return;
+        };
+        let Some(span) = hir_arguments
+            .get(index)
+            .and_then(|it| context.db.hir_id_to_span(it))
+        else {
+            continue;
+        };
+        context.errors.insert(CompilerError {
+            module: call_id.module.clone(),
+            span,
+            payload: MirError::IntArgumentOutOfRange {
+                builtin_function: *builtin_function,
+                parameter: builtin_function.parameters()[index].name.to_string(),
+            }
+            .into(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::CompilerErrorPayload,
+        hir_to_mir::ExecutionTarget,
+        mir::MirError,
+        mir_optimize::OptimizeMir,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+    use itertools::Itertools;
+
+    /// The arguments reported as out of range, as they're written in the
+    /// source.
+    fn out_of_range_arguments(source: &str) -> Vec<String> {
+        let mut db = Database::default();
+        // Only the builtins package can use `✨`.
+        let module = Module {
+            package: Package::builtins(),
+            path: vec!["test".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+        let (_, _, errors) = db
+            .optimized_mir(ExecutionTarget::Module(module), TracingConfig::off())
+            .unwrap();
+        errors
+            .iter()
+            .filter(|error| {
+                matches!(
+                    error.payload,
+                    CompilerErrorPayload::Mir(MirError::IntArgumentOutOfRange { .. }),
+                )
+            })
+            .map(|error| source[*error.span.start..*error.span.end].to_string())
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn ints_that_fit_in_a_usize_are_fine() {
+        let source = "foo = ✨.listFilled 3 Foo\nbar = ✨.listGet (1, 2) 0\n";
+        assert_eq!(out_of_range_arguments(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn too_large_ints_are_reported() {
+        let source = "foo = ✨.listFilled 100000000000000000000 Foo\n\
+                      bar = ✨.textGetRange \"abc\" 0 200000000000000000000\n";
+        assert_eq!(
+            out_of_range_arguments(source),
+            vec!["100000000000000000000", "200000000000000000000"],
+        );
+    }
+
+    #[test]
+    fn other_arguments_are_not_checked() {
+        // The list elements aren't an index.
+        let source = "foo = ✨.listFilled 2 100000000000000000000\n";
+        assert_eq!(out_of_range_arguments(source), Vec::<String>::new());
+    }
+}
@@ -1,5 +1,6 @@
-use crate::mir::{Body, Expression, Mir};
+use crate::mir::{Body, Expression, Id, Mir};
 use core::fmt;
+use rustc_hash::FxHashSet;
 use std::{cmp::Ordering, ops::Add};
 
 #[derive(Clone, Copy, Debug)]
@@ -76,6 +77,28 @@ impl Body {
                 complexity + expression.complexity()
             })
     }
+
+    /// Like [`Body::complexity`], but trace expressions and the HIR IDs that
+    /// only they reference don't count. This way, the inliner makes the same
+    /// decisions regardless of the tracing config.
+    #[must_use]
+    pub fn complexity_ignoring_tracing(&self) -> Complexity {
+        let ids_used_outside_of_tracing: FxHashSet<Id> = self
+            .iter()
+            .filter(|(_, expression)| !expression.is_trace())
+            .flat_map(|(_, expression)| expression.referenced_ids())
+            .collect();
+        self.iter()
+            .filter(|(id, expression)| {
+                let is_only_for_tracing = expression.is_trace()
+                    || (matches!(expression, Expression::HirId(_))
+                        && !ids_used_outside_of_tracing.contains(id));
+                !is_only_for_tracing
+            })
+            .fold(Complexity::none(), |complexity, (_, expression)| {
+                complexity + expression.complexity_ignoring_tracing()
+            })
+    }
 }
 impl Expression {
     fn complexity(&self) -> Complexity {
@@ -88,4 +111,22 @@ impl Expression {
             _ => Complexity::single_expression(),
         }
     }
+    fn complexity_ignoring_tracing(&self) -> Complexity {
+        match self {
+            Self::Function { body, .. } => {
+                Complexity::single_expression() + body.complexity_ignoring_tracing()
+            }
+            _ => self.complexity(),
+        }
+    }
+
+    const fn is_trace(&self) -> bool {
+        matches!(
+            self,
+            Self::TraceCallStarts { .. }
+                | Self::TraceCallEnds { .. }
+                | Self::TraceExpressionEvaluated { .. }
+                | Self::TraceFoundFuzzableFunction { .. }
+        )
+    }
 }
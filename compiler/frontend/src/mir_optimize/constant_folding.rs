@@ -34,7 +34,7 @@ use super::{
 };
 use crate::{
     builtin_functions::BuiltinFunction,
-    format::{format_value, FormatValue, MaxLength, Precedence},
+    format::{format_value_with_limits, FormatValue, MaxLength, NestingLimits, Precedence},
     id::IdGenerator,
     mir::{Body, Expression, Id, VisibleExpressions},
 };
@@ -50,6 +50,9 @@ use std::{
 use tracing::warn;
 use unicode_segmentation::UnicodeSegmentation;
 
+/// Shifting left by more bits than this would put huge ints into the byte code.
+const MAX_FOLDED_SHIFT_LEFT_AMOUNT: u32 = 1 << 10;
+
 pub fn fold_constants(context: &mut Context, expression: &mut CurrentExpression) {
     let Expression::Call {
         function,
@@ -256,8 +259,12 @@ fn run_builtin(
                 unreachable!()
             };
             let amount: &BigInt = visible.get(*amount).try_into().ok()?;
-            // TODO: Support larger shift amounts.
-            let amount: u128 = amount.try_into().unwrap();
+            // Negative amounts panic at runtime and larger ones would result in
+            // gigantic values, so we leave those to the VM.
+            let amount: u32 = amount
+                .try_into()
+                .ok()
+                .filter(|it| *it <= MAX_FOLDED_SHIFT_LEFT_AMOUNT)?;
             if amount == 0 {
                 return Some(value.into());
             }
@@ -270,8 +277,8 @@ fn run_builtin(
                 unreachable!()
             };
             let amount: &BigInt = visible.get(*amount).try_into().ok()?;
-            // TODO: Support larger shift amounts.
-            let amount: u128 = amount.try_into().unwrap();
+            // Negative amounts panic at runtime, so we leave them to the VM.
+            let amount: u128 = amount.try_into().ok()?;
             if amount == 0 {
                 return Some(value.into());
             }
@@ -291,6 +298,8 @@ fn run_builtin(
             let subtrahend: &BigInt = visible.get(*subtrahend).try_into().ok()?;
             (minuend - subtrahend).into()
         }
+        BuiltinFunction::IteratorFoldLeft => return None,
+        BuiltinFunction::IteratorNext => return None,
         BuiltinFunction::ListFilled => {
             let [length, item] = arguments else {
                 unreachable!()
@@ -299,8 +308,10 @@ fn run_builtin(
                 return None;
             };
             // TODO: Support lists longer than `usize::MAX`.
-            vec![*item; length.to_usize().unwrap()].into()
+            vec![*item; length.to_usize()?].into()
         }
+        BuiltinFunction::ListFilter => return None,
+        BuiltinFunction::ListFoldLeft => return None,
         BuiltinFunction::ListGet => {
             let [list, index] = arguments else {
                 unreachable!()
@@ -312,7 +323,7 @@ fn run_builtin(
                 return None;
             };
             // TODO: Support lists longer than `usize::MAX`.
-            list.get(index.to_usize().unwrap())?.into()
+            list.get(index.to_usize()?)?.into()
         }
         BuiltinFunction::ListInsert => return None,
         BuiltinFunction::ListLength => {
@@ -322,6 +333,7 @@ fn run_builtin(
             };
             list.len().into()
         }
+        BuiltinFunction::ListMap => return None,
         BuiltinFunction::ListRemoveAt => return None,
         BuiltinFunction::ListReplace => return None,
         BuiltinFunction::Print => return None,
@@ -534,16 +546,16 @@ fn run_builtin(
                 return None;
             };
             // TODO: Support indices larger than usize.
-            let start_inclusive = start_inclusive.to_usize().unwrap();
+            let start_inclusive = start_inclusive.to_usize()?;
 
-            if text.graphemes(true).count() == start_inclusive.to_usize().unwrap() {
+            if text.graphemes(true).count() == start_inclusive {
                 return Some("".into());
             }
 
             let Some(end_exclusive) = end_exclusive else {
                 return None;
             };
-            let end_exclusive = end_exclusive.to_usize().unwrap();
+            let end_exclusive = end_exclusive.to_usize()?;
 
             text.graphemes(true)
                 .skip(start_inclusive)
@@ -599,23 +611,33 @@ fn run_builtin(
             let [argument] = arguments else {
                 unreachable!()
             };
-            let formatted =
-                format_value(*argument, Precedence::Low, MaxLength::Unlimited, &|id| {
-                    Some(match visible.get(id) {
-                        Expression::Int(int) => FormatValue::Int(Cow::Borrowed(int)),
-                        Expression::Text(text) => FormatValue::Text(text),
-                        Expression::Tag { symbol, value } => FormatValue::Tag {
-                            symbol,
-                            value: *value,
-                        },
-                        Expression::Builtin(_) => FormatValue::Function,
-                        Expression::List(items) => FormatValue::List(items),
-                        Expression::Struct(entries) => FormatValue::Struct(Cow::Borrowed(entries)),
-                        Expression::Function { .. } => FormatValue::Function,
-                        _ => return None,
-                    })
-                })?;
-            formatted.into()
+            format_constant(
+                visible,
+                *argument,
+                MaxLength::Unlimited,
+                NestingLimits::UNLIMITED,
+            )?
+            .into()
+        }
+        BuiltinFunction::ToDebugTextWithLimits => {
+            let [argument, max_depth, max_items, max_length] = arguments else {
+                unreachable!()
+            };
+            // Each limit is either an int or `Unlimited`.
+            let limit = |id: &Id| match visible.get(*id) {
+                Expression::Int(int) => Some(Some(int.to_usize()?)),
+                Expression::Tag {
+                    symbol,
+                    value: None,
+                } if symbol == "Unlimited" => Some(None),
+                _ => None,
+            };
+            let limits = NestingLimits {
+                max_depth: limit(max_depth)?,
+                max_items: limit(max_items)?,
+            };
+            let max_length = limit(max_length)?.map_or(MaxLength::Unlimited, MaxLength::Limited);
+            format_constant(visible, *argument, max_length, limits)?.into()
         }
         BuiltinFunction::TypeOf => Expression::tag(
             match visible.get(arguments[0]) {
@@ -653,10 +675,15 @@ fn run_builtin(
                         BuiltinFunction::IntShiftLeft => "Int",
                         BuiltinFunction::IntShiftRight => "Int",
                         BuiltinFunction::IntSubtract => "Int",
+                        BuiltinFunction::IteratorFoldLeft => return None,
+                        BuiltinFunction::IteratorNext => "Tag",
                         BuiltinFunction::ListFilled => "List",
+                        BuiltinFunction::ListFilter => "List",
+                        BuiltinFunction::ListFoldLeft => return None,
                         BuiltinFunction::ListGet => return None,
                         BuiltinFunction::ListInsert => "List",
                         BuiltinFunction::ListLength => "Int",
+                        BuiltinFunction::ListMap => "List",
                         BuiltinFunction::ListRemoveAt => "List",
                         BuiltinFunction::ListReplace => "List",
                         BuiltinFunction::Print => "Tag",
@@ -678,6 +705,7 @@ fn run_builtin(
                         BuiltinFunction::TextTrimEnd => "Text",
                         BuiltinFunction::TextTrimStart => "Text",
                         BuiltinFunction::ToDebugText => "Text",
+                        BuiltinFunction::ToDebugTextWithLimits => "Text",
                         BuiltinFunction::TypeOf => "Tag",
                     }
                 }
@@ -693,3 +721,74 @@ fn run_builtin(
     };
     Some(result)
 }
+
+fn format_constant(
+    visible: &VisibleExpressions,
+    value: Id,
+    max_length: MaxLength,
+    limits: NestingLimits,
+) -> Option<String> {
+    format_value_with_limits(value, Precedence::Low, max_length, limits, &|id| {
+        Some(match visible.get(id) {
+            Expression::Int(int) => FormatValue::Int(Cow::Borrowed(int)),
+            Expression::Text(text) => FormatValue::Text(text),
+            Expression::Tag { symbol, value } => FormatValue::Tag {
+                symbol,
+                value: *value,
+            },
+            Expression::Builtin(_) => FormatValue::Function,
+            Expression::List(items) => FormatValue::List(items),
+            Expression::Struct(entries) => FormatValue::Struct(Cow::Borrowed(entries)),
+            Expression::Function { .. } => FormatValue::Function,
+            _ => return None,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_builtin, MAX_FOLDED_SHIFT_LEFT_AMOUNT};
+    use crate::{
+        builtin_functions::BuiltinFunction,
+        id::IdGenerator,
+        mir::{Body, Expression, VisibleExpressions},
+        mir_optimize::{current_expression::CurrentExpression, pure::PurenessInsights},
+    };
+    use num_bigint::BigInt;
+
+    fn shift_left(value: i64, amount: u32) -> Option<Expression> {
+        let mut id_generator = IdGenerator::default();
+        let mut visible = VisibleExpressions::none_visible();
+        let value_id = id_generator.generate();
+        visible.insert(value_id, BigInt::from(value).into());
+        let amount_id = id_generator.generate();
+        visible.insert(amount_id, BigInt::from(amount).into());
+        let responsible = id_generator.generate();
+
+        let mut body = Body::default();
+        body.push(id_generator.generate(), Expression::Parameter);
+        run_builtin(
+            &mut CurrentExpression::new(&mut body, 0),
+            BuiltinFunction::IntShiftLeft,
+            &[value_id, amount_id],
+            responsible,
+            &visible,
+            &mut id_generator,
+            &PurenessInsights::default(),
+        )
+    }
+
+    #[test]
+    fn small_left_shifts_are_folded() {
+        assert_eq!(shift_left(3, 2), Some(BigInt::from(12).into()));
+        assert_eq!(
+            shift_left(1, MAX_FOLDED_SHIFT_LEFT_AMOUNT),
+            Some((BigInt::from(1) << MAX_FOLDED_SHIFT_LEFT_AMOUNT).into()),
+        );
+    }
+    #[test]
+    fn large_left_shifts_are_left_to_the_vm() {
+        assert_eq!(shift_left(1, MAX_FOLDED_SHIFT_LEFT_AMOUNT + 1), None);
+        assert_eq!(shift_left(1, u32::MAX), None);
+    }
+}
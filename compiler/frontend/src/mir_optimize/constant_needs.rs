@@ -0,0 +1,147 @@
+//! Warns about `needs` whose condition is known at compile time. Such a `needs`
+//! either never fails, so it's redundant, or it always fails, which usually
+//! means that the condition is inverted:
+//!
+//! ```candy
+//! limit = 10
+//! needs (limit | int.isLessThan 5) # This needs always fails.
+//! ```
+//!
+//! Conditions written as a literal symbol, such as in `needs False "Oops"`, are
+//! intentional and don't cause a warning. We also only look at `needs` in the
+//! code of the module itself: A `needs` in a function often only becomes
+//! constant after the function is inlined into a specific call site, which is
+//! perfectly fine.
+
+use super::current_expression::{Context, CurrentExpression};
+use crate::{
+    error::CompilerError,
+    hir,
+    mir::{Expression, MirError},
+};
+
+pub fn warn_about_constant_needs(context: &mut Context, expression: &CurrentExpression) {
+    if expression.id() >= context.first_generated_id {
+        // The expression was created by an optimization, e.g., by inlining.
+        return;
+    }
+    let Expression::Call {
+        function,
+        arguments,
+        responsible,
+    } = &**expression
+    else {
+        return;
+    };
+    let Expression::Function { original_hirs, .. } = context.visible.get(*function) else {
+        return;
+    };
+    if !original_hirs.contains(&hir::Id::needs()) {
+        return;
+    }
+    let [condition, _, _] = arguments.as_slice() else {
+        return;
+    };
+    let Ok(is_always_true): Result<bool, _> = context.visible.get(*condition).try_into() else {
+        return;
+    };
+    let Expression::HirId(needs_id) = context.visible.get(*responsible) else {
+        return;
+    };
+
+    let Ok((hir, _)) = context.db.hir(needs_id.module.clone()) else {
+        return;
+    };
+    // Inlining keeps the ID of the call it replaces for the last inlined
+    // expression, so we can't rely on the ID alone to skip inlined code.
+    let Some(hir::Expression::Needs { condition, .. }) = hir.expressions.get(needs_id) else {
+        // The `needs` is nested in a function.
+        return;
+    };
+    if matches!(hir.find(condition), Some(hir::Expression::Symbol(_))) {
+        return;
+    }
+    let Some(span) = context.db.hir_id_to_span(condition) else {
+        return;
+    };
+
+    let payload = if is_always_true {
+        MirError::NeedsConditionIsAlwaysTrue
+    } else {
+        MirError::NeedsConditionIsAlwaysFalse
+    };
+    context.errors.insert(CompilerError {
+        module: needs_id.module.clone(),
+        span,
+        payload: payload.into(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::CompilerErrorPayload,
+        hir_to_mir::ExecutionTarget,
+        mir::MirError,
+        mir_optimize::OptimizeMir,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+    use itertools::Itertools;
+
+    /// The conditions of constant `needs` with whether they're always true, as
+    /// they're written in the source.
+    fn constant_conditions(source: &str) -> Vec<(String, bool)> {
+        let mut db = Database::default();
+        // Only the builtins package can use `✨`.
+        let module = Module {
+            package: Package::builtins(),
+            path: vec!["test".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+        let (_, _, errors) = db
+            .optimized_mir(ExecutionTarget::Module(module), TracingConfig::off())
+            .unwrap();
+        errors
+            .iter()
+            .filter_map(|error| {
+                let is_always_true = match error.payload {
+                    CompilerErrorPayload::Mir(MirError::NeedsConditionIsAlwaysTrue) => true,
+                    CompilerErrorPayload::Mir(MirError::NeedsConditionIsAlwaysFalse) => false,
+                    _ => return None,
+                };
+                Some((
+                    source[*error.span.start..*error.span.end].to_string(),
+                    is_always_true,
+                ))
+            })
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn constant_conditions_are_reported() {
+        let source = "needs (✨.equals 1 1)\nneeds (✨.equals 1 2) \"Oops\"\n";
+        assert_eq!(
+            constant_conditions(source),
+            vec![
+                ("✨.equals 1 1".to_string(), true),
+                ("✨.equals 1 2".to_string(), false),
+            ],
+        );
+    }
+
+    #[test]
+    fn literal_symbols_are_intentional() {
+        let source = "needs True\nfoo = { a -> needs False \"Unreachable\" }\n";
+        assert_eq!(constant_conditions(source), vec![]);
+    }
+
+    #[test]
+    fn conditions_that_depend_on_arguments_are_fine() {
+        let source = "foo = { a -> needs (✨.equals a 1) }\nbar = foo 2\n";
+        assert_eq!(constant_conditions(source), vec![]);
+    }
+}
@@ -14,6 +14,9 @@ pub struct Context<'a> {
     pub errors: &'a mut FxHashSet<CompilerError>,
     pub visible: &'a mut VisibleExpressions,
     pub id_generator: &'a mut IdGenerator<Id>,
+    /// All IDs before this one were created while lowering the HIR, the ones
+    /// after it by optimizations.
+    pub first_generated_id: Id,
     pub pureness: &'a mut PurenessInsights,
 }
 
@@ -26,6 +26,10 @@
 //! at the call sites, more information about arguments exist,
 //! [constant folding] and [module folding] can be more effective.
 //!
+//! Trace expressions are copied along with the rest of the body and their IDs
+//! are remapped like all others. They don't count towards a function's
+//! complexity, so traced builds inline the same functions as untraced ones.
+//!
 //! TODO: When we have a metric for judging performance vs. code size, also
 //! speculatively inline more call sites, such as smallish functions and
 //! functions only used once.
@@ -61,7 +65,7 @@ fn inline_functions_of_maximum_complexity(
 ) {
     if let Expression::Call { function, .. } = **expression
         && let Expression::Function { body, .. } = context.visible.get(function)
-        && body.complexity() <= complexity {
+        && body.complexity_ignoring_tracing() <= complexity {
         context.inline_call(expression);
     }
 }
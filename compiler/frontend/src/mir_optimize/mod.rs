@@ -48,21 +48,26 @@ use self::{
 };
 use super::{hir, hir_to_mir::HirToMir, mir::Mir, tracing::TracingConfig};
 use crate::{
+    deprecation,
     error::CompilerError,
     hir_to_mir::ExecutionTarget,
     mir::{Body, Expression, MirError, VisibleExpressions},
+    naming_conventions,
     string_to_rcst::ModuleError,
     utils::DoHash,
 };
+use itertools::Itertools;
 use rustc_hash::FxHashSet;
 use std::{mem, sync::Arc};
 use tracing::debug;
 
+mod builtin_arguments;
 mod cleanup;
 mod common_subtree_elimination;
 mod complexity;
 mod constant_folding;
 mod constant_lifting;
+mod constant_needs;
 mod current_expression;
 mod inlining;
 mod module_folding;
@@ -95,10 +100,18 @@ fn optimized_mir(
 ) -> OptimizedMirResult {
     let module = target.module();
     debug!("{module}: Compiling.");
+    if let Some(cycle) = module_folding::find_use_cycle(db, module) {
+        return Ok(mir_for_use_cycle(db, &target, &cycle));
+    }
     let (mir, errors) = db.mir(target.clone(), tracing.clone())?;
     let mut mir = (*mir).clone();
     let mut pureness = PurenessInsights::default();
     let mut errors = (*errors).clone();
+    errors.extend(deprecation::deprecation_warnings(db, module.clone()));
+    errors.extend(naming_conventions::naming_convention_warnings(
+        db,
+        module.clone(),
+    ));
 
     let complexity_before = mir.complexity();
     mir.optimize(db, &tracing, &mut pureness, &mut errors);
@@ -116,19 +129,24 @@ impl Mir {
         pureness: &mut PurenessInsights,
         errors: &mut FxHashSet<CompilerError>,
     ) {
+        let first_generated_id = self.id_generator.clone().generate();
         let mut context = Context {
             db,
             tracing,
             errors,
             visible: &mut VisibleExpressions::none_visible(),
             id_generator: &mut self.id_generator,
+            first_generated_id,
             pureness,
         };
         context.optimize_body(&mut self.body);
         if cfg!(debug_assertions) {
-            self.validate();
+            self.validate("optimizing the body");
         }
         self.cleanup(pureness);
+        if cfg!(debug_assertions) {
+            self.validate("cleanup");
+        }
     }
 }
 
@@ -141,13 +159,11 @@ impl Context<'_> {
             // Thoroughly optimize the expression.
             let mut expression = CurrentExpression::new(body, index);
             self.optimize_expression(&mut expression);
-            if cfg!(debug_assertions) {
-                expression.validate(self.visible);
-            }
 
             self.pureness.visit_optimized(expression.id(), &expression);
 
             module_folding::apply(self, &mut expression);
+            self.validate_expression(&expression, "module folding");
 
             let new_id = expression.id();
             index = expression.index() + 1;
@@ -160,8 +176,11 @@ impl Context<'_> {
         }
 
         common_subtree_elimination::eliminate_common_subtrees(body, self.pureness);
+        self.validate_body(body, "common subtree elimination");
         tree_shaking::tree_shake(body, self.pureness);
+        self.validate_body(body, "tree shaking");
         reference_following::remove_redundant_return_references(body);
+        self.validate_body(body, "removing redundant return references");
     }
 
     fn optimize_expression(&mut self, expression: &mut CurrentExpression) {
@@ -193,12 +212,19 @@ impl Context<'_> {
                 let hashcode_before = expression.do_hash();
 
                 reference_following::follow_references(self, expression);
+                self.validate_expression(expression, "reference following");
                 constant_folding::fold_constants(self, expression);
+                self.validate_expression(expression, "constant folding");
+                constant_needs::warn_about_constant_needs(self, expression);
+                builtin_arguments::check_index_arguments(self, expression);
 
                 let is_call = matches!(**expression, Expression::Call { .. });
                 inlining::inline_tiny_functions(self, expression);
+                self.validate_expression(expression, "inlining tiny functions");
                 inlining::inline_needs_function(self, expression);
+                self.validate_expression(expression, "inlining the needs function");
                 inlining::inline_functions_containing_use(self, expression);
+                self.validate_expression(expression, "inlining functions containing use");
                 if is_call && matches!(**expression, Expression::Function { .. }) {
                     // We inlined a function call and the resulting code starts with
                     // a function definition. We need to visit that first before
@@ -207,6 +233,7 @@ impl Context<'_> {
                 }
 
                 constant_lifting::lift_constants(self, expression);
+                self.validate_expression(expression, "constant lifting");
 
                 if expression.do_hash() == hashcode_before {
                     break 'outer;
@@ -214,6 +241,59 @@ impl Context<'_> {
             }
         }
     }
+
+    /// In debug builds, checks that the current expression is still valid
+    /// after the given optimization pass.
+    fn validate_expression(&self, expression: &Expression, pass: &str) {
+        if cfg!(debug_assertions) {
+            expression.validate(self.visible, pass);
+        }
+    }
+    /// In debug builds, checks that a fully optimized body is still valid
+    /// after the given optimization pass.
+    fn validate_body(&self, body: &Body, pass: &str) {
+        if cfg!(debug_assertions) {
+            body.validate(self.visible, pass);
+        }
+    }
+}
+
+/// Reports every `use` that's part of the cycle in the module containing it.
+/// Running the module panics with the error of its own `use`.
+fn mir_for_use_cycle(
+    db: &dyn OptimizeMir,
+    target: &ExecutionTarget,
+    cycle: &[hir::Id],
+) -> (
+    Arc<Mir>,
+    Arc<PurenessInsights>,
+    Arc<FxHashSet<CompilerError>>,
+) {
+    let modules = cycle.iter().map(|it| it.module.clone()).collect_vec();
+    let errors = cycle
+        .iter()
+        .enumerate()
+        .map(|(index, use_id)| {
+            let mut cycle = modules.clone();
+            cycle.rotate_left(index);
+            cycle.push(use_id.module.clone());
+            let payload = MirError::UseCycle { cycle };
+            match db.hir_id_to_span(use_id) {
+                Some(span) => CompilerError {
+                    module: use_id.module.clone(),
+                    span,
+                    payload: payload.into(),
+                },
+                None => CompilerError::for_whole_module(use_id.module.clone(), payload),
+            }
+        })
+        .collect_vec();
+    let mir = panicking_mir(target, &errors[0]);
+    (
+        Arc::new(mir),
+        Arc::default(),
+        Arc::new(errors.into_iter().collect()),
+    )
 }
 
 #[allow(clippy::unnecessary_wraps)]
@@ -229,12 +309,7 @@ fn recover_from_cycle(
             cycle: cycle.to_vec(),
         },
     );
-
-    let mir = Mir::build(|body| {
-        let reason = body.push_text(error.payload.to_string());
-        let responsible = body.push_hir_id(hir::Id::new(target.module().clone(), vec![]));
-        body.push_panic(reason, responsible);
-    });
+    let mir = panicking_mir(target, &error);
 
     Ok((
         Arc::new(mir),
@@ -242,3 +317,11 @@ fn recover_from_cycle(
         Arc::new(FxHashSet::from_iter([error])),
     ))
 }
+
+fn panicking_mir(target: &ExecutionTarget, error: &CompilerError) -> Mir {
+    Mir::build(|body| {
+        let reason = body.push_text(error.payload.to_string());
+        let responsible = body.push_hir_id(hir::Id::new(target.module().clone(), vec![]));
+        body.push_panic(reason, responsible);
+    })
+}
@@ -24,18 +24,26 @@
 //! and compiling other modules. Module folding is a necessity for building
 //! binaries that don't include the Candy compiler itself.
 //!
+//! Modules that use each other in a cycle can't be folded. Before optimizing a
+//! module, we therefore look for a cycle of statically resolvable `use`s that
+//! leads back to the module (see [`find_use_cycle`]).
+//!
 //! [constant folding]: super::constant_folding
 //! [inlining]: super::inlining
 
-use super::current_expression::{Context, CurrentExpression};
+use super::{
+    current_expression::{Context, CurrentExpression},
+    OptimizeMir,
+};
 use crate::{
     error::{CompilerError, CompilerErrorPayload},
+    hir,
     hir_to_mir::ExecutionTarget,
     id::IdGenerator,
     mir::{Body, BodyBuilder, Expression, Id, MirError},
     module::{Module, UsePath},
 };
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::mem;
 
 pub fn apply(context: &mut Context, expression: &mut CurrentExpression) {
@@ -146,6 +154,34 @@ pub fn apply(context: &mut Context, expression: &mut CurrentExpression) {
     };
 }
 
+/// Returns the `use` calls that lead from the module back to itself, if there
+/// are any. The first call is in the given module and each following call is
+/// in the module used by the previous one.
+pub fn find_use_cycle(db: &dyn OptimizeMir, module: &Module) -> Option<Vec<hir::Id>> {
+    fn visit(
+        db: &dyn OptimizeMir,
+        current: &Module,
+        start: &Module,
+        visited: &mut FxHashSet<Module>,
+        path: &mut Vec<hir::Id>,
+    ) -> bool {
+        for (use_id, used_module) in db.used_modules(current.clone()).iter() {
+            path.push(use_id.clone());
+            if used_module == start {
+                return true;
+            }
+            if visited.insert(used_module.clone()) && visit(db, used_module, start, visited, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    let mut path = vec![];
+    visit(db, module, module, &mut FxHashSet::default(), &mut path).then_some(path)
+}
+
 fn resolve_module(current_module: &Module, path: &str) -> Result<Module, MirError> {
     let Ok(path) = UsePath::parse(path) else {
         return Err(MirError::UseWithInvalidPath {
@@ -178,3 +214,103 @@ fn panicking_expression(
     );
     body.expressions
 }
+
+#[cfg(test)]
+mod tests {
+    use super::find_use_cycle;
+    use crate::{
+        error::CompilerErrorPayload,
+        hir_to_mir::ExecutionTarget,
+        mir::MirError,
+        mir_optimize::OptimizeMir,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+    use itertools::Itertools;
+
+    fn module(name: &str) -> Module {
+        Module {
+            package: Package::User("/package".into()),
+            path: vec![name.to_string()],
+            kind: ModuleKind::Code,
+        }
+    }
+    fn database(modules: &[(&str, &str)]) -> Database {
+        let mut db = Database::default();
+        for (name, source) in modules {
+            db.did_open_module(&module(name), source.as_bytes().to_vec());
+        }
+        db
+    }
+
+    /// The reported cycles with the `use` they're reported at, as it's written
+    /// in the source.
+    fn use_cycles(db: &Database, name: &str, source: &str) -> Vec<(String, Vec<Module>)> {
+        let (_, _, errors) = db
+            .optimized_mir(ExecutionTarget::Module(module(name)), TracingConfig::off())
+            .unwrap();
+        errors
+            .iter()
+            .filter(|error| error.module == module(name))
+            .filter_map(|error| match &error.payload {
+                CompilerErrorPayload::Mir(MirError::UseCycle { cycle }) => Some((
+                    source[*error.span.start..*error.span.end].to_string(),
+                    cycle.clone(),
+                )),
+                _ => None,
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn modules_without_cycles_are_fine() {
+        let db = database(&[
+            ("a", "b = use \"..b\"\n"),
+            ("b", "c = use \"..c\"\n"),
+            ("c", ""),
+        ]);
+        assert_eq!(find_use_cycle(&db, &module("a")), None);
+        assert_eq!(use_cycles(&db, "a", "b = use \"..b\"\n"), vec![]);
+    }
+
+    #[test]
+    fn cycles_are_reported_at_each_use() {
+        let a = "b = use \"..b\"\n";
+        let b = "c = use \"..c\"\n";
+        let c = "a = use \"..a\"\n";
+        let db = database(&[("a", a), ("b", b), ("c", c)]);
+
+        let cycle = find_use_cycle(&db, &module("a")).unwrap();
+        assert_eq!(
+            cycle.iter().map(|it| it.module.clone()).collect_vec(),
+            vec![module("a"), module("b"), module("c")],
+        );
+
+        assert_eq!(
+            use_cycles(&db, "a", a),
+            vec![(
+                "use \"..b\"".to_string(),
+                vec![module("a"), module("b"), module("c"), module("a")],
+            )],
+        );
+        assert_eq!(
+            use_cycles(&db, "b", b),
+            vec![(
+                "use \"..c\"".to_string(),
+                vec![module("b"), module("c"), module("a"), module("b")],
+            )],
+        );
+    }
+
+    #[test]
+    fn modules_that_only_use_a_cycle_are_not_part_of_it() {
+        let db = database(&[
+            ("main", "a = use \"..a\"\n"),
+            ("a", "b = use \"..b\"\n"),
+            ("b", "a = use \"..a\"\n"),
+        ]);
+        assert_eq!(find_use_cycle(&db, &module("main")), None);
+        assert!(find_use_cycle(&db, &module("a")).is_some());
+    }
+}
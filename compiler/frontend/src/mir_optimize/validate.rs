@@ -1,27 +1,83 @@
+//! Verification checks that the MIR upholds the invariants that the
+//! optimizations and the lowering to LIR rely on:
+//!
+//! - Bodies aren't empty. Functions should at least return a value.
+//! - Each ID is defined exactly once. This includes parameters and responsible
+//!   parameters.
+//! - A function's responsible parameter isn't also one of its parameters.
+//! - IDs are only referenced after they are defined and only from within the
+//!   scope they're defined in. In particular, nothing references IDs of bodies
+//!   that were removed or of functions that were inlined.
+//!
+//! In debug builds, the optimizer verifies the MIR after every pass and panics
+//! with the name of the pass that broke an invariant.
+
 use crate::mir::{Body, Expression, Id, Mir, VisibleExpressions};
 use rustc_hash::FxHashSet;
+use std::fmt::{self, Display, Formatter};
 use tracing::error;
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VerificationError {
+    EmptyBody,
+    DuplicateDefinition(Id),
+    ResponsibleParameterIsAlsoParameter(Id),
+    ReferencesInvisibleId { id: Id, referenced: Id },
+}
+impl Display for VerificationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::EmptyBody => write!(
+                f,
+                "A body of a function is empty. Functions should have at least a return value.",
+            ),
+            Self::DuplicateDefinition(id) => write!(f, "ID {id} is defined more than once."),
+            Self::ResponsibleParameterIsAlsoParameter(id) => write!(
+                f,
+                "ID {id} is used as a parameter and as the responsible parameter of the same function.",
+            ),
+            Self::ReferencesInvisibleId { id, referenced } => write!(
+                f,
+                "{id} references {referenced}, but that's not visible at this point.",
+            ),
+        }
+    }
+}
+
 impl Mir {
-    pub fn validate(&self) {
-        self.body
-            .validate(&mut FxHashSet::default(), im::HashSet::new());
+    pub fn verify(&self) -> Result<(), VerificationError> {
+        self.body.verify(&VisibleExpressions::none_visible())
+    }
+
+    /// Panics if the MIR is invalid. `pass` is the name of the last
+    /// optimization that ran and gets reported as the culprit.
+    pub fn validate(&self, pass: &str) {
+        if let Err(error) = self.verify() {
+            error!("MIR is invalid after {pass}: {error}");
+            error!("This is the MIR:\n{self}");
+            panic!("MIR is invalid after {pass}: {error}");
+        }
     }
 }
 
 impl Body {
-    pub fn validate(&self, defined_ids: &mut FxHashSet<Id>, mut visible: im::HashSet<Id>) {
+    /// Verifies a body that may reference the `outer` expressions.
+    pub fn verify(&self, outer: &VisibleExpressions) -> Result<(), VerificationError> {
+        self.verify_rec(outer, &mut FxHashSet::default(), im::HashSet::new())
+    }
+    fn verify_rec(
+        &self,
+        outer: &VisibleExpressions,
+        defined_ids: &mut FxHashSet<Id>,
+        mut visible: im::HashSet<Id>,
+    ) -> Result<(), VerificationError> {
         if self.expressions.is_empty() {
-            error!("A body of a function is empty! Functions should have at least a return value.");
-            error!("This is the MIR:\n{self}");
-            panic!("MIR is invalid!");
+            return Err(VerificationError::EmptyBody);
         }
         for (id, expression) in self.iter() {
-            for captured in expression.captured_ids() {
-                if !visible.contains(&captured) {
-                    error!("MIR is invalid! {id} captures {captured}, but that's not visible.");
-                    error!("This is the MIR:\n{self}");
-                    panic!("MIR is invalid!");
+            for referenced in expression.captured_ids() {
+                if !visible.contains(&referenced) && !outer.contains(referenced) {
+                    return Err(VerificationError::ReferencesInvisibleId { id, referenced });
                 }
             }
             if let Expression::Function {
@@ -31,31 +87,58 @@ impl Body {
                 body,
             } = expression
             {
+                if parameters.contains(responsible_parameter) {
+                    return Err(VerificationError::ResponsibleParameterIsAlsoParameter(
+                        *responsible_parameter,
+                    ));
+                }
+                for parameter in parameters {
+                    define(outer, defined_ids, *parameter)?;
+                }
+                define(outer, defined_ids, *responsible_parameter)?;
+
                 let mut inner_visible = visible.clone();
                 inner_visible.extend(parameters.iter().copied());
                 inner_visible.insert(*responsible_parameter);
-                body.validate(defined_ids, inner_visible);
+                body.verify_rec(outer, defined_ids, inner_visible)?;
             }
 
-            if defined_ids.contains(&id) {
-                error!("ID {id} exists twice.");
-                error!("This is the MIR:\n{self}");
-                panic!("MIR is invalid!");
-            }
-            defined_ids.insert(id);
-
+            define(outer, defined_ids, id)?;
             visible.insert(id);
         }
+        Ok(())
+    }
+
+    /// Panics if the body is invalid in the context of the `outer` visible
+    /// expressions.
+    pub fn validate(&self, outer: &VisibleExpressions, pass: &str) {
+        if let Err(error) = self.verify(outer) {
+            error!("MIR is invalid after {pass}: {error}");
+            error!("This is the body:\n{self}");
+            panic!("MIR is invalid after {pass}: {error}");
+        }
+    }
+}
+
+fn define(
+    outer: &VisibleExpressions,
+    defined_ids: &mut FxHashSet<Id>,
+    id: Id,
+) -> Result<(), VerificationError> {
+    if outer.contains(id) || !defined_ids.insert(id) {
+        return Err(VerificationError::DuplicateDefinition(id));
     }
+    Ok(())
 }
 
 impl Expression {
-    pub fn validate(&self, visible: &VisibleExpressions) {
+    /// Panics if the expression references IDs that are not visible.
+    pub fn validate(&self, visible: &VisibleExpressions, pass: &str) {
         for id in self.captured_ids() {
             if !visible.contains(id) {
-                println!("Expression references ID {id:?}, but that ID is not visible:");
-                println!("{self}");
-                panic!("Expression references ID that is not in its scope.");
+                error!("Expression references ID {id}, but that ID is not visible after {pass}:");
+                error!("{self}");
+                panic!("MIR is invalid after {pass}: An expression references {id}, which is not in its scope.");
             }
         }
     }
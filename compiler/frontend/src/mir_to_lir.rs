@@ -48,6 +48,10 @@ impl LoweringContext {
     fn constant_for(&self, id: mir::Id) -> Option<lir::ConstantId> {
         self.constant_mapping.get(&id).copied()
     }
+    fn push_constant(&mut self, id: mir::Id, constant: impl Into<lir::Constant>) {
+        let constant_id = self.constants.push(constant);
+        self.constant_mapping.insert(id, constant_id);
+    }
 
     fn compile_function(
         &mut self,
@@ -73,7 +77,6 @@ impl LoweringContext {
 struct CurrentBody {
     id_mapping: FxHashMap<mir::Id, lir::Id>,
     body: lir::Body,
-    last_constant: Option<mir::Id>,
     ids_to_drop: FxHashSet<lir::Id>,
 }
 impl CurrentBody {
@@ -89,7 +92,7 @@ impl CurrentBody {
         for (id, expression) in body.iter() {
             lir_body.compile_expression(context, id, expression);
         }
-        lir_body.finish(&context.constant_mapping)
+        lir_body.finish(&context.constant_mapping, body.return_value())
     }
 
     fn new(
@@ -118,7 +121,6 @@ impl CurrentBody {
         Self {
             id_mapping,
             body,
-            last_constant: None,
             ids_to_drop,
         }
     }
@@ -130,13 +132,12 @@ impl CurrentBody {
         expression: &mir::Expression,
     ) {
         match expression {
-            mir::Expression::Int(int) => self.push_constant(context, id, int.clone()),
-            mir::Expression::Text(text) => self.push_constant(context, id, text.clone()),
+            mir::Expression::Int(int) => context.push_constant(id, int.clone()),
+            mir::Expression::Text(text) => context.push_constant(id, text.clone()),
             mir::Expression::Tag { symbol, value } => {
                 if let Some(value) = value {
                     if let Some(constant_id) = context.constant_for(*value) {
-                        self.push_constant(
-                            context,
+                        context.push_constant(
                             id,
                             lir::Constant::Tag {
                                 symbol: symbol.clone(),
@@ -154,8 +155,7 @@ impl CurrentBody {
                         );
                     }
                 } else {
-                    self.push_constant(
-                        context,
+                    context.push_constant(
                         id,
                         lir::Constant::Tag {
                             symbol: symbol.clone(),
@@ -164,14 +164,14 @@ impl CurrentBody {
                     );
                 }
             }
-            mir::Expression::Builtin(builtin) => self.push_constant(context, id, *builtin),
+            mir::Expression::Builtin(builtin) => context.push_constant(id, *builtin),
             mir::Expression::List(items) => {
                 if let Some(items) = items
                     .iter()
                     .map(|item| context.constant_for(*item))
                     .collect::<Option<Vec<_>>>()
                 {
-                    self.push_constant(context, id, items);
+                    context.push_constant(id, items);
                 } else {
                     let items = self.ids_for(context, items);
                     self.push(id, items);
@@ -185,7 +185,7 @@ impl CurrentBody {
                     })
                     .collect::<Option<FxHashMap<_, _>>>()
                 {
-                    self.push_constant(context, id, fields);
+                    context.push_constant(id, fields);
                 } else {
                     let fields = fields
                         .iter()
@@ -214,7 +214,7 @@ impl CurrentBody {
 
                 self.push(id, context.constant_for(*referenced_id).unwrap());
             }
-            mir::Expression::HirId(hir_id) => self.push_constant(context, id, hir_id.clone()),
+            mir::Expression::HirId(hir_id) => context.push_constant(id, hir_id.clone()),
             mir::Expression::Function {
                 original_hirs,
                 parameters,
@@ -236,7 +236,7 @@ impl CurrentBody {
                     body,
                 );
                 if captured.is_empty() {
-                    self.push_constant(context, id, body_id);
+                    context.push_constant(id, body_id);
                 } else {
                     let captured = self.ids_for(context, &captured);
                     self.push(id, lir::Expression::CreateFunction { captured, body_id });
@@ -341,16 +341,6 @@ impl CurrentBody {
 
         self.push(id, context.constant_for(id).unwrap())
     }
-    fn push_constant(
-        &mut self,
-        context: &mut LoweringContext,
-        id: mir::Id,
-        constant: impl Into<lir::Constant>,
-    ) {
-        let constant_id = context.constants.push(constant);
-        context.constant_mapping.insert(id, constant_id);
-        self.last_constant = Some(id);
-    }
 
     fn push(&mut self, mir_id: mir::Id, expression: impl Into<lir::Expression>) -> lir::Id {
         let expression = expression.into();
@@ -375,25 +365,72 @@ impl CurrentBody {
 
         self.body.push(lir::Expression::Dup { id, amount: 1 });
     }
-    fn finish(mut self, constant_mapping: &FxHashMap<mir::Id, lir::ConstantId>) -> lir::Body {
-        if self.body.expressions().is_empty() {
-            // If the top-level MIR contains only constants, its LIR body will
-            // still be empty. Hence, we push a reference to the last constant
-            // we encountered.
-            let last_constant_id = self.last_constant.unwrap();
-            self.push(last_constant_id, constant_mapping[&last_constant_id]);
-        }
-
-        let last_expression_id = self.body.last_expression_id().unwrap();
-        self.ids_to_drop.remove(&last_expression_id);
-        if !self.ids_to_drop.is_empty() {
+    fn finish(
+        mut self,
+        constant_mapping: &FxHashMap<mir::Id, lir::ConstantId>,
+        return_value: mir::Id,
+    ) -> lir::Body {
+        // Constants and trace expressions don't leave a value in the LIR body,
+        // so its last expression isn't necessarily the return value. E.g., a
+        // module whose last expression is a constant function can end with the
+        // trace of a call.
+        let return_value = match self.id_mapping.get(&return_value) {
+            Some(&id) => id,
+            None => self.push(return_value, constant_mapping[&return_value]),
+        };
+        self.ids_to_drop.remove(&return_value);
+        if !self.ids_to_drop.is_empty() || self.body.last_expression_id() != Some(return_value) {
             for id in self.ids_to_drop.iter().sorted().rev() {
                 self.body.push(lir::Expression::Drop(*id));
             }
-            self.body
-                .push(lir::Expression::Reference(last_expression_id));
+            self.body.push(lir::Expression::Reference(return_value));
         }
 
         self.body
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LoweringContext;
+    use crate::{
+        builtin_functions::BuiltinFunction,
+        hir,
+        id::CountableId,
+        lir,
+        mir::{self, Expression, Mir},
+    };
+    use rustc_hash::FxHashSet;
+
+    #[test]
+    fn constant_return_value_after_trace_is_returned() {
+        let mir = Mir::build(|body| {
+            let function = body.push_builtin(BuiltinFunction::IntAdd);
+            let one = body.push_int(1);
+            let responsible = body.push_hir_id(hir::Id::dummy());
+            let sum = body.push_call(function, vec![one, one], responsible);
+            body.push(Expression::TraceCallEnds { return_value: sum });
+            body.push_int(42);
+        });
+
+        let mut context = LoweringContext::default();
+        let body_id = context.compile_function(
+            FxHashSet::default(),
+            &[],
+            &[],
+            mir::Id::from_usize(0),
+            &mir.body,
+        );
+        let body = context.bodies.get(body_id);
+        let lir::Expression::Reference(return_value) = body.expressions().last().unwrap() else {
+            panic!("The body should end with its return value.");
+        };
+        let lir::Expression::Constant(constant) = body.expression(*return_value).unwrap() else {
+            panic!("The return value should be a constant.");
+        };
+        assert_eq!(
+            context.constants.get(*constant),
+            &lir::Constant::Int(42.into())
+        );
+    }
+}
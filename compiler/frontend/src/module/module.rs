@@ -57,7 +57,48 @@ impl Module {
         let relative_path = canonicalized
             .strip_prefix(package.to_path(packages_path).unwrap())
             .map_err(|_| ModuleFromPathError::NotInPackage(path.to_owned()))?;
+        Ok(Self::from_package_and_relative_path(
+            package,
+            relative_path,
+            kind,
+        ))
+    }
+    /// Unlike [`Self::from_path`], the file doesn't have to exist, e.g., because
+    /// it's about to be created or renamed. Only its parent folder has to
+    /// exist and belong to a package.
+    pub fn from_new_path(
+        packages_path: &PackagesPath,
+        path: &Path,
+        kind: ModuleKind,
+    ) -> Result<Self, ModuleFromPathError> {
+        let not_found = || ModuleFromPathError::NotFound(path.to_owned());
+        let not_in_package = || ModuleFromPathError::NotInPackage(path.to_owned());
+        let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(not_found());
+        };
+        let parent = dunce::canonicalize(parent).map_err(|_| not_found())?;
+        let package = packages_path
+            .find_surrounding_package(&parent)
+            .ok_or_else(not_in_package)?;
 
+        let canonicalized = parent.join(file_name);
+        let relative_path = canonicalized
+            .strip_prefix(package.to_path(packages_path).unwrap())
+            .map_err(|_| not_in_package())?;
+        Ok(Self::from_package_and_relative_path(
+            package,
+            relative_path,
+            kind,
+        ))
+    }
+    /// Unlike [`Self::from_package_and_path`], this doesn't access the file
+    /// system, so it also works for files that don't exist (yet).
+    #[must_use]
+    pub fn from_package_and_relative_path(
+        package: Package,
+        relative_path: &Path,
+        kind: ModuleKind,
+    ) -> Self {
         let mut path = relative_path
             .components()
             .map(|component| match component {
@@ -83,11 +124,11 @@ impl Module {
             }
         }
 
-        Ok(Self {
+        Self {
             package,
             path,
             kind,
-        })
+        }
     }
 
     #[must_use]
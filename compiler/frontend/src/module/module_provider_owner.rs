@@ -22,6 +22,17 @@ pub trait MutableModuleProviderOwner: ModuleProviderOwner {
         self.get_in_memory_module_provider().remove(module);
         self.invalidate_module(module);
     }
+    /// Called after a module's file was renamed. The content of open modules
+    /// moves along.
+    fn did_move_module(&mut self, from: &Module, to: &Module) {
+        let provider = self.get_in_memory_module_provider();
+        if let Some(content) = provider.get_content(from) {
+            provider.remove(from);
+            provider.add(to, (*content).clone());
+        }
+        self.invalidate_module(from);
+        self.invalidate_module(to);
+    }
     #[must_use]
     fn get_open_modules(&mut self) -> Vec<Module> {
         self.get_in_memory_module_provider()
@@ -37,52 +48,12 @@ mod test {
 
     use super::*;
     use crate::{
-        ast::AstDbStorage,
-        ast_to_hir::AstToHirStorage,
-        cst::{CstDbStorage, CstKind},
-        cst_to_ast::CstToAstStorage,
-        hir::HirDbStorage,
-        hir_to_mir::HirToMirStorage,
-        mir_optimize::OptimizeMirStorage,
-        module::{GetModuleContentQuery, ModuleDb, ModuleDbStorage, ModuleKind, Package},
-        position::PositionConversionStorage,
-        rcst_to_cst::RcstToCstStorage,
-        string_to_rcst::{StringToRcst, StringToRcstStorage},
+        cst::CstKind,
+        module::{ModuleDb, ModuleKind, Package},
+        string_to_rcst::StringToRcst,
+        test_database::Database,
     };
 
-    #[salsa::database(
-        AstDbStorage,
-        AstToHirStorage,
-        CstDbStorage,
-        CstToAstStorage,
-        HirDbStorage,
-        HirToMirStorage,
-        ModuleDbStorage,
-        OptimizeMirStorage,
-        PositionConversionStorage,
-        RcstToCstStorage,
-        StringToRcstStorage
-    )]
-    #[derive(Default)]
-    pub struct Database {
-        storage: salsa::Storage<Self>,
-        module_provider: InMemoryModuleProvider,
-    }
-    impl salsa::Database for Database {}
-    impl ModuleProviderOwner for Database {
-        fn get_module_provider(&self) -> &dyn ModuleProvider {
-            &self.module_provider
-        }
-    }
-    impl MutableModuleProviderOwner for Database {
-        fn get_in_memory_module_provider(&mut self) -> &mut InMemoryModuleProvider {
-            &mut self.module_provider
-        }
-        fn invalidate_module(&mut self, module: &Module) {
-            GetModuleContentQuery.in_db_mut(self).invalidate(module);
-        }
-    }
-
     #[test]
     fn on_demand_module_content_works() {
         let mut db = Database::default();
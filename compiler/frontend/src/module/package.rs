@@ -1,3 +1,4 @@
+use super::module::{Module, ModuleKind};
 use derive_more::Deref;
 use rustc_hash::FxHashSet;
 use shellexpand::tilde;
@@ -121,6 +122,17 @@ impl Package {
         Self::Managed(PathBuf::from("Core"))
     }
 
+    /// The `_package.candy` module that marks the root folder of the package.
+    /// It configures the package, e.g., the levels of lints.
+    #[must_use]
+    pub fn manifest(&self) -> Module {
+        Module {
+            package: self.clone(),
+            path: vec!["_package".to_string()],
+            kind: ModuleKind::Code,
+        }
+    }
+
     #[must_use]
     pub fn to_path(&self, packages_path: &PackagesPath) -> Option<PathBuf> {
         match self {
@@ -130,6 +142,41 @@ impl Package {
             Self::Tooling(_) => None,
         }
     }
+
+    /// All code modules in the package's folder. Hidden folders like `.candy`
+    /// are skipped.
+    #[must_use]
+    pub fn modules(&self, packages_path: &PackagesPath) -> Vec<Module> {
+        let Some(root) = self.to_path(packages_path) else {
+            return vec![];
+        };
+
+        let mut modules = vec![];
+        let mut folders = vec![root.clone()];
+        while let Some(folder) = folders.pop() {
+            let Ok(children) = fs::read_dir(&folder) else {
+                continue;
+            };
+            for child in children.filter_map(Result::ok) {
+                if child.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+
+                let path = child.path();
+                if path.is_dir() {
+                    folders.push(path);
+                } else if path.extension() == Some(OsStr::new("candy")) {
+                    modules.push(Module::from_package_and_relative_path(
+                        self.clone(),
+                        path.strip_prefix(&root).unwrap(),
+                        ModuleKind::Code,
+                    ));
+                }
+            }
+        }
+        modules.sort();
+        modules
+    }
 }
 impl Display for Package {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -0,0 +1,192 @@
+//! Names of definitions and parameters are written in `camelCase` (`fooBar`),
+//! symbols used as struct keys in `PascalCase` (`FooBar`). The parser accepts
+//! underscores in both, so names like `foo_bar` or `Foo_bar` result in a
+//! warning that suggests the conventional name.
+//!
+//! Leading underscores are kept, so names like `_` or `_unused` are fine.
+
+use crate::{
+    ast::{
+        Assignment, AssignmentBody, Ast, AstKind, AstString, Call, Function, Identifier, List,
+        Match, MatchCase, OrPattern, Struct, StructAccess, Symbol, Text,
+    },
+    cst_to_ast::CstToAst,
+    error::CompilerError,
+    mir::MirError,
+    module::Module,
+    utils::AdjustCasingOfFirstLetter,
+};
+
+pub fn naming_convention_warnings<Db: CstToAst + ?Sized>(
+    db: &Db,
+    module: Module,
+) -> Vec<CompilerError> {
+    let Ok((asts, _)) = db.ast(module) else {
+        return vec![];
+    };
+
+    let mut names = vec![];
+    for ast in asts.iter() {
+        collect_names_in_expression(ast, &mut names);
+    }
+    names
+        .into_iter()
+        .filter_map(|(name, casing)| {
+            let conventional_name = casing.apply(name);
+            if conventional_name == name.value {
+                return None;
+            }
+            Some(CompilerError {
+                module: name.id.module.clone(),
+                span: db.ast_id_to_span(&name.id)?,
+                payload: MirError::UnconventionalName {
+                    name: name.value.clone(),
+                    conventional_name,
+                }
+                .into(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Casing {
+    CamelCase,
+    PascalCase,
+}
+impl Casing {
+    fn apply(self, name: &str) -> String {
+        let words = name.trim_start_matches('_');
+        let leading_underscores = &name[..name.len() - words.len()];
+        let mut words = words.split('_').filter(|it| !it.is_empty());
+
+        let mut conventional_name = leading_underscores.to_string();
+        if let Some(first_word) = words.next() {
+            conventional_name.push_str(&match self {
+                Self::CamelCase => first_word.lowercase_first_letter(),
+                Self::PascalCase => first_word.uppercase_first_letter(),
+            });
+        }
+        for word in words {
+            conventional_name.push_str(&word.uppercase_first_letter());
+        }
+        conventional_name
+    }
+}
+
+fn collect_names_in_expression<'a>(ast: &'a Ast, names: &mut Vec<(&'a AstString, Casing)>) {
+    match &ast.kind {
+        AstKind::Int(_)
+        | AstKind::TextPart(_)
+        | AstKind::Identifier(_)
+        | AstKind::Symbol(_)
+        | AstKind::Error { .. } => {}
+        AstKind::Text(Text(parts)) | AstKind::List(List(parts)) => {
+            for part in parts {
+                collect_names_in_expression(part, names);
+            }
+        }
+        AstKind::Struct(Struct { fields }) => {
+            for (key, value) in fields {
+                if let Some(key) = key {
+                    if let AstKind::Symbol(Symbol(symbol)) = &key.kind {
+                        names.push((symbol, Casing::PascalCase));
+                    } else {
+                        collect_names_in_expression(key, names);
+                    }
+                }
+                collect_names_in_expression(value, names);
+            }
+        }
+        AstKind::StructAccess(StructAccess { struct_, .. }) => {
+            collect_names_in_expression(struct_, names);
+        }
+        AstKind::Function(function) => collect_names_in_function(function, names),
+        AstKind::Call(Call {
+            receiver,
+            arguments,
+            ..
+        }) => {
+            collect_names_in_expression(receiver, names);
+            for argument in arguments {
+                collect_names_in_expression(argument, names);
+            }
+        }
+        AstKind::Assignment(Assignment { body, .. }) => match body {
+            AssignmentBody::Function { name, function } => {
+                names.push((name, Casing::CamelCase));
+                collect_names_in_function(function, names);
+            }
+            AssignmentBody::Body { pattern, body } => {
+                collect_names_in_pattern(pattern, names);
+                for ast in body {
+                    collect_names_in_expression(ast, names);
+                }
+            }
+        },
+        AstKind::Match(Match { expression, cases }) => {
+            collect_names_in_expression(expression, names);
+            for case in cases {
+                collect_names_in_expression(case, names);
+            }
+        }
+        AstKind::MatchCase(MatchCase {
+            pattern,
+            condition,
+            body,
+        }) => {
+            collect_names_in_pattern(pattern, names);
+            if let Some(condition) = condition {
+                collect_names_in_expression(condition, names);
+            }
+            for ast in body {
+                collect_names_in_expression(ast, names);
+            }
+        }
+        AstKind::OrPattern(_) => collect_names_in_pattern(ast, names),
+    }
+}
+fn collect_names_in_function<'a>(function: &'a Function, names: &mut Vec<(&'a AstString, Casing)>) {
+    for parameter in &function.parameters {
+        collect_names_in_pattern(parameter, names);
+    }
+    for ast in &function.body {
+        collect_names_in_expression(ast, names);
+    }
+}
+/// Identifiers in patterns define new names. Struct keys in patterns refer to
+/// existing keys, so they're reported where those are defined.
+fn collect_names_in_pattern<'a>(pattern: &'a Ast, names: &mut Vec<(&'a AstString, Casing)>) {
+    match &pattern.kind {
+        AstKind::Identifier(Identifier(name)) => names.push((name, Casing::CamelCase)),
+        AstKind::List(List(items)) | AstKind::OrPattern(OrPattern(items)) => {
+            for item in items {
+                collect_names_in_pattern(item, names);
+            }
+        }
+        AstKind::Struct(Struct { fields }) => {
+            for (_, value) in fields {
+                collect_names_in_pattern(value, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Casing;
+
+    #[test]
+    fn test_casing() {
+        assert_eq!(Casing::CamelCase.apply("fooBar"), "fooBar");
+        assert_eq!(Casing::CamelCase.apply("foo_bar"), "fooBar");
+        assert_eq!(Casing::CamelCase.apply("foo__bar_"), "fooBar");
+        assert_eq!(Casing::CamelCase.apply("foo_bar_2"), "fooBar2");
+        assert_eq!(Casing::CamelCase.apply("_"), "_");
+        assert_eq!(Casing::CamelCase.apply("_unused_value"), "_unusedValue");
+        assert_eq!(Casing::PascalCase.apply("FooBar"), "FooBar");
+        assert_eq!(Casing::PascalCase.apply("Foo_bar"), "FooBar");
+        assert_eq!(Casing::PascalCase.apply("HTTP_STATUS"), "HTTPSTATUS");
+    }
+}
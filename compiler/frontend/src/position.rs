@@ -20,7 +20,8 @@ pub struct Offset(pub usize);
 pub struct Position {
     /// Zero-based line index (`\n`-separated)
     pub line: usize,
-    /// Zero-based character index (counting grapheme clusters)
+    /// Zero-based character index (counting grapheme clusters for positions
+    /// shown to users and [`PositionEncoding`] units for [`LineIndex`])
     pub character: usize,
 }
 impl Display for Position {
@@ -45,7 +46,7 @@ pub trait PositionConversionDb: ModuleDb {
     #[salsa::transparent]
     fn offset_to_position(&self, module: Module, position: Offset) -> Position;
 
-    fn line_start_offsets(&self, module: Module) -> Arc<Vec<Offset>>;
+    fn line_index(&self, module: Module) -> Arc<LineIndex>;
 }
 
 fn range_to_positions(
@@ -72,30 +73,348 @@ fn offset_to_position(
     if *offset > text.len() {
         *offset = text.len();
     }
-    let line_start_offsets = db.line_start_offsets(module);
+    let line_index = db.line_index(module);
+    let line = line_index.line_of(offset);
 
-    let line = line_start_offsets
-        .binary_search(&offset)
-        .unwrap_or_else(|i| i - 1);
-
-    let character = text[*line_start_offsets[line]..*offset]
+    let character = text[*line_index.line_start_offsets()[line]..*offset]
         .graphemes(true)
         .count();
     Position { line, character }
 }
 
-fn line_start_offsets(db: &dyn PositionConversionDb, module: Module) -> Arc<Vec<Offset>> {
+fn line_index(db: &dyn PositionConversionDb, module: Module) -> Arc<LineIndex> {
     let text = db.get_module_content_as_string(module).unwrap();
-    Arc::new(line_start_offsets_raw(&*text))
+    Arc::new(LineIndex::new(&text))
+}
+
+/// The unit in which [`LineIndex`] counts characters within a line.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PositionEncoding {
+    Utf8,
+    /// Used by the LSP by default.
+    Utf16,
+    CodePoint,
+}
+impl PositionEncoding {
+    const fn length_of(self, character: char) -> usize {
+        match self {
+            Self::Utf8 => character.len_utf8(),
+            Self::Utf16 => character.len_utf16(),
+            Self::CodePoint => 1,
+        }
+    }
+}
+
+/// Converts between offsets and positions of a text in O(log n).
+///
+/// Besides the start of each line, this stores the position of each non-ASCII
+/// character. All characters in between are ASCII and, therefore, take up one
+/// unit in every [`PositionEncoding`].
+///
+/// Offsets within a character are rounded down to the start of the character,
+/// and so are positions within a character (e.g., between the two UTF-16 code
+/// units of an emoji). Positions beyond the end of a line refer to the end of
+/// that line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineIndex {
+    text_length: usize,
+    line_start_offsets: Vec<Offset>,
+    /// The index of the first entry of `wide_characters` in each line.
+    first_wide_character_of_line: Vec<usize>,
+    wide_characters: Vec<WideCharacter>,
+}
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct WideCharacter {
+    offset: Offset,
+    character: char,
+    utf16_column: usize,
+    code_point_column: usize,
+}
+
+impl LineIndex {
+    #[must_use]
+    pub fn new(text: &str) -> Self {
+        let mut line_start_offsets = vec![Offset(0)];
+        let mut first_wide_character_of_line = vec![0];
+        let mut wide_characters = vec![];
+        let mut utf16_column = 0;
+        let mut code_point_column = 0;
+        for (offset, character) in text.char_indices() {
+            if character == '\n' {
+                line_start_offsets.push(Offset(offset + 1));
+                first_wide_character_of_line.push(wide_characters.len());
+                utf16_column = 0;
+                code_point_column = 0;
+                continue;
+            }
+
+            if !character.is_ascii() {
+                wide_characters.push(WideCharacter {
+                    offset: Offset(offset),
+                    character,
+                    utf16_column,
+                    code_point_column,
+                });
+            }
+            utf16_column += character.len_utf16();
+            code_point_column += 1;
+        }
+        Self {
+            text_length: text.len(),
+            line_start_offsets,
+            first_wide_character_of_line,
+            wide_characters,
+        }
+    }
+
+    #[must_use]
+    pub fn line_start_offsets(&self) -> &[Offset] {
+        &self.line_start_offsets
+    }
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.line_start_offsets.len()
+    }
+    /// The line containing the given offset. Offsets after the end of the text
+    /// belong to the last line.
+    #[must_use]
+    pub fn line_of(&self, offset: Offset) -> usize {
+        self.line_start_offsets
+            .partition_point(|it| *it <= offset)
+            .saturating_sub(1)
+    }
+    /// The range of the given line, excluding its line break.
+    #[must_use]
+    pub fn line_range(&self, line: usize) -> Range<Offset> {
+        let start = self.line_start_offsets[line];
+        let end = self
+            .line_start_offsets
+            .get(line + 1)
+            .map_or(Offset(self.text_length), |it| Offset(**it - 1));
+        start..end
+    }
+    #[must_use]
+    pub fn line_length(&self, line: usize, encoding: PositionEncoding) -> usize {
+        self.offset_to_position(self.line_range(line).end, encoding)
+            .character
+    }
+
+    #[must_use]
+    pub fn offset_to_position(&self, offset: Offset, encoding: PositionEncoding) -> Position {
+        let offset = offset.min(Offset(self.text_length));
+        let line = self.line_of(offset);
+        let line_start = self.line_start_offsets[line];
+
+        let wide_characters = self.wide_characters_of_line(line);
+        let index = wide_characters.partition_point(|it| it.offset <= offset);
+        let character = match index.checked_sub(1).map(|index| wide_characters[index]) {
+            None => *offset - *line_start,
+            Some(wide) if *offset < *wide.end() => wide.column(line_start, encoding),
+            Some(wide) => {
+                wide.column(line_start, encoding)
+                    + encoding.length_of(wide.character)
+                    + (*offset - *wide.end())
+            }
+        };
+        Position { line, character }
+    }
+    #[must_use]
+    pub fn position_to_offset(&self, position: Position, encoding: PositionEncoding) -> Offset {
+        let Position { line, character } = position;
+        if line >= self.line_count() {
+            return Offset(self.text_length);
+        }
+        let line_range = self.line_range(line);
+
+        let wide_characters = self.wide_characters_of_line(line);
+        let index = wide_characters
+            .partition_point(|it| it.column(line_range.start, encoding) <= character);
+        let offset = index
+            .checked_sub(1)
+            .map(|index| wide_characters[index])
+            .map_or_else(
+                || *line_range.start + character,
+                |wide| {
+                    let wide_end_column = wide.column(line_range.start, encoding)
+                        + encoding.length_of(wide.character);
+                    if character < wide_end_column {
+                        *wide.offset
+                    } else {
+                        *wide.end() + (character - wide_end_column)
+                    }
+                },
+            );
+        Offset(offset).min(line_range.end)
+    }
+
+    fn wide_characters_of_line(&self, line: usize) -> &[WideCharacter] {
+        let start = self.first_wide_character_of_line[line];
+        let end = self
+            .first_wide_character_of_line
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.wide_characters.len());
+        &self.wide_characters[start..end]
+    }
 }
-pub fn line_start_offsets_raw<S: AsRef<str>>(text: S) -> Vec<Offset> {
-    let mut offsets = vec![Offset(0)];
-    offsets.extend(
-        text.as_ref()
-            .bytes()
-            .enumerate()
-            .filter(|(_, it)| it == &b'\n')
-            .map(|(index, _)| Offset(index + 1)),
-    );
-    offsets
+impl WideCharacter {
+    fn end(self) -> Offset {
+        Offset(*self.offset + self.character.len_utf8())
+    }
+    fn column(self, line_start: Offset, encoding: PositionEncoding) -> usize {
+        match encoding {
+            PositionEncoding::Utf8 => *self.offset - *line_start,
+            PositionEncoding::Utf16 => self.utf16_column,
+            PositionEncoding::CodePoint => self.code_point_column,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ENCODINGS: [PositionEncoding; 3] = [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::CodePoint,
+    ];
+
+    fn naive_column(line: &str, encoding: PositionEncoding) -> usize {
+        line.chars().map(|it| encoding.length_of(it)).sum()
+    }
+    fn check(text: &str) {
+        let line_index = LineIndex::new(text);
+        let lines = text.split('\n').collect::<Vec<_>>();
+        assert_eq!(line_index.line_count(), lines.len());
+
+        let mut line_start = 0;
+        for (line_number, line) in lines.iter().enumerate() {
+            for encoding in ENCODINGS {
+                assert_eq!(
+                    line_index.line_length(line_number, encoding),
+                    naive_column(line, encoding),
+                );
+            }
+
+            for (column_offset, _) in line.char_indices().chain([(line.len(), ' ')]) {
+                let offset = Offset(line_start + column_offset);
+                for encoding in ENCODINGS {
+                    let position = Position {
+                        line: line_number,
+                        character: naive_column(&line[..column_offset], encoding),
+                    };
+                    assert_eq!(
+                        line_index.offset_to_position(offset, encoding),
+                        position,
+                        "{text:?} at {offset:?} in {encoding:?}",
+                    );
+                    assert_eq!(
+                        line_index.position_to_offset(position, encoding),
+                        offset,
+                        "{text:?} at {position:?} in {encoding:?}",
+                    );
+                }
+            }
+            line_start += line.len() + 1;
+        }
+    }
+
+    #[test]
+    fn test_line_index() {
+        check("");
+        check("foo");
+        check("foo\nbar\n");
+        check("äöü\n€ = 😀\n\nfoo😀bar");
+        check("e\u{301}\r\n");
+    }
+
+    #[test]
+    fn test_line_index_rounding() {
+        let line_index = LineIndex::new("a😀b\nc");
+        // Within the emoji.
+        assert_eq!(
+            line_index.offset_to_position(Offset(3), PositionEncoding::Utf16),
+            Position {
+                line: 0,
+                character: 1,
+            },
+        );
+        assert_eq!(
+            line_index.position_to_offset(
+                Position {
+                    line: 0,
+                    character: 2,
+                },
+                PositionEncoding::Utf16,
+            ),
+            Offset(1),
+        );
+        // Beyond the end of a line, the line, and the text.
+        assert_eq!(
+            line_index.position_to_offset(
+                Position {
+                    line: 0,
+                    character: 10,
+                },
+                PositionEncoding::Utf16,
+            ),
+            Offset(6),
+        );
+        assert_eq!(
+            line_index.position_to_offset(
+                Position {
+                    line: 5,
+                    character: 0,
+                },
+                PositionEncoding::Utf16,
+            ),
+            Offset(8),
+        );
+        assert_eq!(
+            line_index.offset_to_position(Offset(100), PositionEncoding::Utf16),
+            Position {
+                line: 1,
+                character: 1,
+            },
+        );
+    }
+
+    #[test]
+    fn test_line_index_with_random_documents() {
+        const CHARACTERS: &[char] = &[
+            'a',
+            'Z',
+            ' ',
+            '\n',
+            '\r',
+            '\t',
+            'ä',
+            'ß',
+            '€',
+            '😀',
+            '🍭',
+            '\u{301}',
+            '\u{FEFF}',
+            '\u{10FFFF}',
+        ];
+
+        // A simple xorshift generator so that failures are reproducible.
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next_random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            #[allow(clippy::cast_possible_truncation)]
+            let random = state as usize;
+            random
+        };
+        for _ in 0..200 {
+            let length = next_random() % 64;
+            let text = (0..length)
+                .map(|_| CHARACTERS[next_random() % CHARACTERS.len()])
+                .collect::<String>();
+            check(&text);
+        }
+    }
 }
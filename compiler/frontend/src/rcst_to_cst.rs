@@ -114,6 +114,10 @@ impl Rcst {
                 *state.offset += 1;
                 CstKind::Octothorpe
             }
+            CstKind::If => {
+                *state.offset += 2;
+                CstKind::If
+            }
             CstKind::Whitespace(whitespace) => {
                 *state.offset += whitespace.len();
                 CstKind::Whitespace(whitespace.clone())
@@ -257,10 +261,14 @@ impl Rcst {
             },
             CstKind::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
             } => CstKind::MatchCase {
                 pattern: Box::new(pattern.to_cst(state)),
+                guard: guard.as_deref().map(|(keyword, condition)| {
+                    Box::new((keyword.to_cst(state), condition.to_cst(state)))
+                }),
                 arrow: Box::new(arrow.to_cst(state)),
                 body: body.to_csts_helper(state),
             },
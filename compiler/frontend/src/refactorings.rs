@@ -0,0 +1,1075 @@
+//! Refactorings that tools like the language server offer as code actions.
+//!
+//! The refactorings edit the source code directly, so comments and the
+//! formatting of the moved code are preserved.
+//!
+//! - [`extract_function`] moves the selected expression(s) into a new
+//!   top-level assignment. Local values that the selection references become
+//!   parameters of the new function.
+//! - [`inline_definition`] replaces the only reference to an assignment with
+//!   the assigned value and removes the assignment. Comments in the
+//!   assignment move along with the value (see [`crate::trivia`]).
+//! - [`convert_to_pipeline`] and [`convert_to_call`] switch between nested
+//!   calls (`f (g x) y`) and pipelines (`x | g | f y`).
+//! - [`move_modules`] updates relative `use` paths across a package when
+//!   module files are moved or renamed.
+
+use crate::{
+    cst::{Cst, CstDb, CstKind},
+    hir::{self, Expression, HirDb, IdKey},
+    module::{Module, ModuleKind, UsePath},
+    position::Offset,
+    trivia::{ast_trivia, replace_ast_keeping_comments},
+};
+use itertools::Itertools;
+use std::{collections::HashMap, hash::BuildHasher, iter, ops::Range};
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<Offset>,
+    pub new_text: String,
+}
+
+/// Returns `None` if the selection doesn't consist of whole expressions or
+/// if the extracted code would behave differently.
+#[must_use]
+pub fn extract_function<DB>(
+    db: &DB,
+    module: &Module,
+    selection: Range<Offset>,
+) -> Option<Vec<TextEdit>>
+where
+    DB: HirDb,
+{
+    let source = db.get_module_content_as_string(module.clone())?;
+    let selection = trim_whitespace(&source, selection);
+    if selection.is_empty() {
+        return None;
+    }
+
+    let csts = db.cst(module.clone()).ok()?;
+    let top_level = csts.iter().find(|it| contains(&it.data.span, &selection))?;
+    if expression_span(top_level) == selection {
+        // Top-level expressions can't capture anything, so there's nothing to
+        // gain.
+        return None;
+    }
+    let selected = find_selection(top_level, &selection, false)?;
+    if let Selection::Body(expressions) = &selected
+        && unwrap_trailing_whitespace(expressions.last().unwrap())
+            .kind
+            .is_assignment()
+    {
+        // The value of the body would change.
+        return None;
+    }
+
+    let references = references_in(db, module);
+    let is_in_selection = |span: &Range<Offset>| contains(&selection, span);
+    let mut parameters: Vec<(hir::Id, String)> = vec![];
+    for reference in &references {
+        let target_is_in_selection = reference.target_span.as_ref().is_some_and(is_in_selection);
+        if !is_in_selection(&reference.span) {
+            if target_is_in_selection && reference.target_is_named() {
+                // A value defined in the selection is used afterwards.
+                return None;
+            }
+            continue;
+        }
+
+        if reference.target_span.is_none() {
+            // Generated code is available everywhere.
+            continue;
+        }
+        if target_is_in_selection
+            || reference.target.parent().unwrap().is_root()
+            || parameters.iter().any(|(id, _)| id == &reference.target)
+        {
+            continue;
+        }
+        let name = source[*reference.span.start..*reference.span.end].to_string();
+        parameters.push((reference.target.clone(), name));
+    }
+
+    let name = unused_top_level_name(db, module, "extracted");
+    let signature = parameters
+        .iter()
+        .map(|(_, name)| name.as_str())
+        .fold(name, |signature, parameter| {
+            format!("{signature} {parameter}")
+        });
+
+    let code = &source[*selection.start..*selection.end];
+    let indentation = indentation_of_line(&source, selection.start);
+    let is_multiline = matches!(&selected, Selection::Body(it) if it.len() > 1);
+    let definition = if is_multiline || code.contains('\n') {
+        format!("{signature} =\n  {}\n\n", reindent(code, indentation, "  "))
+    } else {
+        format!("{signature} = {code}\n")
+    };
+    let call = match selected {
+        Selection::Expression {
+            needs_parentheses: true,
+            ..
+        } if !parameters.is_empty() => format!("({signature})"),
+        _ => signature,
+    };
+
+    let insertion_offset = start_including_comments(&source, top_level.data.span.start);
+    Some(vec![
+        TextEdit {
+            range: insertion_offset..insertion_offset,
+            new_text: definition,
+        },
+        TextEdit {
+            range: selection,
+            new_text: call,
+        },
+    ])
+}
+
+/// Returns `None` if there's no assignment at the offset that is referenced
+/// exactly once or if inlining it would change the meaning of the code.
+#[must_use]
+pub fn inline_definition<DB>(db: &DB, module: Module, offset: Offset) -> Option<Vec<TextEdit>>
+where
+    DB: HirDb,
+{
+    let source = db.get_module_content_as_string(module.clone())?;
+    let origin = db.find_cst_by_offset(module.clone(), offset);
+    let CstKind::Identifier(name) = &origin.kind else {
+        return None;
+    };
+    let hir_id = db.cst_to_last_hir_id(module.clone(), origin.data.id)?;
+    let target = if db
+        .containing_body_of(hir_id.clone())
+        .identifiers
+        .contains_key(&hir_id)
+    {
+        hir_id
+    } else {
+        let Expression::Reference(target) = db.find_expression(hir_id)? else {
+            return None;
+        };
+        target
+    };
+    let definition_id = db.hir_to_cst_id(&target)?;
+
+    let csts = db.cst(module.clone()).ok()?;
+    let (assignment, value) = descendants(&csts).into_iter().find_map(|cst| {
+        let CstKind::Assignment {
+            left,
+            assignment_sign,
+            body,
+        } = &cst.kind
+        else {
+            return None;
+        };
+        let left = unwrap_trailing_whitespace(left);
+        if left.data.id != definition_id
+            || !matches!(&left.kind, CstKind::Identifier(it) if it == name)
+            || !unwrap_trailing_whitespace(assignment_sign)
+                .kind
+                .is_equals_sign()
+        {
+            return None;
+        }
+        let value = body
+            .iter()
+            .filter(|it| !it.is_whitespace_or_comment())
+            .exactly_one()
+            .ok()?;
+        Some((cst, unwrap_trailing_whitespace(value)))
+    })?;
+    let assignment_span = expression_span(assignment);
+    let value_span = expression_span(value);
+
+    let references = references_in(db, &module);
+    let Ok(usage) = references
+        .iter()
+        .filter(|it| it.target == target)
+        .exactly_one()
+    else {
+        return None;
+    };
+    // Names used in the value must still refer to the same definitions at the
+    // usage. To keep this simple, we don't allow any definitions with these
+    // names in between.
+    let used_names = references
+        .iter()
+        .filter(|it| contains(&value_span, &it.span))
+        .map(|it| &source[*it.span.start..*it.span.end])
+        .collect_vec();
+    let is_shadowed = db.all_hir_ids(module.clone()).into_iter().any(|id| {
+        let Some(IdKey::Named { name, .. }) = id.keys.last() else {
+            return false;
+        };
+        used_names.contains(&name.as_str())
+            && db.hir_id_to_span(&id).is_some_and(|span| {
+                span.start > assignment_span.end && span.start < usage.span.start
+            })
+    });
+    if is_shadowed {
+        return None;
+    }
+
+    let value_code = reindent(
+        &source[*value_span.start..*value_span.end],
+        indentation_of_line(&source, assignment_span.start),
+        indentation_of_line(&source, usage.span.start),
+    );
+    let usage_cst = db.find_cst_by_offset(module.clone(), usage.span.start);
+    let needs_parentheses = !is_atomic(value)
+        && find_parent(&csts, &usage_cst).is_some_and(requires_parentheses_for_children);
+    let value_code = if needs_parentheses {
+        format!("({value_code})")
+    } else {
+        value_code
+    };
+    // Comments in the assignment that aren't part of the value, e.g., on a
+    // line before it, are moved to the usage.
+    let assignment_comments = db
+        .cst_to_ast_ids(module, assignment.data.id)
+        .first()
+        .and_then(|id| ast_trivia(db, id))
+        .map(|it| it.inner_comments)
+        .unwrap_or_default();
+    let usage = replace_ast_keeping_comments(
+        db,
+        &db.hir_to_ast_id(&usage.id)?,
+        &value_code,
+        &assignment_comments,
+    )?;
+
+    Some(vec![
+        TextEdit {
+            range: whole_lines(&source, assignment_span),
+            new_text: String::new(),
+        },
+        TextEdit {
+            range: usage.range,
+            new_text: usage.new_text,
+        },
+    ])
+}
+
+/// Rewrites the call at the offset and calls nested in its first argument into
+/// a pipeline, e.g., `f (g x) y` into `x | g | f y`.
+///
+/// Returns `None` if there's no call at the offset, if it's the right side of
+/// a pipe, or if it spans multiple lines.
+#[must_use]
+pub fn convert_to_pipeline<DB>(db: &DB, module: Module, offset: Offset) -> Option<Vec<TextEdit>>
+where
+    DB: HirDb,
+{
+    let source = db.get_module_content_as_string(module.clone())?;
+    let csts = db.cst(module).ok()?;
+    let mut call = innermost_containing(&csts, offset, |it| it.kind.is_call())?;
+    // The whole chain of calls becomes one pipeline.
+    loop {
+        let Some(parenthesized) = find_parent(&csts, call) else {
+            break;
+        };
+        let Some(
+            parent @ Cst {
+                kind: CstKind::Call { arguments, .. },
+                ..
+            },
+        ) = find_parent(&csts, parenthesized)
+        else {
+            break;
+        };
+        let first_argument = arguments.first().map(unwrap_trailing_whitespace);
+        if !parenthesized.kind.is_parenthesized()
+            || first_argument.map(|it| it.data.id) != Some(parenthesized.data.id)
+        {
+            break;
+        }
+        call = parent;
+    }
+    if let Some(Cst {
+        kind: CstKind::BinaryBar { right, .. },
+        ..
+    }) = find_parent(&csts, call)
+        && unwrap_trailing_whitespace(right).data.id == call.data.id
+    {
+        // In `a | f b`, `f b` is not a call on its own.
+        return None;
+    }
+
+    let span = code_span(call);
+    if source[*span.start..*span.end].contains('\n') {
+        return None;
+    }
+    Some(vec![TextEdit {
+        range: span,
+        new_text: pipeline_code(&source, call),
+    }])
+}
+fn pipeline_code(source: &str, call: &Cst) -> String {
+    let CstKind::Call {
+        receiver,
+        arguments,
+    } = &call.kind
+    else {
+        return code_of(source, call).to_string();
+    };
+    let Some((first, rest)) = arguments.split_first() else {
+        return code_of(source, call).to_string();
+    };
+
+    let first = unwrap_trailing_whitespace(first);
+    let left = match &first.kind {
+        CstKind::Parenthesized { inner, .. } => {
+            let inner = unwrap_trailing_whitespace(inner);
+            match &inner.kind {
+                CstKind::Call { .. } => pipeline_code(source, inner),
+                // Pipes are left-associative, so `f (a | g)` becomes `a | g | f`.
+                CstKind::BinaryBar { .. } => code_of(source, inner).to_string(),
+                _ => code_of(source, first).to_string(),
+            }
+        }
+        _ => code_of(source, first).to_string(),
+    };
+    let right = iter::once(receiver.as_ref())
+        .chain(rest)
+        .map(|it| code_of(source, it))
+        .join(" ");
+    format!("{left} | {right}")
+}
+
+/// Rewrites the pipeline at the offset into nested calls, e.g., `x | g | f y`
+/// into `f (g x) y`. Like the desugaring of pipes, the left side becomes the
+/// first argument of the right side.
+///
+/// Returns `None` if there's no pipeline at the offset or if it spans
+/// multiple lines.
+#[must_use]
+pub fn convert_to_call<DB>(db: &DB, module: Module, offset: Offset) -> Option<Vec<TextEdit>>
+where
+    DB: HirDb,
+{
+    let source = db.get_module_content_as_string(module.clone())?;
+    let csts = db.cst(module.clone()).ok()?;
+    let mut bar = innermost_containing(&csts, offset, |it| it.kind.is_binary_bar())?;
+    // The whole pipeline becomes one nested call.
+    loop {
+        let Some(
+            parent @ Cst {
+                kind: CstKind::BinaryBar { left, .. },
+                ..
+            },
+        ) = find_parent(&csts, bar)
+        else {
+            break;
+        };
+        if unwrap_trailing_whitespace(left).data.id != bar.data.id {
+            break;
+        }
+        bar = parent;
+    }
+    // Bars in patterns separate alternatives instead.
+    let hir_id = db.cst_to_last_hir_id(module, bar.data.id)?;
+    if !matches!(db.find_expression(hir_id)?, Expression::Call { .. }) {
+        return None;
+    }
+
+    let span = code_span(bar);
+    if source[*span.start..*span.end].contains('\n') {
+        return None;
+    }
+    Some(vec![TextEdit {
+        range: span,
+        new_text: call_code(&source, bar),
+    }])
+}
+fn call_code(source: &str, bar: &Cst) -> String {
+    let CstKind::BinaryBar { left, right, .. } = &bar.kind else {
+        return code_of(source, bar).to_string();
+    };
+
+    let left = unwrap_trailing_whitespace(left);
+    let left = if left.kind.is_binary_bar() {
+        format!("({})", call_code(source, left))
+    } else if is_atomic(left) {
+        code_of(source, left).to_string()
+    } else {
+        format!("({})", code_of(source, left))
+    };
+
+    let right = unwrap_trailing_whitespace(right);
+    match &right.kind {
+        CstKind::Call {
+            receiver,
+            arguments,
+        } => {
+            let arguments = arguments.iter().map(|it| code_of(source, it));
+            [code_of(source, receiver), left.as_str()]
+                .into_iter()
+                .chain(arguments)
+                .join(" ")
+        }
+        _ if is_atomic(right) => format!("{} {left}", code_of(source, right)),
+        _ => format!("({}) {left}", code_of(source, right)),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ModuleMove {
+    /// Edits per module. They refer to the modules' locations before the move.
+    pub edits: Vec<(Module, Vec<TextEdit>)>,
+    /// `use`s that can't refer to their target after the move. Relative paths
+    /// can only reach children of the using module's ancestors.
+    pub unreachable_uses: Vec<(Module, Range<Offset>)>,
+}
+
+/// Rewrites relative `use` paths so that they still refer to the same modules
+/// after moving each key of `moves` to its value.
+///
+/// `modules` should contain all modules of the affected package, e.g., from
+/// [`crate::module::Package::modules`]. The `use`s in moved modules are always
+/// updated since they are relative to the module's own location.
+#[must_use]
+pub fn move_modules<DB, S>(
+    db: &DB,
+    modules: &[Module],
+    moves: &HashMap<Module, Module, S>,
+) -> ModuleMove
+where
+    DB: CstDb,
+    S: BuildHasher,
+{
+    let mut result = ModuleMove::default();
+    for module in modules.iter().chain(moves.keys()).unique().sorted() {
+        let Ok(csts) = db.cst(module.clone()) else {
+            continue;
+        };
+        let new_module = moves.get(module).unwrap_or(module);
+
+        let mut edits = vec![];
+        for (path, span) in use_paths(&csts) {
+            let Ok(use_path @ UsePath::Relative { .. }) = UsePath::parse(path) else {
+                continue;
+            };
+            let Ok(target) = use_path.resolve_relative_to(module.clone()) else {
+                continue;
+            };
+            let new_target = moves.get(&target).unwrap_or(&target);
+            if new_module == module && *new_target == target {
+                continue;
+            }
+
+            match relative_use_path(new_module, new_target) {
+                Some(new_path) => {
+                    let new_text = new_path.to_string();
+                    if new_text != path {
+                        edits.push(TextEdit {
+                            range: span,
+                            new_text,
+                        });
+                    }
+                }
+                None => result.unreachable_uses.push((module.clone(), span)),
+            }
+        }
+        if !edits.is_empty() {
+            result.edits.push((module.clone(), edits));
+        }
+    }
+    result
+}
+
+/// The paths of `use "…"` calls together with the spans of the text between
+/// the quotes.
+fn use_paths(csts: &[Cst]) -> Vec<(&str, Range<Offset>)> {
+    descendants(csts)
+        .into_iter()
+        .filter_map(|cst| {
+            let CstKind::Call {
+                receiver,
+                arguments,
+            } = &cst.kind
+            else {
+                return None;
+            };
+            let CstKind::Identifier(function) = &unwrap_trailing_whitespace(receiver).kind else {
+                return None;
+            };
+            if function != "use" {
+                return None;
+            }
+            let [argument] = arguments.as_slice() else {
+                return None;
+            };
+            let CstKind::Text { parts, .. } = &unwrap_trailing_whitespace(argument).kind else {
+                return None;
+            };
+            let [part] = parts.as_slice() else {
+                return None;
+            };
+            let CstKind::TextPart(path) = &part.kind else {
+                return None;
+            };
+            Some((path.as_str(), part.data.span.clone()))
+        })
+        .collect()
+}
+/// The path that a `use` in `from` needs to refer to `to`.
+fn relative_use_path(from: &Module, to: &Module) -> Option<UsePath> {
+    if from.package != to.package {
+        return None;
+    }
+    let (name, parent) = to.path.split_last()?;
+    if !from.path.starts_with(parent) {
+        return None;
+    }
+
+    let path = UsePath::Relative {
+        parent_navigations: from.path.len() - parent.len(),
+        path: name.clone(),
+    };
+    // Dots mark assets, so code modules can't contain them.
+    let is_valid = UsePath::parse(&path.to_string()).is_ok()
+        && name.contains('.') == (to.kind == ModuleKind::Asset);
+    is_valid.then_some(path)
+}
+
+enum Selection<'c> {
+    /// Consecutive expressions of a body.
+    Body(Vec<&'c Cst>),
+    Expression {
+        needs_parentheses: bool,
+    },
+}
+fn find_selection<'c>(
+    cst: &'c Cst,
+    selection: &Range<Offset>,
+    needs_parentheses: bool,
+) -> Option<Selection<'c>> {
+    if !contains(&cst.data.span, selection) {
+        return None;
+    }
+    if let CstKind::BinaryBar { right, .. } = &cst.kind
+        && unwrap_trailing_whitespace(right).kind.is_call()
+        && &expression_span(right) == selection
+    {
+        // In `a | f b`, `f b` is not an expression on its own.
+        return None;
+    }
+
+    let (bodies, children) = match &cst.kind {
+        CstKind::Assignment { body, .. } | CstKind::Function { body, .. } => {
+            (vec![body], body.iter().collect_vec())
+        }
+        CstKind::MatchCase { guard, body, .. } => {
+            let mut children = guard.iter().map(|it| &it.1).collect_vec();
+            children.extend(body);
+            (vec![body], children)
+        }
+        // Keys can't be extracted.
+        CstKind::StructField { value, .. } => (vec![], vec![value.as_ref()]),
+        kind => (vec![], kind.children()),
+    };
+    for body in bodies {
+        let expressions = body
+            .iter()
+            .filter(|it| !it.is_whitespace_or_comment())
+            .collect_vec();
+        let start = expressions
+            .iter()
+            .position(|it| it.data.span.start == selection.start);
+        let end = expressions
+            .iter()
+            .position(|it| expression_span(it).end == selection.end);
+        if let (Some(start), Some(end)) = (start, end)
+            && start <= end
+        {
+            return Some(Selection::Body(expressions[start..=end].to_vec()));
+        }
+    }
+
+    let children_need_parentheses = requires_parentheses_for_children(cst);
+    for child in children {
+        if let Some(selection) = find_selection(child, selection, children_need_parentheses) {
+            return Some(selection);
+        }
+    }
+
+    let is_expression = matches!(
+        cst.kind,
+        CstKind::Identifier(_)
+            | CstKind::Symbol(_)
+            | CstKind::Int { .. }
+            | CstKind::Text { .. }
+            | CstKind::BinaryBar { .. }
+            | CstKind::Parenthesized { .. }
+            | CstKind::Call { .. }
+            | CstKind::List { .. }
+            | CstKind::Struct { .. }
+            | CstKind::StructAccess { .. }
+            | CstKind::Match { .. }
+            | CstKind::Function { .. },
+    );
+    (is_expression && &cst.data.span == selection)
+        .then_some(Selection::Expression { needs_parentheses })
+}
+/// Whether a call as a child of this CST needs to be wrapped in parentheses.
+const fn requires_parentheses_for_children(cst: &Cst) -> bool {
+    matches!(
+        cst.kind,
+        CstKind::Call { .. }
+            | CstKind::BinaryBar { .. }
+            | CstKind::StructAccess { .. }
+            | CstKind::Match { .. },
+    )
+}
+const fn is_atomic(cst: &Cst) -> bool {
+    matches!(
+        cst.kind,
+        CstKind::Identifier(_)
+            | CstKind::Symbol(_)
+            | CstKind::Int { .. }
+            | CstKind::Text { .. }
+            | CstKind::Parenthesized { .. }
+            | CstKind::List { .. }
+            | CstKind::Struct { .. }
+            | CstKind::StructAccess { .. }
+            | CstKind::Function { .. },
+    )
+}
+fn find_parent<'c>(csts: &'c [Cst], child: &Cst) -> Option<&'c Cst> {
+    descendants(csts).into_iter().find(|it| {
+        !it.kind.is_trailing_whitespace()
+            && it
+                .children()
+                .into_iter()
+                .any(|it| unwrap_trailing_whitespace(it).data.id == child.data.id)
+    })
+}
+/// The innermost CST containing the offset that matches the predicate.
+fn innermost_containing(
+    csts: &[Cst],
+    offset: Offset,
+    predicate: impl Fn(&Cst) -> bool,
+) -> Option<&Cst> {
+    descendants(csts)
+        .into_iter()
+        .filter(|it| predicate(it) && contains(&code_span(it), &(offset..offset)))
+        .min_by_key(|it| *it.data.span.end - *it.data.span.start)
+}
+fn descendants(csts: &[Cst]) -> Vec<&Cst> {
+    let mut descendants = vec![];
+    let mut to_visit = csts.iter().collect_vec();
+    while let Some(cst) = to_visit.pop() {
+        descendants.push(cst);
+        to_visit.extend(cst.children());
+    }
+    descendants
+}
+
+struct Reference {
+    id: hir::Id,
+    span: Range<Offset>,
+    target: hir::Id,
+    /// `None` for generated code.
+    target_span: Option<Range<Offset>>,
+}
+impl Reference {
+    fn target_is_named(&self) -> bool {
+        matches!(self.target.keys.last(), Some(IdKey::Named { .. }))
+    }
+}
+fn references_in<DB>(db: &DB, module: &Module) -> Vec<Reference>
+where
+    DB: HirDb,
+{
+    // IDs of referenced expressions occur multiple times.
+    db.all_hir_ids(module.clone())
+        .into_iter()
+        .unique()
+        .filter_map(|id| {
+            let Expression::Reference(target) = db.find_expression(id.clone())? else {
+                return None;
+            };
+            let span = db.hir_id_to_span(&id)?;
+            let target_span = db.hir_id_to_span(&target);
+            Some(Reference {
+                id,
+                span,
+                target,
+                target_span,
+            })
+        })
+        .collect()
+}
+
+// There are only finitely many identifiers, so one of the names is unused.
+#[allow(clippy::maybe_infinite_iter)]
+fn unused_top_level_name<DB>(db: &DB, module: &Module, base: &str) -> String
+where
+    DB: HirDb,
+{
+    let Ok((hir, _)) = db.hir(module.clone()) else {
+        return base.to_string();
+    };
+    (1..)
+        .map(|index| {
+            if index == 1 {
+                base.to_string()
+            } else {
+                format!("{base}{index}")
+            }
+        })
+        .find(|name| !hir.identifiers.values().contains(name))
+        .unwrap()
+}
+
+fn unwrap_trailing_whitespace(mut cst: &Cst) -> &Cst {
+    while let CstKind::TrailingWhitespace { child, .. } = &cst.kind {
+        cst = child;
+    }
+    cst
+}
+fn expression_span(cst: &Cst) -> Range<Offset> {
+    unwrap_trailing_whitespace(cst).data.span.clone()
+}
+/// Like [`expression_span`], but also excludes trailing whitespace and
+/// comments that are nested in the last child of calls and pipes.
+fn code_span(cst: &Cst) -> Range<Offset> {
+    let cst = unwrap_trailing_whitespace(cst);
+    let end = match &cst.kind {
+        CstKind::Call { arguments, .. } => arguments
+            .last()
+            .map_or(cst.data.span.end, |it| code_span(it).end),
+        CstKind::BinaryBar { right, .. } => code_span(right).end,
+        _ => cst.data.span.end,
+    };
+    cst.data.span.start..end
+}
+fn code_of<'s>(source: &'s str, cst: &Cst) -> &'s str {
+    let span = code_span(cst);
+    &source[*span.start..*span.end]
+}
+const fn contains(outer: &Range<Offset>, inner: &Range<Offset>) -> bool {
+    outer.start.0 <= inner.start.0 && inner.end.0 <= outer.end.0
+}
+fn trim_whitespace(source: &str, range: Range<Offset>) -> Range<Offset> {
+    let text = &source[*range.start..*range.end];
+    let start = *range.start + (text.len() - text.trim_start().len());
+    let end = *range.end - (text.len() - text.trim_end().len());
+    Offset(start)..Offset(end.max(start))
+}
+fn indentation_of_line(source: &str, offset: Offset) -> &str {
+    let line_start = source[..*offset].rfind('\n').map_or(0, |it| it + 1);
+    let line = &source[line_start..];
+    &line[..line.len() - line.trim_start_matches(' ').len()]
+}
+/// Moves the offset to the start of the comment lines directly preceding it.
+fn start_including_comments(source: &str, offset: Offset) -> Offset {
+    let mut start = *offset;
+    while start > 0 {
+        let previous_line_start = source[..start - 1].rfind('\n').map_or(0, |it| it + 1);
+        if !source[previous_line_start..start]
+            .trim_start()
+            .starts_with('#')
+        {
+            break;
+        }
+        start = previous_line_start;
+    }
+    Offset(start)
+}
+/// Moves all lines except the first from one indentation to another.
+fn reindent(code: &str, from: &str, to: &str) -> String {
+    code.split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            if index == 0 || line.is_empty() {
+                line.to_string()
+            } else {
+                format!("{to}{}", line.strip_prefix(from).unwrap_or(line))
+            }
+        })
+        .join("\n")
+}
+/// Extends the range to whole lines if nothing else is on them.
+fn whole_lines(source: &str, range: Range<Offset>) -> Range<Offset> {
+    let line_start = source[..*range.start].rfind('\n').map_or(0, |it| it + 1);
+    let line_end = source[*range.end..]
+        .find('\n')
+        .map_or(source.len(), |it| *range.end + it + 1);
+    if source[line_start..*range.start].trim().is_empty()
+        && source[*range.end..line_end].trim().is_empty()
+    {
+        Offset(line_start)..Offset(line_end)
+    } else {
+        range
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        convert_to_call, convert_to_pipeline, extract_function, inline_definition, reindent,
+        relative_use_path, trim_whitespace, whole_lines, TextEdit,
+    };
+    use crate::{
+        module::{Module, ModuleKind, Package},
+        position::Offset,
+        test_database::Database,
+    };
+    use itertools::Itertools;
+
+    fn apply(source: &str, edits: Vec<TextEdit>) -> String {
+        let mut source = source.to_string();
+        for edit in edits.into_iter().sorted_by_key(|it| it.range.start).rev() {
+            source.replace_range(*edit.range.start..*edit.range.end, &edit.new_text);
+        }
+        source
+    }
+    #[track_caller]
+    fn extract(source: &str, selection: &str) -> Option<String> {
+        let (db, module) = Database::with_main_module(source);
+        let start = source.find(selection).unwrap();
+        let selection = Offset(start)..Offset(start + selection.len());
+        extract_function(&db, &module, selection).map(|edits| apply(source, edits))
+    }
+    #[track_caller]
+    fn inline(source: &str, needle: &str) -> Option<String> {
+        let (db, module) = Database::with_main_module(source);
+        let offset = Offset(source.find(needle).unwrap());
+        inline_definition(&db, module, offset).map(|edits| apply(source, edits))
+    }
+    #[track_caller]
+    fn to_pipeline(source: &str, needle: &str) -> Option<String> {
+        let (db, module) = Database::with_main_module(source);
+        let offset = Offset(source.find(needle).unwrap());
+        convert_to_pipeline(&db, module, offset).map(|edits| apply(source, edits))
+    }
+    #[track_caller]
+    fn to_call(source: &str, needle: &str) -> Option<String> {
+        let (db, module) = Database::with_main_module(source);
+        let offset = Offset(source.find(needle).unwrap());
+        convert_to_call(&db, module, offset).map(|edits| apply(source, edits))
+    }
+
+    #[test]
+    fn extracting_turns_local_values_into_parameters() {
+        assert_eq!(
+            extract(
+                "double x = x\nfoo a =\n  b = double a\n  double b\n",
+                "double b",
+            )
+            .as_deref(),
+            Some("double x = x\nextracted b = double b\nfoo a =\n  b = double a\n  extracted b\n"),
+        );
+    }
+    #[test]
+    fn extracting_multiple_expressions() {
+        assert_eq!(
+            extract(
+                "double x = x\nfoo a =\n  b = double a\n  double b\n",
+                "b = double a\n  double b",
+            )
+            .as_deref(),
+            Some(
+                "double x = x\nextracted a =\n  b = double a\n  double b\n\nfoo a =\n  extracted a\n",
+            ),
+        );
+    }
+    #[test]
+    fn extracting_from_a_function_captures_its_parameters() {
+        assert_eq!(
+            extract(
+                "double y = y\nfoo a =\n  { x -> double (double x) }\n",
+                "double x",
+            )
+            .as_deref(),
+            Some(
+                "double y = y\nextracted x = double x\nfoo a =\n  { x -> double (extracted x) }\n",
+            ),
+        );
+        assert_eq!(
+            extract("double y = y\nfoo a =\n  { x -> double a }\n", "double a").as_deref(),
+            Some("double y = y\nextracted a = double a\nfoo a =\n  { x -> extracted a }\n"),
+        );
+    }
+    #[test]
+    fn extracting_parts_of_pipes() {
+        assert_eq!(
+            extract(
+                "double x = x\nfoo a =\n  b = a | double\n  double b\n",
+                "a | double",
+            )
+            .as_deref(),
+            Some(
+                "double x = x\nextracted a = a | double\nfoo a =\n  b = extracted a\n  double b\n"
+            ),
+        );
+        assert_eq!(
+            extract("double x = x\nfoo a =\n  double a | double\n", "double a").as_deref(),
+            Some("double x = x\nextracted a = double a\nfoo a =\n  extracted a | double\n"),
+        );
+        // In `a | f b`, `f b` is not an expression on its own.
+        assert_eq!(
+            extract("add x y = x\nfoo a =\n  a | add a\n", "add a"),
+            None,
+        );
+    }
+    #[test]
+    fn extracting_values_that_are_used_later_fails() {
+        assert_eq!(
+            extract(
+                "double x = x\nfoo a =\n  b = double a\n  double b\n",
+                "b = double a",
+            ),
+            None,
+        );
+    }
+
+    #[test]
+    fn inlining_replaces_the_only_usage() {
+        assert_eq!(
+            inline("double x = x\nfoo a =\n  b = double a\n  double b\n", "b =",).as_deref(),
+            Some("double x = x\nfoo a =\n  double (double a)\n"),
+        );
+        // It also works from the usage.
+        assert_eq!(
+            inline("foo a =\n  b = a\n  { x -> b }\n", "b }").as_deref(),
+            Some("foo a =\n  { x -> a }\n"),
+        );
+    }
+    #[test]
+    fn inlining_moves_comments_of_the_assignment_to_the_usage() {
+        assert_eq!(
+            inline(
+                "foo =\n  bar =\n    # Explains bar.\n    1\n  baz bar\n",
+                "bar =",
+            )
+            .as_deref(),
+            Some("foo =\n  baz # Explains bar.\n    1\n"),
+        );
+    }
+    #[test]
+    fn inlining_values_that_are_used_multiple_times_fails() {
+        assert_eq!(
+            inline(
+                "double x = x\nfoo a =\n  b = double a\n  double b\n  b\n",
+                "b =",
+            ),
+            None,
+        );
+    }
+    #[test]
+    fn inlining_shadowed_values_fails() {
+        assert_eq!(
+            inline(
+                "double x = x\nfoo a =\n  b = double a\n  { x -> double b }\n",
+                "b =",
+            )
+            .as_deref(),
+            Some("double x = x\nfoo a =\n  { x -> double (double a) }\n"),
+        );
+        assert_eq!(
+            inline(
+                "double x = x\nfoo a =\n  b = double a\n  { a -> double b }\n",
+                "b =",
+            ),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_reindent() {
+        assert_eq!(reindent("foo", "    ", "  "), "foo");
+        assert_eq!(
+            reindent("foo\n      bar\n\n    baz", "    ", "  "),
+            "foo\n    bar\n\n  baz",
+        );
+    }
+
+    #[test]
+    fn test_trim_whitespace() {
+        assert_eq!(
+            trim_whitespace("a  foo bar\n  b", Offset(1)..Offset(12)),
+            Offset(3)..Offset(10),
+        );
+        assert_eq!(
+            trim_whitespace("a   b", Offset(1)..Offset(4)),
+            Offset(4)..Offset(4)
+        );
+    }
+
+    #[test]
+    fn test_whole_lines() {
+        let source = "foo =\n  bar = 1\n  bar\n";
+        assert_eq!(
+            whole_lines(source, Offset(8)..Offset(15)),
+            Offset(6)..Offset(16)
+        );
+        assert_eq!(
+            whole_lines("a = 1 # b", Offset(0)..Offset(5)),
+            Offset(0)..Offset(5)
+        );
+    }
+
+    #[test]
+    fn test_relative_use_path() {
+        let module = |path: &[&str], kind| Module {
+            package: Package::User("/package".into()),
+            path: path.iter().map(ToString::to_string).collect(),
+            kind,
+        };
+        let use_path = |from: &[&str], to: &[&str], kind| {
+            relative_use_path(&module(from, ModuleKind::Code), &module(to, kind))
+                .map(|it| it.to_string())
+        };
+        assert_eq!(
+            use_path(&["a"], &["a", "b"], ModuleKind::Code).as_deref(),
+            Some(".b")
+        );
+        assert_eq!(
+            use_path(&["a"], &["b"], ModuleKind::Code).as_deref(),
+            Some("..b")
+        );
+        assert_eq!(
+            use_path(&["a", "b", "c"], &["d.txt"], ModuleKind::Asset).as_deref(),
+            Some("....d.txt"),
+        );
+        assert_eq!(use_path(&["a"], &["b", "c"], ModuleKind::Code), None);
+        assert_eq!(use_path(&["a"], &["b.c"], ModuleKind::Code), None);
+        assert_eq!(use_path(&["a"], &[], ModuleKind::Code), None);
+    }
+
+    #[test]
+    fn nested_calls_become_one_pipeline() {
+        assert_eq!(
+            to_pipeline("f a b = a\ng a = a\nfoo x = f (g x) 1\n", "g x").as_deref(),
+            Some("f a b = a\ng a = a\nfoo x = x | g | f 1\n"),
+        );
+        // Pipes are left-associative, so nested pipelines don't need parentheses.
+        assert_eq!(
+            to_pipeline("f a b = a\ng a = a\nfoo x = f (x | g) 1\n", "f (").as_deref(),
+            Some("f a b = a\ng a = a\nfoo x = x | g | f 1\n"),
+        );
+    }
+    #[test]
+    fn the_right_side_of_a_pipe_is_not_converted_to_a_pipeline() {
+        assert_eq!(to_pipeline("f a b = a\nfoo x = x | f 1\n", "f 1"), None);
+    }
+    #[test]
+    fn pipelines_become_nested_calls() {
+        assert_eq!(
+            to_call("f a b = a\ng a = a\nfoo x = x | g | f 1\n", "g |").as_deref(),
+            Some("f a b = a\ng a = a\nfoo x = f (g x) 1\n"),
+        );
+    }
+    #[test]
+    fn bars_in_patterns_are_not_converted_to_calls() {
+        assert_eq!(
+            to_call("foo x = x %\n  Foo | Bar -> 1\n  _ -> 2\n", "| Bar"),
+            None,
+        );
+    }
+}
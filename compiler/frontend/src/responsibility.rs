@@ -0,0 +1,233 @@
+//! Who gets blamed when an expression panics.
+//!
+//! Every panic in Candy has a responsible HIR ID. For most expressions that
+//! can panic, that's the expression itself: A call is blamed if the called
+//! function's needs aren't fulfilled, a match is blamed if no case matches,
+//! etc. The interesting cases are `needs` and `use`: If their condition isn't
+//! fulfilled, they blame whoever called the surrounding function. Functions
+//! with curly braces don't have their own responsibility, so the blame is
+//! passed on to the next outer function (or, at the top level, to whoever
+//! uses the module).
+//!
+//! This mirrors the responsibilities passed around in [`crate::hir_to_mir`].
+
+use crate::hir::{self, Expression, HirDb, Id, Pattern};
+use itertools::Itertools;
+
+/// See the [module documentation](self).
+#[allow(clippy::needless_pass_by_value)]
+pub fn responsibility(db: &dyn HirDb, id: Id) -> Option<Responsibility> {
+    let responsibility = match db.find_expression(id.clone())? {
+        Expression::Int(_)
+        | Expression::Text(_)
+        | Expression::Reference(_)
+        | Expression::Symbol(_)
+        | Expression::List(_)
+        | Expression::Struct(_)
+        | Expression::Function(_)
+        | Expression::Builtin(_) => Responsibility::CantPanic,
+        Expression::Destructure {
+            pattern: Pattern::NewIdentifier(_),
+            ..
+        } => Responsibility::CantPanic,
+        Expression::Destructure { .. } => Responsibility::Destructure,
+        // Extracting captured values only happens after the destructuring
+        // checked that the pattern matches.
+        Expression::PatternIdentifierReference(_) => Responsibility::CantPanic,
+        Expression::Match { .. } => Responsibility::Match,
+        Expression::Call { .. } => Responsibility::Call,
+        Expression::UseModule { .. } => Responsibility::UseModule(scope_responsibility(db, &id)),
+        Expression::Needs { .. } => Responsibility::Needs(scope_responsibility(db, &id)),
+        Expression::Error { .. } => Responsibility::Error,
+    };
+    Some(responsibility)
+}
+fn scope_responsibility(db: &dyn HirDb, id: &Id) -> ScopeResponsibility {
+    let mut passed_through = vec![];
+    let mut current = id.parent().unwrap();
+    while !current.is_root() {
+        match db.find_expression(current.clone()).unwrap() {
+            Expression::Function(hir::Function { kind, .. }) => {
+                if kind.uses_own_responsibility() {
+                    return ScopeResponsibility {
+                        blamed_function: Some(current),
+                        passed_through,
+                    };
+                }
+                passed_through.push(current.clone());
+            }
+            // Match cases use the responsibility of the surrounding scope.
+            Expression::Match { .. } => {}
+            _ => panic!("Parent of an expression must be a function, a match, or the root."),
+        }
+        current = current.parent().unwrap();
+    }
+    ScopeResponsibility {
+        blamed_function: None,
+        passed_through,
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Responsibility {
+    CantPanic,
+    /// The call is blamed if the called function's needs aren't fulfilled.
+    Call,
+    /// The destructuring is blamed if the value doesn't match the pattern.
+    Destructure,
+    /// The match is blamed if no case matches.
+    Match,
+    /// An expression containing compiler errors blames itself when it's
+    /// evaluated.
+    Error,
+    /// If the condition is `False`, the scope is blamed. If the condition is
+    /// not a boolean or the reason not a text, the `needs` itself is blamed.
+    Needs(ScopeResponsibility),
+    /// Failing to use a module is the fault of the scope, just like a `needs`.
+    UseModule(ScopeResponsibility),
+}
+impl Responsibility {
+    #[must_use]
+    pub const fn can_panic(&self) -> bool {
+        !matches!(self, Self::CantPanic)
+    }
+
+    /// A human-readable explanation, one sentence per item.
+    #[must_use]
+    pub fn explain(&self) -> Vec<String> {
+        match self {
+            Self::CantPanic => vec!["This expression can't panic.".to_string()],
+            Self::Call => vec![
+                "If the called function panics because one of its `needs` isn't fulfilled, this call is blamed.".to_string(),
+            ],
+            Self::Destructure => vec![
+                "If the value doesn't match the pattern, this destructuring is blamed.".to_string(),
+            ],
+            Self::Match => vec!["If no case matches the value, this match is blamed.".to_string()],
+            Self::Error => vec![
+                "This expression contains errors. Evaluating it panics and blames the expression itself.".to_string(),
+            ],
+            Self::Needs(scope) => vec![
+                format!("If the condition is `False`, {}.", scope.explain()),
+                "If the condition isn't `True` or `False` or the reason isn't a text, this `needs` is blamed.".to_string(),
+            ],
+            Self::UseModule(scope) => {
+                vec![format!("If the module can't be used, {}.", scope.explain())]
+            }
+        }
+    }
+}
+
+/// The scope that's blamed for a failing `needs` or `use`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ScopeResponsibility {
+    /// The innermost surrounding function that has its own responsibility. Its
+    /// callers are blamed. If this is `None`, the expression is (only nested in
+    /// curly-brace functions) at the top level of the module and whoever uses
+    /// the module is blamed.
+    pub blamed_function: Option<Id>,
+
+    /// Functions with curly braces that are nested between the expression and
+    /// the blamed scope, innermost first. They pass on the responsibility of
+    /// their surrounding scope.
+    pub passed_through: Vec<Id>,
+}
+impl ScopeResponsibility {
+    #[must_use]
+    pub fn explain(&self) -> String {
+        let blamed = self.blamed_function.as_ref().map_or_else(
+            || "whoever uses this module is blamed".to_string(),
+            |function| format!("whoever calls `{}` is blamed", function.function_name()),
+        );
+        if self.passed_through.is_empty() {
+            blamed
+        } else {
+            format!(
+                "the responsibility is passed on through {} (which use curly braces) and {blamed}",
+                self.passed_through
+                    .iter()
+                    .map(|it| format!("`{}`", it.function_name()))
+                    .join(", "),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast_to_hir::AstToHir,
+        hir::{Expression, HirDb},
+        test_database::Database,
+    };
+    use itertools::Itertools;
+
+    /// The explanations of all `needs` in the source.
+    fn explain_needs(source: &str) -> Vec<Vec<String>> {
+        let (db, module) = Database::with_main_module(source);
+        let (hir, _) = db.hir(module).unwrap();
+        let mut ids = vec![];
+        for (id, expression) in &hir.expressions {
+            ids.push(id.clone());
+            expression.collect_all_ids(&mut ids);
+        }
+        ids.into_iter()
+            .filter(|id| {
+                matches!(
+                    db.find_expression(id.clone()),
+                    Some(Expression::Needs { .. })
+                )
+            })
+            .map(|id| db.responsibility(id).unwrap().explain())
+            .collect_vec()
+    }
+
+    #[test]
+    fn top_level_needs_blame_the_user_of_the_module() {
+        assert_eq!(
+            explain_needs("needs True\n"),
+            [[
+                "If the condition is `False`, whoever uses this module is blamed.",
+                "If the condition isn't `True` or `False` or the reason isn't a text, this `needs` is blamed.",
+            ]],
+        );
+    }
+
+    #[test]
+    fn needs_in_functions_blame_the_caller() {
+        let explanations = explain_needs("foo a := needs a\n");
+        assert_eq!(
+            explanations[0][0],
+            "If the condition is `False`, whoever calls `foo` is blamed.",
+        );
+    }
+
+    #[test]
+    fn curly_brace_functions_pass_on_the_responsibility() {
+        let explanations = explain_needs("foo a :=\n  bar = { b -> needs b }\n  bar a\n");
+        assert_eq!(
+            explanations[0][0],
+            "If the condition is `False`, the responsibility is passed on through `foo → <anonymous 0>` (which use curly braces) and whoever calls `foo` is blamed.",
+        );
+    }
+
+    #[test]
+    fn only_some_expressions_can_panic() {
+        let (db, module) = Database::with_main_module("foo a := a\nbar = foo 1\n");
+        let (hir, _) = db.hir(module).unwrap();
+        let can_panic = hir
+            .expressions
+            .iter()
+            .map(|(id, expression)| {
+                (
+                    expression.to_string(),
+                    db.responsibility(id.clone()).unwrap().can_panic(),
+                )
+            })
+            .collect_vec();
+        assert!(can_panic.contains(&("1".to_string(), false)));
+        assert!(can_panic
+            .iter()
+            .any(|(expression, can_panic)| expression.starts_with("call") && *can_panic));
+    }
+}
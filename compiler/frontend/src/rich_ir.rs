@@ -7,6 +7,7 @@ use crate::{
     module::Module,
     position::Offset,
     rcst_to_cst::CstResult,
+    responsibility::Responsibility,
     string_to_rcst::{ModuleError, RcstResult},
     TracingConfig, TracingMode,
 };
@@ -369,9 +370,34 @@ impl RichIr {
         Self::for_ir("AST", module, None, |builder| asts.build_rich_ir(builder))
     }
     #[must_use]
+    pub fn for_desugared_ast(module: &Module, asts: &[Ast]) -> Self {
+        Self::for_ir("Desugared AST", module, None, |builder| {
+            asts.build_rich_ir(builder);
+        })
+    }
+    #[must_use]
     pub fn for_hir(module: &Module, body: &hir::Body) -> Self {
         Self::for_ir("HIR", module, None, |builder| body.build_rich_ir(builder))
     }
+    /// Lists the expressions that can panic and explains who is blamed.
+    #[must_use]
+    pub fn for_responsibilities(
+        module: &Module,
+        responsibilities: &[(hir::Id, Responsibility)],
+    ) -> Self {
+        Self::for_ir("Responsibilities", module, None, |builder| {
+            builder.push_custom_multiline(responsibilities, |builder, (id, responsibility)| {
+                id.build_rich_ir(builder);
+                builder.push(":", None, EnumSet::empty());
+                builder.indent();
+                for sentence in responsibility.explain() {
+                    builder.push_newline();
+                    builder.push(sentence, None, EnumSet::empty());
+                }
+                builder.dedent();
+            });
+        })
+    }
     #[must_use]
     pub fn for_mir(module: &Module, mir: &Mir, tracing_config: &TracingConfig) -> Self {
         Self::for_ir("MIR", module, tracing_config, |builder| {
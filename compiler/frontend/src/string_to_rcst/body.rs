@@ -51,6 +51,7 @@ pub fn body(mut input: &str, indentation: usize) -> (&str, Vec<Rcst>) {
                 allow_call: true,
                 allow_bar: true,
                 allow_function: true,
+                stop_at_guard: false,
             },
         );
         if let Some((new_input, expression)) = parsed_expression {
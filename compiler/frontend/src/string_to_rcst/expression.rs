@@ -7,10 +7,11 @@ use super::{
         arrow, bar, closing_bracket, closing_curly_brace, closing_parenthesis, colon_equals_sign,
         dot, equals_sign, percent,
     },
+    nesting::{too_deeply_nested, NestingGuard},
     struct_::struct_,
     text::text,
     whitespace::{comment, single_line_whitespace, whitespaces_and_newlines},
-    word::{identifier, symbol, word},
+    word::{identifier, if_keyword, symbol, word},
 };
 use crate::{
     cst::{CstError, CstKind, IsMultiline},
@@ -25,6 +26,9 @@ pub struct ExpressionParsingOptions {
     pub allow_call: bool,
     pub allow_bar: bool,
     pub allow_function: bool,
+    /// In match case patterns, `if` starts the guard, so it can't be an
+    /// identifier (e.g., an argument of a call).
+    pub stop_at_guard: bool,
 }
 
 #[instrument(level = "trace")]
@@ -33,6 +37,10 @@ pub fn expression(
     indentation: usize,
     options: ExpressionParsingOptions,
 ) -> Option<(&str, Rcst)> {
+    let Some(_nesting_guard) = NestingGuard::enter() else {
+        return too_deeply_nested(input);
+    };
+
     // If we start the call list with `if … else …`, the formatting looks weird.
     // Hence, we start with a single `None`.
     let (mut input, mut result) = None
@@ -48,8 +56,16 @@ pub fn expression(
                 None
             }
         })
-        .or_else(|| identifier(input))
         .or_else(|| {
+            if options.stop_at_guard && if_keyword(input).is_some() {
+                return None;
+            }
+            identifier(input)
+        })
+        .or_else(|| {
+            if options.stop_at_guard && if_keyword(input).is_some() {
+                return None;
+            }
             word(input).map(|(input, word)| {
                 (
                     input,
@@ -67,7 +83,7 @@ pub fn expression(
             input: &mut &'input str,
             indentation: usize,
             result: &mut Rcst,
-            parser: fn(&'input str, &Rcst, usize) -> Option<(&'input str, Rcst)>,
+            parser: impl FnOnce(&'input str, &Rcst, usize) -> Option<(&'input str, Rcst)>,
         ) -> bool {
             if let Some((new_input, expression)) = parser(input, result, indentation) {
                 *input = new_input;
@@ -88,12 +104,24 @@ pub fn expression(
         );
 
         if options.allow_call {
-            did_make_progress |=
-                parse_suffix(&mut input, indentation, &mut result, expression_suffix_call);
+            did_make_progress |= parse_suffix(
+                &mut input,
+                indentation,
+                &mut result,
+                |input, current, indentation| {
+                    expression_suffix_call(input, current, indentation, options.stop_at_guard)
+                },
+            );
         }
         if options.allow_bar {
-            did_make_progress |=
-                parse_suffix(&mut input, indentation, &mut result, expression_suffix_bar);
+            did_make_progress |= parse_suffix(
+                &mut input,
+                indentation,
+                &mut result,
+                |input, current, indentation| {
+                    expression_suffix_bar(input, current, indentation, options.stop_at_guard)
+                },
+            );
             did_make_progress |= parse_suffix(
                 &mut input,
                 indentation,
@@ -148,6 +176,7 @@ fn expression_suffix_call<'a>(
     mut input: &'a str,
     current: &Rcst,
     indentation: usize,
+    stop_at_guard: bool,
 ) -> Option<(&'a str, Rcst)> {
     let mut expressions = vec![current.clone()];
 
@@ -171,6 +200,7 @@ fn expression_suffix_call<'a>(
                 allow_call: has_multiline_whitespace,
                 allow_bar: has_multiline_whitespace,
                 allow_function: true,
+                stop_at_guard,
             },
         );
         let (i, expr) = if let Some(it) = parsed_expression {
@@ -215,6 +245,7 @@ fn expression_suffix_bar<'a>(
     input: &'a str,
     current: &Rcst,
     indentation: usize,
+    stop_at_guard: bool,
 ) -> Option<(&'a str, Rcst)> {
     let (input, whitespace_after_receiver) = whitespaces_and_newlines(input, indentation, true);
 
@@ -235,6 +266,7 @@ fn expression_suffix_bar<'a>(
             allow_call: true,
             allow_bar: false,
             allow_function: true,
+            stop_at_guard,
         },
     )
     .unwrap_or_else(|| {
@@ -352,6 +384,7 @@ fn expression_suffix_assignment<'a>(
                 allow_call: true,
                 allow_bar: true,
                 allow_function: true,
+                stop_at_guard: false,
             },
         ) {
             input = new_input;
@@ -400,11 +433,42 @@ fn match_case(input: &str, indentation: usize) -> Option<(&str, Rcst)> {
             allow_call: true,
             allow_bar: true,
             allow_function: true,
+            stop_at_guard: true,
         },
     )?;
     let (input, whitespace) = whitespaces_and_newlines(input, indentation, true);
     let pattern = pattern.wrap_in_whitespace(whitespace);
 
+    let (input, guard) = if let Some((input, keyword)) = if_keyword(input) {
+        let (input, whitespace) = whitespaces_and_newlines(input, indentation, true);
+        let keyword = keyword.wrap_in_whitespace(whitespace);
+
+        let (input, condition) = expression(
+            input,
+            indentation,
+            ExpressionParsingOptions {
+                allow_assignment: false,
+                allow_call: true,
+                allow_bar: true,
+                allow_function: true,
+                stop_at_guard: false,
+            },
+        )
+        .unwrap_or_else(|| {
+            let error = CstKind::Error {
+                unparsable_input: String::new(),
+                error: CstError::MatchCaseGuardMissesCondition,
+            };
+            (input, error.into())
+        });
+        let (input, whitespace) = whitespaces_and_newlines(input, indentation, true);
+        let condition = condition.wrap_in_whitespace(whitespace);
+
+        (input, Some(Box::new((keyword, condition))))
+    } else {
+        (input, None)
+    };
+
     let (input, arrow) = if let Some((input, arrow)) = arrow(input) {
         let (input, whitespace) = whitespaces_and_newlines(input, indentation, true);
         (input, arrow.wrap_in_whitespace(whitespace))
@@ -429,6 +493,7 @@ fn match_case(input: &str, indentation: usize) -> Option<(&str, Rcst)> {
 
     let case = CstKind::MatchCase {
         pattern: Box::new(pattern),
+        guard,
         arrow: Box::new(arrow),
         body,
     };
@@ -452,7 +517,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some(("", build_identifier("foo")))
@@ -465,7 +531,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: false,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -494,7 +561,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -520,7 +588,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some(("\n.bar", build_identifier("foo"))),
@@ -535,7 +604,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -561,7 +631,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -593,7 +664,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -606,6 +678,7 @@ mod test {
                     ])),
                     cases: vec![CstKind::MatchCase {
                         pattern: Box::new(build_simple_int(123).with_trailing_space()),
+                        guard: None,
                         arrow: Box::new(CstKind::Arrow.with_trailing_space()),
                         body: vec![build_simple_int(123)],
                     }
@@ -622,7 +695,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -679,7 +753,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -699,7 +774,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -726,7 +802,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -759,7 +836,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -792,7 +870,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -820,7 +899,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -859,7 +939,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -879,7 +960,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -900,7 +982,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -924,7 +1007,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -944,7 +1028,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: false,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -969,7 +1054,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: false,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -997,7 +1083,8 @@ mod test {
                     allow_assignment: false,
                     allow_call: false,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1010,6 +1097,7 @@ mod test {
                     ])),
                     cases: vec![CstKind::MatchCase {
                         pattern: Box::new(build_simple_int(1).with_trailing_space()),
+                        guard: None,
                         arrow: Box::new(CstKind::Arrow.with_trailing_space()),
                         body: vec![build_simple_int(2)],
                     }
@@ -1018,6 +1106,48 @@ mod test {
                 .into(),
             )),
         );
+        // foo %
+        //   Foo x if x -> x
+        assert_eq!(
+            expression(
+                "foo %\n  Foo x if x -> x",
+                0,
+                ExpressionParsingOptions {
+                    allow_assignment: false,
+                    allow_call: false,
+                    allow_bar: true,
+                    allow_function: true,
+                    stop_at_guard: false
+                }
+            ),
+            Some((
+                "",
+                CstKind::Match {
+                    expression: Box::new(build_identifier("foo").with_trailing_space()),
+                    percent: Box::new(CstKind::Percent.with_trailing_whitespace(vec![
+                        CstKind::Newline("\n".to_string()),
+                        CstKind::Whitespace("  ".to_string()),
+                    ])),
+                    cases: vec![CstKind::MatchCase {
+                        pattern: Box::new(
+                            CstKind::Call {
+                                receiver: Box::new(build_symbol("Foo").with_trailing_space()),
+                                arguments: vec![build_identifier("x")],
+                            }
+                            .with_trailing_space(),
+                        ),
+                        guard: Some(Box::new((
+                            CstKind::If.with_trailing_space(),
+                            build_identifier("x").with_trailing_space(),
+                        ))),
+                        arrow: Box::new(CstKind::Arrow.with_trailing_space()),
+                        body: vec![build_identifier("x")],
+                    }
+                    .into()],
+                }
+                .into(),
+            )),
+        );
         // foo bar =
         //   3
         // 2
@@ -1029,7 +1159,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1062,7 +1193,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1100,7 +1232,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1135,7 +1268,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1156,7 +1290,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1177,7 +1312,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1205,7 +1341,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1233,7 +1370,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1262,7 +1400,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1317,7 +1456,8 @@ mod test {
                     allow_assignment: true,
                     allow_call: true,
                     allow_bar: true,
-                    allow_function: true
+                    allow_function: true,
+                    stop_at_guard: false
                 }
             ),
             Some((
@@ -1346,4 +1486,120 @@ mod test {
             )),
         );
     }
+
+    #[test]
+    fn test_match_case_guard() {
+        // Foo bar if baz bar -> bar
+        assert_eq!(
+            match_case("Foo bar if baz bar -> bar", 0),
+            Some((
+                "",
+                CstKind::MatchCase {
+                    pattern: Box::new(
+                        CstKind::Call {
+                            receiver: Box::new(build_symbol("Foo").with_trailing_space()),
+                            arguments: vec![build_identifier("bar")],
+                        }
+                        .with_trailing_space(),
+                    ),
+                    guard: Some(Box::new((
+                        CstKind::If.with_trailing_space(),
+                        CstKind::Call {
+                            receiver: Box::new(build_identifier("baz").with_trailing_space()),
+                            arguments: vec![build_identifier("bar")],
+                        }
+                        .with_trailing_space(),
+                    ))),
+                    arrow: Box::new(CstKind::Arrow.with_trailing_space()),
+                    body: vec![build_identifier("bar")],
+                }
+                .into(),
+            )),
+        );
+        // _ -> foo if
+        // In the body, `if` is a normal identifier again.
+        assert_eq!(
+            match_case("_ -> foo if", 0),
+            Some((
+                "",
+                CstKind::MatchCase {
+                    pattern: Box::new(build_identifier("_").with_trailing_space()),
+                    guard: None,
+                    arrow: Box::new(CstKind::Arrow.with_trailing_space()),
+                    body: vec![CstKind::Call {
+                        receiver: Box::new(build_identifier("foo").with_trailing_space()),
+                        arguments: vec![build_identifier("if")],
+                    }
+                    .into()],
+                }
+                .into(),
+            )),
+        );
+        // foo if -> foo
+        assert_eq!(
+            match_case("foo if -> foo", 0),
+            Some((
+                "",
+                CstKind::MatchCase {
+                    pattern: Box::new(build_identifier("foo").with_trailing_space()),
+                    guard: Some(Box::new((
+                        CstKind::If.with_trailing_space(),
+                        CstKind::Error {
+                            unparsable_input: String::new(),
+                            error: CstError::MatchCaseGuardMissesCondition,
+                        }
+                        .into(),
+                    ))),
+                    arrow: Box::new(CstKind::Arrow.with_trailing_space()),
+                    body: vec![build_identifier("foo")],
+                }
+                .into(),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_trailing_function() {
+        fn assert_is_call_with_trailing_function(source: &str, remaining: &str) {
+            let (actual_remaining, call) = expression(
+                source,
+                0,
+                ExpressionParsingOptions {
+                    allow_assignment: true,
+                    allow_call: true,
+                    allow_bar: true,
+                    allow_function: true,
+                    stop_at_guard: false,
+                },
+            )
+            .unwrap();
+            assert_eq!(actual_remaining, remaining);
+            let CstKind::Call { arguments, .. } = &call.kind else {
+                panic!("Expected a call, got `{}`.", call.kind);
+            };
+            assert_eq!(arguments.len(), 2);
+            assert!(
+                matches!(arguments[1].kind, CstKind::Function { .. }),
+                "Got `{}`.",
+                call.kind,
+            );
+        }
+
+        // foo bar { baz ->
+        //   baz
+        // }
+        // qux
+        assert_is_call_with_trailing_function("foo bar { baz ->\n  baz\n}\nqux", "\nqux");
+        // foo
+        //   bar
+        //   { baz ->
+        //     baz
+        //   }
+        // qux
+        assert_is_call_with_trailing_function("foo\n  bar\n  { baz ->\n    baz\n  }\nqux", "\nqux");
+        // foo bar
+        //   { baz -> baz }
+        // qux
+        assert_is_call_with_trailing_function("foo bar\n  { baz -> baz }\nqux", "\nqux");
+    }
 }
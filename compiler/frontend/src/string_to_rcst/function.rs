@@ -37,6 +37,7 @@ pub fn function(input: &str, indentation: usize) -> Option<(&str, Rcst)> {
                     allow_call: false,
                     allow_bar: false,
                     allow_function: false,
+                    stop_at_guard: false,
                 },
             ) {
                 Some((i, parameter)) => {
@@ -73,6 +74,7 @@ pub fn function(input: &str, indentation: usize) -> Option<(&str, Rcst)> {
                 allow_call: true,
                 allow_bar: true,
                 allow_function: true,
+                stop_at_guard: false,
             },
         ) {
             Some((input, expression)) => {
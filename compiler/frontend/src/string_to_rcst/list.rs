@@ -39,6 +39,7 @@ pub fn list(input: &str, indentation: usize) -> Option<(&str, Rcst)> {
                 allow_call: true,
                 allow_bar: true,
                 allow_function: true,
+                stop_at_guard: false,
             },
         )
         .map_or((input, None), |(input, expression)| {
@@ -12,6 +12,7 @@ mod function;
 mod int;
 mod list;
 mod literal;
+mod nesting;
 mod struct_;
 mod text;
 mod utils;
@@ -25,6 +26,7 @@ use crate::{
     rich_ir::{RichIrBuilder, ToRichIr, TokenType},
 };
 use enumset::EnumSet;
+pub use nesting::DEFAULT_MAX_NESTING_DEPTH;
 use std::{str, sync::Arc};
 
 #[salsa::query_group(StringToRcstStorage)]
@@ -55,7 +57,14 @@ fn rcst(db: &dyn StringToRcst, module: Module) -> RcstResult {
 }
 #[must_use]
 pub fn parse_rcst(source: &str) -> Vec<Rcst> {
-    let (mut rest, mut rcsts) = body::body(source, 0);
+    parse_rcst_with_max_nesting_depth(source, DEFAULT_MAX_NESTING_DEPTH)
+}
+/// Parses the source, reporting expressions nested deeper than
+/// `max_nesting_depth` as errors instead of parsing them.
+#[must_use]
+pub fn parse_rcst_with_max_nesting_depth(source: &str, max_nesting_depth: usize) -> Vec<Rcst> {
+    let (mut rest, mut rcsts) =
+        nesting::with_max_nesting_depth(max_nesting_depth, || body::body(source, 0));
     if !rest.is_empty() {
         let trailing_newline = if rest.ends_with("\r\n") {
             let (_, newline) = literal::newline(&rest[rest.len() - 2..]).unwrap();
@@ -0,0 +1,233 @@
+//! Pathological inputs like thousands of opening parentheses would overflow
+//! the stack of our recursive-descent parser as well as of later passes that
+//! recursively walk the resulting tree. Hence, we limit how deeply expressions
+//! may be nested and report deeper code as [`CstError::NestingTooDeep`].
+
+use super::{
+    literal::{
+        closing_bracket, closing_curly_brace, closing_parenthesis, double_quote, newline,
+        opening_bracket, opening_curly_brace, opening_parenthesis, single_quote,
+    },
+    utils::parse_multiple,
+    whitespace::comment,
+};
+use crate::{
+    cst::{CstError, CstKind},
+    rcst::Rcst,
+};
+use std::cell::Cell;
+
+/// Candy code written by humans rarely nests more than a few dozen levels.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+
+thread_local! {
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_NESTING_DEPTH) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Runs the parser with a different maximum nesting depth.
+pub fn with_max_nesting_depth<T>(max_depth: usize, parse: impl FnOnce() -> T) -> T {
+    let previous_max_depth = MAX_DEPTH.with(|it| it.replace(max_depth));
+    let result = parse();
+    MAX_DEPTH.with(|it| it.set(previous_max_depth));
+    result
+}
+
+/// Marks that the parser is one level deeper until it's dropped.
+pub struct NestingGuard(());
+impl NestingGuard {
+    /// Returns `None` if the parser is already at the maximum depth.
+    pub fn enter() -> Option<Self> {
+        DEPTH.with(|depth| {
+            if depth.get() >= MAX_DEPTH.with(Cell::get) {
+                return None;
+            }
+            depth.set(depth.get() + 1);
+            Some(Self(()))
+        })
+    }
+}
+impl Drop for NestingGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// What [`too_deeply_nested`] is currently inside of.
+enum Nesting {
+    Brackets,
+    Text { single_quotes: usize },
+    TextInterpolation { curly_braces: usize },
+}
+
+/// Consumes the expression that is nested too deeply without parsing it into a
+/// tree.
+///
+/// Instead, we split it into the tokens that affect the nesting. Brackets are
+/// balanced so that the closing brackets of the surrounding expressions remain
+/// for them to parse. Brackets in comments and texts (outside of
+/// interpolations) don't count.
+pub fn too_deeply_nested(input: &str) -> Option<(&str, Rcst)> {
+    let mut nestings = vec![];
+    let mut rest = input;
+    loop {
+        if let Some(Nesting::Text { single_quotes }) = nestings.last() {
+            let single_quotes = *single_quotes;
+            if let Some((new_rest, _)) = double_quote(rest)
+                && let Some((new_rest, _)) =
+                    parse_multiple(new_rest, single_quote, Some((single_quotes, false)))
+            {
+                rest = new_rest;
+                nestings.pop();
+            } else if let Some((new_rest, _)) =
+                parse_multiple(rest, opening_curly_brace, Some((single_quotes + 1, true)))
+            {
+                rest = new_rest;
+                nestings.push(Nesting::TextInterpolation {
+                    curly_braces: single_quotes + 1,
+                });
+            } else if let Some(c) = rest.chars().next() {
+                rest = &rest[c.len_utf8()..];
+            } else {
+                break;
+            }
+            continue;
+        }
+
+        if let Some(Nesting::TextInterpolation { curly_braces }) = nestings.last()
+            && let Some((new_rest, _)) =
+                parse_multiple(rest, closing_curly_brace, Some((*curly_braces, false)))
+        {
+            rest = new_rest;
+            nestings.pop();
+        } else if let Some((new_rest, _)) = comment(rest) {
+            rest = new_rest;
+        } else if let Some((new_rest, single_quotes)) = parse_multiple(rest, single_quote, None)
+            && let Some((new_rest, _)) = double_quote(new_rest)
+        {
+            rest = new_rest;
+            nestings.push(Nesting::Text {
+                single_quotes: single_quotes.len(),
+            });
+        } else if let Some((new_rest, _)) = opening_parenthesis(rest)
+            .or_else(|| opening_bracket(rest))
+            .or_else(|| opening_curly_brace(rest))
+        {
+            rest = new_rest;
+            nestings.push(Nesting::Brackets);
+        } else if let Some((new_rest, _)) = closing_parenthesis(rest)
+            .or_else(|| closing_bracket(rest))
+            .or_else(|| closing_curly_brace(rest))
+        {
+            match nestings.last() {
+                None => break,
+                Some(Nesting::Brackets) => {
+                    nestings.pop();
+                }
+                Some(_) => {}
+            }
+            rest = new_rest;
+        } else if nestings.is_empty() && (newline(rest).is_some() || rest.starts_with('\r')) {
+            break;
+        } else if let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+        } else {
+            break;
+        }
+    }
+
+    let end = input.len() - rest.len();
+    if end == 0 {
+        return None;
+    }
+
+    let (unparsable_input, input) = input.split_at(end);
+    Some((
+        input,
+        CstKind::Error {
+            unparsable_input: unparsable_input.to_string(),
+            error: CstError::NestingTooDeep,
+        }
+        .into(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::string_to_rcst::parse_rcst_with_max_nesting_depth;
+
+    #[test]
+    fn test_too_deeply_nested() {
+        assert_eq!(
+            too_deeply_nested("((foo)) bar)"),
+            Some((
+                ")",
+                CstKind::Error {
+                    unparsable_input: "((foo)) bar".to_string(),
+                    error: CstError::NestingTooDeep,
+                }
+                .into(),
+            )),
+        );
+        assert_eq!(
+            too_deeply_nested("[Foo: (\n  bar)]\nbaz"),
+            Some((
+                "\nbaz",
+                CstKind::Error {
+                    unparsable_input: "[Foo: (\n  bar)]".to_string(),
+                    error: CstError::NestingTooDeep,
+                }
+                .into(),
+            )),
+        );
+        assert_eq!(too_deeply_nested(")"), None);
+    }
+    #[test]
+    fn brackets_in_comments_and_texts_dont_count() {
+        assert_eq!(
+            too_deeply_nested("(foo # )\n  \")\") bar)"),
+            Some((
+                ")",
+                CstKind::Error {
+                    unparsable_input: "(foo # )\n  \")\") bar".to_string(),
+                    error: CstError::NestingTooDeep,
+                }
+                .into(),
+            )),
+        );
+        assert_eq!(
+            too_deeply_nested("'\"{\"'\"}\"\n"),
+            Some((
+                "\n",
+                CstKind::Error {
+                    unparsable_input: "'\"{\"'\"}\"".to_string(),
+                    error: CstError::NestingTooDeep,
+                }
+                .into(),
+            )),
+        );
+    }
+    #[test]
+    fn brackets_in_text_interpolations_count() {
+        assert_eq!(
+            too_deeply_nested("\"{ foo (bar) }\" baz) qux"),
+            Some((
+                ") qux",
+                CstKind::Error {
+                    unparsable_input: "\"{ foo (bar) }\" baz".to_string(),
+                    error: CstError::NestingTooDeep,
+                }
+                .into(),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_parentheses() {
+        let depth = 10_000;
+        let source = format!("foo = {}bar{}", "(".repeat(depth), ")".repeat(depth));
+        let rcsts = parse_rcst_with_max_nesting_depth(&source, 16);
+        assert!(format!("{rcsts:?}").contains("NestingTooDeep"));
+    }
+}
@@ -40,6 +40,7 @@ pub fn struct_(input: &str, indentation: usize, allow_function: bool) -> Option<
                 allow_call: true,
                 allow_bar: true,
                 allow_function,
+                stop_at_guard: false,
             },
         ) {
             Some((input, key)) => (input, Some(key)),
@@ -85,6 +86,7 @@ pub fn struct_(input: &str, indentation: usize, allow_function: bool) -> Option<
                 allow_call: true,
                 allow_bar: true,
                 allow_function,
+                stop_at_guard: false,
             },
         ) {
             Some((input, value)) => (input, value, true),
@@ -161,6 +161,7 @@ fn text_interpolation(
             allow_call: true,
             allow_bar: true,
             allow_function: true,
+            stop_at_guard: false,
         },
     )
     .unwrap_or((
@@ -176,14 +177,21 @@ fn text_interpolation(
     expression = expression.wrap_in_whitespace(whitespace);
 
     let (input, closing_curly_braces) =
-        parse_multiple(input, closing_curly_brace, Some((curly_brace_count, false))).unwrap_or((
-            input,
-            vec![CstKind::Error {
-                unparsable_input: String::new(),
-                error: CstError::TextInterpolationNotClosed,
-            }
-            .into()],
-        ));
+        parse_multiple(input, closing_curly_brace, Some((curly_brace_count, false)))
+            .unwrap_or_else(|| {
+                // Keep the closing curly braces that are there so that we know
+                // how many are missing.
+                let (input, mut closing_curly_braces) =
+                    parse_multiple(input, closing_curly_brace, None).unwrap();
+                closing_curly_braces.push(
+                    CstKind::Error {
+                        unparsable_input: String::new(),
+                        error: CstError::TextInterpolationNotClosed,
+                    }
+                    .into(),
+                );
+                (input, closing_curly_braces)
+            });
 
     Some((
         input,
@@ -394,6 +402,35 @@ mod test {
                 build_text(2, vec![CstKind::TextPart("foo\"'bar".to_string()).into()])
             )),
         );
+        assert_eq!(
+            text("'\"foo {{bar} baz\"'", 0),
+            Some((
+                "",
+                build_text(
+                    1,
+                    vec![
+                        CstKind::TextPart("foo ".to_string()).into(),
+                        CstKind::TextInterpolation {
+                            opening_curly_braces: vec![
+                                CstKind::OpeningCurlyBrace.into(),
+                                CstKind::OpeningCurlyBrace.into(),
+                            ],
+                            expression: Box::new(build_identifier("bar")),
+                            closing_curly_braces: vec![
+                                CstKind::ClosingCurlyBrace.into(),
+                                CstKind::Error {
+                                    unparsable_input: String::new(),
+                                    error: CstError::TextInterpolationNotClosed,
+                                }
+                                .into(),
+                            ],
+                        }
+                        .into(),
+                        CstKind::TextPart(" baz".to_string()).into(),
+                    ]
+                )
+            )),
+        );
         assert_eq!(
             text("\"foo {\"bar\"} baz\"", 0),
             Some((
@@ -71,6 +71,17 @@ pub fn symbol(input: &str) -> Option<(&str, Rcst)> {
     }
 }
 
+/// The keyword that starts the guard of a match case. Everywhere else, `if` is
+/// a regular identifier because Core exports a function named `if`.
+#[instrument(level = "trace")]
+pub fn if_keyword(input: &str) -> Option<(&str, Rcst)> {
+    let (input, w) = word(input)?;
+    if w != "if" {
+        return None;
+    }
+    Some((input, CstKind::If.into()))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,68 @@
+use crate::{
+    ast::AstDbStorage,
+    ast_to_hir::AstToHirStorage,
+    cst::CstDbStorage,
+    cst_to_ast::CstToAstStorage,
+    hir::HirDbStorage,
+    hir_to_mir::HirToMirStorage,
+    lir_optimize::OptimizeLirStorage,
+    mir_optimize::OptimizeMirStorage,
+    mir_to_lir::MirToLirStorage,
+    module::{
+        GetModuleContentQuery, InMemoryModuleProvider, Module, ModuleDbStorage, ModuleKind,
+        ModuleProvider, ModuleProviderOwner, MutableModuleProviderOwner, Package,
+    },
+    position::PositionConversionStorage,
+    rcst_to_cst::RcstToCstStorage,
+    string_to_rcst::StringToRcstStorage,
+};
+
+/// A database for tests that analyze or compile modules from memory.
+#[salsa::database(
+    AstDbStorage,
+    AstToHirStorage,
+    CstDbStorage,
+    CstToAstStorage,
+    HirDbStorage,
+    HirToMirStorage,
+    MirToLirStorage,
+    ModuleDbStorage,
+    OptimizeLirStorage,
+    OptimizeMirStorage,
+    PositionConversionStorage,
+    RcstToCstStorage,
+    StringToRcstStorage
+)]
+#[derive(Default)]
+pub struct Database {
+    storage: salsa::Storage<Self>,
+    module_provider: InMemoryModuleProvider,
+}
+impl Database {
+    /// A database with an open module `main` of a user package.
+    #[must_use]
+    pub fn with_main_module(source: &str) -> (Self, Module) {
+        let mut db = Self::default();
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+        (db, module)
+    }
+}
+impl salsa::Database for Database {}
+impl ModuleProviderOwner for Database {
+    fn get_module_provider(&self) -> &dyn ModuleProvider {
+        &self.module_provider
+    }
+}
+impl MutableModuleProviderOwner for Database {
+    fn get_in_memory_module_provider(&mut self) -> &mut InMemoryModuleProvider {
+        &mut self.module_provider
+    }
+    fn invalidate_module(&mut self, module: &Module) {
+        GetModuleContentQuery.in_db_mut(self).invalidate(module);
+    }
+}
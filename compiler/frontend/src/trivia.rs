@@ -0,0 +1,274 @@
+//! Whitespace and comments around AST nodes.
+//!
+//! The AST doesn't contain trivia, but refactorings that rewrite AST nodes
+//! mustn't lose the user's comments or formatting. The CST is lossless, so we
+//! recover an AST node's trivia through the AST-to-CST mapping.
+
+use crate::{
+    ast,
+    cst::{Cst, CstKind},
+    cst_to_ast::CstToAst,
+    position::Offset,
+};
+use itertools::Itertools;
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Trivia {
+    Whitespace(String),
+    Newline(String),
+    /// The comment's content without the leading `#`.
+    Comment(String),
+}
+impl Trivia {
+    fn from_cst(cst: &Cst) -> Option<Self> {
+        match &cst.kind {
+            CstKind::Whitespace(whitespace) => Some(Self::Whitespace(whitespace.clone())),
+            CstKind::Newline(newline) => Some(Self::Newline(newline.clone())),
+            CstKind::Comment { comment, .. } => Some(Self::Comment(comment.clone())),
+            _ => None,
+        }
+    }
+}
+impl Display for Trivia {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Whitespace(whitespace) => write!(f, "{whitespace}"),
+            Self::Newline(newline) => write!(f, "{newline}"),
+            Self::Comment(comment) => write!(f, "#{comment}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AstTrivia {
+    /// The span of the node itself, excluding any trailing trivia.
+    pub span: Range<Offset>,
+
+    /// Comments inside the node, e.g., between the arguments of a call.
+    pub inner_comments: Vec<String>,
+
+    /// Whitespace and comments between the node and the next token.
+    ///
+    /// There's no leading trivia: Trivia before a node is the trailing trivia
+    /// of the preceding token.
+    pub trailing: Vec<Trivia>,
+}
+
+#[must_use]
+pub fn ast_trivia(db: &dyn CstToAst, id: &ast::Id) -> Option<AstTrivia> {
+    let cst_id = db.ast_to_cst_id(id)?;
+    let csts = db.cst(id.module.clone()).ok()?;
+    let cst = db.find_cst(id.module.clone(), cst_id);
+
+    let mut tokens = vec![];
+    for cst in csts.iter() {
+        collect_tokens(cst, &mut tokens);
+    }
+
+    let span = cst.data.span.start..end_without_trailing_trivia(&cst);
+    let inner_comments = tokens
+        .iter()
+        .filter(|it| span.start <= it.data.span.start && it.data.span.end <= span.end)
+        .filter_map(|it| match &it.kind {
+            CstKind::Comment { comment, .. } => Some(comment.clone()),
+            _ => None,
+        })
+        .collect();
+    let trailing = tokens
+        .iter()
+        .skip_while(|it| it.data.span.start < span.end)
+        .map_while(|it| Trivia::from_cst(it))
+        .collect();
+    Some(AstTrivia {
+        span,
+        inner_comments,
+        trailing,
+    })
+}
+
+/// Collects the leaves of the CST, treating comments as leaves.
+fn collect_tokens<'a>(cst: &'a Cst, tokens: &mut Vec<&'a Cst>) {
+    let children = cst.kind.children();
+    if children.is_empty() || cst.kind.is_comment() {
+        tokens.push(cst);
+        return;
+    }
+    for child in children {
+        collect_tokens(child, tokens);
+    }
+}
+fn end_without_trailing_trivia(cst: &Cst) -> Offset {
+    cst.kind
+        .children()
+        .into_iter()
+        .rev()
+        .find(|it| !it.kind.is_whitespace_or_comment())
+        .map_or(cst.data.span.end, end_without_trailing_trivia)
+}
+
+/// A replacement of an AST node's source code.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AstReplacement {
+    pub range: Range<Offset>,
+    pub new_text: String,
+}
+
+/// Replaces the source code of the node with `new_text`.
+///
+/// Only the node itself is replaced, so whitespace and comments around it stay
+/// untouched. Comments inside the node that don't occur in `new_text` are
+/// moved to separate lines before the replacement.
+///
+/// The returned range refers to the module's current source code.
+#[must_use]
+pub fn replace_ast(db: &dyn CstToAst, id: &ast::Id, new_text: &str) -> Option<AstReplacement> {
+    replace_ast_keeping_comments(db, id, new_text, &[])
+}
+/// Like [`replace_ast`], but also keeps the `comments` (without the leading
+/// `#`) of code that the refactoring removes elsewhere.
+#[must_use]
+pub fn replace_ast_keeping_comments(
+    db: &dyn CstToAst,
+    id: &ast::Id,
+    new_text: &str,
+    comments: &[String],
+) -> Option<AstReplacement> {
+    let trivia = ast_trivia(db, id)?;
+    let lost_comments = comments
+        .iter()
+        .chain(&trivia.inner_comments)
+        .filter(|comment| !new_text.contains(comment.as_str()))
+        .unique()
+        .collect_vec();
+    if lost_comments.is_empty() {
+        return Some(AstReplacement {
+            range: trivia.span,
+            new_text: new_text.to_string(),
+        });
+    }
+
+    let source = db.get_module_content_as_string(id.module.clone())?;
+    let line_start = source[..*trivia.span.start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let line_before_node = &source[line_start..*trivia.span.start];
+    let mut indentation = line_before_node
+        .chars()
+        .take_while(|it| *it == ' ')
+        .collect::<String>();
+    if indentation.len() < line_before_node.len() {
+        // The node doesn't start its line, e.g., it's the body of an
+        // assignment or an argument of a call. Both can continue on an
+        // indented line.
+        indentation.push_str("  ");
+    }
+
+    let mut replacement = String::new();
+    for comment in lost_comments {
+        replacement.push('#');
+        replacement.push_str(comment);
+        replacement.push('\n');
+        replacement.push_str(&indentation);
+    }
+    replacement.push_str(new_text);
+    Some(AstReplacement {
+        range: trivia.span,
+        new_text: replacement,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ast_trivia, replace_ast, replace_ast_keeping_comments, AstReplacement, Trivia};
+    use crate::{
+        ast, cst_to_ast::CstToAst, module::Module, position::Offset, rcst_to_cst::RcstToCst,
+        test_database::Database,
+    };
+    use itertools::Itertools;
+
+    /// The outermost AST node that starts at the offset of `needle`.
+    fn ast_id_at(db: &Database, module: &Module, source: &str, needle: &str) -> ast::Id {
+        let offset = Offset(source.find(needle).unwrap());
+        let csts = db.cst(module.clone()).unwrap();
+        let mut to_visit = csts.iter().collect_vec();
+        while let Some(cst) = to_visit.pop() {
+            if cst.data.span.start == offset
+                && let Some(id) = db.cst_to_ast_ids(module.clone(), cst.data.id).first()
+            {
+                return id.clone();
+            }
+            to_visit.extend(cst.kind.children());
+        }
+        panic!("There's no AST node at `{needle}`.");
+    }
+
+    #[test]
+    fn trivia_of_a_reference() {
+        let source = "x = 1\nfoo = x # Explains x.\n";
+        let (db, module) = Database::with_main_module(source);
+        let id = ast_id_at(&db, &module, source, "x #");
+        let trivia = ast_trivia(&db, &id).unwrap();
+        assert_eq!(trivia.span, Offset(12)..Offset(13));
+        assert!(trivia.inner_comments.is_empty());
+        assert_eq!(
+            trivia.trailing,
+            [
+                Trivia::Whitespace(" ".to_string()),
+                Trivia::Comment(" Explains x.".to_string()),
+                Trivia::Newline("\n".to_string()),
+            ],
+        );
+    }
+    #[test]
+    fn trivia_of_a_call_with_comments() {
+        let source = "foo =\n  bar # First.\n    baz\n";
+        let (db, module) = Database::with_main_module(source);
+        let id = ast_id_at(&db, &module, source, "bar");
+        let trivia = ast_trivia(&db, &id).unwrap();
+        assert_eq!(
+            &source[*trivia.span.start..*trivia.span.end],
+            "bar # First.\n    baz"
+        );
+        assert_eq!(trivia.inner_comments, [" First."]);
+    }
+
+    #[test]
+    fn replacing_keeps_surrounding_trivia() {
+        let source = "x = 1\nfoo = x # Explains x.\n";
+        let (db, module) = Database::with_main_module(source);
+        let id = ast_id_at(&db, &module, source, "x #");
+        assert_eq!(
+            replace_ast(&db, &id, "2"),
+            Some(AstReplacement {
+                range: Offset(12)..Offset(13),
+                new_text: "2".to_string(),
+            }),
+        );
+    }
+    #[test]
+    fn replacing_moves_lost_comments_before_the_node() {
+        let source = "foo =\n  bar # First.\n    baz\n";
+        let (db, module) = Database::with_main_module(source);
+        let id = ast_id_at(&db, &module, source, "bar");
+        let replacement = replace_ast(&db, &id, "qux").unwrap();
+        assert_eq!(replacement.new_text, "# First.\n  qux");
+
+        // Comments that are still part of the new code aren't duplicated.
+        let replacement = replace_ast(&db, &id, "qux # First.\n    baz").unwrap();
+        assert_eq!(replacement.new_text, "qux # First.\n    baz");
+    }
+    #[test]
+    fn replacing_in_the_middle_of_a_line_continues_on_an_indented_line() {
+        let source = "foo = bar 1\n";
+        let (db, module) = Database::with_main_module(source);
+        let id = ast_id_at(&db, &module, source, "1");
+        let comments = [" Moved.".to_string()];
+        let replacement = replace_ast_keeping_comments(&db, &id, "2", &comments).unwrap();
+        assert_eq!(replacement.range, Offset(10)..Offset(11));
+        assert_eq!(replacement.new_text, "# Moved.\n  2");
+    }
+}
@@ -0,0 +1,77 @@
+//! Code of a fuzzed function that none of the inputs reached. Such code either
+//! needs an input that the fuzzer is unlikely to generate, so adding an example
+//! guides it there, or it's dead.
+//!
+//! The byte code only knows which functions an instruction belongs to, so blind
+//! spots are nested functions that never ran, such as the bodies of match
+//! cases.
+
+use crate::coverage::Coverage;
+use candy_frontend::hir::Id;
+use candy_vm::{byte_code::ByteCode, InstructionPointer};
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlindSpot {
+    /// The outermost function that never ran. Functions nested in it didn't
+    /// run either and aren't reported separately.
+    pub id: Id,
+    /// Includes the instructions of nested functions.
+    pub num_instructions: usize,
+}
+
+/// The blind spots of the fuzzed function, largest first.
+pub fn blind_spots(byte_code: &ByteCode, function: &Id, coverage: &Coverage) -> Vec<BlindSpot> {
+    // The bodies of nested functions aren't part of the fuzzed function's
+    // instructions, so we look at all of them.
+    let coverage = coverage.all();
+
+    // For each function, how many of the instructions directly in its body ran
+    // and how many there are in total. Instructions can also originate from
+    // functions of other modules that got inlined, but we only report code of
+    // the fuzzed function itself.
+    let mut counts: FxHashMap<&Id, (usize, usize)> = FxHashMap::default();
+    for ip in (0..coverage.num_instructions()).map(InstructionPointer::from) {
+        let Some(innermost) = byte_code
+            .functions_behind(ip)
+            .iter()
+            .filter(|it| *it == function || function.is_same_module_and_any_parent_of(it))
+            .max_by_key(|it| it.keys.len())
+        else {
+            continue;
+        };
+        let (covered, total) = counts.entry(innermost).or_default();
+        if coverage.is_covered(ip) {
+            *covered += 1;
+        }
+        *total += 1;
+    }
+
+    let unreached = counts
+        .iter()
+        .filter(|(id, (covered, _))| **id != function && *covered == 0)
+        .map(|(id, _)| *id)
+        .collect_vec();
+    unreached
+        .iter()
+        .filter(|id| {
+            !unreached
+                .iter()
+                .any(|other| other.is_same_module_and_any_parent_of(id))
+        })
+        .map(|id| BlindSpot {
+            id: (*id).clone(),
+            num_instructions: counts
+                .iter()
+                .filter(|(other, _)| other == &id || id.is_same_module_and_any_parent_of(other))
+                .map(|(_, (_, total))| total)
+                .sum(),
+        })
+        .sorted_by(|a, b| {
+            b.num_instructions
+                .cmp(&a.num_instructions)
+                .then_with(|| a.id.cmp(&b.id))
+        })
+        .collect()
+}
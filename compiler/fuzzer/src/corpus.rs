@@ -0,0 +1,327 @@
+//! Inputs that the fuzzer found interesting are stored on disk so that later
+//! runs don't have to find them again. The language server's analyzer and
+//! `candy fuzz` use the same corpus, so inputs found in CI also help while
+//! editing and vice versa.
+//!
+//! The corpus of a module is a JSON file in the `.candy/fuzzingCorpus` folder
+//! of its package. It maps the keys of fuzzed functions to lists of inputs.
+//! Like other caches in `.candy`, it shouldn't be checked into version
+//! control.
+//! Several processes may fuzz the same module at once, so files are only
+//! accessed while holding a lock file and saving merges our inputs with the
+//! ones already on disk.
+
+use crate::input::Input;
+use candy_frontend::{
+    hir::Id,
+    module::{Module, PackagesPath},
+};
+use candy_vm::{heap::Heap, recording::RecordedValue};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io, mem,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+pub struct Corpus {
+    path: PathBuf,
+}
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CorpusContent {
+    /// Inputs per function, most interesting first.
+    functions: BTreeMap<String, Vec<Vec<RecordedValue>>>,
+}
+
+impl Corpus {
+    const MAX_INPUTS_PER_FUNCTION: usize = 64;
+
+    /// Returns `None` for modules that aren't stored in a package folder.
+    #[must_use]
+    pub fn for_module(module: &Module, packages_path: &PackagesPath) -> Option<Self> {
+        let mut path = module
+            .package
+            .to_path(packages_path)?
+            .join(".candy")
+            .join("fuzzingCorpus");
+        if module.path.is_empty() {
+            path.push("_");
+        } else {
+            path.extend(&module.path);
+        }
+        path.set_extension("json");
+        Some(Self { path })
+    }
+
+    pub fn load(&self) -> io::Result<CorpusContent> {
+        let _lock = Lock::acquire(&self.path)?;
+        self.read()
+    }
+    /// Merges `content` into the corpus on disk. For each function, our inputs
+    /// take precedence over existing ones if there are too many.
+    pub fn save(&self, content: CorpusContent) -> io::Result<()> {
+        if content.functions.is_empty() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(self.path.parent().unwrap())?;
+        let _lock = Lock::acquire(&self.path)?;
+        let mut merged = content;
+        for (function, existing_inputs) in self.read()?.functions {
+            merged
+                .functions
+                .entry(function)
+                .or_default()
+                .extend(existing_inputs);
+        }
+        for inputs in merged.functions.values_mut() {
+            *inputs = mem::take(inputs)
+                .into_iter()
+                .unique()
+                .take(Self::MAX_INPUTS_PER_FUNCTION)
+                .collect();
+        }
+
+        // Other processes may read the file without our lock being held for
+        // long, so we never leave a partially written file behind.
+        let temporary_path = self.path.with_extension("json.tmp");
+        fs::write(
+            &temporary_path,
+            serde_json::to_string_pretty(&merged).unwrap(),
+        )?;
+        fs::rename(temporary_path, &self.path)
+    }
+    fn read(&self) -> io::Result<CorpusContent> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(CorpusContent::default()),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl CorpusContent {
+    fn key(function: &Id) -> String {
+        function.keys.iter().join(":")
+    }
+
+    /// Creates the stored inputs of `function` in the `heap`. Inputs with the
+    /// wrong number of arguments (e.g., because the function changed since)
+    /// are skipped.
+    #[must_use]
+    pub fn inputs_for(&self, function: &Id, argument_count: usize, heap: &mut Heap) -> Vec<Input> {
+        let Some(inputs) = self.functions.get(&Self::key(function)) else {
+            return vec![];
+        };
+        inputs
+            .iter()
+            .filter(|arguments| {
+                arguments.len() == argument_count
+                    && arguments.iter().all(RecordedValue::is_self_contained)
+            })
+            .map(|arguments| {
+                Input::new(
+                    arguments
+                        .iter()
+                        .map(|argument| argument.create_object(heap))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// Adds an input unless it contains values that only make sense in the
+    /// current program, e.g., functions.
+    pub fn add(&mut self, function: &Id, input: &Input) {
+        let arguments = input
+            .arguments()
+            .iter()
+            .map(|argument| RecordedValue::from_object(*argument, &|_| usize::MAX))
+            .collect_vec();
+        if !arguments.iter().all(RecordedValue::is_self_contained) {
+            return;
+        }
+        self.functions
+            .entry(Self::key(function))
+            .or_default()
+            .push(arguments);
+    }
+}
+
+/// A lock file next to the corpus file. Creating a file fails if it already
+/// exists, so only one process can hold the lock at a time.
+struct Lock {
+    path: PathBuf,
+}
+impl Lock {
+    /// Locks of crashed processes are removed after this time.
+    const STALE_AFTER: Duration = Duration::from_secs(30);
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn acquire(corpus_path: &Path) -> io::Result<Self> {
+        let path = corpus_path.with_extension("json.lock");
+        let start = SystemTime::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    // The corpus folder doesn't exist yet, so there's nothing
+                    // to protect.
+                    return Ok(Self { path });
+                }
+                Err(error) => return Err(error),
+            }
+
+            let is_stale = fs::metadata(&path)
+                .and_then(|it| it.modified())
+                .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > Self::STALE_AFTER);
+            if is_stale {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if start.elapsed().unwrap_or_default() > Self::TIMEOUT {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("The corpus is locked by {}.", path.display()),
+                ));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Corpus, CorpusContent, Lock};
+    use candy_vm::recording::RecordedValue;
+    use std::{
+        collections::BTreeMap,
+        env, fs, process,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    /// A corpus in a fresh temporary folder that's removed afterwards.
+    struct TemporaryCorpus(Corpus);
+    impl TemporaryCorpus {
+        fn new(name: &str) -> Self {
+            let folder = env::temp_dir().join(format!("candy-corpus-{name}-{}", process::id()));
+            let _ = fs::remove_dir_all(&folder);
+            Self(Corpus {
+                path: folder.join("module.json"),
+            })
+        }
+    }
+    impl Drop for TemporaryCorpus {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.path.parent().unwrap());
+        }
+    }
+
+    fn content(functions: &[(&str, &[i32])]) -> CorpusContent {
+        let functions = functions
+            .iter()
+            .map(|(function, inputs)| {
+                let inputs = inputs
+                    .iter()
+                    .map(|it| vec![RecordedValue::Int(it.to_string())])
+                    .collect();
+                ((*function).to_string(), inputs)
+            })
+            .collect();
+        CorpusContent { functions }
+    }
+    fn inputs(content: &CorpusContent) -> BTreeMap<&str, Vec<&str>> {
+        content
+            .functions
+            .iter()
+            .map(|(function, inputs)| {
+                let inputs = inputs
+                    .iter()
+                    .map(|it| match it.as_slice() {
+                        [RecordedValue::Int(value)] => value.as_str(),
+                        _ => panic!("Unexpected input: {it:?}"),
+                    })
+                    .collect();
+                (function.as_str(), inputs)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saving_merges_with_the_corpus_on_disk() {
+        let corpus = TemporaryCorpus::new("merge");
+        assert_eq!(inputs(&corpus.0.load().unwrap()), BTreeMap::new());
+
+        corpus.0.save(content(&[("a", &[1, 2])])).unwrap();
+        corpus
+            .0
+            .save(content(&[("a", &[3, 1]), ("b", &[4])]))
+            .unwrap();
+        assert_eq!(
+            inputs(&corpus.0.load().unwrap()),
+            BTreeMap::from([("a", vec!["3", "1", "2"]), ("b", vec!["4"])]),
+        );
+    }
+    #[test]
+    fn saving_keeps_our_inputs_if_there_are_too_many() {
+        let corpus = TemporaryCorpus::new("limit");
+        let old = (0..100).collect::<Vec<_>>();
+        corpus.0.save(content(&[("a", &old)])).unwrap();
+        corpus.0.save(content(&[("a", &[-1])])).unwrap();
+
+        let content = corpus.0.load().unwrap();
+        let inputs = &inputs(&content)["a"];
+        assert_eq!(inputs.len(), Corpus::MAX_INPUTS_PER_FUNCTION);
+        assert_eq!(inputs[0], "-1");
+        assert_eq!(inputs[1], "0");
+    }
+    #[test]
+    fn saving_nothing_doesnt_create_a_file() {
+        let corpus = TemporaryCorpus::new("empty");
+        corpus.0.save(CorpusContent::default()).unwrap();
+        assert!(!corpus.0.path.exists());
+    }
+
+    #[test]
+    fn the_corpus_is_only_accessed_by_one_process_at_a_time() {
+        let corpus = TemporaryCorpus::new("lock");
+        fs::create_dir_all(corpus.0.path.parent().unwrap()).unwrap();
+
+        let lock = Lock::acquire(&corpus.0.path).unwrap();
+        let is_released = Arc::new(AtomicBool::new(false));
+        let holder = thread::spawn({
+            let is_released = is_released.clone();
+            move || {
+                thread::sleep(Duration::from_millis(100));
+                is_released.store(true, Ordering::SeqCst);
+                drop(lock);
+            }
+        });
+        corpus.0.save(content(&[("a", &[1])])).unwrap();
+        assert!(is_released.load(Ordering::SeqCst));
+        holder.join().unwrap();
+
+        assert!(!corpus.0.path.with_extension("json.lock").exists());
+        assert_eq!(
+            inputs(&corpus.0.load().unwrap()),
+            BTreeMap::from([("a", vec!["1"])]),
+        );
+    }
+}
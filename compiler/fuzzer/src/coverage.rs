@@ -62,6 +62,13 @@ impl<'a> RangeCoverage<'a> {
             .count()
     }
 
+    pub fn num_instructions(&self) -> usize {
+        self.coverage.len()
+    }
+    pub fn num_uncovered(&self) -> usize {
+        self.coverage.count_zeros()
+    }
+
     #[allow(clippy::cast_precision_loss)]
     pub fn relative_coverage(&self) -> f64 {
         assert!(!self.coverage.is_empty());
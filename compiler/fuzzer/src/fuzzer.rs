@@ -1,8 +1,11 @@
 use crate::{
+    blind_spots::{blind_spots, BlindSpot},
+    corpus::CorpusContent,
     coverage::Coverage,
     input::Input,
     input_pool::{InputPool, Score},
     runner::{RunResult, Runner},
+    synthetic_functions::SyntheticFunctions,
     utils::collect_symbols_in_heap,
 };
 use candy_frontend::hir::Id;
@@ -13,6 +16,7 @@ use candy_vm::{
     Panic,
 };
 use itertools::Itertools;
+use rustc_hash::FxHashSet;
 use std::rc::Rc;
 use tracing::debug;
 
@@ -24,6 +28,12 @@ pub struct Fuzzer {
     pub function: Function,
     pub function_id: Id,
     pool: InputPool,
+    /// Inputs from the corpus that weren't run yet. They're run before
+    /// generating new inputs, the last one first.
+    seeds: Vec<Input>,
+    /// Responsible IDs of panics that static analysis already found. The
+    /// fuzzer doesn't report these again.
+    known_panics: FxHashSet<Id>,
     status: Option<Status>, // only `None` during transitions
 }
 
@@ -62,8 +72,14 @@ pub enum FuzzerResult {
 }
 
 impl Fuzzer {
+    /// The byte code must contain the bodies of the synthetic functions.
     #[must_use]
-    pub fn new(byte_code: Rc<ByteCode>, function: Function, function_id: Id) -> Self {
+    pub fn new(
+        byte_code: Rc<ByteCode>,
+        synthetic_functions: Rc<SyntheticFunctions>,
+        function: Function,
+        function_id: Id,
+    ) -> Self {
         let mut persistent_heap = Heap::default();
         let function: Function = function
             .clone_to_heap(&mut persistent_heap)
@@ -76,6 +92,7 @@ impl Fuzzer {
             collect_symbols_in_heap(&persistent_heap)
                 .into_iter()
                 .collect_vec(),
+            synthetic_functions,
         );
 
         let input = pool.generate_new_input(&mut persistent_heap);
@@ -90,6 +107,8 @@ impl Fuzzer {
             function,
             function_id,
             pool,
+            seeds: vec![],
+            known_panics: FxHashSet::default(),
             status: Some(Status::StillFuzzing {
                 total_coverage: Coverage::none(num_instructions),
                 input,
@@ -98,6 +117,41 @@ impl Fuzzer {
         }
     }
 
+    /// Makes the fuzzer skip panics for which one of the given IDs is
+    /// responsible, e.g., because they're reported by static analysis anyway.
+    ///
+    /// Inputs causing these panics don't become part of the input pool, so the
+    /// fuzzer focuses on finding other code paths instead.
+    #[must_use]
+    pub fn with_known_panics(mut self, responsible_ids: impl IntoIterator<Item = Id>) -> Self {
+        self.known_panics.extend(responsible_ids);
+        self
+    }
+
+    /// Makes the fuzzer start with the inputs stored in the corpus for this
+    /// function.
+    #[must_use]
+    pub fn with_corpus(mut self, corpus: &CorpusContent) -> Self {
+        let mut seeds = corpus.inputs_for(
+            &self.function_id,
+            self.function.argument_count(),
+            &mut self.persistent_heap,
+        );
+        seeds.reverse();
+        self.seeds = seeds;
+        self
+    }
+    /// Adds the input that made the function panic (if any) and the most
+    /// interesting inputs of the pool to the corpus.
+    pub fn add_to_corpus(&self, corpus: &mut CorpusContent) {
+        if let Status::FoundPanic { input, .. } = self.status() {
+            corpus.add(&self.function_id, input);
+        }
+        for input in self.pool.inputs_by_score() {
+            corpus.add(&self.function_id, input);
+        }
+    }
+
     #[must_use]
     pub fn byte_code(&self) -> Rc<ByteCode> {
         self.byte_code.clone()
@@ -138,11 +192,34 @@ impl Fuzzer {
         }
     }
 
+    /// Code of the function that no input reached so far, largest first. Once
+    /// the fuzzer found a panic, it doesn't know the coverage anymore and
+    /// returns nothing.
+    #[must_use]
+    pub fn blind_spots(&self) -> Vec<BlindSpot> {
+        match self.status() {
+            Status::StillFuzzing { total_coverage, .. } => {
+                blind_spots(&self.byte_code, &self.function_id, total_coverage)
+            }
+            Status::FoundPanic { .. } => vec![],
+        }
+    }
+
     #[must_use]
     pub const fn input_pool(&self) -> &InputPool {
         &self.pool
     }
 
+    /// The memory used by this fuzzer's heaps.
+    #[must_use]
+    pub fn live_bytes(&self) -> usize {
+        let status_bytes = match self.status() {
+            Status::StillFuzzing { runner, .. } => runner.live_bytes(),
+            Status::FoundPanic { heap, .. } => heap.live_bytes(),
+        };
+        self.persistent_heap.live_bytes() + status_bytes
+    }
+
     pub fn run(&mut self, max_instructions: usize) {
         let mut status = self.status.take().unwrap();
         let mut instructions_left = max_instructions;
@@ -205,6 +282,11 @@ impl Fuzzer {
 
                 self.create_new_fuzzing_case(&total_coverage + &runner.coverage)
             }
+            RunResult::Panicked { panic, .. } if self.known_panics.contains(&panic.responsible) => {
+                // The code that leads to this panic counts as covered so that
+                // inputs reaching it again aren't favored.
+                self.create_new_fuzzing_case(&total_coverage + &runner.coverage)
+            }
             RunResult::Panicked {
                 heap,
                 tracer,
@@ -218,7 +300,10 @@ impl Fuzzer {
         }
     }
     fn create_new_fuzzing_case(&mut self, total_coverage: Coverage) -> Status {
-        let input = self.pool.generate_new_input(&mut self.persistent_heap);
+        let input = self
+            .seeds
+            .pop()
+            .unwrap_or_else(|| self.pool.generate_new_input(&mut self.persistent_heap));
         let runner = Runner::new(self.byte_code.clone(), self.function, &input);
         Status::StillFuzzing {
             total_coverage,
@@ -227,3 +312,96 @@ impl Fuzzer {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Fuzzer, Status};
+    use crate::{synthetic_functions::SyntheticFunctions, utils::FuzzablesFinder};
+    use candy_frontend::{
+        hir::Id,
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig, TracingMode,
+    };
+    use candy_vm::{heap::Heap, lir_to_byte_code::compile_byte_code, Vm, VmFinished};
+    use std::rc::Rc;
+
+    /// Creates a fuzzer for the function `foo` of the source.
+    fn fuzzer(source: &str, known_panics: impl IntoIterator<Item = Id>) -> Fuzzer {
+        let mut db = Database::default();
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, source.as_bytes().to_vec());
+        let tracing = TracingConfig {
+            register_fuzzables: TracingMode::OnlyCurrent,
+            calls: TracingMode::Off,
+            evaluated_expressions: TracingMode::Off,
+        };
+        let (mut byte_code, _) = compile_byte_code(&db, ExecutionTarget::Module(module), tracing);
+        let synthetic_functions = Rc::new(SyntheticFunctions::add_to(&mut byte_code));
+        let byte_code = Rc::new(byte_code);
+
+        let mut heap = Heap::default();
+        let VmFinished {
+            tracer: FuzzablesFinder { fuzzables },
+            ..
+        } = Vm::for_module(byte_code.clone(), &mut heap, FuzzablesFinder::default())
+            .run_forever_without_handles(&mut heap);
+        let (id, function) = fuzzables
+            .iter()
+            .find(|(id, _)| id.function_name() == "foo")
+            .unwrap();
+        Fuzzer::new(byte_code, synthetic_functions, *function, id.clone())
+            .with_known_panics(known_panics)
+    }
+
+    /// `foo` is responsible for calling `bar` with an argument that `bar`
+    /// doesn't accept.
+    const SOURCE: &str = "bar b := needs False \"Oops\"\nfoo a := bar a\n";
+
+    #[test]
+    fn panics_are_found() {
+        let mut fuzzer = fuzzer(SOURCE, []);
+        fuzzer.run(10_000);
+        let Status::FoundPanic { panic, .. } = fuzzer.status() else {
+            panic!("The fuzzer should find the panic.");
+        };
+        assert_eq!(panic.reason, "Oops");
+    }
+
+    #[test]
+    fn known_panics_are_skipped() {
+        let mut fuzzer_without_known_panics = fuzzer(SOURCE, []);
+        fuzzer_without_known_panics.run(10_000);
+        let Status::FoundPanic { panic, .. } = fuzzer_without_known_panics.status() else {
+            panic!("The fuzzer should find the panic.");
+        };
+
+        let mut fuzzer = fuzzer(SOURCE, [panic.responsible.clone()]);
+        fuzzer.run(10_000);
+        assert!(matches!(fuzzer.status(), Status::StillFuzzing { .. }));
+    }
+
+    #[test]
+    fn unreached_match_cases_are_blind_spots() {
+        let mut fuzzer = fuzzer("foo a := a %\n  123456789 -> (a, a, a)\n  _ -> a\n", []);
+        fuzzer.run(10_000);
+        let blind_spots = fuzzer.blind_spots();
+        // The case's body and the comparison with the number are never run.
+        assert_eq!(blind_spots.len(), 2);
+        assert!(blind_spots[0].id.to_string().ends_with(":case-0:matched"));
+        assert!(blind_spots.iter().all(|it| it.num_instructions > 0));
+    }
+    #[test]
+    fn covered_functions_have_no_blind_spots() {
+        let mut fuzzer = fuzzer("foo a := (a, a)\n", []);
+        fuzzer.run(10_000);
+        assert_eq!(fuzzer.blind_spots(), []);
+    }
+}
@@ -1,24 +1,31 @@
 use super::input::Input;
-use crate::runner::RunResult;
+use crate::{runner::RunResult, synthetic_functions::SyntheticFunctions};
 use candy_vm::heap::{Heap, Text};
 use itertools::Itertools;
 use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
 use rustc_hash::FxHashMap;
+use std::rc::Rc;
 
 pub type Score = f64;
 
 pub struct InputPool {
     num_args: usize,
     symbols: Vec<Text>,
+    synthetic_functions: Rc<SyntheticFunctions>,
     results_and_scores: FxHashMap<Input, (RunResult, Score)>,
 }
 
 impl InputPool {
     #[must_use]
-    pub fn new(num_args: usize, symbols: Vec<Text>) -> Self {
+    pub fn new(
+        num_args: usize,
+        symbols: Vec<Text>,
+        synthetic_functions: Rc<SyntheticFunctions>,
+    ) -> Self {
         Self {
             num_args,
             symbols,
+            synthetic_functions,
             results_and_scores: FxHashMap::default(),
         }
     }
@@ -40,7 +47,12 @@ impl InputPool {
         let mut rng = ThreadRng::default();
 
         if rng.gen_bool(0.1) || self.results_and_scores.len() < 20 {
-            return Input::generate(heap, self.num_args, &self.symbols);
+            return Input::generate(
+                heap,
+                self.num_args,
+                &self.symbols,
+                &self.synthetic_functions,
+            );
         }
 
         let inputs_and_scores = self
@@ -51,7 +63,7 @@ impl InputPool {
         let (input, _) = inputs_and_scores
             .choose_weighted(&mut rng, |(_, score)| *score)
             .unwrap();
-        input.mutated(heap, &mut rng, &self.symbols)
+        input.mutated(heap, &mut rng, &self.symbols, &self.synthetic_functions)
     }
 
     pub fn add(&mut self, input: Input, result: RunResult, score: Score) {
@@ -79,6 +91,14 @@ impl InputPool {
             .collect_vec()
     }
 
+    /// All inputs in the pool, the ones with the highest score first.
+    pub fn inputs_by_score(&self) -> impl Iterator<Item = &Input> {
+        self.results_and_scores
+            .iter()
+            .sorted_by(|(_, (_, score_a)), (_, (_, score_b))| score_b.total_cmp(score_a))
+            .map(|(input, _)| input)
+    }
+
     pub fn result_of(&self, input: &Input) -> &RunResult {
         &self.results_and_scores.get(input).unwrap().0
     }
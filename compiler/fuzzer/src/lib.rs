@@ -1,20 +1,36 @@
 #![feature(let_chains, round_char_boundary)]
 #![warn(clippy::nursery, clippy::pedantic, unused_crate_dependencies)]
-#![allow(clippy::missing_panics_doc, clippy::module_name_repetitions)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::module_name_repetitions
+)]
 
+mod blind_spots;
+mod corpus;
 mod coverage;
 mod fuzzer;
 mod input;
 mod input_pool;
+mod panic_groups;
+mod pool;
+mod regression_test;
 mod runner;
+mod synthetic_functions;
 mod utils;
 mod values;
 
 use self::input::Input;
 pub use self::{
+    blind_spots::BlindSpot,
+    corpus::{Corpus, CorpusContent},
     fuzzer::{Fuzzer, Status},
     input_pool::InputPool,
+    panic_groups::{group_by_panic, PanicGroup, PanicSignature},
+    pool::FuzzerPool,
+    regression_test::RegressionTest,
     runner::RunResult,
+    synthetic_functions::SyntheticFunctions,
     utils::FuzzablesFinder,
 };
 use crate::fuzzer::FuzzerResult;
@@ -31,10 +47,19 @@ use candy_vm::{
     heap::Heap, lir_to_byte_code::compile_byte_code, tracer::stack_trace::StackTracer, Panic, Vm,
     VmFinished,
 };
+use itertools::Itertools;
 use std::rc::Rc;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-pub fn fuzz<DB>(db: &DB, module: Module) -> Vec<FailingFuzzCase>
+/// Fuzzes all fuzzable functions of the module or, if `only_public` is set,
+/// only the ones it exports. If a `corpus` is given, the fuzzers start with the
+/// inputs stored in it and new interesting inputs are added to it afterwards.
+pub fn fuzz<DB>(
+    db: &DB,
+    module: Module,
+    corpus: Option<&Corpus>,
+    only_public: bool,
+) -> Vec<FailingFuzzCase>
 where
     DB: AstToHir + CstDb + OptimizeLir + PositionConversionDb,
 {
@@ -43,7 +68,9 @@ where
         calls: TracingMode::Off,
         evaluated_expressions: TracingMode::Off,
     };
-    let (byte_code, _) = compile_byte_code(db, ExecutionTarget::Module(module), tracing);
+    let (mut byte_code, _) =
+        compile_byte_code(db, ExecutionTarget::Module(module.clone()), tracing);
+    let synthetic_functions = Rc::new(SyntheticFunctions::add_to(&mut byte_code));
     let byte_code = Rc::new(byte_code);
 
     let mut heap = Heap::default();
@@ -53,24 +80,62 @@ where
     } = Vm::for_module(byte_code.clone(), &mut heap, FuzzablesFinder::default())
         .run_forever_without_handles(&mut heap);
 
+    let corpus_content = corpus
+        .map(|corpus| {
+            corpus.load().unwrap_or_else(|error| {
+                warn!("Couldn't load the fuzzing corpus: {error}");
+                CorpusContent::default()
+            })
+        })
+        .unwrap_or_default();
+    let mut pool = FuzzerPool::new(fuzzables.iter().map(|(id, function)| {
+        Fuzzer::new(
+            byte_code.clone(),
+            synthetic_functions.clone(),
+            *function,
+            id.clone(),
+        )
+        .with_corpus(&corpus_content)
+    }));
+    if only_public {
+        pool = pool.with_only_functions(&db.exported_functions(module));
+    }
+
+    let functions = pool.fuzzers().map(|it| &it.function_id).collect_vec();
     info!(
-        "Now, the fuzzing begins. We have {} functions to fuzz: {fuzzables:?}.",
-        fuzzables.len(),
+        "Now, the fuzzing begins. We have {} functions to fuzz: {functions:?}.",
+        functions.len(),
     );
+    // Functions get more or less than this on average depending on their size
+    // and coverage.
+    pool.run(100_000 * functions.len(), 1000);
 
-    let mut failing_cases = vec![];
-
-    for (id, function) in fuzzables {
-        info!("Fuzzing {id}.");
-        let mut fuzzer = Fuzzer::new(byte_code.clone(), function, id.clone());
-        fuzzer.run(100_000);
+    if let Some(corpus) = corpus {
+        let mut new_content = CorpusContent::default();
+        for fuzzer in pool.fuzzers() {
+            fuzzer.add_to_corpus(&mut new_content);
+        }
+        if let Err(error) = corpus.save(new_content) {
+            warn!("Couldn't save the fuzzing corpus: {error}");
+        }
+    }
 
+    let mut failing_cases = vec![];
+    for fuzzer in pool.into_fuzzers() {
+        let id = fuzzer.function_id.clone();
+        let blind_spots = fuzzer.blind_spots();
         match fuzzer.into_result() {
             FuzzerResult::StillFuzzing { total_coverage, .. } => {
                 let coverage = total_coverage
                     .in_range(&byte_code.range_of_function(&id))
                     .relative_coverage();
                 debug!("Achieved a coverage of {:.1} %.", coverage * 100.0);
+                for blind_spot in blind_spots.iter().take(3) {
+                    info!(
+                        "The fuzzer could not reach {} ({} instructions).",
+                        blind_spot.id, blind_spot.num_instructions,
+                    );
+                }
             }
             FuzzerResult::FoundPanic {
                 input,
@@ -105,7 +170,18 @@ pub struct FailingFuzzCase {
     tracer: StackTracer,
 }
 
+/// Groups the failing cases so that each bug is only reported once.
+#[must_use]
+pub fn group_failing_cases(cases: Vec<FailingFuzzCase>) -> Vec<PanicGroup<FailingFuzzCase>> {
+    group_by_panic(cases, |case| (&case.input, &case.panic))
+}
+
 impl FailingFuzzCase {
+    #[must_use]
+    pub const fn function(&self) -> &Id {
+        &self.function
+    }
+
     #[allow(unused_variables)]
     pub fn dump<DB>(&self, db: &DB)
     where
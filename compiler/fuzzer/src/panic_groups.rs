@@ -0,0 +1,145 @@
+use crate::input::Input;
+use candy_frontend::hir::Id;
+use candy_vm::Panic;
+use rustc_hash::FxHashMap;
+
+/// The same bug often makes many inputs and even several fuzzed functions
+/// panic. Panics with the same signature are probably caused by the same bug,
+/// so we only report one of them.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PanicSignature {
+    pub responsible: Id,
+    /// The reason with all parts that depend on the input (numbers and
+    /// quoted texts) replaced by placeholders.
+    pub reason_shape: String,
+}
+impl PanicSignature {
+    #[must_use]
+    pub fn of(panic: &Panic) -> Self {
+        Self {
+            responsible: panic.responsible.clone(),
+            reason_shape: reason_shape(&panic.reason),
+        }
+    }
+}
+fn reason_shape(reason: &str) -> String {
+    let mut shape = String::with_capacity(reason.len());
+    let mut chars = reason.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            '0'..='9' => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                shape.push('#');
+            }
+            '"' => {
+                for char in chars.by_ref() {
+                    if char == '"' {
+                        break;
+                    }
+                }
+                shape.push_str("\"…\"");
+            }
+            _ => shape.push(char),
+        }
+    }
+    shape
+}
+
+pub struct PanicGroup<T> {
+    pub signature: PanicSignature,
+    /// The finding with the simplest input.
+    pub representative: T,
+    pub others: Vec<T>,
+}
+impl<T> PanicGroup<T> {
+    #[must_use]
+    pub fn occurrences(&self) -> usize {
+        1 + self.others.len()
+    }
+}
+
+/// Groups findings by the [`PanicSignature`] of their panic. Groups are ordered
+/// by their first finding.
+pub fn group_by_panic<T>(
+    findings: impl IntoIterator<Item = T>,
+    input_and_panic: impl Fn(&T) -> (&Input, &Panic),
+) -> Vec<PanicGroup<T>> {
+    let mut groups: Vec<PanicGroup<T>> = vec![];
+    let mut indices = FxHashMap::default();
+    for finding in findings {
+        let (input, panic) = input_and_panic(&finding);
+        let signature = PanicSignature::of(panic);
+        let Some(&index) = indices.get(&signature) else {
+            indices.insert(signature.clone(), groups.len());
+            groups.push(PanicGroup {
+                signature,
+                representative: finding,
+                others: vec![],
+            });
+            continue;
+        };
+
+        let group = &mut groups[index];
+        let complexity = input.complexity();
+        if complexity < input_and_panic(&group.representative).0.complexity() {
+            let previous = std::mem::replace(&mut group.representative, finding);
+            group.others.push(previous);
+        } else {
+            group.others.push(finding);
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use super::{group_by_panic, reason_shape};
+    use crate::input::Input;
+    use candy_frontend::hir::Id;
+    use candy_vm::{
+        heap::{Heap, Int},
+        Panic,
+    };
+    use itertools::Itertools;
+
+    #[test]
+    fn numbers_and_texts_are_replaced_in_reasons() {
+        assert_eq!(
+            reason_shape("Expected 12 items, got \"a 3\" and 4."),
+            "Expected # items, got \"…\" and #.",
+        );
+    }
+
+    #[test]
+    fn panics_are_grouped_by_responsible_and_reason_shape() {
+        let mut heap = Heap::default();
+        let mut finding = |name: &'static str, argument: i64, responsible: Id, reason: &str| {
+            let input = Input::new(vec![Int::create(&mut heap, true, argument).into()]);
+            let panic = Panic {
+                reason: reason.to_string(),
+                responsible,
+            };
+            (name, input, panic)
+        };
+        let findings = vec![
+            finding("a", 1000, Id::dummy(), "Got 1000."),
+            finding("b", 1, Id::fuzzer(), "Got 1."),
+            finding("c", 2, Id::dummy(), "Got 2."),
+            finding("d", 3, Id::dummy(), "Oops."),
+        ];
+
+        let groups = group_by_panic(findings, |(_, input, panic)| (input, panic));
+        assert_eq!(
+            groups
+                .iter()
+                .map(|group| (
+                    group.representative.0,
+                    group.others.iter().map(|(name, ..)| *name).collect_vec(),
+                ))
+                .collect_vec(),
+            [("c", vec!["a"]), ("b", vec![]), ("d", vec![])],
+        );
+        assert_eq!(groups[0].occurrences(), 2);
+        assert_eq!(groups[0].signature.reason_shape, "Got #.");
+    }
+}
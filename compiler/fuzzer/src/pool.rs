@@ -0,0 +1,230 @@
+//! Schedules fuzzing across all fuzzable functions of a module.
+//!
+//! Rather than giving every function the same number of instructions, the pool
+//! weights functions by the size of their byte code plus the number of their
+//! instructions that no input has covered yet. Big, complicated functions thus
+//! get proportionally more attention, and functions whose code is already
+//! fully covered fall back to a share proportional to their size.
+//!
+//! The pool keeps track of how many instructions each fuzzer has received and
+//! always continues the one that is furthest behind its share (like a stride
+//! scheduler). Weights are recomputed on every step, so they follow the
+//! coverage as it grows.
+
+use crate::fuzzer::{Fuzzer, Status};
+use candy_frontend::hir::Id;
+use candy_vm::InstructionPointer;
+use rustc_hash::FxHashSet;
+use std::ops::Range;
+
+pub struct FuzzerPool {
+    entries: Vec<Entry>,
+}
+struct Entry {
+    fuzzer: Fuzzer,
+    /// The byte code of the fuzzed function. Finding it requires scanning the
+    /// whole byte code, so we only do that once.
+    function_range: Range<InstructionPointer>,
+    instructions_spent: usize,
+}
+
+impl FuzzerPool {
+    #[must_use]
+    pub fn new(fuzzers: impl IntoIterator<Item = Fuzzer>) -> Self {
+        let entries = fuzzers
+            .into_iter()
+            .map(|fuzzer| Entry {
+                function_range: fuzzer.byte_code.range_of_function(&fuzzer.function_id),
+                fuzzer,
+                instructions_spent: 0,
+            })
+            .collect();
+        Self { entries }
+    }
+    /// Only fuzzes the given functions, e.g., the ones a module exports. This
+    /// way, only inputs that external callers can actually pass are tried and
+    /// private helpers are only fuzzed indirectly.
+    #[must_use]
+    pub fn with_only_functions(mut self, functions: &FxHashSet<Id>) -> Self {
+        self.entries
+            .retain(|entry| functions.contains(&entry.fuzzer.function_id));
+        self
+    }
+
+    pub fn fuzzers(&self) -> impl Iterator<Item = &Fuzzer> {
+        self.entries.iter().map(|entry| &entry.fuzzer)
+    }
+    pub fn into_fuzzers(self) -> impl Iterator<Item = Fuzzer> {
+        self.entries.into_iter().map(|entry| entry.fuzzer)
+    }
+    /// The fuzzers together with the number of instructions they received so
+    /// far.
+    pub fn fuzzers_with_instructions_spent(&self) -> impl Iterator<Item = (&Fuzzer, usize)> {
+        self.entries
+            .iter()
+            .map(|entry| (&entry.fuzzer, entry.instructions_spent))
+    }
+
+    /// The memory used by the heaps of all fuzzers.
+    #[must_use]
+    pub fn live_bytes(&self) -> usize {
+        self.fuzzers().map(Fuzzer::live_bytes).sum()
+    }
+
+    /// Whether every fuzzer found a panic so there's nothing left to do.
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.next_index().is_none()
+    }
+
+    /// The fuzzer that [`Self::run_next`] will continue.
+    #[must_use]
+    pub fn next_fuzzer(&self) -> Option<&Fuzzer> {
+        self.next_index().map(|index| &self.entries[index].fuzzer)
+    }
+
+    /// Continues the fuzzer that received the least instructions relative to
+    /// its weight and returns it.
+    pub fn run_next(&mut self, max_instructions: usize) -> Option<&Fuzzer> {
+        let index = self.next_index()?;
+        let entry = &mut self.entries[index];
+        entry.fuzzer.run(max_instructions);
+        entry.instructions_spent += max_instructions;
+        Some(&entry.fuzzer)
+    }
+
+    /// Distributes `total_instructions` among the fuzzers in slices of
+    /// `instructions_per_slice`. Stops early if all fuzzers found a panic.
+    pub fn run(&mut self, total_instructions: usize, instructions_per_slice: usize) {
+        let mut instructions_left = total_instructions;
+        while instructions_left > 0 {
+            let instructions = instructions_per_slice.min(instructions_left);
+            if self.run_next(instructions).is_none() {
+                break;
+            }
+            instructions_left -= instructions;
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn next_index(&self) -> Option<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let weight = entry.weight()?;
+                Some((index, entry.instructions_spent as f64 / weight as f64))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+}
+impl Entry {
+    /// Returns `None` for fuzzers that are done.
+    fn weight(&self) -> Option<usize> {
+        let Status::StillFuzzing { total_coverage, .. } = self.fuzzer.status() else {
+            return None;
+        };
+        let coverage = total_coverage.in_range(&self.function_range);
+        Some((coverage.num_instructions() + coverage.num_uncovered()).max(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FuzzerPool;
+    use crate::{fuzzer::Fuzzer, synthetic_functions::SyntheticFunctions, utils::FuzzablesFinder};
+    use candy_frontend::{
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig, TracingMode,
+    };
+    use candy_vm::{heap::Heap, lir_to_byte_code::compile_byte_code, Vm, VmFinished};
+    use itertools::Itertools;
+    use rustc_hash::FxHashSet;
+    use std::rc::Rc;
+
+    fn pool(source: &str) -> FuzzerPool {
+        let mut db = Database::default();
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, source.as_bytes().to_vec());
+        let tracing = TracingConfig {
+            register_fuzzables: TracingMode::OnlyCurrent,
+            calls: TracingMode::Off,
+            evaluated_expressions: TracingMode::Off,
+        };
+        let (mut byte_code, _) = compile_byte_code(&db, ExecutionTarget::Module(module), tracing);
+        let synthetic_functions = Rc::new(SyntheticFunctions::add_to(&mut byte_code));
+        let byte_code = Rc::new(byte_code);
+
+        let mut heap = Heap::default();
+        let VmFinished {
+            tracer: FuzzablesFinder { fuzzables },
+            ..
+        } = Vm::for_module(byte_code.clone(), &mut heap, FuzzablesFinder::default())
+            .run_forever_without_handles(&mut heap);
+        FuzzerPool::new(fuzzables.iter().map(|(id, function)| {
+            Fuzzer::new(
+                byte_code.clone(),
+                synthetic_functions.clone(),
+                *function,
+                id.clone(),
+            )
+        }))
+    }
+    fn instructions_spent(pool: &FuzzerPool, name: &str) -> usize {
+        pool.fuzzers_with_instructions_spent()
+            .find(|(fuzzer, _)| fuzzer.function_id.function_name() == name)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn bigger_functions_get_more_instructions() {
+        let mut pool = pool(
+            "small a := a
+big a :=
+  b = (a, a, a, a)
+  c = (b, b, b, b)
+  d = (c, c, c, c)
+  (d, d, d, d)
+",
+        );
+        pool.run(100_000, 100);
+        assert!(instructions_spent(&pool, "big") > instructions_spent(&pool, "small"));
+        assert_eq!(
+            instructions_spent(&pool, "big") + instructions_spent(&pool, "small"),
+            100_000,
+        );
+    }
+
+    #[test]
+    fn fuzzers_that_found_a_panic_are_done() {
+        let mut pool = pool("bar b := needs False \"Oops\"\nfoo a := bar a\n");
+        let foo = pool
+            .fuzzers()
+            .map(|it| it.function_id.clone())
+            .find(|it| it.function_name() == "foo")
+            .unwrap();
+        pool = pool.with_only_functions(&FxHashSet::from_iter([foo.clone()]));
+        assert_eq!(
+            pool.fuzzers()
+                .map(|it| it.function_id.clone())
+                .collect_vec(),
+            vec![foo],
+        );
+
+        pool.run(100_000, 100);
+        assert!(pool.is_done());
+        assert!(pool.next_fuzzer().is_none());
+        // The pool stops early.
+        assert!(instructions_spent(&pool, "foo") < 100_000);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::FailingFuzzCase;
+use candy_frontend::{
+    format::Precedence,
+    hir::IdKey,
+    module::{Module, ModuleKind, Package, PackagesPath},
+};
+use candy_vm::heap::ToCandySource;
+use itertools::Itertools;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A Candy file that reproduces a failing fuzz case.
+///
+/// Like the compiler's self tests, it comes with companion files describing
+/// the expected behavior: `….candy.stdout` is empty and `….candy.panic`
+/// contains the reason of the panic. Hence, `candy selftest` checks that the
+/// panic still occurs.
+pub struct RegressionTest {
+    /// The module of the test file. It lives next to the fuzzed module.
+    pub module: Module,
+    pub source: String,
+    pub panic_reason: String,
+}
+
+impl FailingFuzzCase {
+    /// Generates a Candy file that calls the fuzzed function with the input
+    /// that made it panic.
+    ///
+    /// Only top-level functions can be imported from another module, so this
+    /// returns `None` for nested functions. It also returns `None` if the
+    /// fuzzed module has no location the test could be placed next to or if an
+    /// argument can't be written as a literal.
+    #[must_use]
+    pub fn to_regression_test(&self, index: usize) -> Option<RegressionTest> {
+        let [IdKey::Named { name, .. }] = self.function.keys.as_slice() else {
+            return None;
+        };
+        let fuzzed_module = &self.function.module;
+
+        let (test_path, use_path) = match fuzzed_module.path.split_last() {
+            Some((last, parent)) => {
+                let mut test_path = parent.to_vec();
+                test_path.push(format!("{last}FuzzCase{index}"));
+                (test_path, format!("..{last}"))
+            }
+            None => match &fuzzed_module.package {
+                Package::Managed(package) => (
+                    vec![format!("fuzzCase{index}")],
+                    package.to_str()?.to_string(),
+                ),
+                _ => return None,
+            },
+        };
+
+        let arguments = self
+            .input
+            .arguments()
+            .iter()
+            .map(|argument| argument.to_candy_source(Precedence::High))
+            .collect::<Option<Vec<_>>>()?
+            .join(" ");
+        let call = if arguments.is_empty() {
+            format!("{name} | function.run")
+        } else {
+            format!("{name} {arguments}")
+        };
+        let mut core_imports = vec![];
+        if arguments.is_empty() {
+            core_imports.push("function");
+        }
+        if self
+            .input
+            .arguments()
+            .iter()
+            .any(|argument| argument.candy_source_uses_int())
+        {
+            core_imports.push("int");
+        }
+        let core_import = if core_imports.is_empty() {
+            String::new()
+        } else {
+            format!("[{}] = use \"Core\"\n", core_imports.join(", "))
+        };
+
+        let source = format!(
+            "# Generated by the fuzzer. This reproduces a panic found in `{name}`:\n\
+             # {reason}\n\
+             # {responsible} is responsible.\n\
+             #\n\
+             # `candy selftest` checks that it still panics with the reason stored in\n\
+             # the `.panic` file next to this one.\n\
+             \n\
+             {core_import}\
+             [{name}] = use \"{use_path}\"\n\
+             \n\
+             main := {{ environment ->\n  \
+               {call}\n\
+             }}\n",
+            reason = self.panic.reason.lines().join(" "),
+            responsible = self.panic.responsible,
+        );
+
+        Some(RegressionTest {
+            module: Module {
+                package: fuzzed_module.package.clone(),
+                path: test_path,
+                kind: ModuleKind::Code,
+            },
+            source,
+            panic_reason: self.panic.reason.clone(),
+        })
+    }
+}
+
+impl RegressionTest {
+    /// Writes the test and its companion files next to the fuzzed module and
+    /// returns the path of the test.
+    ///
+    /// Unless `overwrite` is set, this fails with
+    /// [`io::ErrorKind::AlreadyExists`] if one of the files exists already.
+    pub fn write(&self, packages_path: &PackagesPath, overwrite: bool) -> io::Result<PathBuf> {
+        let path = self
+            .module
+            .to_possible_paths(packages_path)
+            .and_then(|paths| paths.into_iter().last())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "The fuzzed module doesn't live on the file system.",
+                )
+            })?;
+        let files = [
+            (path.clone(), self.source.as_str()),
+            (companion_file(&path, "stdout"), ""),
+            (companion_file(&path, "panic"), self.panic_reason.as_str()),
+        ];
+
+        if !overwrite {
+            if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists.", existing.display()),
+                ));
+            }
+        }
+        for (path, content) in files {
+            fs::write(path, content)?;
+        }
+        Ok(path)
+    }
+}
+
+/// E.g., `example.candy.panic` for `example.candy`.
+fn companion_file(program: &Path, extension: &str) -> PathBuf {
+    let mut path = program.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{input::Input, FailingFuzzCase};
+    use candy_frontend::{
+        hir::{Id, IdKey},
+        module::{Module, ModuleKind, Package, PackagesPath},
+    };
+    use candy_vm::{
+        heap::{Heap, InlineObject, Int, Text},
+        tracer::stack_trace::StackTracer,
+        Panic,
+    };
+    use std::{env, fs, io, path::Path, process};
+
+    fn module(path: &[&str]) -> Module {
+        module_in(&env::temp_dir().join("package"), path)
+    }
+    fn module_in(package: &Path, path: &[&str]) -> Module {
+        Module {
+            package: Package::User(package.to_path_buf()),
+            path: path.iter().map(ToString::to_string).collect(),
+            kind: ModuleKind::Code,
+        }
+    }
+    fn failing_case(
+        keys: Vec<IdKey>,
+        create_arguments: impl FnOnce(&mut Heap) -> Vec<InlineObject>,
+    ) -> FailingFuzzCase {
+        let mut heap = Heap::default();
+        let arguments = create_arguments(&mut heap);
+        FailingFuzzCase {
+            function: Id::new(module(&["foo"]), keys),
+            input: Input::new(arguments),
+            panic: Panic {
+                reason: "Oops.\nSomething went wrong.".to_string(),
+                responsible: Id::new(module(&["foo"]), vec!["bar".to_string().into()]),
+            },
+            heap,
+            tracer: StackTracer::default(),
+        }
+    }
+    /// The generated source without the comment at the beginning.
+    fn code(case: &FailingFuzzCase) -> String {
+        let test = case.to_regression_test(2).unwrap();
+        assert_eq!(test.module, module(&["fooFuzzCase2"]));
+        test.source
+            .lines()
+            .skip_while(|line| line.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn arguments_are_written_as_literals() {
+        let case = failing_case(vec!["bar".to_string().into()], |heap| {
+            vec![
+                Text::create(heap, true, "Say \"hi\"").into(),
+                Int::create(heap, true, -1).into(),
+            ]
+        });
+        assert_eq!(
+            code(&case),
+            "
+[int] = use \"Core\"
+[bar] = use \"..foo\"
+
+main := { environment ->
+  bar '\"Say \"hi\"\"' (int.negate 1)
+}",
+        );
+    }
+    #[test]
+    fn only_negative_ints_need_int_from_core() {
+        let case = failing_case(vec!["bar".to_string().into()], |heap| {
+            vec![Text::create(heap, true, "int.negate").into()]
+        });
+        assert!(!code(&case).contains("[int] = use \"Core\""));
+    }
+    #[test]
+    fn functions_without_parameters_are_run() {
+        let case = failing_case(vec!["bar".to_string().into()], |_| vec![]);
+        assert_eq!(
+            code(&case),
+            "
+[function] = use \"Core\"
+[bar] = use \"..foo\"
+
+main := { environment ->
+  bar | function.run
+}",
+        );
+    }
+    #[test]
+    fn nested_functions_cant_be_reproduced() {
+        let case = failing_case(
+            vec!["bar".to_string().into(), "baz".to_string().into()],
+            |_| vec![],
+        );
+        assert!(case.to_regression_test(0).is_none());
+    }
+    #[test]
+    fn tests_expect_the_panic_and_are_not_overwritten() {
+        let folder = env::temp_dir().join(format!("candy-regression-test-{}", process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let packages_path = PackagesPath::try_from(folder.as_path()).unwrap();
+        let mut case = failing_case(vec!["bar".to_string().into()], |_| vec![]);
+        case.function.module = module_in(&folder, &["foo"]);
+        let test = case.to_regression_test(0).unwrap();
+
+        let path = test.write(&packages_path, false).unwrap();
+        assert_eq!(path, folder.join("fooFuzzCase0.candy"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), test.source);
+        assert_eq!(
+            fs::read_to_string(folder.join("fooFuzzCase0.candy.stdout")).unwrap(),
+            "",
+        );
+        assert_eq!(
+            fs::read_to_string(folder.join("fooFuzzCase0.candy.panic")).unwrap(),
+            "Oops.\nSomething went wrong.",
+        );
+
+        fs::write(&path, "# Changed by hand.").unwrap();
+        let error = test.write(&packages_path, false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Changed by hand.");
+
+        test.write(&packages_path, true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), test.source);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
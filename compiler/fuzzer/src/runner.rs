@@ -97,6 +97,19 @@ impl<B: Borrow<ByteCode> + Clone> Runner<B> {
         }
     }
 
+    /// The memory used by the heap of the execution, or of its result if it's
+    /// finished.
+    #[must_use]
+    pub fn live_bytes(&self) -> usize {
+        match self.state.as_ref().unwrap() {
+            State::Running { heap, .. }
+            | State::Finished(RunResult::Done { heap, .. } | RunResult::Panicked { heap, .. }) => {
+                heap.live_bytes()
+            }
+            State::Finished(RunResult::Timeout | RunResult::NeedsUnfulfilled { .. }) => 0,
+        }
+    }
+
     pub fn run(&mut self, instructions_left: &mut usize) {
         let State::Running { mut heap, mut vm } = self.state.take().unwrap() else {
             panic!("Runner is not running anymore.");
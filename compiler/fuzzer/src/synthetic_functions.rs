@@ -0,0 +1,273 @@
+//! Fuzzed functions can take other functions as arguments, such as the
+//! function that `list.map` applies to each item. The fuzzer can't come up with
+//! arbitrary Candy code, so it passes synthetic functions instead. Their bodies
+//! are appended to the byte code and either return a captured value, return one
+//! of their arguments, or panic.
+//!
+//! Like the caller of a higher-order function, the fuzzer is responsible for
+//! panics of the functions it passes.
+
+use candy_vm::{
+    byte_code::{ByteCode, Instruction},
+    heap::{Function, Heap, InlineObject},
+    InstructionPointer,
+};
+
+/// Functions taking more arguments are rarely passed around.
+pub const MAX_ARGUMENT_COUNT: usize = 3;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Behavior {
+    /// Returns the only captured value.
+    ReturnCaptured,
+    /// Returns the argument at the given index.
+    ReturnArgument(usize),
+    /// Panics with the captured reason and responsible HIR ID.
+    Panic,
+}
+impl Behavior {
+    #[must_use]
+    pub const fn captured_count(self) -> usize {
+        match self {
+            Self::ReturnCaptured => 1,
+            Self::ReturnArgument(_) => 0,
+            Self::Panic => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SyntheticFunctions {
+    bodies: Vec<Body>,
+}
+#[derive(Debug)]
+struct Body {
+    argument_count: usize,
+    behavior: Behavior,
+    start: InstructionPointer,
+}
+
+impl SyntheticFunctions {
+    /// Appends the bodies of all synthetic functions to the byte code.
+    #[must_use]
+    pub fn add_to(byte_code: &mut ByteCode) -> Self {
+        let mut bodies = vec![];
+        for argument_count in 0..=MAX_ARGUMENT_COUNT {
+            let behaviors = [Behavior::ReturnCaptured, Behavior::Panic]
+                .into_iter()
+                .chain((0..argument_count).map(Behavior::ReturnArgument));
+            for behavior in behaviors {
+                let start = byte_code.append_instructions(compile_body(argument_count, behavior));
+                bodies.push(Body {
+                    argument_count,
+                    behavior,
+                    start,
+                });
+            }
+        }
+        Self { bodies }
+    }
+
+    /// Creates a function with the given behavior, taking ownership of the
+    /// captured values.
+    #[must_use]
+    pub fn create(
+        &self,
+        heap: &mut Heap,
+        argument_count: usize,
+        behavior: Behavior,
+        captured: &[InlineObject],
+    ) -> Function {
+        assert_eq!(captured.len(), behavior.captured_count());
+        let body = self
+            .bodies
+            .iter()
+            .find(|it| it.argument_count == argument_count && it.behavior == behavior)
+            .unwrap_or_else(|| {
+                panic!("No synthetic function `{behavior:?}` takes {argument_count} arguments.")
+            });
+        Function::create(heap, true, captured, argument_count, body.start)
+    }
+
+    /// Returns `None` for functions that aren't synthetic.
+    #[must_use]
+    pub fn behavior_of(&self, function: Function) -> Option<Behavior> {
+        self.bodies
+            .iter()
+            .find(|it| it.start == function.body())
+            .map(|it| it.behavior)
+    }
+}
+
+fn compile_body(argument_count: usize, behavior: Behavior) -> Vec<Instruction> {
+    // When the body starts, the stack contains the captured values, arguments,
+    // and the responsible HIR ID.
+    let stack_size = behavior.captured_count() + argument_count + 1;
+    let offset_of_captured = |index: usize| stack_size - 1 - index;
+    let offset_of_argument = |index: usize| argument_count - index;
+
+    if behavior == Behavior::Panic {
+        // Panics end the execution, so we don't have to drop anything.
+        return vec![
+            Instruction::PushFromStack(offset_of_captured(0)),
+            Instruction::PushFromStack(offset_of_captured(1) + 1),
+            Instruction::Panic,
+        ];
+    }
+
+    // The callee owns its arguments and captured values. The only captured
+    // value is returned, so we only have to drop the other arguments.
+    let mut instructions = vec![];
+    for index in 0..argument_count {
+        if behavior != Behavior::ReturnArgument(index) {
+            instructions.push(Instruction::PushFromStack(offset_of_argument(index)));
+            instructions.push(Instruction::Drop);
+        }
+    }
+    let returned = match behavior {
+        Behavior::ReturnCaptured => offset_of_captured(0),
+        Behavior::ReturnArgument(index) => offset_of_argument(index),
+        Behavior::Panic => unreachable!(),
+    };
+    instructions.push(Instruction::PushFromStack(returned));
+    instructions.push(Instruction::PopMultipleBelowTop(stack_size));
+    instructions.push(Instruction::Return);
+    instructions
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Behavior, SyntheticFunctions, MAX_ARGUMENT_COUNT};
+    use candy_frontend::test_database::Database;
+    use candy_frontend::{
+        hir::Id,
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        TracingConfig,
+    };
+    use candy_vm::{
+        byte_code::ByteCode,
+        heap::{Heap, HirId, InlineObject, Int, Text},
+        lir_to_byte_code::compile_byte_code,
+        tracer::DummyTracer,
+        Panic, Vm, VmFinished,
+    };
+
+    fn byte_code_with_synthetic_functions() -> (ByteCode, SyntheticFunctions) {
+        let mut db = Database::default();
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, b"".to_vec());
+        let (mut byte_code, _) =
+            compile_byte_code(&db, ExecutionTarget::Module(module), TracingConfig::off());
+        let synthetic_functions = SyntheticFunctions::add_to(&mut byte_code);
+        (byte_code, synthetic_functions)
+    }
+
+    /// Calls a synthetic function with the arguments `0`, `1`, etc. and checks
+    /// that it neither leaks objects nor frees objects it doesn't own.
+    fn call(
+        argument_count: usize,
+        behavior: Behavior,
+        create_captured: impl FnOnce(&mut Heap) -> Vec<InlineObject>,
+        check_result: impl FnOnce(&Result<InlineObject, Panic>),
+    ) {
+        let (byte_code, synthetic_functions) = byte_code_with_synthetic_functions();
+        let mut heap = Heap::default();
+        let objects_before = heap.iter().count();
+
+        let captured = create_captured(&mut heap);
+        let function = synthetic_functions.create(&mut heap, argument_count, behavior, &captured);
+        assert_eq!(synthetic_functions.behavior_of(function), Some(behavior));
+        let arguments = (0..argument_count)
+            .map(|index| Int::create(&mut heap, true, index).into())
+            .collect::<Vec<InlineObject>>();
+        let responsible = HirId::create(&mut heap, true, Id::fuzzer());
+        let VmFinished { result, .. } = Vm::for_function(
+            &byte_code,
+            &mut heap,
+            function,
+            &arguments,
+            responsible,
+            DummyTracer,
+        )
+        .with_checks()
+        .run_forever_without_handles(&mut heap);
+        check_result(&result);
+
+        // Calling the function consumed it, but we still own the responsible
+        // HIR ID.
+        if let Ok(return_value) = result {
+            return_value.drop(&mut heap);
+            InlineObject::from(responsible).drop(&mut heap);
+            assert_eq!(heap.iter().count(), objects_before, "Objects were leaked.");
+        }
+    }
+
+    #[test]
+    fn functions_return_the_captured_value() {
+        for argument_count in 0..=MAX_ARGUMENT_COUNT {
+            call(
+                argument_count,
+                Behavior::ReturnCaptured,
+                |heap| vec![Text::create(heap, true, "captured").into()],
+                |result| {
+                    let text = Text::try_from(*result.as_ref().unwrap()).unwrap();
+                    assert_eq!(text.get(), "captured");
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn functions_return_an_argument() {
+        for argument_count in 1..=MAX_ARGUMENT_COUNT {
+            for index in 0..argument_count {
+                call(
+                    argument_count,
+                    Behavior::ReturnArgument(index),
+                    |_| vec![],
+                    |result| {
+                        let int = Int::try_from(*result.as_ref().unwrap()).unwrap();
+                        assert_eq!(int.try_get::<usize>(), Some(index));
+                    },
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn functions_panic_with_the_captured_reason_and_responsibility() {
+        for argument_count in 0..=MAX_ARGUMENT_COUNT {
+            call(
+                argument_count,
+                Behavior::Panic,
+                |heap| {
+                    vec![
+                        Text::create(heap, true, "Oops.").into(),
+                        HirId::create(heap, true, Id::user()).into(),
+                    ]
+                },
+                |result| {
+                    let panic = result.as_ref().unwrap_err();
+                    assert_eq!(panic.reason, "Oops.");
+                    assert_eq!(panic.responsible, Id::user());
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn other_functions_are_not_synthetic() {
+        let (byte_code, synthetic_functions) = byte_code_with_synthetic_functions();
+        assert_eq!(
+            synthetic_functions.behavior_of(byte_code.module_function),
+            None,
+        );
+    }
+}
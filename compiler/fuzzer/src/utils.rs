@@ -1,6 +1,6 @@
 use candy_frontend::hir::Id;
 use candy_vm::{
-    heap::{Function, Heap, Tag, Text},
+    heap::{AddressMap, Function, Heap, Relocate, Tag, Text},
     tracer::Tracer,
 };
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -26,4 +26,10 @@ impl Tracer for FuzzablesFinder {
         function.dup();
         self.fuzzables.insert(definition.get().clone(), function);
     }
+
+    fn relocate(&mut self, address_map: &AddressMap) {
+        for function in self.fuzzables.values_mut() {
+            function.relocate(address_map);
+        }
+    }
 }
@@ -1,6 +1,7 @@
 use super::input::Input;
-use candy_frontend::builtin_functions;
-use candy_vm::heap::{Data, Heap, I64BitLength, InlineObject, Int, List, Struct, Tag, Text};
+use crate::synthetic_functions::{Behavior, SyntheticFunctions, MAX_ARGUMENT_COUNT};
+use candy_frontend::{builtin_functions, hir::Id};
+use candy_vm::heap::{Data, Heap, HirId, I64BitLength, InlineObject, Int, List, Struct, Tag, Text};
 use extension_trait::extension_trait;
 use itertools::Itertools;
 use num_bigint::RandBigInt;
@@ -13,19 +14,38 @@ use rustc_hash::FxHashMap;
 use std::collections::hash_map;
 
 impl Input {
-    pub fn generate(heap: &mut Heap, num_args: usize, symbols: &[Text]) -> Self {
+    pub fn generate(
+        heap: &mut Heap,
+        num_args: usize,
+        symbols: &[Text],
+        synthetic_functions: &SyntheticFunctions,
+    ) -> Self {
         let arguments = (0..num_args)
-            .map(|_| InlineObject::generate(heap, &mut rand::thread_rng(), 5.0, symbols))
+            .map(|_| {
+                InlineObject::generate(
+                    heap,
+                    &mut rand::thread_rng(),
+                    5.0,
+                    symbols,
+                    synthetic_functions,
+                )
+            })
             .collect();
         Self::new(arguments)
     }
-    pub fn mutated(&self, heap: &mut Heap, rng: &mut ThreadRng, symbols: &[Text]) -> Self {
+    pub fn mutated(
+        &self,
+        heap: &mut Heap,
+        rng: &mut ThreadRng,
+        symbols: &[Text],
+        synthetic_functions: &SyntheticFunctions,
+    ) -> Self {
         let mut arguments = self.arguments().to_owned();
 
         let index_to_mutate = rng.gen_range(0..arguments.len());
         for (index, argument) in arguments.iter_mut().enumerate() {
             if index == index_to_mutate {
-                *argument = argument.generate_mutated(heap, rng, symbols);
+                *argument = argument.generate_mutated(heap, rng, symbols, synthetic_functions);
             } else {
                 argument.dup(heap);
             }
@@ -47,13 +67,15 @@ impl InlineObjectGeneration for InlineObject {
         rng: &mut ThreadRng,
         mut complexity: f32,
         symbols: &[Text],
+        synthetic_functions: &SyntheticFunctions,
     ) -> InlineObject {
-        match rng.gen_range(1..=5) {
+        match rng.gen_range(1..=6) {
             1 => Int::create_from_bigint(heap, true, rng.gen_bigint(10)).into(),
             2 => Text::create(heap, true, "test").into(),
             3 => {
                 if rng.gen_bool(0.2) {
-                    let value = Self::generate(heap, rng, complexity - 10.0, symbols);
+                    let value =
+                        Self::generate(heap, rng, complexity - 10.0, symbols, synthetic_functions);
                     Tag::create_with_value(heap, true, *symbols.choose(rng).unwrap(), value).into()
                 } else {
                     let symbol = *symbols.choose(rng).unwrap();
@@ -65,7 +87,7 @@ impl InlineObjectGeneration for InlineObject {
                 complexity -= 1.0;
                 let mut items = vec![];
                 while complexity > 10.0 {
-                    let item = Self::generate(heap, rng, 10.0, symbols);
+                    let item = Self::generate(heap, rng, 10.0, symbols, synthetic_functions);
                     items.push(item);
                     complexity -= 10.0;
                 }
@@ -77,35 +99,80 @@ impl InlineObjectGeneration for InlineObject {
                 while complexity > 20.0 {
                     // Generate a key that is not already in the struct
                     let entry = loop {
-                        let key = Self::generate(heap, rng, 10.0, symbols);
+                        let key = Self::generate(heap, rng, 10.0, symbols, synthetic_functions);
                         match fields.entry(key) {
                             hash_map::Entry::Occupied(_) => key.drop(heap),
                             hash_map::Entry::Vacant(entry) => break entry,
                         }
                     };
 
-                    let value = Self::generate(heap, rng, 10.0, symbols);
+                    let value = Self::generate(heap, rng, 10.0, symbols, synthetic_functions);
                     entry.insert(value);
                     complexity -= 20.0;
                 }
                 Struct::create(heap, true, &fields).into()
             }
             6 => {
+                let argument_count = rng.gen_range(0..=MAX_ARGUMENT_COUNT);
+                Self::generate_synthetic_function(
+                    heap,
+                    rng,
+                    complexity,
+                    symbols,
+                    synthetic_functions,
+                    argument_count,
+                )
+            }
+            7 => {
                 // No `dup()` necessary since these are inline.
                 builtin_functions::VALUES[rng.gen_range(0..builtin_functions::VALUES.len())].into()
             }
             _ => unreachable!(),
         }
     }
+    fn generate_synthetic_function(
+        heap: &mut Heap,
+        rng: &mut ThreadRng,
+        complexity: f32,
+        symbols: &[Text],
+        synthetic_functions: &SyntheticFunctions,
+        argument_count: usize,
+    ) -> InlineObject {
+        let behavior = if rng.gen_bool(0.1) {
+            Behavior::Panic
+        } else if argument_count > 0 && rng.gen_bool(0.5) {
+            Behavior::ReturnArgument(rng.gen_range(0..argument_count))
+        } else {
+            Behavior::ReturnCaptured
+        };
+        let captured = match behavior {
+            Behavior::ReturnCaptured => vec![Self::generate(
+                heap,
+                rng,
+                complexity - 10.0,
+                symbols,
+                synthetic_functions,
+            )],
+            Behavior::ReturnArgument(_) => vec![],
+            Behavior::Panic => vec![
+                Text::create(heap, true, "A function passed by the fuzzer panicked.").into(),
+                HirId::create(heap, true, Id::fuzzer()).into(),
+            ],
+        };
+        synthetic_functions
+            .create(heap, argument_count, behavior, &captured)
+            .into()
+    }
     #[allow(clippy::too_many_lines)]
     fn generate_mutated(
         self,
         heap: &mut Heap,
         rng: &mut ThreadRng,
         symbols: &[Text],
+        synthetic_functions: &SyntheticFunctions,
     ) -> InlineObject {
         if rng.gen_bool(0.1) {
-            return Self::generate(heap, rng, 100.0, symbols);
+            return Self::generate(heap, rng, 100.0, symbols, synthetic_functions);
         }
 
         match self.into() {
@@ -133,7 +200,7 @@ impl InlineObjectGeneration for InlineObject {
                     tag.symbol().dup();
                     if rng.gen_bool(0.9) {
                         // Keep symbol, mutate value
-                        let value = value.generate_mutated(heap, rng, symbols);
+                        let value = value.generate_mutated(heap, rng, symbols, synthetic_functions);
                         Tag::create_with_value(heap, true, tag.symbol(), value).into()
                     } else {
                         // Keep symbol, remove value
@@ -142,7 +209,7 @@ impl InlineObjectGeneration for InlineObject {
                 } else {
                     // Keep symbol, add value
                     tag.symbol().dup();
-                    let value = Self::generate(heap, rng, 100.0, symbols);
+                    let value = Self::generate(heap, rng, 100.0, symbols, synthetic_functions);
                     Tag::create_with_value(heap, true, tag.symbol(), value).into()
                 }
             }
@@ -151,9 +218,12 @@ impl InlineObjectGeneration for InlineObject {
                 if len > 0 && rng.gen_bool(0.9) {
                     // Replace item
                     let index_to_mutate = rng.gen_range(0..len);
-                    let new_item = list
-                        .get(index_to_mutate)
-                        .generate_mutated(heap, rng, symbols);
+                    let new_item = list.get(index_to_mutate).generate_mutated(
+                        heap,
+                        rng,
+                        symbols,
+                        synthetic_functions,
+                    );
                     for (index, item) in list.items().iter().enumerate() {
                         if index != index_to_mutate {
                             item.dup(heap);
@@ -172,7 +242,7 @@ impl InlineObjectGeneration for InlineObject {
                     for item in list.items() {
                         item.dup(heap);
                     }
-                    let new_item = Self::generate(heap, rng, 100.0, symbols);
+                    let new_item = Self::generate(heap, rng, 100.0, symbols, synthetic_functions);
                     list.insert(heap, rng.gen_range(0..=len), new_item).into()
                 }
             }
@@ -189,8 +259,12 @@ impl InlineObjectGeneration for InlineObject {
                             value.dup(heap);
                         }
                     }
-                    let value =
-                        struct_.values()[index_to_mutate].generate_mutated(heap, rng, symbols);
+                    let value = struct_.values()[index_to_mutate].generate_mutated(
+                        heap,
+                        rng,
+                        symbols,
+                        synthetic_functions,
+                    );
                     struct_
                         .replace_at_index(heap, index_to_mutate, value)
                         .into()
@@ -209,14 +283,14 @@ impl InlineObjectGeneration for InlineObject {
 
                     // Generate a key that is not already in the struct
                     let key = loop {
-                        let key = Self::generate(heap, rng, 10.0, symbols);
+                        let key = Self::generate(heap, rng, 10.0, symbols, synthetic_functions);
                         if struct_.contains(key) {
                             key.drop(heap);
                         } else {
                             break key;
                         }
                     };
-                    let value = Self::generate(heap, rng, 100.0, symbols);
+                    let value = Self::generate(heap, rng, 100.0, symbols, synthetic_functions);
                     struct_.insert(heap, key, value).into()
                 }
             }
@@ -224,7 +298,39 @@ impl InlineObjectGeneration for InlineObject {
                 // No `dup()` necessary since these are inline.
                 (*builtin_functions::VALUES.choose(rng).unwrap()).into()
             }
-            Data::HirId(_) | Data::Function(_) | Data::Handle(_) => {
+            Data::Function(function) => {
+                let Some(behavior) = synthetic_functions.behavior_of(function) else {
+                    panic!("Couldn't have been created for fuzzing.");
+                };
+                if behavior == Behavior::ReturnCaptured && rng.gen_bool(0.5) {
+                    // Keep behavior, mutate returned value
+                    let value = function.captured()[0].generate_mutated(
+                        heap,
+                        rng,
+                        symbols,
+                        synthetic_functions,
+                    );
+                    synthetic_functions
+                        .create(heap, function.argument_count(), behavior, &[value])
+                        .into()
+                } else {
+                    // New behavior, maybe with a different number of arguments
+                    let argument_count = if rng.gen_bool(0.5) {
+                        function.argument_count()
+                    } else {
+                        rng.gen_range(0..=MAX_ARGUMENT_COUNT)
+                    };
+                    Self::generate_synthetic_function(
+                        heap,
+                        rng,
+                        100.0,
+                        symbols,
+                        synthetic_functions,
+                        argument_count,
+                    )
+                }
+            }
+            Data::HirId(_) | Data::Handle(_) => {
                 panic!("Couldn't have been created for fuzzing.")
             }
         }
@@ -257,7 +363,15 @@ impl InlineObjectGeneration for InlineObject {
                     .sum::<usize>()
                     + 1
             }
-            Data::HirId(_) | Data::Function(_) | Data::Builtin(_) | Data::Handle(_) => 1,
+            Data::Function(function) => {
+                function
+                    .captured()
+                    .iter()
+                    .map(|captured| captured.complexity())
+                    .sum::<usize>()
+                    + 1
+            }
+            Data::HirId(_) | Data::Builtin(_) | Data::Handle(_) => 1,
         }
     }
 }
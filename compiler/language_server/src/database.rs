@@ -1,5 +1,6 @@
 #[cfg(feature = "inkwell")]
 use candy_backend_inkwell::LlvmIrStorage;
+use candy_formatter::FormatterOptions;
 use candy_frontend::{
     ast::AstDbStorage,
     ast_to_hir::AstToHirStorage,
@@ -60,6 +61,7 @@ use candy_frontend::{
 pub struct Database {
     storage: salsa::Storage<Self>,
     pub packages_path: PackagesPath,
+    pub formatter_options: FormatterOptions,
     module_provider: OverlayModuleProvider<InMemoryModuleProvider, Box<dyn ModuleProvider + Send>>,
 }
 impl salsa::Database for Database {}
@@ -81,6 +83,7 @@ impl Database {
         Self {
             storage: salsa::Storage::default(),
             packages_path,
+            formatter_options: FormatterOptions::default(),
             module_provider: OverlayModuleProvider::new(
                 InMemoryModuleProvider::default(),
                 module_provider,
@@ -109,7 +109,7 @@ impl PausedState {
                     adapter_data: None,
                     checksums: None,
                 };
-                let range = db.hir_id_to_span(function).unwrap();
+                let range = db.hir_id_to_origin_span(function).unwrap();
                 let range = db.range_to_lsp_range(function.module.clone(), range);
                 let range = start_at_1_config.range_to_dap(range);
                 (function.function_name(), Some(source), Some(range))
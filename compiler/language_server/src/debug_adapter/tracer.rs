@@ -1,6 +1,6 @@
 use candy_frontend::hir::Id;
 use candy_vm::{
-    heap::{Heap, HirId, InlineObject},
+    heap::{AddressMap, Heap, HirId, InlineObject, Relocate},
     tracer::{stack_trace::Call, Tracer},
 };
 
@@ -59,4 +59,16 @@ impl Tracer for DebugTracer {
     fn call_ended(&mut self, heap: &mut Heap, _return_value: InlineObject) {
         self.call_stack.pop().unwrap().drop(heap);
     }
+
+    fn relocate(&mut self, address_map: &AddressMap) {
+        for (_, value) in &mut self.root_locals {
+            value.relocate(address_map);
+        }
+        for frame in &mut self.call_stack {
+            frame.call.relocate(address_map);
+            for (_, value) in &mut frame.locals {
+                value.relocate(address_map);
+            }
+        }
+    }
 }
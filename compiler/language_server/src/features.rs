@@ -1,7 +1,8 @@
 use crate::database::Database;
 use async_trait::async_trait;
 use lsp_types::{
-    self, FoldingRange, LocationLink, SemanticToken, TextDocumentContentChangeEvent, TextEdit, Url,
+    self, CodeActionKind, CodeLens, DocumentDiagnosticReport, FoldingRange, Hover, LocationLink,
+    SemanticToken, TextDocumentContentChangeEvent, TextEdit, Url,
 };
 use rustc_hash::FxHashMap;
 use std::collections::HashMap;
@@ -21,18 +22,21 @@ pub trait LanguageFeatures: Send + Sync {
     fn supports_did_open(&self) -> bool {
         false
     }
-    async fn did_open(&self, _db: &Mutex<Database>, _uri: Url, _content: Vec<u8>) {
+    async fn did_open(&self, _db: &Mutex<Database>, _uri: Url, _content: Vec<u8>, _version: i32) {
         unimplemented!()
     }
     fn supports_did_change(&self) -> bool {
         false
     }
+    /// Applies the changes to the document's content in the database. Fails if
+    /// the changes don't fit the current content.
     async fn did_change(
         &self,
         _db: &Mutex<Database>,
         _uri: Url,
         _changes: Vec<TextDocumentContentChangeEvent>,
-    ) {
+        _version: i32,
+    ) -> Result<(), DocumentOutOfSync> {
         unimplemented!()
     }
     fn supports_did_close(&self) -> bool {
@@ -42,6 +46,22 @@ pub trait LanguageFeatures: Send + Sync {
         unimplemented!()
     }
 
+    fn supports_pull_diagnostics(&self) -> bool {
+        false
+    }
+    /// The diagnostics for the given version of the document. Returns `None`
+    /// if they aren't available yet.
+    #[must_use]
+    async fn pull_diagnostics(
+        &self,
+        _db: &Mutex<Database>,
+        _uri: Url,
+        _version: Option<i32>,
+        _previous_result_id: Option<String>,
+    ) -> Option<DocumentDiagnosticReport> {
+        unimplemented!()
+    }
+
     fn supports_folding_ranges(&self) -> bool {
         false
     }
@@ -50,6 +70,14 @@ pub trait LanguageFeatures: Send + Sync {
         unimplemented!()
     }
 
+    fn supports_code_lenses(&self) -> bool {
+        false
+    }
+    #[must_use]
+    async fn code_lenses(&self, _db: &Mutex<Database>, _uri: Url) -> Vec<CodeLens> {
+        unimplemented!()
+    }
+
     fn supports_format(&self) -> bool {
         false
     }
@@ -58,6 +86,28 @@ pub trait LanguageFeatures: Send + Sync {
         unimplemented!()
     }
 
+    fn supports_organize_imports(&self) -> bool {
+        false
+    }
+    #[must_use]
+    async fn organize_imports(&self, _db: &Mutex<Database>, _uri: Url) -> Vec<TextEdit> {
+        unimplemented!()
+    }
+
+    fn supports_refactorings(&self) -> bool {
+        false
+    }
+    /// Refactorings that are available for the selected range.
+    #[must_use]
+    async fn refactorings(
+        &self,
+        _db: &Mutex<Database>,
+        _uri: Url,
+        _range: lsp_types::Range,
+    ) -> Vec<Refactoring> {
+        unimplemented!()
+    }
+
     fn supports_find_definition(&self) -> bool {
         false
     }
@@ -71,6 +121,19 @@ pub trait LanguageFeatures: Send + Sync {
         unimplemented!()
     }
 
+    fn supports_hover(&self) -> bool {
+        false
+    }
+    #[must_use]
+    async fn hover(
+        &self,
+        _db: &Mutex<Database>,
+        _uri: Url,
+        _position: lsp_types::Position,
+    ) -> Option<Hover> {
+        unimplemented!()
+    }
+
     fn supports_references(&self) -> bool {
         false
     }
@@ -110,6 +173,22 @@ pub trait LanguageFeatures: Send + Sync {
         unimplemented!()
     }
 
+    fn supports_rename_files(&self) -> bool {
+        false
+    }
+    /// Edits that keep references working when files are renamed. The client
+    /// applies them before renaming the files, so they refer to the old
+    /// locations.
+    #[must_use]
+    async fn will_rename_files(
+        &self,
+        _db: &Mutex<Database>,
+        _renames: Vec<(Url, Url)>,
+    ) -> HashMap<Url, Vec<TextEdit>> {
+        unimplemented!()
+    }
+    async fn did_rename_files(&self, _db: &Mutex<Database>, _renames: Vec<(Url, Url)>) {}
+
     fn supports_semantic_tokens(&self) -> bool {
         false
     }
@@ -119,6 +198,12 @@ pub trait LanguageFeatures: Send + Sync {
     }
 }
 
+pub struct Refactoring {
+    pub kind: CodeActionKind,
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
 pub struct Reference {
     pub range: lsp_types::Range,
     pub is_write: bool,
@@ -127,3 +212,10 @@ pub struct Reference {
 pub enum RenameError {
     NewNameInvalid,
 }
+
+/// The server's copy of a document diverged from the client's, so the client
+/// has to send the full content again.
+#[derive(Debug)]
+pub struct DocumentOutOfSync {
+    pub reason: String,
+}
@@ -0,0 +1,302 @@
+//! Code examples in doc comments are run so that the documentation doesn't
+//! silently go stale:
+//!
+//! ```candy
+//! double a :=
+//!   # Returns twice the value of `a`.
+//!   #
+//!   # ```candy
+//!   # double 2
+//!   # ```
+//!   int.multiply a 2
+//! ```
+//!
+//! Only code blocks marked as `candy` are checked. Unmarked blocks often
+//! contain pseudo-code such as `double 2 => 4`.
+//!
+//! Examples are evaluated in the context of their module, so they can use all
+//! of its definitions. An example fails if it doesn't compile or if it panics,
+//! e.g., because a pattern like `4 = double 2` doesn't match.
+//! Since every example compiles the module again, examples are checked one at
+//! a time and only while the user isn't typing.
+
+use super::insights::{ErrorDiagnostic, Insight};
+use crate::{database::Database, utils::LspPositionConversion};
+use candy_frontend::{
+    cst::{Cst, CstKind},
+    module::Module,
+    position::Offset,
+    rcst_to_cst::RcstToCst,
+};
+use candy_vm::{
+    eval::{eval_expression, ExecutionResult},
+    heap::Heap,
+};
+use lsp_types::Diagnostic;
+use std::ops::Range;
+
+pub struct DocExamples {
+    module: Module,
+    /// The examples that weren't checked yet, in reverse order.
+    unchecked: Vec<DocExample>,
+    diagnostics: Vec<Diagnostic>,
+}
+impl DocExamples {
+    /// Examples are meant to be short, so this only stops endless loops.
+    const INSTRUCTION_BUDGET: usize = 100_000;
+
+    pub fn for_module(db: &Database, module: Module) -> Self {
+        let mut unchecked = find_examples(db, &module);
+        unchecked.reverse();
+        Self {
+            module,
+            unchecked,
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.unchecked.is_empty()
+    }
+
+    /// Runs the next unchecked example and records a diagnostic if it fails.
+    ///
+    /// Evaluating an example temporarily changes the content of the module, so
+    /// the database shouldn't be the one used for analyzing the module.
+    pub fn check_next(&mut self, db: &mut Database) {
+        let Some(example) = self.unchecked.pop() else {
+            return;
+        };
+
+        let mut heap = Heap::default();
+        let result = eval_expression(
+            db,
+            &mut heap,
+            &example.source,
+            Some(&self.module),
+            Some(Self::INSTRUCTION_BUDGET),
+        );
+        let to_lsp_range = |span| db.range_to_lsp_range(self.module.clone(), span);
+        match result {
+            ExecutionResult::Value(_) => {}
+            ExecutionResult::CompilerErrors(errors) => {
+                self.diagnostics.extend(errors.into_iter().map(|error| {
+                    Diagnostic::error(
+                        to_lsp_range(example.map_span(&error.span)),
+                        format!("This example doesn't compile: {}", error.payload),
+                    )
+                }));
+            }
+            ExecutionResult::Panicked {
+                panic,
+                snippet_span,
+            } => {
+                let span =
+                    snippet_span.map_or_else(|| example.span(), |span| example.map_span(&span));
+                self.diagnostics.push(Diagnostic::error(
+                    to_lsp_range(span),
+                    format!("This example panics: {}", panic.reason),
+                ));
+            }
+            ExecutionResult::DidNotFinish => {
+                self.diagnostics.push(Diagnostic::warning(
+                    to_lsp_range(example.span()),
+                    format!(
+                        "This example didn't finish within {} instructions.",
+                        Self::INSTRUCTION_BUDGET,
+                    ),
+                ));
+            }
+        }
+    }
+
+    pub fn insights(&self) -> impl Iterator<Item = Insight> + '_ {
+        self.diagnostics.iter().cloned().map(Insight::Diagnostic)
+    }
+}
+
+/// The content of a `candy` code block in a doc comment.
+#[derive(Default)]
+struct DocExample {
+    source: String,
+    /// For each line, the offset where it starts in the module and in the
+    /// source.
+    line_starts: Vec<(Offset, usize)>,
+    /// Where the last line ends in the module.
+    end: Offset,
+}
+impl DocExample {
+    fn push_line(&mut self, start: Offset, line: &str) {
+        self.line_starts.push((start, self.source.len()));
+        self.source.push_str(line);
+        self.source.push('\n');
+        self.end = Offset(*start + line.len());
+    }
+
+    fn span(&self) -> Range<Offset> {
+        let start = self
+            .line_starts
+            .first()
+            .map_or(self.end, |(start, _)| *start);
+        start..self.end
+    }
+    fn map_span(&self, span: &Range<Offset>) -> Range<Offset> {
+        self.map_offset(span.start)..self.map_offset(span.end)
+    }
+    fn map_offset(&self, offset: Offset) -> Offset {
+        let index = self
+            .line_starts
+            .partition_point(|(_, in_source)| *in_source <= *offset)
+            .saturating_sub(1);
+        let (in_module, in_source) = self.line_starts[index];
+        Offset((*in_module + (*offset - in_source)).min(*self.end))
+    }
+}
+
+/// Finds the examples in the doc comments of the module and of all its
+/// assignments. See [`candy_frontend::comment::doc_comment_lines`] for where
+/// doc comments are.
+fn find_examples(db: &Database, module: &Module) -> Vec<DocExample> {
+    let Ok(cst) = db.cst(module.clone()) else {
+        return vec![];
+    };
+
+    let module_doc_comment = cst.iter().take_while(|it| {
+        matches!(
+            it.kind,
+            CstKind::Whitespace(_) | CstKind::Newline(_) | CstKind::Comment { .. }
+        )
+    });
+    let mut examples = examples_in_comments(module_doc_comment);
+    for it in cst.iter() {
+        add_examples_of_assignments(it, &mut examples);
+    }
+    examples
+}
+fn add_examples_of_assignments(cst: &Cst, examples: &mut Vec<DocExample>) {
+    if let CstKind::Assignment {
+        assignment_sign,
+        body,
+        ..
+    } = &cst.kind
+    {
+        let trivia = match &assignment_sign.kind {
+            CstKind::TrailingWhitespace { whitespace, .. } => whitespace.as_slice(),
+            _ => &[],
+        };
+        // The comment can also be part of the body.
+        let body_trivia = body.iter().take_while(|it| {
+            matches!(
+                it.kind,
+                CstKind::Whitespace(_) | CstKind::Newline(_) | CstKind::Comment { .. }
+            )
+        });
+        examples.extend(examples_in_comments(trivia.iter().chain(body_trivia)));
+    }
+    for child in cst.kind.children() {
+        add_examples_of_assignments(child, examples);
+    }
+}
+
+fn examples_in_comments<'a>(csts: impl IntoIterator<Item = &'a Cst>) -> Vec<DocExample> {
+    let lines = csts.into_iter().filter_map(|it| match &it.kind {
+        CstKind::Comment {
+            octothorpe,
+            comment,
+        } => {
+            let start = *octothorpe.data.span.end;
+            Some(
+                comment
+                    .strip_prefix(' ')
+                    .map_or((Offset(start), comment.as_str()), |line| {
+                        (Offset(start + 1), line)
+                    }),
+            )
+        }
+        _ => None,
+    });
+
+    let mut examples = vec![];
+    // The indentation of the opening fence of the current code block and, if
+    // it's a `candy` block, its content so far.
+    let mut current_block: Option<(usize, Option<DocExample>)> = None;
+    for (start, line) in lines {
+        let trimmed = line.trim_start();
+        let indentation = line.len() - trimmed.len();
+        if let Some(info) = trimmed.strip_prefix("```") {
+            if let Some((_, example)) = current_block.take() {
+                examples.extend(example);
+            } else {
+                let example = (info.trim() == "candy").then(DocExample::default);
+                current_block = Some((indentation, example));
+            }
+            continue;
+        }
+
+        if let Some((fence_indentation, Some(example))) = &mut current_block {
+            // Like in Markdown, the content of an indented block is unindented
+            // by the indentation of the fence.
+            let indentation = indentation.min(*fence_indentation);
+            example.push_line(Offset(*start + indentation), &line[indentation..]);
+        }
+    }
+    // An unclosed code block continues until the end of the comment.
+    if let Some((_, example)) = current_block {
+        examples.extend(example);
+    }
+
+    examples.retain(|it| !it.source.trim().is_empty());
+    examples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DocExamples;
+    use crate::database::Database;
+    use candy_frontend::module::{
+        InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+        PackagesPath,
+    };
+    use lsp_types::{Diagnostic, DiagnosticSeverity};
+    use std::path::Path;
+
+    fn check_examples(source: &str) -> Vec<Diagnostic> {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let mut db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+
+        let mut examples = DocExamples::for_module(&db, module);
+        while !examples.is_done() {
+            examples.check_next(&mut db);
+        }
+        examples.diagnostics
+    }
+
+    #[test]
+    fn correct_examples_produce_no_diagnostics() {
+        let diagnostics =
+            check_examples("identity a :=\n  # ```candy\n  # 2 = identity 2\n  # ```\n  a\n");
+        assert_eq!(diagnostics, vec![]);
+    }
+
+    #[test]
+    fn examples_with_a_wrong_result_produce_a_diagnostic() {
+        let diagnostics =
+            check_examples("identity a :=\n  # ```candy\n  # 3 = identity 2\n  # ```\n  a\n");
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        assert!(diagnostic.message.starts_with("This example panics"));
+        assert_eq!(diagnostic.range.start.line, 2);
+    }
+}
@@ -0,0 +1,257 @@
+//! Fuzzing never really finishes, so without limits, the analyzer would keep a
+//! CPU core busy for as long as the editor is open. The governor decides which
+//! module analyzers may continue:
+//!
+//! - While the user is typing, fuzzing is paused entirely. The results would be
+//!   outdated soon anyway and the CPU is better spent on compiling and
+//!   evaluating the latest version of the code.
+//! - Each module gets a budget of instructions and memory. Once it's used up,
+//!   fuzzing that module pauses until the module changes again.
+//!
+//! Compiling and evaluating modules is never throttled since hints and
+//! diagnostics depend on it. Doc examples are only checked while the user isn't
+//! typing, but they don't count towards the budget.
+
+use candy_frontend::module::Module;
+use rustc_hash::FxHashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct ResourceGovernor {
+    last_edit: Option<Instant>,
+    usages: FxHashMap<Module, Usage>,
+}
+#[derive(Clone, Copy, Debug, Default)]
+struct Usage {
+    /// Instructions executed since the module last changed.
+    instructions: usize,
+    /// The memory used by the module's analyzer after its last step.
+    live_bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Throttling {
+    None,
+    PausedWhileTyping,
+    InstructionBudgetExceeded,
+    MemoryBudgetExceeded,
+}
+
+impl ResourceGovernor {
+    /// How long after the last edit we consider the user to still be typing.
+    const TYPING_COOLDOWN: Duration = Duration::from_secs(2);
+    const INSTRUCTION_BUDGET_PER_MODULE: usize = 100_000_000;
+    const MEMORY_BUDGET_PER_MODULE: usize = 512 * 1024 * 1024;
+
+    pub fn module_changed(&mut self, module: &Module) {
+        self.last_edit = Some(Instant::now());
+        self.usages.remove(module);
+    }
+    pub fn module_closed(&mut self, module: &Module) {
+        self.usages.remove(module);
+    }
+
+    pub fn record_step(&mut self, module: Module, instructions: usize, live_bytes: usize) {
+        let usage = self.usages.entry(module).or_default();
+        usage.instructions += instructions;
+        usage.live_bytes = live_bytes;
+    }
+
+    #[must_use]
+    pub fn is_typing(&self) -> bool {
+        self.typing_cooldown_remaining().is_some()
+    }
+    /// How long until we no longer consider the user to be typing, or `None`
+    /// if they aren't.
+    #[must_use]
+    pub fn typing_cooldown_remaining(&self) -> Option<Duration> {
+        Self::TYPING_COOLDOWN
+            .checked_sub(self.last_edit?.elapsed())
+            .filter(|it| !it.is_zero())
+    }
+
+    /// Whether the analyzer of `module` may continue. Only fuzzing gets
+    /// throttled.
+    #[must_use]
+    pub fn throttling(&self, module: &Module, is_fuzzing: bool) -> Throttling {
+        if !is_fuzzing {
+            return Throttling::None;
+        }
+        if self.is_typing() {
+            return Throttling::PausedWhileTyping;
+        }
+
+        let usage = self.usages.get(module).copied().unwrap_or_default();
+        if usage.instructions >= Self::INSTRUCTION_BUDGET_PER_MODULE {
+            Throttling::InstructionBudgetExceeded
+        } else if usage.live_bytes >= Self::MEMORY_BUDGET_PER_MODULE {
+            Throttling::MemoryBudgetExceeded
+        } else {
+            Throttling::None
+        }
+    }
+
+    /// A status text explaining why the analyzers of the given modules are
+    /// paused.
+    #[must_use]
+    pub fn status(&self, throttled: &[(Module, Throttling)]) -> Option<String> {
+        if throttled
+            .iter()
+            .any(|(_, it)| *it == Throttling::PausedWhileTyping)
+        {
+            return Some("Fuzzing paused while typing".to_string());
+        }
+        let [(module, throttling)] = throttled else {
+            return (!throttled.is_empty()).then(|| {
+                format!(
+                    "Fuzzing paused for {} modules (budget exceeded)",
+                    throttled.len(),
+                )
+            });
+        };
+        let usage = self.usages.get(module).copied().unwrap_or_default();
+        Some(match throttling {
+            Throttling::None | Throttling::PausedWhileTyping => unreachable!(),
+            Throttling::InstructionBudgetExceeded => format!(
+                "Fuzzing of {module} paused after {} million instructions",
+                usage.instructions / 1_000_000,
+            ),
+            Throttling::MemoryBudgetExceeded => format!(
+                "Fuzzing of {module} paused after using {} MiB of memory",
+                usage.live_bytes / 1024 / 1024,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResourceGovernor, Throttling};
+    use candy_frontend::module::{Module, ModuleKind, Package};
+    use std::time::Instant;
+
+    fn module(name: &str) -> Module {
+        Module {
+            package: Package::User("/package".into()),
+            path: vec![name.to_string()],
+            kind: ModuleKind::Code,
+        }
+    }
+    /// A governor whose user stopped typing a while ago.
+    fn idle_governor() -> ResourceGovernor {
+        ResourceGovernor {
+            last_edit: Instant::now().checked_sub(ResourceGovernor::TYPING_COOLDOWN * 2),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn typing_cooldown_runs_out() {
+        let mut governor = ResourceGovernor::default();
+        assert_eq!(governor.typing_cooldown_remaining(), None);
+
+        governor.module_changed(&module("foo"));
+        let remaining = governor.typing_cooldown_remaining().unwrap();
+        assert!(remaining <= ResourceGovernor::TYPING_COOLDOWN);
+        assert!(governor.is_typing());
+
+        governor.last_edit = Instant::now().checked_sub(ResourceGovernor::TYPING_COOLDOWN);
+        assert_eq!(governor.typing_cooldown_remaining(), None);
+        assert!(!governor.is_typing());
+    }
+
+    #[test]
+    fn only_fuzzing_is_paused_while_typing() {
+        let mut governor = ResourceGovernor::default();
+        let foo = module("foo");
+        governor.module_changed(&foo);
+        assert_eq!(
+            governor.throttling(&foo, true),
+            Throttling::PausedWhileTyping
+        );
+        assert_eq!(governor.throttling(&foo, false), Throttling::None);
+        assert_eq!(
+            governor.status(&[(foo, Throttling::PausedWhileTyping)]),
+            Some("Fuzzing paused while typing".to_string()),
+        );
+    }
+
+    #[test]
+    fn exhausting_the_instruction_budget_pauses_fuzzing() {
+        let mut governor = idle_governor();
+        let foo = module("foo");
+        let bar = module("bar");
+        governor.record_step(
+            foo.clone(),
+            ResourceGovernor::INSTRUCTION_BUDGET_PER_MODULE - 1,
+            0,
+        );
+        assert_eq!(governor.throttling(&foo, true), Throttling::None);
+
+        governor.record_step(foo.clone(), 1, 0);
+        assert_eq!(
+            governor.throttling(&foo, true),
+            Throttling::InstructionBudgetExceeded,
+        );
+        assert_eq!(governor.throttling(&foo, false), Throttling::None);
+        // Budgets are per module.
+        assert_eq!(governor.throttling(&bar, true), Throttling::None);
+        assert_eq!(
+            governor.status(&[(foo, Throttling::InstructionBudgetExceeded)]),
+            Some(format!(
+                "Fuzzing of {} paused after 100 million instructions",
+                module("foo"),
+            )),
+        );
+    }
+
+    #[test]
+    fn exceeding_the_memory_budget_pauses_fuzzing() {
+        let mut governor = idle_governor();
+        let foo = module("foo");
+        governor.record_step(foo.clone(), 1, ResourceGovernor::MEMORY_BUDGET_PER_MODULE);
+        assert_eq!(
+            governor.throttling(&foo, true),
+            Throttling::MemoryBudgetExceeded,
+        );
+
+        // Only the memory used after the latest step counts.
+        governor.record_step(foo.clone(), 1, 0);
+        assert_eq!(governor.throttling(&foo, true), Throttling::None);
+    }
+
+    #[test]
+    fn changing_or_closing_a_module_resets_its_budget() {
+        let mut governor = idle_governor();
+        let foo = module("foo");
+        let exhaust = |governor: &mut ResourceGovernor| {
+            governor.record_step(
+                foo.clone(),
+                ResourceGovernor::INSTRUCTION_BUDGET_PER_MODULE,
+                0,
+            );
+        };
+
+        exhaust(&mut governor);
+        governor.module_changed(&foo);
+        governor.last_edit = None;
+        assert_eq!(governor.throttling(&foo, true), Throttling::None);
+
+        exhaust(&mut governor);
+        governor.module_closed(&foo);
+        assert_eq!(governor.throttling(&foo, true), Throttling::None);
+    }
+
+    #[test]
+    fn status_summarizes_multiple_throttled_modules() {
+        let governor = idle_governor();
+        assert_eq!(governor.status(&[]), None);
+        assert_eq!(
+            governor.status(&[
+                (module("foo"), Throttling::InstructionBudgetExceeded),
+                (module("bar"), Throttling::MemoryBudgetExceeded),
+            ]),
+            Some("Fuzzing paused for 2 modules (budget exceeded)".to_string()),
+        );
+    }
+}
@@ -1,15 +1,22 @@
 use super::utils::IdToEndOfLine;
-use crate::{database::Database, utils::LspPositionConversion};
+use crate::{
+    database::Database,
+    utils::{pluralize, LspPositionConversion},
+};
+use candy_formatter::SuspiciousLayout;
 use candy_frontend::{
     ast::{Assignment, AssignmentBody, AstDb, AstKind},
     ast_to_hir::AstToHir,
+    error::CompilerError,
     format::{MaxLength, Precedence},
     hir::{Expression, HirDb, Id},
+    lints::LintLevel,
     module::Module,
 };
 use candy_fuzzer::{Fuzzer, RunResult, Status};
 use candy_vm::{
     heap::{InlineObject, ToDebugText},
+    tracer::{cost::CostTracer, stack_trace::StackTracer},
     Panic,
 };
 use extension_trait::extension_trait;
@@ -37,6 +44,7 @@ pub enum HintKind {
     SampleInputReturningNormally,
     SampleInputPanickingWithCallerResponsible,
     SampleInputPanickingWithInternalCodeResponsible,
+    HotSpot,
 }
 
 impl Insight {
@@ -82,7 +90,7 @@ impl Insight {
             kind: HintKind::Value,
             position: db.id_to_end_of_line(id).unwrap(),
             text: if let Some(i) = text.find('\n') {
-                // TODO: Show all lines when hovering the hint
+                // Hovering shows the full value.
                 format!("{}...", &text[0..i])
             } else {
                 text
@@ -146,9 +154,116 @@ impl Insight {
         insights
     }
 
+    /// The largest parts of a fuzzed function that no input reached so far.
+    pub fn for_blind_spots(db: &Database, fuzzer: &Fuzzer) -> Vec<Self> {
+        const MAX_BLIND_SPOTS_PER_FUNCTION: usize = 3;
+
+        let function_name = fuzzer.function_id.function_name();
+        fuzzer
+            .blind_spots()
+            .into_iter()
+            .take(MAX_BLIND_SPOTS_PER_FUNCTION)
+            .filter_map(|blind_spot| {
+                let span = db.hir_id_to_display_span(&blind_spot.id)?;
+                Some(Self::Diagnostic(Diagnostic::information(
+                    db.range_to_lsp_range(blind_spot.id.module.clone(), span),
+                    format!(
+                        "The fuzzer could not reach this code of `{function_name}` ({}). An \
+                         example input could guide it here, or the code might be dead.",
+                        pluralize(blind_spot.num_instructions, "instruction"),
+                    ),
+                )))
+            })
+            .collect()
+    }
+
+    /// Call sites in the module that executed at least a tenth of all
+    /// instructions.
+    pub fn for_hot_spots(db: &Database, module: &Module, costs: &CostTracer) -> Vec<Self> {
+        const MIN_SHARE: f64 = 0.1;
+
+        #[allow(clippy::cast_precision_loss)]
+        let all_instructions = costs.instructions().max(1) as f64;
+        costs
+            .sorted_costs()
+            .into_iter()
+            .filter(|(id, _)| &id.module == module)
+            .filter_map(|(id, cost)| {
+                #[allow(clippy::cast_precision_loss)]
+                let share = cost.total_instructions as f64 / all_instructions;
+                if share < MIN_SHARE {
+                    return None;
+                }
+
+                let calls = if cost.calls == 1 {
+                    "1 call".to_string()
+                } else {
+                    format!("{} calls", cost.calls)
+                };
+                Some(Self::Hint(Hint {
+                    kind: HintKind::HotSpot,
+                    position: db.id_to_end_of_line(id.clone())?,
+                    text: format!(
+                        "🔥 {:.0} % of instructions ({calls}, {} instructions)",
+                        100. * share,
+                        cost.total_instructions,
+                    ),
+                }))
+            })
+            .collect()
+    }
+
+    /// Points at the innermost call in the module that was still running when
+    /// the evaluation was stopped. For endless recursions, that's usually the
+    /// recursive call.
+    pub fn for_unfinished_evaluation(
+        db: &Database,
+        module: &Module,
+        stack_tracer: &StackTracer,
+        instructions: usize,
+    ) -> Self {
+        let message =
+            format!("Module evaluation did not finish within {instructions} instructions.");
+        let call_span = stack_tracer
+            .call_stack
+            .iter()
+            .rev()
+            .map(|call| call.call_site.get())
+            .filter(|id| &id.module == module)
+            .find_map(|id| db.hir_id_to_display_span(id));
+        Self::Diagnostic(match call_span {
+            Some(span) => Diagnostic::warning(
+                db.range_to_lsp_range(module.clone(), span),
+                format!("{message} This call was still running and might never return."),
+            ),
+            None => Diagnostic::warning(Range::default(), message),
+        })
+    }
+
+    pub fn for_lint(db: &Database, lint: &CompilerError, level: LintLevel) -> Option<Self> {
+        let span = db.range_to_lsp_range(lint.module.clone(), lint.span.clone());
+        let message = lint.payload.to_string();
+        match level {
+            LintLevel::Allow => None,
+            LintLevel::Warn => Some(Self::Diagnostic(Diagnostic::warning(span, message))),
+            LintLevel::Deny => Some(Self::Diagnostic(Diagnostic::error(span, message))),
+        }
+    }
+
+    pub fn for_suspicious_layout(
+        db: &Database,
+        module: Module,
+        suspicious_layout: &SuspiciousLayout,
+    ) -> Self {
+        Self::Diagnostic(Diagnostic::warning(
+            db.range_to_lsp_range(module, suspicious_layout.span.clone()),
+            suspicious_layout.kind.to_string(),
+        ))
+    }
+
     pub fn for_static_panic(db: &Database, module: Module, panic: &Panic) -> Self {
         let call_span = db
-            .hir_id_to_display_span(&panic.responsible)
+            .hir_id_to_origin_display_span(&panic.responsible)
             .unwrap_or_else(|| panic!("Can't resolve responsible ID for panic: {:?}", panic));
         let call_span = db.range_to_lsp_range(module, call_span);
 
@@ -162,9 +277,18 @@ impl Insight {
 #[extension_trait]
 pub impl ErrorDiagnostic for Diagnostic {
     fn error(range: Range, message: String) -> Self {
+        Self::with_severity(range, DiagnosticSeverity::ERROR, message)
+    }
+    fn warning(range: Range, message: String) -> Self {
+        Self::with_severity(range, DiagnosticSeverity::WARNING, message)
+    }
+    fn information(range: Range, message: String) -> Self {
+        Self::with_severity(range, DiagnosticSeverity::INFORMATION, message)
+    }
+    fn with_severity(range: Range, severity: DiagnosticSeverity, message: String) -> Self {
         Self {
             range,
-            severity: Some(DiagnosticSeverity::ERROR),
+            severity: Some(severity),
             code: None,
             code_description: None,
             source: Some("🍭 Candy".to_owned()),
@@ -175,3 +299,64 @@ pub impl ErrorDiagnostic for Diagnostic {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Insight;
+    use crate::database::Database;
+    use candy_frontend::{
+        hir_to_mir::ExecutionTarget,
+        module::{
+            InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+            PackagesPath,
+        },
+        TracingConfig, TracingMode,
+    };
+    use candy_vm::{
+        heap::Heap, lir_to_byte_code::compile_byte_code, tracer::stack_trace::StackTracer, Vm,
+        VmEvent,
+    };
+    use lsp_types::Position;
+    use std::path::Path;
+
+    #[test]
+    fn unfinished_evaluations_point_at_the_innermost_running_call() {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let mut db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, b"recurse f := f f\nx = recurse recurse\n".to_vec());
+
+        let tracing = TracingConfig {
+            register_fuzzables: TracingMode::Off,
+            calls: TracingMode::OnlyCurrent,
+            evaluated_expressions: TracingMode::Off,
+        };
+        let (byte_code, _) =
+            compile_byte_code(&db, ExecutionTarget::Module(module.clone()), tracing);
+        let mut heap = Heap::default();
+        let vm = Vm::for_module(&byte_code, &mut heap, StackTracer::with_max_depth(100));
+        let VmEvent::BudgetExhausted(vm) = vm.run_until_event(&mut heap, &mut 10_000) else {
+            panic!("The recursion should never finish.");
+        };
+
+        let Insight::Diagnostic(diagnostic) =
+            Insight::for_unfinished_evaluation(&db, &module, vm.tracer(), 10_000)
+        else {
+            panic!("Unfinished evaluations are reported as diagnostics.");
+        };
+        assert_eq!(diagnostic.range.start, Position::new(0, 13));
+        assert_eq!(
+            diagnostic.message,
+            "Module evaluation did not finish within 10000 instructions. This call was still running and might never return.",
+        );
+    }
+}
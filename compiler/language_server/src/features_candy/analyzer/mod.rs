@@ -7,27 +7,52 @@
 //! displayed over time.
 //!
 //! While doing all that, we can pause regularly between executing instructions
-//! so that we don't occupy a single CPU at 100 %.
+//! so that we don't occupy a single CPU at 100 %. The [`ResourceGovernor`]
+//! additionally pauses fuzzing while the user is typing and once a module used
+//! up its budget. When there's nothing left to do, the server waits for the
+//! next message instead of polling for it.
+//!
+//! Besides the analyzers, the server checks the code examples in doc comments
+//! (see [`DocExamples`]).
 
 use self::{
+    doc_examples::DocExamples,
+    governor::{ResourceGovernor, Throttling},
     insights::{Hint, Insight},
     module_analyzer::ModuleAnalyzer,
 };
 use super::AnalyzerClient;
-use crate::database::Database;
-use candy_frontend::module::{Module, MutableModuleProviderOwner, PackagesPath};
+use crate::{database::Database, utils::module_to_url};
+use candy_frontend::{
+    error::{LimitedErrors, DEFAULT_MAX_REPORTED_ERRORS},
+    hir,
+    module::{Module, MutableModuleProviderOwner, PackagesPath},
+    utils::DoHash,
+};
 use itertools::{Either, Itertools};
-use lsp_types::{notification::Notification, Url};
+use lsp_types::{
+    notification::Notification, Diagnostic, DiagnosticRelatedInformation, DocumentDiagnosticReport,
+    FullDocumentDiagnosticReport, Location, Range, RelatedFullDocumentDiagnosticReport,
+    RelatedUnchangedDocumentDiagnosticReport, UnchangedDocumentDiagnosticReport, Url,
+};
 use rand::{seq::IteratorRandom, thread_rng};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
-use std::{fmt, future::Future, time::Duration, vec};
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+    vec,
+};
 use tokio::{
     sync::mpsc::{self, error::TryRecvError},
-    time::sleep,
+    time::timeout,
 };
 use tracing::debug;
 
+mod doc_examples;
+mod governor;
 pub mod insights;
 mod module_analyzer;
 mod static_panics;
@@ -35,7 +60,8 @@ mod utils;
 
 #[derive(Debug)]
 pub enum Message {
-    UpdateModule(Module, Vec<u8>),
+    /// The new content of the module and, if it's open, its document version.
+    UpdateModule(Module, Vec<u8>, Option<i32>),
     CloseModule(Module),
     Shutdown,
 }
@@ -51,43 +77,230 @@ impl Notification for HintsNotification {
     type Params = Self;
 }
 
+/// The textual representations of the values that the analyzer evaluated so
+/// far. The analyzer runs on its own thread with its own database, so this is
+/// how other features (e.g., hovering) get access to its results.
+#[derive(Clone, Debug, Default)]
+pub struct EvaluatedValues(Arc<Mutex<FxHashMap<Module, FxHashMap<hir::Id, String>>>>);
+impl EvaluatedValues {
+    pub(super) fn update(&self, module: Module, values: FxHashMap<hir::Id, String>) {
+        self.0.lock().unwrap().insert(module, values);
+    }
+    fn remove(&self, module: &Module) {
+        self.0.lock().unwrap().remove(module);
+    }
+
+    #[must_use]
+    pub fn get(&self, id: &hir::Id) -> Option<String> {
+        self.0.lock().unwrap().get(&id.module)?.get(id).cloned()
+    }
+}
+
+/// The diagnostics that the analyzer reported last for each module. Clients
+/// that support the pull model request them via `textDocument/diagnostic`
+/// instead of receiving them as notifications.
+#[derive(Clone, Debug, Default)]
+pub struct ReportedDiagnostics(Arc<Mutex<ReportedDiagnosticsInner>>);
+#[derive(Debug, Default)]
+struct ReportedDiagnosticsInner {
+    /// `None` if diagnostics are pushed to the client.
+    pull_model: Option<PullModel>,
+    next_result_id: u64,
+    snapshots: FxHashMap<Module, DiagnosticsSnapshot>,
+}
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PullModel {
+    /// Whether we can ask the client to pull diagnostics again once they
+    /// changed.
+    pub supports_refresh: bool,
+}
+#[derive(Clone, Debug)]
+struct DiagnosticsSnapshot {
+    /// Changes whenever the diagnostics of the module change.
+    result_id: String,
+    /// The version of the document that the diagnostics belong to, or `None`
+    /// if the module isn't open.
+    version: Option<i32>,
+    diagnostics: Vec<Diagnostic>,
+}
+impl ReportedDiagnostics {
+    pub fn use_pull_model(&self, pull_model: PullModel) {
+        self.0.lock().unwrap().pull_model = Some(pull_model);
+    }
+    #[must_use]
+    pub fn pull_model(&self) -> Option<PullModel> {
+        self.0.lock().unwrap().pull_model
+    }
+
+    /// Stores the new diagnostics and returns the pull model if the client
+    /// doesn't want them to be pushed.
+    #[must_use]
+    pub fn update(
+        &self,
+        module: Module,
+        version: Option<i32>,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Option<PullModel> {
+        let mut inner = self.0.lock().unwrap();
+        let result_id = inner.next_result_id.to_string();
+        inner.next_result_id += 1;
+        inner.snapshots.insert(
+            module,
+            DiagnosticsSnapshot {
+                result_id,
+                version,
+                diagnostics,
+            },
+        );
+        inner.pull_model
+    }
+    pub fn remove(&self, module: &Module) {
+        self.0.lock().unwrap().snapshots.remove(module);
+    }
+
+    /// The diagnostics for the given version of the module's document. If the
+    /// client already has them, the report only says that they're unchanged.
+    ///
+    /// Returns `None` if the analyzer didn't look at this version yet and we
+    /// can ask the client to pull again later. Without refresh support, the
+    /// diagnostics of the previous version are returned instead.
+    #[must_use]
+    pub fn report(
+        &self,
+        module: &Module,
+        version: Option<i32>,
+        previous_result_id: Option<&str>,
+    ) -> Option<DocumentDiagnosticReport> {
+        let (snapshot, supports_refresh) = {
+            let inner = self.0.lock().unwrap();
+            (
+                inner.snapshots.get(module).cloned(),
+                inner.pull_model.is_some_and(|it| it.supports_refresh),
+            )
+        };
+        let Some(snapshot) = snapshot else {
+            return Some(DocumentDiagnosticReport::Full(
+                RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: None,
+                        items: vec![],
+                    },
+                },
+            ));
+        };
+        let is_outdated = version.is_some() && snapshot.version != version;
+        if is_outdated && supports_refresh {
+            return None;
+        }
+
+        Some(if previous_result_id == Some(snapshot.result_id.as_str()) {
+            DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id: snapshot.result_id,
+                },
+            })
+        } else {
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(snapshot.result_id),
+                    items: snapshot.diagnostics,
+                },
+            })
+        })
+    }
+}
+
+/// How long the server waits for messages between two analysis steps so that
+/// it doesn't occupy a whole CPU core.
+const PAUSE_BETWEEN_STEPS: Duration = Duration::from_millis(100);
+
 #[tokio::main(worker_threads = 1)]
-#[allow(clippy::needless_pass_by_value, unused_must_use)]
+#[allow(clippy::needless_pass_by_value)]
 pub async fn run_server(
     packages_path: PackagesPath,
     mut incoming_events: mpsc::Receiver<Message>,
     client: AnalyzerClient,
+    evaluated_values: EvaluatedValues,
 ) {
-    let mut db = Database::new_with_file_system_module_provider(packages_path);
+    let mut db = Database::new_with_file_system_module_provider(packages_path.clone());
+    // Doc examples are compiled as part of their module, so they're evaluated
+    // in a separate database. Otherwise, each example would invalidate the
+    // queries of the module it belongs to.
+    let mut examples_db = Database::new_with_file_system_module_provider(packages_path);
     let mut analyzers: FxHashMap<Module, ModuleAnalyzer> = FxHashMap::default();
+    let mut doc_examples: FxHashMap<Module, DocExamples> = FxHashMap::default();
+    let mut governor = ResourceGovernor::default();
+    // Diagnostics are reported together with the document version they belong
+    // to.
+    let mut document_versions: FxHashMap<Module, Option<i32>> = FxHashMap::default();
+    // The status we reported while no analyzer was allowed to run. Analyzers
+    // report their own status, so this is reset whenever one of them runs.
+    let mut throttling_status = None;
     let client_ref = &client;
-    let mut outgoing_diagnostics = OutgoingCache::new(move |module, diagnostics| {
-        client_ref.update_diagnostics(module, diagnostics)
+    let mut outgoing_diagnostics = OutgoingCache::new(move |module, (version, diagnostics)| {
+        client_ref.update_diagnostics(module, version, diagnostics)
     });
     let mut outgoing_hints =
         OutgoingCache::new(move |module, hints| client_ref.update_hints(module, hints));
 
+    // How long to wait for messages before the next step, or `None` if only a
+    // message can create more work.
+    let mut pause = None;
     'server_loop: loop {
-        sleep(Duration::from_millis(100)).await;
+        let first_event = match pause {
+            Some(pause) => timeout(pause, incoming_events.recv()).await.ok(),
+            None => Some(incoming_events.recv().await),
+        };
+        let mut first_event = match first_event {
+            Some(Some(event)) => Some(event),
+            Some(None) => break 'server_loop,
+            None => None,
+        };
+        pause = Some(PAUSE_BETWEEN_STEPS);
 
+        let mut changed_modules = FxHashSet::default();
         loop {
-            let event = match incoming_events.try_recv() {
+            let event = match first_event
+                .take()
+                .map_or_else(|| incoming_events.try_recv(), Ok)
+            {
                 Ok(event) => event,
                 Err(TryRecvError::Empty) => break,
                 Err(TryRecvError::Disconnected) => break 'server_loop,
             };
             match event {
-                Message::UpdateModule(module, content) => {
-                    db.did_change_module(&module, content);
+                Message::UpdateModule(module, content, version) => {
+                    if let Some(analyzer) = analyzers.get(&module) {
+                        analyzer.save_corpus(&db.packages_path);
+                    }
+                    db.did_change_module(&module, content.clone());
+                    examples_db.did_change_module(&module, content);
+                    document_versions.insert(module.clone(), version);
+                    governor.module_changed(&module);
                     outgoing_hints.send(module.clone(), vec![]).await;
                     analyzers
                         .entry(module.clone())
                         .and_modify(ModuleAnalyzer::module_changed)
                         .or_insert_with(|| ModuleAnalyzer::for_module(module.clone()));
+                    doc_examples
+                        .insert(module.clone(), DocExamples::for_module(&db, module.clone()));
+                    changed_modules.insert(module);
                 }
                 Message::CloseModule(module) => {
                     db.did_close_module(&module);
-                    analyzers.remove(&module);
+                    examples_db.did_close_module(&module);
+                    governor.module_closed(&module);
+                    if let Some(analyzer) = analyzers.remove(&module) {
+                        analyzer.save_corpus(&db.packages_path);
+                    }
+                    doc_examples.remove(&module);
+                    changed_modules.remove(&module);
+                    evaluated_values.remove(&module);
+                    document_versions.remove(&module);
+                    client.forget_diagnostics(&module);
                 }
                 Message::Shutdown => {
                     incoming_events.close();
@@ -95,25 +308,187 @@ pub async fn run_server(
             }
         }
 
-        let Some(module) = analyzers.keys().choose(&mut thread_rng()).cloned() else {
-            client.update_status(None);
+        // Parse and lowering errors of changed modules are published right
+        // away instead of waiting for the next analysis step of that module.
+        for module in changed_modules {
+            let insights = module_insights(&db, &analyzers[&module], &doc_examples[&module]);
+            let (diagnostics, _) = split_insights(&db, &module, insights);
+            let version = document_versions.get(&module).copied().flatten();
+            outgoing_diagnostics
+                .send(module, (version, diagnostics))
+                .await;
+        }
+
+        // Each check compiles the module again, so we check only one example
+        // per iteration and wait until the user stopped typing.
+        if !governor.is_typing()
+            && let Some((module, examples)) =
+                doc_examples.iter_mut().find(|(_, it)| !it.is_done())
+        {
+            examples.check_next(&mut examples_db);
+            let insights = module_insights(&db, &analyzers[module], examples);
+            let (diagnostics, _) = split_insights(&db, module, insights);
+            let version = document_versions.get(module).copied().flatten();
+            outgoing_diagnostics
+                .send(module.clone(), (version, diagnostics))
+                .await;
+        }
+
+        let (runnable, throttled): (Vec<_>, Vec<_>) = analyzers
+            .iter()
+            .filter(|(_, analyzer)| !analyzer.is_idle())
+            .map(|(module, analyzer)| {
+                let throttling = governor.throttling(module, analyzer.is_fuzzing());
+                (module.clone(), throttling)
+            })
+            .partition(|(_, throttling)| *throttling == Throttling::None);
+        let Some((module, _)) = runnable.into_iter().choose(&mut thread_rng()) else {
+            let status = governor.status(&throttled);
+            if throttling_status.as_ref() != Some(&status) {
+                client.update_status(status.clone()).await;
+                throttling_status = Some(status);
+            }
+
+            let has_unchecked_examples = doc_examples.values().any(|it| !it.is_done());
+            let is_paused_while_typing = throttled
+                .iter()
+                .any(|(_, it)| *it == Throttling::PausedWhileTyping);
+            let typing_cooldown = governor.typing_cooldown_remaining();
+            pause = if has_unchecked_examples || is_paused_while_typing {
+                // Doc examples and fuzzing continue once the user stops typing.
+                Some(typing_cooldown.unwrap_or(PAUSE_BETWEEN_STEPS))
+            } else {
+                // Modules that used up their budget wait for a change.
+                None
+            };
             continue;
         };
+        throttling_status = None;
         let analyzer = analyzers.get_mut(&module).unwrap();
 
-        analyzer.run(&db, &client).await;
+        let instructions = analyzer.run(&db, &client).await;
+        governor.record_step(module.clone(), instructions, analyzer.live_bytes());
 
-        let insights = analyzer.insights(&db);
-        let (diagnostics, mut hints): (Vec<_>, Vec<_>) =
-            insights.into_iter().partition_map(|it| match it {
-                Insight::Diagnostic(diagnostic) => Either::Left(diagnostic),
-                Insight::Hint(hint) => Either::Right(hint),
-            });
-        hints.sort_by_key(|hint| hint.position);
+        evaluated_values.update(module.clone(), analyzer.evaluated_value_texts());
 
-        outgoing_diagnostics.send(module.clone(), diagnostics).await;
+        let insights = module_insights(&db, analyzer, &doc_examples[&module]);
+        let (diagnostics, hints) = split_insights(&db, &module, insights);
+        let version = document_versions.get(&module).copied().flatten();
+        outgoing_diagnostics
+            .send(module.clone(), (version, diagnostics))
+            .await;
         outgoing_hints.send(module, hints).await;
     }
+
+    for analyzer in analyzers.values() {
+        analyzer.save_corpus(&db.packages_path);
+    }
+}
+
+fn module_insights(
+    db: &Database,
+    analyzer: &ModuleAnalyzer,
+    doc_examples: &DocExamples,
+) -> Vec<Insight> {
+    let mut insights = analyzer.insights(db);
+    insights.extend(doc_examples.insights());
+    insights
+}
+
+/// Each analysis step reports all diagnostics known so far, replacing the ones
+/// reported by the previous step. To avoid flickering, diagnostics are sorted
+/// and get an ID derived from their content. That way, a diagnostic that's
+/// still present after a step is reported identically.
+fn split_insights(
+    db: &Database,
+    module: &Module,
+    insights: Vec<Insight>,
+) -> (Vec<Diagnostic>, Vec<Hint>) {
+    let (mut diagnostics, mut hints): (Vec<_>, Vec<_>) =
+        insights.into_iter().partition_map(|it| match it {
+            Insight::Diagnostic(diagnostic) => Either::Left(diagnostic),
+            Insight::Hint(hint) => Either::Right(hint),
+        });
+
+    for diagnostic in &mut diagnostics {
+        let Range { start, end } = diagnostic.range;
+        let id = (
+            start.line,
+            start.character,
+            end.line,
+            end.character,
+            &diagnostic.message,
+        )
+            .do_hash();
+        diagnostic.data = Some(format!("{id:016x}").into());
+    }
+    diagnostics.sort_by(|a, b| {
+        let key = |it: &Diagnostic| {
+            (
+                it.range.start.line,
+                it.range.start.character,
+                it.range.end.line,
+                it.range.end.character,
+            )
+        };
+        key(a).cmp(&key(b)).then_with(|| a.message.cmp(&b.message))
+    });
+    diagnostics.dedup();
+    let diagnostics = limit_diagnostics(db, module, diagnostics);
+
+    hints.sort_by_key(|hint| hint.position);
+    (diagnostics, hints)
+}
+/// After a syntax error, the parser often reports the same error over and
+/// over. We merge such repetitions into the first diagnostic and report at
+/// most [`DEFAULT_MAX_REPORTED_ERRORS`] diagnostics. The last one mentions how
+/// many were omitted.
+fn limit_diagnostics(
+    db: &Database,
+    module: &Module,
+    diagnostics: Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    let limited = LimitedErrors::new(diagnostics, DEFAULT_MAX_REPORTED_ERRORS, |last, next| {
+        let is_adjacent_or_overlapping = next.range.start <= last.range.end;
+        next.message == last.message && next.severity == last.severity && is_adjacent_or_overlapping
+    });
+    let mut diagnostics = limited
+        .groups
+        .into_iter()
+        .map(|group| {
+            let mut diagnostic = group.first;
+            if let Some(last) = group.repetitions.last() {
+                diagnostic.range.end = diagnostic.range.end.max(last.range.end);
+                match group.repetitions.len() {
+                    1 => diagnostic.message.push_str(" (repeated once)"),
+                    n => diagnostic
+                        .message
+                        .push_str(&format!(" (repeated {n} times)")),
+                }
+            }
+            diagnostic
+        })
+        .collect_vec();
+
+    if limited.num_omitted > 0
+        && let Some(last) = diagnostics.last_mut()
+    {
+        let message = format!("{} more diagnostics are not shown.", limited.num_omitted);
+        match module_to_url(module, &db.packages_path) {
+            Some(uri) => last
+                .related_information
+                .get_or_insert_with(Vec::new)
+                .push(DiagnosticRelatedInformation {
+                    location: Location {
+                        uri,
+                        range: last.range,
+                    },
+                    message,
+                }),
+            None => last.message.push_str(&format!("\n{message}")),
+        }
+    }
+    diagnostics
 }
 
 struct OutgoingCache<T, R: Fn(Module, T) -> F, F: Future> {
@@ -136,3 +511,133 @@ impl<T: Clone + fmt::Debug + Eq, R: Fn(Module, T) -> F, F: Future> OutgoingCache
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{insights::Insight, split_insights, PullModel, ReportedDiagnostics};
+    use crate::database::Database;
+    use candy_frontend::module::{
+        InMemoryModuleProvider, Module, ModuleKind, Package, PackagesPath,
+    };
+    use itertools::Itertools;
+    use lsp_types::{Diagnostic, DocumentDiagnosticReport, Position, Range};
+    use std::path::Path;
+
+    fn diagnostic(line: u32, message: &str) -> Insight {
+        Insight::Diagnostic(Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 3)),
+            message: message.to_string(),
+            ..Default::default()
+        })
+    }
+    fn split_diagnostics(insights: Vec<Insight>) -> Vec<Diagnostic> {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        split_insights(&db, &module(), insights).0
+    }
+    fn module() -> Module {
+        Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        }
+    }
+
+    #[test]
+    fn diagnostics_are_sorted_and_deduplicated() {
+        let diagnostics = split_diagnostics(vec![
+            diagnostic(2, "b"),
+            diagnostic(1, "c"),
+            diagnostic(2, "a"),
+            diagnostic(1, "c"),
+        ]);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|it| it.message.as_str())
+                .collect_vec(),
+            ["c", "a", "b"],
+        );
+    }
+
+    #[test]
+    fn diagnostic_ids_only_depend_on_the_content() {
+        let first = split_diagnostics(vec![diagnostic(1, "a"), diagnostic(2, "b")]);
+        let second = split_diagnostics(vec![diagnostic(2, "b"), diagnostic(3, "c")]);
+        assert!(first[0].data.is_some());
+        assert_eq!(first[1], second[0]);
+        assert_ne!(first[0].data, first[1].data);
+    }
+
+    /// The result ID and the diagnostics' messages of a full report, or `None`
+    /// for unchanged reports.
+    fn full_report(report: DocumentDiagnosticReport) -> Option<(Option<String>, Vec<String>)> {
+        match report {
+            DocumentDiagnosticReport::Full(report) => {
+                let report = report.full_document_diagnostic_report;
+                Some((
+                    report.result_id,
+                    report.items.into_iter().map(|it| it.message).collect(),
+                ))
+            }
+            DocumentDiagnosticReport::Unchanged(_) => None,
+        }
+    }
+    fn diagnostics(message: &str) -> Vec<Diagnostic> {
+        vec![Diagnostic {
+            message: message.to_string(),
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn unknown_modules_have_no_diagnostics() {
+        let reported = ReportedDiagnostics::default();
+        let report = reported.report(&module(), Some(1), None).unwrap();
+        assert_eq!(full_report(report), Some((None, vec![])));
+    }
+
+    #[test]
+    fn unchanged_diagnostics_are_not_sent_again() {
+        let reported = ReportedDiagnostics::default();
+        let _ = reported.update(module(), Some(1), diagnostics("a"));
+        let report = reported.report(&module(), Some(1), None).unwrap();
+        let Some((Some(result_id), messages)) = full_report(report) else {
+            panic!("The first report should be full.");
+        };
+        assert_eq!(messages, ["a"]);
+
+        let report = reported.report(&module(), Some(1), Some(&result_id));
+        assert_eq!(full_report(report.unwrap()), None);
+
+        let _ = reported.update(module(), Some(1), diagnostics("b"));
+        let report = reported.report(&module(), Some(1), Some(&result_id));
+        let Some((Some(new_result_id), messages)) = full_report(report.unwrap()) else {
+            panic!("Changed diagnostics should be reported in full.");
+        };
+        assert_ne!(new_result_id, result_id);
+        assert_eq!(messages, ["b"]);
+    }
+
+    #[test]
+    fn outdated_diagnostics_are_only_reported_without_refresh_support() {
+        let reported = ReportedDiagnostics::default();
+        let _ = reported.update(module(), Some(1), diagnostics("a"));
+
+        reported.use_pull_model(PullModel {
+            supports_refresh: true,
+        });
+        assert!(reported.report(&module(), Some(2), None).is_none());
+
+        reported.use_pull_model(PullModel {
+            supports_refresh: false,
+        });
+        let report = reported.report(&module(), Some(2), None).unwrap();
+        assert_eq!(full_report(report).unwrap().1, ["a"]);
+    }
+}
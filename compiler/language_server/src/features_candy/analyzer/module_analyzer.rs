@@ -1,31 +1,40 @@
 use super::{insights::Insight, static_panics::StaticPanicsOfMir};
 use crate::{
-    database::Database, features_candy::analyzer::insights::ErrorDiagnostic,
-    server::AnalyzerClient, utils::LspPositionConversion,
+    database::Database,
+    features_candy::analyzer::insights::ErrorDiagnostic,
+    server::AnalyzerClient,
+    utils::{error_to_diagnostic, module_to_url, LspPositionConversion},
 };
+use candy_formatter::find_suspicious_layouts;
 use candy_frontend::{
     ast_to_hir::AstToHir,
-    format::{MaxLength, Precedence},
-    hir_to_mir::ExecutionTarget,
+    format::{MaxLength, NestingLimits, Precedence},
+    hir,
+    hir_to_mir::{ExecutionTarget, HirToMir},
+    lints::{Lint, LintLevelResolver},
     mir_optimize::OptimizeMir,
-    module::Module,
+    module::{Module, PackagesPath},
+    rcst_to_cst::RcstToCst,
     TracingConfig, TracingMode,
 };
-use candy_fuzzer::{FuzzablesFinder, Fuzzer, Status};
+use candy_fuzzer::{
+    group_by_panic, Corpus, CorpusContent, FuzzablesFinder, Fuzzer, FuzzerPool, Status,
+    SyntheticFunctions,
+};
 use candy_vm::{
     byte_code::ByteCode,
-    environment::StateAfterRunWithoutHandles,
+    environment::{EmptyEnvironment, Environment},
     heap::{Heap, ToDebugText},
     lir_to_byte_code::compile_byte_code,
-    tracer::{evaluated_values::EvaluatedValuesTracer, stack_trace::StackTracer},
-    Panic, Vm, VmFinished,
+    tracer::{cost::CostTracer, evaluated_values::EvaluatedValuesTracer, stack_trace::StackTracer},
+    Panic, Vm, VmEvent,
 };
 use extension_trait::extension_trait;
 use itertools::Itertools;
-use lsp_types::Diagnostic;
-use rand::{prelude::SliceRandom, thread_rng};
+use lsp_types::{Diagnostic, DiagnosticRelatedInformation, Location};
+use rustc_hash::FxHashMap;
 use std::rc::Rc;
-use tracing::debug;
+use tracing::{debug, warn};
 
 /// A hints finder is responsible for finding hints for a single module.
 pub struct ModuleAnalyzer {
@@ -34,13 +43,24 @@ pub struct ModuleAnalyzer {
 }
 enum State {
     Initial,
-    /// First, we run the module with tracing of evaluated expressions enabled.
-    /// This enables us to show hints for constants.
+    /// First, we run the module with tracing of evaluated expressions and
+    /// calls enabled. This enables us to show hints for constants and for the
+    /// calls that take most of the time.
     EvaluateConstants {
         static_panics: Vec<Panic>,
         byte_code: Rc<ByteCode>,
         heap: Heap,
-        vm: Vm<Rc<ByteCode>, (StackTracer, EvaluatedValuesTracer)>,
+        vm: Vm<Rc<ByteCode>, (StackTracer, EvaluatedValuesTracer, CostTracer)>,
+        /// Executed so far.
+        instructions: usize,
+    },
+    /// The module didn't finish within the evaluation budget, e.g., because of
+    /// an endless recursion. We keep the VM to report where it got stuck and
+    /// the values evaluated until then.
+    EvaluationDidNotFinish {
+        static_panics: Vec<Panic>,
+        heap: Heap,
+        vm: Vm<Rc<ByteCode>, (StackTracer, EvaluatedValuesTracer, CostTracer)>,
     },
     /// Next, we run the module again to finds fuzzable functions. This time, we
     /// disable tracing of evaluated expressions, but we enable registration of
@@ -54,7 +74,9 @@ enum State {
         /// since objects in `evaluated_values` refer to it.
         evaluated_values_byte_code: Rc<ByteCode>,
         evaluated_values: EvaluatedValuesTracer,
+        costs: CostTracer,
         byte_code: Rc<ByteCode>,
+        synthetic_functions: Rc<SyntheticFunctions>,
         heap: Heap,
         vm: Vm<Rc<ByteCode>, FuzzablesFinder>,
     },
@@ -66,12 +88,45 @@ enum State {
         stack_tracer: StackTracer,
         evaluated_values_byte_code: Rc<ByteCode>,
         evaluated_values: EvaluatedValuesTracer,
+        costs: CostTracer,
         heap_for_fuzzables: Heap,
-        fuzzers: Vec<Fuzzer>,
+        fuzzers: FuzzerPool,
     },
 }
 
 impl ModuleAnalyzer {
+    const TRACING_FOR_STATIC_ANALYSIS: TracingConfig = TracingConfig {
+        register_fuzzables: TracingMode::OnlyCurrent,
+        calls: TracingMode::Off,
+        evaluated_expressions: TracingMode::Off,
+    };
+    /// Calls in the current module are traced so that panics caused by
+    /// another function can be traced back to the call in the fuzzed function
+    /// (see [`panic_propagation_path`]).
+    const TRACING_FOR_FUZZING: TracingConfig = TracingConfig {
+        register_fuzzables: TracingMode::OnlyCurrent,
+        calls: TracingMode::OnlyCurrent,
+        evaluated_expressions: TracingMode::Off,
+    };
+    /// How many instructions a single call to [`Self::run`] executes at most.
+    const INSTRUCTIONS_PER_STEP: usize = 500;
+    /// Evaluating a module should be quick, so we stop after this many
+    /// instructions instead of spinning forever.
+    const EVALUATION_BUDGET: usize = 1_000_000;
+    /// Within the evaluation budget, an endless recursion can nest many calls.
+    /// We only keep the innermost ones since those are the ones we report.
+    const MAX_TRACED_CALL_DEPTH: usize = 1000;
+    /// Evaluating a module can allocate and free lots of objects, so we compact
+    /// the heap once more than half of its memory got freed.
+    const HEAP_COMPACTION_THRESHOLD: f64 = 0.5;
+    /// For debugging memory growth: After evaluating a module, log which
+    /// instructions allocated the objects that are still alive, e.g., because
+    /// tracers keep them.
+    const TRACK_ALLOCATION_SITES: bool = false;
+    /// How many instructions a fuzzer runs before we report the code it
+    /// couldn't reach.
+    const BLIND_SPOTS_AFTER_INSTRUCTIONS: usize = 100_000;
+
     pub const fn for_module(module: Module) -> Self {
         Self {
             module,
@@ -83,10 +138,57 @@ impl ModuleAnalyzer {
         self.state = Some(State::Initial);
     }
 
-    pub async fn run(&mut self, db: &Database, client: &AnalyzerClient) {
+    /// Advances the analysis by one step and returns the number of
+    /// instructions that were executed (approximately).
+    pub async fn run(&mut self, db: &Database, client: &AnalyzerClient) -> usize {
         let state = self.state.take().unwrap();
+        let instructions = match &state {
+            State::Initial | State::EvaluationDidNotFinish { .. } => 0,
+            State::Fuzz { fuzzers, .. } if fuzzers.is_done() => 0,
+            _ => Self::INSTRUCTIONS_PER_STEP,
+        };
         let state = self.update_state(db, client, state).await;
         self.state = Some(state);
+        instructions
+    }
+
+    /// Whether the analyzer finished evaluating the module and is now fuzzing
+    /// its functions.
+    pub fn is_fuzzing(&self) -> bool {
+        matches!(self.state.as_ref().unwrap(), State::Fuzz { fuzzers, .. } if !fuzzers.is_done())
+    }
+    /// Whether running the analyzer wouldn't find anything new until the
+    /// module changes.
+    pub fn is_idle(&self) -> bool {
+        match self.state.as_ref().unwrap() {
+            State::EvaluationDidNotFinish { .. } => true,
+            State::Fuzz { fuzzers, .. } => fuzzers.is_done(),
+            _ => false,
+        }
+    }
+    /// The memory used by all heaps of this analyzer.
+    pub fn live_bytes(&self) -> usize {
+        match self.state.as_ref().unwrap() {
+            State::Initial => 0,
+            State::EvaluateConstants { heap, .. } | State::EvaluationDidNotFinish { heap, .. } => {
+                heap.live_bytes()
+            }
+            State::FindFuzzables {
+                heap_for_constants,
+                heap,
+                ..
+            } => heap_for_constants.live_bytes() + heap.live_bytes(),
+            State::Fuzz {
+                heap_for_constants,
+                heap_for_fuzzables,
+                fuzzers,
+                ..
+            } => {
+                heap_for_constants.live_bytes()
+                    + heap_for_fuzzables.live_bytes()
+                    + fuzzers.live_bytes()
+            }
+        }
     }
     async fn update_state(&self, db: &Database, client: &AnalyzerClient, state: State) -> State {
         match state {
@@ -98,11 +200,7 @@ impl ModuleAnalyzer {
                 let (mir, _, _) = db
                     .optimized_mir(
                         ExecutionTarget::Module(self.module.clone()),
-                        TracingConfig {
-                            register_fuzzables: TracingMode::OnlyCurrent,
-                            calls: TracingMode::Off,
-                            evaluated_expressions: TracingMode::Off,
-                        },
+                        Self::TRACING_FOR_STATIC_ANALYSIS,
                     )
                     .unwrap();
                 let mut mir = (*mir).clone();
@@ -111,7 +209,7 @@ impl ModuleAnalyzer {
 
                 let tracing = TracingConfig {
                     register_fuzzables: TracingMode::Off,
-                    calls: TracingMode::Off,
+                    calls: TracingMode::OnlyCurrent,
                     evaluated_expressions: TracingMode::OnlyCurrent,
                 };
                 let (byte_code, _) =
@@ -119,49 +217,83 @@ impl ModuleAnalyzer {
                 let byte_code = Rc::new(byte_code);
 
                 let mut heap = Heap::default();
+                // The analysis runs in short slices to keep the server
+                // responsive, so dropping huge values shouldn't block it.
+                heap.limit_objects_freed_at_once(10_000);
+                if Self::TRACK_ALLOCATION_SITES {
+                    heap.track_allocation_sites();
+                }
                 let tracer = (
-                    StackTracer::default(),
+                    StackTracer::with_max_depth(Self::MAX_TRACED_CALL_DEPTH),
                     EvaluatedValuesTracer::new(self.module.clone()),
+                    CostTracer::default(),
                 );
-                let vm = Vm::for_module(byte_code.clone(), &mut heap, tracer);
+                let vm = Vm::for_module(byte_code.clone(), &mut heap, tracer)
+                    .with_automatic_heap_compaction(Self::HEAP_COMPACTION_THRESHOLD);
 
                 State::EvaluateConstants {
                     static_panics,
                     byte_code,
                     heap,
                     vm,
+                    instructions: 0,
                 }
             }
             State::EvaluateConstants {
                 static_panics,
                 byte_code,
                 heap: mut heap_for_constants,
-                vm,
+                mut vm,
+                instructions,
             } => {
                 client
                     .update_status(Some(format!("Evaluating {}", self.module)))
                     .await;
 
-                let tracer = match vm.run_n_without_handles(&mut heap_for_constants, 500) {
-                    StateAfterRunWithoutHandles::Running(vm) => {
-                        return State::EvaluateConstants {
-                            static_panics,
-                            byte_code,
-                            heap: heap_for_constants,
-                            vm,
+                let mut instructions_left = Self::INSTRUCTIONS_PER_STEP;
+                let tracer = loop {
+                    match vm.run_until_event(&mut heap_for_constants, &mut instructions_left) {
+                        VmEvent::BudgetExhausted(vm) => {
+                            let instructions = instructions + Self::INSTRUCTIONS_PER_STEP;
+                            return if instructions < Self::EVALUATION_BUDGET {
+                                State::EvaluateConstants {
+                                    static_panics,
+                                    byte_code,
+                                    heap: heap_for_constants,
+                                    vm,
+                                    instructions,
+                                }
+                            } else {
+                                State::EvaluationDidNotFinish {
+                                    static_panics,
+                                    heap: heap_for_constants,
+                                    vm,
+                                }
+                            };
+                        }
+                        // Modules are evaluated without an environment.
+                        VmEvent::HandleCall(call) => {
+                            vm = EmptyEnvironment.handle(&mut heap_for_constants, call);
+                        }
+                        VmEvent::Finished { tracer, .. } | VmEvent::Panicked { tracer, .. } => {
+                            break tracer;
                         }
                     }
-                    StateAfterRunWithoutHandles::Finished(VmFinished { tracer, .. }) => tracer,
                 };
-                let (stack_tracer, evaluated_values) = tracer;
+                let (stack_tracer, evaluated_values, costs) = tracer;
+                if Self::TRACK_ALLOCATION_SITES {
+                    debug!(
+                        "Live objects after evaluating {}:\n{}",
+                        self.module,
+                        format_allocation_sites(&heap_for_constants, &byte_code),
+                    );
+                }
 
-                let tracing = TracingConfig {
-                    register_fuzzables: TracingMode::OnlyCurrent,
-                    calls: TracingMode::Off,
-                    evaluated_expressions: TracingMode::Off,
-                };
-                let (fuzzing_byte_code, _) =
+                let tracing = Self::TRACING_FOR_FUZZING;
+                let (mut fuzzing_byte_code, _) =
                     compile_byte_code(db, ExecutionTarget::Module(self.module.clone()), tracing);
+                let synthetic_functions =
+                    Rc::new(SyntheticFunctions::add_to(&mut fuzzing_byte_code));
                 let fuzzing_byte_code = Rc::new(fuzzing_byte_code);
 
                 let mut heap = Heap::default();
@@ -176,48 +308,70 @@ impl ModuleAnalyzer {
                     stack_tracer,
                     evaluated_values_byte_code: byte_code,
                     evaluated_values,
+                    costs,
                     byte_code: fuzzing_byte_code,
+                    synthetic_functions,
                     heap,
                     vm,
                 }
             }
+            state @ State::EvaluationDidNotFinish { .. } => {
+                client.update_status(None).await;
+                state
+            }
             State::FindFuzzables {
                 static_panics,
                 heap_for_constants,
                 stack_tracer,
                 evaluated_values_byte_code,
                 evaluated_values,
+                costs,
                 byte_code,
+                synthetic_functions,
                 mut heap,
-                vm,
+                mut vm,
             } => {
                 client
                     .update_status(Some(format!("Evaluating {}", self.module)))
                     .await;
 
-                let (heap, tracer) = match vm.run_n_without_handles(&mut heap, 500) {
-                    StateAfterRunWithoutHandles::Running(vm) => {
-                        return State::FindFuzzables {
-                            static_panics,
-                            heap_for_constants,
-                            stack_tracer,
-                            evaluated_values_byte_code,
-                            evaluated_values,
-                            byte_code,
-                            heap,
-                            vm,
+                let mut instructions_left = Self::INSTRUCTIONS_PER_STEP;
+                let tracer = loop {
+                    match vm.run_until_event(&mut heap, &mut instructions_left) {
+                        VmEvent::BudgetExhausted(vm) => {
+                            return State::FindFuzzables {
+                                static_panics,
+                                heap_for_constants,
+                                stack_tracer,
+                                evaluated_values_byte_code,
+                                evaluated_values,
+                                costs,
+                                byte_code,
+                                synthetic_functions,
+                                heap,
+                                vm,
+                            };
+                        }
+                        VmEvent::HandleCall(call) => vm = EmptyEnvironment.handle(&mut heap, call),
+                        VmEvent::Finished { tracer, .. } | VmEvent::Panicked { tracer, .. } => {
+                            break tracer;
                         }
-                    }
-                    StateAfterRunWithoutHandles::Finished(VmFinished { tracer, .. }) => {
-                        (heap, tracer)
                     }
                 };
 
-                let fuzzers = tracer
-                    .fuzzables
-                    .iter()
-                    .map(|(id, function)| Fuzzer::new(byte_code.clone(), *function, id.clone()))
-                    .collect();
+                let corpus = Corpus::for_module(&self.module, &db.packages_path)
+                    .and_then(|corpus| corpus.load().ok())
+                    .unwrap_or_default();
+                let fuzzers = FuzzerPool::new(tracer.fuzzables.iter().map(|(id, function)| {
+                    Fuzzer::new(
+                        byte_code.clone(),
+                        synthetic_functions.clone(),
+                        *function,
+                        id.clone(),
+                    )
+                    .with_known_panics(static_panics.iter().map(|panic| panic.responsible.clone()))
+                    .with_corpus(&corpus)
+                }));
                 State::Fuzz {
                     byte_code,
                     static_panics,
@@ -225,6 +379,7 @@ impl ModuleAnalyzer {
                     stack_tracer,
                     evaluated_values_byte_code,
                     evaluated_values,
+                    costs,
                     heap_for_fuzzables: heap,
                     fuzzers,
                 }
@@ -236,14 +391,11 @@ impl ModuleAnalyzer {
                 stack_tracer,
                 evaluated_values_byte_code,
                 evaluated_values,
+                costs,
                 heap_for_fuzzables,
                 mut fuzzers,
             } => {
-                let mut running_fuzzers = fuzzers
-                    .iter_mut()
-                    .filter(|fuzzer| matches!(fuzzer.status(), Status::StillFuzzing { .. }))
-                    .collect_vec();
-                let Some(fuzzer) = running_fuzzers.choose_mut(&mut thread_rng()) else {
+                let Some(fuzzer) = fuzzers.next_fuzzer() else {
                     client.update_status(None).await;
                     return State::Fuzz {
                         byte_code,
@@ -252,6 +404,7 @@ impl ModuleAnalyzer {
                         stack_tracer,
                         evaluated_values_byte_code,
                         evaluated_values,
+                        costs,
                         heap_for_fuzzables,
                         fuzzers,
                     };
@@ -261,7 +414,7 @@ impl ModuleAnalyzer {
                     .update_status(Some(format!("Fuzzing {}", fuzzer.function_id)))
                     .await;
 
-                fuzzer.run(500);
+                fuzzers.run_next(Self::INSTRUCTIONS_PER_STEP);
 
                 State::Fuzz {
                     byte_code,
@@ -270,6 +423,7 @@ impl ModuleAnalyzer {
                     stack_tracer,
                     evaluated_values_byte_code,
                     evaluated_values,
+                    costs,
                     heap_for_fuzzables,
                     fuzzers,
                 }
@@ -277,18 +431,131 @@ impl ModuleAnalyzer {
         }
     }
 
+    /// Stores the interesting inputs found by the fuzzers in the module's
+    /// corpus so that later sessions and `candy fuzz` can start with them.
+    pub fn save_corpus(&self, packages_path: &PackagesPath) {
+        let State::Fuzz { fuzzers, .. } = self.state.as_ref().unwrap() else {
+            return;
+        };
+        let Some(corpus) = Corpus::for_module(&self.module, packages_path) else {
+            return;
+        };
+        let mut content = CorpusContent::default();
+        for fuzzer in fuzzers.fuzzers() {
+            fuzzer.add_to_corpus(&mut content);
+        }
+        if let Err(error) = corpus.save(content) {
+            warn!(
+                "Couldn't save the fuzzing corpus of {}: {error}",
+                self.module
+            );
+        }
+    }
+
+    /// The values of the module's expressions that are known so far, formatted
+    /// as Candy code.
+    pub fn evaluated_value_texts(&self) -> FxHashMap<hir::Id, String> {
+        let evaluated_values = match self.state.as_ref().unwrap() {
+            State::Initial | State::EvaluateConstants { .. } => return FxHashMap::default(),
+            State::EvaluationDidNotFinish { vm, .. } => &vm.tracer().1,
+            State::FindFuzzables {
+                evaluated_values, ..
+            }
+            | State::Fuzz {
+                evaluated_values, ..
+            } => evaluated_values,
+        };
+        evaluated_values
+            .values()
+            .iter()
+            .map(|(id, value)| {
+                let text = value.to_debug_text_with_limits(
+                    Precedence::Low,
+                    NestingLimits::MAX_LENGTH_FOR_MESSAGES,
+                    NestingLimits::FOR_MESSAGES,
+                );
+                (id.clone(), text)
+            })
+            .collect()
+    }
+
     pub fn insights(&self, db: &Database) -> Vec<Insight> {
         let mut insights = vec![];
 
-        match self.state.as_ref().unwrap() {
+        let state = self.state.as_ref().unwrap();
+        // Parse and lowering errors are known as soon as the frontend is done,
+        // so we report them before the module got optimized and analyzed. Once
+        // that happened, they're reported as static panics instead and the
+        // optimized MIR also contains lints. Both are cached, so this doesn't
+        // compile the module again.
+        let target = ExecutionTarget::Module(self.module.clone());
+        let errors = if matches!(state, State::Initial) {
+            db.mir(target, Self::TRACING_FOR_STATIC_ANALYSIS)
+                .map(|(_, errors)| errors)
+        } else {
+            db.optimized_mir(target, Self::TRACING_FOR_STATIC_ANALYSIS)
+                .map(|(_, _, errors)| errors)
+        };
+        if let Ok(errors) = errors {
+            if matches!(state, State::Initial) {
+                insights.extend(
+                    errors
+                        .iter()
+                        .filter(|it| it.module == self.module && Lint::of(&it.payload).is_none())
+                        .map(|it| {
+                            Insight::Diagnostic(error_to_diagnostic(db, self.module.clone(), it))
+                        }),
+                );
+            }
+            let lint_levels = LintLevelResolver::for_package(db, self.module.package.clone());
+            insights.extend(
+                errors
+                    .iter()
+                    .filter(|it| it.module == self.module)
+                    .filter_map(|it| {
+                        let level = lint_levels.level_of(&it.payload)?;
+                        Insight::for_lint(db, it, level)
+                    }),
+            );
+            if let Ok(csts) = db.cst(self.module.clone()) {
+                let source = csts.iter().join("");
+                insights.extend(
+                    find_suspicious_layouts(&source, &csts)
+                        .iter()
+                        .map(|it| Insight::for_suspicious_layout(db, self.module.clone(), it)),
+                );
+            }
+        }
+
+        match state {
             State::Initial => {}
             State::EvaluateConstants { static_panics, .. } => {
                 // TODO: Show incremental constant evaluation hints.
                 insights.extend(static_panics.to_insights(db, &self.module));
             }
+            State::EvaluationDidNotFinish {
+                static_panics, vm, ..
+            } => {
+                let (stack_tracer, evaluated_values, costs) = vm.tracer();
+                insights.extend(static_panics.to_insights(db, &self.module));
+                insights.extend(
+                    evaluated_values
+                        .values()
+                        .iter()
+                        .filter_map(|(id, value)| Insight::for_value(db, id.clone(), *value)),
+                );
+                insights.extend(Insight::for_hot_spots(db, &self.module, costs));
+                insights.push(Insight::for_unfinished_evaluation(
+                    db,
+                    &self.module,
+                    stack_tracer,
+                    Self::EVALUATION_BUDGET,
+                ));
+            }
             State::FindFuzzables {
                 static_panics,
                 evaluated_values,
+                costs,
                 ..
             } => {
                 insights.extend(static_panics.to_insights(db, &self.module));
@@ -298,10 +565,12 @@ impl ModuleAnalyzer {
                         .iter()
                         .filter_map(|(id, value)| Insight::for_value(db, id.clone(), *value)),
                 );
+                insights.extend(Insight::for_hot_spots(db, &self.module, costs));
             }
             State::Fuzz {
                 static_panics,
                 evaluated_values,
+                costs,
                 fuzzers,
                 ..
             } => {
@@ -312,40 +581,64 @@ impl ModuleAnalyzer {
                         .iter()
                         .filter_map(|(id, value)| Insight::for_value(db, id.clone(), *value)),
                 );
+                insights.extend(Insight::for_hot_spots(db, &self.module, costs));
 
-                for fuzzer in fuzzers {
+                for fuzzer in fuzzers.fuzzers() {
                     insights.append(&mut Insight::for_fuzzer_status(db, fuzzer));
+                }
+                // Code that wasn't reached early on often just needs more
+                // time, so we wait until the fuzzer had a decent budget.
+                for (fuzzer, instructions_spent) in fuzzers.fuzzers_with_instructions_spent() {
+                    if instructions_spent >= Self::BLIND_SPOTS_AFTER_INSTRUCTIONS {
+                        insights.extend(Insight::for_blind_spots(db, fuzzer));
+                    }
+                }
 
-                    let Status::FoundPanic { input, panic, .. } = fuzzer.status() else {
-                        continue;
+                let found_panics = fuzzers.fuzzers().filter_map(|fuzzer| {
+                    let Status::FoundPanic {
+                        input,
+                        panic,
+                        tracer,
+                        ..
+                    } = fuzzer.status()
+                    else {
+                        return None;
                     };
-
-                    let id = fuzzer.function_id.clone();
-                    if !id.is_same_module_and_any_parent_of(&panic.responsible) {
-                        // The function panics internally for an input, but it's
-                        // the fault of another function that's called
-                        // internally.
-                        // TODO: The fuzz case should instead be highlighted in
-                        // the used function directly. We don't do that right
-                        // now because we assume the fuzzer will find the panic
-                        // when fuzzing the faulty function, but we should save
-                        // the panicking case (or something like that) in the
-                        // future.
-                        continue;
+                    if static_panics
+                        .iter()
+                        .any(|it| it.responsible == panic.responsible)
+                    {
+                        // Static analysis already reported this panic.
+                        return None;
                     }
-                    assert!(
-                        db.hir_to_cst_id(&id).is_some(),
-                        "It looks like the generated code {} is at fault for a panic.",
-                        panic.responsible,
-                    );
-
+                    Some((fuzzer, input, panic, tracer))
+                });
+                // Many fuzzed functions often panic because of the same bug, so
+                // we only report the simplest input for each bug.
+                for group in group_by_panic(found_panics, |(_, input, panic, _)| (*input, *panic)) {
+                    let (fuzzer, input, panic, tracer) = group.representative;
+                    let id = fuzzer.function_id.clone();
+                    let (highlighted_id, related_information) =
+                        if id.is_same_module_and_any_parent_of(&panic.responsible) {
+                            (panic.responsible.clone(), vec![])
+                        } else {
+                            // The function panics internally for an input, but
+                            // it's the fault of another function that's called
+                            // internally. We highlight the call in this
+                            // function that leads to the panic and show the
+                            // path to the responsible code.
+                            let Some(path) = panic_propagation_path(db, &id, panic, tracer) else {
+                                continue;
+                            };
+                            path
+                        };
                     // TODO: In the future, re-run only the failing case with
                     // tracing enabled and also show the arguments to the failing
                     // function in the hint.
                     let call_span = db
-                        .hir_id_to_display_span(&panic.responsible)
+                        .hir_id_to_origin_display_span(&highlighted_id)
                         .unwrap_or_else(|| panic!("Couldn't find the span for {panic:?}."));
-                    insights.push(Insight::Diagnostic(Diagnostic::error(
+                    let mut diagnostic = Diagnostic::error(
                         db.range_to_lsp_range(self.module.clone(), call_span),
                         format!(
                             "For `{} {}`, this call panics: {}",
@@ -357,7 +650,25 @@ impl ModuleAnalyzer {
                                 .join(" "),
                             panic.reason,
                         ),
-                    )));
+                    );
+                    if !group.others.is_empty() {
+                        diagnostic.message.push_str(&format!(
+                            "\nThe fuzzer found this panic {} times, also in {}.",
+                            group.occurrences(),
+                            group
+                                .others
+                                .iter()
+                                .map(|(fuzzer, ..)| {
+                                    format!("`{}`", fuzzer.function_id.function_name())
+                                })
+                                .unique()
+                                .join(", "),
+                        ));
+                    }
+                    if !related_information.is_empty() {
+                        diagnostic.related_information = Some(related_information);
+                    }
+                    insights.push(Insight::Diagnostic(diagnostic));
                 }
             }
         }
@@ -368,6 +679,82 @@ impl ModuleAnalyzer {
     }
 }
 
+fn format_allocation_sites(heap: &Heap, byte_code: &ByteCode) -> String {
+    heap.live_objects_by_allocation_site()
+        .unwrap_or_default()
+        .iter()
+        .map(|usage| {
+            let functions = usage.site.map_or_else(
+                || "outside of instructions".to_string(),
+                |site| byte_code.functions_behind(site).iter().join(", "),
+            );
+            format!(
+                "{} bytes in {} objects (max. age: {} allocations) from {functions}",
+                usage.bytes, usage.objects, usage.max_age,
+            )
+        })
+        .join("\n")
+}
+
+/// For a panic that's the fault of code outside of `function`, finds the call
+/// in `function` that leads to the panic and the path of calls from there to
+/// the responsible code.
+fn panic_propagation_path(
+    db: &Database,
+    function: &hir::Id,
+    panic: &Panic,
+    tracer: &StackTracer,
+) -> Option<(hir::Id, Vec<DiagnosticRelatedInformation>)> {
+    let calls = &tracer.call_stack;
+    let index_in_function = calls
+        .iter()
+        .rposition(|call| function.is_same_module_and_any_parent_of(call.call_site.get()))?;
+
+    let mut related_information = vec![];
+    let mut add_related_information = |id: &hir::Id, message: String| {
+        let (Some(uri), Some(span)) = (
+            module_to_url(&id.module, &db.packages_path),
+            db.hir_id_to_origin_display_span(id),
+        ) else {
+            return;
+        };
+        related_information.push(DiagnosticRelatedInformation {
+            location: Location {
+                uri,
+                range: db.range_to_lsp_range(id.module.clone(), span),
+            },
+            message,
+        });
+    };
+    for call in calls.range(index_in_function + 1..) {
+        let call_site = call.call_site.get();
+        if call_site == &panic.responsible {
+            break;
+        }
+        if call_site.module.package.is_tooling() {
+            continue;
+        }
+        // The call site's parent is the function containing the call.
+        let function_name = call_site
+            .parent()
+            .map(|it| it.function_name())
+            .unwrap_or_default();
+        add_related_information(
+            call_site,
+            format!("The panic propagates through this call in `{function_name}`."),
+        );
+    }
+    add_related_information(
+        &panic.responsible,
+        format!("This is responsible for the panic: {}", panic.reason),
+    );
+
+    Some((
+        calls[index_in_function].call_site.get().clone(),
+        related_information,
+    ))
+}
+
 #[extension_trait]
 pub impl StaticPanics for Vec<Panic> {
     fn to_insights(&self, db: &Database, module: &Module) -> Vec<Insight> {
@@ -376,3 +763,113 @@ pub impl StaticPanics for Vec<Panic> {
             .collect_vec()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{panic_propagation_path, Insight, ModuleAnalyzer};
+    use crate::database::Database;
+    use candy_frontend::{
+        ast_to_hir::AstToHir,
+        hir_to_mir::ExecutionTarget,
+        module::{
+            InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+            PackagesPath,
+        },
+    };
+    use candy_fuzzer::{FuzzablesFinder, Fuzzer, Status, SyntheticFunctions};
+    use candy_vm::{heap::Heap, lir_to_byte_code::compile_byte_code, Vm, VmFinished};
+    use itertools::Itertools;
+    use std::{path::Path, rc::Rc};
+
+    fn database_with_main_module(source: &str) -> (Database, Module) {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let mut db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+        (db, module)
+    }
+
+    /// Fuzzes `function` until it panics and returns the highlighted code and
+    /// the messages of the related information.
+    fn propagation_path(source: &str, function: &str) -> Option<(String, Vec<String>)> {
+        let (db, module) = database_with_main_module(source);
+        let (mut byte_code, _) = compile_byte_code(
+            &db,
+            ExecutionTarget::Module(module),
+            ModuleAnalyzer::TRACING_FOR_FUZZING,
+        );
+        let synthetic_functions = Rc::new(SyntheticFunctions::add_to(&mut byte_code));
+        let byte_code = Rc::new(byte_code);
+        let mut heap = Heap::default();
+        let VmFinished {
+            tracer: FuzzablesFinder { fuzzables },
+            ..
+        } = Vm::for_module(byte_code.clone(), &mut heap, FuzzablesFinder::default())
+            .run_forever_without_handles(&mut heap);
+        let (id, function) = fuzzables
+            .iter()
+            .find(|(id, _)| id.function_name() == function)
+            .unwrap();
+
+        let mut fuzzer = Fuzzer::new(byte_code, synthetic_functions, *function, id.clone());
+        fuzzer.run(100_000);
+        let Status::FoundPanic { panic, tracer, .. } = fuzzer.status() else {
+            panic!("The fuzzer should find a panic.");
+        };
+        let (highlighted_id, related_information) = panic_propagation_path(&db, id, panic, tracer)?;
+        let span = db.hir_id_to_display_span(&highlighted_id).unwrap();
+        Some((
+            source[*span.start..*span.end].to_string(),
+            related_information
+                .into_iter()
+                .map(|it| it.message)
+                .collect_vec(),
+        ))
+    }
+
+    #[test]
+    fn panics_in_called_functions_are_traced_back_to_the_call() {
+        let source = "inner b := needs False \"Oops\"
+helper c := inner c
+middle d := helper d
+outer a := middle a
+";
+        assert_eq!(
+            propagation_path(source, "outer"),
+            Some((
+                "middle".to_string(),
+                vec![
+                    "The panic propagates through this call in `middle`.".to_string(),
+                    "This is responsible for the panic: Oops".to_string(),
+                ],
+            )),
+        );
+    }
+
+    #[test]
+    fn errors_are_reported_before_the_analysis_starts() {
+        let (db, module) = database_with_main_module("foo = (\n");
+        let analyzer = ModuleAnalyzer::for_module(module);
+        let messages = analyzer
+            .insights(&db)
+            .into_iter()
+            .filter_map(|it| match it {
+                Insight::Diagnostic(diagnostic) => Some(diagnostic.message),
+                Insight::Hint(_) => None,
+            })
+            .collect_vec();
+        assert!(messages.contains(
+            &"This expression is parenthesized, but the closing parenthesis is missing."
+                .to_string()
+        ));
+    }
+}
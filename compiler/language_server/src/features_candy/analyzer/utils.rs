@@ -15,12 +15,10 @@ where
         let line = self
             .offset_to_lsp_position(id.module.clone(), span.start)
             .line;
-        let line_start_offsets = self.line_start_offsets(id.module.clone());
-        let last_characer_of_line = if line as usize == line_start_offsets.len() - 1 {
-            self.get_module_content(id.module.clone()).unwrap().len()
-        } else {
-            *line_start_offsets[(line + 1) as usize] - 1
-        };
-        Some(self.offset_to_lsp_position(id.module, last_characer_of_line.into()))
+        let end_of_line = self
+            .line_index(id.module.clone())
+            .line_range(line as usize)
+            .end;
+        Some(self.offset_to_lsp_position(id.module, end_of_line))
     }
 }
@@ -0,0 +1,204 @@
+//! Code lenses showing how large each top-level function is after optimization,
+//! which helps to keep functions that are called a lot small.
+//!
+//! The optimizer inlines small functions into their callers and may specialize
+//! a function for constant arguments. Hence, a function can exist zero times
+//! (it was inlined everywhere or isn't used at all), once, or several times in
+//! the compiled code.
+
+use crate::{
+    database::Database,
+    utils::{pluralize, LspPositionConversion},
+};
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    hir::{self, FunctionKind},
+    hir_to_mir::ExecutionTarget,
+    lir::Lir,
+    lir_optimize::OptimizeLir,
+    mir::{Expression, Mir, VisitorResult},
+    mir_optimize::OptimizeMir,
+    module::Module,
+    TracingConfig,
+};
+use lsp_types::{CodeLens, Command, Position, Range};
+use std::fmt::{self, Display, Formatter};
+
+#[allow(clippy::similar_names)]
+pub fn code_lenses(db: &Database, module: &Module) -> Vec<CodeLens> {
+    let Ok((hir, _)) = db.hir(module.clone()) else {
+        return vec![];
+    };
+    let target = ExecutionTarget::Module(module.clone());
+    let Ok((mir, _, _)) = db.optimized_mir(target.clone(), TracingConfig::off()) else {
+        return vec![];
+    };
+    let Ok((lir, _)) = db.optimized_lir(target, TracingConfig::off()) else {
+        return vec![];
+    };
+
+    hir.expressions
+        .iter()
+        .filter(|(_, expression)| {
+            matches!(
+                expression,
+                hir::Expression::Function(hir::Function { kind, .. }) if *kind != FunctionKind::Use,
+            )
+        })
+        .filter_map(|(id, _)| {
+            let span = db.hir_id_to_display_span(id)?;
+            let line = db.offset_to_lsp_position(module.clone(), span.start).line;
+            // Lenses are shown above the line that their range starts in.
+            let start_of_line = Position { line, character: 0 };
+            Some(CodeLens {
+                range: Range {
+                    start: start_of_line,
+                    end: start_of_line,
+                },
+                command: Some(Command {
+                    title: FunctionSize::of(id, &mir, &lir).to_string(),
+                    command: String::new(),
+                    arguments: None,
+                }),
+                data: None,
+            })
+        })
+        .collect()
+}
+
+struct FunctionSize {
+    /// How often the function exists in the optimized MIR.
+    copies: usize,
+    /// The expressions of all copies, including nested functions.
+    mir_expressions: usize,
+    /// The expressions of all LIR bodies created for the function or the
+    /// functions nested in it.
+    lir_expressions: usize,
+}
+impl FunctionSize {
+    #[allow(clippy::similar_names)]
+    fn of(id: &hir::Id, mir: &Mir, lir: &Lir) -> Self {
+        let mut copies = 0;
+        let mut mir_expressions = 0;
+        mir.body.visit(&mut |_, expression, _| {
+            if let Expression::Function {
+                original_hirs,
+                body,
+                ..
+            } = expression
+                && original_hirs.contains(id)
+            {
+                copies += 1;
+                mir_expressions += body.complexity().expressions;
+            }
+            VisitorResult::Continue
+        });
+
+        let lir_expressions = lir
+            .bodies()
+            .ids_and_bodies()
+            .filter(|(_, body)| {
+                body.original_hirs()
+                    .iter()
+                    .any(|it| it == id || id.is_same_module_and_any_parent_of(it))
+            })
+            .map(|(_, body)| body.expressions().len())
+            .sum();
+
+        Self {
+            copies,
+            mir_expressions,
+            lir_expressions,
+        }
+    }
+}
+impl Display for FunctionSize {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.copies == 0 {
+            return write!(f, "Inlined everywhere or unused");
+        }
+
+        if self.copies > 1 {
+            write!(f, "{} specialized copies, ", self.copies)?;
+        }
+        write!(
+            f,
+            "{} in MIR, {} in LIR",
+            pluralize(self.mir_expressions, "expression"),
+            pluralize(self.lir_expressions, "expression"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code_lenses, FunctionSize};
+    use crate::database::Database;
+    use candy_frontend::module::{
+        InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+        PackagesPath,
+    };
+    use itertools::Itertools;
+    use std::path::Path;
+
+    /// The line and title of each lens.
+    fn lenses(source: &str) -> Vec<(u32, String)> {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let mut db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+        code_lenses(&db, &module)
+            .into_iter()
+            .map(|lens| (lens.range.start.line, lens.command.unwrap().title))
+            .collect_vec()
+    }
+
+    #[test]
+    fn each_function_gets_a_lens_above_it() {
+        assert_eq!(
+            lenses("foo a := a\n\nbar a :=\n  b = (a, a)\n  (b, b)\n"),
+            [
+                (0, "1 expression in MIR, 1 expression in LIR".to_string()),
+                (2, "2 expressions in MIR, 4 expressions in LIR".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn inlined_functions_are_marked() {
+        assert_eq!(
+            lenses("helper a = (a, a)\nfoo a := (helper a, 1)\n"),
+            [
+                (0, "Inlined everywhere or unused".to_string()),
+                (1, "2 expressions in MIR, 4 expressions in LIR".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn sizes_are_described() {
+        let size = |copies, mir_expressions, lir_expressions| {
+            FunctionSize {
+                copies,
+                mir_expressions,
+                lir_expressions,
+            }
+            .to_string()
+        };
+        assert_eq!(size(0, 0, 0), "Inlined everywhere or unused");
+        assert_eq!(size(1, 1, 2), "1 expression in MIR, 2 expressions in LIR");
+        assert_eq!(
+            size(3, 12, 9),
+            "3 specialized copies, 12 expressions in MIR, 9 expressions in LIR",
+        );
+    }
+}
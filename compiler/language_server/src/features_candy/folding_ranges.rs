@@ -61,6 +61,7 @@ where
             | CstKind::DoubleQuote
             | CstKind::Percent
             | CstKind::Octothorpe
+            | CstKind::If
             | CstKind::Whitespace(_)
             | CstKind::Newline(_) => {}
             // TODO: support folding ranges for comments
@@ -137,10 +138,14 @@ where
             }
             CstKind::MatchCase {
                 pattern,
+                guard,
                 arrow,
                 body,
             } => {
                 self.visit_cst(pattern);
+                if let Some(box (_, condition)) = guard {
+                    self.visit_cst(condition);
+                }
 
                 let arrow = arrow.unwrap_whitespace_and_comment();
                 let body_end = body
@@ -0,0 +1,223 @@
+use super::analyzer::EvaluatedValues;
+use crate::{
+    database::Database,
+    utils::{pluralize, LspPositionConversion},
+};
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    builtin_functions::{self, BuiltinFunction},
+    comment::doc_comment_lines,
+    cst::CstDb,
+    hir::{Expression, HirDb},
+    module::{Module, Package},
+    position::Offset,
+};
+use itertools::Itertools;
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind};
+use tracing::debug;
+
+/// Shows what we know about the expression under the cursor: Its value (if the
+/// analyzer evaluated it), its shape, the documentation of builtins, the doc
+/// comment of its definition, and who is blamed if it panics.
+pub fn hover(
+    db: &Database,
+    evaluated_values: &EvaluatedValues,
+    module: Module,
+    offset: Offset,
+) -> Option<Hover> {
+    let cst = db.find_cst_by_offset(module.clone(), offset);
+    let id = db.cst_to_last_hir_id(module.clone(), cst.data.id)?;
+    let expression = db.find_expression(id.clone())?;
+    debug!("Hovering over {id}: {expression}");
+
+    // When hovering over a reference, the referenced definition is more
+    // interesting than the reference itself. References point to the name of
+    // an assignment, which in turn refers to the assignment itself.
+    let mut definition = id.clone();
+    while doc_comment_lines(db, &definition).is_none()
+        && let Some(Expression::Reference(target)) = db.find_expression(definition.clone())
+    {
+        definition = target;
+    }
+
+    let mut sections = vec![];
+    if let Some(value) = evaluated_values
+        .get(&id)
+        .or_else(|| evaluated_values.get(&definition))
+    {
+        sections.push(format!("```candy\n{value}\n```"));
+    }
+    if let Some(shape) = shape_of(db, &expression) {
+        sections.push(format!("*{shape}*"));
+    }
+    if let Some(builtin_function) = builtin_function_of(db, &module, &expression) {
+        sections.push(
+            db.builtin_function_documentation(builtin_function)
+                .to_markdown(),
+        );
+    }
+    if let Some(doc_comment) = doc_comment_lines(db, &definition)
+        && !doc_comment.is_empty()
+    {
+        sections.push(doc_comment.iter().join("\n"));
+    }
+    if let Some(responsibility) = db.responsibility(id)
+        && responsibility.can_panic()
+    {
+        sections.push(format!(
+            "**Responsibility:** {}",
+            responsibility.explain().join(" "),
+        ));
+    }
+    if sections.is_empty() {
+        return None;
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: sections.join("\n\n---\n\n"),
+        }),
+        range: Some(db.range_to_lsp_range(module, cst.data.span)),
+    })
+}
+
+/// Describes the shape of an expression's values as far as it is known without
+/// running the code.
+fn shape_of(db: &Database, expression: &Expression) -> Option<String> {
+    let shape = match expression {
+        Expression::Int(_) => "Int".to_string(),
+        Expression::Text(_) => "Text".to_string(),
+        Expression::Symbol(_) => "Tag".to_string(),
+        Expression::List(items) => format!("List with {}", pluralize(items.len(), "item")),
+        Expression::Struct(fields) => format!("Struct with {}", pluralize(fields.len(), "field")),
+        Expression::Function(function) => format!(
+            "Function with {}",
+            pluralize(function.parameters.len(), "parameter"),
+        ),
+        Expression::Builtin(builtin) => format!(
+            "Builtin function with {}",
+            pluralize(builtin.num_parameters(), "parameter"),
+        ),
+        Expression::Reference(target) => return shape_of(db, &db.find_expression(target.clone())?),
+        _ => return None,
+    };
+    Some(shape)
+}
+/// Inside the `Builtins` package, builtins are accessed via `✨.intAdd` etc.,
+/// so hovering over the key also shows the builtin's documentation.
+fn builtin_function_of(
+    db: &Database,
+    module: &Module,
+    expression: &Expression,
+) -> Option<BuiltinFunction> {
+    match expression {
+        Expression::Builtin(builtin_function) => Some(*builtin_function),
+        Expression::Reference(target) => {
+            builtin_function_of(db, module, &db.find_expression(target.clone())?)
+        }
+        Expression::Symbol(symbol) if module.package == Package::builtins() => {
+            builtin_functions::VALUES
+                .iter()
+                .find(|it| format!("{it:?}") == *symbol)
+                .copied()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hover;
+    use crate::{database::Database, features_candy::analyzer::EvaluatedValues};
+    use candy_frontend::{
+        hir::{self, IdKey},
+        module::{
+            InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+            PackagesPath,
+        },
+        position::Offset,
+    };
+    use lsp_types::HoverContents;
+    use rustc_hash::FxHashMap;
+    use std::path::Path;
+
+    fn module() -> Module {
+        Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        }
+    }
+
+    fn hover_text(
+        source: &str,
+        hovered: &str,
+        evaluated_values: &EvaluatedValues,
+    ) -> Option<String> {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let mut db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        db.did_open_module(&module(), source.as_bytes().to_vec());
+
+        // The last occurrence is a reference in case of names.
+        let offset = Offset(source.rfind(hovered).unwrap());
+        let hover = hover(&db, evaluated_values, module(), offset)?;
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Hovers contain Markdown.");
+        };
+        Some(content.value)
+    }
+
+    const SOURCE: &str = r#"answer =
+  # The answer to everything.
+  42
+numbers = (1, 2, 3)
+copy = answer
+"#;
+
+    #[test]
+    fn literals_show_their_shape() {
+        let values = EvaluatedValues::default();
+        assert_eq!(hover_text(SOURCE, "42", &values).unwrap(), "*Int*");
+        assert_eq!(
+            hover_text(SOURCE, "numbers", &values).unwrap(),
+            "*List with 3 items*",
+        );
+    }
+
+    #[test]
+    fn references_show_the_doc_comment_of_their_definition() {
+        let values = EvaluatedValues::default();
+        assert_eq!(
+            hover_text(SOURCE, "answer", &values).unwrap(),
+            "*Int*\n\n---\n\nThe answer to everything.",
+        );
+        assert_eq!(hover_text(SOURCE, "copy", &values).unwrap(), "*Int*");
+    }
+
+    #[test]
+    fn evaluated_values_are_shown_first() {
+        let values = EvaluatedValues::default();
+        values.update(
+            module(),
+            FxHashMap::from_iter([(
+                hir::Id::new(module(), vec![IdKey::from("copy")]),
+                "42".to_string(),
+            )]),
+        );
+        assert_eq!(
+            hover_text(SOURCE, "copy", &values).unwrap(),
+            "```candy\n42\n```\n\n---\n\n*Int*",
+        );
+    }
+
+    #[test]
+    fn whitespace_has_no_hover() {
+        assert_eq!(hover_text(SOURCE, "\n", &EvaluatedValues::default()), None,);
+    }
+}
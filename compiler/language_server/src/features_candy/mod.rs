@@ -1,24 +1,29 @@
 use self::{
+    analyzer::{EvaluatedValues, PullModel, ReportedDiagnostics},
+    code_lenses::code_lenses,
     find_definition::find_definition,
     folding_ranges::folding_ranges,
+    hover::hover,
+    refactorings::{edits_for_file_renames, module_moves, refactorings},
     references::{reference_query_for_offset, references, ReferenceQuery},
     semantic_tokens::semantic_tokens,
 };
 use crate::{
     database::Database,
-    features::{LanguageFeatures, Reference, RenameError},
+    features::{DocumentOutOfSync, LanguageFeatures, Refactoring, Reference, RenameError},
     server::AnalyzerClient,
     utils::{lsp_range_to_range_raw, module_from_url, LspPositionConversion},
 };
 use async_trait::async_trait;
-use candy_formatter::Formatter;
+use candy_formatter::{Formatter, FormatterOptions};
 use candy_frontend::{
     module::{Module, ModuleDb, ModuleKind, MutableModuleProviderOwner, PackagesPath},
     rcst_to_cst::RcstToCst,
 };
+use itertools::Itertools;
 use lsp_types::{
-    self, notification::Notification, FoldingRange, LocationLink, SemanticToken,
-    TextDocumentContentChangeEvent, TextEdit, Url,
+    self, notification::Notification, CodeLens, DocumentDiagnosticReport, FoldingRange, Hover,
+    LocationLink, SemanticToken, TextDocumentContentChangeEvent, TextEdit, Url,
 };
 use regex::Regex;
 use rustc_hash::FxHashMap;
@@ -27,8 +32,11 @@ use std::{collections::HashMap, thread};
 use tokio::sync::{mpsc::Sender, Mutex};
 
 pub mod analyzer;
+pub mod code_lenses;
 pub mod find_definition;
 pub mod folding_ranges;
+pub mod hover;
+pub mod refactorings;
 pub mod references;
 pub mod semantic_tokens;
 
@@ -45,19 +53,43 @@ impl Notification for ServerStatusNotification {
 #[derive(Debug)]
 pub struct CandyFeatures {
     hints_events_sender: Sender<analyzer::Message>,
+    evaluated_values: EvaluatedValues,
+    diagnostics: ReportedDiagnostics,
 }
 impl CandyFeatures {
     #[must_use]
     pub fn new(packages_path: PackagesPath, client: AnalyzerClient) -> Self {
         let (hints_events_sender, hints_events_receiver) = tokio::sync::mpsc::channel(1024);
-        thread::spawn(move || {
-            analyzer::run_server(packages_path, hints_events_receiver, client);
+        let evaluated_values = EvaluatedValues::default();
+        let diagnostics = client.reported_diagnostics().clone();
+        thread::spawn({
+            let evaluated_values = evaluated_values.clone();
+            move || {
+                analyzer::run_server(
+                    packages_path,
+                    hints_events_receiver,
+                    client,
+                    evaluated_values,
+                );
+            }
         });
         Self {
             hints_events_sender,
+            evaluated_values,
+            diagnostics,
         }
     }
 
+    /// Stops pushing diagnostics. Instead, the client pulls them via
+    /// `textDocument/diagnostic`.
+    pub fn use_pull_diagnostics(&self, pull_model: PullModel) {
+        self.diagnostics.use_pull_model(pull_model);
+    }
+    #[must_use]
+    pub fn pull_model(&self) -> Option<PullModel> {
+        self.diagnostics.pull_model()
+    }
+
     async fn send_to_analyzer(&self, event: analyzer::Message) {
         match self.hints_events_sender.send(event).await {
             Ok(_) => {}
@@ -83,15 +115,19 @@ impl LanguageFeatures for CandyFeatures {
     fn supports_did_open(&self) -> bool {
         true
     }
-    async fn did_open(&self, db: &Mutex<Database>, uri: Url, content: Vec<u8>) {
+    async fn did_open(&self, db: &Mutex<Database>, uri: Url, content: Vec<u8>, version: i32) {
         let module = {
             let mut db = db.lock().await;
             let module = decode_module(&uri, &db.packages_path);
             db.did_open_module(&module, content.clone());
             module
         };
-        self.send_to_analyzer(analyzer::Message::UpdateModule(module, content))
-            .await;
+        self.send_to_analyzer(analyzer::Message::UpdateModule(
+            module,
+            content,
+            Some(version),
+        ))
+        .await;
     }
     fn supports_did_change(&self) -> bool {
         true
@@ -101,16 +137,22 @@ impl LanguageFeatures for CandyFeatures {
         db: &Mutex<Database>,
         uri: Url,
         changes: Vec<TextDocumentContentChangeEvent>,
-    ) {
+        version: i32,
+    ) -> Result<(), DocumentOutOfSync> {
         let (module, content) = {
             let mut db = db.lock().await;
             let module = decode_module(&uri, &db.packages_path);
-            let content = apply_text_changes(&db, module.clone(), changes).into_bytes();
+            let content = apply_text_changes(&db, module.clone(), changes)?.into_bytes();
             db.did_change_module(&module, content.clone());
             (module, content)
         };
-        self.send_to_analyzer(analyzer::Message::UpdateModule(module, content))
-            .await;
+        self.send_to_analyzer(analyzer::Message::UpdateModule(
+            module,
+            content,
+            Some(version),
+        ))
+        .await;
+        Ok(())
     }
     fn supports_did_close(&self) -> bool {
         true
@@ -126,6 +168,21 @@ impl LanguageFeatures for CandyFeatures {
             .await;
     }
 
+    fn supports_pull_diagnostics(&self) -> bool {
+        true
+    }
+    async fn pull_diagnostics(
+        &self,
+        db: &Mutex<Database>,
+        uri: Url,
+        version: Option<i32>,
+        previous_result_id: Option<String>,
+    ) -> Option<DocumentDiagnosticReport> {
+        let module = decode_module(&uri, &db.lock().await.packages_path);
+        self.diagnostics
+            .report(&module, version, previous_result_id.as_deref())
+    }
+
     fn supports_folding_ranges(&self) -> bool {
         true
     }
@@ -135,24 +192,48 @@ impl LanguageFeatures for CandyFeatures {
         folding_ranges(&*db, module)
     }
 
+    fn supports_code_lenses(&self) -> bool {
+        true
+    }
+    async fn code_lenses(&self, db: &Mutex<Database>, uri: Url) -> Vec<CodeLens> {
+        let db = db.lock().await;
+        let module = decode_module(&uri, &db.packages_path);
+        code_lenses(&db, &module)
+    }
+
     fn supports_format(&self) -> bool {
         true
     }
     async fn format(&self, db: &Mutex<Database>, uri: Url) -> Vec<TextEdit> {
         let db = db.lock().await;
-        let module = decode_module(&uri, &db.packages_path);
-        let Ok(cst) = db.cst(module.clone()) else {
-            return vec![];
+        let options = db.formatter_options;
+        format(&db, &uri, options)
+    }
+
+    fn supports_organize_imports(&self) -> bool {
+        true
+    }
+    async fn organize_imports(&self, db: &Mutex<Database>, uri: Url) -> Vec<TextEdit> {
+        let db = db.lock().await;
+        let options = FormatterOptions {
+            organize_uses: true,
+            ..db.formatter_options
         };
+        format(&db, &uri, options)
+    }
 
-        cst.format_to_edits()
-            .finish()
-            .into_iter()
-            .map(|it| TextEdit {
-                range: db.range_to_lsp_range(module.clone(), it.range),
-                new_text: it.new_text,
-            })
-            .collect()
+    fn supports_refactorings(&self) -> bool {
+        true
+    }
+    async fn refactorings(
+        &self,
+        db: &Mutex<Database>,
+        uri: Url,
+        range: lsp_types::Range,
+    ) -> Vec<Refactoring> {
+        let db = db.lock().await;
+        let module = decode_module(&uri, &db.packages_path);
+        refactorings(&*db, &module, range)
     }
 
     fn supports_find_definition(&self) -> bool {
@@ -170,6 +251,21 @@ impl LanguageFeatures for CandyFeatures {
         find_definition(&db, module, offset)
     }
 
+    fn supports_hover(&self) -> bool {
+        true
+    }
+    async fn hover(
+        &self,
+        db: &Mutex<Database>,
+        uri: Url,
+        position: lsp_types::Position,
+    ) -> Option<Hover> {
+        let db = db.lock().await;
+        let module = decode_module(&uri, &db.packages_path);
+        let offset = db.lsp_position_to_offset(module.clone(), position);
+        hover(&db, &self.evaluated_values, module, offset)
+    }
+
     fn supports_references(&self) -> bool {
         true
     }
@@ -263,6 +359,42 @@ impl LanguageFeatures for CandyFeatures {
         Ok(changes)
     }
 
+    fn supports_rename_files(&self) -> bool {
+        true
+    }
+    async fn will_rename_files(
+        &self,
+        db: &Mutex<Database>,
+        renames: Vec<(Url, Url)>,
+    ) -> HashMap<Url, Vec<TextEdit>> {
+        let db = db.lock().await;
+        edits_for_file_renames(&*db, &db.packages_path, &renames)
+    }
+    async fn did_rename_files(&self, db: &Mutex<Database>, renames: Vec<(Url, Url)>) {
+        let moved_open_modules = {
+            let mut db = db.lock().await;
+            let moves = module_moves(&db.packages_path, &renames);
+            for (from, to) in &moves {
+                db.did_move_module(from, to);
+            }
+            let open_modules = db.get_open_modules();
+            moves
+                .into_iter()
+                .filter(|(_, to)| open_modules.contains(to))
+                .map(|(from, to)| {
+                    let content = db.get_module_content(to.clone()).unwrap();
+                    (from, to, (*content).clone())
+                })
+                .collect_vec()
+        };
+        for (from, to, content) in moved_open_modules {
+            self.send_to_analyzer(analyzer::Message::CloseModule(from))
+                .await;
+            self.send_to_analyzer(analyzer::Message::UpdateModule(to, content, None))
+                .await;
+        }
+    }
+
     fn supports_semantic_tokens(&self) -> bool {
         true
     }
@@ -276,29 +408,152 @@ impl LanguageFeatures for CandyFeatures {
 fn decode_module(uri: &Url, packages_path: &PackagesPath) -> Module {
     module_from_url(uri, ModuleKind::Code, packages_path).unwrap()
 }
+fn format(db: &Database, uri: &Url, options: FormatterOptions) -> Vec<TextEdit> {
+    let module = decode_module(uri, &db.packages_path);
+    let Ok(cst) = db.cst(module.clone()) else {
+        return vec![];
+    };
+
+    cst.format_to_edits_with_options(options)
+        .finish()
+        .into_iter()
+        .map(|it| TextEdit {
+            range: db.range_to_lsp_range(module.clone(), it.range),
+            new_text: it.new_text,
+        })
+        .collect()
+}
+/// Changes without a range replace the whole content, so they work even if the
+/// previous content is unknown.
 fn apply_text_changes(
     db: &Database,
     module: Module,
     changes: Vec<TextDocumentContentChangeEvent>,
-) -> String {
+) -> Result<String, DocumentOutOfSync> {
     let mut text = db
         .get_module_content_as_string(module)
-        .unwrap()
-        .as_ref()
-        .clone();
+        .map(|it| it.as_ref().clone());
     for change in changes {
         match change.range {
             Some(range) => {
-                let range = lsp_range_to_range_raw(&text, range);
-                text = format!(
+                let Some(old_text) = text else {
+                    return Err(DocumentOutOfSync {
+                        reason: "The document's content is unknown.".to_string(),
+                    });
+                };
+                let Some(range) = lsp_range_to_range_raw(&old_text, range) else {
+                    return Err(DocumentOutOfSync {
+                        reason: format!(
+                            "The range {}:{}–{}:{} doesn't fit the document's content.",
+                            range.start.line,
+                            range.start.character,
+                            range.end.line,
+                            range.end.character,
+                        ),
+                    });
+                };
+                text = Some(format!(
                     "{}{}{}",
-                    &text[..*range.start],
+                    &old_text[..*range.start],
                     &change.text,
-                    &text[*range.end..],
-                );
+                    &old_text[*range.end..],
+                ));
             }
-            None => text = change.text,
+            None => text = Some(change.text),
         }
     }
-    text
+    text.ok_or_else(|| DocumentOutOfSync {
+        reason: "The document's content is unknown.".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply_text_changes;
+    use crate::database::Database;
+    use candy_frontend::module::{
+        InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+        PackagesPath,
+    };
+    use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    use std::path::Path;
+
+    fn database() -> (Database, Module) {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        (db, module)
+    }
+    fn change(
+        range: Option<((u32, u32), (u32, u32))>,
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: range.map(|(start, end)| {
+                Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+            }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn incremental_changes_are_applied_in_order() {
+        let (mut db, module) = database();
+        db.did_open_module(&module, b"foo = 1\n".to_vec());
+        let text = apply_text_changes(
+            &db,
+            module,
+            vec![
+                change(Some(((0, 6), (0, 7))), "23"),
+                change(Some(((0, 0), (0, 3))), "bar"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(text, "bar = 23\n");
+    }
+
+    #[test]
+    fn changes_that_dont_fit_the_content_are_rejected() {
+        let (mut db, module) = database();
+        db.did_open_module(&module, b"foo = 1\n".to_vec());
+        let error =
+            apply_text_changes(&db, module, vec![change(Some(((3, 0), (3, 1))), "x")]).unwrap_err();
+        assert_eq!(
+            error.reason,
+            "The range 3:0–3:1 doesn't fit the document's content."
+        );
+    }
+
+    #[test]
+    fn full_content_changes_work_without_known_content() {
+        let (db, module) = database();
+        assert!(apply_text_changes(
+            &db,
+            module.clone(),
+            vec![change(Some(((0, 0), (0, 0))), "x")]
+        )
+        .is_err());
+        assert_eq!(
+            apply_text_changes(
+                &db,
+                module,
+                vec![
+                    change(None, "foo = 1\n"),
+                    change(Some(((0, 6), (0, 7))), "2")
+                ],
+            )
+            .unwrap(),
+            "foo = 2\n",
+        );
+    }
 }
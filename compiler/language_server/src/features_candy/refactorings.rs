@@ -0,0 +1,377 @@
+use super::references::references;
+use crate::{
+    features::Refactoring,
+    utils::{module_to_url, LspPositionConversion},
+};
+use candy_frontend::{
+    ast::CollectErrors,
+    cst::CstDb,
+    error::{CompilerError, CompilerErrorPayload},
+    hir::HirDb,
+    mir::MirError,
+    module::{Module, ModuleDb, ModuleKind, PackagesPath},
+    naming_conventions::naming_convention_warnings,
+    position::{Offset, PositionConversionDb},
+    refactorings::{
+        convert_to_call, convert_to_pipeline, extract_function, inline_definition, move_modules,
+        ModuleMove, TextEdit,
+    },
+    utils::AdjustCasingOfFirstLetter,
+};
+use itertools::Itertools;
+use lsp_types::{CodeActionKind, Url};
+use rustc_hash::FxHashMap;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+pub fn refactorings<DB>(db: &DB, module: &Module, range: lsp_types::Range) -> Vec<Refactoring>
+where
+    DB: HirDb + ModuleDb + PositionConversionDb,
+{
+    let start = db.lsp_position_to_offset(module.clone(), range.start);
+    let end = db.lsp_position_to_offset(module.clone(), range.end);
+
+    let mut refactorings = vec![];
+    if start < end
+        && let Some(edits) = extract_function(db, module, start..end)
+    {
+        refactorings.push(Refactoring {
+            kind: CodeActionKind::REFACTOR_EXTRACT,
+            title: "Extract function".to_string(),
+            edits: to_lsp_edits(db, module, edits),
+        });
+    }
+    if let Some(edits) = inline_definition(db, module.clone(), start) {
+        refactorings.push(Refactoring {
+            kind: CodeActionKind::REFACTOR_INLINE,
+            title: "Inline definition".to_string(),
+            edits: to_lsp_edits(db, module, edits),
+        });
+    }
+    if let Some(edits) = convert_to_pipeline(db, module.clone(), start) {
+        refactorings.push(Refactoring {
+            kind: CodeActionKind::REFACTOR_REWRITE,
+            title: "Convert to pipeline".to_string(),
+            edits: to_lsp_edits(db, module, edits),
+        });
+    }
+    if let Some(edits) = convert_to_call(db, module.clone(), start) {
+        refactorings.push(Refactoring {
+            kind: CodeActionKind::REFACTOR_REWRITE,
+            title: "Convert to call".to_string(),
+            edits: to_lsp_edits(db, module, edits),
+        });
+    }
+    refactorings.extend(
+        naming_convention_warnings(db, module.clone())
+            .iter()
+            .filter(|it| &it.module == module && it.span.start <= end && start <= it.span.end)
+            .filter_map(|it| rename_to_conventional_name(db, module, it)),
+    );
+    refactorings.extend(insert_missing_tokens(db, module, start..end));
+    refactorings
+}
+
+/// The parser recovers from missing closing tokens, e.g., parentheses, and
+/// records where they should be inserted.
+fn insert_missing_tokens<DB>(db: &DB, module: &Module, range: Range<Offset>) -> Vec<Refactoring>
+where
+    DB: HirDb + PositionConversionDb,
+{
+    let Ok((asts, _)) = db.ast(module.clone()) else {
+        return vec![];
+    };
+    let mut errors = vec![];
+    (*asts).clone().collect_errors(&mut errors);
+
+    errors
+        .iter()
+        .filter(|it| it.span.start <= range.end && range.start <= it.span.end)
+        .filter_map(|it| it.payload.recovery_hint())
+        .unique()
+        .map(|hint| Refactoring {
+            kind: CodeActionKind::QUICKFIX,
+            title: format!("Insert `{}`", hint.expected_token),
+            edits: to_lsp_edits(
+                db,
+                module,
+                vec![TextEdit {
+                    range: hint.insertion_offset..hint.insertion_offset,
+                    new_text: hint.expected_token.clone(),
+                }],
+            ),
+        })
+        .collect()
+}
+
+/// Renames the definition and all its references. Struct keys are also
+/// accessed with a lowercase first letter (`foo.fooBar`), so those accesses
+/// get a lowercase first letter as well.
+fn rename_to_conventional_name<DB>(
+    db: &DB,
+    module: &Module,
+    warning: &CompilerError,
+) -> Option<Refactoring>
+where
+    DB: HirDb + ModuleDb + PositionConversionDb,
+{
+    let CompilerErrorPayload::Mir(MirError::UnconventionalName {
+        name,
+        conventional_name,
+    }) = &warning.payload
+    else {
+        return None;
+    };
+    let source = db.get_module_content_as_string(module.clone())?;
+
+    let definition_range = db.range_to_lsp_range(module.clone(), warning.span.clone());
+    let mut edits = references(db, module.clone(), warning.span.start, true)
+        .into_iter()
+        .filter_map(|reference| {
+            let start = db.lsp_position_to_offset(module.clone(), reference.range.start);
+            let end = db.lsp_position_to_offset(module.clone(), reference.range.end);
+            let text = &source[*start..*end];
+            let new_text = if text == name {
+                conventional_name.clone()
+            } else if text == name.lowercase_first_letter() {
+                conventional_name.lowercase_first_letter()
+            } else {
+                return None;
+            };
+            Some(lsp_types::TextEdit {
+                range: reference.range,
+                new_text,
+            })
+        })
+        .collect_vec();
+    if !edits.iter().any(|it| it.range == definition_range) {
+        edits.push(lsp_types::TextEdit {
+            range: definition_range,
+            new_text: conventional_name.clone(),
+        });
+    }
+
+    Some(Refactoring {
+        kind: CodeActionKind::QUICKFIX,
+        title: format!("Rename to `{conventional_name}`"),
+        edits,
+    })
+}
+
+/// Edits that keep `use`s working when files or folders are renamed. They
+/// refer to the files' old locations, so they have to be applied before the
+/// renames.
+pub fn edits_for_file_renames<DB>(
+    db: &DB,
+    packages_path: &PackagesPath,
+    renames: &[(Url, Url)],
+) -> HashMap<Url, Vec<lsp_types::TextEdit>>
+where
+    DB: CstDb + PositionConversionDb,
+{
+    let moves = module_moves(packages_path, renames);
+    let modules = moves
+        .keys()
+        .map(|it| &it.package)
+        .unique()
+        .flat_map(|package| package.modules(packages_path))
+        .collect_vec();
+
+    let ModuleMove {
+        edits,
+        unreachable_uses,
+    } = move_modules(db, &modules, &moves);
+    for (module, span) in unreachable_uses {
+        warn!("The `use` at {module}:{span:?} can't refer to its moved target anymore.");
+    }
+    edits
+        .into_iter()
+        .filter_map(|(module, edits)| {
+            let url = module_to_url(&module, packages_path)?;
+            Some((url, to_lsp_edits(db, &module, edits)))
+        })
+        .collect()
+}
+
+/// The modules that move when renaming the given files or folders. This works
+/// before and after the renames are performed.
+///
+/// Moves between packages are ignored since relative `use`s can't refer to
+/// other packages anyway.
+#[must_use]
+pub fn module_moves(
+    packages_path: &PackagesPath,
+    renames: &[(Url, Url)],
+) -> FxHashMap<Module, Module> {
+    let mut moves = FxHashMap::default();
+    for (old, new) in renames {
+        let (Ok(old), Ok(new)) = (old.to_file_path(), new.to_file_path()) else {
+            continue;
+        };
+        // Asset modules correspond exactly to their path, which is what we
+        // need for folders as well.
+        let (Ok(old_location), Ok(new_location)) = (
+            Module::from_new_path(packages_path, &old, ModuleKind::Asset),
+            Module::from_new_path(packages_path, &new, ModuleKind::Asset),
+        ) else {
+            continue;
+        };
+        if old_location.package != new_location.package {
+            continue;
+        }
+
+        let existing = if old.exists() { &old } else { &new };
+        let files = if existing.is_dir() {
+            files_in(existing)
+        } else {
+            vec![PathBuf::new()]
+        };
+        let package = old_location.package;
+        let old_folder: PathBuf = old_location.path.iter().collect();
+        let new_folder: PathBuf = new_location.path.iter().collect();
+        for file in files {
+            let old_path = old_folder.join(&file);
+            let new_path = new_folder.join(&file);
+            let kind = module_kind_of(&old_path);
+            if module_kind_of(&new_path) != kind {
+                continue;
+            }
+
+            moves.insert(
+                Module::from_package_and_relative_path(package.clone(), &old_path, kind),
+                Module::from_package_and_relative_path(package.clone(), &new_path, kind),
+            );
+        }
+    }
+    moves
+}
+fn module_kind_of(path: &Path) -> ModuleKind {
+    if path.extension() == Some(OsStr::new("candy")) {
+        ModuleKind::Code
+    } else {
+        ModuleKind::Asset
+    }
+}
+/// The paths of all files in the folder, relative to it.
+fn files_in(folder: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let mut folders = vec![PathBuf::new()];
+    while let Some(relative_folder) = folders.pop() {
+        let Ok(children) = fs::read_dir(folder.join(&relative_folder)) else {
+            continue;
+        };
+        for child in children.filter_map(Result::ok) {
+            let path = relative_folder.join(child.file_name());
+            if child.path().is_dir() {
+                folders.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+fn to_lsp_edits<DB>(db: &DB, module: &Module, edits: Vec<TextEdit>) -> Vec<lsp_types::TextEdit>
+where
+    DB: PositionConversionDb,
+{
+    edits
+        .into_iter()
+        .map(|it| lsp_types::TextEdit {
+            range: db.range_to_lsp_range(module.clone(), it.range),
+            new_text: it.new_text,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::insert_missing_tokens;
+    use crate::database::Database;
+    use candy_frontend::{
+        module::{
+            InMemoryModuleProvider, Module, ModuleKind, MutableModuleProviderOwner, Package,
+            PackagesPath,
+        },
+        position::Offset,
+    };
+    use itertools::Itertools;
+    use std::path::Path;
+
+    /// Applies each quick fix to the single-line source.
+    fn fixed_sources(source: &str) -> Vec<(String, String)> {
+        let packages_path = PackagesPath::try_from(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../packages")
+                .as_path(),
+        )
+        .unwrap();
+        let mut db = Database::new(packages_path, Box::<InMemoryModuleProvider>::default());
+        let module = Module {
+            package: Package::User("/package".into()),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_open_module(&module, source.as_bytes().to_vec());
+
+        insert_missing_tokens(&db, &module, Offset(0)..Offset(source.len()))
+            .into_iter()
+            .map(|refactoring| {
+                let [edit] = refactoring.edits.as_slice() else {
+                    panic!("Each quick fix inserts a single token.");
+                };
+                let offset = edit.range.start.character as usize;
+                let fixed = format!(
+                    "{}{}{}",
+                    &source[..offset],
+                    edit.new_text,
+                    &source[offset..]
+                );
+                (refactoring.title, fixed)
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn missing_closing_tokens_are_inserted() {
+        for (source, title, fixed) in [
+            ("foo = (bar", "Insert `)`", "foo = (bar)"),
+            ("foo = (1, 2", "Insert `)`", "foo = (1, 2)"),
+            ("foo = [Bar: 1", "Insert `]`", "foo = [Bar: 1]"),
+            ("foo = { a -> a", "Insert `}`", "foo = { a -> a}"),
+            ("foo = \"bar", "Insert `\"`", "foo = \"bar\""),
+        ] {
+            assert_eq!(
+                fixed_sources(source),
+                [(title.to_string(), fixed.to_string())],
+                "Source: {source}",
+            );
+        }
+    }
+
+    #[test]
+    fn interpolations_only_get_the_missing_curly_braces() {
+        assert_eq!(
+            fixed_sources("foo = '\"a{{bar}"),
+            [
+                ("Insert `}`".to_string(), "foo = '\"a{{bar}}".to_string()),
+                (
+                    "Insert `\"'`".to_string(),
+                    "foo = '\"a{{bar}\"'".to_string()
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn complete_code_has_no_quick_fixes() {
+        assert_eq!(fixed_sources("foo = (bar)\n"), []);
+    }
+}
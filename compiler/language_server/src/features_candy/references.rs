@@ -2,7 +2,7 @@ use crate::{features::Reference, utils::LspPositionConversion};
 use candy_frontend::{
     ast_to_hir::AstToHir,
     cst::{CstDb, CstKind},
-    hir::{self, Body, Expression, Function, HirDb},
+    hir::{self, Body, Expression, Function, HirDb, MatchCase},
     module::{Module, ModuleDb},
     position::{Offset, PositionConversionDb},
 };
@@ -167,7 +167,7 @@ where
             | Expression::Destructure { .. }
             | Expression::PatternIdentifierReference (_) => {},
             Expression::Match { cases, .. } => {
-                for (_, body) in cases {
+                for body in cases.iter().flat_map(MatchCase::bodies) {
                     self.visit_body(body);
                 }
             },
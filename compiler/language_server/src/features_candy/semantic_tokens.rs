@@ -1,28 +1,58 @@
 use candy_frontend::{
-    cst::{Cst, CstKind, UnwrapWhitespaceAndComment},
+    cst::{self, Cst, CstKind, UnwrapWhitespaceAndComment},
+    definitions::DefinitionKind,
+    hir::HirDb,
     module::{Module, ModuleDb},
-    position::PositionConversionDb,
+    position::{Offset, PositionConversionDb},
     rcst_to_cst::RcstToCst,
 };
 use enumset::EnumSet;
 use lsp_types::{self, SemanticToken};
+use rustc_hash::FxHashSet;
+use std::ops::Range;
 
-use crate::semantic_tokens::{SemanticTokenType, SemanticTokensBuilder};
+use crate::semantic_tokens::{SemanticTokenModifier, SemanticTokenType, SemanticTokensBuilder};
 
-pub fn semantic_tokens<DB: ModuleDb + PositionConversionDb + RcstToCst>(
+pub fn semantic_tokens<DB: HirDb + ModuleDb + PositionConversionDb + RcstToCst>(
     db: &DB,
     module: Module,
 ) -> Vec<SemanticToken> {
-    let text = db.get_module_content_as_string(module.clone()).unwrap();
-    let line_start_offsets = db.line_start_offsets(module.clone());
-    let mut builder = SemanticTokensBuilder::new(&*text, &*line_start_offsets);
+    let line_index = db.line_index(module.clone());
+    let mut builder = Builder {
+        tokens: SemanticTokensBuilder::new(&line_index),
+        function_definitions: function_definitions(db, module.clone()),
+    };
     let cst = db.cst(module).unwrap();
     visit_csts(&mut builder, &cst, None);
-    builder.finish()
+    builder.tokens.finish()
+}
+
+struct Builder<'a> {
+    tokens: SemanticTokensBuilder<'a>,
+    /// Top-level definitions whose value is a function, even if it's not
+    /// written as a function literal.
+    function_definitions: FxHashSet<cst::Id>,
+}
+impl Builder<'_> {
+    fn add(
+        &mut self,
+        range: Range<Offset>,
+        type_: SemanticTokenType,
+        modifiers: EnumSet<SemanticTokenModifier>,
+    ) {
+        self.tokens.add(range, type_, modifiers);
+    }
+}
+fn function_definitions(db: &dyn HirDb, module: Module) -> FxHashSet<cst::Id> {
+    db.definition_kinds(module)
+        .iter()
+        .filter(|(_, kind)| matches!(kind, DefinitionKind::Function { .. }))
+        .filter_map(|(id, _)| db.hir_to_cst_id(id))
+        .collect()
 }
 
 fn visit_csts(
-    builder: &mut SemanticTokensBuilder<'_>,
+    builder: &mut Builder<'_>,
     csts: &[Cst],
     token_type_for_identifier: Option<SemanticTokenType>,
 ) {
@@ -31,7 +61,7 @@ fn visit_csts(
     }
 }
 fn visit_cst(
-    builder: &mut SemanticTokensBuilder<'_>,
+    builder: &mut Builder<'_>,
     cst: &Cst,
     token_type_for_identifier: Option<SemanticTokenType>,
 ) {
@@ -52,7 +82,7 @@ fn visit_cst(
         | CstKind::ClosingBracket
         | CstKind::OpeningCurlyBrace
         | CstKind::ClosingCurlyBrace => {}
-        CstKind::Arrow => builder.add(
+        CstKind::Arrow | CstKind::If => builder.add(
             cst.data.span.clone(),
             SemanticTokenType::Operator,
             EnumSet::empty(),
@@ -236,10 +266,15 @@ fn visit_cst(
         }
         CstKind::MatchCase {
             pattern,
+            guard,
             arrow,
             body,
         } => {
             visit_cst(builder, pattern, None);
+            if let Some(box (keyword, condition)) = guard {
+                visit_cst(builder, keyword, None);
+                visit_cst(builder, condition, None);
+            }
             visit_cst(builder, arrow, None);
             visit_csts(builder, body, None);
         }
@@ -270,8 +305,14 @@ fn visit_cst(
                 visit_cst(builder, receiver, Some(SemanticTokenType::Function));
                 visit_csts(builder, arguments, Some(SemanticTokenType::Parameter));
             } else {
-                let token_type = if let [single] = body.as_slice()
-                    && single.unwrap_whitespace_and_comment().kind.is_function() {
+                let is_function = builder
+                    .function_definitions
+                    .contains(&left.unwrap_whitespace_and_comment().data.id)
+                    || matches!(
+                        body.as_slice(),
+                        [single] if single.unwrap_whitespace_and_comment().kind.is_function(),
+                    );
+                let token_type = if is_function {
                     SemanticTokenType::Function
                 } else {
                     SemanticTokenType::Variable
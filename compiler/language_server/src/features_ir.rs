@@ -4,12 +4,13 @@ use candy_backend_inkwell::LlvmIrDb;
 use candy_frontend::{
     ast_to_hir::{AstToHir, HirResult},
     cst_to_ast::{AstResult, CstToAst},
+    desugar::DesugaredAstResult,
     hir_to_mir::{ExecutionTarget, HirToMir, MirResult},
     lir_optimize::OptimizeLir,
     mir_optimize::{OptimizeMir, OptimizedMirResult},
     mir_to_lir::{LirResult, MirToLir},
     module::{Module, ModuleKind, PackagesPath},
-    position::{line_start_offsets_raw, Offset},
+    position::{LineIndex, Offset},
     rich_ir::{
         ReferenceCollection, ReferenceKey, RichIr, RichIrBuilder, ToRichIr, TokenModifier,
         TokenType,
@@ -105,6 +106,10 @@ impl IrFeatures {
         let ir = match &config.ir {
             Ir::Rcst => Self::rich_ir_for_rcst(&config.module, db.rcst(config.module.clone())),
             Ir::Ast => Self::rich_ir_for_ast(&config.module, db.ast(config.module.clone())),
+            Ir::DesugaredAst => Self::rich_ir_for_desugared_ast(
+                &config.module,
+                db.desugared_ast(config.module.clone()),
+            ),
             Ir::Hir => Self::rich_ir_for_hir(&config.module, db.hir(config.module.clone())),
             Ir::Mir(tracing_config) => Self::rich_ir_for_mir(
                 &config.module,
@@ -154,11 +159,11 @@ impl IrFeatures {
                 .unwrap(),
         };
 
-        let line_start_offsets = line_start_offsets_raw(&ir.text);
+        let line_index = LineIndex::new(&ir.text);
         OpenIr {
             config,
             ir,
-            line_start_offsets,
+            line_index,
         }
     }
     fn rich_ir_for_rcst(module: &Module, rcst: RcstResult) -> RichIr {
@@ -173,6 +178,12 @@ impl IrFeatures {
             Err(error) => Self::build_rich_ir_for_module_error(builder, module, error),
         })
     }
+    fn rich_ir_for_desugared_ast(module: &Module, asts: DesugaredAstResult) -> RichIr {
+        Self::rich_ir_for("Desugared AST", module, None, |builder| match asts {
+            Ok(asts) => asts.build_rich_ir(builder),
+            Err(error) => Self::build_rich_ir_for_module_error(builder, module, error),
+        })
+    }
     fn rich_ir_for_hir(module: &Module, hir: HirResult) -> RichIr {
         Self::rich_ir_for("HIR", module, None, |builder| match hir {
             Ok((hir, _)) => hir.build_rich_ir(builder),
@@ -282,7 +293,7 @@ impl IrFeatures {
 struct OpenIr {
     config: IrConfig,
     ir: RichIr,
-    line_start_offsets: Vec<Offset>,
+    line_index: LineIndex,
 }
 #[derive(Clone, Debug)]
 struct IrConfig {
@@ -329,6 +340,7 @@ impl IrConfig {
         let ir = match ir {
             IrDiscriminants::Rcst => Ir::Rcst,
             IrDiscriminants::Ast => Ir::Ast,
+            IrDiscriminants::DesugaredAst => Ir::DesugaredAst,
             IrDiscriminants::Hir => Ir::Hir,
             IrDiscriminants::Mir => Ir::Mir(tracing_config.expect("Tracing config is missing.")),
             IrDiscriminants::OptimizedMir => {
@@ -399,6 +411,7 @@ impl UrlFromIrConfig for Url {
 pub enum Ir {
     Rcst,
     Ast,
+    DesugaredAst,
     Hir,
     Mir(TracingConfig),
     OptimizedMir(TracingConfig),
@@ -411,7 +424,7 @@ pub enum Ir {
 impl Ir {
     const fn tracing_config(&self) -> Option<&TracingConfig> {
         match self {
-            Self::Rcst | Self::Ast | Self::Hir => None,
+            Self::Rcst | Self::Ast | Self::DesugaredAst | Self::Hir => None,
             Self::Mir(tracing_config)
             | Self::OptimizedMir(tracing_config)
             | Self::Lir(tracing_config)
@@ -678,7 +691,7 @@ impl OpenIr {
     }
 
     fn semantic_tokens(&self) -> Vec<SemanticToken> {
-        let mut builder = SemanticTokensBuilder::new(&self.ir.text, &self.line_start_offsets);
+        let mut builder = SemanticTokensBuilder::new(&self.line_index);
         for annotation in &self.ir.annotations {
             let Some(token_type) = annotation.token_type else {
                 continue;
@@ -697,10 +710,10 @@ impl OpenIr {
     }
 
     fn lsp_position_to_offset(&self, position: lsp_types::Position) -> Offset {
-        lsp_position_to_offset_raw(&self.ir.text, &self.line_start_offsets, position)
+        lsp_position_to_offset_raw(&self.line_index, position)
     }
     fn range_to_lsp_range(&self, range: &Range<Offset>) -> lsp_types::Range {
-        range_to_lsp_range_raw(&self.ir.text, &self.line_start_offsets, range)
+        range_to_lsp_range_raw(&self.line_index, range)
     }
 }
 
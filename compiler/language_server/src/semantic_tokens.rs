@@ -1,6 +1,6 @@
 use std::ops::Range;
 
-use candy_frontend::position::Offset;
+use candy_frontend::position::{LineIndex, Offset, PositionEncoding};
 use enumset::{EnumSet, EnumSetType};
 use lazy_static::lazy_static;
 use lsp_types::{Position, SemanticToken, SemanticTokensLegend};
@@ -77,20 +77,14 @@ impl SemanticTokenModifier {
 }
 
 pub struct SemanticTokensBuilder<'a> {
-    text: &'a str,
-    line_start_offsets: &'a [Offset],
+    line_index: &'a LineIndex,
     tokens: Vec<SemanticToken>,
     cursor: Position,
 }
 impl<'a> SemanticTokensBuilder<'a> {
-    pub fn new<S, L>(text: &'a S, line_start_offsets: &'a L) -> Self
-    where
-        S: AsRef<str>,
-        L: AsRef<[Offset]>,
-    {
+    pub fn new(line_index: &'a LineIndex) -> Self {
         Self {
-            text: text.as_ref(),
-            line_start_offsets: line_start_offsets.as_ref(),
+            line_index,
             tokens: Vec::new(),
             cursor: Position::new(0, 0),
         }
@@ -103,15 +97,15 @@ impl<'a> SemanticTokensBuilder<'a> {
         modifiers: EnumSet<SemanticTokenModifier>,
     ) {
         // Reduce the token to multiple single-line tokens.
-        let mut range = range_to_lsp_range_raw(self.text, self.line_start_offsets, &range);
+        let mut range = range_to_lsp_range_raw(self.line_index, &range);
 
         if range.start.line != range.end.line {
             while range.start.line != range.end.line {
                 assert!(range.start.line < range.end.line);
 
-                let line_length = *self.line_start_offsets[(range.start.line as usize) + 1]
-                    - *self.line_start_offsets[range.start.line as usize]
-                    - 1;
+                let line_length = self
+                    .line_index
+                    .line_length(range.start.line as usize, PositionEncoding::Utf16);
                 self.add_single_line(
                     range.start,
                     line_length.try_into().unwrap(),
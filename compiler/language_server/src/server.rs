@@ -1,9 +1,9 @@
 use crate::{
     database::Database,
     debug_adapter::DebugSessionManager,
-    features::{LanguageFeatures, Reference, RenameError},
+    features::{DocumentOutOfSync, LanguageFeatures, Reference, RenameError},
     features_candy::{
-        analyzer::{insights::Hint, HintsNotification},
+        analyzer::{insights::Hint, HintsNotification, PullModel, ReportedDiagnostics},
         CandyFeatures, ServerStatusNotification,
     },
     features_ir::{IrFeatures, UpdateIrNotification},
@@ -11,30 +11,40 @@ use crate::{
     utils::{module_from_url, module_to_url},
 };
 use async_trait::async_trait;
+use candy_formatter::{CallArgumentsLayout, FormatterOptions, LambdaBodyLayout};
 use candy_frontend::module::{Module, ModuleKind, PackagesPath};
 use lsp_types::{
-    Diagnostic, DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
-    DocumentFilter, DocumentFormattingParams, DocumentHighlight, DocumentHighlightKind,
-    DocumentHighlightParams, FoldingRange, FoldingRangeParams, GotoDefinitionParams,
-    GotoDefinitionResponse, InitializeParams, InitializeResult, InitializedParams, Location,
-    MessageType, Position, PrepareRenameResponse, ReferenceParams, Registration, RenameOptions,
-    RenameParams, SemanticTokens, SemanticTokensFullOptions, SemanticTokensOptions,
-    SemanticTokensParams, SemanticTokensRegistrationOptions, SemanticTokensResult,
-    SemanticTokensServerCapabilities, ServerCapabilities, ServerInfo, StaticRegistrationOptions,
+    notification::Notification, CodeAction, CodeActionKind, CodeActionOptions, CodeActionOrCommand,
+    CodeActionParams, CodeActionResponse, CodeLens, CodeLensOptions, CodeLensParams, Diagnostic,
+    DiagnosticOptions, DiagnosticRegistrationOptions, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentDiagnosticParams,
+    DocumentDiagnosticReportResult, DocumentFilter, DocumentFormattingParams, DocumentHighlight,
+    DocumentHighlightKind, DocumentHighlightParams, FileOperationFilter, FileOperationPattern,
+    FileOperationRegistrationOptions, FoldingRange, FoldingRangeParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverParams, InitializeParams, InitializeResult,
+    InitializedParams, Location, MessageType, Position, PrepareRenameResponse, ReferenceParams,
+    Registration, RenameFilesParams, RenameOptions, RenameParams, SemanticTokens,
+    SemanticTokensFullOptions, SemanticTokensOptions, SemanticTokensParams,
+    SemanticTokensRegistrationOptions, SemanticTokensResult, SemanticTokensServerCapabilities,
+    ServerCapabilities, ServerInfo, StaticRegistrationOptions,
     TextDocumentChangeRegistrationOptions, TextDocumentPositionParams,
     TextDocumentRegistrationOptions, TextEdit, Url, WorkDoneProgressOptions, WorkspaceEdit,
 };
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, mem};
+use std::{borrow::Cow, collections::HashMap, mem};
 use tokio::sync::{Mutex, RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard};
 use tower_lsp::{jsonrpc, Client, ClientSocket, LanguageServer, LspService};
-use tracing::{debug, span, Level};
+use tracing::{debug, span, warn, Level};
 
 pub struct Server {
     pub client: Client,
     pub db: Mutex<Database>,
     pub state: RwLock<ServerState>,
+    /// The version of each open document that the database's content belongs
+    /// to. `None` means that the content is out of sync and we wait for the
+    /// client to send the full content.
+    document_versions: Mutex<FxHashMap<Url, Option<i32>>>,
 }
 #[derive(Debug)]
 pub enum ServerState {
@@ -120,11 +130,36 @@ impl ServerFeatures {
             document_selector: Some(self.selectors_where(filter)),
         }
     }
+    /// Matches all files and folders since assets can be `use`d as well.
+    fn file_operation_registration_options_where<F>(
+        &self,
+        mut filter: F,
+    ) -> FileOperationRegistrationOptions
+    where
+        F: FnMut(&dyn LanguageFeatures) -> bool,
+    {
+        let filters = self
+            .all_features()
+            .into_iter()
+            .filter(|it| filter(*it))
+            .flat_map(LanguageFeatures::supported_url_schemes)
+            .map(|scheme| FileOperationFilter {
+                scheme: Some(scheme.to_owned()),
+                pattern: FileOperationPattern {
+                    glob: "**/*".to_string(),
+                    matches: None,
+                    options: None,
+                },
+            })
+            .collect();
+        FileOperationRegistrationOptions { filters }
+    }
 }
 
 pub struct AnalyzerClient {
     client: Client,
     packages_path: PackagesPath,
+    diagnostics: ReportedDiagnostics,
 }
 impl AnalyzerClient {
     pub async fn update_status(&self, status: Option<String>) {
@@ -134,14 +169,40 @@ impl AnalyzerClient {
             })
             .await;
     }
-    pub async fn update_diagnostics(&self, module: Module, diagnostics: Vec<Diagnostic>) {
-        self.client
-            .publish_diagnostics(
-                module_to_url(&module, &self.packages_path).unwrap(),
-                diagnostics,
-                None,
-            )
-            .await;
+    #[must_use]
+    pub const fn reported_diagnostics(&self) -> &ReportedDiagnostics {
+        &self.diagnostics
+    }
+    pub async fn update_diagnostics(
+        &self,
+        module: Module,
+        version: Option<i32>,
+        diagnostics: Vec<Diagnostic>,
+    ) {
+        let uri = module_to_url(&module, &self.packages_path).unwrap();
+        match self
+            .diagnostics
+            .update(module, version, diagnostics.clone())
+        {
+            None => {
+                self.client
+                    .publish_diagnostics(uri, diagnostics, version)
+                    .await;
+            }
+            Some(PullModel {
+                supports_refresh: true,
+            }) => {
+                if let Err(error) = self.client.workspace_diagnostic_refresh().await {
+                    warn!("Couldn't ask the client to pull diagnostics again: {error}");
+                }
+            }
+            Some(PullModel {
+                supports_refresh: false,
+            }) => {}
+        }
+    }
+    pub fn forget_diagnostics(&self, module: &Module) {
+        self.diagnostics.remove(module);
     }
     pub async fn update_hints(&self, module: Module, hints: Vec<Hint>) {
         self.client
@@ -163,6 +224,7 @@ impl Server {
                         AnalyzerClient {
                             client: client.clone(),
                             packages_path: packages_path.clone(),
+                            diagnostics: ReportedDiagnostics::default(),
                         },
                     ),
                     ir: IrFeatures::default(),
@@ -176,6 +238,7 @@ impl Server {
                     packages_path,
                 )),
                 state: RwLock::new(state),
+                document_versions: Mutex::default(),
             }
         })
         .custom_method(
@@ -252,6 +315,77 @@ impl LanguageServer for Server {
             }
         };
 
+        let lambda_body_layout = params
+            .initialization_options
+            .as_ref()
+            .and_then(|options| options.pointer("/formatter/lambdaBodyLayout"))
+            .and_then(serde_json::Value::as_str);
+        let lambda_body_layout = match lambda_body_layout {
+            None | Some("collapseIfFits") => LambdaBodyLayout::CollapseIfFits,
+            Some("alwaysExpand") => LambdaBodyLayout::AlwaysExpand,
+            Some("preserve") => LambdaBodyLayout::Preserve,
+            Some(other) => {
+                let message = format!(
+                    "Unknown lambda body layout `{other}`, using `collapseIfFits` instead."
+                );
+                self.client
+                    .show_message(MessageType::WARNING, message)
+                    .await;
+                LambdaBodyLayout::CollapseIfFits
+            }
+        };
+        let call_arguments_layout = params
+            .initialization_options
+            .as_ref()
+            .and_then(|options| options.pointer("/formatter/callArgumentsLayout"))
+            .and_then(serde_json::Value::as_str);
+        let call_arguments_layout = match call_arguments_layout {
+            None | Some("onePerLine") => CallArgumentsLayout::OnePerLine,
+            Some("packed") => CallArgumentsLayout::Packed,
+            Some(other) => {
+                let message =
+                    format!("Unknown call arguments layout `{other}`, using `onePerLine` instead.");
+                self.client
+                    .show_message(MessageType::WARNING, message)
+                    .await;
+                CallArgumentsLayout::OnePerLine
+            }
+        };
+        let organize_uses = params
+            .initialization_options
+            .as_ref()
+            .and_then(|options| options.pointer("/formatter/organizeUses"))
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or_default();
+        self.db.lock().await.formatter_options = FormatterOptions {
+            lambda_body_layout,
+            call_arguments_layout,
+            organize_uses,
+        };
+
+        // Like all other features, pulled diagnostics are registered
+        // dynamically.
+        let supports_pulling_diagnostics = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|it| it.diagnostic.as_ref())
+            .and_then(|it| it.dynamic_registration)
+            .unwrap_or_default();
+        if supports_pulling_diagnostics {
+            let supports_refresh = params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|it| it.diagnostic.as_ref())
+                .and_then(|it| it.refresh_support)
+                .unwrap_or_default();
+            self.require_features()
+                .await
+                .candy
+                .use_pull_diagnostics(PullModel { supports_refresh });
+        }
+
         {
             let mut state = self.state.write().await;
             let owned_state = mem::replace(&mut *state, ServerState::Shutdown);
@@ -279,6 +413,8 @@ impl LanguageServer for Server {
         })
     }
 
+    // TODO: Fix lifetimes and remove this allow
+    #[allow(clippy::redundant_closure_for_method_calls)]
     async fn initialized(&self, _: InitializedParams) {
         fn registration(method: &'static str, options: impl Serialize) -> Registration {
             Registration {
@@ -293,80 +429,141 @@ impl LanguageServer for Server {
         let state = self.state.read().await;
         let features = state.require_features();
 
-        // TODO: Fix lifetimes and remove this allow
-        #[allow(clippy::redundant_closure_for_method_calls)]
-        self.client
-            .register_capability(vec![
-                registration(
-                    "textDocument/didOpen",
-                    features.registration_options_where(|it| it.supports_did_open()),
-                ),
-                registration(
-                    "textDocument/didChange",
-                    TextDocumentChangeRegistrationOptions {
-                        document_selector: Some(
-                            features.selectors_where(|it| it.supports_did_change()),
-                        ),
-                        sync_kind: 2, // incremental
+        let mut registrations = vec![
+            registration(
+                "textDocument/didOpen",
+                features.registration_options_where(|it| it.supports_did_open()),
+            ),
+            registration(
+                "textDocument/didChange",
+                TextDocumentChangeRegistrationOptions {
+                    document_selector: Some(
+                        features.selectors_where(|it| it.supports_did_change()),
+                    ),
+                    sync_kind: 2, // incremental
+                },
+            ),
+            registration(
+                "textDocument/didClose",
+                features.registration_options_where(|it| it.supports_did_close()),
+            ),
+            registration(
+                "textDocument/definition",
+                features.registration_options_where(|it| it.supports_find_definition()),
+            ),
+            registration(
+                "textDocument/hover",
+                features.registration_options_where(|it| it.supports_hover()),
+            ),
+            registration(
+                "textDocument/references",
+                features.registration_options_where(|it| it.supports_references()),
+            ),
+            registration(
+                "textDocument/documentHighlight",
+                features.registration_options_where(|it| it.supports_references()),
+            ),
+            registration(
+                "textDocument/foldingRange",
+                features.registration_options_where(|it| it.supports_folding_ranges()),
+            ),
+            registration(
+                "textDocument/codeLens",
+                CodeLensRegistrationOptions {
+                    text_document_registration_options: features
+                        .registration_options_where(|it| it.supports_code_lenses()),
+                    code_lens_options: CodeLensOptions {
+                        resolve_provider: Some(false),
                     },
-                ),
-                registration(
-                    "textDocument/didClose",
-                    features.registration_options_where(|it| it.supports_did_close()),
-                ),
-                registration(
-                    "textDocument/definition",
-                    features.registration_options_where(|it| it.supports_find_definition()),
-                ),
-                registration(
-                    "textDocument/references",
-                    features.registration_options_where(|it| it.supports_references()),
-                ),
-                registration(
-                    "textDocument/documentHighlight",
-                    features.registration_options_where(|it| it.supports_references()),
-                ),
-                registration(
-                    "textDocument/foldingRange",
-                    features.registration_options_where(|it| it.supports_folding_ranges()),
-                ),
-                registration(
-                    "textDocument/formatting",
-                    features.registration_options_where(|it| it.supports_format()),
-                ),
-                registration(
-                    "textDocument/rename",
-                    RenameRegistrationOptions {
+                },
+            ),
+            registration(
+                "textDocument/formatting",
+                features.registration_options_where(|it| it.supports_format()),
+            ),
+            registration(
+                "textDocument/codeAction",
+                CodeActionRegistrationOptions {
+                    text_document_registration_options: features.registration_options_where(|it| {
+                        it.supports_organize_imports() || it.supports_refactorings()
+                    }),
+                    code_action_options: CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                            CodeActionKind::REFACTOR_EXTRACT,
+                            CodeActionKind::REFACTOR_INLINE,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            CodeActionKind::QUICKFIX,
+                        ]),
+                        work_done_progress_options: WorkDoneProgressOptions {
+                            work_done_progress: None,
+                        },
+                        resolve_provider: None,
+                    },
+                },
+            ),
+            registration(
+                "textDocument/rename",
+                RenameRegistrationOptions {
+                    text_document_registration_options: features
+                        .registration_options_where(|it| it.supports_rename()),
+                    rename_options: RenameOptions {
+                        prepare_provider: Some(true),
+                        work_done_progress_options: WorkDoneProgressOptions {
+                            work_done_progress: None,
+                        },
+                    },
+                },
+            ),
+            registration(
+                "workspace/willRenameFiles",
+                features.file_operation_registration_options_where(|it| it.supports_rename_files()),
+            ),
+            registration(
+                "workspace/didRenameFiles",
+                features.file_operation_registration_options_where(|it| it.supports_rename_files()),
+            ),
+            registration(
+                "textDocument/semanticTokens",
+                SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
+                    SemanticTokensRegistrationOptions {
                         text_document_registration_options: features
-                            .registration_options_where(|it| it.supports_rename()),
-                        rename_options: RenameOptions {
-                            prepare_provider: Some(true),
+                            .registration_options_where(|it| it.supports_semantic_tokens()),
+                        semantic_tokens_options: SemanticTokensOptions {
                             work_done_progress_options: WorkDoneProgressOptions {
                                 work_done_progress: None,
                             },
+                            legend: semantic_tokens::LEGEND.clone(),
+                            // TODO
+                            range: Some(false),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
                         },
+                        static_registration_options: StaticRegistrationOptions { id: None },
                     },
                 ),
-                registration(
-                    "textDocument/semanticTokens",
-                    SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
-                        SemanticTokensRegistrationOptions {
-                            text_document_registration_options: features
-                                .registration_options_where(|it| it.supports_semantic_tokens()),
-                            semantic_tokens_options: SemanticTokensOptions {
-                                work_done_progress_options: WorkDoneProgressOptions {
-                                    work_done_progress: None,
-                                },
-                                legend: semantic_tokens::LEGEND.clone(),
-                                // TODO
-                                range: Some(false),
-                                full: Some(SemanticTokensFullOptions::Bool(true)),
-                            },
-                            static_registration_options: StaticRegistrationOptions { id: None },
+            ),
+        ];
+        if features.candy.pull_model().is_some() {
+            registrations.push(registration(
+                "textDocument/diagnostic",
+                DiagnosticRegistrationOptions {
+                    text_document_registration_options: features
+                        .registration_options_where(|it| it.supports_pull_diagnostics()),
+                    diagnostic_options: DiagnosticOptions {
+                        identifier: None,
+                        // Diagnostics change when used modules change.
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: false,
+                        work_done_progress_options: WorkDoneProgressOptions {
+                            work_done_progress: None,
                         },
-                    ),
-                ),
-            ])
+                    },
+                    static_registration_options: StaticRegistrationOptions { id: None },
+                },
+            ));
+        }
+        self.client
+            .register_capability(registrations)
             .await
             .expect("Dynamic capability registration failed.");
         self.client
@@ -390,27 +587,39 @@ impl LanguageServer for Server {
         let features = self.features_from_url(&state.features, &params.text_document.uri);
         assert!(features.supports_did_open());
         let content = params.text_document.text.into_bytes();
+        let version = params.text_document.version;
+        self.document_versions
+            .lock()
+            .await
+            .insert(params.text_document.uri.clone(), Some(version));
         features
-            .did_open(&self.db, params.text_document.uri, content)
+            .did_open(&self.db, params.text_document.uri, content, version)
             .await;
     }
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let state = self.require_running_state().await;
-        {
-            let features = self.features_from_url(&state.features, &params.text_document.uri);
-            assert!(features.supports_did_change());
-            features
-                .did_change(
-                    &self.db,
-                    params.text_document.uri.clone(),
-                    params.content_changes,
-                )
-                .await;
-        };
+        let uri = params.text_document.uri.clone();
+        match self.apply_document_changes(&state.features, params).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(DocumentOutOfSync { reason }) => {
+                warn!("Document {uri} is out of sync: {reason} Requesting its full content.");
+                self.document_versions
+                    .lock()
+                    .await
+                    .insert(uri.clone(), None);
+                self.client
+                    .send_notification::<ResyncDocumentNotification>(ResyncDocumentNotification {
+                        uri,
+                    })
+                    .await;
+                return;
+            }
+        }
 
         let module_result = module_from_url(
-            &params.text_document.uri,
-            if std::path::Path::new(params.text_document.uri.path())
+            &uri,
+            if std::path::Path::new(uri.path())
                 .extension()
                 .map_or(false, |ext| ext.eq_ignore_ascii_case("candy"))
             {
@@ -440,9 +649,42 @@ impl LanguageServer for Server {
         let state = self.require_running_state().await;
         let features = self.features_from_url(&state.features, &params.text_document.uri);
         assert!(features.supports_did_close());
+        self.document_versions
+            .lock()
+            .await
+            .remove(&params.text_document.uri);
         features.did_close(&self.db, params.text_document.uri).await;
     }
 
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> jsonrpc::Result<DocumentDiagnosticReportResult> {
+        let state = self.require_running_state().await;
+        let uri = params.text_document.uri;
+        let features = self.features_from_url(&state.features, &uri);
+        assert!(features.supports_pull_diagnostics());
+        let version = self
+            .document_versions
+            .lock()
+            .await
+            .get(&uri)
+            .copied()
+            .flatten();
+        let report = features
+            .pull_diagnostics(&self.db, uri, version, params.previous_result_id)
+            .await;
+        report
+            .map(DocumentDiagnosticReportResult::Report)
+            .ok_or_else(|| jsonrpc::Error {
+                // `ServerCancelled` from LSP 3.17. We ask the client to pull
+                // again once the analyzer caught up.
+                code: jsonrpc::ErrorCode::ServerError(-32802),
+                message: "The diagnostics for this version aren't available yet.".into(),
+                data: Some(serde_json::json!({ "retriggerRequest": false })),
+            })
+    }
+
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
@@ -464,6 +706,23 @@ impl LanguageServer for Server {
         Ok(response)
     }
 
+    async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+        let state = self.require_running_state().await;
+        let features = self.features_from_url(
+            &state.features,
+            &params.text_document_position_params.text_document.uri,
+        );
+        assert!(features.supports_hover());
+        let response = features
+            .hover(
+                &self.db,
+                params.text_document_position_params.text_document.uri,
+                params.text_document_position_params.position,
+            )
+            .await;
+        Ok(response)
+    }
+
     async fn references(&self, params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
         let uri = params.text_document_position.text_document.uri;
         let highlights = self
@@ -543,6 +802,62 @@ impl LanguageServer for Server {
         ))
     }
 
+    async fn code_lens(&self, params: CodeLensParams) -> jsonrpc::Result<Option<Vec<CodeLens>>> {
+        let state = self.require_running_state().await;
+        let features = self.features_from_url(&state.features, &params.text_document.uri);
+        assert!(features.supports_code_lenses());
+        Ok(Some(
+            features
+                .code_lenses(&self.db, params.text_document.uri)
+                .await,
+        ))
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        let state = self.require_running_state().await;
+        let uri = params.text_document.uri;
+        let features = self.features_from_url(&state.features, &uri);
+        let is_requested = |kind: &CodeActionKind| {
+            params.context.only.as_ref().map_or(true, |only| {
+                only.iter().any(|it| kind.as_str().starts_with(it.as_str()))
+            })
+        };
+
+        let mut actions = vec![];
+        if features.supports_organize_imports()
+            && is_requested(&CodeActionKind::SOURCE_ORGANIZE_IMPORTS)
+        {
+            let edits = features.organize_imports(&self.db, uri.clone()).await;
+            if !edits.is_empty() {
+                actions.push(code_action(
+                    "Organize uses".to_string(),
+                    CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                    uri.clone(),
+                    edits,
+                ));
+            }
+        }
+        if features.supports_refactorings() {
+            let refactorings = features
+                .refactorings(&self.db, uri.clone(), params.range)
+                .await;
+            actions.extend(
+                refactorings
+                    .into_iter()
+                    .filter(|it| is_requested(&it.kind))
+                    .map(|it| code_action(it.title, it.kind, uri.clone(), it.edits)),
+            );
+        }
+        Ok(if actions.is_empty() {
+            None
+        } else {
+            Some(actions)
+        })
+    }
+
     async fn prepare_rename(
         &self,
         params: TextDocumentPositionParams,
@@ -580,6 +895,37 @@ impl LanguageServer for Server {
         }
     }
 
+    async fn will_rename_files(
+        &self,
+        params: RenameFilesParams,
+    ) -> jsonrpc::Result<Option<WorkspaceEdit>> {
+        let state = self.require_running_state().await;
+        let renames = file_renames(params);
+        let mut changes = HashMap::new();
+        for features in state.features.all_features() {
+            if features.supports_rename_files() {
+                changes.extend(features.will_rename_files(&self.db, renames.clone()).await);
+            }
+        }
+        Ok(if changes.is_empty() {
+            None
+        } else {
+            Some(WorkspaceEdit {
+                changes: Some(changes),
+                ..Default::default()
+            })
+        })
+    }
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        let state = self.require_running_state().await;
+        let renames = file_renames(params);
+        for features in state.features.all_features() {
+            if features.supports_rename_files() {
+                features.did_rename_files(&self.db, renames.clone()).await;
+            }
+        }
+    }
+
     async fn semantic_tokens_full(
         &self,
         params: SemanticTokensParams,
@@ -595,7 +941,71 @@ impl LanguageServer for Server {
         })))
     }
 }
+fn file_renames(params: RenameFilesParams) -> Vec<(Url, Url)> {
+    params
+        .files
+        .into_iter()
+        .filter_map(|it| Some((Url::parse(&it.old_uri).ok()?, Url::parse(&it.new_uri).ok()?)))
+        .collect()
+}
+fn code_action(
+    title: String,
+    kind: CodeActionKind,
+    uri: Url,
+    edits: Vec<TextEdit>,
+) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(kind),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, edits)])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
 impl Server {
+    /// Returns whether the changes were applied. While we wait for the full
+    /// content of an out-of-sync document, incremental changes are ignored.
+    async fn apply_document_changes(
+        &self,
+        features: &ServerFeatures,
+        params: DidChangeTextDocumentParams,
+    ) -> Result<bool, DocumentOutOfSync> {
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        // Changes without a range contain the full content, so they also
+        // resynchronize documents.
+        let contains_full_content = params.content_changes.iter().any(|it| it.range.is_none());
+        let previous_version = self.document_versions.lock().await.get(&uri).copied();
+        match previous_version {
+            _ if contains_full_content => {}
+            Some(Some(previous_version)) if version > previous_version => {}
+            Some(Some(previous_version)) => {
+                return Err(DocumentOutOfSync {
+                    reason: format!("Expected a version after {previous_version}, got {version}."),
+                });
+            }
+            Some(None) => return Ok(false),
+            None => {
+                return Err(DocumentOutOfSync {
+                    reason: "The document isn't open.".to_string(),
+                });
+            }
+        }
+
+        let features = self.features_from_url(features, &uri);
+        assert!(features.supports_did_change());
+        features
+            .did_change(&self.db, uri.clone(), params.content_changes, version)
+            .await?;
+        self.document_versions
+            .lock()
+            .await
+            .insert(uri, Some(version));
+        Ok(true)
+    }
+
     async fn references_raw(
         &self,
         uri: Url,
@@ -619,6 +1029,29 @@ impl Server {
     }
 }
 
+/// Asks the client to send the full content of a document in a
+/// `textDocument/didChange` notification because the server's copy diverged.
+#[derive(Serialize, Deserialize)]
+pub struct ResyncDocumentNotification {
+    pub uri: Url,
+}
+impl Notification for ResyncDocumentNotification {
+    const METHOD: &'static str = "candy/textDocument/resync";
+
+    type Params = Self;
+}
+
+/// <https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#codeLensRegistrationOptions>
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeLensRegistrationOptions {
+    #[serde(flatten)]
+    pub text_document_registration_options: TextDocumentRegistrationOptions,
+
+    #[serde(flatten)]
+    pub code_lens_options: CodeLensOptions,
+}
+
 /// <https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#renameRegistrationOptions>
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -629,3 +1062,14 @@ pub struct RenameRegistrationOptions {
     #[serde(flatten)]
     pub rename_options: RenameOptions,
 }
+
+/// <https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#codeActionRegistrationOptions>
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeActionRegistrationOptions {
+    #[serde(flatten)]
+    pub text_document_registration_options: TextDocumentRegistrationOptions,
+
+    #[serde(flatten)]
+    pub code_action_options: CodeActionOptions,
+}
@@ -3,7 +3,7 @@ use candy_frontend::{
     cst::CstDb,
     error::CompilerError,
     module::{Module, ModuleDb, ModuleKind, Package, PackagesPath},
-    position::{line_start_offsets_raw, Offset, PositionConversionDb},
+    position::{self, LineIndex, Offset, PositionConversionDb, PositionEncoding},
 };
 use extension_trait::extension_trait;
 use itertools::Itertools;
@@ -87,101 +87,59 @@ pub fn module_to_url(module: &Module, packages_path: &PackagesPath) -> Option<Ur
 #[extension_trait]
 pub impl<DB: ModuleDb + PositionConversionDb + ?Sized> LspPositionConversion for DB {
     fn lsp_position_to_offset(&self, module: Module, position: Position) -> Offset {
-        let text = self.get_module_content_as_string(module.clone()).unwrap();
-        let line_start_offsets = self.line_start_offsets(module);
-        lsp_position_to_offset_raw(&text, &line_start_offsets, position)
+        lsp_position_to_offset_raw(&self.line_index(module), position)
     }
 
     fn range_to_lsp_range(&self, module: Module, range: Range<Offset>) -> lsp_types::Range {
-        lsp_types::Range {
-            start: self.offset_to_lsp_position(module.clone(), range.start),
-            end: self.offset_to_lsp_position(module, range.end),
-        }
+        range_to_lsp_range_raw(&self.line_index(module), &range)
     }
     fn offset_to_lsp_position(&self, module: Module, offset: Offset) -> Position {
-        let text = self.get_module_content_as_string(module.clone()).unwrap();
-        let line_start_offsets = self.line_start_offsets(module);
-        offset_to_lsp_position_raw(&*text, &*line_start_offsets, offset)
+        offset_to_lsp_position_raw(&self.line_index(module), offset)
     }
 }
 
+/// Returns `None` if the range lies outside of the text or ends before it
+/// starts.
 #[must_use]
-pub fn lsp_range_to_range_raw(text: &str, range: lsp_types::Range) -> Range<Offset> {
-    let line_start_offsets = line_start_offsets_raw(text);
-    let start = lsp_position_to_offset_raw(text, &line_start_offsets, range.start);
-    let end = lsp_position_to_offset_raw(text, &line_start_offsets, range.end);
-    start..end
-}
-#[must_use]
-pub fn lsp_position_to_offset_raw(
-    text: &str,
-    line_start_offsets: &[Offset],
-    position: Position,
-) -> Offset {
-    let line_offset = line_start_offsets[position.line as usize];
-    let line_length = if position.line as usize == line_start_offsets.len() - 1 {
-        text.len() - *line_offset
-    } else {
-        *line_start_offsets[(position.line + 1) as usize] - *line_offset
-    };
-
-    let line = &text[*line_offset..*line_offset + line_length];
-
-    let words = line.encode_utf16().collect::<Vec<_>>();
-    let char_offset = if position.character as usize >= words.len() {
-        line_length
-    } else {
-        String::from_utf16(&words[0..position.character as usize])
-            .unwrap()
-            .len()
+pub fn lsp_range_to_range_raw(text: &str, range: lsp_types::Range) -> Option<Range<Offset>> {
+    let line_index = LineIndex::new(text);
+    let is_valid = |position: Position| {
+        let line = position.line as usize;
+        line < line_index.line_count()
+            && position.character as usize <= line_index.line_length(line, PositionEncoding::Utf16)
     };
+    if !is_valid(range.start) || !is_valid(range.end) {
+        return None;
+    }
 
-    Offset(*line_offset + char_offset)
+    let start = lsp_position_to_offset_raw(&line_index, range.start);
+    let end = lsp_position_to_offset_raw(&line_index, range.end);
+    (start <= end).then_some(start..end)
+}
+#[must_use]
+pub fn lsp_position_to_offset_raw(line_index: &LineIndex, position: Position) -> Offset {
+    line_index.position_to_offset(
+        position::Position {
+            line: position.line as usize,
+            character: position.character as usize,
+        },
+        PositionEncoding::Utf16,
+    )
 }
 
 #[must_use]
-pub fn range_to_lsp_range_raw<S, L>(
-    text: S,
-    line_start_offsets: L,
-    range: &Range<Offset>,
-) -> lsp_types::Range
-where
-    S: AsRef<str>,
-    L: AsRef<[Offset]>,
-{
-    let text = text.as_ref();
-    let line_start_offsets = line_start_offsets.as_ref();
+pub fn range_to_lsp_range_raw(line_index: &LineIndex, range: &Range<Offset>) -> lsp_types::Range {
     lsp_types::Range {
-        start: offset_to_lsp_position_raw(text, line_start_offsets, range.start),
-        end: offset_to_lsp_position_raw(text, line_start_offsets, range.end),
+        start: offset_to_lsp_position_raw(line_index, range.start),
+        end: offset_to_lsp_position_raw(line_index, range.end),
     }
 }
 #[must_use]
-pub fn offset_to_lsp_position_raw<S, L>(
-    text: S,
-    line_start_offsets: L,
-    mut offset: Offset,
-) -> Position
-where
-    S: AsRef<str>,
-    L: AsRef<[Offset]>,
-{
-    let text = text.as_ref();
-    let line_start_offsets = line_start_offsets.as_ref();
-
-    if *offset > text.len() {
-        *offset = text.len();
-    }
-
-    let line = line_start_offsets
-        .binary_search(&offset)
-        .unwrap_or_else(|i| i - 1);
-
-    let line_start = line_start_offsets[line];
-    let character_utf16_offset = text[*line_start..*offset].encode_utf16().count();
+pub fn offset_to_lsp_position_raw(line_index: &LineIndex, offset: Offset) -> Position {
+    let position = line_index.offset_to_position(offset, PositionEncoding::Utf16);
     Position {
-        line: line.try_into().unwrap(),
-        character: character_utf16_offset.try_into().unwrap(),
+        line: position.line.try_into().unwrap(),
+        character: position.character.try_into().unwrap(),
     }
 }
 
@@ -203,3 +161,51 @@ impl<S: AsRef<str>> JoinWithCommasAndAnd for [S] {
         }
     }
 }
+
+#[must_use]
+pub fn pluralize(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lsp_range_to_range_raw;
+    use candy_frontend::position::Offset;
+    use lsp_types::{Position, Range};
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn ranges_within_the_text_are_converted() {
+        assert_eq!(
+            lsp_range_to_range_raw("ab\ncd", range((0, 1), (1, 1))),
+            Some(Offset(1)..Offset(4)),
+        );
+        assert_eq!(
+            lsp_range_to_range_raw("ab\ncd", range((1, 2), (1, 2))),
+            Some(Offset(5)..Offset(5)),
+        );
+    }
+
+    #[test]
+    fn ranges_that_dont_fit_the_text_are_rejected() {
+        assert_eq!(
+            lsp_range_to_range_raw("ab\ncd", range((0, 3), (1, 0))),
+            None
+        );
+        assert_eq!(
+            lsp_range_to_range_raw("ab\ncd", range((0, 0), (2, 0))),
+            None
+        );
+        assert_eq!(
+            lsp_range_to_range_raw("ab\ncd", range((1, 0), (0, 0))),
+            None
+        );
+    }
+}
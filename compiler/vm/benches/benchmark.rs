@@ -50,6 +50,15 @@ fn benchmark_vm_runtime<M: Measurement>(c: &mut Criterion<M>, prefix: &str) {
     benchmark!("hello_world", r#"main _ := "Hello, world!""#, 100);
     benchmark!("fibonacci", 15, create_fibonacci_code, 20);
     benchmark!("PLB/binarytrees", 6, create_binary_trees_code, 10);
+    benchmark!("builtin_calls", 1000, create_builtin_calls_code, 20);
+    benchmark!("list_pipeline", 1000, create_list_pipeline_code, 20);
+    benchmark!(
+        "list_pipeline_via_iterable",
+        1000,
+        create_list_pipeline_via_iterable_code,
+        20,
+    );
+    benchmark!("text_processing", 1000, create_text_processing_code, 10);
 
     group.finish();
 }
@@ -118,6 +127,63 @@ main _ :=
     )
 }
 
+/// Mostly consists of calls to simple builtins like `✨.equals`, `✨.intAdd`,
+/// and `✨.structGet`, which the VM runs without the generic call machinery.
+fn create_builtin_calls_code(n: usize) -> String {
+    format!(
+        r#"[equals, ifElse, int, recursive] = use "Core"
+
+main _ :=
+  step = [Amount: 1]
+  recursive 0 {{ recurse index ->
+    ifElse (index | equals {n}) {{ index }} {{ recurse (index | int.add step.amount) }}
+  }}"#,
+    )
+}
+
+/// Maps, filters, and sums a list using the list builtins, which don't create
+/// intermediate lists for every item.
+fn create_list_pipeline_code(n: usize) -> String {
+    format!(
+        r#"[int, list] = use "Core"
+
+main _ :=
+  list.generate {n} {{ index -> index }}
+  | list.map {{ it -> it | int.multiply 3 }}
+  | list.where {{ it -> it | int.isEven }}
+  | list.foldLeft 0 {{ sum it -> sum | int.add it }}"#,
+    )
+}
+/// The same as [`create_list_pipeline_code`], but converting the list to an
+/// iterable and back after each step.
+fn create_list_pipeline_via_iterable_code(n: usize) -> String {
+    format!(
+        r#"[int, iterable, list] = use "Core"
+
+main _ :=
+  list.generate {n} {{ index -> index }}
+  | iterable.fromList | iterable.map {{ it -> it | int.multiply 3 }} | iterable.toList
+  | iterable.fromList | iterable.where {{ it -> it | int.isEven }} | iterable.toList
+  | iterable.fromList | iterable.foldLeft 0 {{ sum it -> sum | int.add it }}"#,
+    )
+}
+
+/// Splits a long text into words by repeatedly removing the first one, which
+/// creates a slice of the remaining text for every word.
+fn create_text_processing_code(n: usize) -> String {
+    let input = "word ".repeat(n);
+    format!(
+        r#"[ifElse, int, recursive, text] = use "Core"
+
+main _ :=
+  recursive [Rest: "{input}", Count: 0] {{ recurse state ->
+    ifElse (state.rest | text.isEmpty) {{ state.count }} {{
+      recurse [Rest: state.rest | text.removePrefix "word ", Count: state.count | int.add 1]
+    }}
+  }}"#,
+    )
+}
+
 trait BencherExtension {
     fn compile(&mut self, source_code: &str);
     fn run_vm(&mut self, source_code: &str);
@@ -92,10 +92,10 @@ pub fn setup() -> Database {
         TRACING.clone(),
     )
     .1;
-    if !errors.is_empty() {
-        for error in errors.iter() {
-            warn!("{}", error.to_string_with_location(&db));
-        }
+    for error in errors.iter() {
+        warn!("{}", error.to_string_with_location(&db));
+    }
+    if errors.iter().any(|it| !it.payload.is_warning()) {
         panic!("There are errors in the benchmarking code.");
     }
 
@@ -1,11 +1,15 @@
 use crate::{
-    heap::{Data, Function, Heap, HirId, InlineObject, Int, List, Struct, Tag, Text, ToDebugText},
+    heap::{
+        AddressMap, Data, Function, Heap, HirId, InlineObject, Int, List, Relocate, Struct, Tag,
+        Text, ToDebugText,
+    },
+    instruction_pointer::InstructionPointer,
     instructions::InstructionResult,
     vm::{CallHandle, MachineState, Panic},
 };
 use candy_frontend::{
     builtin_functions::BuiltinFunction,
-    format::{MaxLength, Precedence},
+    format::{MaxLength, NestingLimits, Precedence},
 };
 use derive_more::Deref;
 use itertools::Itertools;
@@ -49,10 +53,15 @@ impl MachineState {
             BuiltinFunction::IntShiftLeft => heap.int_shift_left(args),
             BuiltinFunction::IntShiftRight => heap.int_shift_right(args),
             BuiltinFunction::IntSubtract => heap.int_subtract(args),
+            BuiltinFunction::IteratorFoldLeft => Heap::iterator_fold_left(args),
+            BuiltinFunction::IteratorNext => Heap::iterator_next(args),
             BuiltinFunction::ListFilled => heap.list_filled(args),
+            BuiltinFunction::ListFilter => heap.list_filter(args),
+            BuiltinFunction::ListFoldLeft => heap.list_fold_left(args),
             BuiltinFunction::ListGet => heap.list_get(args),
             BuiltinFunction::ListInsert => heap.list_insert(args),
             BuiltinFunction::ListLength => heap.list_length(args),
+            BuiltinFunction::ListMap => heap.list_map(args),
             BuiltinFunction::ListRemoveAt => heap.list_remove_at(args),
             BuiltinFunction::ListReplace => heap.list_replace(args),
             BuiltinFunction::Print => heap.print(args),
@@ -74,9 +83,120 @@ impl MachineState {
             BuiltinFunction::TextTrimEnd => heap.text_trim_end(args),
             BuiltinFunction::TextTrimStart => heap.text_trim_start(args),
             BuiltinFunction::ToDebugText => heap.to_debug_text(args),
+            BuiltinFunction::ToDebugTextWithLimits => heap.to_debug_text_with_limits(args),
             BuiltinFunction::TypeOf => heap.type_of(args),
         });
+        self.handle_builtin_result(heap, result, responsible)
+    }
+
+    /// Calls of simple builtins like `✨.intAdd` or `✨.structGet` are common
+    /// and don't need the generic call machinery: These builtins only ever
+    /// return a value (or panic), so we run them on the arguments directly on
+    /// the data stack, without collecting them into a `Vec` first.
+    ///
+    /// The stack has to contain the callee, the arguments, and the responsible
+    /// HIR ID on top, as for [`Instruction::Call`]. If this is a tail call,
+    /// `num_locals_to_pop` contains the number of locals below the callee that
+    /// have to be removed before returning to the caller.
+    ///
+    /// Returns `None` without touching the stack if the callee isn't a builtin
+    /// with a fast path or if the number of arguments doesn't match, in which
+    /// case the generic call produces the appropriate panic.
+    ///
+    /// [`Instruction::Call`]: crate::byte_code::Instruction::Call
+    pub(super) fn try_run_builtin_fast_path(
+        &mut self,
+        heap: &mut Heap,
+        num_args: usize,
+        num_locals_to_pop: Option<usize>,
+    ) -> Option<InstructionResult> {
+        let responsible_index = self.data_stack.len() - 1;
+        let arguments_start = responsible_index - num_args;
+        let Data::Builtin(builtin) = self.data_stack[arguments_start - 1].into() else {
+            return None;
+        };
+        let builtin = builtin.get();
+        if builtin.num_parameters() != num_args {
+            return None;
+        }
 
+        let arguments = &self.data_stack[arguments_start..responsible_index];
+        let result = match builtin {
+            BuiltinFunction::Equals => heap.equals(arguments),
+            BuiltinFunction::IntAdd => heap.int_add(arguments),
+            BuiltinFunction::IntBitLength => heap.int_bit_length(arguments),
+            BuiltinFunction::IntBitwiseAnd => heap.int_bitwise_and(arguments),
+            BuiltinFunction::IntBitwiseOr => heap.int_bitwise_or(arguments),
+            BuiltinFunction::IntBitwiseXor => heap.int_bitwise_xor(arguments),
+            BuiltinFunction::IntCompareTo => heap.int_compare_to(arguments),
+            BuiltinFunction::IntMultiply => heap.int_multiply(arguments),
+            BuiltinFunction::IntSubtract => heap.int_subtract(arguments),
+            BuiltinFunction::ListGet => heap.list_get(arguments),
+            BuiltinFunction::ListLength => heap.list_length(arguments),
+            BuiltinFunction::StructGet => heap.struct_get(arguments),
+            BuiltinFunction::StructHasKey => heap.struct_has_key(arguments),
+            BuiltinFunction::TagGetValue => heap.tag_get_value(arguments),
+            BuiltinFunction::TagHasValue => heap.tag_has_value(arguments),
+            BuiltinFunction::TagWithoutValue => heap.tag_without_value(arguments),
+            BuiltinFunction::TextConcatenate => heap.text_concatenate(arguments),
+            BuiltinFunction::TextIsEmpty => heap.text_is_empty(arguments),
+            BuiltinFunction::TextLength => heap.text_length(arguments),
+            BuiltinFunction::TypeOf => heap.type_of(arguments),
+            _ => return None,
+        };
+        debug_assert!(matches!(result, Ok(Return(_)) | Err(_)));
+
+        let responsible = self.data_stack[responsible_index].try_into().unwrap();
+        // Removes the callee, arguments, and responsible HIR ID.
+        self.data_stack.truncate(arguments_start - 1);
+        if let Some(num_locals_to_pop) = num_locals_to_pop {
+            self.data_stack
+                .truncate(self.data_stack.len() - num_locals_to_pop);
+            self.next_instruction = self.call_stack.pop();
+        }
+        Some(self.handle_builtin_result(heap, result, responsible))
+    }
+
+    /// Resumes the builtin that control flow returned to, if any (see
+    /// [`BuiltinContinuation`]).
+    ///
+    /// This resumes at most one builtin per call. If a builtin calls another
+    /// builtin, e.g., `✨.listFoldLeft` with `✨.intAdd`, the callee returns
+    /// immediately and control flow is back at
+    /// [`InstructionPointer::RESUME_BUILTIN`]. Resuming in a loop would then
+    /// process the whole list within a single step of the VM, ignoring the
+    /// instruction budget of [`Vm::run_n`](crate::Vm::run_n) and starving
+    /// everything else running on the same thread.
+    pub(super) fn resume_returned_builtin(
+        &mut self,
+        heap: &mut Heap,
+        result: InstructionResult,
+    ) -> InstructionResult {
+        if !matches!(result, InstructionResult::Done)
+            || self.next_instruction != Some(InstructionPointer::RESUME_BUILTIN)
+        {
+            return result;
+        }
+
+        let BuiltinContinuation {
+            return_to,
+            responsible,
+            continuation,
+        } = self.builtin_continuations.pop().unwrap();
+        self.next_instruction = return_to;
+        let return_value = self.data_stack.pop().unwrap();
+
+        let builtin_result = span!(Level::TRACE, "Resuming builtin")
+            .in_scope(|| heap.resume_builtin(continuation, return_value));
+        self.handle_builtin_result(heap, builtin_result, responsible)
+    }
+
+    fn handle_builtin_result(
+        &mut self,
+        heap: &mut Heap,
+        result: BuiltinResult,
+        responsible: HirId,
+    ) -> InstructionResult {
         match result {
             Ok(Return(value)) => {
                 self.data_stack.push(value);
@@ -85,7 +205,20 @@ impl MachineState {
             Ok(DivergeControlFlow {
                 function,
                 responsible,
-            }) => self.call_function(function, &[], responsible),
+            }) => self.call_function(heap, function, &[], responsible),
+            Ok(CallAndResume {
+                callee,
+                arguments,
+                continuation,
+            }) => {
+                self.builtin_continuations.push(BuiltinContinuation {
+                    return_to: self.next_instruction,
+                    responsible,
+                    continuation,
+                });
+                self.next_instruction = Some(InstructionPointer::RESUME_BUILTIN);
+                self.call(heap, callee, &arguments, responsible)
+            }
             Ok(CallHandle(call)) => InstructionResult::CallHandle(call),
             Err(reason) => InstructionResult::Panic(Panic {
                 reason,
@@ -102,9 +235,115 @@ enum SuccessfulBehavior {
         function: Function,
         responsible: HirId,
     },
+    /// Calls the `callee` and afterwards resumes the builtin with the value it
+    /// returned.
+    CallAndResume {
+        callee: InlineObject,
+        arguments: Vec<InlineObject>,
+        continuation: Continuation,
+    },
     CallHandle(CallHandle),
 }
 
+/// Builtins like `✨.listMap` call back into Candy code, which takes more than
+/// the single instruction a builtin usually runs in.
+///
+/// Before calling the function, such builtins store a continuation and let the
+/// function return to [`InstructionPointer::RESUME_BUILTIN`]. Once the VM
+/// reaches that address, the builtin is resumed with the function's return
+/// value and either calls another function or returns to the original caller.
+/// This way, pipelines over lists and iterators run without going through
+/// Candy's own list operations that create a new list for every item.
+pub struct BuiltinContinuation {
+    /// Where to continue after the builtin returns.
+    return_to: Option<InstructionPointer>,
+    responsible: HirId,
+    continuation: Continuation,
+}
+enum Continuation {
+    /// Waits for an iterator to return its next item.
+    IteratorNext,
+    /// Waits for an iterator to return the next item to combine with the
+    /// `accumulator`.
+    IteratorFoldLeftNext {
+        combine: InlineObject,
+        accumulator: InlineObject,
+    },
+    /// Waits for `combine` to return the new accumulator before calling the
+    /// `rest` of the iterator.
+    IteratorFoldLeftCombine {
+        combine: InlineObject,
+        rest: InlineObject,
+    },
+    /// Waits for `tester` to decide whether to keep the item at `index`.
+    ListFilter {
+        list: List,
+        tester: InlineObject,
+        index: usize,
+        kept_items: Vec<InlineObject>,
+    },
+    /// Waits for `combine` to return the accumulator for the items before
+    /// `index`.
+    ListFoldLeft {
+        list: List,
+        combine: InlineObject,
+        index: usize,
+    },
+    /// Waits for `mapper` to map the item after the `mapped_items`.
+    ListMap {
+        list: List,
+        mapper: InlineObject,
+        mapped_items: Vec<InlineObject>,
+    },
+}
+
+impl Relocate for BuiltinContinuation {
+    fn relocate(&mut self, address_map: &AddressMap) {
+        self.responsible.relocate(address_map);
+        match &mut self.continuation {
+            Continuation::IteratorNext => {}
+            Continuation::IteratorFoldLeftNext {
+                combine,
+                accumulator,
+            } => {
+                combine.relocate(address_map);
+                accumulator.relocate(address_map);
+            }
+            Continuation::IteratorFoldLeftCombine { combine, rest } => {
+                combine.relocate(address_map);
+                rest.relocate(address_map);
+            }
+            Continuation::ListFilter {
+                list,
+                tester,
+                kept_items,
+                ..
+            } => {
+                list.relocate(address_map);
+                tester.relocate(address_map);
+                for item in kept_items {
+                    item.relocate(address_map);
+                }
+            }
+            Continuation::ListFoldLeft { list, combine, .. } => {
+                list.relocate(address_map);
+                combine.relocate(address_map);
+            }
+            Continuation::ListMap {
+                list,
+                mapper,
+                mapped_items,
+            } => {
+                list.relocate(address_map);
+                mapper.relocate(address_map);
+                for item in mapped_items {
+                    item.relocate(address_map);
+                }
+            }
+        }
+    }
+}
+
 impl From<SuccessfulBehavior> for BuiltinResult {
     fn from(ok: SuccessfulBehavior) -> Self {
         Ok(ok)
@@ -313,6 +552,30 @@ impl Heap {
         })
     }
 
+    fn iterator_fold_left(args: &[InlineObject]) -> BuiltinResult {
+        unpack!(self, args, |iterator: Any,
+                             initial_value: Any,
+                             combine: Any| {
+            CallAndResume {
+                callee: iterator.object,
+                arguments: vec![],
+                continuation: Continuation::IteratorFoldLeftNext {
+                    combine: combine.object,
+                    accumulator: initial_value.object,
+                },
+            }
+        })
+    }
+    fn iterator_next(args: &[InlineObject]) -> BuiltinResult {
+        unpack!(self, args, |iterator: Any| {
+            CallAndResume {
+                callee: iterator.object,
+                arguments: vec![],
+                continuation: Continuation::IteratorNext,
+            }
+        })
+    }
+
     fn list_filled(&mut self, args: &[InlineObject]) -> BuiltinResult {
         unpack!(self, args, |length: Int, item: Any| {
             let length_usize = length.try_get().unwrap();
@@ -328,6 +591,50 @@ impl Heap {
             Return(List::create(self, true, &vec![item_object; length_usize]).into())
         })
     }
+    fn list_filter(&mut self, args: &[InlineObject]) -> BuiltinResult {
+        unpack!(self, args, |list: List, tester: Any| {
+            if list.len() == 0 {
+                tester.object.drop(self);
+                return Return(list.object).into();
+            }
+
+            self.call_with_list_item(
+                tester.object,
+                None,
+                *list,
+                0,
+                Continuation::ListFilter {
+                    list: *list,
+                    tester: tester.object,
+                    index: 0,
+                    kept_items: vec![],
+                },
+            )
+        })
+    }
+    fn list_fold_left(&mut self, args: &[InlineObject]) -> BuiltinResult {
+        unpack!(self, args, |list: List,
+                             initial_value: Any,
+                             combine: Any| {
+            if list.len() == 0 {
+                list.object.drop(self);
+                combine.object.drop(self);
+                return Return(initial_value.object).into();
+            }
+
+            self.call_with_list_item(
+                combine.object,
+                Some(initial_value.object),
+                *list,
+                0,
+                Continuation::ListFoldLeft {
+                    list: *list,
+                    combine: combine.object,
+                    index: 0,
+                },
+            )
+        })
+    }
     fn list_get(&mut self, args: &[InlineObject]) -> BuiltinResult {
         unpack_and_later_drop!(self, args, |list: List, index: Int| {
             let index = index.try_get().unwrap();
@@ -341,6 +648,9 @@ impl Heap {
             let index_usize = index.try_get().unwrap();
             index.object.drop(self);
 
+            for item in list.items() {
+                item.dup(self);
+            }
             let new_list = list.insert(self, index_usize, item.object).into();
             list.object.drop(self);
             Return(new_list)
@@ -351,9 +661,35 @@ impl Heap {
             Return(Int::create(self, true, list.len()).into())
         })
     }
+    fn list_map(&mut self, args: &[InlineObject]) -> BuiltinResult {
+        unpack!(self, args, |list: List, mapper: Any| {
+            if list.len() == 0 {
+                mapper.object.drop(self);
+                return Return(list.object).into();
+            }
+
+            self.call_with_list_item(
+                mapper.object,
+                None,
+                *list,
+                0,
+                Continuation::ListMap {
+                    list: *list,
+                    mapper: mapper.object,
+                    mapped_items: Vec::with_capacity(list.len()),
+                },
+            )
+        })
+    }
     fn list_remove_at(&mut self, args: &[InlineObject]) -> BuiltinResult {
         unpack_and_later_drop!(self, args, |list: List, index: Int| {
-            Return(list.remove(self, index.try_get().unwrap()).into())
+            let index = index.try_get().unwrap();
+            for (item_index, item) in list.items().iter().enumerate() {
+                if item_index != index {
+                    item.dup(self);
+                }
+            }
+            Return(list.remove(self, index).into())
         })
     }
     fn list_replace(&mut self, args: &[InlineObject]) -> BuiltinResult {
@@ -361,8 +697,11 @@ impl Heap {
             let index_usize = index.try_get().unwrap();
             index.object.drop(self);
 
-            list.get(index_usize).drop(self);
-
+            for (item_index, item) in list.items().iter().enumerate() {
+                if item_index != index_usize {
+                    item.dup(self);
+                }
+            }
             let new_list = list.replace(self, index_usize, new_item.object).into();
             list.object.drop(self);
             Return(new_list)
@@ -513,6 +852,32 @@ impl Heap {
             Return(Text::create(self, true, &formatted).into())
         })
     }
+    #[allow(clippy::wrong_self_convention)]
+    fn to_debug_text_with_limits(&mut self, args: &[InlineObject]) -> BuiltinResult {
+        unpack_and_later_drop!(
+            self,
+            args,
+            |value: Any, max_depth: Any, max_items: Any, max_length: Any| {
+                // Each limit is either an int or `Unlimited`.
+                let limit = |it: &Data| match it {
+                    Data::Int(int) => Some(int.try_get::<usize>().unwrap()),
+                    Data::Tag(_) => None,
+                    _ => unreachable!(),
+                };
+                let limits = NestingLimits {
+                    max_depth: limit(&max_depth),
+                    max_items: limit(&max_items),
+                };
+                let max_length =
+                    limit(&max_length).map_or(MaxLength::Unlimited, MaxLength::Limited);
+                let formatted =
+                    value
+                        .object
+                        .to_debug_text_with_limits(Precedence::Low, max_length, limits);
+                Return(Text::create(self, true, &formatted).into())
+            }
+        )
+    }
 
     fn type_of(&mut self, args: &[InlineObject]) -> BuiltinResult {
         unpack_and_later_drop!(self, args, |value: Any| {
@@ -534,6 +899,204 @@ impl Heap {
     }
 }
 
+impl Heap {
+    fn resume_builtin(
+        &mut self,
+        continuation: Continuation,
+        return_value: InlineObject,
+    ) -> BuiltinResult {
+        match continuation {
+            Continuation::IteratorNext => {
+                self.unpack_iterator_result(return_value)?;
+                Return(return_value).into()
+            }
+            Continuation::IteratorFoldLeftNext {
+                combine,
+                accumulator,
+            } => {
+                let Some((item, rest)) = self.unpack_iterator_result(return_value)? else {
+                    return_value.drop(self);
+                    combine.drop(self);
+                    return Return(accumulator).into();
+                };
+                item.dup(self);
+                rest.dup(self);
+                return_value.drop(self);
+
+                combine.dup(self);
+                CallAndResume {
+                    callee: combine,
+                    arguments: vec![accumulator, item],
+                    continuation: Continuation::IteratorFoldLeftCombine { combine, rest },
+                }
+                .into()
+            }
+            Continuation::IteratorFoldLeftCombine { combine, rest } => CallAndResume {
+                callee: rest,
+                arguments: vec![],
+                continuation: Continuation::IteratorFoldLeftNext {
+                    combine,
+                    accumulator: return_value,
+                },
+            }
+            .into(),
+            Continuation::ListFilter {
+                list,
+                tester,
+                index,
+                mut kept_items,
+            } => {
+                let keep = Tag::try_from(return_value)
+                    .ok()
+                    .and_then(|tag| tag.try_into_bool(self).ok())
+                    .ok_or_else(|| {
+                        format!("The tester returned {return_value}, but it should return `True` or `False`.")
+                    })?;
+                if keep {
+                    let item = list.get(index);
+                    item.dup(self);
+                    kept_items.push(item);
+                }
+
+                let index = index + 1;
+                if index < list.len() {
+                    return self
+                        .call_with_list_item(
+                            tester,
+                            None,
+                            list,
+                            index,
+                            Continuation::ListFilter {
+                                list,
+                                tester,
+                                index,
+                                kept_items,
+                            },
+                        )
+                        .into();
+                }
+
+                tester.drop(self);
+                if kept_items.len() == list.len() {
+                    // Nothing was filtered out, so we can reuse the list.
+                    for item in kept_items {
+                        item.drop(self);
+                    }
+                    return Return(list.into()).into();
+                }
+                let filtered = List::create(self, true, &kept_items);
+                list.drop(self);
+                Return(filtered.into()).into()
+            }
+            Continuation::ListFoldLeft {
+                list,
+                combine,
+                index,
+            } => {
+                let index = index + 1;
+                if index < list.len() {
+                    return self
+                        .call_with_list_item(
+                            combine,
+                            Some(return_value),
+                            list,
+                            index,
+                            Continuation::ListFoldLeft {
+                                list,
+                                combine,
+                                index,
+                            },
+                        )
+                        .into();
+                }
+
+                combine.drop(self);
+                list.drop(self);
+                Return(return_value).into()
+            }
+            Continuation::ListMap {
+                list,
+                mapper,
+                mut mapped_items,
+            } => {
+                mapped_items.push(return_value);
+
+                let index = mapped_items.len();
+                if index < list.len() {
+                    return self
+                        .call_with_list_item(
+                            mapper,
+                            None,
+                            list,
+                            index,
+                            Continuation::ListMap {
+                                list,
+                                mapper,
+                                mapped_items,
+                            },
+                        )
+                        .into();
+                }
+
+                mapper.drop(self);
+                let mapped = List::create(self, true, &mapped_items);
+                list.drop(self);
+                Return(mapped.into()).into()
+            }
+        }
+    }
+
+    /// Calls `callee` with the list item at `index`, preceded by the
+    /// `accumulator` if there is one.
+    ///
+    /// The continuation keeps its own reference to the `callee`.
+    fn call_with_list_item(
+        &mut self,
+        callee: InlineObject,
+        accumulator: Option<InlineObject>,
+        list: List,
+        index: usize,
+        continuation: Continuation,
+    ) -> SuccessfulBehavior {
+        callee.dup(self);
+        let item = list.get(index);
+        item.dup(self);
+        CallAndResume {
+            callee,
+            arguments: accumulator.into_iter().chain([item]).collect(),
+            continuation,
+        }
+    }
+
+    /// Returns the item and rest of an iterator's `Ok [Item, Rest]`, or
+    /// `None` if the iterator returned an `Error` because it's exhausted.
+    fn unpack_iterator_result(
+        &self,
+        value: InlineObject,
+    ) -> Result<Option<(InlineObject, InlineObject)>, String> {
+        let symbols = self.default_symbols();
+        let invalid = || {
+            format!(
+                "An iterator returned {value}, but it should return `Ok [Item, Rest]` or `Error`."
+            )
+        };
+
+        let tag = Tag::try_from(value).map_err(|_| invalid())?;
+        if tag.symbol() == symbols.error {
+            return Ok(None);
+        }
+        if tag.symbol() != symbols.ok {
+            return Err(invalid());
+        }
+        let Some(Ok(struct_)) = tag.value().map(Struct::try_from) else {
+            return Err(invalid());
+        };
+        let item = struct_.get(Tag::create(symbols.item)).ok_or_else(invalid)?;
+        let rest = struct_.get(Tag::create(symbols.rest)).ok_or_else(invalid)?;
+        Ok(Some((item, rest)))
+    }
+}
+
 #[derive(Deref)]
 struct UnpackedData<T> {
     object: InlineObject,
@@ -553,3 +1116,344 @@ impl TryInto<Any> for InlineObject {
         Ok(Any { data: self.into() })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        byte_code::ByteCode,
+        heap::{Heap, HirId, InlineObject, Int, List, Struct, Tag, Text, ToDebugText},
+        instruction_pointer::InstructionPointer,
+        instructions::InstructionResult,
+        lir_to_byte_code::compile_byte_code,
+        tracer::DummyTracer,
+        vm::MachineState,
+        PopulateInMemoryProviderFromFileSystem, StateAfterRun, Vm, VmFinished,
+    };
+    use candy_frontend::{
+        builtin_functions::BuiltinFunction,
+        format::{MaxLength, Precedence},
+        hir::Id,
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+    use std::iter;
+
+    #[track_caller]
+    fn compile_module(package: Package, source: &str) -> ByteCode {
+        let mut db = Database::default();
+        db.get_in_memory_module_provider()
+            .load_package_from_file_system("Builtins");
+        let module = Module {
+            package,
+            path: vec!["test".to_string()],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, source.as_bytes().to_vec());
+        let (byte_code, errors) =
+            compile_byte_code(&db, ExecutionTarget::Module(module), TracingConfig::off());
+        assert!(
+            errors.iter().all(|it| it.payload.is_warning()),
+            "{errors:?}",
+        );
+        byte_code
+    }
+
+    /// Runs a module that can use the `builtins` with VM checks enabled and
+    /// returns the debug text of its exports (or the panic reason).
+    #[track_caller]
+    fn run(source: &str) -> String {
+        let package = Package::Anonymous {
+            url: "test".to_string(),
+        };
+        let byte_code =
+            compile_module(package, &format!("builtins = use \"Builtins\"\n{source}\n"));
+        let mut heap = Heap::default();
+        let VmFinished { result, .. } = Vm::for_module(&byte_code, &mut heap, DummyTracer)
+            .with_checks()
+            .run_forever_without_handles(&mut heap);
+        match result {
+            Ok(exports) => {
+                let text = exports.to_debug_text(Precedence::Low, MaxLength::Unlimited);
+                exports.drop(&mut heap);
+                text
+            }
+            Err(panic) => panic.reason,
+        }
+    }
+
+    #[test]
+    fn list_builtins_resume_after_calling_back() {
+        assert_eq!(
+            run(
+                r#"result := builtins.listMap ("a", "b") { it -> builtins.textConcatenate it "!" }"#
+            ),
+            r#"[Result: ("a!", "b!")]"#,
+        );
+        assert_eq!(
+            run(
+                r#"result := builtins.listFilter ("a", "b", "c") { it -> builtins.equals it "b" | builtins.equals False }"#
+            ),
+            r#"[Result: ("a", "c")]"#,
+        );
+        assert_eq!(
+            run(
+                r#"result := builtins.listFoldLeft ("a", "b") "" { a b -> builtins.textConcatenate a b }"#
+            ),
+            r#"[Result: "ab"]"#,
+        );
+    }
+
+    #[test]
+    fn list_builtins_respect_the_instruction_budget() {
+        const LENGTH: usize = 1000;
+        // Inside the `Builtins` package, builtins can be passed as callbacks
+        // directly. They return without running any instructions.
+        for callback in [
+            "✨.listFoldLeft items 0 ✨.intAdd",
+            "✨.listFilter items { it -> ✨.equals it 1 }",
+        ] {
+            let byte_code = compile_module(
+                Package::builtins(),
+                &format!("items = ✨.listFilled {LENGTH} 1\nresult := {callback}\n"),
+            );
+            let mut heap = Heap::default();
+            let mut vm = Vm::for_module(&byte_code, &mut heap, DummyTracer);
+            // Each step runs a single instruction, so the callbacks for the
+            // items need at least one step each.
+            let mut steps = 0;
+            loop {
+                steps += 1;
+                match vm.run_n(&mut heap, 1) {
+                    StateAfterRun::Running(next) => vm = next,
+                    StateAfterRun::Finished(VmFinished { result, .. }) => {
+                        result.unwrap().drop(&mut heap);
+                        break;
+                    }
+                    StateAfterRun::CallingHandle(_) => panic!("The module doesn't use handles."),
+                }
+            }
+            assert!(
+                steps > LENGTH,
+                "`{callback}` finished within {steps} steps.",
+            );
+        }
+    }
+
+    #[test]
+    fn changed_lists_keep_the_items_of_the_original_list() {
+        // The mapped texts are only referenced by the list, which is freed once
+        // the new list exists.
+        let items =
+            r#"items = builtins.listMap ("a", "b") { it -> builtins.textConcatenate it "!" }"#;
+        assert_eq!(
+            run(&format!(
+                "{items}\nresult := builtins.listInsert items 1 \"c\""
+            )),
+            r#"[Result: ("a!", "c", "b!")]"#,
+        );
+        assert_eq!(
+            run(&format!("{items}\nresult := builtins.listRemoveAt items 0")),
+            r#"[Result: ("b!",)]"#,
+        );
+        assert_eq!(
+            run(&format!(
+                "{items}\nresult := builtins.listReplace items 0 \"c\""
+            )),
+            r#"[Result: ("c", "b!")]"#,
+        );
+    }
+
+    #[test]
+    fn called_back_functions_keep_their_captured_values() {
+        let suffix = r#"suffix = builtins.listGet (builtins.listMap ("!",) { it -> builtins.textConcatenate it "?" }) 0"#;
+        assert_eq!(
+            run(&format!(
+                "{suffix}\nresult := builtins.listMap (\"a\", \"b\", \"c\") {{ it -> builtins.textConcatenate it suffix }}"
+            )),
+            r#"[Result: ("a!?", "b!?", "c!?")]"#,
+        );
+    }
+
+    #[test]
+    fn iterator_builtins_resume_after_calling_back() {
+        let iterator =
+            r#"iterator = { Ok [Item: "a", Rest: { Ok [Item: "b", Rest: { Error Empty }] }] }"#;
+        assert_eq!(
+            run(&format!(
+                "{iterator}\nresult := builtins.iteratorFoldLeft iterator \"\" {{ a b -> builtins.textConcatenate a b }}",
+            )),
+            r#"[Result: "ab"]"#,
+        );
+        assert_eq!(
+            run(&format!(
+                "{iterator}\nresult := builtins.iteratorNext iterator | builtins.tagGetValue | builtins.structGet Item",
+            )),
+            r#"[Result: "a"]"#,
+        );
+        assert_eq!(
+            run("result := builtins.iteratorNext { Foo }"),
+            "An iterator returned Foo, but it should return `Ok [Item, Rest]` or `Error`.",
+        );
+    }
+
+    /// Runs the builtin via the fast path and via the generic call and returns
+    /// both results as debug texts or panic reasons.
+    fn run_both_paths(
+        heap: &mut Heap,
+        builtin: BuiltinFunction,
+        arguments: &[InlineObject],
+        responsible: HirId,
+    ) -> (String, String) {
+        fn state(data_stack: Vec<InlineObject>) -> MachineState {
+            MachineState {
+                next_instruction: None,
+                data_stack,
+                call_stack: vec![],
+                builtin_continuations: vec![],
+            }
+        }
+        fn describe(state: &MachineState, result: InstructionResult) -> String {
+            match result {
+                InstructionResult::Done => {
+                    let [value] = state.data_stack.as_slice() else {
+                        panic!("The stack should only contain the return value.");
+                    };
+                    value.to_debug_text(Precedence::Low, MaxLength::Unlimited)
+                }
+                InstructionResult::Panic(panic) => format!("Panic: {}", panic.reason),
+                _ => panic!("Simple builtins only return or panic."),
+            }
+        }
+
+        let mut fast_state = state(
+            iter::once(builtin.into())
+                .chain(arguments.iter().copied())
+                .chain([responsible.into()])
+                .collect(),
+        );
+        let fast_result = fast_state
+            .try_run_builtin_fast_path(heap, arguments.len(), None)
+            .unwrap_or_else(|| panic!("{builtin:?} has no fast path."));
+
+        let mut slow_state = state(vec![]);
+        let slow_result = slow_state.run_builtin_function(heap, builtin, arguments, responsible);
+
+        (
+            describe(&fast_state, fast_result),
+            describe(&slow_state, slow_result),
+        )
+    }
+
+    /// The fast path only covers builtins that can't fail once their
+    /// arguments have the right types. Invalid arguments make the wrappers in
+    /// the `Builtins` package panic before the builtin runs.
+    #[test]
+    fn fast_path_agrees_with_the_generic_call() {
+        let mut heap = Heap::default();
+        let heap = &mut heap;
+        let responsible = HirId::create(heap, false, Id::user());
+        let int = |heap: &mut Heap, value: i64| -> InlineObject {
+            Int::create(heap, false, value).into()
+        };
+        let text = |heap: &mut Heap, value: &str| -> InlineObject {
+            Text::create(heap, false, value).into()
+        };
+        let symbols = heap.default_symbols();
+        let (true_symbol, false_symbol, ok_symbol) = (symbols.true_, symbols.false_, symbols.ok);
+        let true_: InlineObject = Tag::create(true_symbol).into();
+
+        let one = int(heap, 1);
+        let two = int(heap, 2);
+        let max = int(heap, i64::MAX);
+        let a = text(heap, "a");
+        let empty = text(heap, "");
+        let list: InlineObject = List::create(heap, false, &[one, two]).into();
+        let struct_: InlineObject =
+            Struct::create_with_symbol_keys(heap, false, [(true_symbol, one)]).into();
+        let tag: InlineObject = Tag::create_with_value(heap, false, ok_symbol, one).into();
+        let false_: InlineObject = Tag::create(false_symbol).into();
+
+        let cases = [
+            (BuiltinFunction::Equals, vec![one, one]),
+            (BuiltinFunction::Equals, vec![one, a]),
+            (BuiltinFunction::IntAdd, vec![one, two]),
+            // The result doesn't fit into an inline int.
+            (BuiltinFunction::IntAdd, vec![max, one]),
+            (BuiltinFunction::IntBitLength, vec![two]),
+            (BuiltinFunction::IntBitwiseAnd, vec![one, two]),
+            (BuiltinFunction::IntBitwiseOr, vec![one, two]),
+            (BuiltinFunction::IntBitwiseXor, vec![one, max]),
+            (BuiltinFunction::IntCompareTo, vec![one, two]),
+            (BuiltinFunction::IntMultiply, vec![max, two]),
+            (BuiltinFunction::IntSubtract, vec![one, two]),
+            (BuiltinFunction::ListGet, vec![list, one]),
+            (BuiltinFunction::ListLength, vec![list]),
+            (BuiltinFunction::StructGet, vec![struct_, true_]),
+            (BuiltinFunction::StructHasKey, vec![struct_, false_]),
+            (BuiltinFunction::TagGetValue, vec![tag]),
+            (BuiltinFunction::TagHasValue, vec![tag]),
+            (BuiltinFunction::TagWithoutValue, vec![tag]),
+            (BuiltinFunction::TextConcatenate, vec![a, a]),
+            (BuiltinFunction::TextIsEmpty, vec![empty]),
+            (BuiltinFunction::TextLength, vec![a]),
+            (BuiltinFunction::TypeOf, vec![list]),
+        ];
+        for (builtin, arguments) in cases {
+            let (fast, slow) = run_both_paths(heap, builtin, &arguments, responsible);
+            assert_eq!(fast, slow, "{builtin:?} differs for {arguments:?}.");
+        }
+    }
+
+    #[test]
+    fn invalid_arguments_of_fast_path_builtins_panic() {
+        assert_eq!(
+            run(r#"result := builtins.intAdd 1 "a""#),
+            "`b | typeIs Int` was not satisfied",
+        );
+        assert_eq!(
+            run("result := builtins.listGet (1, 2) 2"),
+            "`index | intCompareTo (list | ✨.listLength) | equals Less` was not satisfied",
+        );
+        assert_eq!(
+            run("result := builtins.tagGetValue Foo"),
+            "`tag | ✨.tagHasValue` was not satisfied",
+        );
+    }
+
+    #[test]
+    fn fast_tail_calls_return_to_the_caller() {
+        let mut heap = Heap::default();
+        let responsible = HirId::create(&mut heap, false, Id::user());
+        let local = Int::create(&mut heap, false, 0).into();
+        let caller = InstructionPointer::null_pointer().next();
+        let mut state = MachineState {
+            next_instruction: None,
+            data_stack: vec![
+                local,
+                BuiltinFunction::IntAdd.into(),
+                Int::create(&mut heap, false, 1).into(),
+                Int::create(&mut heap, false, 2).into(),
+                responsible.into(),
+            ],
+            call_stack: vec![caller],
+            builtin_continuations: vec![],
+        };
+
+        let result = state.try_run_builtin_fast_path(&mut heap, 2, Some(1));
+        assert!(matches!(result, Some(InstructionResult::Done)));
+        assert_eq!(state.next_instruction, Some(caller));
+        assert!(state.call_stack.is_empty());
+        let [value] = state.data_stack.as_slice() else {
+            panic!(
+                "Expected only the return value, got {:?}.",
+                state.data_stack
+            );
+        };
+        assert_eq!(
+            value.to_debug_text(Precedence::Low, MaxLength::Unlimited),
+            "3"
+        );
+    }
+}
@@ -248,6 +248,20 @@ impl ByteCode {
                 .count();
         start.into()..end.into()
     }
+
+    /// Appends instructions that don't originate from any HIR expression, e.g.,
+    /// bodies of functions that tools create at runtime. Returns a pointer to
+    /// the first appended instruction.
+    pub fn append_instructions(
+        &mut self,
+        instructions: impl IntoIterator<Item = Instruction>,
+    ) -> InstructionPointer {
+        let start = self.instructions.len().into();
+        self.instructions.extend(instructions);
+        self.origins
+            .resize_with(self.instructions.len(), FxHashSet::default);
+        start
+    }
 }
 
 impl ToRichIr for ByteCode {
@@ -279,7 +293,7 @@ impl ToRichIr for ByteCode {
             let origins = &self.origins[i];
             if origins != previous_origins {
                 builder.push(
-                    format!("# {}", origins.iter().join(", ")),
+                    format!("# {}", origins.iter().sorted().join(", ")),
                     TokenType::Comment,
                     EnumSet::empty(),
                 );
@@ -302,7 +316,7 @@ impl ToRichIr for ByteCode {
     }
 }
 impl Instruction {
-    fn build_rich_ir(&self, builder: &mut RichIrBuilder) {
+    pub(crate) fn build_rich_ir(&self, builder: &mut RichIrBuilder) {
         let discriminant: InstructionDiscriminants = self.into();
         builder.push(
             Into::<&'static str>::into(discriminant),
@@ -0,0 +1,116 @@
+//! Sources of nondeterminism that handles can access.
+//!
+//! Handles that produce random values or read the current time don't access
+//! the host directly. Instead, they use the [`RandomSource`] and [`Clock`] of
+//! the VM that called them. By default, these are backed by the host, but
+//! embedders can replace them to make runs reproducible, e.g., in tests:
+//!
+//! ```ignore
+//! let vm = Vm::for_main_function(&byte_code, &mut heap, environment, tracer)
+//!     .with_random_source(SeededRandomSource::new(42))
+//!     .with_clock(FixedClock::new(SystemTime::UNIX_EPOCH));
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+pub trait RandomSource {
+    fn fill_bytes(&mut self, bytes: &mut [u8]) -> Result<(), String>;
+}
+pub trait Clock {
+    fn now(&mut self) -> SystemTime;
+}
+
+/// Uses the random number generator of the operating system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HostRandomSource;
+impl RandomSource for HostRandomSource {
+    fn fill_bytes(&mut self, bytes: &mut [u8]) -> Result<(), String> {
+        getrandom::getrandom(bytes).map_err(|error| error.to_string())
+    }
+}
+
+/// Generates the same bytes for the same seed.
+///
+/// This uses a xorshift generator, so the bytes are not suitable for
+/// cryptography.
+#[derive(Clone, Debug)]
+pub struct SeededRandomSource {
+    state: u64,
+}
+impl SeededRandomSource {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        // Xorshift generators only produce zeros when starting at zero.
+        let state = if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        };
+        Self { state }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+impl RandomSource for SeededRandomSource {
+    fn fill_bytes(&mut self, bytes: &mut [u8]) -> Result<(), String> {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+/// Uses the system time of the host.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HostClock;
+impl Clock for HostClock {
+    fn now(&mut self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Always returns the same time unless it's advanced manually.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock {
+    time: SystemTime,
+}
+impl FixedClock {
+    #[must_use]
+    pub const fn new(time: SystemTime) -> Self {
+        Self { time }
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        self.time += duration;
+    }
+}
+impl Clock for FixedClock {
+    fn now(&mut self) -> SystemTime {
+        self.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RandomSource, SeededRandomSource};
+
+    #[test]
+    fn seeded_random_source_is_deterministic() {
+        let mut a = [0u8; 20];
+        let mut b = [0u8; 20];
+        SeededRandomSource::new(42).fill_bytes(&mut a).unwrap();
+        SeededRandomSource::new(42).fill_bytes(&mut b).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, [0u8; 20]);
+
+        let mut c = [0u8; 20];
+        SeededRandomSource::new(43).fill_bytes(&mut c).unwrap();
+        assert_ne!(a, c);
+    }
+}
@@ -0,0 +1,169 @@
+//! Expensive sanity checks that the VM can run before every instruction (see
+//! [`Vm::with_checks`](crate::Vm::with_checks)).
+//!
+//! Bugs in the VM or in the generated byte code often only surface much later
+//! as corrupted memory. These checks instead panic right before the first
+//! instruction that accesses invalid data.
+
+use crate::{
+    byte_code::{ByteCode, Instruction},
+    heap::{Heap, HeapObject, InlineData, InlineObject, ObjectInHeap},
+    instruction_pointer::InstructionPointer,
+    vm::MachineState,
+};
+use itertools::Itertools;
+
+impl MachineState {
+    pub(crate) fn check_invariants(
+        &self,
+        heap: &Heap,
+        byte_code: &ByteCode,
+        instruction_pointer: InstructionPointer,
+        instruction: &Instruction,
+    ) {
+        let fail =
+            |problem: String| -> ! {
+                let functions = byte_code
+                    .functions_behind(instruction_pointer)
+                    .iter()
+                    .map(ToString::to_string)
+                    .sorted()
+                    .join(", ");
+                panic!(
+                "VM check failed before running `{instruction:?}` at {instruction_pointer:?} (in \
+                 {}): {problem}",
+                if functions.is_empty() { "no function" } else { functions.as_str() },
+            );
+            };
+
+        let stack_depth = instruction.accessed_stack_depth();
+        if self.data_stack.len() < stack_depth {
+            fail(format!(
+                "The instruction accesses {stack_depth} stack entries, but the data stack only \
+                 contains {}.",
+                self.data_stack.len(),
+            ));
+        }
+
+        for offset in instruction.read_stack_offsets() {
+            let value = self.data_stack[self.data_stack.len() - 1 - offset];
+            if let Err(problem) = check_value(heap, &byte_code.constant_heap, value) {
+                fail(format!(
+                    "The stack entry at offset {offset} is invalid: {problem}"
+                ));
+            }
+        }
+    }
+}
+
+fn check_value(heap: &Heap, constant_heap: &Heap, value: InlineObject) -> Result<(), String> {
+    match InlineData::from(value) {
+        InlineData::Pointer(pointer) => check_heap_object(heap, constant_heap, pointer.get()),
+        InlineData::Tag(tag) => check_heap_object(heap, constant_heap, **tag.get()),
+        InlineData::Handle(handle) => {
+            let handle_id = handle.handle_id();
+            if heap.known_handles().into_iter().contains(&handle_id) {
+                Ok(())
+            } else {
+                Err(format!("{handle_id:?} was already dropped."))
+            }
+        }
+        InlineData::Int(_) | InlineData::Builtin(_) => Ok(()),
+    }
+}
+fn check_heap_object(heap: &Heap, constant_heap: &Heap, object: HeapObject) -> Result<(), String> {
+    // Constants live in the byte code's heap and are not reference counted.
+    let object_in_heap = ObjectInHeap(object);
+    if constant_heap.objects().contains(&object_in_heap) {
+        return Ok(());
+    }
+    // We have to check this before reading anything from the object.
+    if !heap.objects().contains(&object_in_heap) {
+        return Err(format!(
+            "{:p} doesn't point to a live heap object.",
+            object.address()
+        ));
+    }
+    if object.reference_count() == Some(0) {
+        return Err(format!("{object:?} has a reference count of zero."));
+    }
+    Ok(())
+}
+
+impl Instruction {
+    /// How many entries at the top of the data stack this instruction reads or
+    /// pops.
+    fn accessed_stack_depth(&self) -> usize {
+        match self {
+            Self::CreateTag { .. } => 1,
+            Self::CreateList { num_items } => *num_items,
+            Self::CreateStruct { num_fields } => 2 * *num_fields,
+            Self::CreateFunction { captured, .. } => captured.iter().max().map_or(0, |it| it + 1),
+            Self::PushConstant(_) => 0,
+            Self::PushFromStack(offset) => *offset + 1,
+            Self::PopMultipleBelowTop(n) => *n + 1,
+            Self::Dup { .. } | Self::Drop | Self::Return | Self::TraceCallEnds => 1,
+            Self::Call { num_args } => *num_args + 2,
+            Self::TailCall {
+                num_locals_to_pop,
+                num_args,
+            } => *num_locals_to_pop + *num_args + 2,
+            Self::Panic | Self::TraceExpressionEvaluated | Self::TraceFoundFuzzableFunction => 2,
+            Self::TraceCallStarts { num_args } => *num_args + 3,
+        }
+    }
+
+    /// The offsets (from the top) of the data stack entries this instruction
+    /// reads.
+    ///
+    /// Locals stay on the stack after they were dropped for the last time, so
+    /// entries that are only popped may point to freed objects.
+    fn read_stack_offsets(&self) -> Vec<usize> {
+        match self {
+            Self::CreateFunction { captured, .. } => captured.clone(),
+            Self::PushFromStack(offset) => vec![*offset],
+            Self::PopMultipleBelowTop(_) => vec![0],
+            Self::TailCall { num_args, .. } => (0..*num_args + 2).collect(),
+            _ => (0..self.accessed_stack_depth()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_value;
+    use crate::{
+        byte_code::Instruction,
+        heap::{Heap, Text},
+    };
+
+    #[test]
+    fn check_value_reports_freed_objects() {
+        let mut heap = Heap::default();
+        let constant_heap = Heap::default();
+        let text = Text::create(&mut heap, true, "item");
+        assert!(check_value(&heap, &constant_heap, text.into()).is_ok());
+
+        text.drop(&mut heap);
+        assert!(check_value(&heap, &constant_heap, text.into()).is_err());
+    }
+
+    #[test]
+    fn only_read_stack_entries_are_checked() {
+        // Entries above the one that's pushed again may be locals that were
+        // already dropped.
+        assert_eq!(Instruction::PushFromStack(3).read_stack_offsets(), vec![3]);
+        assert_eq!(
+            Instruction::PopMultipleBelowTop(2).read_stack_offsets(),
+            vec![0]
+        );
+        assert_eq!(
+            Instruction::TailCall {
+                num_locals_to_pop: 2,
+                num_args: 1,
+            }
+            .read_stack_offsets(),
+            vec![0, 1, 2],
+        );
+    }
+}
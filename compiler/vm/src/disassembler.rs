@@ -0,0 +1,368 @@
+//! A listing of byte code that is meant to be read by compiler developers.
+//!
+//! Compared to the byte code's regular rich IR, the disassembly doesn't contain
+//! any heap addresses: Constants are listed once in a table at the top and
+//! referred to by name, and the targets of functions are rendered as labels.
+//! Whenever the function that instructions originate from changes, the
+//! corresponding source line is printed.
+
+use crate::{
+    byte_code::{ByteCode, Instruction},
+    heap::{Data, InlineData, InlineObject},
+    instruction_pointer::InstructionPointer,
+};
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    hir,
+    module::Module,
+    position::PositionConversionDb,
+    rich_ir::{RichIr, RichIrBuilder, TokenType},
+    TracingConfig,
+};
+use enumset::EnumSet;
+use extension_trait::extension_trait;
+use itertools::Itertools;
+use pad::{Alignment, PadStr};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Maps HIR IDs back to the source code they were created from.
+pub trait SourceMap {
+    /// Returns the one-based line number and the content of the line where the
+    /// code of the given ID starts.
+    fn source_line(&self, id: &hir::Id) -> Option<(usize, String)>;
+}
+impl<Db: AstToHir + PositionConversionDb> SourceMap for Db {
+    fn source_line(&self, id: &hir::Id) -> Option<(usize, String)> {
+        let span = self.hir_id_to_span(id)?;
+        let source = self.get_module_content_as_string(id.module.clone())?;
+        let line = self.offset_to_position(id.module.clone(), span.start).line;
+        let range = self.line_index(id.module.clone()).line_range(line);
+        Some((
+            line + 1,
+            source[*range.start..*range.end].trim_end().to_string(),
+        ))
+    }
+}
+
+impl ByteCode {
+    #[must_use]
+    pub fn disassemble(&self, source_map: &impl SourceMap) -> String {
+        let mut builder = RichIrBuilder::default();
+        Disassembler::new(self, source_map).build(&mut builder);
+        builder.finish(true).text
+    }
+}
+
+#[extension_trait]
+pub impl RichIrForDisassembly for RichIr {
+    fn for_disassembly(
+        module: &Module,
+        byte_code: &ByteCode,
+        tracing_config: &TracingConfig,
+        source_map: &impl SourceMap,
+    ) -> RichIr {
+        let mut builder = RichIrBuilder::default();
+        builder.push(
+            format!("# Disassembled VM Byte Code for module {module}"),
+            TokenType::Comment,
+            EnumSet::empty(),
+        );
+        builder.push_newline();
+        builder.push_tracing_config(tracing_config);
+        builder.push_newline();
+        Disassembler::new(byte_code, source_map).build(&mut builder);
+        builder.finish(true)
+    }
+}
+
+struct Disassembler<'a, S: SourceMap> {
+    byte_code: &'a ByteCode,
+    source_map: &'a S,
+
+    /// Constants in the order of their first use.
+    constants: Vec<InlineObject>,
+    constant_names: FxHashMap<InlineObject, String>,
+    labels: FxHashMap<InstructionPointer, String>,
+}
+impl<'a, S: SourceMap> Disassembler<'a, S> {
+    fn new(byte_code: &'a ByteCode, source_map: &'a S) -> Self {
+        let mut constants = vec![];
+        let mut constant_names = FxHashMap::default();
+        let mut function_bodies = FxHashSet::default();
+        for instruction in &byte_code.instructions {
+            match instruction {
+                Instruction::CreateFunction { body, .. } => {
+                    function_bodies.insert(*body);
+                }
+                Instruction::PushConstant(constant) => {
+                    // Inline values are short enough to be shown directly.
+                    if !matches!(InlineData::from(*constant), InlineData::Pointer(_))
+                        || constant_names.contains_key(constant)
+                    {
+                        continue;
+                    }
+                    if let Data::Function(function) = Data::from(*constant) {
+                        function_bodies.insert(function.body());
+                    }
+                    constant_names.insert(*constant, format!("c{}", constants.len()));
+                    constants.push(*constant);
+                }
+                _ => {}
+            }
+        }
+
+        let module_body = byte_code.module_function.body();
+        function_bodies.remove(&module_body);
+        let mut labels: FxHashMap<_, _> = function_bodies
+            .into_iter()
+            .sorted()
+            .enumerate()
+            .map(|(index, body)| (body, format!("function{index}")))
+            .collect();
+        labels.insert(module_body, "module".to_string());
+
+        Self {
+            byte_code,
+            source_map,
+            constants,
+            constant_names,
+            labels,
+        }
+    }
+
+    fn build(&self, builder: &mut RichIrBuilder) {
+        builder.push("# Constants", TokenType::Comment, EnumSet::empty());
+        for constant in &self.constants {
+            builder.push_newline();
+            builder.push(
+                &self.constant_names[constant],
+                TokenType::Constant,
+                EnumSet::empty(),
+            );
+            builder.push(" = ", None, EnumSet::empty());
+            self.push_constant_value(builder, *constant);
+        }
+        builder.push_newline();
+        builder.push_newline();
+
+        builder.push("# Instructions", TokenType::Comment, EnumSet::empty());
+        let address_width = self.byte_code.instructions.len().to_string().len();
+        let mut previous_origins = &FxHashSet::default();
+        for (index, instruction) in self.byte_code.instructions.iter().enumerate() {
+            let ip = InstructionPointer::from(index);
+            if let Some(label) = self.labels.get(&ip) {
+                builder.push_newline();
+                builder.push_newline();
+                builder.push(format!("{label}:"), TokenType::Function, EnumSet::empty());
+            }
+
+            let origins = self.byte_code.functions_behind(ip);
+            if origins != previous_origins {
+                for origin in origins.iter().sorted_by_key(ToString::to_string) {
+                    builder.push_newline();
+                    self.push_source_line(builder, origin);
+                }
+                previous_origins = origins;
+            }
+
+            builder.push_newline();
+            builder.push(
+                format!(
+                    "{}: ",
+                    index
+                        .to_string()
+                        .pad_to_width_with_alignment(address_width, Alignment::Right),
+                ),
+                TokenType::Address,
+                EnumSet::empty(),
+            );
+            self.push_instruction(builder, instruction);
+        }
+    }
+
+    fn push_source_line(&self, builder: &mut RichIrBuilder, id: &hir::Id) {
+        let text = if let Some((line, content)) = self.source_map.source_line(id) {
+            format!("; {line} | {content}")
+        } else {
+            format!("; {id}")
+        };
+        builder.push(text, TokenType::Comment, EnumSet::empty());
+    }
+
+    fn push_instruction(&self, builder: &mut RichIrBuilder, instruction: &Instruction) {
+        match instruction {
+            Instruction::CreateFunction {
+                captured,
+                num_args,
+                body,
+            } => {
+                builder.push(
+                    format!(
+                        "createFunction with {num_args} {} capturing {} → ",
+                        if *num_args == 1 {
+                            "argument"
+                        } else {
+                            "arguments"
+                        },
+                        if captured.is_empty() {
+                            "nothing".to_string()
+                        } else {
+                            captured.iter().join(", ")
+                        },
+                    ),
+                    None,
+                    EnumSet::empty(),
+                );
+                self.push_target(builder, *body);
+            }
+            Instruction::PushConstant(constant) => {
+                builder.push("pushConstant ", None, EnumSet::empty());
+                if let Some(name) = self.constant_names.get(constant) {
+                    builder.push(name, TokenType::Constant, EnumSet::empty());
+                } else {
+                    self.push_constant_value(builder, *constant);
+                }
+            }
+            _ => instruction.build_rich_ir(builder),
+        }
+    }
+
+    fn push_constant_value(&self, builder: &mut RichIrBuilder, constant: InlineObject) {
+        match Data::from(constant) {
+            Data::Function(function) => {
+                builder.push(
+                    format!(
+                        "{{ {} {} (capturing {}) → ",
+                        function.argument_count(),
+                        if function.argument_count() == 1 {
+                            "argument"
+                        } else {
+                            "arguments"
+                        },
+                        function.captured_len(),
+                    ),
+                    None,
+                    EnumSet::empty(),
+                );
+                self.push_target(builder, function.body());
+                builder.push(" }", None, EnumSet::empty());
+            }
+            Data::HirId(id) => {
+                builder.push(
+                    format!("{constant:?}"),
+                    TokenType::Constant,
+                    EnumSet::empty(),
+                );
+                if let Some((line, _)) = self.source_map.source_line(id.get()) {
+                    builder.push(
+                        format!("  ; line {line}"),
+                        TokenType::Comment,
+                        EnumSet::empty(),
+                    );
+                }
+            }
+            _ => {
+                builder.push(
+                    format!("{constant:?}"),
+                    TokenType::Constant,
+                    EnumSet::empty(),
+                );
+            }
+        }
+    }
+
+    fn push_target(&self, builder: &mut RichIrBuilder, target: InstructionPointer) {
+        let label = self
+            .labels
+            .get(&target)
+            .cloned()
+            .unwrap_or_else(|| format!("{target:?}"));
+        builder.push(label, TokenType::Function, EnumSet::empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lir_to_byte_code::compile_byte_code, PopulateInMemoryProviderFromFileSystem};
+    use candy_frontend::{
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+
+    #[track_caller]
+    fn disassemble(source: &str) -> String {
+        let mut db = Database::default();
+        db.get_in_memory_module_provider()
+            .load_package_from_file_system("Builtins");
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, source.as_bytes().to_vec());
+        let (byte_code, errors) = compile_byte_code(
+            &db,
+            ExecutionTarget::MainFunction(module),
+            TracingConfig::off(),
+        );
+        assert!(
+            errors.iter().all(|it| it.payload.is_warning()),
+            "{errors:?}",
+        );
+        byte_code.disassemble(&db)
+    }
+
+    #[test]
+    fn constants_and_targets_are_named_and_source_lines_are_interleaved() {
+        let disassembly = disassemble(
+            r#"greeting = "Hello"
+main := { environment ->
+  greeting
+}
+"#,
+        );
+        assert_eq!(
+            disassembly,
+            r#"# Constants
+c0 = "Hello"
+c1 = { 1 argument (capturing 0) → function0 }
+
+# Instructions
+
+function0:
+; 2 | main := { environment ->
+0: pushFromStack 1
+1: drop
+2: pushConstant c0
+3: popMultipleBelowTop 2
+4: return
+
+module:
+; anonymous:test::
+5: pushConstant c1
+6: popMultipleBelowTop 1
+7: return
+"#,
+        );
+    }
+
+    #[test]
+    fn each_function_gets_its_own_label() {
+        let disassembly = disassemble(
+            r#"identity = { value -> value }
+main := { environment -> identity }
+"#,
+        );
+        let labels = disassembly
+            .lines()
+            .filter(|line| !line.starts_with(';') && line.ends_with(':'))
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["function0:", "function1:", "module:"]);
+        assert!(disassembly.contains("; 1 | identity = { value -> value }"));
+        assert!(disassembly.contains("; 2 | main := { environment -> identity }"));
+    }
+}
@@ -1,21 +1,33 @@
 use crate::{
     byte_code::ByteCode,
-    heap::{Data, Handle, Heap, InlineObject, Int, List, Struct, Tag, Text},
+    capabilities::{Clock, RandomSource},
+    handle_registry::HandleRegistry,
+    heap::{Data, Handle, Heap, InlineObject, Int, List, Struct, Tag, Text, ToDebugText},
     tracer::Tracer,
     vm::VmHandleCall,
-    StateAfterRun, StateAfterRunForever, Vm, VmFinished,
+    Vm, VmEvent, VmFinished,
+};
+use candy_frontend::{
+    format::{MaxLength, Precedence},
+    module::{Module, ModuleKind, ModuleProvider, UsePath},
+    utils::HashMapExtension,
 };
-use candy_frontend::utils::HashMapExtension;
 use itertools::Itertools;
+use num_bigint::BigInt;
 use rustc_hash::FxHashMap;
 use std::{
     borrow::{Borrow, Cow},
-    io::{self, BufRead},
+    cell::RefCell,
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, BufRead, Cursor, Write},
     net::SocketAddr,
+    rc::Rc,
     str::FromStr,
+    sync::Arc,
+    time::SystemTime,
 };
 use tiny_http::{Request, Response, Server};
-use tracing::info;
+use tracing::{debug, error, info, trace, warn};
 
 pub trait Environment {
     fn handle<B: Borrow<ByteCode>, T: Tracer>(
@@ -29,10 +41,13 @@ pub struct EmptyEnvironment;
 impl Environment for EmptyEnvironment {
     fn handle<B: Borrow<ByteCode>, T: Tracer>(
         &mut self,
-        _heap: &mut Heap,
-        _call: VmHandleCall<B, T>,
+        heap: &mut Heap,
+        call: VmHandleCall<B, T>,
     ) -> Vm<B, T> {
-        panic!("A handle was called.")
+        call.fail(
+            heap,
+            "A handle was called, but the environment doesn't provide any.",
+        )
     }
 }
 impl<B: Borrow<ByteCode>, T: Tracer> Vm<B, T> {
@@ -52,16 +67,23 @@ impl<B: Borrow<ByteCode>, T: Tracer> Vm<B, T> {
 }
 
 pub struct DefaultEnvironment {
+    /// Handles that don't need access to the environment's state.
+    registry: HandleRegistry,
+
+    /// These use the [`RandomSource`] and [`Clock`] of the calling VM.
     get_random_bytes_handle: Handle,
+    get_current_time_handle: Handle,
 
     http_server_handle: Handle,
     /// `None` means the server got closed.
     http_server_states: Vec<Option<HttpServerState>>,
 
-    stdin_handle: Handle,
-    stdout_handle: Handle,
-
     dynamic_handles: FxHashMap<Handle, DynamicHandle>,
+
+    output: Output,
+    /// Shared with the `LoadAsset` handle, which fails until
+    /// [`DefaultEnvironment::with_assets`] is called.
+    assets: Rc<RefCell<Option<Assets>>>,
 }
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[allow(clippy::enum_variant_names)]
@@ -78,41 +100,225 @@ struct HttpServerState {
 type HttpServerIndex = usize;
 type HttpRequestId = usize;
 
+/// Where the `Stdin` handle of a [`DefaultEnvironment`] reads lines from.
+#[derive(Clone, Debug, Default)]
+pub enum Input {
+    /// The stdin of the host process.
+    #[default]
+    Host,
+    /// A fixed text that is read line by line, e.g., to run interactive
+    /// programs in tests. Afterwards, stdin behaves as if it was closed.
+    Scripted(String),
+}
+
+/// Where the `Stdout` and `Stderr` handles of a [`DefaultEnvironment`] write
+/// messages to.
+#[derive(Clone, Debug, Default)]
+pub enum Output {
+    /// The stdout and stderr of the host process.
+    #[default]
+    Host,
+    /// In-memory buffers so that tools can show or check the output without
+    /// touching the host process's stdio.
+    Captured(CapturedOutput),
+    /// Passes each message to a callback as soon as it's written, e.g., to
+    /// show it in a browser while the program is still running.
+    Streamed(OutputCallback),
+}
+
+/// The messages that Candy code sent to `environment.stdout` and
+/// `environment.stderr`, each followed by a newline.
+///
+/// Clones share their buffers, so you can keep a clone around to read the
+/// output after the VM finished.
+#[derive(Clone, Debug, Default)]
+pub struct CapturedOutput {
+    stdout: Rc<RefCell<String>>,
+    stderr: Rc<RefCell<String>>,
+}
+impl CapturedOutput {
+    #[must_use]
+    pub fn stdout(&self) -> String {
+        RefCell::borrow(&self.stdout).clone()
+    }
+    #[must_use]
+    pub fn stderr(&self) -> String {
+        RefCell::borrow(&self.stderr).clone()
+    }
+}
+
+/// Receives each message (without a trailing newline) and the stream it was
+/// written to.
+#[derive(Clone)]
+pub struct OutputCallback(Rc<OutputFn>);
+type OutputFn = dyn Fn(OutputStream, &str);
+impl OutputCallback {
+    pub fn new(callback: impl Fn(OutputStream, &str) + 'static) -> Self {
+        Self(Rc::new(callback))
+    }
+}
+impl Debug for OutputCallback {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("OutputCallback(..)")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+impl OutputStream {
+    const fn handle_name(self) -> &'static str {
+        match self {
+            Self::Stdout => "Stdout",
+            Self::Stderr => "Stderr",
+        }
+    }
+}
+impl Display for OutputStream {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Stdout => write!(f, "stdout"),
+            Self::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+/// Where the `LoadAsset` handle of a [`DefaultEnvironment`] reads assets from.
+///
+/// `environment.loadAsset "..data.json"` resolves the path like a `use` in the
+/// `module`, but only reads the asset when the program calls the handle. This
+/// way, programs can load large or optional files lazily instead of embedding
+/// them in the byte code.
+struct Assets {
+    module: Module,
+    provider: Box<dyn ModuleProvider>,
+    loaded: Vec<Module>,
+}
+impl Assets {
+    fn load(&mut self, path: &str) -> Result<Arc<Vec<u8>>, String> {
+        let asset = UsePath::parse(path)?.resolve_relative_to(self.module.clone())?;
+        if asset.kind != ModuleKind::Asset {
+            return Err(format!(
+                "`{path}` refers to a code module. Use `use` to import it."
+            ));
+        }
+        if !self.loaded.contains(&asset) {
+            self.loaded.push(asset.clone());
+        }
+        self.provider
+            .get_content(&asset)
+            .ok_or_else(|| format!("The asset `{path}` doesn't exist."))
+    }
+}
+
 impl DefaultEnvironment {
     pub fn new(heap: &mut Heap, args: &[String]) -> (Struct, Self) {
+        Self::new_with_output(heap, args, Output::Host)
+    }
+    pub fn new_with_output(heap: &mut Heap, args: &[String], output: Output) -> (Struct, Self) {
+        Self::new_with_input_and_output(heap, args, Input::Host, output)
+    }
+    pub fn new_with_input_and_output(
+        heap: &mut Heap,
+        args: &[String],
+        input: Input,
+        output: Output,
+    ) -> (Struct, Self) {
         let arguments = args
             .iter()
             .map(|it| Text::create(heap, true, it).into())
             .collect_vec();
         let arguments = List::create(heap, true, arguments.as_slice());
+
+        let mut registry = HandleRegistry::default();
+        match input {
+            Input::Host => registry.register(heap, "Stdin", 0, Self::stdin),
+            Input::Scripted(text) => {
+                let mut input = Cursor::new(text.into_bytes());
+                registry.register(heap, "Stdin", 0, move |heap, arguments| {
+                    assert!(arguments.is_empty());
+                    Self::read_line(heap, &mut input)
+                })
+            }
+        };
+        registry.register(heap, "Log", 3, |heap, arguments| Self::log(heap, arguments));
+        for stream in [OutputStream::Stdout, OutputStream::Stderr] {
+            let output = output.clone();
+            registry.register(heap, stream.handle_name(), 1, move |heap, arguments| {
+                Self::write_output(heap, arguments, &output, stream)
+            });
+        }
+
+        let assets = Rc::new(RefCell::new(None));
+        {
+            let assets = assets.clone();
+            registry.register(heap, "LoadAsset", 1, move |heap, arguments| {
+                Self::load_asset(heap, arguments, &assets)
+            });
+        }
+
         let get_random_bytes_handle = Handle::new(heap, 1);
+        let get_current_time_handle = Handle::new(heap, 0);
         let http_server_handle = Handle::new(heap, 1);
-        let stdin_handle = Handle::new(heap, 0);
-        let stdout_handle = Handle::new(heap, 1);
-        let environment_object = Struct::create_with_symbol_keys(
+        let environment_object = registry.create_environment_object(
             heap,
-            true,
             [
                 (heap.default_symbols().arguments, arguments.into()),
                 (
                     heap.default_symbols().get_random_bytes,
                     **get_random_bytes_handle,
                 ),
+                (
+                    heap.default_symbols().get_current_time,
+                    **get_current_time_handle,
+                ),
                 (heap.default_symbols().http_server, **http_server_handle),
-                (heap.default_symbols().stdin, **stdin_handle),
-                (heap.default_symbols().stdout, **stdout_handle),
             ],
         );
         let environment = Self {
+            registry,
             get_random_bytes_handle,
+            get_current_time_handle,
             http_server_handle,
             http_server_states: vec![],
-            stdin_handle,
-            stdout_handle,
             dynamic_handles: FxHashMap::default(),
+            output,
+            assets,
         };
         (environment_object, environment)
     }
+
+    /// Lets the program load assets relative to the `module` at runtime using
+    /// `environment.loadAsset`.
+    #[must_use]
+    pub fn with_assets(self, module: Module, provider: impl ModuleProvider + 'static) -> Self {
+        *self.assets.borrow_mut() = Some(Assets {
+            module,
+            provider: Box::new(provider),
+            loaded: vec![],
+        });
+        self
+    }
+    /// The assets that the program loaded at runtime so far, in the order in
+    /// which it first loaded them.
+    #[must_use]
+    pub fn loaded_assets(&self) -> Vec<Module> {
+        RefCell::borrow(&self.assets)
+            .as_ref()
+            .map(|assets| assets.loaded.clone())
+            .unwrap_or_default()
+    }
+
+    /// The output of the program if it was created with [`Output::Captured`].
+    #[must_use]
+    pub const fn captured_output(&self) -> Option<&CapturedOutput> {
+        match &self.output {
+            Output::Host | Output::Streamed(_) => None,
+            Output::Captured(captured) => Some(captured),
+        }
+    }
 }
 impl Environment for DefaultEnvironment {
     fn handle<B: Borrow<ByteCode>, T: Tracer>(
@@ -120,21 +326,24 @@ impl Environment for DefaultEnvironment {
         heap: &mut Heap,
         call: VmHandleCall<B, T>,
     ) -> Vm<B, T> {
+        let mut call = match self.registry.try_handle(heap, call) {
+            Ok(vm) => return vm,
+            Err(call) => call,
+        };
+
         let result = if call.handle == self.get_random_bytes_handle {
-            Self::get_random_bytes(heap, &call.arguments)
+            let arguments = call.arguments.clone();
+            Self::get_random_bytes(heap, call.random_source(), &arguments)
+        } else if call.handle == self.get_current_time_handle {
+            assert!(call.arguments.is_empty());
+            Self::get_current_time(heap, call.clock())
         } else if call.handle == self.http_server_handle {
             self.http_server(heap, &call.arguments)
-        } else if call.handle == self.stdin_handle {
-            Self::stdin(heap, &call.arguments)
-        } else if call.handle == self.stdout_handle {
-            Self::stdout(heap, &call.arguments)
         } else {
-            let dynamic_handle = self.dynamic_handles.get(&call.handle).unwrap_or_else(|| {
-                panic!(
-                    "A handle was called that doesn't exist: {handle:?}",
-                    handle = call.handle
-                )
-            });
+            let Some(dynamic_handle) = self.dynamic_handles.get(&call.handle) else {
+                let reason = format!("A handle was called that doesn't exist: {:?}", call.handle);
+                return call.fail(heap, reason);
+            };
             match dynamic_handle {
                 DynamicHandle::HttpServerGetNextRequest(server_index) => {
                     self.http_server_get_next_request(heap, *server_index, &call.arguments)
@@ -155,7 +364,11 @@ impl Environment for DefaultEnvironment {
     }
 }
 impl DefaultEnvironment {
-    fn get_random_bytes(heap: &mut Heap, arguments: &[InlineObject]) -> InlineObject {
+    fn get_random_bytes(
+        heap: &mut Heap,
+        random_source: &mut dyn RandomSource,
+        arguments: &[InlineObject],
+    ) -> InlineObject {
         let [length] = arguments else { unreachable!() };
         let Data::Int(length) = (*length).into() else {
             // TODO: Panic
@@ -177,8 +390,8 @@ impl DefaultEnvironment {
         };
 
         let mut bytes = vec![0u8; length];
-        if let Err(error) = getrandom::getrandom(&mut bytes) {
-            let message = Text::create(heap, true, &error.to_string());
+        if let Err(error) = random_source.fill_bytes(&mut bytes) {
+            let message = Text::create(heap, true, &error);
             return Tag::create_result(heap, true, Err(message.into())).into();
         }
 
@@ -189,6 +402,42 @@ impl DefaultEnvironment {
         let bytes = List::create(heap, true, bytes.as_slice());
         Tag::create_result(heap, true, Ok(bytes.into())).into()
     }
+    fn load_asset(
+        heap: &mut Heap,
+        arguments: &[InlineObject],
+        assets: &RefCell<Option<Assets>>,
+    ) -> InlineObject {
+        let [path] = arguments else { unreachable!() };
+        let result = match (*path).into() {
+            Data::Text(path) => assets.borrow_mut().as_mut().map_or_else(
+                || Err("This environment doesn't provide assets.".to_string()),
+                |assets| assets.load(path.get()),
+            ),
+            _ => Err("Handle `loadAsset` was called with a non-text.".to_string()),
+        };
+        match result {
+            Ok(bytes) => {
+                let bytes = bytes
+                    .iter()
+                    .map(|it| Int::create(heap, true, *it).into())
+                    .collect_vec();
+                let bytes = List::create(heap, true, bytes.as_slice());
+                Tag::create_result(heap, true, Ok(bytes.into())).into()
+            }
+            Err(message) => {
+                let message = Text::create(heap, true, &message);
+                Tag::create_result(heap, true, Err(message.into())).into()
+            }
+        }
+    }
+    /// Returns the microseconds since the Unix epoch.
+    fn get_current_time(heap: &mut Heap, clock: &mut dyn Clock) -> InlineObject {
+        let micros = match clock.now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(duration) => BigInt::from(duration.as_micros()),
+            Err(error) => -BigInt::from(error.duration().as_micros()),
+        };
+        Int::create_from_bigint(heap, true, micros).into()
+    }
 
     fn http_server(&mut self, heap: &mut Heap, arguments: &[InlineObject]) -> InlineObject {
         let [list_of_socket_texts] = arguments else {
@@ -385,22 +634,99 @@ impl DefaultEnvironment {
 
     fn stdin(heap: &mut Heap, arguments: &[InlineObject]) -> InlineObject {
         assert!(arguments.is_empty());
-        let input = {
-            let stdin = io::stdin();
-            stdin.lock().lines().next().unwrap().unwrap()
+        Self::read_line(heap, &mut io::stdin().lock())
+    }
+    /// Returns the next line without its line break. If stdin is closed or
+    /// can't be read, the program receives an `Error` instead of crashing.
+    fn read_line(heap: &mut Heap, input: &mut impl BufRead) -> InlineObject {
+        match input.lines().next() {
+            Some(Ok(line)) => Text::create(heap, true, &line).into(),
+            Some(Err(error)) => Self::io_error(heap, &format!("Couldn't read from stdin: {error}")),
+            None => Self::io_error(heap, "Stdin is closed."),
+        }
+    }
+    /// Candy code can log messages via `environment.log level message fields`,
+    /// e.g., `environment.log Info "Request handled." [Path: path]`. The level
+    /// is one of `Trace`, `Debug`, `Info`, `Warn`, and `Error`.
+    ///
+    /// Messages go to the host's `tracing` subscriber with the target `candy`,
+    /// so they show up next to the logs of the tooling.
+    fn log(heap: &Heap, arguments: &[InlineObject]) -> InlineObject {
+        let [level, message, fields] = arguments else {
+            unreachable!()
+        };
+        let level = match (*level).into() {
+            Data::Tag(tag) => tag.symbol().get(),
+            _ => "",
         };
-        Text::create(heap, true, &input).into()
+        let message = match (*message).into() {
+            Data::Text(text) => text.get().to_string(),
+            _ => message.to_debug_text(Precedence::Low, MaxLength::Unlimited),
+        };
+        let fields = fields.to_debug_text(Precedence::Low, MaxLength::Unlimited);
+        match level {
+            "Trace" => trace!(target: "candy", fields = %fields, "{message}"),
+            "Debug" => debug!(target: "candy", fields = %fields, "{message}"),
+            "Info" => info!(target: "candy", fields = %fields, "{message}"),
+            "Warn" => warn!(target: "candy", fields = %fields, "{message}"),
+            "Error" => error!(target: "candy", fields = %fields, "{message}"),
+            _ => {
+                warn!("Invalid log level sent to `environment.log`: {level:?}");
+                info!(target: "candy", fields = %fields, "{message}");
+            }
+        }
+
+        Tag::create_nothing(heap).into()
     }
-    fn stdout(heap: &Heap, arguments: &[InlineObject]) -> InlineObject {
+
+    fn write_output(
+        heap: &mut Heap,
+        arguments: &[InlineObject],
+        output: &Output,
+        stream: OutputStream,
+    ) -> InlineObject {
         let [message] = arguments else { unreachable!() };
-        if let Data::Text(text) = (*message).into() {
-            println!("{}", text.get());
-        } else {
-            info!("Non-text value sent to stdout: {message:?}");
-        }
+        let Data::Text(text) = (*message).into() else {
+            info!("Non-text value sent to {stream}: {message:?}");
+            return Tag::create_nothing(heap).into();
+        };
 
+        match (output, stream) {
+            (Output::Host, OutputStream::Stdout) => {
+                return Self::write_line(heap, &mut io::stdout().lock(), stream, text.get());
+            }
+            (Output::Host, OutputStream::Stderr) => {
+                return Self::write_line(heap, &mut io::stderr().lock(), stream, text.get());
+            }
+            (Output::Captured(captured), stream) => {
+                let mut buffer = match stream {
+                    OutputStream::Stdout => captured.stdout.borrow_mut(),
+                    OutputStream::Stderr => captured.stderr.borrow_mut(),
+                };
+                buffer.push_str(text.get());
+                buffer.push('\n');
+            }
+            (Output::Streamed(callback), stream) => (callback.0)(stream, text.get()),
+        }
         Tag::create_nothing(heap).into()
     }
+    /// A closed pipe shouldn't crash the program (as `println!` would), so IO
+    /// errors are returned to the program as an `Error`.
+    fn write_line(
+        heap: &mut Heap,
+        output: &mut impl Write,
+        stream: OutputStream,
+        text: &str,
+    ) -> InlineObject {
+        match writeln!(output, "{text}").and_then(|()| output.flush()) {
+            Ok(()) => Tag::create_nothing(heap).into(),
+            Err(error) => Self::io_error(heap, &format!("Couldn't write to {stream}: {error}")),
+        }
+    }
+    fn io_error(heap: &mut Heap, message: &str) -> InlineObject {
+        let message = Text::create(heap, true, message);
+        Tag::create_result(heap, true, Err(message.into())).into()
+    }
 
     fn create_dynamic_handle(
         &mut self,
@@ -435,13 +761,7 @@ impl<B: Borrow<ByteCode>, T: Tracer> Vm<B, T> {
         heap: &mut Heap,
         environment: &mut impl Environment,
     ) -> StateAfterRunWithoutHandles<B, T> {
-        match self.run(heap) {
-            StateAfterRun::Running(vm) => StateAfterRunWithoutHandles::Running(vm),
-            StateAfterRun::CallingHandle(call) => {
-                StateAfterRunWithoutHandles::Running(environment.handle(heap, call))
-            }
-            StateAfterRun::Finished(finished) => StateAfterRunWithoutHandles::Finished(finished),
-        }
+        self.run_n_with_environment(heap, environment, 1)
     }
 
     pub fn run_n_with_environment(
@@ -450,13 +770,16 @@ impl<B: Borrow<ByteCode>, T: Tracer> Vm<B, T> {
         environment: &mut impl Environment,
         max_instructions: usize,
     ) -> StateAfterRunWithoutHandles<B, T> {
-        for _ in 0..max_instructions {
-            match self.run_with_environment(heap, environment) {
-                StateAfterRunWithoutHandles::Running(vm) => self = vm,
-                finished @ StateAfterRunWithoutHandles::Finished(_) => return finished,
+        let mut instructions_left = max_instructions;
+        loop {
+            match self.run_until_event(heap, &mut instructions_left) {
+                VmEvent::BudgetExhausted(vm) => return StateAfterRunWithoutHandles::Running(vm),
+                VmEvent::HandleCall(call) => self = environment.handle(heap, call),
+                event => {
+                    return StateAfterRunWithoutHandles::Finished(event.into_finished().unwrap())
+                }
             }
         }
-        StateAfterRunWithoutHandles::Running(self)
     }
 
     pub fn run_forever_with_environment(
@@ -464,11 +787,290 @@ impl<B: Borrow<ByteCode>, T: Tracer> Vm<B, T> {
         heap: &mut Heap,
         environment: &mut impl Environment,
     ) -> VmFinished<T> {
+        // The VM only checks whether to compact the heap (see
+        // [`Vm::with_automatic_heap_compaction`]) when it starts running, so we
+        // run it in slices.
+        const INSTRUCTIONS_PER_SLICE: usize = 1_000_000;
+
+        let mut instructions_left = INSTRUCTIONS_PER_SLICE;
         loop {
-            match self.run_forever(heap) {
-                StateAfterRunForever::CallingHandle(call) => self = environment.handle(heap, call),
-                StateAfterRunForever::Finished(finished) => return finished,
+            match self.run_until_event(heap, &mut instructions_left) {
+                VmEvent::BudgetExhausted(vm) => {
+                    instructions_left = INSTRUCTIONS_PER_SLICE;
+                    self = vm;
+                }
+                VmEvent::HandleCall(call) => self = environment.handle(heap, call),
+                event => return event.into_finished().unwrap(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Assets, CapturedOutput, DefaultEnvironment, Output, OutputCallback, OutputStream};
+    use crate::heap::{Heap, InlineObject, Int, Struct, Tag, Text, ToDebugText};
+    use candy_frontend::{
+        format::{MaxLength, Precedence},
+        module::{InMemoryModuleProvider, Module, ModuleKind, Package},
+    };
+    use std::{
+        cell::RefCell,
+        fmt,
+        io::{self, Cursor, Write},
+        rc::Rc,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        Event, Level, Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        Layer,
+    };
+
+    #[test]
+    fn captured_output_is_written_to_buffers() {
+        let mut heap = Heap::default();
+        let captured = CapturedOutput::default();
+        let output = Output::Captured(captured.clone());
+
+        let hello = Text::create(&mut heap, true, "Hello");
+        let oops = Text::create(&mut heap, true, "Oops");
+        DefaultEnvironment::write_output(&mut heap, &[hello.into()], &output, OutputStream::Stdout);
+        DefaultEnvironment::write_output(&mut heap, &[hello.into()], &output, OutputStream::Stdout);
+        DefaultEnvironment::write_output(&mut heap, &[oops.into()], &output, OutputStream::Stderr);
+
+        assert_eq!(captured.stdout(), "Hello\nHello\n");
+        assert_eq!(captured.stderr(), "Oops\n");
+    }
+
+    #[test]
+    fn streamed_output_is_passed_to_the_callback() {
+        let mut heap = Heap::default();
+        let messages = Rc::new(RefCell::new(vec![]));
+        let output = Output::Streamed(OutputCallback::new({
+            let messages = messages.clone();
+            move |stream, message| messages.borrow_mut().push((stream, message.to_string()))
+        }));
+
+        let hello = Text::create(&mut heap, true, "Hello");
+        let oops = Text::create(&mut heap, true, "Oops");
+        DefaultEnvironment::write_output(&mut heap, &[hello.into()], &output, OutputStream::Stdout);
+        DefaultEnvironment::write_output(&mut heap, &[oops.into()], &output, OutputStream::Stderr);
+
+        assert_eq!(
+            *messages.borrow(),
+            [
+                (OutputStream::Stdout, "Hello".to_string()),
+                (OutputStream::Stderr, "Oops".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn reading_from_closed_stdin_returns_an_error() {
+        let mut heap = Heap::default();
+        let mut input = Cursor::new("line\n");
+
+        let line = DefaultEnvironment::read_line(&mut heap, &mut input);
+        assert_eq!(debug_text(line), r#""line""#);
+        let line = DefaultEnvironment::read_line(&mut heap, &mut input);
+        assert_eq!(debug_text(line), r#"Error "Stdin is closed.""#);
+    }
+    #[test]
+    fn reading_invalid_utf8_returns_an_error() {
+        let mut heap = Heap::default();
+        let mut input = Cursor::new(b"\xff\n");
+
+        let line = DefaultEnvironment::read_line(&mut heap, &mut input);
+        assert!(debug_text(line).starts_with(r#"Error "Couldn't read from stdin: "#));
+    }
+
+    #[test]
+    fn writing_to_broken_pipe_returns_an_error() {
+        struct BrokenPipe;
+        impl Write for BrokenPipe {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        let mut heap = Heap::default();
+        let result =
+            DefaultEnvironment::write_line(&mut heap, &mut BrokenPipe, OutputStream::Stdout, "Hi");
+        assert!(debug_text(result).starts_with(r#"Error "Couldn't write to stdout: "#));
+
+        let mut output = vec![];
+        let result =
+            DefaultEnvironment::write_line(&mut heap, &mut output, OutputStream::Stdout, "Hi");
+        assert_eq!(debug_text(result), "Nothing");
+        assert_eq!(output, b"Hi\n");
+    }
+
+    #[test]
+    fn assets_are_loaded_relative_to_the_module() {
+        let package = Package::Anonymous {
+            url: "test".to_string(),
+        };
+        let module = Module {
+            package: package.clone(),
+            path: vec!["main".to_string()],
+            kind: ModuleKind::Code,
+        };
+        let greeting = Module {
+            package,
+            path: vec!["greeting.txt".to_string()],
+            kind: ModuleKind::Asset,
+        };
+        let mut provider = InMemoryModuleProvider::default();
+        provider.add_str(&greeting, "Hi");
+        let assets = RefCell::new(Some(Assets {
+            module,
+            provider: Box::new(provider),
+            loaded: vec![],
+        }));
+
+        let mut heap = Heap::default();
+        let mut load = |path: &str| {
+            let path = Text::create(&mut heap, true, path);
+            debug_text(DefaultEnvironment::load_asset(
+                &mut heap,
+                &[path.into()],
+                &assets,
+            ))
+        };
+        assert_eq!(load("..greeting.txt"), "Ok (72, 105)");
+        assert_eq!(load("..greeting.txt"), "Ok (72, 105)");
+        assert_eq!(
+            load("..missing.txt"),
+            r#"Error "The asset `..missing.txt` doesn't exist.""#,
+        );
+        assert!(load("..main").starts_with(r#"Error "`..main` refers to a code module."#));
+
+        let loaded = assets.borrow().as_ref().unwrap().loaded.clone();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0], greeting);
+    }
+    #[test]
+    fn loading_assets_fails_without_a_provider() {
+        let mut heap = Heap::default();
+        let path = Text::create(&mut heap, true, "..greeting.txt");
+        let result = DefaultEnvironment::load_asset(&mut heap, &[path.into()], &RefCell::new(None));
+        assert_eq!(
+            debug_text(result),
+            r#"Error "This environment doesn't provide assets.""#,
+        );
+    }
+
+    /// The level, target, message, and `fields` of a logged event.
+    type CapturedEvent = (Level, String, String, String);
+    #[derive(Clone, Default)]
+    struct CapturedEvents(Arc<Mutex<Vec<CapturedEvent>>>);
+    impl<S: Subscriber> Layer<S> for CapturedEvents {
+        fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+            #[derive(Default)]
+            struct Visitor {
+                message: String,
+                fields: String,
+            }
+            impl Visit for Visitor {
+                fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                    match field.name() {
+                        "message" => self.message = format!("{value:?}"),
+                        "fields" => self.fields = format!("{value:?}"),
+                        _ => {}
+                    }
+                }
             }
+
+            let mut visitor = Visitor::default();
+            event.record(&mut visitor);
+            self.0.lock().unwrap().push((
+                *event.metadata().level(),
+                event.metadata().target().to_string(),
+                visitor.message,
+                visitor.fields,
+            ));
         }
     }
+
+    #[test]
+    fn log_messages_are_routed_into_tracing() {
+        let events = CapturedEvents::default();
+        let subscriber = tracing_subscriber::registry().with(events.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let mut heap = Heap::default();
+            let log = |heap: &mut Heap, level: &str, message: InlineObject| {
+                let level = Text::create(heap, true, level);
+                let level = Tag::create(level);
+                let path_key = Text::create(heap, true, "Path");
+                let path = Text::create(heap, true, "/");
+                let fields = Struct::create_with_symbol_keys(heap, true, [(path_key, path.into())]);
+                let result = DefaultEnvironment::log(heap, &[level.into(), message, fields.into()]);
+                assert_eq!(debug_text(result), "Nothing");
+            };
+            let message = Text::create(&mut heap, true, "Request handled.");
+            log(&mut heap, "Warn", message.into());
+            let message = Int::create(&mut heap, true, 42);
+            log(&mut heap, "Info", message.into());
+        });
+
+        let events = events.0.lock().unwrap().clone();
+        assert_eq!(
+            *events,
+            [
+                (
+                    Level::WARN,
+                    "candy".to_string(),
+                    "Request handled.".to_string(),
+                    r#"[Path: "/"]"#.to_string(),
+                ),
+                (
+                    Level::INFO,
+                    "candy".to_string(),
+                    "42".to_string(),
+                    r#"[Path: "/"]"#.to_string(),
+                ),
+            ],
+        );
+    }
+    #[test]
+    fn invalid_log_levels_fall_back_to_info() {
+        let events = CapturedEvents::default();
+        let subscriber = tracing_subscriber::registry().with(events.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let mut heap = Heap::default();
+            let level = Text::create(&mut heap, true, "Loud");
+            let message = Text::create(&mut heap, true, "Hi");
+            let fields = Struct::create_with_symbol_keys(&mut heap, true, []);
+
+            DefaultEnvironment::log(
+                &heap,
+                &[Tag::create(level).into(), message.into(), fields.into()],
+            );
+        });
+
+        let events = events.0.lock().unwrap().clone();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].0, Level::WARN);
+        assert!(events[0].2.contains("Loud"));
+        assert_eq!(
+            events[1],
+            (
+                Level::INFO,
+                "candy".to_string(),
+                "Hi".to_string(),
+                "[]".to_string(),
+            ),
+        );
+    }
+
+    fn debug_text(object: InlineObject) -> String {
+        object.to_debug_text(Precedence::Low, MaxLength::Unlimited)
+    }
 }
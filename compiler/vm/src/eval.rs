@@ -0,0 +1,332 @@
+//! Evaluating a single expression, e.g., for a REPL, for inspecting values in
+//! a debugger, or for checking examples in doc comments.
+//!
+//! The expression is compiled as the value of a definition that's appended to
+//! a module and that module is then run. Without a context, that module is
+//! otherwise empty. With a context, it's the context module itself, so the
+//! expression can access all of its definitions (even private ones) and
+//! relative `use`s work as usual. The module's content is only replaced during
+//! the evaluation.
+//!
+//! Tools that evaluate code the user didn't explicitly ask to run, such as doc
+//! examples, should pass an instruction budget so that an endless loop doesn't
+//! block them.
+
+use crate::{
+    environment::StateAfterRunWithoutHandles,
+    heap::{Data, Heap, InlineObject},
+    lir_to_byte_code::compile_byte_code,
+    tracer::DummyTracer,
+    Panic, Vm, VmFinished,
+};
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    cst::CstDb,
+    error::CompilerErrorPayload,
+    hir_to_mir::ExecutionTarget,
+    lir_optimize::OptimizeLir,
+    module::{Module, ModuleKind, ModuleProvider, MutableModuleProviderOwner, Package},
+    position::Offset,
+    string_to_rcst::ModuleError,
+    TracingConfig,
+};
+use itertools::Itertools;
+use std::{ops::Range, str};
+
+/// The definition that holds the evaluated expression. It's public so that
+/// we can get its value from the module's exports.
+const RESULT_NAME: &str = "toolingEvaluationResult";
+const RESULT_KEY: &str = "ToolingEvaluationResult";
+
+#[derive(Debug)]
+pub enum ExecutionResult {
+    /// The expression evaluated to this value. It lives in the heap that was
+    /// passed to [`eval_expression`].
+    Value(InlineObject),
+    /// The expression contains errors, so it didn't run. Warnings are not
+    /// reported.
+    CompilerErrors(Vec<SnippetError>),
+    Panicked {
+        panic: Panic,
+        /// The span of the responsible code if it's part of the expression.
+        snippet_span: Option<Range<Offset>>,
+    },
+    /// The expression didn't finish within the instruction budget.
+    DidNotFinish,
+}
+/// An error in the evaluated expression. The span is relative to the source of
+/// the expression.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SnippetError {
+    pub span: Range<Offset>,
+    pub payload: CompilerErrorPayload,
+}
+
+pub fn eval_expression<Db>(
+    db: &mut Db,
+    heap: &mut Heap,
+    source: &str,
+    context: Option<&Module>,
+    max_instructions: Option<usize>,
+) -> ExecutionResult
+where
+    Db: AstToHir + CstDb + MutableModuleProviderOwner + OptimizeLir,
+{
+    let (module, prefix) = if let Some(module) = context {
+        let Some(content) = db.get_module_content(module.clone()) else {
+            return ExecutionResult::CompilerErrors(vec![SnippetError {
+                span: Offset(0)..Offset(0),
+                payload: ModuleError::DoesNotExist.into(),
+            }]);
+        };
+        let Ok(content) = str::from_utf8(&content) else {
+            return ExecutionResult::CompilerErrors(vec![SnippetError {
+                span: Offset(0)..Offset(0),
+                payload: ModuleError::InvalidUtf8.into(),
+            }]);
+        };
+        (module.clone(), content.to_string())
+    } else {
+        let module = Module {
+            package: Package::Anonymous {
+                url: "evaluation".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        (module, String::new())
+    };
+    let snippet = Snippet::new(prefix, source);
+
+    let previous_overlay = db.get_in_memory_module_provider().get_content(&module);
+    db.did_change_module(&module, snippet.code.as_bytes().to_vec());
+    let result = compile_and_run(db, heap, &module, &snippet, max_instructions);
+    match previous_overlay {
+        Some(content) => db.did_change_module(&module, (*content).clone()),
+        None => db.did_close_module(&module),
+    }
+    result
+}
+
+fn compile_and_run<Db>(
+    db: &Db,
+    heap: &mut Heap,
+    module: &Module,
+    snippet: &Snippet,
+    max_instructions: Option<usize>,
+) -> ExecutionResult
+where
+    Db: AstToHir + CstDb + OptimizeLir,
+{
+    let (byte_code, errors) = compile_byte_code(
+        db,
+        ExecutionTarget::Module(module.clone()),
+        TracingConfig::off(),
+    );
+    // Errors in the context module or in modules it uses would also occur
+    // without the expression, so we only report those in the expression.
+    let mut errors = errors
+        .iter()
+        .filter(|error| {
+            &error.module == module
+                && !error.payload.is_warning()
+                && snippet.contains(error.span.start)
+        })
+        .map(|error| SnippetError {
+            span: snippet.map_span(&error.span),
+            payload: error.payload.clone(),
+        })
+        .collect_vec();
+    if !errors.is_empty() {
+        errors.sort_by_key(|it| (it.span.start, it.span.end));
+        return ExecutionResult::CompilerErrors(errors);
+    }
+
+    let vm = Vm::for_module(&byte_code, heap, DummyTracer);
+    let VmFinished { result, .. } = match max_instructions {
+        Some(max_instructions) => match vm.run_n_without_handles(heap, max_instructions) {
+            StateAfterRunWithoutHandles::Running(_) => return ExecutionResult::DidNotFinish,
+            StateAfterRunWithoutHandles::Finished(finished) => finished,
+        },
+        None => vm.run_forever_without_handles(heap),
+    };
+    match result {
+        Ok(return_value) => {
+            let Data::Struct(exports) = return_value.into() else {
+                panic!("A module didn't return a struct of its exports.");
+            };
+            let value = exports
+                .iter()
+                .find_map(|(_, key, value)| match Data::from(key) {
+                    Data::Tag(tag) if tag.symbol().get() == RESULT_KEY => Some(value),
+                    _ => None,
+                })
+                .expect("The exports don't contain the evaluated expression.");
+            // The value might live in the constant heap of the byte code,
+            // which is freed when we return.
+            let value = value.clone_to_heap(heap);
+            return_value.drop(heap);
+            ExecutionResult::Value(value)
+        }
+        Err(panic) => {
+            let snippet_span = Some(&panic.responsible)
+                .filter(|responsible| &responsible.module == module)
+                .and_then(|responsible| db.hir_id_to_origin_span(responsible))
+                .filter(|span| snippet.contains(span.start))
+                .map(|span| snippet.map_span(&span));
+            ExecutionResult::Panicked {
+                panic,
+                snippet_span,
+            }
+        }
+    }
+}
+
+/// The code of the module with the expression appended to it.
+///
+/// The expression is indented so that it can span multiple lines, so offsets
+/// have to be mapped line by line.
+struct Snippet {
+    code: String,
+    /// For each line of the expression, the offset where it starts in the
+    /// code and in the expression.
+    line_starts: Vec<(usize, usize)>,
+}
+impl Snippet {
+    fn new(mut code: String, source: &str) -> Self {
+        if !code.is_empty() && !code.ends_with('\n') {
+            code.push('\n');
+        }
+        code.push_str(RESULT_NAME);
+        code.push_str(" :=");
+
+        let mut line_starts = vec![];
+        let mut offset_in_source = 0;
+        // An empty expression still gets a line so that the resulting error
+        // is reported.
+        let lines = source.split_inclusive('\n').collect_vec();
+        let lines = if lines.is_empty() { vec![""] } else { lines };
+        for line in lines {
+            code.push_str("\n  ");
+            line_starts.push((code.len(), offset_in_source));
+            code.push_str(line.trim_end_matches(['\r', '\n']));
+            offset_in_source += line.len();
+        }
+        code.push('\n');
+        Self { code, line_starts }
+    }
+
+    fn contains(&self, offset: Offset) -> bool {
+        self.line_starts
+            .first()
+            .is_some_and(|(start, _)| *offset >= *start)
+    }
+    fn map_span(&self, span: &Range<Offset>) -> Range<Offset> {
+        self.map_offset(span.start)..self.map_offset(span.end)
+    }
+    fn map_offset(&self, offset: Offset) -> Offset {
+        let index = self
+            .line_starts
+            .partition_point(|(in_code, _)| *in_code <= *offset);
+        let Some(index) = index.checked_sub(1) else {
+            return Offset(0);
+        };
+        let (in_code, in_source) = self.line_starts[index];
+        let mapped = in_source + (*offset - in_code);
+        // Offsets in the indentation of the next line belong to the end of
+        // this one.
+        let next_line_start = self
+            .line_starts
+            .get(index + 1)
+            .map_or(usize::MAX, |(_, in_source)| *in_source);
+        Offset(mapped.min(next_line_start))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{HeapObject, InlineData, ObjectInHeap, ToDebugText};
+    use candy_frontend::{
+        format::{MaxLength, Precedence},
+        test_database::Database,
+    };
+
+    #[test]
+    fn evaluated_constants_outlive_the_byte_code() {
+        let mut db = Database::default();
+        let mut heap = Heap::default();
+        for (source, expected) in [(r#""hi""#, r#""hi""#), ("Foo", "Foo"), ("Foo 1", "Foo 1")] {
+            let ExecutionResult::Value(value) =
+                eval_expression(&mut db, &mut heap, source, None, Some(1000))
+            else {
+                panic!("Evaluating `{source}` failed.");
+            };
+            let object: Option<HeapObject> = match InlineData::from(value) {
+                InlineData::Pointer(pointer) => Some(pointer.get()),
+                InlineData::Tag(tag) => Some(**tag.get()),
+                _ => None,
+            };
+            if let Some(object) = object {
+                assert!(heap.objects().contains(&ObjectInHeap(object)));
+            }
+            assert_eq!(
+                value.to_debug_text(Precedence::Low, MaxLength::Unlimited),
+                expected,
+            );
+            value.drop(&mut heap);
+        }
+    }
+
+    #[test]
+    fn match_cases_with_guards_and_wildcards() {
+        let is_three = "isThree = { a ->\n  a %\n    3 -> True\n    _ -> False\n}\n";
+        for (source, expected) in [
+            // A false guard falls through to the next case.
+            ("Foo 4 %\n  Foo x if False -> x\n  Foo x -> 5", "5"),
+            // Guards can use the captured identifiers.
+            (
+                &format!("{is_three}Foo 3 %\n  Foo x if isThree x -> x\n  _ -> 5"),
+                "3",
+            ),
+            (
+                &format!("{is_three}Foo 4 %\n  Foo x if isThree x -> x\n  _ -> 5"),
+                "5",
+            ),
+            // `_` matches everything, also in lists and tags.
+            ("4 %\n  3 -> 3\n  _ -> 5", "5"),
+            ("(1, Foo 2) %\n  (_, Bar _) -> 3\n  (_, Foo _) -> 5", "5"),
+        ] {
+            let mut db = Database::default();
+            let mut heap = Heap::default();
+            let ExecutionResult::Value(value) =
+                eval_expression(&mut db, &mut heap, source, None, Some(10000))
+            else {
+                panic!("Evaluating `{source}` failed.");
+            };
+            assert_eq!(
+                value.to_debug_text(Precedence::Low, MaxLength::Unlimited),
+                expected,
+                "Evaluating `{source}` returned the wrong value.",
+            );
+        }
+    }
+
+    #[test]
+    fn test_snippet_offsets() {
+        let snippet = Snippet::new("foo = 1".to_string(), "bar\n  | baz");
+        assert_eq!(
+            snippet.code,
+            "foo = 1\ntoolingEvaluationResult :=\n  bar\n    | baz\n",
+        );
+
+        let bar = snippet.code.find("bar").unwrap();
+        let baz = snippet.code.find("baz").unwrap();
+        assert!(!snippet.contains(Offset(0)));
+        assert!(snippet.contains(Offset(bar)));
+        assert_eq!(snippet.map_offset(Offset(bar)), Offset(0));
+        assert_eq!(snippet.map_offset(Offset(bar + 3)), Offset(3));
+        assert_eq!(snippet.map_offset(Offset(baz)), Offset(8));
+        assert_eq!(snippet.map_offset(Offset(baz + 3)), Offset(11));
+    }
+}
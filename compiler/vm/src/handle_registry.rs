@@ -0,0 +1,302 @@
+//! Handles that embedders expose to Candy code.
+//!
+//! A handle is a value that Candy code can call like a function, but calling
+//! it pauses the VM with [`StateAfterRun::CallingHandle`] so that the host can
+//! compute the result. The [`HandleRegistry`] takes care of the bookkeeping:
+//! You register named handles together with the Rust functions implementing
+//! them, pass the generated environment struct to the `main` function, and use
+//! the registry as the [`Environment`] when running the VM:
+//!
+//! ```ignore
+//! let mut registry = HandleRegistry::default();
+//! registry.register(&mut heap, "Print", 1, |heap, arguments| {
+//!     println!("{}", arguments[0].to_debug_text(Precedence::Low, MaxLength::Unlimited));
+//!     Tag::create_nothing(heap).into()
+//! });
+//! let environment = registry.create_environment_object(&mut heap, []);
+//! let vm = Vm::for_main_function(&byte_code, &mut heap, environment, tracer);
+//! let VmFinished { result, .. } = vm.run_forever_with_environment(&mut heap, &mut registry);
+//! ```
+//!
+//! Candy code can then call `environment.print "Hello"`.
+//!
+//! [`StateAfterRun::CallingHandle`]: crate::StateAfterRun::CallingHandle
+
+use crate::{
+    byte_code::ByteCode,
+    environment::Environment,
+    heap::{Handle, Heap, InlineObject, Struct, Text},
+    tracer::Tracer,
+    vm::VmHandleCall,
+    Vm,
+};
+use candy_frontend::utils::HashMapExtension;
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+use std::borrow::Borrow;
+
+/// Computes the result of a handle call.
+///
+/// The arguments are dropped after the function returns, so it has to `dup`
+/// any argument that it wants to keep or return.
+pub type HandleFunction = Box<dyn FnMut(&mut Heap, &[InlineObject]) -> InlineObject>;
+
+#[derive(Default)]
+pub struct HandleRegistry {
+    handles: FxHashMap<Handle, RegisteredHandle>,
+
+    /// The names in registration order, which is also the order of the fields
+    /// in the environment struct.
+    names: Vec<(String, Handle)>,
+}
+struct RegisteredHandle {
+    name: String,
+    function: HandleFunction,
+}
+
+impl HandleRegistry {
+    /// Creates a new handle that accepts `argument_count` arguments and is
+    /// implemented by `function`.
+    ///
+    /// The `name` is used as the key in the environment struct, so it has to
+    /// be a valid symbol, e.g., `Stdout`.
+    pub fn register(
+        &mut self,
+        heap: &mut Heap,
+        name: impl Into<String>,
+        argument_count: usize,
+        function: impl FnMut(&mut Heap, &[InlineObject]) -> InlineObject + 'static,
+    ) -> Handle {
+        let name = name.into();
+        assert!(
+            name.starts_with(|it: char| it.is_ascii_uppercase()),
+            "Handle names must be valid symbols, but got `{name}`.",
+        );
+        assert!(
+            self.get(&name).is_none(),
+            "A handle named `{name}` is already registered.",
+        );
+
+        let handle = Handle::new(heap, argument_count);
+        self.names.push((name.clone(), handle));
+        self.handles.force_insert(
+            handle,
+            RegisteredHandle {
+                name,
+                function: Box::new(function),
+            },
+        );
+        handle
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Handle> {
+        self.names
+            .iter()
+            .find(|(it, _)| it == name)
+            .map(|(_, handle)| *handle)
+    }
+    #[must_use]
+    pub fn name_of(&self, handle: Handle) -> Option<&str> {
+        self.handles.get(&handle).map(|it| it.name.as_str())
+    }
+
+    /// Creates a struct that maps each handle's name to the handle, followed by
+    /// the `additional_fields`.
+    ///
+    /// The struct takes ownership of the registered handles' references, so
+    /// call this at most once.
+    pub fn create_environment_object(
+        &self,
+        heap: &mut Heap,
+        additional_fields: impl IntoIterator<Item = (Text, InlineObject)>,
+    ) -> Struct {
+        let fields = self
+            .names
+            .iter()
+            .map(|(name, handle)| (Text::create(heap, true, name), InlineObject::from(*handle)))
+            .collect_vec();
+        Struct::create_with_symbol_keys(heap, true, fields.into_iter().chain(additional_fields))
+    }
+
+    /// Completes the call if it calls a registered handle. Otherwise, the call
+    /// is returned so that another environment can handle it.
+    pub fn try_handle<B: Borrow<ByteCode>, T: Tracer>(
+        &mut self,
+        heap: &mut Heap,
+        call: VmHandleCall<B, T>,
+    ) -> Result<Vm<B, T>, VmHandleCall<B, T>> {
+        let Some(handle) = self.handles.get_mut(&call.handle) else {
+            return Err(call);
+        };
+        let result = (handle.function)(heap, &call.arguments);
+        Ok(call.complete(heap, result))
+    }
+}
+impl Environment for HandleRegistry {
+    fn handle<B: Borrow<ByteCode>, T: Tracer>(
+        &mut self,
+        heap: &mut Heap,
+        call: VmHandleCall<B, T>,
+    ) -> Vm<B, T> {
+        self.try_handle(heap, call).unwrap_or_else(|call| {
+            panic!(
+                "A handle was called that isn't registered: {handle:?}",
+                handle = call.handle,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HandleRegistry;
+    use crate::{
+        byte_code::ByteCode,
+        heap::{Data, Heap, Int, Tag, Text, ToDebugText},
+        lir_to_byte_code::compile_byte_code,
+        tracer::DummyTracer,
+        PopulateInMemoryProviderFromFileSystem, Vm, VmEvent, VmFinished,
+    };
+    use candy_frontend::{
+        format::{MaxLength, Precedence},
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+
+    #[track_caller]
+    fn compile(source: &str) -> ByteCode {
+        let mut db = Database::default();
+        db.get_in_memory_module_provider()
+            .load_package_from_file_system("Builtins");
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, source.as_bytes().to_vec());
+        let (byte_code, errors) = compile_byte_code(
+            &db,
+            ExecutionTarget::MainFunction(module),
+            TracingConfig::off(),
+        );
+        assert!(
+            errors.iter().all(|it| it.payload.is_warning()),
+            "{errors:?}",
+        );
+        byte_code
+    }
+
+    fn register_double(registry: &mut HandleRegistry, heap: &mut Heap) {
+        registry.register(heap, "Double", 1, |heap, arguments| {
+            let Data::Int(value) = Data::from(arguments[0]) else {
+                panic!("Expected an int.");
+            };
+            Int::create_from_bigint(heap, true, value.get().as_ref() * 2).into()
+        });
+    }
+
+    #[test]
+    fn registered_handles_can_be_looked_up() {
+        let mut heap = Heap::default();
+        let mut registry = HandleRegistry::default();
+        let print = registry.register(&mut heap, "Print", 1, |heap, _| {
+            Tag::create_nothing(heap).into()
+        });
+        let exit = registry.register(&mut heap, "Exit", 0, |heap, _| {
+            Tag::create_nothing(heap).into()
+        });
+
+        assert_ne!(print, exit);
+        assert_eq!(registry.get("Print"), Some(print));
+        assert_eq!(registry.get("Exit"), Some(exit));
+        assert_eq!(registry.get("Stdout"), None);
+        assert_eq!(registry.name_of(print), Some("Print"));
+        assert_eq!(registry.name_of(exit), Some("Exit"));
+    }
+
+    #[test]
+    #[should_panic(expected = "A handle named `Print` is already registered.")]
+    fn registering_a_name_twice_panics() {
+        let mut heap = Heap::default();
+        let mut registry = HandleRegistry::default();
+        registry.register(&mut heap, "Print", 1, |heap, _| {
+            Tag::create_nothing(heap).into()
+        });
+        registry.register(&mut heap, "Print", 1, |heap, _| {
+            Tag::create_nothing(heap).into()
+        });
+    }
+    #[test]
+    #[should_panic(expected = "Handle names must be valid symbols, but got `print`.")]
+    fn registering_a_lowercase_name_panics() {
+        let mut heap = Heap::default();
+        HandleRegistry::default().register(&mut heap, "print", 1, |heap, _| {
+            Tag::create_nothing(heap).into()
+        });
+    }
+
+    #[test]
+    fn the_environment_object_contains_handles_and_additional_fields() {
+        let mut heap = Heap::default();
+        let mut registry = HandleRegistry::default();
+        let print = registry.register(&mut heap, "Print", 1, |heap, _| {
+            Tag::create_nothing(heap).into()
+        });
+        let answer_key = Text::create(&mut heap, true, "Answer");
+        let answer = Int::create(&mut heap, true, 42);
+
+        let environment =
+            registry.create_environment_object(&mut heap, [(answer_key, answer.into())]);
+        assert_eq!(environment.len(), 2);
+        let print_key = Text::create(&mut heap, true, "Print");
+        assert_eq!(environment.get(Tag::create(print_key)), Some(print.into()),);
+        let answer_key = Text::create(&mut heap, true, "Answer");
+        assert_eq!(
+            environment.get(Tag::create(answer_key)),
+            Some(answer.into()),
+        );
+    }
+
+    #[test]
+    fn handle_calls_are_completed_by_the_registered_function() {
+        let byte_code =
+            compile("main := { environment -> environment.double (environment.double 10) }");
+        let mut heap = Heap::default();
+        let mut registry = HandleRegistry::default();
+        register_double(&mut registry, &mut heap);
+        let environment = registry.create_environment_object(&mut heap, []);
+
+        let vm = Vm::for_main_function(&byte_code, &mut heap, environment, DummyTracer);
+        let VmFinished { result, .. } = vm.run_forever_with_environment(&mut heap, &mut registry);
+        assert_eq!(
+            result
+                .unwrap()
+                .to_debug_text(Precedence::Low, MaxLength::Unlimited),
+            "40",
+        );
+    }
+
+    #[test]
+    fn calls_of_unregistered_handles_are_returned() {
+        let byte_code = compile("main := { environment -> environment.double 21 }");
+        let mut heap = Heap::default();
+        let mut registry = HandleRegistry::default();
+        register_double(&mut registry, &mut heap);
+        let environment = registry.create_environment_object(&mut heap, []);
+
+        let vm = Vm::for_main_function(&byte_code, &mut heap, environment, DummyTracer);
+        let mut instructions_left = usize::MAX;
+        let VmEvent::HandleCall(call) = vm.run_until_event(&mut heap, &mut instructions_left)
+        else {
+            panic!("Expected a handle call.");
+        };
+        assert!(HandleRegistry::default()
+            .try_handle(&mut heap, call)
+            .is_err());
+    }
+}
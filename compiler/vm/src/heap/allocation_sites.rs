@@ -0,0 +1,163 @@
+//! Optionally, the heap remembers which instruction allocated each object.
+//! Grouping the live objects by these allocation sites shows which code (or
+//! which tracer holding on to values) is responsible for memory growth in
+//! long-running VMs, e.g., in the language server.
+
+use super::{AddressMap, Heap, HeapData, HeapObject, HeapObjectTrait, ObjectInHeap};
+use crate::instruction_pointer::InstructionPointer;
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+use std::cmp::Reverse;
+
+#[derive(Default)]
+pub(super) struct AllocationSites {
+    /// The instruction that is currently running, if any.
+    current_site: Option<InstructionPointer>,
+    /// How many objects were allocated since tracking started. We use this as a
+    /// clock for the age of objects.
+    allocation_count: usize,
+    objects: FxHashMap<ObjectInHeap, Allocation>,
+}
+#[derive(Clone, Copy)]
+struct Allocation {
+    site: Option<InstructionPointer>,
+    allocation_count: usize,
+}
+
+/// The live objects that were allocated by one instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AllocationSiteUsage {
+    /// The allocating instruction, or `None` for objects allocated outside of
+    /// running instructions, e.g., arguments created by the host.
+    pub site: Option<InstructionPointer>,
+    pub objects: usize,
+    /// The total size of these objects, including their headers.
+    pub bytes: usize,
+    /// How many allocations happened since the oldest of these objects was
+    /// allocated.
+    pub max_age: usize,
+}
+
+impl Heap {
+    /// Starts remembering which instruction allocated each new object (see
+    /// [`Heap::live_objects_by_allocation_site`]).
+    ///
+    /// This costs memory and time for every allocation, so only enable it for
+    /// debugging.
+    pub fn track_allocation_sites(&mut self) {
+        self.allocation_sites
+            .get_or_insert_with(AllocationSites::default);
+    }
+    /// Attributes the following allocations to `site`. Does nothing unless
+    /// tracking is enabled.
+    pub(crate) fn set_allocation_site(&mut self, site: Option<InstructionPointer>) {
+        if let Some(allocation_sites) = &mut self.allocation_sites {
+            allocation_sites.current_site = site;
+        }
+    }
+
+    /// Groups the live objects by the instruction that allocated them, sorted
+    /// by their total size in descending order.
+    ///
+    /// Returns `None` if [`Heap::track_allocation_sites`] wasn't called.
+    #[must_use]
+    pub fn live_objects_by_allocation_site(&self) -> Option<Vec<AllocationSiteUsage>> {
+        let allocation_sites = self.allocation_sites.as_ref()?;
+        let mut usages = FxHashMap::<Option<InstructionPointer>, AllocationSiteUsage>::default();
+        for (object, allocation) in &allocation_sites.objects {
+            let usage = usages
+                .entry(allocation.site)
+                .or_insert_with(|| AllocationSiteUsage {
+                    site: allocation.site,
+                    objects: 0,
+                    bytes: 0,
+                    max_age: 0,
+                });
+            usage.objects += 1;
+            usage.bytes += HeapData::from(**object).total_size();
+            let age = allocation_sites
+                .allocation_count
+                .saturating_sub(allocation.allocation_count);
+            usage.max_age = usage.max_age.max(age);
+        }
+        Some(
+            usages
+                .into_values()
+                .sorted_by_key(|it| (Reverse(it.bytes), it.site))
+                .collect(),
+        )
+    }
+}
+
+impl AllocationSites {
+    pub(super) fn notify_allocated(&mut self, object: HeapObject) {
+        let allocation = Allocation {
+            site: self.current_site,
+            allocation_count: self.allocation_count,
+        };
+        self.objects.insert(ObjectInHeap(object), allocation);
+        self.allocation_count += 1;
+    }
+    pub(super) fn notify_deallocated(&mut self, object: HeapObject) {
+        self.objects.remove(&ObjectInHeap(object));
+    }
+    /// Objects of the other heap keep their site, but their age is relative to
+    /// the other heap's allocations.
+    pub(super) fn adopt(&mut self, other: Self) {
+        self.objects.extend(other.objects);
+    }
+
+    /// Compaction merges objects with equal contents. The merged object keeps
+    /// the allocation of the oldest one.
+    pub(super) fn relocate(&mut self, address_map: &AddressMap) {
+        let mut objects = FxHashMap::<ObjectInHeap, Allocation>::default();
+        for (object, allocation) in self.objects.drain() {
+            let Some(new_object) = address_map.get(*object) else {
+                continue;
+            };
+            objects
+                .entry(ObjectInHeap(new_object))
+                .and_modify(|existing| {
+                    if allocation.allocation_count < existing.allocation_count {
+                        *existing = allocation;
+                    }
+                })
+                .or_insert(allocation);
+        }
+        self.objects = objects;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::heap::{Heap, List, Text};
+
+    #[test]
+    fn groups_live_objects_by_allocation_site() {
+        let mut heap = Heap::default();
+        assert_eq!(heap.live_objects_by_allocation_site(), None);
+        heap.track_allocation_sites();
+
+        heap.set_allocation_site(Some(1.into()));
+        let text = Text::create(&mut heap, true, "item");
+        heap.set_allocation_site(Some(2.into()));
+        let list = List::create(&mut heap, true, &[text.into()]);
+        let other_list = List::create(&mut heap, true, &[]);
+        other_list.drop(&mut heap);
+
+        let mut usages = heap.live_objects_by_allocation_site().unwrap();
+        usages.sort_by_key(|it| it.site);
+        let sites_and_objects = usages
+            .iter()
+            .map(|it| (it.site, it.objects))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sites_and_objects,
+            [(Some(1.into()), 1), (Some(2.into()), 1)]
+        );
+        assert_eq!(usages[0].max_age, 3);
+
+        list.drop(&mut heap);
+        assert_eq!(heap.live_objects_by_allocation_site(), Some(vec![]));
+    }
+}
@@ -0,0 +1,221 @@
+//! Long-running VMs (e.g., in the language server) allocate and free lots of
+//! objects, which leaves the memory of the remaining objects scattered.
+//! Compaction moves all live objects into freshly allocated memory at once.
+//!
+//! Moving objects changes their addresses, so everyone holding a reference
+//! into the heap has to update it using the returned [`AddressMap`], e.g., with
+//! [`Relocate`].
+//!
+//! Compaction reuses the machinery for cloning heaps. That machinery merges
+//! objects with equal contents, so compacting also deduplicates values.
+
+use super::{
+    object_heap::text::HeapText, object_inline::tag::InlineTag, Function, Heap, HeapObject, HirId,
+    InlineData, InlineObject, Int, List, ObjectInHeap, Struct, Tag, Text, WeakReferences,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::mem;
+
+/// Maps the old addresses of objects to their new ones.
+///
+/// Objects that weren't part of the compacted heap (e.g., constants of the
+/// byte code) are not contained.
+#[derive(Default)]
+pub struct AddressMap(FxHashMap<ObjectInHeap, HeapObject>);
+impl AddressMap {
+    #[must_use]
+    pub fn get(&self, object: HeapObject) -> Option<HeapObject> {
+        self.0.get(&ObjectInHeap(object)).copied()
+    }
+}
+
+impl Heap {
+    /// If less memory than this was freed since the last compaction, compacting
+    /// isn't worth it.
+    const MIN_FREED_BYTES_FOR_COMPACTION: usize = 1024 * 1024;
+
+    /// Estimates the fragmentation as the share of memory freed since the last
+    /// compaction, relative to the memory that's still in use.
+    ///
+    /// Returns a value between 0 (no objects were freed) and 1.
+    #[must_use]
+    pub fn fragmentation(&self) -> f64 {
+        let total = self.live_bytes + self.freed_bytes;
+        if total == 0 {
+            return 0.;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let fragmentation = self.freed_bytes as f64 / total as f64;
+        fragmentation
+    }
+    #[must_use]
+    pub fn should_compact(&self, fragmentation_threshold: f64) -> bool {
+        self.freed_bytes >= Self::MIN_FREED_BYTES_FOR_COMPACTION
+            && self.fragmentation() > fragmentation_threshold
+    }
+
+    /// Moves all objects to new addresses and returns a mapping from the old
+    /// to the new addresses.
+    ///
+    /// Reference counts, handles, and weak references are preserved and no
+    /// finalizers run, except for those of objects that were still waiting to
+    /// be freed (see [`Heap::limit_objects_freed_at_once`]).
+    pub fn compact(&mut self) -> AddressMap {
+        self.finish_freeing();
+
+        let mut compacted = Self {
+            objects: FxHashSet::default(),
+            default_symbols: None,
+            handle_id_generator: self.handle_id_generator.clone(),
+            handle_refcounts: FxHashMap::default(),
+            weak_references: WeakReferences::default(),
+            objects_to_free: None,
+            objects_to_clone: None,
+            objects_being_compacted: Some(mem::take(&mut self.objects)),
+            max_objects_freed_at_once: self.max_objects_freed_at_once,
+            live_bytes: 0,
+            freed_bytes: 0,
+            allocation_sites: None,
+        };
+
+        let mut mapping = FxHashMap::default();
+        compacted.default_symbols = Some(
+            self.default_symbols()
+                .clone_to_heap_with_mapping(&mut compacted, &mut mapping),
+        );
+        let objects = compacted.objects_being_compacted.clone().unwrap();
+        let address_map = AddressMap(
+            objects
+                .iter()
+                .map(|&object| {
+                    let new_object =
+                        object.clone_to_heap_with_mapping(&mut compacted, &mut mapping);
+                    (object, new_object)
+                })
+                .collect(),
+        );
+        self.objects = compacted.objects_being_compacted.take().unwrap();
+
+        // Cloning counts every reference it encounters, but nothing new
+        // references the moved objects. Objects with equal contents got merged,
+        // so their reference counts add up.
+        let mut reference_counts = FxHashMap::<ObjectInHeap, usize>::default();
+        for (old, new) in &address_map.0 {
+            if let Some(reference_count) = old.reference_count() {
+                *reference_counts.entry(ObjectInHeap(*new)).or_default() += reference_count;
+            }
+        }
+        for (object, reference_count) in reference_counts {
+            if object.reference_count().is_some() {
+                object.set_reference_count(reference_count);
+            }
+        }
+        // Cloning handles registers them again, so we keep the original counts.
+        compacted.handle_refcounts = mem::take(&mut self.handle_refcounts);
+        compacted.weak_references = mem::take(&mut self.weak_references);
+        compacted.weak_references.relocate(&address_map);
+        compacted.allocation_sites = self.allocation_sites.take().map(|mut allocation_sites| {
+            allocation_sites.relocate(&address_map);
+            allocation_sites
+        });
+
+        // Dropping the old heap frees the old objects.
+        *self = compacted;
+        address_map
+    }
+}
+
+impl InlineObject {
+    /// Returns the object at its new address after the heap containing it got
+    /// compacted.
+    #[must_use]
+    pub fn relocated(self, address_map: &AddressMap) -> Self {
+        match InlineData::from(self) {
+            InlineData::Pointer(pointer) => address_map.get(pointer.get()).map_or(self, Self::from),
+            InlineData::Tag(tag) => address_map.get(**tag.get()).map_or(self, |symbol| {
+                *InlineTag::new(HeapText::new_unchecked(symbol).into())
+            }),
+            InlineData::Int(_) | InlineData::Builtin(_) | InlineData::Handle(_) => self,
+        }
+    }
+}
+
+/// Objects that know how to update the references they hold into a heap after
+/// that heap got compacted.
+pub trait Relocate {
+    fn relocate(&mut self, address_map: &AddressMap);
+}
+impl Relocate for InlineObject {
+    fn relocate(&mut self, address_map: &AddressMap) {
+        *self = self.relocated(address_map);
+    }
+}
+macro_rules! impl_relocate_via_inline_object {
+    ($($type:ty),*$(,)?) => {
+        $(
+            impl Relocate for $type {
+                fn relocate(&mut self, address_map: &AddressMap) {
+                    let object: InlineObject = (*self).into();
+                    *self = object.relocated(address_map).try_into().unwrap();
+                }
+            }
+        )*
+    };
+}
+impl_relocate_via_inline_object!(Function, HirId, Int, List, Struct, Tag, Text);
+
+#[cfg(test)]
+mod tests {
+    use crate::heap::{Heap, List, ObjectInHeap, Relocate, Text};
+
+    #[test]
+    fn compaction_keeps_objects_and_reference_counts() {
+        let mut heap = Heap::default();
+        let text = Text::create(&mut heap, true, "item");
+        text.dup_by(2);
+        let mut list = List::create(&mut heap, true, &[text.into(), text.into()]);
+        let old_list = **list;
+
+        let address_map = heap.compact();
+        list.relocate(&address_map);
+        assert!(!list.pointer_equals(old_list));
+        assert!(heap.objects().contains(&ObjectInHeap(**list)));
+
+        let text: Text = list.get(0).try_into().unwrap();
+        assert_eq!(text.get(), "item");
+        assert_eq!(text.reference_count(), Some(3));
+
+        list.drop(&mut heap);
+        assert!(heap.objects().contains(&ObjectInHeap(**text)));
+        text.drop(&mut heap);
+        assert!(!heap.objects().contains(&ObjectInHeap(**text)));
+    }
+
+    #[test]
+    fn compaction_keeps_constants_and_weak_references() {
+        let mut constants = Heap::default();
+        let constant = Text::create(&mut constants, false, "constant");
+
+        let mut heap = Heap::default();
+        let mut list = List::create(&mut heap, true, &[constant.into()]);
+        let constant_reference = heap.downgrade(**constant);
+        let list_reference = heap.downgrade(**list);
+        let object_count = heap.objects().len();
+
+        let address_map = heap.compact();
+        list.relocate(&address_map);
+        assert_eq!(heap.objects().len(), object_count);
+        assert!(!heap.objects().contains(&ObjectInHeap(**constant)));
+        let item: Text = list.get(0).try_into().unwrap();
+        assert!(item.pointer_equals(**constant));
+
+        let upgraded = heap.upgrade(list_reference).unwrap();
+        assert!(upgraded.pointer_equals(**list));
+        upgraded.drop(&mut heap);
+        let upgraded = heap.upgrade(constant_reference).unwrap();
+        assert!(upgraded.pointer_equals(**constant));
+
+        list.drop(&mut heap);
+        assert_eq!(constant.get(), "constant");
+    }
+}
@@ -1,15 +1,18 @@
-use self::object_heap::text::HeapText;
 pub use self::{
+    allocation_sites::AllocationSiteUsage,
+    compaction::{AddressMap, Relocate},
     object::{
         Builtin, Data, DataDiscriminants, Function, Handle, HirId, Int, List, Struct, Tag, Text,
     },
     object_heap::{HeapData, HeapObject, HeapObjectTrait},
     object_inline::{
         int::I64BitLength, InlineData, InlineObject, InlineObjectSliceCloneToHeap,
-        InlineObjectTrait, ToDebugText,
+        InlineObjectTrait, ToCandySource, ToDebugText,
     },
     pointer::Pointer,
+    weak::{Finalizer, WeakReference},
 };
+use self::{allocation_sites::AllocationSites, object_heap::text::HeapText, weak::WeakReferences};
 use crate::handle_id::HandleId;
 use candy_frontend::id::IdGenerator;
 use derive_more::{DebugCustom, Deref, Pointer};
@@ -21,16 +24,38 @@ use std::{
     mem,
 };
 
+mod allocation_sites;
+mod compaction;
 mod object;
 mod object_heap;
 mod object_inline;
 mod pointer;
+mod weak;
 
 pub struct Heap {
     objects: FxHashSet<ObjectInHeap>,
     default_symbols: Option<DefaultSymbols>,
     handle_id_generator: IdGenerator<HandleId>,
     handle_refcounts: FxHashMap<HandleId, usize>,
+    weak_references: WeakReferences,
+    /// While objects are freed or cloned into this heap, nested calls enqueue
+    /// their work here instead of recursing. Otherwise, deeply nested values
+    /// would overflow the stack.
+    objects_to_free: Option<Vec<HeapObject>>,
+    objects_to_clone: Option<Vec<(HeapData, HeapObject)>>,
+    /// While another heap is compacted into this one, its objects. Objects
+    /// outside of it (e.g., constants of the byte code) are referenced as they
+    /// are instead of being copied.
+    objects_being_compacted: Option<FxHashSet<ObjectInHeap>>,
+    /// See [`Heap::limit_objects_freed_at_once`].
+    max_objects_freed_at_once: Option<usize>,
+
+    /// The total size of all objects in this heap.
+    live_bytes: usize,
+    /// The total size of objects freed since the last compaction.
+    freed_bytes: usize,
+    /// See [`Heap::track_allocation_sites`].
+    allocation_sites: Option<AllocationSites>,
 }
 
 impl Heap {
@@ -69,6 +94,10 @@ impl Heap {
             object.set_reference_count(1);
         }
         self.objects.insert(ObjectInHeap(object));
+        self.live_bytes += layout.size();
+        if let Some(allocation_sites) = &mut self.allocation_sites {
+            allocation_sites.notify_allocated(object);
+        }
         object
     }
     /// Don't call this method directly, call [drop] or [free] instead!
@@ -80,7 +109,57 @@ impl Heap {
         )
         .unwrap();
         self.objects.remove(&ObjectInHeap(*object));
+        if let Some(allocation_sites) = &mut self.allocation_sites {
+            allocation_sites.notify_deallocated(*object);
+        }
         unsafe { alloc::Global.deallocate(object.address().cast(), layout) };
+        self.live_bytes = self.live_bytes.saturating_sub(layout.size());
+        self.freed_bytes += layout.size();
+
+        if let Some((reference, finalizers)) = self.weak_references.notify_deallocated(*object) {
+            for finalizer in finalizers {
+                finalizer(reference);
+            }
+        }
+    }
+
+    /// Limits how many objects are freed at once when a value is dropped. The
+    /// rest of a large list or struct stays in the heap with a reference count
+    /// of zero and is freed in later calls to [`Heap::continue_freeing`],
+    /// which the VM does after each instruction.
+    ///
+    /// This keeps the pauses caused by dropping huge values short, but their
+    /// memory becomes available later.
+    pub fn limit_objects_freed_at_once(&mut self, max_objects: usize) {
+        self.max_objects_freed_at_once = Some(max_objects.max(1));
+    }
+    /// Frees some of the objects that are left over from dropping large values
+    /// (see [`Heap::limit_objects_freed_at_once`]).
+    pub fn continue_freeing(&mut self) {
+        if self.objects_to_free.is_some() {
+            self.free_enqueued_objects(self.max_objects_freed_at_once);
+        }
+    }
+    /// Frees all objects that are left over from dropping large values.
+    pub fn finish_freeing(&mut self) {
+        if self.objects_to_free.is_some() {
+            self.free_enqueued_objects(None);
+        }
+    }
+    /// Dropping the children of freed objects can enqueue more objects, which
+    /// are freed in the same loop.
+    pub(self) fn free_enqueued_objects(&mut self, max_objects: Option<usize>) {
+        let mut freed_objects = 0;
+        while max_objects.map_or(true, |max_objects| freed_objects < max_objects) {
+            let Some(object) = self.objects_to_free.as_mut().unwrap().pop() else {
+                self.objects_to_free = None;
+                return;
+            };
+            let data = HeapData::from(object);
+            data.drop_children(self);
+            self.deallocate(data);
+            freed_objects += 1;
+        }
     }
 
     pub(self) fn notify_handle_created(&mut self, handle_id: HandleId) {
@@ -104,11 +183,27 @@ impl Heap {
 
     pub fn adopt(&mut self, mut other: Self) {
         self.objects.extend(mem::take(&mut other.objects));
+        self.live_bytes += mem::take(&mut other.live_bytes);
+        if let Some(other_objects_to_free) = other.objects_to_free.take() {
+            self.objects_to_free
+                .get_or_insert_with(Vec::new)
+                .extend(other_objects_to_free);
+        }
         for (handle_id, refcount) in mem::take(&mut other.handle_refcounts) {
             *self.handle_refcounts.entry(handle_id).or_default() += refcount;
         }
+        if let Some(allocation_sites) = &mut self.allocation_sites
+            && let Some(other_allocation_sites) = other.allocation_sites.take()
+        {
+            allocation_sites.adopt(other_allocation_sites);
+        }
     }
 
+    /// The total size of all objects in this heap, including their headers.
+    #[must_use]
+    pub const fn live_bytes(&self) -> usize {
+        self.live_bytes
+    }
     #[must_use]
     pub const fn objects(&self) -> &FxHashSet<ObjectInHeap> {
         &self.objects
@@ -136,6 +231,14 @@ impl Heap {
             default_symbols: None,
             handle_id_generator: self.handle_id_generator.clone(),
             handle_refcounts: self.handle_refcounts.clone(),
+            weak_references: WeakReferences::default(),
+            objects_to_free: None,
+            objects_to_clone: None,
+            objects_being_compacted: None,
+            max_objects_freed_at_once: self.max_objects_freed_at_once,
+            live_bytes: 0,
+            freed_bytes: 0,
+            allocation_sites: None,
         };
 
         let mut mapping = FxHashMap::default();
@@ -147,6 +250,11 @@ impl Heap {
         );
 
         for object in &self.objects {
+            // Objects waiting to be freed are only referenced by other such
+            // objects.
+            if object.reference_count() == Some(0) {
+                continue;
+            }
             _ = object.clone_to_heap_with_mapping(&mut cloned, &mut mapping);
         }
 
@@ -157,7 +265,9 @@ impl Heap {
         for object in mem::take(&mut self.objects) {
             self.deallocate(HeapData::from(object.0));
         }
+        self.objects_to_free = None;
         self.handle_refcounts.clear();
+        self.freed_bytes = 0;
     }
 }
 
@@ -188,6 +298,14 @@ impl Default for Heap {
             default_symbols: None,
             handle_id_generator: IdGenerator::default(),
             handle_refcounts: FxHashMap::default(),
+            weak_references: WeakReferences::default(),
+            objects_to_free: None,
+            objects_to_clone: None,
+            objects_being_compacted: None,
+            max_objects_freed_at_once: None,
+            live_bytes: 0,
+            freed_bytes: 0,
+            allocation_sites: None,
         };
         heap.default_symbols = Some(DefaultSymbols::new(&mut heap));
         heap
@@ -234,11 +352,13 @@ pub struct DefaultSymbols {
     pub error: Text,
     pub false_: Text,
     pub function: Text,
+    pub get_current_time: Text,
     pub get_random_bytes: Text,
     pub get_next_request: Text,
     pub greater: Text,
     pub http_server: Text,
     pub int: Text,
+    pub item: Text,
     pub less: Text,
     pub list: Text,
     pub not_an_integer: Text,
@@ -246,6 +366,7 @@ pub struct DefaultSymbols {
     pub nothing: Text,
     pub ok: Text,
     pub request: Text,
+    pub rest: Text,
     pub send_response: Text,
     pub stdin: Text,
     pub stdout: Text,
@@ -264,11 +385,13 @@ impl DefaultSymbols {
             error: Text::create(heap, false, "Error"),
             false_: Text::create(heap, false, "False"),
             function: Text::create(heap, false, "Function"),
+            get_current_time: Text::create(heap, false, "GetCurrentTime"),
             get_next_request: Text::create(heap, false, "GetNextRequest"),
             get_random_bytes: Text::create(heap, false, "GetRandomBytes"),
             greater: Text::create(heap, false, "Greater"),
             http_server: Text::create(heap, false, "HttpServer"),
             int: Text::create(heap, false, "Int"),
+            item: Text::create(heap, false, "Item"),
             less: Text::create(heap, false, "Less"),
             list: Text::create(heap, false, "List"),
             not_an_integer: Text::create(heap, false, "NotAnInteger"),
@@ -276,6 +399,7 @@ impl DefaultSymbols {
             nothing: Text::create(heap, false, "Nothing"),
             ok: Text::create(heap, false, "Ok"),
             request: Text::create(heap, false, "Request"),
+            rest: Text::create(heap, false, "Rest"),
             send_response: Text::create(heap, false, "SendResponse"),
             stdin: Text::create(heap, false, "Stdin"),
             stdout: Text::create(heap, false, "Stdout"),
@@ -307,11 +431,13 @@ impl DefaultSymbols {
             error: clone_to_heap(heap, address_map, self.error),
             false_: clone_to_heap(heap, address_map, self.false_),
             function: clone_to_heap(heap, address_map, self.function),
+            get_current_time: clone_to_heap(heap, address_map, self.get_current_time),
             get_next_request: clone_to_heap(heap, address_map, self.get_next_request),
             get_random_bytes: clone_to_heap(heap, address_map, self.get_random_bytes),
             greater: clone_to_heap(heap, address_map, self.greater),
             http_server: clone_to_heap(heap, address_map, self.http_server),
             int: clone_to_heap(heap, address_map, self.int),
+            item: clone_to_heap(heap, address_map, self.item),
             less: clone_to_heap(heap, address_map, self.less),
             list: clone_to_heap(heap, address_map, self.list),
             not_an_integer: clone_to_heap(heap, address_map, self.not_an_integer),
@@ -319,6 +445,7 @@ impl DefaultSymbols {
             nothing: clone_to_heap(heap, address_map, self.nothing),
             ok: clone_to_heap(heap, address_map, self.ok),
             request: clone_to_heap(heap, address_map, self.request),
+            rest: clone_to_heap(heap, address_map, self.rest),
             send_response: clone_to_heap(heap, address_map, self.send_response),
             stdin: clone_to_heap(heap, address_map, self.stdin),
             stdout: clone_to_heap(heap, address_map, self.stdout),
@@ -338,7 +465,7 @@ impl DefaultSymbols {
             .map(|it| symbols[it])
     }
     #[must_use]
-    pub const fn all_symbols(&self) -> [Text; 26] {
+    pub const fn all_symbols(&self) -> [Text; 29] {
         [
             self.arguments,
             self.builtin,
@@ -347,11 +474,13 @@ impl DefaultSymbols {
             self.error,
             self.false_,
             self.function,
+            self.get_current_time,
             self.get_next_request,
             self.get_random_bytes,
             self.greater,
             self.http_server,
             self.int,
+            self.item,
             self.less,
             self.list,
             self.not_an_integer,
@@ -359,6 +488,7 @@ impl DefaultSymbols {
             self.nothing,
             self.ok,
             self.request,
+            self.rest,
             self.send_response,
             self.stdin,
             self.stdout,
@@ -369,3 +499,14 @@ impl DefaultSymbols {
         ]
     }
 }
+
+/// Whether `value` still fits into a 64-bit word after shifting it left by
+/// `shift` bits.
+///
+/// Header words are always 64 bits wide, so we calculate with `u64` instead of
+/// `usize`: On 32-bit targets like wasm32, shifting a `usize` by 32 or more
+/// bits would overflow.
+const fn fits_in_word_after_shift(value: usize, shift: usize) -> bool {
+    let value = value as u64;
+    (value << shift) >> shift == value
+}
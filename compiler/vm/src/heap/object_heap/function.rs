@@ -1,6 +1,6 @@
 use super::{utils::heap_object_impls, HeapObjectTrait};
 use crate::{
-    heap::{object_heap::HeapObject, Heap, InlineObject},
+    heap::{fits_in_word_after_shift, object_heap::HeapObject, Heap, InlineObject},
     instruction_pointer::InstructionPointer,
     utils::{impl_debug_display_via_debugdisplay, DebugDisplay},
 };
@@ -33,18 +33,15 @@ impl HeapFunction {
         body: InstructionPointer,
     ) -> Self {
         let captured_len = captured.len();
-        assert_eq!(
-            (captured_len << Self::CAPTURED_LEN_SHIFT) >> Self::CAPTURED_LEN_SHIFT,
-            captured_len,
+        assert!(
+            fits_in_word_after_shift(captured_len, Self::CAPTURED_LEN_SHIFT),
             "Function captures too many things.",
         );
 
         let argument_count_shift_for_max_size =
             Self::CAPTURED_LEN_SHIFT + Self::ARGUMENT_COUNT_SHIFT;
-        assert_eq!(
-            (argument_count << argument_count_shift_for_max_size)
-                >> argument_count_shift_for_max_size,
-            argument_count,
+        assert!(
+            fits_in_word_after_shift(argument_count, argument_count_shift_for_max_size),
             "Function accepts too many arguments.",
         );
 
@@ -1,6 +1,6 @@
 use super::{utils::heap_object_impls, HeapObjectTrait};
 use crate::{
-    heap::{object_heap::HeapObject, Heap, InlineObject},
+    heap::{fits_in_word_after_shift, object_heap::HeapObject, Heap, InlineObject},
     utils::{impl_debug_display_via_debugdisplay, DebugDisplay},
 };
 use derive_more::Deref;
@@ -30,9 +30,8 @@ impl HeapList {
         list
     }
     fn create_uninitialized(heap: &mut Heap, is_reference_counted: bool, len: usize) -> Self {
-        assert_eq!(
-            (len << Self::LEN_SHIFT) >> Self::LEN_SHIFT,
-            len,
+        assert!(
+            fits_in_word_after_shift(len, Self::LEN_SHIFT),
             "List is too long.",
         );
         Self(heap.allocate(
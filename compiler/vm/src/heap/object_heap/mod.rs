@@ -2,7 +2,10 @@ use self::{
     function::HeapFunction, hir_id::HeapHirId, int::HeapInt, list::HeapList, struct_::HeapStruct,
     tag::HeapTag, text::HeapText,
 };
-use super::{Data, Heap};
+use super::{
+    object_inline::{InlineData, InlineObject},
+    Data, Heap, ObjectInHeap,
+};
 use crate::utils::{impl_debug_display_via_debugdisplay, DebugDisplay};
 use enum_dispatch::enum_dispatch;
 use rustc_hash::FxHashMap;
@@ -11,6 +14,7 @@ use std::{
     collections::hash_map,
     fmt::{self, Formatter, Pointer},
     hash::{Hash, Hasher},
+    mem,
     num::NonZeroUsize,
     ops::{Deref, Range},
     ptr::NonNull,
@@ -142,9 +146,15 @@ impl HeapObject {
     pub(super) fn free(self, heap: &mut Heap) {
         trace!("Freeing object at {self:p}.");
         assert_eq!(self.reference_count().unwrap_or_default(), 0);
-        let data = HeapData::from(self);
-        data.drop_children(heap);
-        heap.deallocate(data);
+        if let Some(objects_to_free) = &mut heap.objects_to_free {
+            // We're called while dropping the children of another object or
+            // while a large value is freed incrementally.
+            objects_to_free.push(self);
+            return;
+        }
+
+        heap.objects_to_free = Some(vec![self]);
+        heap.free_enqueued_objects(heap.max_objects_freed_at_once);
     }
 
     // Cloning
@@ -158,6 +168,12 @@ impl HeapObject {
         heap: &mut Heap,
         address_map: &mut FxHashMap<Self, Self>,
     ) -> Self {
+        if let Some(objects_being_compacted) = &heap.objects_being_compacted
+            && !objects_being_compacted.contains(&ObjectInHeap(self))
+        {
+            return self;
+        }
+
         match address_map.entry(self) {
             hash_map::Entry::Occupied(entry) => {
                 let object = entry.get();
@@ -170,7 +186,21 @@ impl HeapObject {
                 let data = HeapData::from(self);
                 let new_object = heap.allocate_raw(self.header_word(), data.content_size());
                 entry.insert(new_object);
-                data.clone_content_to_heap_with_mapping(heap, new_object, address_map);
+                if let Some(objects_to_clone) = &mut heap.objects_to_clone {
+                    // We're called while cloning the content of another object.
+                    // The mapping already contains this object, so it's enough
+                    // to fill in the content later.
+                    objects_to_clone.push((data, new_object));
+                    return new_object;
+                }
+
+                heap.objects_to_clone = Some(vec![]);
+                let mut next = Some((data, new_object));
+                while let Some((data, clone)) = next {
+                    data.clone_content_to_heap_with_mapping(heap, clone, address_map);
+                    next = heap.objects_to_clone.as_mut().unwrap().pop();
+                }
+                heap.objects_to_clone = None;
                 new_object
             }
         }
@@ -208,15 +238,47 @@ impl Pointer for HeapObject {
     }
 }
 
+// Values can be nested arbitrarily deep, so comparing and hashing them uses
+// explicit worklists instead of recursing into children.
 impl Eq for HeapObject {}
 impl PartialEq for HeapObject {
     fn eq(&self, other: &Self) -> bool {
-        self.pointer_equals(*other) || HeapData::from(*self) == HeapData::from(*other)
+        let mut pairs = vec![(*self, *other)];
+        while let Some((a, b)) = pairs.pop() {
+            if a.pointer_equals(b) {
+                continue;
+            }
+
+            let (a, b) = (HeapData::from(a), HeapData::from(b));
+            if !a.shallow_equals(b) {
+                return false;
+            }
+            for (a, b) in a.children().zip(b.children()) {
+                match (InlineData::from(a), InlineData::from(b)) {
+                    (InlineData::Pointer(a), InlineData::Pointer(b)) => {
+                        pairs.push((a.get(), b.get()));
+                    }
+                    (a, b) if a != b => return false,
+                    _ => {}
+                }
+            }
+        }
+        true
     }
 }
 impl Hash for HeapObject {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        HeapData::from(*self).hash(state);
+        let mut objects = vec![*self];
+        while let Some(object) = objects.pop() {
+            let data = HeapData::from(object);
+            data.shallow_hash(state);
+            for child in data.children() {
+                match InlineData::from(child) {
+                    InlineData::Pointer(child) => objects.push(child.get()),
+                    child => child.hash(state),
+                }
+            }
+        }
     }
 }
 impl Ord for HeapObject {
@@ -273,6 +335,57 @@ pub enum HeapData {
     HirId(HeapHirId),
 }
 
+impl HeapData {
+    /// Inline objects directly referenced by this object.
+    ///
+    /// Two objects are equal iff they are [shallowly equal](Self::shallow_equals)
+    /// and their children are equal.
+    fn children<'a>(self) -> impl Iterator<Item = InlineObject> + 'a {
+        let (first, second, value): (&[InlineObject], &[InlineObject], _) = match self {
+            Self::List(list) => (list.items(), &[], None),
+            Self::Struct(struct_) => (struct_.keys(), struct_.values(), None),
+            Self::Tag(tag) => (&[], &[], Some(tag.value())),
+            Self::Function(function) => (function.captured(), &[], None),
+            Self::Int(_) | Self::Text(_) | Self::HirId(_) => (&[], &[], None),
+        };
+        first.iter().chain(second).copied().chain(value)
+    }
+    /// Compares everything except for the [children](Self::children). If this
+    /// returns `true`, both objects have the same number of children.
+    fn shallow_equals(self, other: Self) -> bool {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::List(a), Self::List(b)) => a.len() == b.len(),
+            (Self::Struct(a), Self::Struct(b)) => a.hashes() == b.hashes(),
+            (Self::Text(a), Self::Text(b)) => a == b,
+            (Self::Tag(a), Self::Tag(b)) => a.symbol().get() == b.symbol().get(),
+            (Self::Function(a), Self::Function(b)) => {
+                a.captured_len() == b.captured_len()
+                    && a.argument_count() == b.argument_count()
+                    && a.body() == b.body()
+            }
+            (Self::HirId(a), Self::HirId(b)) => a == b,
+            _ => false,
+        }
+    }
+    fn shallow_hash<H: Hasher>(self, state: &mut H) {
+        mem::discriminant(&self).hash(state);
+        match self {
+            Self::Int(int) => int.hash(state),
+            Self::List(list) => list.len().hash(state),
+            Self::Struct(struct_) => struct_.hashes().hash(state),
+            Self::Text(text) => text.hash(state),
+            Self::Tag(tag) => tag.symbol().get().hash(state),
+            Self::Function(function) => {
+                function.captured_len().hash(state);
+                function.argument_count().hash(state);
+                function.body().hash(state);
+            }
+            Self::HirId(hir_id) => hir_id.hash(state),
+        }
+    }
+}
+
 impl DebugDisplay for HeapData {
     fn fmt(&self, f: &mut Formatter, is_debug: bool) -> fmt::Result {
         match self {
@@ -336,3 +449,111 @@ impl From<HeapData> for HeapObject {
         *value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        capabilities::{RandomSource, SeededRandomSource},
+        heap::{Heap, InlineObject, Int, List, Struct, Tag, Text},
+    };
+    use rustc_hash::{FxHashMap, FxHasher};
+    use std::{
+        hash::{Hash, Hasher},
+        panic, thread,
+    };
+
+    /// Deep enough to overflow the stack of [`with_small_stack`] when recursing
+    /// into children.
+    const DEPTH: usize = 5_000;
+
+    /// Runs the test on a thread with a small stack so that a moderate nesting
+    /// depth suffices to catch recursion. Deeper values take long to clone
+    /// because cloning looks up every nested object by its deep hash.
+    fn with_small_stack(test: impl FnOnce() + Send + 'static) {
+        let result = thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(test)
+            .unwrap()
+            .join();
+        if let Err(panic) = result {
+            panic::resume_unwind(panic);
+        }
+    }
+
+    /// Randomly nests lists, structs, and tags around the given innermost
+    /// value.
+    fn nest(heap: &mut Heap, seed: u64, innermost: i64) -> InlineObject {
+        let mut kinds = vec![0; DEPTH];
+        SeededRandomSource::new(seed)
+            .fill_bytes(&mut kinds)
+            .unwrap();
+
+        // Default symbols aren't reference-counted, so they would stay in the
+        // heap that we clone into.
+        let symbol = Text::create(heap, true, "Foo");
+        let mut value: InlineObject = Int::create(heap, true, innermost).into();
+        for kind in kinds {
+            value = match kind % 3 {
+                0 => List::create(heap, true, &[value]).into(),
+                1 => {
+                    symbol.dup();
+                    let key = Tag::create(symbol).into();
+                    Struct::create(heap, true, &FxHashMap::from_iter([(key, value)])).into()
+                }
+                _ => {
+                    symbol.dup();
+                    Tag::create_with_value(heap, true, symbol, value).into()
+                }
+            };
+        }
+        symbol.drop(heap);
+        value
+    }
+    fn hash(value: InlineObject) -> u64 {
+        let mut hasher = FxHasher::default();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn deeply_nested_values_dont_overflow_the_stack() {
+        with_small_stack(|| {
+            let mut heap = Heap::default();
+            let empty_heap_objects = heap.objects().len();
+            let a = nest(&mut heap, 42, 1);
+            let b = nest(&mut heap, 42, 1);
+            let c = nest(&mut heap, 42, 2);
+            assert!(a == b);
+            assert!(a != c);
+            assert_eq!(hash(a), hash(b));
+
+            let mut other_heap = Heap::default();
+            let clone = a.clone_to_heap(&mut other_heap);
+            assert!(clone == a);
+            assert_eq!(hash(clone), hash(a));
+
+            for value in [a, b, c] {
+                value.drop(&mut heap);
+            }
+            assert_eq!(heap.objects().len(), empty_heap_objects);
+            clone.drop(&mut other_heap);
+            assert_eq!(other_heap.objects().len(), empty_heap_objects);
+        });
+    }
+
+    #[test]
+    fn large_values_are_freed_incrementally() {
+        let mut heap = Heap::default();
+        heap.limit_objects_freed_at_once(1000);
+        let empty_heap_objects = heap.objects().len();
+        let value = nest(&mut heap, 42, 1);
+
+        value.drop(&mut heap);
+        assert!(heap.objects().len() > empty_heap_objects + DEPTH - 1000);
+        heap.continue_freeing();
+        assert!(heap.objects().len() > empty_heap_objects + DEPTH - 2000);
+
+        heap.finish_freeing();
+        assert_eq!(heap.objects().len(), empty_heap_objects);
+    }
+}
@@ -1,6 +1,6 @@
 use super::{utils::heap_object_impls, HeapObjectTrait};
 use crate::{
-    heap::{object_heap::HeapObject, Heap, InlineObject},
+    heap::{fits_in_word_after_shift, object_heap::HeapObject, Heap, InlineObject},
     utils::{impl_debug_display_via_debugdisplay, DebugDisplay},
 };
 use candy_frontend::utils::DoHash;
@@ -29,9 +29,8 @@ impl HeapStruct {
         value: &FxHashMap<InlineObject, InlineObject>,
     ) -> Self {
         let len = value.len();
-        assert_eq!(
-            (len << Self::LEN_SHIFT) >> Self::LEN_SHIFT,
-            len,
+        assert!(
+            fits_in_word_after_shift(len, Self::LEN_SHIFT),
             "Struct is too long.",
         );
         let entries = value
@@ -53,9 +52,8 @@ impl HeapStruct {
         struct_
     }
     fn create_uninitialized(heap: &mut Heap, is_reference_counted: bool, len: usize) -> Self {
-        assert_eq!(
-            (len << Self::LEN_SHIFT) >> Self::LEN_SHIFT,
-            len,
+        assert!(
+            fits_in_word_after_shift(len, Self::LEN_SHIFT),
             "Struct is too long.",
         );
         Self(heap.allocate(
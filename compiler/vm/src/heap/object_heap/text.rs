@@ -1,6 +1,6 @@
 use super::{utils::heap_object_impls, HeapObjectTrait};
 use crate::{
-    heap::{object_heap::HeapObject, Heap, Int, List, Tag, Text},
+    heap::{fits_in_word_after_shift, object_heap::HeapObject, Heap, Int, List, Tag, Text},
     utils::{impl_debug_display_via_debugdisplay, impl_eq_hash_ord_via_get, DebugDisplay},
 };
 use derive_more::Deref;
@@ -8,26 +8,36 @@ use itertools::Itertools;
 use rustc_hash::FxHashMap;
 use std::{
     fmt::{self, Formatter},
+    iter,
     ops::Range,
     ptr::{self, NonNull},
     slice, str,
 };
 use unicode_segmentation::UnicodeSegmentation;
 
+/// Texts either store their bytes directly or are slices that share the bytes
+/// of another text.
+///
+/// Slices keep the text they're part of alive, so short slices are copied
+/// instead: Allocating them is as cheap as copying a few bytes, and we don't
+/// want a single character to keep a large file in memory.
 #[derive(Clone, Copy, Deref)]
 pub struct HeapText(HeapObject);
 
 impl HeapText {
-    const BYTE_LEN_SHIFT: usize = 4;
+    const IS_SLICE_SHIFT: usize = 4;
+    const IS_SLICE_MASK: u64 = 0b1 << Self::IS_SLICE_SHIFT;
+    const BYTE_LEN_SHIFT: usize = 5;
+
+    const MIN_SLICE_BYTE_LEN: usize = 64;
 
     pub const fn new_unchecked(object: HeapObject) -> Self {
         Self(object)
     }
     pub fn create(heap: &mut Heap, is_reference_counted: bool, value: &str) -> Self {
         let byte_len = value.len();
-        assert_eq!(
-            (byte_len << Self::BYTE_LEN_SHIFT) >> Self::BYTE_LEN_SHIFT,
-            byte_len,
+        assert!(
+            fits_in_word_after_shift(byte_len, Self::BYTE_LEN_SHIFT),
             "Text is too long.",
         );
         let text = Self(heap.allocate(
@@ -40,15 +50,81 @@ impl HeapText {
         text
     }
 
+    /// Returns a text containing the given bytes of this text, sharing them if
+    /// the range is long enough.
+    fn slice(self, heap: &mut Heap, byte_range: Range<usize>) -> Text {
+        debug_assert!(self.get().is_char_boundary(byte_range.start));
+        debug_assert!(self.get().is_char_boundary(byte_range.end));
+
+        let byte_len = byte_range.len();
+        if byte_len == self.byte_len() {
+            self.dup();
+            return self.into();
+        }
+        if byte_len < Self::MIN_SLICE_BYTE_LEN {
+            return Text::create(heap, true, &self.get()[byte_range]);
+        }
+
+        let (text, offset) = self.flat();
+        let slice = Self(heap.allocate(
+            HeapObject::KIND_TEXT,
+            true,
+            Self::IS_SLICE_MASK | ((byte_len as u64) << Self::BYTE_LEN_SHIFT),
+            2 * HeapObject::WORD_SIZE,
+        ));
+        text.dup();
+        slice.set_sliced(text, offset + byte_range.start);
+        slice.into()
+    }
+
     pub fn byte_len(self) -> usize {
         (self.header_word() >> Self::BYTE_LEN_SHIFT) as usize
     }
+    fn is_slice(self) -> bool {
+        self.header_word() & Self::IS_SLICE_MASK != 0
+    }
+    /// For slices, the text they're part of and their byte offset in it.
+    fn sliced(self) -> Option<(Self, usize)> {
+        if !self.is_slice() {
+            return None;
+        }
+
+        let address = self.unsafe_get_content_word(0);
+        let text = Self(HeapObject::new(unsafe {
+            NonNull::new_unchecked(address as *mut u64)
+        }));
+        #[allow(clippy::cast_possible_truncation)]
+        let offset = self.unsafe_get_content_word(1) as usize;
+        Some((text, offset))
+    }
+    fn set_sliced(self, text: Self, offset: usize) {
+        debug_assert!(self.is_slice());
+        self.unsafe_set_content_word(0, text.address().addr().get() as u64);
+        self.unsafe_set_content_word(1, offset as u64);
+    }
+    /// The text that stores the bytes of this text and their offset in it.
+    ///
+    /// Slices usually reference such a text directly, but cloning merges equal
+    /// texts and can therefore turn the referenced text into a slice.
+    fn flat(self) -> (Self, usize) {
+        let mut text = self;
+        let mut offset = 0;
+        while let Some((sliced, sliced_offset)) = text.sliced() {
+            text = sliced;
+            offset += sliced_offset;
+        }
+        (text, offset)
+    }
     fn text_pointer(self) -> NonNull<u8> {
+        debug_assert!(!self.is_slice());
         self.content_word_pointer(0).cast()
     }
     pub fn get<'a>(self) -> &'a str {
-        let pointer = self.text_pointer().as_ptr();
-        unsafe { str::from_utf8_unchecked(slice::from_raw_parts(pointer, self.byte_len())) }
+        let (text, offset) = self.flat();
+        unsafe {
+            let pointer = text.text_pointer().as_ptr().add(offset);
+            str::from_utf8_unchecked(slice::from_raw_parts(pointer, self.byte_len()))
+        }
     }
 
     pub fn is_empty(self, heap: &Heap) -> Tag {
@@ -84,23 +160,37 @@ impl HeapText {
             .end
             .try_get::<usize>()
             .expect("Tried to get a range from a text with an index that's too large for usize.");
-        let text: String = self
+        let byte_len = self.byte_len();
+        let mut grapheme_boundaries = self
             .get()
-            .graphemes(true)
-            .skip(start_inclusive)
-            .take(end_exclusive - start_inclusive)
-            .collect();
-        Text::create(heap, true, &text)
+            .grapheme_indices(true)
+            .map(|(index, _)| index)
+            .chain(iter::once(byte_len));
+        let start = grapheme_boundaries.nth(start_inclusive).unwrap_or(byte_len);
+        let end = match end_exclusive - start_inclusive {
+            0 => start,
+            len => grapheme_boundaries.nth(len - 1).unwrap_or(byte_len),
+        };
+        self.slice(heap, start..end)
     }
 
     pub fn concatenate(self, heap: &mut Heap, other: Text) -> Text {
+        // Joining adjacent slices of the same text, e.g., after splitting it,
+        // doesn't need to copy anything.
+        let (text, offset) = self.flat();
+        let (other_text, other_offset) = other.flat();
+        if text.pointer_equals(*other_text) && offset + self.byte_len() == other_offset {
+            return text.slice(heap, offset..other_offset + other.byte_len());
+        }
+
         Text::create(heap, true, &format!("{}{}", self.get(), other.get()))
     }
     pub fn trim_start(self, heap: &mut Heap) -> Text {
-        Text::create(heap, true, self.get().trim_start())
+        let start = self.byte_len() - self.get().trim_start().len();
+        self.slice(heap, start..self.byte_len())
     }
     pub fn trim_end(self, heap: &mut Heap) -> Text {
-        Text::create(heap, true, self.get().trim_end())
+        self.slice(heap, 0..self.get().trim_end().len())
     }
 }
 
@@ -117,16 +207,26 @@ heap_object_impls!(HeapText);
 
 impl HeapObjectTrait for HeapText {
     fn content_size(self) -> usize {
-        self.byte_len()
+        if self.is_slice() {
+            2 * HeapObject::WORD_SIZE
+        } else {
+            self.byte_len()
+        }
     }
 
     fn clone_content_to_heap_with_mapping(
         self,
-        _heap: &mut Heap,
+        heap: &mut Heap,
         clone: HeapObject,
-        _address_map: &mut FxHashMap<HeapObject, HeapObject>,
+        address_map: &mut FxHashMap<HeapObject, HeapObject>,
     ) {
         let clone = Self(clone);
+        if let Some((sliced, offset)) = self.sliced() {
+            let sliced = sliced.clone_to_heap_with_mapping(heap, address_map);
+            clone.set_sliced(Self(sliced), offset);
+            return;
+        }
+
         unsafe {
             ptr::copy_nonoverlapping(
                 self.text_pointer().as_ptr(),
@@ -136,7 +236,126 @@ impl HeapObjectTrait for HeapText {
         };
     }
 
-    fn drop_children(self, _heap: &mut Heap) {}
+    fn drop_children(self, heap: &mut Heap) {
+        if let Some((sliced, _)) = self.sliced() {
+            sliced.drop(heap);
+        }
+    }
 
     fn deallocate_external_stuff(self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::heap::{Heap, Int, ObjectInHeap, Relocate, Text};
+
+    fn long_text(heap: &mut Heap) -> Text {
+        Text::create(
+            heap,
+            true,
+            &format!("{}{}", "a".repeat(100), "b".repeat(100)),
+        )
+    }
+    fn get_range(heap: &mut Heap, text: Text, start: usize, end: usize) -> Text {
+        let start = Int::create(heap, true, start);
+        let end = Int::create(heap, true, end);
+        text.get_range(heap, start..end)
+    }
+
+    #[test]
+    fn long_slices_share_the_text() {
+        let mut heap = Heap::default();
+        let text = long_text(&mut heap);
+
+        let slice = get_range(&mut heap, text, 50, 150);
+        assert!(slice.is_slice());
+        assert_eq!(slice.get(), format!("{}{}", "a".repeat(50), "b".repeat(50)));
+        let copy = Text::create(&mut heap, true, slice.get());
+        assert_eq!(slice, copy);
+        assert_eq!(text.reference_count(), Some(2));
+
+        let short_slice = get_range(&mut heap, text, 95, 105);
+        assert!(!short_slice.is_slice());
+        assert_eq!(short_slice.get(), "aaaaabbbbb");
+    }
+
+    #[test]
+    fn slices_keep_the_text_alive() {
+        let mut heap = Heap::default();
+        let text = long_text(&mut heap);
+        let slice = get_range(&mut heap, text, 0, 150);
+        let nested_slice = get_range(&mut heap, slice, 0, 100);
+        assert!(nested_slice
+            .sliced()
+            .is_some_and(|(it, _)| it.pointer_equals(**text)));
+
+        text.drop(&mut heap);
+        slice.drop(&mut heap);
+        assert!(heap.objects().contains(&ObjectInHeap(**text)));
+        assert_eq!(nested_slice.get(), "a".repeat(100));
+        nested_slice.drop(&mut heap);
+        assert!(!heap.objects().contains(&ObjectInHeap(**text)));
+    }
+
+    #[test]
+    fn slices_of_slices_reference_the_original_text() {
+        let mut heap = Heap::default();
+        let text = long_text(&mut heap);
+        let slice = get_range(&mut heap, text, 50, 200);
+        let nested_slice = get_range(&mut heap, slice, 20, 120);
+
+        let (sliced, offset) = nested_slice.sliced().unwrap();
+        assert!(sliced.pointer_equals(**text));
+        assert_eq!(offset, 70);
+        assert_eq!(
+            nested_slice.get(),
+            format!("{}{}", "a".repeat(30), "b".repeat(70)),
+        );
+        assert_eq!(slice.reference_count(), Some(1));
+        assert_eq!(text.reference_count(), Some(3));
+    }
+
+    #[test]
+    fn freeing_the_text_of_a_live_slice_waits_for_the_slice() {
+        let mut heap = Heap::default();
+        heap.limit_objects_freed_at_once(1);
+        let text = long_text(&mut heap);
+        let slice = get_range(&mut heap, text, 100, 200);
+
+        text.drop(&mut heap);
+        assert_eq!(text.reference_count(), Some(1));
+        assert_eq!(slice.get(), "b".repeat(100));
+
+        slice.drop(&mut heap);
+        heap.finish_freeing();
+        assert!(!heap.objects().contains(&ObjectInHeap(**slice)));
+        assert!(!heap.objects().contains(&ObjectInHeap(**text)));
+    }
+
+    #[test]
+    fn concatenating_adjacent_slices_reuses_the_text() {
+        let mut heap = Heap::default();
+        let text = long_text(&mut heap);
+        let start = get_range(&mut heap, text, 0, 120);
+        let end = get_range(&mut heap, text, 120, 200);
+
+        let concatenated = start.concatenate(&mut heap, end);
+        assert!(concatenated.pointer_equals(**text));
+        assert_eq!(text.reference_count(), Some(4));
+    }
+
+    #[test]
+    fn compaction_keeps_slices() {
+        let mut heap = Heap::default();
+        let text = long_text(&mut heap);
+        let mut slice = get_range(&mut heap, text, 100, 200);
+        text.drop(&mut heap);
+
+        let address_map = heap.compact();
+        slice.relocate(&address_map);
+        assert_eq!(slice.get(), "b".repeat(100));
+        let num_objects = heap.objects().len();
+        slice.drop(&mut heap);
+        assert_eq!(heap.objects().len(), num_objects - 2);
+    }
+}
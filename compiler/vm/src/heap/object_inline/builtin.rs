@@ -1,6 +1,6 @@
 use super::InlineObjectTrait;
 use crate::{
-    heap::{object_heap::HeapObject, Heap, InlineObject},
+    heap::{fits_in_word_after_shift, object_heap::HeapObject, Heap, InlineObject},
     utils::{impl_debug_display_via_debugdisplay, DebugDisplay},
 };
 use candy_frontend::builtin_functions::{self, BuiltinFunction};
@@ -68,9 +68,8 @@ impl From<BuiltinFunction> for InlineObject {
 impl From<BuiltinFunction> for InlineBuiltin {
     fn from(builtin_function: BuiltinFunction) -> Self {
         let index = builtin_function as usize;
-        debug_assert_eq!(
-            (index << Self::INDEX_SHIFT) >> Self::INDEX_SHIFT,
-            index,
+        debug_assert!(
+            fits_in_word_after_shift(index, Self::INDEX_SHIFT),
             "Builtin function index is too large.",
         );
         let header_word = InlineObject::KIND_BUILTIN | ((index as u64) << Self::INDEX_SHIFT);
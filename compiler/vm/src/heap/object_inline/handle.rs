@@ -1,7 +1,7 @@
 use super::{InlineObject, InlineObjectTrait};
 use crate::{
     handle_id::HandleId,
-    heap::{object_heap::HeapObject, Heap},
+    heap::{fits_in_word_after_shift, object_heap::HeapObject, Heap},
     utils::{impl_debug_display_via_debugdisplay, DebugDisplay},
 };
 use candy_frontend::id::CountableId;
@@ -28,16 +28,13 @@ impl InlineHandle {
     pub fn create(heap: &mut Heap, handle_id: HandleId, argument_count: usize) -> Self {
         heap.notify_handle_created(handle_id);
         let handle_id = handle_id.to_usize();
-        debug_assert_eq!(
-            (handle_id << Self::HANDLE_ID_SHIFT) >> Self::HANDLE_ID_SHIFT,
-            handle_id,
+        debug_assert!(
+            fits_in_word_after_shift(handle_id, Self::HANDLE_ID_SHIFT),
             "Handle ID is too large.",
         );
         let argument_count_shift_for_max_size = Self::HANDLE_ID_SHIFT + Self::ARGUMENT_COUNT_SHIFT;
-        debug_assert_eq!(
-            (argument_count << argument_count_shift_for_max_size)
-                >> argument_count_shift_for_max_size,
-            argument_count,
+        debug_assert!(
+            fits_in_word_after_shift(argument_count, argument_count_shift_for_max_size),
             "Handle accepts too many arguments.",
         );
 
@@ -7,10 +7,13 @@ use crate::{
     handle_id::HandleId,
     utils::{impl_debug_display_via_debugdisplay, DebugDisplay},
 };
-use candy_frontend::format::{format_value, FormatValue, MaxLength, Precedence};
+use candy_frontend::format::{
+    format_value_with_limits, FormatValue, MaxLength, NestingLimits, Precedence,
+};
 use enum_dispatch::enum_dispatch;
 use extension_trait::extension_trait;
 use itertools::Itertools;
+use num_bigint::Sign;
 use rustc_hash::FxHashMap;
 use std::{
     borrow::Cow,
@@ -43,9 +46,18 @@ pub impl InlineObjectSliceCloneToHeap for [InlineObject] {
     }
 }
 
+/// A single 64-bit word, independent of the target's pointer width. The lowest
+/// [`InlineObject::KIND_WIDTH`] bits store the kind of the object.
+///
+/// Pointers to heap objects are stored as is: Heap objects are aligned to
+/// [`HeapObject::WORD_SIZE`] bytes (even on 32-bit targets like wasm32), so the
+/// kind bits of their addresses are always zero.
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct InlineObject(NonZeroU64);
+const _: () = assert!(HeapObject::WORD_SIZE.trailing_zeros() as usize >= InlineObject::KIND_WIDTH);
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(usize::BITS <= InlineObject::BITS);
 
 impl InlineObject {
     pub const BITS: u32 = NonZeroU64::BITS;
@@ -222,7 +234,15 @@ impl Deref for InlineData {
 #[extension_trait]
 pub impl ToDebugText for InlineObject {
     fn to_debug_text(self, precendence: Precedence, max_length: MaxLength) -> String {
-        format_value(self, precendence, max_length, &|value| {
+        self.to_debug_text_with_limits(precendence, max_length, NestingLimits::UNLIMITED)
+    }
+    fn to_debug_text_with_limits(
+        self,
+        precendence: Precedence,
+        max_length: MaxLength,
+        limits: NestingLimits,
+    ) -> String {
+        format_value_with_limits(self, precendence, max_length, limits, &|value| {
             Some(match value.into() {
                 Data::Int(int) => FormatValue::Int(int.get()),
                 Data::Tag(tag) => FormatValue::Tag {
@@ -244,3 +264,357 @@ pub impl ToDebugText for InlineObject {
         .unwrap()
     }
 }
+
+/// Formats values as Candy source code that evaluates to an equal value.
+///
+/// Negative ints are written as `(int.negate …)`, so the generated code needs
+/// `int` from Core to be in scope if [`ToCandySource::candy_source_uses_int`]
+/// returns `true`.
+#[extension_trait]
+pub impl ToCandySource for InlineObject {
+    /// Returns `None` if this value or a nested value has no literal syntax.
+    ///
+    /// This is the case for functions, builtins, handles, and texts that start
+    /// with whitespace or contain line breaks.
+    fn to_candy_source(self, precedence: Precedence) -> Option<String> {
+        candy_source(self, precedence, false)
+    }
+    /// Like [`ToCandySource::to_candy_source`], but writes values without a
+    /// literal syntax as close as possible. Functions, builtins, and handles
+    /// become `{ … }`.
+    fn to_candy_source_lossy(self, precedence: Precedence) -> String {
+        candy_source(self, precedence, true).unwrap()
+    }
+    /// Whether this value or a nested value is a negative int.
+    fn candy_source_uses_int(self) -> bool {
+        match self.into() {
+            Data::Int(int) => int.get().sign() == Sign::Minus,
+            Data::Tag(tag) => tag.value().is_some_and(Self::candy_source_uses_int),
+            Data::List(list) => list.items().iter().any(|it| it.candy_source_uses_int()),
+            Data::Struct(struct_) => struct_.iter().any(|(_, key, value)| {
+                key.candy_source_uses_int() || value.candy_source_uses_int()
+            }),
+            Data::Text(_)
+            | Data::HirId(_)
+            | Data::Function(_)
+            | Data::Builtin(_)
+            | Data::Handle(_) => false,
+        }
+    }
+}
+
+fn candy_source(value: InlineObject, precedence: Precedence, lossy: bool) -> Option<String> {
+    let with_parentheses_if_high = |source: String| match precedence {
+        Precedence::Low => source,
+        Precedence::High => format!("({source})"),
+    };
+
+    Some(match value.into() {
+        Data::Int(int) => {
+            let int = int.get();
+            if int.sign() == Sign::Minus {
+                with_parentheses_if_high(format!("int.negate {}", -int.as_ref()))
+            } else {
+                int.to_string()
+            }
+        }
+        Data::Tag(tag) => {
+            let symbol = tag.symbol().get().to_string();
+            match tag.value() {
+                Some(value) => with_parentheses_if_high(format!(
+                    "{symbol} {}",
+                    candy_source(value, Precedence::High, lossy)?,
+                )),
+                None => symbol,
+            }
+        }
+        Data::Text(text) => text_literal(text.get(), lossy)?,
+        Data::List(list) => match list.items() {
+            [] => "(,)".to_string(),
+            [item] => format!("({},)", candy_source(*item, Precedence::Low, lossy)?),
+            items => format!(
+                "({})",
+                items
+                    .iter()
+                    .map(|item| candy_source(*item, Precedence::Low, lossy))
+                    .collect::<Option<Vec<_>>>()?
+                    .join(", "),
+            ),
+        },
+        Data::Struct(struct_) => {
+            // Structs are unordered, so we sort the entries to get a
+            // deterministic output.
+            let entries = struct_
+                .iter()
+                .map(|(_, key, value)| {
+                    Some((
+                        candy_source(key, Precedence::Low, lossy)?,
+                        candy_source(value, Precedence::Low, lossy)?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            format!(
+                "[{}]",
+                entries
+                    .into_iter()
+                    .sorted()
+                    .map(|(key, value)| format!("{key}: {value}"))
+                    .join(", "),
+            )
+        }
+        Data::HirId(_) => unreachable!(),
+        Data::Function(_) | Data::Builtin(_) | Data::Handle(_) => {
+            if !lossy {
+                return None;
+            }
+            "{ … }".to_string()
+        }
+    })
+}
+
+fn text_literal(text: &str, lossy: bool) -> Option<String> {
+    // The parser treats whitespace after the opening quote as trivia and
+    // turns line breaks into separate text parts, so we can't reproduce those.
+    let is_representable = !text.starts_with(char::is_whitespace) && !text.contains(['\n', '\r']);
+    if !is_representable && !lossy {
+        return None;
+    }
+
+    // A text literal opened with n single quotes only ends at a double quote
+    // followed by at least n single quotes, and only n + 1 opening curly
+    // braces start an interpolation.
+    let quotes_to_end_text = text
+        .match_indices('"')
+        .map(|(index, _)| {
+            text[index + 1..]
+                .chars()
+                .take_while(|it| *it == '\'')
+                .count()
+                + 1
+        })
+        .max()
+        .unwrap_or_default();
+    let longest_curly_brace_run = text
+        .split(|it| it != '{')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let quotes = "'".repeat(quotes_to_end_text.max(longest_curly_brace_run));
+    Some(format!("{quotes}\"{text}\"{quotes}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        handle::InlineHandle, int::InlineInt, tag::InlineTag, InlineData, InlineObject,
+        ToCandySource,
+    };
+    use crate::{
+        environment::DefaultEnvironment,
+        handle_id::HandleId,
+        heap::{Heap, HeapObject, Int, List, Struct, Tag, Text},
+        lir_to_byte_code::compile_byte_code,
+        tracer::DummyTracer,
+        PopulateInMemoryProviderFromFileSystem, Vm,
+    };
+    use candy_frontend::{
+        builtin_functions,
+        builtin_functions::BuiltinFunction,
+        format::Precedence,
+        hir_to_mir::ExecutionTarget,
+        id::CountableId,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+    use rustc_hash::FxHashMap;
+
+    #[test]
+    fn ints_round_trip() {
+        let max = i64::MAX >> InlineObject::KIND_WIDTH;
+        let min = i64::MIN >> InlineObject::KIND_WIDTH;
+        for value in [0, 1, -1, max, min] {
+            assert!(InlineInt::fits(value));
+            let int = InlineInt::from_unchecked(value);
+            assert!(matches!(InlineData::from(*int), InlineData::Int(_)));
+            assert_eq!(int.get(), value);
+        }
+        assert!(!InlineInt::fits(max + 1));
+        assert!(!InlineInt::fits(min - 1));
+    }
+
+    #[test]
+    fn builtins_round_trip() {
+        for builtin_function in builtin_functions::VALUES.iter() {
+            let object = InlineObject::from(*builtin_function);
+            let InlineData::Builtin(builtin) = InlineData::from(object) else {
+                panic!("{builtin_function:?} isn't encoded as a builtin.");
+            };
+            assert_eq!(builtin.get(), *builtin_function);
+        }
+    }
+
+    #[test]
+    fn handles_round_trip() {
+        let mut heap = Heap::default();
+        let max_handle_id = u32::MAX as usize;
+        let max_argument_count = (1 << 28) - 1;
+        for (handle_id, argument_count) in [(0, 0), (1, 2), (max_handle_id, max_argument_count)] {
+            let handle =
+                InlineHandle::create(&mut heap, HandleId::from_usize(handle_id), argument_count);
+            assert!(matches!(InlineData::from(*handle), InlineData::Handle(_)));
+            assert_eq!(handle.handle_id().to_usize(), handle_id);
+            assert_eq!(handle.argument_count(), argument_count);
+        }
+    }
+
+    #[test]
+    fn pointers_and_tags_round_trip() {
+        let mut heap = Heap::default();
+        let text = Text::create(&mut heap, false, "Foo");
+        let heap_object: HeapObject = **text;
+        assert_eq!(
+            heap_object.address().addr().get() % HeapObject::WORD_SIZE,
+            0,
+            "Heap objects must be aligned to words.",
+        );
+
+        let InlineData::Pointer(pointer) = InlineData::from(InlineObject::from(heap_object)) else {
+            panic!("A heap object isn't encoded as a pointer.");
+        };
+        assert!(pointer.get().pointer_equals(heap_object));
+
+        let tag = InlineTag::new(text);
+        assert!(matches!(InlineData::from(*tag), InlineData::Tag(_)));
+        assert!((**tag.get()).pointer_equals(heap_object));
+    }
+
+    fn text(heap: &mut Heap, value: &str) -> InlineObject {
+        Text::create(heap, true, value).into()
+    }
+    fn tag(heap: &mut Heap, symbol: &str, value: Option<InlineObject>) -> InlineObject {
+        let symbol = Text::create(heap, true, symbol);
+        Tag::create_with_value_option(heap, true, symbol, value).into()
+    }
+    /// Values of all kinds that have a literal syntax.
+    fn values(heap: &mut Heap) -> Vec<InlineObject> {
+        let one = Int::create(heap, true, 1).into();
+        let minus_two = Int::create(heap, true, -2).into();
+        let foo = tag(heap, "Foo", None);
+        let foo_with_value = tag(heap, "Foo", Some(minus_two));
+        let bar = tag(heap, "Bar", None);
+        let text = text(heap, "Hi, \"{you}\"!");
+        let empty_list = List::create(heap, true, &[]).into();
+        let list_with_one_item = List::create(heap, true, &[one]).into();
+        let list = List::create(heap, true, &[one, foo_with_value]).into();
+        let struct_ = Struct::create(
+            heap,
+            true,
+            &FxHashMap::from_iter([(foo, one), (bar, text), (one, empty_list)]),
+        )
+        .into();
+        vec![
+            one,
+            minus_two,
+            foo,
+            foo_with_value,
+            text,
+            empty_list,
+            list_with_one_item,
+            list,
+            struct_,
+        ]
+    }
+
+    #[test]
+    fn values_are_formatted_as_literals() {
+        let mut heap = Heap::default();
+        let sources = values(&mut heap)
+            .into_iter()
+            .map(|value| value.to_candy_source(Precedence::High).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            [
+                "1",
+                "(int.negate 2)",
+                "Foo",
+                "(Foo (int.negate 2))",
+                "'\"Hi, \"{you}\"!\"'",
+                "(,)",
+                "(1,)",
+                "(1, Foo (int.negate 2))",
+                "[1: (,), Bar: '\"Hi, \"{you}\"!\"', Foo: 1]",
+            ],
+        );
+    }
+    #[test]
+    fn texts_are_escaped() {
+        let mut heap = Heap::default();
+        let mut source = |value: &str| text(&mut heap, value).to_candy_source(Precedence::Low);
+        assert_eq!(source("foo").unwrap(), "\"foo\"");
+        assert_eq!(source("a\"b").unwrap(), "'\"a\"b\"'");
+        assert_eq!(source("a\"'b\"").unwrap(), "''\"a\"'b\"\"''");
+        assert_eq!(source("{a}").unwrap(), "'\"{a}\"'");
+        assert_eq!(source("{{a}} {b}").unwrap(), "''\"{{a}} {b}\"''");
+        assert_eq!(source(" a"), None);
+        assert_eq!(source("a\nb"), None);
+    }
+    #[test]
+    fn functions_have_no_literals() {
+        let function = InlineObject::from(BuiltinFunction::IntAdd);
+        assert_eq!(function.to_candy_source(Precedence::Low), None);
+        assert_eq!(function.to_candy_source_lossy(Precedence::Low), "{ … }");
+    }
+    #[test]
+    fn negative_ints_use_int_from_core() {
+        let mut heap = Heap::default();
+        let uses_int = values(&mut heap)
+            .into_iter()
+            .map(ToCandySource::candy_source_uses_int)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            uses_int,
+            [false, true, false, true, false, false, false, true, false],
+        );
+    }
+    #[test]
+    fn literals_evaluate_to_the_original_values() {
+        let mut heap = Heap::default();
+        let values = values(&mut heap);
+        let list = List::create(&mut heap, true, &values);
+        let source = InlineObject::from(list)
+            .to_candy_source(Precedence::Low)
+            .unwrap();
+
+        let mut db = Database::default();
+        let provider = db.get_in_memory_module_provider();
+        provider.load_package_from_file_system("Builtins");
+        provider.load_package_from_file_system("Core");
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(
+            &module,
+            format!("[int] = use \"Core\"\nmain := {{ environment -> {source} }}\n").into_bytes(),
+        );
+        let (byte_code, errors) = compile_byte_code(
+            &db,
+            ExecutionTarget::MainFunction(module),
+            TracingConfig::off(),
+        );
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let mut heap = Heap::default();
+        let (environment_object, _) = DefaultEnvironment::new(&mut heap, &[]);
+        let result = Vm::for_main_function(&byte_code, &mut heap, environment_object, DummyTracer)
+            .run_forever_without_handles(&mut heap)
+            .result
+            .unwrap();
+        assert_eq!(result.to_candy_source(Precedence::Low).unwrap(), source,);
+    }
+}
@@ -0,0 +1,206 @@
+//! Weak references and finalizers allow embedders to associate host resources
+//! (e.g., file handles or sockets) with Candy values without keeping those
+//! values alive.
+//!
+//! A [`WeakReference`] identifies a heap object until that object is freed,
+//! either because its reference count reached zero or because the whole heap
+//! was cleared. Afterwards, [`Heap::upgrade`] returns `None` and the object's
+//! finalizers run.
+
+use super::{AddressMap, Heap, HeapObject, ObjectInHeap};
+use candy_frontend::id::{CountableId, IdGenerator};
+use rustc_hash::FxHashMap;
+use std::{
+    collections::hash_map::Entry,
+    fmt::{self, Debug, Formatter},
+    mem,
+};
+
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct WeakReference(usize);
+impl CountableId for WeakReference {
+    fn from_usize(id: usize) -> Self {
+        Self(id)
+    }
+    fn to_usize(&self) -> usize {
+        self.0
+    }
+}
+impl Debug for WeakReference {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "weak_{:x}", self.0)
+    }
+}
+
+/// Gets called after the object behind the weak reference was freed.
+pub type Finalizer = Box<dyn FnOnce(WeakReference)>;
+
+#[derive(Default)]
+pub(super) struct WeakReferences {
+    id_generator: IdGenerator<WeakReference>,
+    objects: FxHashMap<WeakReference, HeapObject>,
+    entries: FxHashMap<ObjectInHeap, WeakEntry>,
+}
+struct WeakEntry {
+    reference: WeakReference,
+    /// References to objects that got merged into this one during compaction.
+    merged_references: Vec<WeakReference>,
+    finalizers: Vec<Finalizer>,
+}
+
+impl WeakReferences {
+    fn entry_for(&mut self, object: HeapObject) -> &mut WeakEntry {
+        self.entries.entry(ObjectInHeap(object)).or_insert_with(|| {
+            let reference = self.id_generator.generate();
+            self.objects.insert(reference, object);
+            WeakEntry {
+                reference,
+                merged_references: vec![],
+                finalizers: vec![],
+            }
+        })
+    }
+
+    /// Forgets about the object and returns the finalizers that have to be
+    /// run.
+    pub(super) fn notify_deallocated(
+        &mut self,
+        object: HeapObject,
+    ) -> Option<(WeakReference, Vec<Finalizer>)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let entry = self.entries.remove(&ObjectInHeap(object))?;
+        self.objects.remove(&entry.reference);
+        for reference in &entry.merged_references {
+            self.objects.remove(reference);
+        }
+        Some((entry.reference, entry.finalizers))
+    }
+
+    /// Points all weak references to the new addresses after compaction.
+    ///
+    /// Objects that weren't moved (e.g., constants) keep their addresses.
+    pub(super) fn relocate(&mut self, address_map: &AddressMap) {
+        for (object, entry) in mem::take(&mut self.entries) {
+            let new_object = address_map.get(*object).unwrap_or(*object);
+            self.objects.insert(entry.reference, new_object);
+            for reference in &entry.merged_references {
+                self.objects.insert(*reference, new_object);
+            }
+
+            match self.entries.entry(ObjectInHeap(new_object)) {
+                Entry::Vacant(vacant) => {
+                    vacant.insert(entry);
+                }
+                Entry::Occupied(mut occupied) => {
+                    // Finalizers get called with the reference they were
+                    // registered for.
+                    let existing = occupied.get_mut();
+                    let reference = entry.reference;
+                    existing.merged_references.push(reference);
+                    existing.merged_references.extend(entry.merged_references);
+                    existing.finalizers.extend(
+                        entry.finalizers.into_iter().map(|finalizer| -> Finalizer {
+                            Box::new(move |_| finalizer(reference))
+                        }),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Heap {
+    /// Returns a weak reference to the object. Calling this multiple times for
+    /// the same object returns the same reference.
+    pub fn downgrade(&mut self, object: HeapObject) -> WeakReference {
+        self.weak_references.entry_for(object).reference
+    }
+
+    /// Returns the object if it's still alive. The object's reference count is
+    /// increased, so the caller is responsible for dropping it.
+    ///
+    /// Objects that are only waiting to be freed (see
+    /// [`Heap::limit_objects_freed_at_once`]) are already dead.
+    #[must_use]
+    pub fn upgrade(&self, reference: WeakReference) -> Option<HeapObject> {
+        let object = *self.weak_references.objects.get(&reference)?;
+        if object.reference_count() == Some(0) {
+            return None;
+        }
+        object.dup();
+        Some(object)
+    }
+
+    /// Registers a callback that is invoked after the object is freed and
+    /// returns the weak reference that gets passed to it.
+    ///
+    /// Finalizers of objects that are still alive run when the heap is cleared
+    /// or dropped. They're not carried over when cloning the heap.
+    pub fn add_finalizer(
+        &mut self,
+        object: HeapObject,
+        finalizer: impl FnOnce(WeakReference) + 'static,
+    ) -> WeakReference {
+        let entry = self.weak_references.entry_for(object);
+        entry.finalizers.push(Box::new(finalizer));
+        entry.reference
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::heap::{Heap, List, Text};
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn finalizer_runs_when_object_is_freed() {
+        let mut heap = Heap::default();
+        let text = **Text::create(&mut heap, true, "resource");
+        let was_finalized = Rc::new(Cell::new(false));
+
+        let reference = heap.add_finalizer(text, {
+            let was_finalized = was_finalized.clone();
+            move |_| was_finalized.set(true)
+        });
+        assert_eq!(heap.downgrade(text), reference);
+
+        let upgraded = heap.upgrade(reference).unwrap();
+        assert!(upgraded.pointer_equals(text));
+        upgraded.drop(&mut heap);
+        assert!(!was_finalized.get());
+
+        text.drop(&mut heap);
+        assert!(was_finalized.get());
+        assert!(heap.upgrade(reference).is_none());
+    }
+
+    #[test]
+    fn objects_waiting_to_be_freed_cant_be_upgraded() {
+        let mut heap = Heap::default();
+        heap.limit_objects_freed_at_once(1);
+        let text = Text::create(&mut heap, true, "resource");
+        let list = List::create(&mut heap, true, &[text.into()]);
+        let was_finalized = Rc::new(Cell::new(false));
+        let reference = heap.add_finalizer(**text, {
+            let was_finalized = was_finalized.clone();
+            move |_| was_finalized.set(true)
+        });
+
+        // Freeing the list enqueues the text instead of freeing it right away.
+        list.drop(&mut heap);
+        assert_eq!(text.reference_count(), Some(0));
+        assert!(!was_finalized.get());
+        assert!(heap.upgrade(reference).is_none());
+
+        let (cloned, mapping) = heap.clone();
+        assert!(!mapping.contains_key(&**text));
+        assert_eq!(cloned.objects().len(), heap.objects().len() - 1);
+
+        heap.finish_freeing();
+        assert!(was_finalized.get());
+        assert!(heap.upgrade(reference).is_none());
+    }
+}
@@ -7,6 +7,10 @@ use std::{
 #[derive(Clone, Copy, Deref, Eq, From, Hash, Ord, PartialEq, PartialOrd)]
 pub struct InstructionPointer(usize);
 impl InstructionPointer {
+    /// Functions called by builtins return to this address so that the VM
+    /// resumes the builtin afterwards.
+    pub(crate) const RESUME_BUILTIN: Self = Self(usize::MAX);
+
     #[must_use]
     pub const fn null_pointer() -> Self {
         Self(0)
@@ -1,9 +1,12 @@
 use crate::{
-    byte_code::Instruction,
-    heap::{Data, Function, Heap, HirId, InlineObject, List, Pointer, Struct, Tag, Text},
+    byte_code::{ByteCode, Instruction},
+    heap::{
+        Data, Function, Heap, HirId, InlineObject, List, Pointer, Struct, Tag, Text, ToDebugText,
+    },
     tracer::Tracer,
     vm::{CallHandle, MachineState, Panic},
 };
+use candy_frontend::format::{NestingLimits, Precedence};
 use extension_trait::extension_trait;
 use itertools::Itertools;
 use tracing::trace;
@@ -13,9 +16,78 @@ const TRACE: bool = false;
 pub enum InstructionResult {
     Done,
     CallHandle(CallHandle),
+    /// The VM turns this into a [`Panic`] once it can look up where the
+    /// function is defined.
+    ArityMismatch(ArityMismatch),
     Panic(Panic),
 }
 
+/// A function was called with the wrong number of arguments.
+pub struct ArityMismatch {
+    pub function: Function,
+    pub arguments: Vec<InlineObject>,
+    pub responsible: HirId,
+}
+impl ArityMismatch {
+    #[must_use]
+    pub fn into_panic(self, byte_code: &ByteCode) -> Panic {
+        // Functions with the same code share their byte code, so there can be
+        // several definitions.
+        let definition = byte_code
+            .functions_behind(self.function.body())
+            .iter()
+            .min();
+        let expected = pluralize(self.function.argument_count(), "argument");
+        let reason = if let Some(definition) = definition {
+            format!(
+                "`{}` expects {expected}, but you called it with {}. It's defined at {definition}.",
+                definition.function_name(),
+                format_arguments(&self.arguments),
+            )
+        } else {
+            format!(
+                "A function expects {expected}, but you called it with {}.",
+                format_arguments(&self.arguments),
+            )
+        };
+        Panic {
+            reason,
+            responsible: self.responsible.get().clone(),
+        }
+    }
+}
+
+/// E.g., ``2 arguments: `1`, `Foo` `` – previews of the values help to find
+/// out which argument is missing or superfluous.
+fn format_arguments(arguments: &[InlineObject]) -> String {
+    if arguments.is_empty() {
+        return "no arguments".to_string();
+    }
+
+    format!(
+        "{}: {}",
+        pluralize(arguments.len(), "argument"),
+        arguments
+            .iter()
+            .map(|it| {
+                let preview = it.to_debug_text_with_limits(
+                    Precedence::High,
+                    NestingLimits::MAX_LENGTH_FOR_MESSAGES,
+                    NestingLimits::FOR_MESSAGES,
+                );
+                format!("`{preview}`")
+            })
+            .join(", "),
+    )
+}
+fn pluralize(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
 impl MachineState {
     pub fn run_instruction(
         &mut self,
@@ -116,6 +188,10 @@ impl MachineState {
                 InstructionResult::Done
             }
             Instruction::Call { num_args } => {
+                if let Some(result) = self.try_run_builtin_fast_path(heap, *num_args, None) {
+                    return result;
+                }
+
                 let responsible = self.pop_from_data_stack().try_into().unwrap();
                 let mut arguments = (0..*num_args)
                     .map(|_| self.pop_from_data_stack())
@@ -130,6 +206,12 @@ impl MachineState {
                 num_locals_to_pop,
                 num_args,
             } => {
+                if let Some(result) =
+                    self.try_run_builtin_fast_path(heap, *num_args, Some(*num_locals_to_pop))
+                {
+                    return result;
+                }
+
                 let responsible = self.pop_from_data_stack().try_into().unwrap();
                 let mut arguments = (0..*num_args)
                     .map(|_| self.pop_from_data_stack())
@@ -213,21 +295,18 @@ impl MachineState {
         responsible: HirId,
     ) -> InstructionResult {
         match callee.into() {
-            Data::Function(function) => self.call_function( function, arguments, responsible),
+            Data::Function(function) => self.call_function(heap, function, arguments, responsible),
             Data::Builtin(builtin) => {
                 self.run_builtin_function(heap, builtin.get(), arguments, responsible)
             }
             Data::Handle(handle) => {
                 let parameter_count = handle.argument_count();
-                let argument_count = arguments.len();
-                if argument_count != parameter_count {
+                if arguments.len() != parameter_count {
                     return InstructionResult::Panic(Panic {
                         reason: format!(
-                            "A function expected {} {}, but you called it with {} {}.",
-                            parameter_count,
-                            if parameter_count == 1 { "parameter" } else { "parameters" },
-                            argument_count,
-                            if argument_count == 1 { "argument" } else { "arguments" },
+                            "A handle expects {}, but you called it with {}.",
+                            pluralize(parameter_count, "argument"),
+                            format_arguments(arguments),
                         ),
                         responsible: responsible.get().clone(),
                     });
@@ -268,30 +347,36 @@ impl MachineState {
             }),
         }
     }
+    /// Calls the `function`, consuming the references to it and to the
+    /// `arguments`.
     pub fn call_function(
         &mut self,
+        heap: &mut Heap,
         function: Function,
         arguments: &[InlineObject],
         responsible: HirId,
     ) -> InstructionResult {
-        let expected_num_args = function.argument_count();
-        if arguments.len() != expected_num_args {
-            return InstructionResult::Panic(Panic {
-                reason: format!(
-                    "A function expected {expected_num_args} parameters, but you called it with {} arguments.",
-                    arguments.len(),
-                ),
-                responsible: responsible.get().clone(),
+        if arguments.len() != function.argument_count() {
+            return InstructionResult::ArityMismatch(ArityMismatch {
+                function,
+                arguments: arguments.to_vec(),
+                responsible,
             });
         }
 
         if let Some(next_instruction) = self.next_instruction {
             self.call_stack.push(next_instruction);
         }
+        // The body drops the captured values when it's done, but the function
+        // keeps its own references in case it's called again.
+        for captured in function.captured() {
+            captured.dup(heap);
+        }
         self.data_stack.extend_from_slice(function.captured());
         self.data_stack.extend_from_slice(arguments);
         self.push_to_data_stack(responsible);
         self.next_instruction = Some(function.body());
+        InlineObject::from(function).drop(heap);
         InstructionResult::Done
     }
 
@@ -316,3 +401,23 @@ impl NthLast for Vec<Pointer> {
         self[self.len() - 1 - index]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::format_arguments;
+    use crate::heap::{Heap, Int, Text};
+
+    #[test]
+    fn arguments_are_previewed() {
+        let mut heap = Heap::default();
+        let one = Int::create(&mut heap, true, 1);
+        let text = Text::create(&mut heap, true, "Hi");
+
+        assert_eq!(format_arguments(&[]), "no arguments");
+        assert_eq!(format_arguments(&[one.into()]), "1 argument: `1`");
+        assert_eq!(
+            format_arguments(&[one.into(), text.into()]),
+            "2 arguments: `1`, `\"Hi\"`",
+        );
+    }
+}
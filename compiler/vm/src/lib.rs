@@ -31,16 +31,22 @@
 pub use builtin_functions::CAN_USE_STDOUT;
 pub use instruction_pointer::InstructionPointer;
 pub use utils::PopulateInMemoryProviderFromFileSystem;
-pub use vm::{Panic, StateAfterRun, StateAfterRunForever, Vm, VmFinished};
+pub use vm::{Panic, StateAfterRun, StateAfterRunForever, Vm, VmEvent, VmFinished};
 
 mod builtin_functions;
 pub mod byte_code;
+pub mod capabilities;
+mod checks;
+pub mod disassembler;
 pub mod environment;
+pub mod eval;
 mod handle_id;
+pub mod handle_registry;
 pub mod heap;
 mod instruction_pointer;
 mod instructions;
 pub mod lir_to_byte_code;
+pub mod recording;
 pub mod tracer;
 mod utils;
 mod vm;
@@ -17,6 +17,7 @@ use candy_frontend::{
 };
 use extension_trait::extension_trait;
 use itertools::Itertools;
+use num_bigint::BigInt;
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::{mem, sync::Arc};
 
@@ -68,6 +69,10 @@ struct LoweringContext<'c> {
     lir: &'c Lir,
     byte_code: ByteCode,
     constant_mapping: FxHashMap<ConstantId, InlineObject>,
+    /// LIR constants of different modules often have the same value (e.g.,
+    /// common tags or texts). All of them share a single object in the
+    /// constant heap, which saves memory and makes comparing them cheap.
+    interned_constants: FxHashMap<InternKey, InlineObject>,
     body_mapping: FxHashMap<BodyId, InstructionPointer>,
     stack: Vec<Id>,
     instructions: Vec<Instruction>,
@@ -97,6 +102,7 @@ impl<'c> LoweringContext<'c> {
             lir,
             byte_code,
             constant_mapping: FxHashMap::default(),
+            interned_constants: FxHashMap::default(),
             body_mapping: FxHashMap::default(),
             stack: vec![],
             instructions: vec![],
@@ -164,14 +170,7 @@ impl<'c> LoweringContext<'c> {
     fn compile_expression(&mut self, id: Id, expression: &Expression) {
         match expression {
             Expression::CreateTag { symbol, value } => {
-                let symbol = self
-                    .byte_code
-                    .constant_heap
-                    .default_symbols()
-                    .get(symbol)
-                    .unwrap_or_else(|| {
-                        Text::create(&mut self.byte_code.constant_heap, false, symbol)
-                    });
+                let symbol = self.get_symbol(symbol);
 
                 self.emit_reference_to(*value);
                 self.emit(id, Instruction::CreateTag { symbol });
@@ -306,62 +305,70 @@ impl<'c> LoweringContext<'c> {
             .unwrap_or_else(|| self.compile_constant(id))
     }
     fn compile_constant(&mut self, id: ConstantId) -> InlineObject {
-        let constant: InlineObject = match self.lir.constants().get(id) {
-            Constant::Int(int) => {
-                Int::create_from_bigint(&mut self.byte_code.constant_heap, false, int.clone())
-                    .into()
-            }
-            Constant::Text(text) => {
-                Text::create(&mut self.byte_code.constant_heap, false, text).into()
-            }
-            Constant::Tag { symbol, value } => {
-                let symbol = self
-                    .byte_code
-                    .constant_heap
-                    .default_symbols()
-                    .get(symbol)
-                    .unwrap_or_else(|| {
-                        Text::create(&mut self.byte_code.constant_heap, false, symbol)
-                    });
-                let value = value.map(|id| self.get_constant(id));
-                Tag::create_with_value_option(
-                    &mut self.byte_code.constant_heap,
-                    false,
-                    symbol,
-                    value,
-                )
-                .into()
-            }
-            Constant::Builtin(builtin) => Builtin::create(*builtin).into(),
+        // The children of a constant are interned before the constant itself,
+        // so the keys only contain interned objects.
+        let key = match self.lir.constants().get(id) {
+            Constant::Int(int) => InternKey::Int(int.clone()),
+            Constant::Text(text) => InternKey::Text(text.clone()),
+            Constant::Tag { symbol, value } => InternKey::Tag {
+                symbol: self.get_symbol(symbol),
+                value: value.map(|id| self.get_constant(id)),
+            },
+            Constant::Builtin(builtin) => InternKey::Builtin(Builtin::create(*builtin)),
             Constant::List(items) => {
-                let items = items.iter().map(|id| self.get_constant(*id)).collect_vec();
-                List::create(&mut self.byte_code.constant_heap, false, &items).into()
+                InternKey::List(items.iter().map(|id| self.get_constant(*id)).collect())
             }
-            Constant::Struct(fields) => {
-                let fields = fields
+            Constant::Struct(fields) => InternKey::Struct(
+                fields
                     .iter()
                     .map(|(key, value)| (self.get_constant(*key), self.get_constant(*value)))
-                    .collect();
-                Struct::create(&mut self.byte_code.constant_heap, false, &fields).into()
-            }
-            Constant::HirId(hir_id) => {
-                HirId::create(&mut self.byte_code.constant_heap, false, hir_id.clone()).into()
-            }
-            Constant::Function(body_id) => {
-                let body = self.get_body(*body_id);
-                Function::create(
-                    &mut self.byte_code.constant_heap,
-                    false,
-                    &[],
-                    self.lir.bodies().get(*body_id).parameter_count(),
-                    body,
-                )
-                .into()
-            }
+                    .sorted()
+                    .collect(),
+            ),
+            Constant::HirId(hir_id) => InternKey::HirId(hir_id.clone()),
+            Constant::Function(body_id) => InternKey::Function {
+                argument_count: self.lir.bodies().get(*body_id).parameter_count(),
+                body: self.get_body(*body_id),
+            },
         };
+        let constant = self.intern(key);
         self.constant_mapping.force_insert(id, constant);
         constant
     }
+    fn get_symbol(&mut self, symbol: &str) -> Text {
+        if let Some(symbol) = self.byte_code.constant_heap.default_symbols().get(symbol) {
+            return symbol;
+        }
+        self.intern(InternKey::Text(symbol.to_string()))
+            .try_into()
+            .unwrap()
+    }
+    fn intern(&mut self, key: InternKey) -> InlineObject {
+        if let Some(object) = self.interned_constants.get(&key) {
+            return *object;
+        }
+
+        let heap = &mut self.byte_code.constant_heap;
+        let object = match &key {
+            InternKey::Int(int) => Int::create_from_bigint(heap, false, int.clone()).into(),
+            InternKey::Text(text) => Text::create(heap, false, text).into(),
+            InternKey::Tag { symbol, value } => {
+                Tag::create_with_value_option(heap, false, *symbol, *value).into()
+            }
+            InternKey::Builtin(builtin) => (*builtin).into(),
+            InternKey::List(items) => List::create(heap, false, items).into(),
+            InternKey::Struct(fields) => {
+                Struct::create(heap, false, &fields.iter().copied().collect()).into()
+            }
+            InternKey::HirId(hir_id) => HirId::create(heap, false, hir_id.clone()).into(),
+            InternKey::Function {
+                argument_count,
+                body,
+            } => Function::create(heap, false, &[], *argument_count, *body).into(),
+        };
+        self.interned_constants.force_insert(key, object);
+        object
+    }
 
     fn emit_reference_to(&mut self, id: Id) {
         let offset = self.stack.find_id(id);
@@ -373,6 +380,26 @@ impl<'c> LoweringContext<'c> {
     }
 }
 
+/// The structural identity of a constant.
+#[derive(Eq, Hash, PartialEq)]
+enum InternKey {
+    Int(BigInt),
+    Text(String),
+    Tag {
+        symbol: Text,
+        value: Option<InlineObject>,
+    },
+    Builtin(Builtin),
+    List(Vec<InlineObject>),
+    /// Sorted by key.
+    Struct(Vec<(InlineObject, InlineObject)>),
+    HirId(hir::Id),
+    Function {
+        argument_count: usize,
+        body: InstructionPointer,
+    },
+}
+
 #[extension_trait]
 impl StackExt for Vec<Id> {
     fn pop_multiple(&mut self, n: usize) {
@@ -387,3 +414,45 @@ impl StackExt for Vec<Id> {
             .unwrap_or_else(|| panic!("Id {id} not found in stack: {}", self.iter().join(" ")))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::compile_byte_code;
+    use crate::heap::HeapData;
+    use candy_frontend::{
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+
+    /// How often the text occurs in the constant heap of the compiled source.
+    fn count_texts(source: &str, text: &str) -> usize {
+        let mut db = Database::default();
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, source.as_bytes().to_vec());
+        let (byte_code, errors) =
+            compile_byte_code(&db, ExecutionTarget::Module(module), TracingConfig::off());
+        assert!(errors.is_empty(), "{errors:?}");
+
+        byte_code
+            .constant_heap
+            .iter()
+            .filter(
+                |object| matches!(HeapData::from(*object), HeapData::Text(it) if it.get() == text),
+            )
+            .count()
+    }
+
+    #[test]
+    fn symbols_and_equal_texts_are_interned() {
+        let source = "name := \"Greeting\"\ntag := Greeting\nstruct := [Greeting: 1]\n";
+        assert_eq!(count_texts(source, "Greeting"), 1);
+    }
+}
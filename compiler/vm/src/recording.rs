@@ -0,0 +1,425 @@
+//! Recording and replaying the interactions of a program with the outside
+//! world.
+//!
+//! Everything a Candy program receives from outside (stdin, random bytes, HTTP
+//! requests, etc.) is the result of a handle call. A [`RecordingEnvironment`]
+//! wraps another [`Environment`] and captures the environment object as well
+//! as every handle call with its arguments, result, and timestamp. A
+//! [`ReplayEnvironment`] later answers the same handle calls from such a
+//! [`Recording`] without touching the outside world, so bug reports involving
+//! interactive programs can be reproduced exactly:
+//!
+//! ```ignore
+//! let (environment_object, environment) = DefaultEnvironment::new(&mut heap, &arguments);
+//! let mut environment = RecordingEnvironment::new(environment_object, environment);
+//! // Run the VM with the environment…
+//! environment.into_recording().save(path)?;
+//!
+//! let (environment_object, mut environment) =
+//!     ReplayEnvironment::new(&mut heap, Recording::load(path)?);
+//! // …and run the VM again with the replaying environment.
+//! ```
+
+use crate::{
+    byte_code::ByteCode,
+    environment::Environment,
+    heap::{Data, Handle, Heap, InlineObject, Int, List, Struct, Tag, Text},
+    tracer::Tracer,
+    vm::VmHandleCall,
+    Vm,
+};
+use candy_frontend::{id::CountableId, utils::HashMapExtension};
+use itertools::Itertools;
+use num_bigint::BigInt;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Borrow,
+    fs, io,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+    vec,
+};
+use tracing::warn;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    /// The struct that was passed to the main function.
+    pub environment: RecordedValue,
+    pub events: Vec<RecordedEvent>,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedEvent {
+    /// When the handle was called, relative to the start of the recording.
+    pub time: Duration,
+    /// The ID of the handle that was called.
+    pub handle: usize,
+    pub arguments: Vec<RecordedValue>,
+    pub result: RecordedValue,
+}
+
+/// A heap-independent copy of a value.
+///
+/// Handles are stored by their ID in the recorded program.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecordedValue {
+    Int(String),
+    Tag {
+        symbol: String,
+        value: Option<Box<RecordedValue>>,
+    },
+    Text(String),
+    List(Vec<RecordedValue>),
+    Struct(Vec<(RecordedValue, RecordedValue)>),
+    Handle {
+        id: usize,
+        argument_count: usize,
+    },
+    /// Functions, builtins, and HIR IDs can be passed to handles, but they
+    /// only make sense inside the VM that created them. We only keep their
+    /// debug representation to compare them during replay.
+    Opaque(String),
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(self).unwrap();
+        fs::write(path, content)
+    }
+}
+
+impl RecordedValue {
+    pub fn from_object(object: InlineObject, handle_id: &impl Fn(Handle) -> usize) -> Self {
+        match Data::from(object) {
+            Data::Int(int) => Self::Int(int.get().to_string()),
+            Data::Tag(tag) => Self::Tag {
+                symbol: tag.symbol().get().to_string(),
+                value: tag
+                    .value()
+                    .map(|value| Box::new(Self::from_object(value, handle_id))),
+            },
+            Data::Text(text) => Self::Text(text.get().to_string()),
+            Data::List(list) => Self::List(
+                list.items()
+                    .iter()
+                    .map(|item| Self::from_object(*item, handle_id))
+                    .collect(),
+            ),
+            Data::Struct(struct_) => Self::Struct(
+                struct_
+                    .iter()
+                    .map(|(_, key, value)| {
+                        (
+                            Self::from_object(key, handle_id),
+                            Self::from_object(value, handle_id),
+                        )
+                    })
+                    .collect(),
+            ),
+            Data::Handle(handle) => Self::Handle {
+                id: handle_id(handle),
+                argument_count: handle.argument_count(),
+            },
+            Data::HirId(_) | Data::Function(_) | Data::Builtin(_) => {
+                Self::Opaque(format!("{object:?}"))
+            }
+        }
+    }
+
+    /// Whether the value can be recreated outside of the program it was
+    /// recorded in, i.e., it contains neither handles nor opaque values.
+    #[must_use]
+    pub fn is_self_contained(&self) -> bool {
+        match self {
+            Self::Int(_) | Self::Text(_) => true,
+            Self::Tag { value, .. } => value.as_ref().map_or(true, |it| it.is_self_contained()),
+            Self::List(items) => items.iter().all(Self::is_self_contained),
+            Self::Struct(fields) => fields
+                .iter()
+                .all(|(key, value)| key.is_self_contained() && value.is_self_contained()),
+            Self::Handle { .. } | Self::Opaque(_) => false,
+        }
+    }
+
+    /// Creates a [self-contained](Self::is_self_contained) value in the heap.
+    pub fn create_object(&self, heap: &mut Heap) -> InlineObject {
+        self.create_object_with_handles(heap, &mut |_, id, _| {
+            panic!("The value contains handle {id}, so it can't be recreated on its own.")
+        })
+    }
+    fn create_object_with_handles(
+        &self,
+        heap: &mut Heap,
+        create_handle: &mut impl FnMut(&mut Heap, usize, usize) -> InlineObject,
+    ) -> InlineObject {
+        match self {
+            Self::Int(int) => {
+                let int = BigInt::from_str(int).expect("The recorded value is an invalid int.");
+                Int::create_from_bigint(heap, true, int).into()
+            }
+            Self::Tag { symbol, value } => {
+                let symbol = Text::create(heap, true, symbol);
+                let value = value
+                    .as_ref()
+                    .map(|value| value.create_object_with_handles(heap, create_handle));
+                Tag::create_with_value_option(heap, true, symbol, value).into()
+            }
+            Self::Text(text) => Text::create(heap, true, text).into(),
+            Self::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| item.create_object_with_handles(heap, create_handle))
+                    .collect_vec();
+                List::create(heap, true, &items).into()
+            }
+            Self::Struct(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, value)| {
+                        (
+                            key.create_object_with_handles(heap, create_handle),
+                            value.create_object_with_handles(heap, create_handle),
+                        )
+                    })
+                    .collect();
+                Struct::create(heap, true, &fields).into()
+            }
+            Self::Handle { id, argument_count } => create_handle(heap, *id, *argument_count),
+            Self::Opaque(description) => {
+                panic!("The recorded value can't be recreated: {description}")
+            }
+        }
+    }
+}
+
+/// Wraps an environment and records all handle calls going through it.
+pub struct RecordingEnvironment<E: Environment> {
+    inner: E,
+    start: Instant,
+    recording: Recording,
+}
+impl<E: Environment> RecordingEnvironment<E> {
+    #[must_use]
+    pub fn new(environment_object: Struct, inner: E) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            recording: Recording {
+                environment: RecordedValue::from_object(
+                    environment_object.into(),
+                    &Self::handle_id,
+                ),
+                events: vec![],
+            },
+        }
+    }
+    fn handle_id(handle: Handle) -> usize {
+        handle.handle_id().to_usize()
+    }
+
+    #[must_use]
+    pub fn into_recording(self) -> Recording {
+        self.recording
+    }
+}
+impl<E: Environment> Environment for RecordingEnvironment<E> {
+    fn handle<B: Borrow<ByteCode>, T: Tracer>(
+        &mut self,
+        heap: &mut Heap,
+        call: VmHandleCall<B, T>,
+    ) -> Vm<B, T> {
+        let time = self.start.elapsed();
+        let handle = Self::handle_id(call.handle);
+        // The arguments are dropped when the call completes.
+        let arguments = call
+            .arguments
+            .iter()
+            .map(|argument| RecordedValue::from_object(*argument, &Self::handle_id))
+            .collect();
+
+        let vm = self.inner.handle(heap, call);
+        let result = RecordedValue::from_object(vm.top_of_data_stack().unwrap(), &Self::handle_id);
+        self.recording.events.push(RecordedEvent {
+            time,
+            handle,
+            arguments,
+            result,
+        });
+        vm
+    }
+}
+
+/// Answers handle calls with the results from a [`Recording`].
+///
+/// No handle is actually executed, except that texts sent to the `Stdout`
+/// handle of the environment object are printed so that the replay shows the
+/// same output as the original run.
+pub struct ReplayEnvironment {
+    events: vec::IntoIter<RecordedEvent>,
+    /// Maps handles of the replaying VM to the IDs they had while recording.
+    recorded_ids: FxHashMap<Handle, usize>,
+    handles: FxHashMap<usize, Handle>,
+    stdout: Option<usize>,
+}
+impl ReplayEnvironment {
+    pub fn new(heap: &mut Heap, recording: Recording) -> (Struct, Self) {
+        let mut environment = Self {
+            events: recording.events.into_iter(),
+            recorded_ids: FxHashMap::default(),
+            handles: FxHashMap::default(),
+            stdout: None,
+        };
+        if let RecordedValue::Struct(fields) = &recording.environment {
+            environment.stdout = fields.iter().find_map(|(key, value)| match (key, value) {
+                (
+                    RecordedValue::Tag {
+                        symbol,
+                        value: None,
+                    },
+                    RecordedValue::Handle { id, .. },
+                ) if symbol == "Stdout" => Some(*id),
+                _ => None,
+            });
+        }
+
+        let environment_object = environment
+            .create_object(heap, &recording.environment)
+            .try_into()
+            .expect("The recorded environment is not a struct.");
+        (environment_object, environment)
+    }
+
+    fn create_object(&mut self, heap: &mut Heap, value: &RecordedValue) -> InlineObject {
+        value.create_object_with_handles(heap, &mut |heap, id, argument_count| {
+            if let Some(handle) = self.handles.get(&id) {
+                let handle = InlineObject::from(*handle);
+                handle.dup(heap);
+                return handle;
+            }
+            let handle = Handle::new(heap, argument_count);
+            self.handles.force_insert(id, handle);
+            self.recorded_ids.force_insert(handle, id);
+            handle.into()
+        })
+    }
+}
+impl Environment for ReplayEnvironment {
+    fn handle<B: Borrow<ByteCode>, T: Tracer>(
+        &mut self,
+        heap: &mut Heap,
+        call: VmHandleCall<B, T>,
+    ) -> Vm<B, T> {
+        let handle = *self.recorded_ids.get(&call.handle).unwrap_or_else(|| {
+            panic!(
+                "A handle was called that isn't part of the recording: {handle:?}",
+                handle = call.handle,
+            )
+        });
+        let Some(event) = self.events.next() else {
+            panic!("The program called more handles than were recorded.");
+        };
+        assert_eq!(
+            handle, event.handle,
+            "The program diverged from the recording: It called a different handle.",
+        );
+
+        let recorded_ids = &self.recorded_ids;
+        let arguments = call
+            .arguments
+            .iter()
+            .map(|argument| {
+                RecordedValue::from_object(*argument, &|handle| {
+                    recorded_ids.get(&handle).copied().unwrap_or(usize::MAX)
+                })
+            })
+            .collect_vec();
+        if arguments != event.arguments {
+            warn!(
+                "The program diverged from the recording: Handle {handle} was called with {arguments:?} instead of {:?}.",
+                event.arguments,
+            );
+        }
+        if Some(handle) == self.stdout
+            && let [RecordedValue::Text(text)] = arguments.as_slice()
+        {
+            println!("{text}");
+        }
+
+        let result = self.create_object(heap, &event.result);
+        call.complete(heap, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordedValue, Recording, ReplayEnvironment};
+    use crate::heap::{Handle, Heap, InlineObject, Int, List, Struct, Text};
+    use candy_frontend::id::CountableId;
+
+    #[test]
+    fn replayed_environment_matches_recorded_one() {
+        let mut heap = Heap::default();
+        let handle = Handle::new(&mut heap, 1);
+        let foo = Text::create(&mut heap, true, "foo");
+        let answer = Int::create(&mut heap, true, 42);
+        let arguments = List::create(&mut heap, true, &[foo.into(), answer.into()]);
+        let arguments_symbol = heap.default_symbols().arguments;
+        let stdout_symbol = Text::create(&mut heap, true, "Stdout");
+        let environment = Struct::create_with_symbol_keys(
+            &mut heap,
+            true,
+            [
+                (arguments_symbol, arguments.into()),
+                (stdout_symbol, handle.into()),
+            ],
+        );
+        let recorded =
+            RecordedValue::from_object(environment.into(), &|handle| handle.handle_id().to_usize());
+
+        let mut replay_heap = Heap::default();
+        let (replayed, replay_environment) = ReplayEnvironment::new(
+            &mut replay_heap,
+            Recording {
+                environment: recorded.clone(),
+                events: vec![],
+            },
+        );
+        assert_eq!(
+            replay_environment.stdout,
+            Some(handle.handle_id().to_usize())
+        );
+        let replayed = RecordedValue::from_object(InlineObject::from(replayed), &|handle| {
+            replay_environment.recorded_ids[&handle]
+        });
+        assert_eq!(replayed, recorded);
+    }
+
+    #[test]
+    fn self_contained_values_can_be_recreated() {
+        let mut heap = Heap::default();
+        let handle = Handle::new(&mut heap, 0);
+        let items = [
+            Text::create(&mut heap, true, "foo").into(),
+            Int::create(&mut heap, true, 42).into(),
+        ];
+        let list = List::create(&mut heap, true, &items);
+        let recorded = RecordedValue::from_object(list.into(), &|_| 0);
+        assert!(recorded.is_self_contained());
+
+        let mut other_heap = Heap::default();
+        let recreated = recorded.create_object(&mut other_heap);
+        assert_eq!(RecordedValue::from_object(recreated, &|_| 0), recorded);
+
+        let with_handle = List::create(&mut heap, true, &[handle.into()]);
+        assert!(!RecordedValue::from_object(with_handle.into(), &|_| 0).is_self_contained());
+    }
+}
@@ -0,0 +1,203 @@
+use super::{stack_trace::format_location, Tracer};
+use crate::heap::{Heap, HirId, InlineObject};
+use candy_frontend::{
+    ast_to_hir::AstToHir, hir::Id, module::PackagesPath, position::PositionConversionDb,
+};
+use itertools::Itertools;
+use pad::{Alignment, PadStr};
+use rustc_hash::FxHashMap;
+use std::{cmp::Reverse, env::current_dir, iter};
+
+/// Attributes the executed instructions to the call sites that caused them.
+///
+/// Instructions are counted towards the innermost call on a shadow stack of
+/// call sites, so this only sees calls in code that was compiled with call
+/// tracing enabled. The instructions of calls that didn't end (e.g., because
+/// the program panicked) aren't included in the costs.
+#[derive(Debug, Default)]
+pub struct CostTracer {
+    stack: Vec<Frame>,
+    /// How often each call site is currently on the stack. Recursive calls
+    /// only count towards the total of the outermost call.
+    active_calls: FxHashMap<Id, usize>,
+    instructions: usize,
+    costs: FxHashMap<Id, Cost>,
+}
+#[derive(Debug)]
+struct Frame {
+    call_site: Id,
+    instructions_at_start: usize,
+    self_instructions: usize,
+}
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Cost {
+    pub calls: usize,
+    /// Instructions executed directly by the called function, excluding those
+    /// of nested traced calls.
+    pub self_instructions: usize,
+    /// Instructions executed by the call, including nested calls.
+    pub total_instructions: usize,
+}
+
+impl CostTracer {
+    /// The number of all instructions that were executed so far.
+    #[must_use]
+    pub const fn instructions(&self) -> usize {
+        self.instructions
+    }
+    #[must_use]
+    pub const fn costs(&self) -> &FxHashMap<Id, Cost> {
+        &self.costs
+    }
+    /// The costs of all call sites, most expensive first.
+    #[must_use]
+    pub fn sorted_costs(&self) -> Vec<(&Id, &Cost)> {
+        self.costs
+            .iter()
+            .sorted_by_key(|(id, cost)| (Reverse(cost.total_instructions), *id))
+            .collect()
+    }
+
+    pub fn format<DB>(&self, db: &DB, packages_path: &PackagesPath) -> String
+    where
+        DB: AstToHir + PositionConversionDb,
+    {
+        let current_directory = current_dir().ok();
+        let rows = self
+            .sorted_costs()
+            .into_iter()
+            .map(|(id, cost)| {
+                #[allow(clippy::cast_precision_loss)]
+                let share = 100. * cost.total_instructions as f64 / self.instructions.max(1) as f64;
+                [
+                    format_location(db, packages_path, current_directory.as_deref(), id),
+                    cost.calls.to_string(),
+                    cost.self_instructions.to_string(),
+                    cost.total_instructions.to_string(),
+                    format!("{share:.1} %"),
+                ]
+            })
+            .collect_vec();
+
+        let header = ["Call site", "Calls", "Self", "Total", "Share"].map(ToString::to_string);
+        let widths: [usize; 5] = std::array::from_fn(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap()
+        });
+        iter::once(&header)
+            .chain(&rows)
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .map(|(column, cell)| {
+                        let alignment = if column == 0 {
+                            Alignment::Left
+                        } else {
+                            Alignment::Right
+                        };
+                        cell.pad_to_width_with_alignment(widths[column], alignment)
+                    })
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            })
+            .join("\n")
+    }
+}
+
+impl Tracer for CostTracer {
+    fn call_started(
+        &mut self,
+        _heap: &mut Heap,
+        call_site: HirId,
+        _callee: InlineObject,
+        _arguments: Vec<InlineObject>,
+        _responsible: HirId,
+    ) {
+        let call_site = call_site.get().clone();
+        self.costs.entry(call_site.clone()).or_default().calls += 1;
+        *self.active_calls.entry(call_site.clone()).or_default() += 1;
+        self.stack.push(Frame {
+            call_site,
+            instructions_at_start: self.instructions,
+            self_instructions: 0,
+        });
+    }
+    fn call_ended(&mut self, _heap: &mut Heap, _return_value: InlineObject) {
+        let frame = self.stack.pop().unwrap();
+        let cost = self.costs.get_mut(&frame.call_site).unwrap();
+        cost.self_instructions += frame.self_instructions;
+
+        let active_calls = self.active_calls.get_mut(&frame.call_site).unwrap();
+        *active_calls -= 1;
+        if *active_calls == 0 {
+            self.active_calls.remove(&frame.call_site);
+            cost.total_instructions += self.instructions - frame.instructions_at_start;
+        }
+    }
+
+    fn instruction_executed(&mut self) {
+        self.instructions += 1;
+        // This runs for every instruction, so we only add the costs to the
+        // table once the call ends.
+        if let Some(frame) = self.stack.last_mut() {
+            frame.self_instructions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cost, CostTracer};
+    use crate::{
+        heap::{Heap, HirId, InlineObject, Text},
+        tracer::Tracer,
+    };
+    use candy_frontend::hir::Id;
+
+    #[test]
+    fn recursive_calls_count_once_towards_the_total() {
+        let mut heap = Heap::default();
+        let callee: InlineObject = Text::create(&mut heap, true, "callee").into();
+        let recursive = HirId::create(&mut heap, true, Id::user());
+        let nested = HirId::create(&mut heap, true, Id::platform());
+
+        let mut tracer = CostTracer::default();
+        let run = |tracer: &mut CostTracer, instructions: usize| {
+            for _ in 0..instructions {
+                tracer.instruction_executed();
+            }
+        };
+        tracer.call_started(&mut heap, recursive, callee, vec![], recursive);
+        run(&mut tracer, 1);
+        tracer.call_started(&mut heap, recursive, callee, vec![], recursive);
+        run(&mut tracer, 2);
+        tracer.call_started(&mut heap, nested, callee, vec![], nested);
+        run(&mut tracer, 3);
+        tracer.call_ended(&mut heap, callee);
+        tracer.call_ended(&mut heap, callee);
+        run(&mut tracer, 1);
+        tracer.call_ended(&mut heap, callee);
+
+        assert_eq!(tracer.instructions(), 7);
+        assert_eq!(
+            tracer.costs()[&Id::user()],
+            Cost {
+                calls: 2,
+                self_instructions: 4,
+                total_instructions: 7,
+            },
+        );
+        assert_eq!(
+            tracer.costs()[&Id::platform()],
+            Cost {
+                calls: 1,
+                self_instructions: 3,
+                total_instructions: 3,
+            },
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use super::Tracer;
-use crate::heap::{Heap, HirId, InlineObject};
+use crate::heap::{AddressMap, Heap, HirId, InlineObject, Relocate};
 use candy_frontend::{hir::Id, module::Module};
 use rustc_hash::FxHashMap;
 
@@ -32,4 +32,10 @@ impl Tracer for EvaluatedValuesTracer {
         value.dup(heap);
         self.evaluated_values.insert(id.clone(), value);
     }
+
+    fn relocate(&mut self, address_map: &AddressMap) {
+        for value in self.evaluated_values.values_mut() {
+            value.relocate(address_map);
+        }
+    }
 }
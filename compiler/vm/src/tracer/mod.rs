@@ -1,8 +1,10 @@
 pub use self::dummy::DummyTracer;
-use crate::heap::{Function, Heap, HirId, InlineObject};
+use crate::heap::{AddressMap, Function, Heap, HirId, InlineObject};
 
+pub mod cost;
 mod dummy;
 pub mod evaluated_values;
+mod optional;
 pub mod stack_trace;
 pub mod tuple;
 
@@ -27,4 +29,11 @@ pub trait Tracer {
     ) {
     }
     fn call_ended(&mut self, _heap: &mut Heap, _return_value: InlineObject) {}
+
+    /// Called before each instruction of the byte code gets executed.
+    fn instruction_executed(&mut self) {}
+
+    /// Called after the heap got compacted. Tracers that keep objects from the
+    /// heap have to update them.
+    fn relocate(&mut self, _address_map: &AddressMap) {}
 }
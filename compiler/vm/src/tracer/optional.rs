@@ -0,0 +1,48 @@
+use super::Tracer;
+use crate::heap::{AddressMap, Function, Heap, HirId, InlineObject};
+
+/// A tracer that can be turned off at runtime, e.g., based on a command line
+/// flag.
+impl<T: Tracer> Tracer for Option<T> {
+    fn value_evaluated(&mut self, heap: &mut Heap, expression: HirId, value: InlineObject) {
+        if let Some(tracer) = self {
+            tracer.value_evaluated(heap, expression, value);
+        }
+    }
+
+    fn found_fuzzable_function(&mut self, heap: &mut Heap, definition: HirId, function: Function) {
+        if let Some(tracer) = self {
+            tracer.found_fuzzable_function(heap, definition, function);
+        }
+    }
+
+    fn call_started(
+        &mut self,
+        heap: &mut Heap,
+        call_site: HirId,
+        callee: InlineObject,
+        arguments: Vec<InlineObject>,
+        responsible: HirId,
+    ) {
+        if let Some(tracer) = self {
+            tracer.call_started(heap, call_site, callee, arguments, responsible);
+        }
+    }
+    fn call_ended(&mut self, heap: &mut Heap, return_value: InlineObject) {
+        if let Some(tracer) = self {
+            tracer.call_ended(heap, return_value);
+        }
+    }
+
+    fn instruction_executed(&mut self) {
+        if let Some(tracer) = self {
+            tracer.instruction_executed();
+        }
+    }
+
+    fn relocate(&mut self, address_map: &AddressMap) {
+        if let Some(tracer) = self {
+            tracer.relocate(address_map);
+        }
+    }
+}
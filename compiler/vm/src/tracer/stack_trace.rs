@@ -1,19 +1,30 @@
 use super::Tracer;
-use crate::heap::{Heap, HirId, InlineObject, ToDebugText};
+use crate::{
+    byte_code::ByteCode,
+    heap::{AddressMap, Data, Heap, HirId, InlineObject, Relocate, ToDebugText},
+};
 use candy_frontend::{
     ast_to_hir::AstToHir,
     cst::CstKind,
-    format::{MaxLength, Precedence},
+    format::{NestingLimits, Precedence},
+    hir::{self, HirDb, Id},
     module::PackagesPath,
     position::{PositionConversionDb, RangeOfPosition},
 };
 use itertools::Itertools;
 use pad::PadStr;
-use std::{env::current_dir, path::Path};
+use std::{collections::VecDeque, env::current_dir, iter, path::Path};
 
 #[derive(Debug, Default)]
 pub struct StackTracer {
-    pub call_stack: Vec<Call>,
+    /// The outermost call comes first.
+    pub call_stack: VecDeque<Call>,
+    /// If set, only this many of the innermost calls are kept. Otherwise, deep
+    /// recursions keep the arguments of all their calls alive.
+    max_depth: Option<usize>,
+    /// How many of the outermost calls were dropped because of `max_depth`
+    /// and didn't end yet.
+    dropped_calls: usize,
 }
 
 // Stack traces are a reduced view of the tracing state that represent the stack
@@ -44,6 +55,16 @@ impl Call {
         self.responsible.drop(heap);
     }
 }
+impl Relocate for Call {
+    fn relocate(&mut self, address_map: &AddressMap) {
+        self.call_site.relocate(address_map);
+        self.callee.relocate(address_map);
+        for argument in &mut self.arguments {
+            argument.relocate(address_map);
+        }
+        self.responsible.relocate(address_map);
+    }
+}
 
 impl Tracer for StackTracer {
     fn call_started(
@@ -61,24 +82,77 @@ impl Tracer for StackTracer {
             responsible,
         };
         call.dup(heap);
-        self.call_stack.push(call);
+        self.call_stack.push_back(call);
+        if let Some(max_depth) = self.max_depth
+            && self.call_stack.len() > max_depth
+        {
+            self.call_stack.pop_front().unwrap().drop(heap);
+            self.dropped_calls += 1;
+        }
     }
     fn call_ended(&mut self, heap: &mut Heap, _return_value: InlineObject) {
-        self.call_stack.pop().unwrap().drop(heap);
+        if let Some(call) = self.call_stack.pop_back() {
+            call.drop(heap);
+        } else {
+            self.dropped_calls -= 1;
+        }
+    }
+
+    fn relocate(&mut self, address_map: &AddressMap) {
+        for call in &mut self.call_stack {
+            call.relocate(address_map);
+        }
     }
 }
 
 impl StackTracer {
-    pub fn format<DB>(&self, db: &DB, packages_path: &PackagesPath) -> String
+    /// A tracer that only keeps the innermost `max_depth` calls.
+    ///
+    /// Once calls end, the stack doesn't regain the dropped outer calls, so it
+    /// can be shorter than `max_depth` while [`Self::dropped_calls`] is
+    /// positive.
+    #[must_use]
+    pub fn with_max_depth(max_depth: usize) -> Self {
+        Self {
+            call_stack: VecDeque::new(),
+            max_depth: Some(max_depth),
+            dropped_calls: 0,
+        }
+    }
+
+    /// The number of outer calls that are still running but were dropped to
+    /// stay within the maximum depth.
+    #[must_use]
+    pub const fn dropped_calls(&self) -> usize {
+        self.dropped_calls
+    }
+
+    pub fn format<DB>(&self, db: &DB, packages_path: &PackagesPath, byte_code: &ByteCode) -> String
     where
-        DB: AstToHir + PositionConversionDb,
+        DB: HirDb + PositionConversionDb,
     {
         let current_package_path = current_dir().ok(); // current_package.to_path(packages_path).unwrap();
+        let current_directory = current_package_path.as_deref();
         let caller_locations_and_calls = self
             .call_stack
             .iter()
             .rev()
-            .map(|it| Self::format_call(db, packages_path, current_package_path.as_deref(), it))
+            .flat_map(|call| {
+                let arity_mismatch = Self::format_arity_mismatch(
+                    db,
+                    packages_path,
+                    current_directory,
+                    byte_code,
+                    call,
+                );
+                iter::once(Self::format_call(
+                    db,
+                    packages_path,
+                    current_directory,
+                    call,
+                ))
+                .chain(arity_mismatch)
+            })
             .collect_vec();
 
         let longest_location = caller_locations_and_calls
@@ -87,10 +161,17 @@ impl StackTracer {
             .max()
             .unwrap_or_default();
 
-        caller_locations_and_calls
+        let mut formatted = caller_locations_and_calls
             .into_iter()
             .map(|(location, call)| format!("{} {}", location.pad_to_width(longest_location), call))
-            .join("\n")
+            .join("\n");
+        if self.dropped_calls > 0 {
+            formatted.push_str(&format!(
+                "\n({} outer calls are not shown)",
+                self.dropped_calls
+            ));
+        }
+        formatted
     }
 
     fn format_call<DB>(
@@ -110,40 +191,12 @@ impl StackTracer {
         } = call;
 
         let hir_id = call_site.get();
-        let module = hir_id.module.clone();
-        let cst_id = if module.package.is_tooling() {
+        let cst_id = if hir_id.module.package.is_tooling() {
             None
         } else {
             db.hir_to_cst_id(hir_id)
         };
-
-        let span_string = cst_id.map(|id| {
-            let cst = db.find_cst(module.clone(), id);
-            db.range_to_positions(module.clone(), cst.data.span)
-                .format()
-        });
-        #[allow(clippy::map_unwrap_or)]
-        let caller_location_string = hir_id
-            .module
-            .try_to_path(packages_path)
-            .map(|path| {
-                current_directory
-                    .and_then(|it| path.strip_prefix(it).ok())
-                    .unwrap_or(&path)
-                    .to_string_lossy()
-                    .into_owned()
-            })
-            .map(|path| {
-                span_string
-                    .as_deref()
-                    .map(|span_string| format!("{path}:{span_string}"))
-                    .unwrap_or(path)
-            })
-            .unwrap_or_else(|| {
-                span_string
-                    .map(|span_string| format!("{hir_id}  {span_string}"))
-                    .unwrap_or_else(|| hir_id.to_string())
-            });
+        let caller_location_string = format_location(db, packages_path, current_directory, hir_id);
 
         let call_string = format!(
             "{} {}",
@@ -158,11 +211,98 @@ impl StackTracer {
                 .unwrap_or_else(|| callee.to_string()),
             arguments
                 .iter()
-                .map(|it| it.to_debug_text(Precedence::High, MaxLength::Unlimited))
+                .map(|it| {
+                    it.to_debug_text_with_limits(
+                        Precedence::High,
+                        NestingLimits::MAX_LENGTH_FOR_MESSAGES,
+                        NestingLimits::FOR_MESSAGES,
+                    )
+                })
                 .join(" "),
         );
         (caller_location_string, call_string)
     }
+
+    /// If the callee is a function that expects a different number of
+    /// arguments, returns the location and signature of its definition.
+    fn format_arity_mismatch<DB>(
+        db: &DB,
+        packages_path: &PackagesPath,
+        current_directory: Option<&Path>,
+        byte_code: &ByteCode,
+        call: &Call,
+    ) -> Option<(String, String)>
+    where
+        DB: HirDb + PositionConversionDb,
+    {
+        let Data::Function(function) = call.callee.into() else {
+            return None;
+        };
+        if function.argument_count() == call.arguments.len() {
+            return None;
+        }
+
+        let definition = byte_code.functions_behind(function.body()).iter().min()?;
+        let hir::Expression::Function(hir::Function { parameters, .. }) =
+            db.find_expression(definition.clone())?
+        else {
+            return None;
+        };
+        let signature = iter::once(definition.function_name())
+            .chain(
+                parameters
+                    .iter()
+                    .map(|it| it.keys.last().unwrap().to_string()),
+            )
+            .join(" ");
+        Some((
+            format_location(db, packages_path, current_directory, definition),
+            format!("↳ expects `{signature}`"),
+        ))
+    }
+}
+
+/// Formats the location of the HIR ID as `path:span`, relative to the current
+/// directory if possible.
+#[allow(clippy::map_unwrap_or)]
+pub(super) fn format_location<DB>(
+    db: &DB,
+    packages_path: &PackagesPath,
+    current_directory: Option<&Path>,
+    hir_id: &Id,
+) -> String
+where
+    DB: AstToHir + PositionConversionDb,
+{
+    let module = hir_id.module.clone();
+    let span = if module.package.is_tooling() {
+        None
+    } else {
+        db.hir_id_to_origin_span(hir_id)
+    };
+
+    let span_string = span.map(|span| db.range_to_positions(module, span).format());
+    hir_id
+        .module
+        .try_to_path(packages_path)
+        .map(|path| {
+            current_directory
+                .and_then(|it| path.strip_prefix(it).ok())
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned()
+        })
+        .map(|path| {
+            span_string
+                .as_deref()
+                .map(|span_string| format!("{path}:{span_string}"))
+                .unwrap_or(path)
+        })
+        .unwrap_or_else(|| {
+            span_string
+                .map(|span_string| format!("{hir_id}  {span_string}"))
+                .unwrap_or_else(|| hir_id.to_string())
+        })
 }
 
 fn extract_receiver_name(cst_kind: &CstKind) -> Option<String> {
@@ -178,3 +318,65 @@ fn extract_receiver_name(cst_kind: &CstKind) -> Option<String> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::StackTracer;
+    use crate::{
+        heap::{Heap, HirId, InlineObject, Int, Text},
+        tracer::Tracer,
+    };
+    use candy_frontend::hir::Id;
+
+    #[test]
+    fn deep_recursions_only_keep_the_innermost_calls() {
+        const MAX_DEPTH: usize = 10;
+        const DEPTH: usize = 1000;
+
+        let mut heap = Heap::default();
+        let callee: InlineObject = Text::create(&mut heap, true, "recurse").into();
+        let hir_id = HirId::create(&mut heap, true, Id::user());
+        let objects_before = heap.objects().len();
+
+        let mut tracer = StackTracer::with_max_depth(MAX_DEPTH);
+        for depth in 0..DEPTH {
+            // Large ints live on the heap, so we can see when they're freed.
+            let argument: InlineObject =
+                Int::create(&mut heap, true, u64::MAX - depth as u64).into();
+            tracer.call_started(&mut heap, hir_id, callee, vec![argument], hir_id);
+            argument.drop(&mut heap);
+        }
+        assert_eq!(tracer.call_stack.len(), MAX_DEPTH);
+        assert_eq!(tracer.dropped_calls(), DEPTH - MAX_DEPTH);
+        // Only the arguments of the kept calls are still alive.
+        assert_eq!(heap.objects().len(), objects_before + MAX_DEPTH);
+        let innermost: Int = tracer.call_stack.back().unwrap().arguments[0]
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            innermost.get().into_owned(),
+            (u64::MAX - (DEPTH as u64 - 1)).into()
+        );
+
+        for _ in 0..DEPTH {
+            tracer.call_ended(&mut heap, callee);
+        }
+        assert!(tracer.call_stack.is_empty());
+        assert_eq!(tracer.dropped_calls(), 0);
+        assert_eq!(heap.objects().len(), objects_before);
+    }
+
+    #[test]
+    fn shallow_calls_are_all_kept() {
+        let mut heap = Heap::default();
+        let callee: InlineObject = Text::create(&mut heap, true, "callee").into();
+        let hir_id = HirId::create(&mut heap, true, Id::user());
+
+        let mut tracer = StackTracer::with_max_depth(10);
+        for _ in 0..10 {
+            tracer.call_started(&mut heap, hir_id, callee, vec![], hir_id);
+        }
+        assert_eq!(tracer.call_stack.len(), 10);
+        assert_eq!(tracer.dropped_calls(), 0);
+    }
+}
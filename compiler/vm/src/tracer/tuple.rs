@@ -1,5 +1,5 @@
 use super::Tracer;
-use crate::heap::{Function, Heap, HirId, InlineObject};
+use crate::heap::{AddressMap, Function, Heap, HirId, InlineObject};
 use impl_trait_for_tuples::impl_for_tuples;
 
 #[impl_for_tuples(2, 3)]
@@ -26,4 +26,12 @@ impl Tracer for Tuple {
     fn call_ended(&mut self, heap: &mut Heap, return_value: InlineObject) {
         for_tuples!( #(Tuple.call_ended(heap, return_value);)* );
     }
+
+    fn instruction_executed(&mut self) {
+        for_tuples!( #(Tuple.instruction_executed();)* );
+    }
+
+    fn relocate(&mut self, address_map: &AddressMap) {
+        for_tuples!( #(Tuple.relocate(address_map);)* );
+    }
 }
@@ -1,6 +1,8 @@
 use crate::{
+    builtin_functions::BuiltinContinuation,
     byte_code::ByteCode,
-    heap::{Function, Handle, Heap, HirId, InlineObject, Struct},
+    capabilities::{Clock, HostClock, HostRandomSource, RandomSource},
+    heap::{AddressMap, Function, Handle, Heap, HirId, InlineObject, Relocate, Struct},
     instruction_pointer::InstructionPointer,
     instructions::InstructionResult,
     tracer::Tracer,
@@ -32,11 +34,23 @@ struct VmInner<B: Borrow<ByteCode>, T: Tracer> {
     /// is [`None`] in the second phase or if just running a module or function
     /// on its own.
     environment_for_main_function: Option<Struct>,
+    /// See [`Vm::with_automatic_heap_compaction`].
+    heap_compaction_threshold: Option<f64>,
+    /// See [`Vm::with_checks`].
+    checks: bool,
+    random_source: Box<dyn RandomSource>,
+    clock: Box<dyn Clock>,
+    /// Set if the host failed the last handle call (see
+    /// [`VmHandleCall::fail`]). The VM panics with it instead of running the
+    /// next instruction.
+    failed_handle_call: Option<Panic>,
 }
 pub struct MachineState {
     pub next_instruction: Option<InstructionPointer>,
     pub data_stack: Vec<InlineObject>,
     pub call_stack: Vec<InstructionPointer>,
+    /// Builtins waiting for a function they called to return.
+    pub(crate) builtin_continuations: Vec<BuiltinContinuation>,
 }
 
 #[derive(Debug)]
@@ -92,14 +106,20 @@ where
             next_instruction: None,
             data_stack: vec![],
             call_stack: vec![],
+            builtin_continuations: vec![],
         };
-        state.call_function(function, arguments, responsible);
+        state.call_function(heap, function, arguments, responsible);
 
         let inner = Box::new(VmInner {
             byte_code,
             state,
             tracer,
             environment_for_main_function: None,
+            heap_compaction_threshold: None,
+            checks: false,
+            random_source: Box::new(HostRandomSource),
+            clock: Box::new(HostClock),
+            failed_handle_call: None,
         });
         Self { inner }
     }
@@ -120,6 +140,63 @@ where
         Self::for_function(byte_code, heap, function, &[], responsible, tracer)
     }
 
+    /// Compacts the heap whenever its fragmentation exceeds the threshold (see
+    /// [`Heap::should_compact`]) while running instructions using
+    /// [`Vm::run_n`].
+    ///
+    /// Only enable this if the VM holds all references into the heap.
+    /// Otherwise, use [`Vm::compact_heap`] and update the other references
+    /// yourself.
+    #[must_use]
+    pub fn with_automatic_heap_compaction(mut self, fragmentation_threshold: f64) -> Self {
+        self.inner.heap_compaction_threshold = Some(fragmentation_threshold);
+        self
+    }
+
+    /// Checks the data stack and the heap before running each instruction and
+    /// panics with the location of the instruction if something is off, e.g.,
+    /// if the instruction would read a freed object.
+    ///
+    /// This is slow and only meant for debugging the VM itself or programs
+    /// that behave suspiciously.
+    #[must_use]
+    pub fn with_checks(mut self) -> Self {
+        self.inner.checks = true;
+        self
+    }
+
+    /// Replaces the source of random bytes that handles called by this VM
+    /// use.
+    #[must_use]
+    pub fn with_random_source(mut self, random_source: impl RandomSource + 'static) -> Self {
+        self.inner.random_source = Box::new(random_source);
+        self
+    }
+    /// Replaces the clock that handles called by this VM use.
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.inner.clock = Box::new(clock);
+        self
+    }
+
+    /// Compacts the heap and updates all references that the VM and its tracer
+    /// hold.
+    pub fn compact_heap(&mut self, heap: &mut Heap) -> AddressMap {
+        let address_map = heap.compact();
+        let inner = &mut *self.inner;
+        for value in &mut inner.state.data_stack {
+            value.relocate(&address_map);
+        }
+        for continuation in &mut inner.state.builtin_continuations {
+            continuation.relocate(&address_map);
+        }
+        if let Some(environment) = &mut inner.environment_for_main_function {
+            environment.relocate(&address_map);
+        }
+        inner.tracer.relocate(&address_map);
+        address_map
+    }
+
     #[must_use]
     pub fn byte_code(&self) -> &B {
         &self.inner.byte_code
@@ -128,14 +205,27 @@ where
     pub fn tracer(&self) -> &T {
         &self.inner.tracer
     }
+    /// The instruction that runs next.
+    ///
+    /// This is `None` if the VM is about to finish or about to resume a builtin
+    /// that called a function or handle.
     #[must_use]
     pub fn next_instruction(&self) -> Option<InstructionPointer> {
-        self.inner.state.next_instruction
+        self.inner
+            .state
+            .next_instruction
+            .filter(|it| *it != InstructionPointer::RESUME_BUILTIN)
     }
     #[must_use]
     pub fn call_stack(&self) -> &[InstructionPointer] {
         &self.inner.state.call_stack
     }
+
+    /// The value on top of the data stack, e.g., the result of a handle call
+    /// that was just completed.
+    pub(crate) fn top_of_data_stack(&self) -> Option<InlineObject> {
+        self.inner.state.data_stack.last().copied()
+    }
 }
 
 #[derive(Deref)]
@@ -162,6 +252,13 @@ where
     B: Borrow<ByteCode>,
     T: Tracer,
 {
+    pub fn random_source(&mut self) -> &mut dyn RandomSource {
+        &mut *self.vm.inner.random_source
+    }
+    pub fn clock(&mut self) -> &mut dyn Clock {
+        &mut *self.vm.inner.clock
+    }
+
     pub fn complete(mut self, heap: &mut Heap, return_value: impl Into<InlineObject>) -> Vm<B, T> {
         self.handle.drop(heap);
         for argument in &self.call.arguments {
@@ -171,6 +268,23 @@ where
         self.vm.inner.state.data_stack.push(return_value.into());
         self.vm
     }
+    /// Completes the call without a return value, e.g., because the host
+    /// doesn't support the handle or gave up on the operation behind it.
+    ///
+    /// The VM then panics with the given reason the next time it runs. The
+    /// code responsible for the handle call is blamed.
+    pub fn fail(mut self, heap: &mut Heap, reason: impl Into<String>) -> Vm<B, T> {
+        self.handle.drop(heap);
+        for argument in &self.call.arguments {
+            argument.drop(heap);
+        }
+
+        self.vm.inner.failed_handle_call = Some(Panic {
+            reason: reason.into(),
+            responsible: self.call.responsible.get().clone(),
+        });
+        self.vm
+    }
 }
 
 impl<B, T> Vm<B, T>
@@ -180,6 +294,13 @@ where
 {
     /// Runs one instruction in the VM and returns its new state.
     pub fn run(mut self, heap: &mut Heap) -> StateAfterRun<B, T> {
+        if let Some(panic) = self.inner.failed_handle_call.take() {
+            return StateAfterRun::Finished(VmFinished {
+                tracer: self.inner.tracer,
+                result: Err(panic),
+            });
+        }
+
         let Some(current_instruction) = self.inner.state.next_instruction else {
             let return_value = self.inner.state.data_stack.pop().unwrap();
             self.inner.tracer.call_ended(heap, return_value);
@@ -189,7 +310,7 @@ where
                 // function. Now execute this main function using the
                 // environment we received earlier.
                 let responsible = HirId::create(heap, true, hir::Id::user());
-                let new_vm = Self::for_function(
+                let mut new_vm = Self::for_function(
                     self.inner.byte_code,
                     heap,
                     return_value.try_into().unwrap(),
@@ -197,6 +318,10 @@ where
                     responsible,
                     self.inner.tracer,
                 );
+                new_vm.inner.heap_compaction_threshold = self.inner.heap_compaction_threshold;
+                new_vm.inner.checks = self.inner.checks;
+                new_vm.inner.random_source = self.inner.random_source;
+                new_vm.inner.clock = self.inner.clock;
                 return StateAfterRun::Running(new_vm);
             }
 
@@ -206,24 +331,50 @@ where
             });
         };
 
-        let instruction = self
-            .inner
-            .byte_code
-            .borrow()
-            .instructions
-            .get(*current_instruction)
-            .expect("invalid instruction pointer");
-        self.inner.state.next_instruction = Some(current_instruction.next());
-
-        let result = self
-            .inner
-            .state
-            .run_instruction(heap, instruction, &mut self.inner.tracer);
+        let result = if current_instruction == InstructionPointer::RESUME_BUILTIN {
+            // A handle or builtin called by a builtin returned. Resuming the
+            // calling builtin counts as one step.
+            InstructionResult::Done
+        } else {
+            let byte_code = self.inner.byte_code.borrow();
+            let instruction = byte_code
+                .instructions
+                .get(*current_instruction)
+                .expect("invalid instruction pointer");
+            if self.inner.checks {
+                self.inner.state.check_invariants(
+                    heap,
+                    byte_code,
+                    current_instruction,
+                    instruction,
+                );
+            }
+            self.inner.state.next_instruction = Some(current_instruction.next());
+
+            self.inner.tracer.instruction_executed();
+            heap.set_allocation_site(Some(current_instruction));
+            let result =
+                self.inner
+                    .state
+                    .run_instruction(heap, instruction, &mut self.inner.tracer);
+            heap.set_allocation_site(None);
+            result
+        };
+        let result = self.inner.state.resume_returned_builtin(heap, result);
+        // Continue freeing large values dropped by earlier instructions.
+        heap.continue_freeing();
         match result {
             InstructionResult::Done => StateAfterRun::Running(self),
             InstructionResult::CallHandle(call) => {
                 StateAfterRun::CallingHandle(VmHandleCall { vm: self, call })
             }
+            InstructionResult::ArityMismatch(mismatch) => {
+                let panic = mismatch.into_panic(self.inner.byte_code.borrow());
+                StateAfterRun::Finished(VmFinished {
+                    tracer: self.inner.tracer,
+                    result: Err(panic),
+                })
+            }
             InstructionResult::Panic(panic) => StateAfterRun::Finished(VmFinished {
                 tracer: self.inner.tracer,
                 result: Err(panic),
@@ -232,14 +383,94 @@ where
     }
 
     /// Runs at most `max_instructions` in the VM.
-    pub fn run_n(mut self, heap: &mut Heap, max_instructions: usize) -> StateAfterRun<B, T> {
-        for _ in 0..max_instructions {
+    pub fn run_n(self, heap: &mut Heap, max_instructions: usize) -> StateAfterRun<B, T> {
+        let mut instructions_left = max_instructions;
+        match self.run_until_event(heap, &mut instructions_left) {
+            VmEvent::BudgetExhausted(vm) => StateAfterRun::Running(vm),
+            VmEvent::HandleCall(call) => StateAfterRun::CallingHandle(call),
+            event => StateAfterRun::Finished(event.into_finished().unwrap()),
+        }
+    }
+}
+
+/// The reason why [`Vm::run_until_event`] returned control to the host.
+#[must_use]
+pub enum VmEvent<B: Borrow<ByteCode>, T: Tracer> {
+    /// The VM used up its budget of instructions and can continue running.
+    BudgetExhausted(Vm<B, T>),
+    /// The VM waits for the host to complete a handle call.
+    HandleCall(VmHandleCall<B, T>),
+    Finished {
+        tracer: T,
+        return_value: InlineObject,
+    },
+    Panicked {
+        tracer: T,
+        panic: Panic,
+    },
+}
+impl<B: Borrow<ByteCode>, T: Tracer> From<VmFinished<T>> for VmEvent<B, T> {
+    fn from(finished: VmFinished<T>) -> Self {
+        let VmFinished { tracer, result } = finished;
+        match result {
+            Ok(return_value) => Self::Finished {
+                tracer,
+                return_value,
+            },
+            Err(panic) => Self::Panicked { tracer, panic },
+        }
+    }
+}
+impl<B: Borrow<ByteCode>, T: Tracer> VmEvent<B, T> {
+    /// Returns the result if the VM finished or panicked.
+    pub fn into_finished(self) -> Option<VmFinished<T>> {
+        match self {
+            Self::BudgetExhausted(_) | Self::HandleCall(_) => None,
+            Self::Finished {
+                tracer,
+                return_value,
+            } => Some(VmFinished {
+                tracer,
+                result: Ok(return_value),
+            }),
+            Self::Panicked { tracer, panic } => Some(VmFinished {
+                tracer,
+                result: Err(panic),
+            }),
+        }
+    }
+}
+
+impl<B, T> Vm<B, T>
+where
+    B: Borrow<ByteCode>,
+    T: Tracer,
+{
+    /// Runs the VM until something happens that the host has to react to.
+    ///
+    /// Every executed instruction decreases `instructions_left`. Once it
+    /// reaches zero, [`VmEvent::BudgetExhausted`] is returned. Hosts can thus
+    /// reuse a single budget across handle calls.
+    pub fn run_until_event(
+        mut self,
+        heap: &mut Heap,
+        instructions_left: &mut usize,
+    ) -> VmEvent<B, T> {
+        if let Some(threshold) = self.inner.heap_compaction_threshold
+            && heap.should_compact(threshold)
+        {
+            self.compact_heap(heap);
+        }
+
+        while *instructions_left > 0 {
+            *instructions_left -= 1;
             match self.run(heap) {
                 StateAfterRun::Running(vm) => self = vm,
-                a => return a,
+                StateAfterRun::CallingHandle(call) => return VmEvent::HandleCall(call),
+                StateAfterRun::Finished(finished) => return finished.into(),
             }
         }
-        StateAfterRun::Running(self)
+        VmEvent::BudgetExhausted(self)
     }
 }
 
@@ -279,3 +510,209 @@ impl<K: Eq + Hash, V> ReplaceHashMapValue<K, V> for HashMap<K, V> {
         self.insert(key, value);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{StateAfterRun, Vm, VmEvent, VmFinished};
+    use crate::{
+        byte_code::ByteCode,
+        environment::{CapturedOutput, DefaultEnvironment, Environment, Output},
+        heap::{Heap, InlineData, InlineObject, ObjectInHeap, ToDebugText},
+        lir_to_byte_code::compile_byte_code,
+        tracer::{stack_trace::StackTracer, DummyTracer},
+        PopulateInMemoryProviderFromFileSystem,
+    };
+    use candy_frontend::{
+        format::{MaxLength, Precedence},
+        hir_to_mir::ExecutionTarget,
+        module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+        test_database::Database,
+        TracingConfig,
+    };
+    use std::iter;
+
+    #[track_caller]
+    fn compile(source: &str) -> ByteCode {
+        let mut db = Database::default();
+        db.get_in_memory_module_provider()
+            .load_package_from_file_system("Builtins");
+        let module = Module {
+            package: Package::Anonymous {
+                url: "test".to_string(),
+            },
+            path: vec![],
+            kind: ModuleKind::Code,
+        };
+        db.did_change_module(&module, source.as_bytes().to_vec());
+        let (byte_code, errors) = compile_byte_code(
+            &db,
+            ExecutionTarget::MainFunction(module),
+            TracingConfig::off(),
+        );
+        assert!(
+            errors.iter().all(|it| it.payload.is_warning()),
+            "{errors:?}",
+        );
+        byte_code
+    }
+
+    #[test]
+    fn hosts_complete_handle_calls_until_the_vm_finishes() {
+        let byte_code = compile(r#"main := { environment -> environment.stdout "Hi" }"#);
+        let mut heap = Heap::default();
+        let captured = CapturedOutput::default();
+        let (environment_object, mut environment) =
+            DefaultEnvironment::new_with_output(&mut heap, &[], Output::Captured(captured.clone()));
+        let mut vm = Vm::for_main_function(&byte_code, &mut heap, environment_object, DummyTracer);
+
+        let mut handle_calls = 0;
+        let mut instructions_left = usize::MAX;
+        let return_value = loop {
+            match vm.run_until_event(&mut heap, &mut instructions_left) {
+                VmEvent::BudgetExhausted(_) => panic!("The budget is unlimited."),
+                VmEvent::HandleCall(call) => {
+                    handle_calls += 1;
+                    vm = environment.handle(&mut heap, call);
+                }
+                VmEvent::Finished { return_value, .. } => break return_value,
+                VmEvent::Panicked { panic, .. } => panic!("The VM panicked: {}", panic.reason),
+            }
+        };
+        assert_eq!(handle_calls, 1);
+        assert_eq!(captured.stdout(), "Hi\n");
+        assert_eq!(
+            return_value.to_debug_text(Precedence::Low, MaxLength::Unlimited),
+            "Nothing",
+        );
+    }
+
+    #[test]
+    fn the_budget_is_shared_across_runs() {
+        let byte_code = compile("main := { environment -> 1 }");
+        let run = |budget_per_step| {
+            let mut heap = Heap::default();
+            let (environment_object, _) = DefaultEnvironment::new(&mut heap, &[]);
+            let mut vm =
+                Vm::for_main_function(&byte_code, &mut heap, environment_object, DummyTracer);
+            let mut steps = 0;
+            loop {
+                steps += 1;
+                let mut instructions_left = budget_per_step;
+                match vm.run_until_event(&mut heap, &mut instructions_left) {
+                    VmEvent::BudgetExhausted(next) => {
+                        assert_eq!(instructions_left, 0);
+                        vm = next;
+                    }
+                    VmEvent::Finished { .. } => {
+                        return (steps, budget_per_step - instructions_left);
+                    }
+                    _ => panic!("Expected the VM to finish."),
+                }
+            }
+        };
+
+        let (steps, instructions_in_last_step) = run(usize::MAX);
+        assert_eq!(steps, 1);
+        let instructions = instructions_in_last_step;
+        assert!(instructions > 1);
+        assert_eq!(run(1), (instructions, 1));
+        assert_eq!(
+            run(instructions - 1),
+            (2, 1),
+            "The last step only needs one instruction.",
+        );
+    }
+
+    #[test]
+    fn failed_handle_calls_make_the_vm_panic() {
+        let byte_code = compile(r#"main := { environment -> environment.stdout "Hi" }"#);
+        let mut heap = Heap::default();
+        let (environment_object, _) = DefaultEnvironment::new(&mut heap, &[]);
+        let vm = Vm::for_main_function(&byte_code, &mut heap, environment_object, DummyTracer);
+        let mut instructions_left = usize::MAX;
+        let VmEvent::HandleCall(call) = vm.run_until_event(&mut heap, &mut instructions_left)
+        else {
+            panic!("Expected the VM to call the stdout handle.");
+        };
+        let responsible = call.responsible.get().clone();
+        let vm = call.fail(&mut heap, "Disconnected.");
+
+        match vm.run(&mut heap) {
+            StateAfterRun::Finished(VmFinished {
+                result: Err(panic), ..
+            }) => {
+                assert_eq!(panic.reason, "Disconnected.");
+                assert_eq!(panic.responsible, responsible);
+            }
+            _ => panic!("Expected the VM to panic."),
+        }
+    }
+
+    #[test]
+    fn compacting_the_heap_relocates_everything_the_vm_holds() {
+        let byte_code = compile(
+            r#"builtins = use "Builtins"
+main := { environment ->
+  items = builtins.listFilled 100 "item"
+  builtins.listFoldLeft items "" { result item -> builtins.textConcatenate result item }
+}"#,
+        );
+        let mut heap = Heap::default();
+        let (environment_object, _) = DefaultEnvironment::new(&mut heap, &[]);
+        let mut vm = Vm::for_main_function(
+            &byte_code,
+            &mut heap,
+            environment_object,
+            StackTracer::default(),
+        )
+        .with_checks();
+
+        // Pause while `✨.listFoldLeft` waits for the callback to return.
+        while vm.inner.state.builtin_continuations.is_empty() {
+            vm = match vm.run_n(&mut heap, 1) {
+                StateAfterRun::Running(vm) => vm,
+                _ => panic!("Expected the VM to keep running."),
+            };
+        }
+        let old_objects = heap.objects().clone();
+        let address_map = vm.compact_heap(&mut heap);
+
+        // The old objects got freed, so nothing may point to them anymore.
+        let is_relocated = |object: InlineObject| match InlineData::from(object) {
+            InlineData::Pointer(pointer) => !old_objects.contains(&ObjectInHeap(pointer.get())),
+            _ => true,
+        };
+        assert!(address_map
+            .get(old_objects.iter().next().unwrap().0)
+            .is_some());
+        assert!(vm.inner.state.data_stack.iter().all(|it| is_relocated(*it)));
+        let calls = &vm.tracer().call_stack;
+        assert!(!calls.is_empty());
+        assert!(calls
+            .iter()
+            .flat_map(|call| iter::once(call.callee).chain(call.arguments.iter().copied()))
+            .all(is_relocated));
+
+        // The checks make the VM panic if it reads one of the old objects.
+        let VmFinished { result, .. } = vm.run_forever_without_handles(&mut heap);
+        assert_eq!(
+            result
+                .unwrap()
+                .to_debug_text(Precedence::Low, MaxLength::Unlimited),
+            format!(r#""{}""#, "item".repeat(100)),
+        );
+    }
+
+    #[test]
+    fn panics_are_reported_as_events() {
+        let byte_code = compile(r#"main := { environment -> needs False "Oops" }"#);
+        let mut heap = Heap::default();
+        let (environment_object, _) = DefaultEnvironment::new(&mut heap, &[]);
+        let vm = Vm::for_main_function(&byte_code, &mut heap, environment_object, DummyTracer);
+        let mut instructions_left = usize::MAX;
+        match vm.run_until_event(&mut heap, &mut instructions_left) {
+            VmEvent::Panicked { panic, .. } => assert_eq!(panic.reason, "Oops"),
+            _ => panic!("Expected the VM to panic."),
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use candy_vm::capabilities::Clock;
+use std::time::{Duration, SystemTime};
+
+/// `SystemTime::now()` panics on `wasm32-unknown-unknown`, so we ask
+/// JavaScript for the current time instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsClock;
+impl Clock for JsClock {
+    fn now(&mut self) -> SystemTime {
+        let milliseconds = js_sys::Date::now();
+        SystemTime::UNIX_EPOCH + Duration::from_secs_f64(milliseconds / 1000.0)
+    }
+}
@@ -0,0 +1,106 @@
+use candy_frontend::{
+    ast::AstDbStorage,
+    ast_to_hir::AstToHirStorage,
+    cst::CstDbStorage,
+    cst_to_ast::CstToAstStorage,
+    hir::HirDbStorage,
+    hir_to_mir::HirToMirStorage,
+    lir_optimize::OptimizeLirStorage,
+    mir_optimize::OptimizeMirStorage,
+    mir_to_lir::MirToLirStorage,
+    module::{
+        GetModuleContentQuery, InMemoryModuleProvider, Module, ModuleDbStorage, ModuleKind,
+        ModuleProvider, ModuleProviderOwner, MutableModuleProviderOwner, OverlayModuleProvider,
+        Package,
+    },
+    position::PositionConversionStorage,
+    rcst_to_cst::RcstToCstStorage,
+    string_to_rcst::StringToRcstStorage,
+};
+use include_dir::{include_dir, Dir};
+use std::path::Path;
+
+static BUILTINS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/../../packages/Builtins");
+static CORE: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/../../packages/Core");
+
+#[salsa::database(
+    AstDbStorage,
+    AstToHirStorage,
+    CstDbStorage,
+    CstToAstStorage,
+    HirDbStorage,
+    HirToMirStorage,
+    MirToLirStorage,
+    ModuleDbStorage,
+    OptimizeLirStorage,
+    OptimizeMirStorage,
+    PositionConversionStorage,
+    RcstToCstStorage,
+    StringToRcstStorage
+)]
+pub struct Database {
+    storage: salsa::Storage<Self>,
+    /// The overlay contains the code from the playground, the fallback
+    /// contains the embedded packages.
+    module_provider: OverlayModuleProvider<InMemoryModuleProvider, InMemoryModuleProvider>,
+}
+impl salsa::Database for Database {}
+
+impl Database {
+    pub fn with_embedded_packages() -> Self {
+        let mut packages = InMemoryModuleProvider::default();
+        for (name, dir) in [("Builtins", &BUILTINS), ("Core", &CORE)] {
+            add_embedded_package(&mut packages, &Package::Managed(name.into()), dir);
+        }
+        Self {
+            storage: salsa::Storage::default(),
+            module_provider: OverlayModuleProvider::new(
+                InMemoryModuleProvider::default(),
+                packages,
+            ),
+        }
+    }
+}
+fn add_embedded_package(provider: &mut InMemoryModuleProvider, package: &Package, dir: &Dir) {
+    for file in dir.files() {
+        if let Some(path) = module_path(file.path()) {
+            let module = Module {
+                package: package.clone(),
+                path,
+                kind: ModuleKind::Code,
+            };
+            provider.add(&module, file.contents().to_vec());
+        }
+    }
+    for dir in dir.dirs() {
+        add_embedded_package(provider, package, dir);
+    }
+}
+/// Mirrors [`Module::from_package_and_path`], which only works for files that
+/// actually exist.
+fn module_path(path: &Path) -> Option<Vec<String>> {
+    let mut path = path
+        .iter()
+        .map(|it| it.to_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    let last = path.pop()?;
+    let last = last.strip_suffix(".candy")?;
+    if last != "_" {
+        path.push(last.to_string());
+    }
+    Some(path)
+}
+
+impl ModuleProviderOwner for Database {
+    fn get_module_provider(&self) -> &dyn ModuleProvider {
+        &self.module_provider
+    }
+}
+impl MutableModuleProviderOwner for Database {
+    fn get_in_memory_module_provider(&mut self) -> &mut InMemoryModuleProvider {
+        &mut self.module_provider.overlay
+    }
+    fn invalidate_module(&mut self, module: &Module) {
+        GetModuleContentQuery.in_db_mut(self).invalidate(module);
+    }
+}
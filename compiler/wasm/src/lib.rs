@@ -0,0 +1,171 @@
+//! Compiles and runs Candy code in the browser. See the `README.md` for how to
+//! use it from JavaScript.
+
+#![warn(clippy::nursery, clippy::pedantic)]
+#![allow(clippy::missing_panics_doc, clippy::module_name_repetitions)]
+
+use crate::{capabilities::JsClock, database::Database};
+use candy_frontend::{
+    ast_to_hir::AstToHir,
+    format::{MaxLength, Precedence},
+    hir_to_mir::ExecutionTarget,
+    module::{Module, ModuleKind, MutableModuleProviderOwner, Package},
+    position::{self, Offset, PositionConversionDb},
+    TracingConfig,
+};
+use candy_vm::{
+    environment::{DefaultEnvironment, Output, OutputCallback, OutputStream},
+    heap::{Heap, ToDebugText},
+    lir_to_byte_code::compile_byte_code,
+    tracer::DummyTracer,
+    Vm, VmFinished,
+};
+use js_sys::Function;
+use serde::Serialize;
+use std::{cell::RefCell, ops};
+use wasm_bindgen::prelude::*;
+
+mod capabilities;
+mod database;
+
+thread_local! {
+    // Keeping the database around between runs means that the embedded
+    // packages only have to be compiled once.
+    static DATABASE: RefCell<Database> = RefCell::new(Database::with_embedded_packages());
+}
+
+/// Compiles the source code as a module and runs its `main` function.
+///
+/// Whenever the program writes to `environment.stdout` or
+/// `environment.stderr`, `on_output` is called with the stream (`"stdout"` or
+/// `"stderr"`) and the message. The returned value is a [`RunResult`].
+#[wasm_bindgen]
+#[must_use]
+pub fn compile_and_run(source: &str, on_output: Function) -> JsValue {
+    let result = DATABASE.with(|db| run(&mut db.borrow_mut(), source, on_output));
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+fn run(db: &mut Database, source: &str, on_output: Function) -> RunResult {
+    let module = Module {
+        package: Package::Anonymous {
+            url: "playground".to_string(),
+        },
+        path: vec![],
+        kind: ModuleKind::Code,
+    };
+    db.did_change_module(&module, source.as_bytes().to_vec());
+
+    let (byte_code, errors) = compile_byte_code(
+        &*db,
+        ExecutionTarget::MainFunction(module),
+        TracingConfig::off(),
+    );
+    let errors = errors
+        .iter()
+        .map(|error| CompilerError {
+            module: error.module.to_string(),
+            range: Range::new(db, &error.module, error.span.clone()),
+            severity: if error.payload.is_warning() {
+                Severity::Warning
+            } else {
+                Severity::Error
+            },
+            message: error.payload.to_string(),
+        })
+        .collect();
+
+    let output = Output::Streamed(OutputCallback::new(move |stream, message| {
+        let stream = match stream {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        };
+        // An exception in the playground's UI shouldn't abort the program.
+        let _ = on_output.call2(
+            &JsValue::NULL,
+            &JsValue::from_str(stream),
+            &JsValue::from_str(message),
+        );
+    }));
+    let mut heap = Heap::default();
+    let (environment_object, mut environment) =
+        DefaultEnvironment::new_with_output(&mut heap, &[], output);
+    let VmFinished { result, .. } =
+        Vm::for_main_function(&byte_code, &mut heap, environment_object, DummyTracer)
+            .with_clock(JsClock)
+            .run_forever_with_environment(&mut heap, &mut environment);
+    let outcome = match result {
+        Ok(return_value) => Outcome::Returned {
+            value: return_value.to_debug_text(Precedence::Low, MaxLength::Unlimited),
+        },
+        Err(panic) => Outcome::Panicked {
+            range: db
+                .hir_id_to_origin_span(&panic.responsible)
+                .map(|span| Range::new(db, &panic.responsible.module, span)),
+            reason: panic.reason,
+            responsible: panic.responsible.to_string(),
+        },
+    };
+    RunResult { errors, outcome }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunResult {
+    pub errors: Vec<CompilerError>,
+    pub outcome: Outcome,
+}
+#[derive(Debug, Serialize)]
+pub struct CompilerError {
+    pub module: String,
+    pub range: Range,
+    pub severity: Severity,
+    pub message: String,
+}
+/// Warnings point out suspicious code, but the program still runs as written.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Outcome {
+    /// The value returned by `main`, formatted as Candy code.
+    Returned { value: String },
+    Panicked {
+        reason: String,
+        responsible: String,
+        /// Where the responsible code is, if it's in a module.
+        range: Option<Range>,
+    },
+}
+
+/// Zero-based lines and characters, just like in the Language Server Protocol.
+#[derive(Debug, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+impl Range {
+    fn new(db: &Database, module: &Module, span: ops::Range<Offset>) -> Self {
+        let range = db.range_to_positions(module.clone(), span);
+        Self {
+            start: range.start.into(),
+            end: range.end.into(),
+        }
+    }
+}
+#[derive(Debug, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+impl From<position::Position> for Position {
+    fn from(position: position::Position) -> Self {
+        Self {
+            line: position.line,
+            character: position.character,
+        }
+    }
+}
@@ -6,6 +6,7 @@ import { combineCancellationTokens, PromiseOr } from "./utils";
 type Ir =
   | { type: "rcst" }
   | { type: "ast" }
+  | { type: "desugaredAst" }
   | { type: "hir" }
   | { type: "mir"; tracingConfig: TracingConfig }
   | { type: "optimizedMir"; tracingConfig: TracingConfig }
@@ -20,6 +21,8 @@ function getIrTitle(irType: IrType): string {
       return "RCST";
     case "ast":
       return "AST";
+    case "desugaredAst":
+      return "Desugared AST";
     case "hir":
       return "HIR";
     case "mir":
@@ -51,6 +54,9 @@ export function registerDebugIrCommands(client: LanguageClient) {
 
   registerDebugIrCommand("rcst", "viewRcst", () => ({ type: "rcst" }));
   registerDebugIrCommand("ast", "viewAst", () => ({ type: "ast" }));
+  registerDebugIrCommand("desugaredAst", "viewDesugaredAst", () => ({
+    type: "desugaredAst",
+  }));
   registerDebugIrCommand("hir", "viewHir", () => ({ type: "hir" }));
   registerDebugIrCommand("mir", "viewMir", async () => {
     const tracingConfig = await pickTracingConfig({
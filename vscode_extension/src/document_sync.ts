@@ -0,0 +1,24 @@
+import * as vscode from "vscode";
+import {
+  DidChangeTextDocumentNotification,
+  LanguageClient,
+} from "vscode-languageclient/node";
+import { resyncDocumentType } from "./lsp_custom_protocol";
+
+// The server asks for the full content of a document if the incremental changes
+// we sent don't match its copy of the document.
+export function registerDocumentResync(client: LanguageClient) {
+  client.onNotification(resyncDocumentType, async (notification) => {
+    const document = vscode.workspace.textDocuments.find(
+      (it) => client.code2ProtocolConverter.asUri(it.uri) === notification.uri,
+    );
+    if (!document) {
+      return;
+    }
+
+    await client.sendNotification(DidChangeTextDocumentNotification.type, {
+      textDocument: { uri: notification.uri, version: document.version },
+      contentChanges: [{ text: document.getText() }],
+    });
+  });
+}
@@ -8,6 +8,7 @@ import {
 } from "vscode-languageclient/node";
 import { registerDebugAdapter } from "./debug_adapter";
 import { registerDebugIrCommands } from "./debug_irs";
+import { registerDocumentResync } from "./document_sync";
 import { HintsDecorations } from "./hints";
 import { ServerStatusService } from "./server_status";
 
@@ -35,7 +36,18 @@ export async function activate(context: vscode.ExtensionContext) {
 
   const clientOptions: LanguageClientOptions = {
     outputChannelName: "🍭 Candy Language Server",
-    initializationOptions: { packagesPath },
+    initializationOptions: {
+      packagesPath,
+      formatter: {
+        lambdaBodyLayout: configuration.get<string>(
+          "formatter.lambdaBodyLayout",
+        ),
+        callArgumentsLayout: configuration.get<string>(
+          "formatter.callArgumentsLayout",
+        ),
+        organizeUses: configuration.get<boolean>("formatter.organizeUses"),
+      },
+    },
   };
 
   client = new LanguageClient(
@@ -48,6 +60,7 @@ export async function activate(context: vscode.ExtensionContext) {
 
   context.subscriptions.push(new ServerStatusService(client));
   context.subscriptions.push(new HintsDecorations(client));
+  registerDocumentResync(client);
   registerDebugIrCommands(client);
   registerDebugAdapter(context, client);
 }
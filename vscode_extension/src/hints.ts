@@ -26,6 +26,7 @@ export class HintsDecorations implements vs.Disposable {
         kind: "sampleInputPanickingWithInternalCodeResponsible",
         color: "candy.sampleInput.panickingWithInternalCodeResponsible",
       },
+      { kind: "hotSpot", color: "candy.hotSpotHint" },
     ].forEach((value) =>
       this.decorationTypes.set(
         value.kind as HintKind,
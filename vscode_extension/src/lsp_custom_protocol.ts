@@ -42,6 +42,14 @@ export interface UpdateIrParams {
   readonly uri: DocumentUri;
 }
 
+// Document Synchronization
+export const resyncDocumentType = new NotificationType<ResyncDocumentParams>(
+  "candy/textDocument/resync",
+);
+export interface ResyncDocumentParams {
+  readonly uri: DocumentUri;
+}
+
 // Hints
 export const publishHintsType = new NotificationType<HintsParams>(
   "candy/textDocument/publishHints",
@@ -60,7 +68,8 @@ export type HintKind =
   | "fuzzingStatus"
   | "sampleInputReturningNormally"
   | "sampleInputPanickingWithCallerResponsible"
-  | "sampleInputPanickingWithInternalCodeResponsible";
+  | "sampleInputPanickingWithInternalCodeResponsible"
+  | "hotSpot";
 
 // Status
 export const publishServerStatusType = new NotificationType<ServerStatus>(